@@ -117,6 +117,8 @@ fn benchmark_checker_comparison(c: &mut Criterion) {
     let config = BeancountCheckConfig {
         method: Some(BeancountCheckMethod::SystemCall),
         bean_check_cmd: None,
+        args: Vec::new(),
+        timeout: None,
         python_cmd: None,
     };
 
@@ -140,6 +142,8 @@ fn benchmark_checker_comparison(c: &mut Criterion) {
     let config = BeancountCheckConfig {
         method: Some(BeancountCheckMethod::PythonSystem),
         bean_check_cmd: None,
+        args: Vec::new(),
+        timeout: None,
         python_cmd: None,
     };
 
@@ -165,6 +169,8 @@ fn benchmark_checker_comparison(c: &mut Criterion) {
         let config = BeancountCheckConfig {
             method: Some(BeancountCheckMethod::PythonEmbedded),
             bean_check_cmd: None,
+            args: Vec::new(),
+            timeout: None,
             python_cmd: None,
         };
 
@@ -196,6 +202,8 @@ fn benchmark_system_call_scaling(c: &mut Criterion) {
     let config = BeancountCheckConfig {
         method: Some(BeancountCheckMethod::SystemCall),
         bean_check_cmd: None,
+        args: Vec::new(),
+        timeout: None,
         python_cmd: None,
     };
 
@@ -260,6 +268,8 @@ fn benchmark_pyo3_scaling(c: &mut Criterion) {
     let config = BeancountCheckConfig {
         method: Some(BeancountCheckMethod::PythonEmbedded),
         bean_check_cmd: None,
+        args: Vec::new(),
+        timeout: None,
         python_cmd: None,
     };
 
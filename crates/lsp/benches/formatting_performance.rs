@@ -0,0 +1,75 @@
+use beancount_language_server::config::FormattingConfig;
+use beancount_language_server::providers::formatting::format_text_edits;
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::fmt::Write;
+use std::hint::black_box;
+
+/// Generates a journal of roughly `lines` lines with unaligned postings
+fn generate_journal(lines: usize) -> String {
+    let mut content = String::from("2024-01-01 open Assets:Checking USD\n");
+    content.push_str("2024-01-01 open Expenses:Food\n\n");
+
+    // Each transaction adds five lines
+    for i in 0..lines / 5 {
+        let day = i % 28 + 1;
+        let month = i / 28 % 12 + 1;
+        writeln!(
+            content,
+            "2024-{month:02}-{day:02} * \"Shop {i}\" \"Purchase\""
+        )
+        .unwrap();
+        writeln!(content, "  receipt: \"{i}.pdf\"").unwrap();
+        writeln!(
+            content,
+            "  Expenses:Food  {}.{:02} USD ; item",
+            i % 500,
+            i % 100
+        )
+        .unwrap();
+        writeln!(
+            content,
+            "    Assets:Checking    -{}.{:02} USD",
+            i % 500,
+            i % 100
+        )
+        .unwrap();
+        content.push('\n');
+    }
+
+    content
+}
+
+fn benchmark_formatting(c: &mut Criterion) {
+    let mut group = c.benchmark_group("formatting");
+    group.sample_size(10);
+
+    let all_passes = FormattingConfig {
+        indent_width: Some(2),
+        align_metadata: true,
+        comment_column: Some(0),
+        ..Default::default()
+    };
+
+    for lines in [1_000, 10_000, 100_000] {
+        let content = generate_journal(lines);
+
+        group.bench_with_input(
+            BenchmarkId::new("default", lines),
+            &content,
+            |b, content| {
+                b.iter(|| format_text_edits(black_box(content), &FormattingConfig::default()))
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("all_passes", lines),
+            &content,
+            |b, content| b.iter(|| format_text_edits(black_box(content), &all_passes)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_formatting);
+criterion_main!(benches);
@@ -40,57 +40,14 @@
 /// - Queries are compiled once and can be reused
 /// - Field queries are more efficient than manual field access
 /// - StreamingIterator avoids allocating a Vec of all matches
-use crate::treesitter_utils::text_for_tree_sitter_node;
-use std::sync::{Arc, OnceLock};
+use crate::index::SymbolKind;
+use crate::ledger::{self, LedgerEntry};
+use crate::queries::{get_currency_query, get_note_query, get_option_query, get_unified_query};
+use crate::treesitter_utils::{text_for_tree_sitter_node, tree_sitter_node_to_lsp_range};
+use std::sync::Arc;
 use tree_sitter::StreamingIterator;
 use tree_sitter_beancount::tree_sitter;
 
-/// Static compiled queries for beancount data extraction.
-/// Compiled once on first use and reused for all subsequent parses.
-static UNIFIED_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
-static CURRENCY_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
-static NOTE_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
-
-/// Get or compile the unified query (tags, links, flags, accounts, transactions)
-pub(crate) fn get_unified_query() -> &'static tree_sitter::Query {
-    UNIFIED_QUERY.get_or_init(|| {
-        let query_string = r#"
-            (tag) @tag
-            (link) @link
-            (flag) @flag
-            (open account: (account) @account)
-            (transaction) @transaction
-        "#;
-        tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
-            .expect("Failed to compile unified query")
-    })
-}
-
-/// Get or compile the currency query (open, commodity, all currencies)
-fn get_currency_query() -> &'static tree_sitter::Query {
-    CURRENCY_QUERY.get_or_init(|| {
-        let query_string = r#"
-            (open (currency) @currency)
-            (commodity (currency) @currency)
-            (currency) @currency
-        "#;
-        tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
-            .expect("Failed to compile currency query")
-    })
-}
-
-/// Get or compile the note query (note directives with account and string)
-fn get_note_query() -> &'static tree_sitter::Query {
-    NOTE_QUERY.get_or_init(|| {
-        let query_string = r#"
-            (note account: (account) @account (string) @note)
-            (note (account) @account (string) @note)
-        "#;
-        tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
-            .expect("Failed to compile note query")
-    })
-}
-
 #[derive(Clone, Debug)]
 pub struct FlaggedEntry {
     _file: String,
@@ -98,6 +55,15 @@ pub struct FlaggedEntry {
     pub flag: String,
 }
 
+/// An account named anywhere in a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountReference {
+    pub account: String,
+    pub range: lsp_types::Range,
+    /// Whether this is the account of an `open` directive.
+    pub declaration: bool,
+}
+
 #[derive(Clone, Debug)]
 pub struct BeancountData {
     accounts: Arc<Vec<String>>,
@@ -108,11 +74,21 @@ pub struct BeancountData {
     tags: Arc<Vec<String>>,
     links: Arc<Vec<String>>,
     commodities: Arc<Vec<String>>,
+    account_definitions: Arc<Vec<(String, lsp_types::Range)>>,
+    account_references: Arc<Vec<AccountReference>>,
+    account_closes: Arc<Vec<(String, String)>>,
+    symbol_counts: Arc<Vec<(SymbolKind, String, usize)>>,
+    options: Arc<Vec<(String, String)>>,
+    fava_options: Arc<Vec<(String, String)>>,
+    ledger_entries: Arc<Vec<LedgerEntry>>,
 }
 
 impl BeancountData {
     pub fn new(tree: &tree_sitter::Tree, content: &ropey::Rope) -> Self {
         let mut accounts = vec![];
+        let mut account_definitions = vec![];
+        let mut account_references = vec![];
+        let mut account_closes = vec![];
         let mut payees = vec![];
         let mut narration = vec![];
         let mut flagged_entries = vec![];
@@ -142,13 +118,18 @@ impl BeancountData {
         let account_idx = unified_query
             .capture_index_for_name("account")
             .expect("query should have 'account' capture");
+        let reference_idx = unified_query
+            .capture_index_for_name("reference")
+            .expect("query should have 'reference' capture");
         let transaction_idx = unified_query
             .capture_index_for_name("transaction")
             .expect("query should have 'transaction' capture");
 
         // Collections for frequency tracking
-        let mut tags_set = std::collections::HashSet::new();
-        let mut links_set = std::collections::HashSet::new();
+        let mut tag_count: std::collections::HashMap<String, usize> =
+            std::collections::HashMap::new();
+        let mut link_count: std::collections::HashMap<String, usize> =
+            std::collections::HashMap::new();
         let mut payee_count: std::collections::HashMap<String, usize> =
             std::collections::HashMap::new();
         let mut narration_count: std::collections::HashMap<String, usize> =
@@ -159,10 +140,14 @@ impl BeancountData {
             for capture in qmatch.captures {
                 match capture.index {
                     idx if idx == tag_idx => {
-                        tags_set.insert(text_for_tree_sitter_node(content, &capture.node));
+                        *tag_count
+                            .entry(text_for_tree_sitter_node(content, &capture.node))
+                            .or_insert(0) += 1;
                     }
                     idx if idx == link_idx => {
-                        links_set.insert(text_for_tree_sitter_node(content, &capture.node));
+                        *link_count
+                            .entry(text_for_tree_sitter_node(content, &capture.node))
+                            .or_insert(0) += 1;
                     }
                     idx if idx == flag_idx => {
                         let flag_text = text_for_tree_sitter_node(content, &capture.node);
@@ -179,8 +164,27 @@ impl BeancountData {
                     }
                     idx if idx == account_idx => {
                         let account = text_for_tree_sitter_node(content, &capture.node);
+                        account_definitions.push((
+                            account.clone(),
+                            tree_sitter_node_to_lsp_range(content, &capture.node),
+                        ));
                         accounts.push(account);
                     }
+                    idx if idx == reference_idx => {
+                        let account = text_for_tree_sitter_node(content, &capture.node);
+                        let parent = capture.node.parent();
+                        if let Some(close) = parent.filter(|parent| parent.kind() == "close")
+                            && let Some(date) = close.child_by_field_name("date")
+                        {
+                            account_closes
+                                .push((account.clone(), text_for_tree_sitter_node(content, &date)));
+                        }
+                        account_references.push(AccountReference {
+                            account,
+                            range: tree_sitter_node_to_lsp_range(content, &capture.node),
+                            declaration: parent.is_some_and(|parent| parent.kind() == "open"),
+                        });
+                    }
                     idx if idx == transaction_idx => {
                         // Extract payee/narration with same logic as before
                         let transaction = capture.node;
@@ -231,12 +235,45 @@ impl BeancountData {
 
         // Convert sets to sorted vecs
         tracing::debug!("beancount_data:: processing tags and links");
-        let mut tags: Vec<String> = tags_set.into_iter().collect();
+        let mut tags: Vec<String> = tag_count.keys().cloned().collect();
         tags.sort();
 
-        let mut links: Vec<String> = links_set.into_iter().collect();
+        let mut links: Vec<String> = link_count.keys().cloned().collect();
         links.sort();
 
+        // Occurrence counts feed the workspace index
+        let mut symbol_counts = Vec::new();
+        let mut account_count: std::collections::HashMap<&str, usize> =
+            std::collections::HashMap::new();
+        for account in &accounts {
+            *account_count.entry(account).or_insert(0) += 1;
+        }
+        symbol_counts.extend(
+            account_count
+                .into_iter()
+                .map(|(name, count)| (SymbolKind::Account, name.to_string(), count)),
+        );
+        symbol_counts.extend(
+            payee_count
+                .iter()
+                .map(|(name, count)| (SymbolKind::Payee, name.clone(), *count)),
+        );
+        symbol_counts.extend(
+            narration_count
+                .iter()
+                .map(|(name, count)| (SymbolKind::Narration, name.clone(), *count)),
+        );
+        symbol_counts.extend(
+            tag_count
+                .into_iter()
+                .map(|(name, count)| (SymbolKind::Tag, name, count)),
+        );
+        symbol_counts.extend(
+            link_count
+                .into_iter()
+                .map(|(name, count)| (SymbolKind::Link, name, count)),
+        );
+
         // Sort payees and narrations by frequency
         tracing::debug!("beancount_data:: processing payees and narrations");
         let mut payee_vec: Vec<(String, usize)> = payee_count.into_iter().collect();
@@ -269,6 +306,11 @@ impl BeancountData {
         }
 
         // Sort by frequency (most used first), then alphabetically
+        symbol_counts.extend(
+            commodities_count
+                .iter()
+                .map(|(name, count)| (SymbolKind::Commodity, name.clone(), *count)),
+        );
         let mut commodities: Vec<(String, usize)> = commodities_count.into_iter().collect();
         commodities.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
         let commodities: Vec<String> = commodities.into_iter().map(|(name, _)| name).collect();
@@ -306,6 +348,49 @@ impl BeancountData {
             }
         }
 
+        // Extract option directives in declaration order
+        tracing::debug!("beancount_data:: get options");
+        let option_query = get_option_query();
+        let mut cursor_qry = tree_sitter::QueryCursor::new();
+        let mut matches = cursor_qry.matches(option_query, tree.root_node(), content_bytes);
+
+        let key_idx = option_query
+            .capture_index_for_name("key")
+            .expect("option query should have 'key' capture");
+        let value_idx = option_query
+            .capture_index_for_name("value")
+            .expect("option query should have 'value' capture");
+        let custom_idx = option_query
+            .capture_index_for_name("custom")
+            .expect("option query should have 'custom' capture");
+
+        let mut options = vec![];
+        let mut fava_options = vec![];
+        while let Some(qmatch) = matches.next() {
+            let mut key: Option<String> = None;
+            let mut value: Option<String> = None;
+            let mut custom: Option<String> = None;
+
+            for capture in qmatch.captures {
+                let text = clean_note_text(&text_for_tree_sitter_node(content, &capture.node));
+                if capture.index == key_idx {
+                    key = Some(text);
+                } else if capture.index == value_idx {
+                    value = Some(text);
+                } else if capture.index == custom_idx {
+                    custom = Some(text);
+                }
+            }
+
+            if let (Some(key), Some(value)) = (key, value) {
+                match custom.as_deref() {
+                    None => options.push((key, value)),
+                    Some("fava-option") => fava_options.push((key, value)),
+                    Some(_) => {}
+                }
+            }
+        }
+
         Self {
             accounts: Arc::new(accounts),
             payees: Arc::new(payees),
@@ -315,9 +400,52 @@ impl BeancountData {
             tags: Arc::new(tags),
             links: Arc::new(links),
             commodities: Arc::new(commodities),
+            account_definitions: Arc::new(account_definitions),
+            account_references: Arc::new(account_references),
+            account_closes: Arc::new(account_closes),
+            symbol_counts: Arc::new(symbol_counts),
+            options: Arc::new(options),
+            fava_options: Arc::new(fava_options),
+            ledger_entries: Arc::new(ledger::extract_entries(tree, content)),
         }
     }
 
+    /// Accounts opened in this file with the range of the account name.
+    pub fn get_account_definitions(&self) -> Arc<Vec<(String, lsp_types::Range)>> {
+        Arc::clone(&self.account_definitions)
+    }
+
+    /// Every account named in this file with its range.
+    pub fn get_account_references(&self) -> Arc<Vec<AccountReference>> {
+        Arc::clone(&self.account_references)
+    }
+
+    /// Accounts closed in this file with the date of the `close` directive.
+    pub fn get_account_closes(&self) -> Arc<Vec<(String, String)>> {
+        Arc::clone(&self.account_closes)
+    }
+
+    /// How often each symbol occurs in this file.
+    pub fn get_symbol_counts(&self) -> Arc<Vec<(SymbolKind, String, usize)>> {
+        Arc::clone(&self.symbol_counts)
+    }
+
+    /// `option` directives declared in this file, unquoted, in declaration order.
+    pub fn get_options(&self) -> Arc<Vec<(String, String)>> {
+        Arc::clone(&self.options)
+    }
+
+    /// Fava options declared in this file with `custom "fava-option"`, unquoted, in
+    /// declaration order.
+    pub fn get_fava_options(&self) -> Arc<Vec<(String, String)>> {
+        Arc::clone(&self.fava_options)
+    }
+
+    /// Transactions, pads and balance assertions of this file, for [`ledger::Ledger`].
+    pub fn get_ledger_entries(&self) -> Arc<Vec<LedgerEntry>> {
+        Arc::clone(&self.ledger_entries)
+    }
+
     pub fn get_accounts(&self) -> Arc<Vec<String>> {
         Arc::clone(&self.accounts)
     }
@@ -404,6 +532,10 @@ mod tests {
             query.capture_index_for_name("account").is_some(),
             "Unified query should have 'account' capture"
         );
+        assert!(
+            query.capture_index_for_name("reference").is_some(),
+            "Unified query should have 'reference' capture"
+        );
         assert!(
             query.capture_index_for_name("transaction").is_some(),
             "Unified query should have 'transaction' capture"
@@ -451,6 +583,16 @@ mod tests {
             "Should extract account from open directive"
         );
 
+        // Verify account references, with the open marked as the declaration
+        let references: Vec<_> = data
+            .get_account_references()
+            .iter()
+            .map(|r| (r.account.clone(), r.range.start.line, r.declaration))
+            .collect();
+        assert_eq!(references.len(), 4, "Should extract every account");
+        assert!(references.contains(&("Expenses:Food".to_string(), 3, false)));
+        assert!(references.contains(&("Assets:Checking".to_string(), 8, true)));
+
         // Verify tags
         let tags = data.get_tags();
         assert!(tags.contains(&"#tag".to_string()), "Should extract tags");
@@ -1,4 +1,8 @@
+use crate::config::FeaturesConfig;
+use crate::lsp_ext;
 use crate::providers::semantic_tokens;
+use crate::treesitter_utils;
+use lsp_types::ExecuteCommandOptions;
 use lsp_types::FoldingRangeProviderCapability;
 use lsp_types::InlayHintOptions;
 use lsp_types::InlayHintServerCapabilities;
@@ -11,15 +15,22 @@ use lsp_types::{
     CompletionOptions, OneOf, ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
     TextDocumentSyncOptions,
 };
-
-pub(crate) fn server_capabilities() -> ServerCapabilities {
+use lsp_types::{WorkspaceFoldersServerCapabilities, WorkspaceServerCapabilities};
+
+/// The capabilities advertised to the client. `willSaveWaitUntil` is only requested
+/// with `format_on_save`, since clients wait for its answer before saving.
+pub(crate) fn server_capabilities(
+    features: &FeaturesConfig,
+    format_on_save: bool,
+) -> ServerCapabilities {
     ServerCapabilities {
+        position_encoding: Some(treesitter_utils::position_encoding().kind()),
         text_document_sync: Some(TextDocumentSyncCapability::Options(
             TextDocumentSyncOptions {
                 open_close: Some(true),
                 change: Some(TextDocumentSyncKind::INCREMENTAL),
                 will_save: None,
-                will_save_wait_until: None,
+                will_save_wait_until: (format_on_save && features.formatting).then_some(true),
                 save: Some(lsp_types::TextDocumentSyncSaveOptions::SaveOptions(
                     lsp_types::SaveOptions {
                         include_text: Some(false),
@@ -35,37 +46,74 @@ pub(crate) fn server_capabilities() -> ServerCapabilities {
                 "^".into(),
                 ":".into(),
             ]),
+            resolve_provider: Some(true),
             ..Default::default()
         }),
-        document_formatting_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: features.formatting.then_some(OneOf::Left(true)),
         definition_provider: Some(OneOf::Left(true)),
+        implementation_provider: Some(lsp_types::ImplementationProviderCapability::Simple(true)),
         hover_provider: Some(lsp_types::HoverProviderCapability::Simple(true)),
         references_provider: Some(OneOf::Left(true)),
+        document_highlight_provider: Some(OneOf::Left(true)),
+        code_action_provider: Some(lsp_types::CodeActionProviderCapability::Options(
+            lsp_types::CodeActionOptions {
+                code_action_kinds: Some(vec![lsp_types::CodeActionKind::QUICKFIX]),
+                ..Default::default()
+            },
+        )),
+        code_lens_provider: features.code_lens.then_some(lsp_types::CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
         rename_provider: Some(OneOf::Right(RenameOptions {
             prepare_provider: Some(false),
             work_done_progress_options: WorkDoneProgressOptions {
                 work_done_progress: None,
             },
         })),
-        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
-            SemanticTokensOptions {
+        semantic_tokens_provider: features.semantic_tokens.then(|| {
+            SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
                 legend: semantic_tokens::legend(),
                 full: Some(SemanticTokensFullOptions::Bool(true)),
                 range: None,
                 ..Default::default()
-            },
-        )),
-        inlay_hint_provider: Some(OneOf::Right(InlayHintServerCapabilities::Options(
-            InlayHintOptions {
+            })
+        }),
+        inlay_hint_provider: features.inlay_hints.then_some(OneOf::Right(
+            InlayHintServerCapabilities::Options(InlayHintOptions {
                 resolve_provider: Some(false),
                 work_done_progress_options: WorkDoneProgressOptions {
                     work_done_progress: None,
                 },
-            },
-        ))),
+            }),
+        )),
         folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
         document_symbol_provider: Some(OneOf::Left(true)),
         workspace_symbol_provider: Some(OneOf::Left(true)),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: lsp_ext::COMMANDS.iter().map(|c| c.to_string()).collect(),
+            work_done_progress_options: WorkDoneProgressOptions {
+                work_done_progress: None,
+            },
+        }),
+        workspace: Some(WorkspaceServerCapabilities {
+            workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                supported: Some(true),
+                change_notifications: Some(OneOf::Left(true)),
+            }),
+            file_operations: Some(lsp_types::WorkspaceFileOperationsServerCapabilities {
+                did_create: Some(lsp_types::FileOperationRegistrationOptions {
+                    filters: vec![lsp_types::FileOperationFilter {
+                        scheme: Some("file".to_string()),
+                        pattern: lsp_types::FileOperationPattern {
+                            glob: "**/*.{bean,beancount}".to_string(),
+                            matches: Some(lsp_types::FileOperationPatternKind::File),
+                            options: None,
+                        },
+                    }],
+                }),
+                ..Default::default()
+            }),
+        }),
         ..Default::default()
     }
 }
@@ -74,9 +122,90 @@ pub(crate) fn server_capabilities() -> ServerCapabilities {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_disabled_features_are_not_advertised() {
+        let features = FeaturesConfig {
+            semantic_tokens: false,
+            code_lens: false,
+            inlay_hints: false,
+            formatting: false,
+            commit_characters: false,
+        };
+        let caps = server_capabilities(&features, false);
+        assert!(caps.semantic_tokens_provider.is_none());
+        assert!(caps.code_lens_provider.is_none());
+        assert!(caps.inlay_hint_provider.is_none());
+        assert!(caps.document_formatting_provider.is_none());
+        assert!(caps.completion_provider.is_some());
+
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
+        assert!(caps.semantic_tokens_provider.is_some());
+        assert!(caps.code_lens_provider.is_some());
+        assert!(caps.inlay_hint_provider.is_some());
+        assert!(caps.document_formatting_provider.is_some());
+    }
+
+    #[test]
+    fn test_will_save_wait_until_with_format_on_save() {
+        let will_save_wait_until = |features: &FeaturesConfig| match server_capabilities(
+            features, true,
+        )
+        .text_document_sync
+        {
+            Some(TextDocumentSyncCapability::Options(options)) => options.will_save_wait_until,
+            _ => panic!("Expected TextDocumentSyncOptions"),
+        };
+        assert_eq!(will_save_wait_until(&FeaturesConfig::default()), Some(true));
+        let features = FeaturesConfig {
+            formatting: false,
+            ..FeaturesConfig::default()
+        };
+        assert_eq!(will_save_wait_until(&features), None);
+    }
+
+    #[test]
+    fn test_workspace_folder_capabilities() {
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
+        let folders = caps
+            .workspace
+            .and_then(|workspace| workspace.workspace_folders)
+            .expect("workspace folders should be configured");
+        assert_eq!(folders.supported, Some(true));
+        assert_eq!(folders.change_notifications, Some(OneOf::Left(true)));
+    }
+
+    #[test]
+    fn test_execute_command_capabilities() {
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
+        let commands = caps.execute_command_provider.unwrap().commands;
+        assert_eq!(
+            commands,
+            vec![
+                "beancount.query",
+                "beancount.runQuery",
+                "beancount.importTransactions",
+                "beancount.importCsv",
+                "beancount.favaLink",
+                "beancount.openFava",
+                "beancount.updatePrices",
+                "beancount.payeeHistory",
+                "beancount.insertTransaction"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_position_encoding_defaults_to_utf16() {
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
+        assert_eq!(
+            caps.position_encoding,
+            Some(lsp_types::PositionEncodingKind::UTF16)
+        );
+    }
+
     #[test]
     fn test_text_document_sync_capabilities() {
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         // Verify text_document_sync is configured
         let sync = caps
@@ -103,9 +232,9 @@ mod tests {
 
     #[test]
     fn test_will_save_capabilities() {
-        // Neither will_save nor will_save_wait_until are implemented
-        // Formatting is controlled by the client via documentFormattingProvider
-        let caps = server_capabilities();
+        // will_save is not implemented, and will_save_wait_until needs format_on_save
+        // Formatting is otherwise controlled by the client via documentFormattingProvider
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         let sync = caps
             .text_document_sync
@@ -119,7 +248,7 @@ mod tests {
                 );
                 assert_eq!(
                     options.will_save_wait_until, None,
-                    "will_save_wait_until should be disabled - formatting controlled by client"
+                    "will_save_wait_until should be disabled without format_on_save"
                 );
             }
             _ => panic!("Expected TextDocumentSyncOptions"),
@@ -128,7 +257,7 @@ mod tests {
 
     #[test]
     fn test_completion_capabilities() {
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         let completion = caps
             .completion_provider
@@ -168,7 +297,7 @@ mod tests {
 
     #[test]
     fn test_formatting_capability() {
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         assert!(
             caps.document_formatting_provider.is_some(),
@@ -185,7 +314,7 @@ mod tests {
 
     #[test]
     fn test_definition_capability() {
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         assert!(
             caps.definition_provider.is_some(),
@@ -202,7 +331,7 @@ mod tests {
 
     #[test]
     fn test_references_capability() {
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         assert!(
             caps.references_provider.is_some(),
@@ -219,7 +348,7 @@ mod tests {
 
     #[test]
     fn test_rename_capability() {
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         let rename = caps.rename_provider.expect("rename_provider should be set");
 
@@ -237,7 +366,7 @@ mod tests {
 
     #[test]
     fn test_semantic_tokens_capability() {
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         let semantic = caps
             .semantic_tokens_provider
@@ -275,7 +404,7 @@ mod tests {
         // This test documents which capabilities are advertised
         // and serves as a regression test to ensure we don't advertise
         // capabilities without implementing handlers
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         // Implemented capabilities (have handlers in server.rs)
         assert!(
@@ -296,6 +425,10 @@ mod tests {
             "references is implemented"
         );
         assert!(caps.rename_provider.is_some(), "rename is implemented");
+        assert!(
+            caps.document_highlight_provider.is_some(),
+            "document_highlight is implemented"
+        );
         assert!(
             caps.semantic_tokens_provider.is_some(),
             "semantic_tokens is implemented"
@@ -318,9 +451,9 @@ mod tests {
             caps.type_definition_provider, None,
             "type_definition is not implemented"
         );
-        assert_eq!(
-            caps.implementation_provider, None,
-            "implementation is not implemented"
+        assert!(
+            caps.implementation_provider.is_some(),
+            "implementation is implemented"
         );
         assert!(
             caps.document_symbol_provider.is_some(),
@@ -330,13 +463,13 @@ mod tests {
             caps.workspace_symbol_provider.is_some(),
             "workspace_symbol is implemented"
         );
-        assert_eq!(
-            caps.code_action_provider, None,
-            "code_action is not implemented"
+        assert!(
+            caps.code_action_provider.is_some(),
+            "code_action is implemented"
         );
-        assert_eq!(
-            caps.code_lens_provider, None,
-            "code_lens is not implemented"
+        assert!(
+            caps.code_lens_provider.is_some(),
+            "code_lens is implemented"
         );
         assert_eq!(
             caps.document_link_provider, None,
@@ -391,7 +524,7 @@ mod tests {
         use crate::server::LspServerStateSnapshot;
 
         // Get the advertised capabilities
-        let caps = server_capabilities();
+        let caps = server_capabilities(&FeaturesConfig::default(), false);
 
         // Completion capability -> handlers::text_document::completion
         if caps.completion_provider.is_some() {
@@ -421,6 +554,25 @@ mod tests {
                 handlers::text_document::handle_references;
         }
 
+        // Document highlight capability -> handlers::text_document::document_highlight
+        if caps.document_highlight_provider.is_some() {
+            let _handler: fn(
+                LspServerStateSnapshot,
+                lsp_types::DocumentHighlightParams,
+            )
+                -> anyhow::Result<Option<Vec<lsp_types::DocumentHighlight>>> =
+                handlers::text_document::document_highlight;
+        }
+
+        // Code lens capability -> handlers::text_document::code_lens
+        if caps.code_lens_provider.is_some() {
+            let _handler: fn(
+                LspServerStateSnapshot,
+                lsp_types::CodeLensParams,
+            ) -> anyhow::Result<Option<Vec<lsp_types::CodeLens>>> =
+                handlers::text_document::code_lens;
+        }
+
         // Definition capability -> handlers::text_document::handle_definition
         if caps.definition_provider.is_some() {
             let _handler: fn(
@@ -430,6 +582,15 @@ mod tests {
                 -> anyhow::Result<Option<lsp_types::GotoDefinitionResponse>> =
                 handlers::text_document::handle_definition;
         }
+        // Implementation capability -> handlers::text_document::handle_implementation
+        if caps.implementation_provider.is_some() {
+            let _handler: fn(
+                LspServerStateSnapshot,
+                lsp_types::request::GotoImplementationParams,
+            ) -> anyhow::Result<
+                Option<lsp_types::request::GotoImplementationResponse>,
+            > = handlers::text_document::handle_implementation;
+        }
         // Hover capability -> handlers::text_document::hover
         if caps.hover_provider.is_some() {
             let _handler: fn(
@@ -495,6 +656,14 @@ mod tests {
                 handlers::text_document::workspace_symbol;
         }
 
+        // Execute command capability -> handlers::workspace::execute_command
+        if caps.execute_command_provider.is_some() {
+            let _handler: fn(
+                LspServerStateSnapshot,
+                lsp_types::ExecuteCommandParams,
+            ) -> anyhow::Result<Option<serde_json::Value>> = handlers::workspace::execute_command;
+        }
+
         // Text document sync notifications (these don't return responses)
         if let Some(TextDocumentSyncCapability::Options(sync_options)) = &caps.text_document_sync {
             // did_open handler
@@ -529,14 +698,14 @@ mod tests {
                 ) -> anyhow::Result<()> = handlers::text_document::did_save;
             }
 
-            // will_save and will_save_wait_until are not implemented
+            // will_save is not implemented, will_save_wait_until is off by default
             assert_eq!(
                 sync_options.will_save, None,
                 "will_save should not be advertised without a handler implementation"
             );
             assert_eq!(
                 sync_options.will_save_wait_until, None,
-                "will_save_wait_until should not be used for formatting (client controls formatting)"
+                "will_save_wait_until should only be used for formatting with format_on_save"
             );
         }
 
@@ -0,0 +1,250 @@
+//! User-configured commands validating a journal beyond bean-check.
+//!
+//! The command is run with its arguments followed by the journal path, and prints one
+//! JSON object per line on stdout:
+//!
+//! ```json
+//! {"file": "trips.beancount", "line": 12, "message": "missing ^trip link", "severity": "warning"}
+//! ```
+//!
+//! `line` is 1-based, 0 for the whole file. `file` defaults to the journal and is
+//! relative to its directory. `column`, `end_line` and `end_column` (1-based) narrow
+//! the range, `severity` is "error" (the default), "warning", "information" or "hint",
+//! and `code` identifies the rule. Lines that are not such objects are ignored.
+
+use super::CheckRun;
+use super::interruptible::POLL_INTERVAL;
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// A command run after bean-check whose output is published as diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalChecker {
+    /// Source of the diagnostics
+    pub name: String,
+    pub command: PathBuf,
+    /// Arguments passed before the journal file
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExternalDiagnostic {
+    file: Option<PathBuf>,
+    line: u32,
+    column: Option<u32>,
+    end_line: Option<u32>,
+    end_column: Option<u32>,
+    message: String,
+    severity: Option<String>,
+    code: Option<String>,
+}
+
+impl ExternalChecker {
+    pub fn new(command: PathBuf) -> Self {
+        let name = command.file_stem().map_or_else(
+            || "external".to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        Self {
+            name,
+            command,
+            args: Vec::new(),
+        }
+    }
+
+    /// Runs the command on `journal`, killing it when it takes longer than `timeout` or
+    /// `run` is superseded.
+    pub fn check(
+        &self,
+        journal: &Path,
+        timeout: Option<Duration>,
+        run: &CheckRun,
+    ) -> Result<HashMap<PathBuf, Vec<lsp_types::Diagnostic>>> {
+        if run.is_superseded() {
+            bail!("{} was superseded by a newer check", self.name);
+        }
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .arg(journal)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("failed to run {}", self.command.display()))?;
+
+        let mut stdout = child.stdout.take().context("stdout is not captured")?;
+        let reader = std::thread::spawn(move || {
+            let mut output = String::new();
+            stdout.read_to_string(&mut output).map(|_| output)
+        });
+
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if run.is_superseded() {
+                let _ = child.kill();
+                let _ = child.wait();
+                bail!("{} was superseded by a newer check", self.name);
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                let _ = child.kill();
+                let _ = child.wait();
+                bail!(
+                    "{} timed out after {:?}",
+                    self.name,
+                    timeout.unwrap_or_default()
+                );
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        let output = reader
+            .join()
+            .map_err(|_| anyhow::anyhow!("failed to read the output of {}", self.name))??;
+        if !status.success() {
+            tracing::debug!("{} exited with {}", self.name, status);
+        }
+
+        Ok(self.parse_output(&output, journal))
+    }
+
+    fn parse_output(
+        &self,
+        output: &str,
+        journal: &Path,
+    ) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+        let journal_dir = journal.parent().unwrap_or(Path::new(""));
+        let mut diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+        for line in output.lines().filter(|line| !line.trim().is_empty()) {
+            let diagnostic: ExternalDiagnostic = match serde_json::from_str(line) {
+                Ok(diagnostic) => diagnostic,
+                Err(e) => {
+                    tracing::debug!("Ignoring output of {}: {:?}: {}", self.name, line, e);
+                    continue;
+                }
+            };
+            let file = match diagnostic.file {
+                Some(file) => journal_dir.join(file),
+                None => journal.to_path_buf(),
+            };
+            let severity = match diagnostic.severity.as_deref() {
+                Some("warning") => lsp_types::DiagnosticSeverity::WARNING,
+                Some("information" | "info") => lsp_types::DiagnosticSeverity::INFORMATION,
+                Some("hint") => lsp_types::DiagnosticSeverity::HINT,
+                _ => lsp_types::DiagnosticSeverity::ERROR,
+            };
+
+            let line = diagnostic.line.saturating_sub(1);
+            let start = diagnostic
+                .column
+                .map_or(0, |column| column.saturating_sub(1));
+            let end_line = diagnostic
+                .end_line
+                .map_or(line, |end_line| end_line.saturating_sub(1));
+            let end = diagnostic
+                .end_column
+                .map_or(u32::MAX, |column| column.saturating_sub(1));
+            diagnostics
+                .entry(file)
+                .or_default()
+                .push(lsp_types::Diagnostic {
+                    range: lsp_types::Range {
+                        start: lsp_types::Position {
+                            line,
+                            character: start,
+                        },
+                        end: lsp_types::Position {
+                            line: end_line,
+                            character: end,
+                        },
+                    },
+                    severity: Some(severity),
+                    code: diagnostic.code.map(lsp_types::NumberOrString::String),
+                    source: Some(self.name.clone()),
+                    message: diagnostic.message,
+                    ..Default::default()
+                });
+        }
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkers::CheckRuns;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_parse_output() {
+        let checker = ExternalChecker::new(PathBuf::from("/usr/bin/trip-links.py"));
+        assert_eq!(checker.name, "trip-links");
+
+        let output = r#"checking...
+{"line": 3, "message": "missing ^trip link", "severity": "warning", "code": "trip-link"}
+{"file": "2024.beancount", "line": 0, "column": 5, "end_column": 9, "message": "bad"}
+"#;
+        let journal = Path::new("/ledger/main.beancount");
+        let diagnostics = checker.parse_output(output, journal);
+        assert_eq!(diagnostics.len(), 2);
+
+        let main = &diagnostics[journal][0];
+        assert_eq!(main.range.start, lsp_types::Position::new(2, 0));
+        assert_eq!(main.range.end, lsp_types::Position::new(2, u32::MAX));
+        assert_eq!(main.severity, Some(lsp_types::DiagnosticSeverity::WARNING));
+        assert_eq!(
+            main.code,
+            Some(lsp_types::NumberOrString::String("trip-link".to_string()))
+        );
+        assert_eq!(main.source.as_deref(), Some("trip-links"));
+
+        let included = &diagnostics[Path::new("/ledger/2024.beancount")][0];
+        assert_eq!(included.range.start, lsp_types::Position::new(0, 4));
+        assert_eq!(included.range.end, lsp_types::Position::new(0, 8));
+        assert_eq!(
+            included.severity,
+            Some(lsp_types::DiagnosticSeverity::ERROR)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_runs_command_with_journal() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = dir.path().join("main.beancount");
+        std::fs::write(&journal, "").unwrap();
+
+        let mut checker = ExternalChecker::new(PathBuf::from("sh"));
+        checker.args = vec![
+            "-c".to_string(),
+            r#"echo "{\"line\": 1, \"message\": \"checked $1\"}""#.to_string(),
+            "sh".to_string(),
+        ];
+        let run = Arc::new(CheckRuns::default()).begin(journal.clone());
+        let diagnostics = checker.check(&journal, None, &run).unwrap();
+        assert_eq!(
+            diagnostics[&journal][0].message,
+            format!("checked {}", journal.display())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_times_out() {
+        let mut checker = ExternalChecker::new(PathBuf::from("sh"));
+        checker.args = vec!["-c".to_string(), "sleep 10".to_string()];
+        let run = Arc::new(CheckRuns::default()).begin(PathBuf::from("main.beancount"));
+        let result = checker.check(
+            Path::new("main.beancount"),
+            Some(Duration::from_millis(200)),
+            &run,
+        );
+        assert!(result.unwrap_err().to_string().contains("timed out"));
+    }
+}
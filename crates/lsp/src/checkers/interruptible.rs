@@ -0,0 +1,345 @@
+//! Running a checker on its own thread, giving up when it takes too long or a newer
+//! check of the same journal supersedes it.
+
+use super::BeancountChecker;
+use super::types::BeancountCheckResult;
+use anyhow::{Result, bail};
+use crossbeam_channel::RecvTimeoutError;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How often a waiting check looks whether it was superseded.
+pub(super) const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Diagnostics by file.
+type FileDiagnostics = HashMap<PathBuf, Vec<lsp_types::Diagnostic>>;
+
+/// The latest checks started for each journal, the files their diagnostics went to and
+/// what the checkers reported last.
+#[derive(Debug, Default)]
+pub struct CheckRuns {
+    next_id: AtomicU64,
+    latest: Mutex<HashMap<PathBuf, u64>>,
+    latest_lints: Mutex<HashMap<PathBuf, u64>>,
+    published: Mutex<HashMap<PathBuf, HashSet<PathBuf>>>,
+    checker_diagnostics: Mutex<HashMap<PathBuf, FileDiagnostics>>,
+}
+
+impl CheckRuns {
+    /// Starts a check of `journal`, superseding the checks and lints runs still
+    /// running for it.
+    pub fn begin(self: &Arc<Self>, journal: PathBuf) -> CheckRun {
+        let run = self.begin_lints(journal);
+        self.latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(run.journal.clone(), run.id);
+        CheckRun {
+            lints_only: false,
+            ..run
+        }
+    }
+
+    /// Starts a run of only the server's own lints for `journal`, superseding the lints
+    /// runs still running for it but not the checks.
+    pub fn begin_lints(self: &Arc<Self>, journal: PathBuf) -> CheckRun {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.latest_lints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(journal.clone(), id);
+        CheckRun {
+            runs: Arc::clone(self),
+            journal,
+            id,
+            lints_only: true,
+        }
+    }
+}
+
+/// One check of a journal, see [`CheckRuns::begin`].
+#[derive(Debug, Clone)]
+pub struct CheckRun {
+    runs: Arc<CheckRuns>,
+    journal: PathBuf,
+    id: u64,
+    lints_only: bool,
+}
+
+impl CheckRun {
+    /// Whether a newer run of the same journal that supersedes this one was started.
+    pub fn is_superseded(&self) -> bool {
+        let latest = if self.lints_only {
+            &self.runs.latest_lints
+        } else {
+            &self.runs.latest
+        };
+        latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&self.journal)
+            .is_some_and(|latest| *latest != self.id)
+    }
+
+    /// Records what the checkers reported for the journal, for the lints runs to
+    /// publish along with their own diagnostics.
+    pub fn replace_checker_diagnostics(&self, diagnostics: FileDiagnostics) {
+        self.runs
+            .checker_diagnostics
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(self.journal.clone(), diagnostics);
+    }
+
+    /// What the checkers reported for the journal the last time they ran.
+    pub fn checker_diagnostics(&self) -> FileDiagnostics {
+        self.runs
+            .checker_diagnostics
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&self.journal)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Records the files this check published diagnostics for, returning those the
+    /// previous check of the journal published to and this one did not.
+    pub fn replace_published(&self, files: HashSet<PathBuf>) -> HashSet<PathBuf> {
+        let mut published = self
+            .runs
+            .published
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let previous = published
+            .insert(self.journal.clone(), files.clone())
+            .unwrap_or_default();
+        previous.difference(&files).cloned().collect()
+    }
+}
+
+/// Why an [`InterruptibleChecker`] gave up on its check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interruption {
+    TimedOut(Duration),
+    Superseded,
+}
+
+/// Wraps a checker so that waiting for it ends after `timeout` or once `run` is
+/// superseded, which makes `check` fail. The abandoned check is cancelled: checkers
+/// running a process kill it, in-process ones finish in the background and their
+/// result is dropped.
+pub struct InterruptibleChecker {
+    inner: Arc<dyn BeancountChecker>,
+    timeout: Option<Duration>,
+    run: CheckRun,
+    interruption: Mutex<Option<Interruption>>,
+}
+
+impl InterruptibleChecker {
+    pub fn new(inner: Arc<dyn BeancountChecker>, timeout: Option<Duration>, run: CheckRun) -> Self {
+        Self {
+            inner,
+            timeout,
+            run,
+            interruption: Mutex::new(None),
+        }
+    }
+
+    /// Why the last check was given up, if it was.
+    pub fn interruption(&self) -> Option<Interruption> {
+        *self.interruption.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn interrupt(&self, interruption: Interruption) {
+        *self.interruption.lock().unwrap_or_else(|e| e.into_inner()) = Some(interruption);
+    }
+}
+
+impl BeancountChecker for InterruptibleChecker {
+    fn check(&self, journal_file: &Path) -> Result<BeancountCheckResult> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        let inner = Arc::clone(&self.inner);
+        let journal = journal_file.to_path_buf();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancel = Arc::clone(&cancelled);
+        std::thread::Builder::new()
+            .name("bean-check".to_string())
+            .spawn(move || {
+                let result = inner.check_cancellable(&journal, &|| cancel.load(Ordering::Relaxed));
+                // The receiver is gone when the check was given up
+                let _ = sender.send(result);
+            })?;
+        // Giving up in any way below cancels the check
+        struct CancelOnDrop(Arc<AtomicBool>);
+        impl Drop for CancelOnDrop {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+        let _cancel = CancelOnDrop(cancelled);
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let wait = deadline.map_or(POLL_INTERVAL, |deadline| {
+                POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now()))
+            });
+            match receiver.recv_timeout(wait) {
+                Ok(result) => return result,
+                Err(RecvTimeoutError::Disconnected) => {
+                    bail!("{} stopped without a result", self.inner.name())
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    if self.run.is_superseded() {
+                        self.interrupt(Interruption::Superseded);
+                        bail!("{} was superseded by a newer check", self.inner.name());
+                    }
+                    if let (Some(deadline), Some(timeout)) = (deadline, self.timeout)
+                        && Instant::now() >= deadline
+                    {
+                        self.interrupt(Interruption::TimedOut(timeout));
+                        bail!("{} timed out after {:?}", self.inner.name(), timeout);
+                    }
+                }
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn is_available(&self) -> bool {
+        self.inner.is_available()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sleeps before reporting a clean journal.
+    struct SlowChecker(Duration);
+
+    impl BeancountChecker for SlowChecker {
+        fn check(&self, _journal_file: &Path) -> Result<BeancountCheckResult> {
+            std::thread::sleep(self.0);
+            Ok(BeancountCheckResult {
+                errors: Vec::new(),
+                flagged_entries: Vec::new(),
+            })
+        }
+
+        fn name(&self) -> &'static str {
+            "Slow"
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+    }
+
+    fn checker(delay: Duration, timeout: Duration, run: CheckRun) -> InterruptibleChecker {
+        InterruptibleChecker::new(Arc::new(SlowChecker(delay)), Some(timeout), run)
+    }
+
+    #[test]
+    fn test_finishes_within_timeout() {
+        let runs = Arc::new(CheckRuns::default());
+        let checker = checker(
+            Duration::ZERO,
+            Duration::from_secs(10),
+            runs.begin("main.beancount".into()),
+        );
+        assert!(checker.check(Path::new("main.beancount")).is_ok());
+        assert_eq!(checker.interruption(), None);
+    }
+
+    #[test]
+    fn test_times_out() {
+        let runs = Arc::new(CheckRuns::default());
+        let timeout = Duration::from_millis(50);
+        let checker = checker(
+            Duration::from_secs(5),
+            timeout,
+            runs.begin("main.beancount".into()),
+        );
+        let started = Instant::now();
+        assert!(checker.check(Path::new("main.beancount")).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            checker.interruption(),
+            Some(Interruption::TimedOut(timeout))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timed_out_process_is_killed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let marker = temp_dir.path().join("finished");
+        let script = format!("sleep 1; touch {}", marker.display());
+        let inner = super::super::SystemCallChecker::new(PathBuf::from("sh"))
+            .with_args(vec!["-c".to_string(), script]);
+        let runs = Arc::new(CheckRuns::default());
+        let checker = InterruptibleChecker::new(
+            Arc::new(inner),
+            Some(Duration::from_millis(50)),
+            runs.begin("main.beancount".into()),
+        );
+        assert!(checker.check(Path::new("main.beancount")).is_err());
+
+        std::thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists(), "the abandoned check kept running");
+    }
+
+    #[test]
+    fn test_superseded_by_newer_check_of_the_same_journal() {
+        let runs = Arc::new(CheckRuns::default());
+        let first = runs.begin("main.beancount".into());
+        let other_journal = runs.begin("other.beancount".into());
+        assert!(!first.is_superseded());
+        assert!(!other_journal.is_superseded());
+
+        let _second = runs.begin("main.beancount".into());
+        assert!(first.is_superseded());
+        assert!(!other_journal.is_superseded());
+
+        let checker = checker(Duration::from_secs(5), Duration::from_secs(10), first);
+        assert!(checker.check(Path::new("main.beancount")).is_err());
+        assert_eq!(checker.interruption(), Some(Interruption::Superseded));
+    }
+
+    #[test]
+    fn test_lints_runs_do_not_supersede_checks() {
+        let runs = Arc::new(CheckRuns::default());
+        let check = runs.begin("main.beancount".into());
+        let lints = runs.begin_lints("main.beancount".into());
+        assert!(!check.is_superseded());
+        assert!(!lints.is_superseded());
+
+        let newer_lints = runs.begin_lints("main.beancount".into());
+        assert!(lints.is_superseded());
+        assert!(!check.is_superseded());
+
+        let _newer_check = runs.begin("main.beancount".into());
+        assert!(check.is_superseded());
+        assert!(newer_lints.is_superseded());
+    }
+
+    #[test]
+    fn test_replace_published_returns_stale_files() {
+        let runs = Arc::new(CheckRuns::default());
+        let files = |names: &[&str]| names.iter().map(PathBuf::from).collect::<HashSet<_>>();
+
+        let first = runs.begin("main.beancount".into());
+        assert!(first.replace_published(files(&["a", "b"])).is_empty());
+        let other = runs.begin("other.beancount".into());
+        assert!(other.replace_published(files(&["c"])).is_empty());
+
+        let second = runs.begin("main.beancount".into());
+        assert_eq!(second.replace_published(files(&["b"])), files(&["a"]));
+    }
+}
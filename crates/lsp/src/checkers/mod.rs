@@ -1,7 +1,15 @@
+use crate::beancount_data::BeancountData;
+use crate::ledger_options::LedgerOptions;
 use anyhow::Result;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
 use which::which;
 
+mod external;
+mod interruptible;
+mod native;
 #[cfg(feature = "python-embedded")]
 mod pyo3_embedded;
 #[cfg(not(feature = "python-embedded"))]
@@ -10,6 +18,9 @@ mod python_checker;
 pub mod system_call;
 pub mod types;
 
+pub use external::ExternalChecker;
+pub use interruptible::{CheckRun, CheckRuns, InterruptibleChecker, Interruption};
+pub use native::NativeChecker;
 #[cfg(feature = "python-embedded")]
 pub use pyo3_embedded::PyO3EmbeddedChecker;
 #[cfg(not(feature = "python-embedded"))]
@@ -34,6 +45,19 @@ pub trait BeancountChecker: Send + Sync {
     /// * `Err(anyhow::Error)` - Execution error (command not found, parsing failed, etc.)
     fn check(&self, journal_file: &Path) -> Result<BeancountCheckResult>;
 
+    /// Like [`check`](Self::check), but stops early once `cancelled` returns true.
+    ///
+    /// Checkers that run a process kill it. The default runs `check` to completion,
+    /// since an in-process check can't be interrupted.
+    fn check_cancellable(
+        &self,
+        journal_file: &Path,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<BeancountCheckResult> {
+        let _ = cancelled;
+        self.check(journal_file)
+    }
+
     /// Get a human-readable name for this checker implementation.
     /// Used for logging and debugging purposes.
     fn name(&self) -> &'static str;
@@ -43,6 +67,94 @@ pub trait BeancountChecker: Send + Sync {
     fn is_available(&self) -> bool;
 }
 
+/// Runs `command` like [`Command::output`], killing it once `cancelled` returns true.
+fn output_unless_cancelled(
+    command: &mut Command,
+    cancelled: &dyn Fn() -> bool,
+) -> std::io::Result<Output> {
+    fn read_to_end(
+        pipe: Option<impl Read + Send + 'static>,
+    ) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            if let Some(mut pipe) = pipe {
+                pipe.read_to_end(&mut output)?;
+            }
+            Ok(output)
+        })
+    }
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Both pipes are drained while waiting, so a chatty process can't block on them
+    let stdout = read_to_end(child.stdout.take());
+    let stderr = read_to_end(child.stderr.take());
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "the check was cancelled",
+            ));
+        }
+        std::thread::sleep(interruptible::POLL_INTERVAL);
+    };
+    let join = |reader: std::thread::JoinHandle<std::io::Result<Vec<u8>>>| {
+        reader
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("reading the output panicked")))
+    };
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
+/// Puts the directory of `journal_file` first on the `PYTHONPATH` of `command` when the
+/// journal declares `option "insert_pythonpath" "TRUE"`, so that plugins kept next to
+/// the journal can be imported.
+fn insert_pythonpath<'a>(command: &'a mut Command, journal_file: &Path) -> &'a mut Command {
+    let Ok(text) = std::fs::read_to_string(journal_file) else {
+        return command;
+    };
+    let mut parser = tree_sitter_beancount::tree_sitter::Parser::new();
+    if parser
+        .set_language(&tree_sitter_beancount::language())
+        .is_err()
+    {
+        return command;
+    }
+    let Some(tree) = parser.parse(&text, None) else {
+        return command;
+    };
+    let data = BeancountData::new(&tree, &ropey::Rope::from_str(&text));
+    if !LedgerOptions::from_data(&data).insert_pythonpath {
+        return command;
+    }
+
+    let dir = match journal_file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let inherited = std::env::var_os("PYTHONPATH").unwrap_or_default();
+    let paths = std::iter::once(dir.to_path_buf()).chain(std::env::split_paths(&inherited));
+    match std::env::join_paths(paths) {
+        Ok(python_path) => command.env("PYTHONPATH", python_path),
+        Err(e) => {
+            tracing::warn!("Not inserting {} into PYTHONPATH: {}", dir.display(), e);
+            command
+        }
+    }
+}
+
 /// Configuration for bean-check execution method selection.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BeancountCheckMethod {
@@ -52,6 +164,8 @@ pub enum BeancountCheckMethod {
     PythonEmbedded,
     /// Use python interpreter to run embedded bean_check code
     PythonSystem,
+    /// Use the language server's own validation, without Python (fewer checks)
+    Native,
 }
 
 impl BeancountCheckMethod {
@@ -60,6 +174,7 @@ impl BeancountCheckMethod {
             BeancountCheckMethod::SystemCall => "system",
             BeancountCheckMethod::PythonEmbedded => "python-embedded",
             BeancountCheckMethod::PythonSystem => "python-system",
+            BeancountCheckMethod::Native => "native",
         }
     }
 }
@@ -72,6 +187,7 @@ impl std::str::FromStr for BeancountCheckMethod {
             "system" => Ok(BeancountCheckMethod::SystemCall),
             "python-embedded" | "pyo3" => Ok(BeancountCheckMethod::PythonEmbedded),
             "python-system" => Ok(BeancountCheckMethod::PythonSystem),
+            "native" => Ok(BeancountCheckMethod::Native),
             _ => Err(format!("invalid BeancountCheckMethod: {:?}", s)),
         }
     }
@@ -84,14 +200,18 @@ impl std::fmt::Display for BeancountCheckMethod {
 }
 
 /// Configuration options for bean-check execution.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BeancountCheckConfig {
     /// Which execution method to use
     pub method: Option<BeancountCheckMethod>,
     /// Path to bean-check executable (for SystemCall method)
     pub bean_check_cmd: Option<PathBuf>,
+    /// Arguments passed to it before the journal file (for SystemCall method)
+    pub args: Vec<String>,
     /// Path to Python executable (for Python method)
     pub python_cmd: Option<PathBuf>,
+    /// How long to wait for a check before publishing without its results
+    pub timeout: Option<Duration>,
 }
 
 impl Default for BeancountCheckConfig {
@@ -105,7 +225,9 @@ impl BeancountCheckConfig {
         Self {
             method: None, // None means auto-discovery
             bean_check_cmd: None,
+            args: Vec::new(),
             python_cmd: None,
+            timeout: Some(Duration::from_secs(120)),
         }
     }
 }
@@ -206,54 +328,63 @@ fn resolve_python_cmd(config: &BeancountCheckConfig, root_dir: &Path) -> Option<
 }
 
 fn resolve_bean_check_cmd(config: &BeancountCheckConfig, root_dir: &Path) -> Option<PathBuf> {
-    if let Some(cmd) = &config.bean_check_cmd
+    resolve_tool_cmd("bean-check", config.bean_check_cmd.as_ref(), root_dir)
+}
+
+/// Finds `bean-query` like `bean-check`: the configured command, the workspace venv,
+/// then `PATH`.
+pub(crate) fn resolve_bean_query_cmd(
+    configured: Option<&PathBuf>,
+    root_dir: &Path,
+) -> Option<PathBuf> {
+    resolve_tool_cmd("bean-query", configured, root_dir)
+}
+
+/// Finds `bean-price` like `bean-check`.
+pub(crate) fn resolve_bean_price_cmd(
+    configured: Option<&PathBuf>,
+    root_dir: &Path,
+) -> Option<PathBuf> {
+    resolve_tool_cmd("bean-price", configured, root_dir)
+}
+
+fn resolve_tool_cmd(tool: &str, configured: Option<&PathBuf>, root_dir: &Path) -> Option<PathBuf> {
+    if let Some(cmd) = configured
         && !cmd.as_os_str().is_empty()
     {
-        tracing::info!("Using configured bean_check_cmd: {}", cmd.to_string_lossy());
+        tracing::info!("Using configured {tool}: {}", cmd.to_string_lossy());
         return Some(cmd.clone());
     }
 
     let venv_dir = root_dir.join(".venv");
     if cfg!(unix) {
-        let venv_bean_check = venv_dir.join("bin").join("bean-check");
-        if venv_bean_check.is_file() {
-            tracing::info!(
-                "Using venv bean-check: {}",
-                venv_bean_check.to_string_lossy()
-            );
-            return Some(venv_bean_check);
+        let venv_tool = venv_dir.join("bin").join(tool);
+        if venv_tool.is_file() {
+            tracing::info!("Using venv {tool}: {}", venv_tool.to_string_lossy());
+            return Some(venv_tool);
         }
     }
 
     if cfg!(windows) {
-        let venv_bean_check = venv_dir.join("Scripts").join("bean-check.exe");
-        if venv_bean_check.is_file() {
-            tracing::info!(
-                "Using venv bean-check: {}",
-                venv_bean_check.to_string_lossy()
-            );
-            return Some(venv_bean_check);
+        let venv_tool = venv_dir.join("Scripts").join(format!("{tool}.exe"));
+        if venv_tool.is_file() {
+            tracing::info!("Using venv {tool}: {}", venv_tool.to_string_lossy());
+            return Some(venv_tool);
         }
-        let venv_bean_check = venv_dir.join("Scripts").join("bean-check");
-        if venv_bean_check.is_file() {
-            tracing::info!(
-                "Using venv bean-check: {}",
-                venv_bean_check.to_string_lossy()
-            );
-            return Some(venv_bean_check);
+        let venv_tool = venv_dir.join("Scripts").join(tool);
+        if venv_tool.is_file() {
+            tracing::info!("Using venv {tool}: {}", venv_tool.to_string_lossy());
+            return Some(venv_tool);
         }
     }
 
-    if let Some(candidate) = find_in_path("bean-check") {
-        tracing::info!(
-            "Using bean-check from PATH: {}",
-            candidate.to_string_lossy()
-        );
+    if let Some(candidate) = find_in_path(tool) {
+        tracing::info!("Using {tool} from PATH: {}", candidate.to_string_lossy());
         return Some(candidate);
     }
 
     tracing::info!(
-        "No usable bean-check found: configured bean_check_cmd missing/empty, no venv bean-check, and bean-check not on PATH."
+        "No usable {tool} found: no configured command, no venv {tool}, and {tool} not on PATH."
     );
 
     None
@@ -279,7 +410,7 @@ fn create_system_call_checker(config: &BeancountCheckConfig, root_dir: &Path) ->
             .clone()
             .unwrap_or_else(|| PathBuf::from("bean-check"))
     });
-    SystemCallChecker::new(bean_check_cmd)
+    SystemCallChecker::new(bean_check_cmd).with_args(config.args.clone())
 }
 
 pub fn create_checker(
@@ -329,6 +460,10 @@ pub fn create_checker(
                 None
             }
         }
+        Some(BeancountCheckMethod::Native) => {
+            tracing::debug!("Using NativeChecker");
+            Some(Box::new(NativeChecker::new()))
+        }
         None => {
             let pyo3 = PyO3EmbeddedChecker::new();
             if pyo3.is_available() {
@@ -377,6 +512,7 @@ mod tests {
         let config = BeancountCheckConfig::new();
         assert!(config.method.is_none()); // None means auto-discovery
         assert_eq!(config.bean_check_cmd, None);
+        assert!(config.args.is_empty());
         assert_eq!(config.python_cmd, None);
     }
 
@@ -527,6 +663,8 @@ mod tests {
         let config = BeancountCheckConfig {
             method: None,
             bean_check_cmd: None,
+            args: Vec::new(),
+            timeout: None,
             python_cmd: Some(PathBuf::from("/config/python")),
         };
 
@@ -563,6 +701,8 @@ mod tests {
         let config = BeancountCheckConfig {
             method: None,
             bean_check_cmd: None,
+            args: Vec::new(),
+            timeout: None,
             python_cmd: Some(PathBuf::from("/custom/python")),
         };
 
@@ -586,6 +726,8 @@ mod tests {
         let config = BeancountCheckConfig {
             method: None,
             bean_check_cmd: None,
+            args: Vec::new(),
+            timeout: None,
             python_cmd: Some(PathBuf::from("")),
         };
 
@@ -618,6 +760,8 @@ mod tests {
             let config = BeancountCheckConfig {
                 method: None,
                 bean_check_cmd: None,
+                args: Vec::new(),
+                timeout: None,
                 python_cmd: None,
             };
             let result = resolve_python_cmd(&config, temp_dir.path());
@@ -633,6 +777,8 @@ mod tests {
         let config = BeancountCheckConfig {
             method: None,
             bean_check_cmd: Some(PathBuf::from("/custom/bean-check")),
+            args: Vec::new(),
+            timeout: None,
             python_cmd: None,
         };
 
@@ -664,6 +810,8 @@ mod tests {
             let config = BeancountCheckConfig {
                 method: None,
                 bean_check_cmd: None,
+                args: Vec::new(),
+                timeout: None,
                 python_cmd: None,
             };
 
@@ -693,6 +841,8 @@ mod tests {
             let config = BeancountCheckConfig {
                 method: None,
                 bean_check_cmd: None,
+                args: Vec::new(),
+                timeout: None,
                 python_cmd: None,
             };
 
@@ -715,6 +865,8 @@ mod tests {
         let config = BeancountCheckConfig {
             method: None,
             bean_check_cmd: None,
+            args: Vec::new(),
+            timeout: None,
             python_cmd: Some(PathBuf::from("/nonexistent/python")),
         };
 
@@ -729,6 +881,8 @@ mod tests {
         let config = BeancountCheckConfig {
             method: Some(BeancountCheckMethod::PythonEmbedded),
             bean_check_cmd: Some(PathBuf::from("/nonexistent/bean-check")),
+            args: Vec::new(),
+            timeout: None,
             python_cmd: Some(PathBuf::from("/nonexistent/python")),
         };
 
@@ -766,6 +920,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_native_method_needs_no_python() {
+        assert_eq!(
+            "native".parse::<BeancountCheckMethod>(),
+            Ok(BeancountCheckMethod::Native)
+        );
+        let config = BeancountCheckConfig {
+            method: Some(BeancountCheckMethod::Native),
+            ..BeancountCheckConfig::new()
+        };
+
+        let checker = create_checker(&config, Path::new("/nonexistent")).unwrap();
+        assert_eq!(checker.name(), "Native");
+        assert!(checker.is_available());
+    }
+
     #[test]
     fn test_auto_discovery_prefers_pyo3() {
         // Test that auto-discovery (method: None) prefers PyO3 when available
@@ -789,4 +959,29 @@ mod tests {
             assert!(checker.name() == "SystemPythonChecker" || checker.name() == "SystemCall");
         }
     }
+
+    #[test]
+    fn test_insert_pythonpath_follows_the_journal_option() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = dir.path().join("main.beancount");
+        let python_path = |text: &str| {
+            std::fs::write(&journal, text).unwrap();
+            let mut command = Command::new("bean-check");
+            insert_pythonpath(&mut command, &journal)
+                .get_envs()
+                .find(|(key, _)| *key == "PYTHONPATH")
+                .and_then(|(_, value)| value.map(|value| value.to_os_string()))
+        };
+
+        assert_eq!(python_path("option \"title\" \"Test\"\n"), None);
+        assert_eq!(
+            python_path("option \"insert_pythonpath\" \"FALSE\"\n"),
+            None
+        );
+        let inserted = python_path("option \"insert_pythonpath\" \"TRUE\"\n").unwrap();
+        assert_eq!(
+            std::env::split_paths(&inserted).next(),
+            Some(dir.path().to_path_buf())
+        );
+    }
 }
@@ -0,0 +1,493 @@
+use super::BeancountChecker;
+use super::types::*;
+use crate::beancount_data::BeancountData;
+use crate::ledger::{EntryKind, Ledger};
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use anyhow::{Context, Result};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tree_sitter_beancount::tree_sitter;
+
+/// Validates a journal without Python, using the language server's own parser and
+/// ledger.
+///
+/// Covers syntax errors, references to unopened or closed accounts, transactions
+/// that do not balance and failed balance assertions. Plugins, booking methods and
+/// beancount's tolerance options are not supported, so results can differ from
+/// bean-check.
+#[derive(Debug, Default)]
+pub struct NativeChecker;
+
+impl NativeChecker {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A parsed journal file.
+struct SourceFile {
+    path: PathBuf,
+    tree: tree_sitter::Tree,
+    content: ropey::Rope,
+}
+
+/// A dated directive mentioning an account.
+struct AccountUse {
+    account: String,
+    date: String,
+    file: PathBuf,
+    line: u32,
+}
+
+impl BeancountChecker for NativeChecker {
+    fn check(&self, journal_file: &Path) -> Result<BeancountCheckResult> {
+        let mut errors = vec![];
+        let files = load_files(journal_file, &mut errors)?;
+
+        for file in &files {
+            syntax_errors(&file.tree.root_node(), &file.path, &mut errors);
+        }
+        account_errors(&files, &mut errors);
+
+        let data: HashMap<PathBuf, Arc<BeancountData>> = files
+            .iter()
+            .map(|file| {
+                let data = BeancountData::new(&file.tree, &file.content);
+                (file.path.clone(), Arc::new(data))
+            })
+            .collect();
+        balance_errors(&data, &mut errors);
+
+        errors.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+        Ok(BeancountCheckResult::with_errors(errors))
+    }
+
+    fn name(&self) -> &'static str {
+        "Native"
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+}
+
+/// Parses the journal and every file it includes. An include that can't be read is
+/// reported in `out` and skipped.
+fn load_files(journal: &Path, out: &mut Vec<BeancountError>) -> Result<Vec<SourceFile>> {
+    let journal = std::fs::canonicalize(journal)
+        .with_context(|| format!("failed to read {}", journal.display()))?;
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_beancount::language())?;
+
+    let root = journal.clone();
+    let mut files: Vec<SourceFile> = vec![];
+    let mut unreadable: Vec<PathBuf> = vec![];
+    let mut queue = vec![journal];
+    while let Some(path) = queue.pop() {
+        if files.iter().any(|file| file.path == path) || unreadable.contains(&path) {
+            continue;
+        }
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if path != root => {
+                out.push(BeancountError::new(
+                    path.clone(),
+                    1,
+                    format!("Failed to read included file: {e}"),
+                ));
+                unreadable.push(path);
+                continue;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()));
+            }
+        };
+        let tree = parser
+            .parse(&text, None)
+            .ok_or_else(|| anyhow::anyhow!("failed to parse {}", path.display()))?;
+
+        queue.extend(crate::forest::included_files(&tree, &text, &path));
+        files.push(SourceFile {
+            path,
+            tree,
+            content: ropey::Rope::from_str(&text),
+        });
+    }
+
+    Ok(files)
+}
+
+fn syntax_errors(node: &tree_sitter::Node, file: &Path, out: &mut Vec<BeancountError>) {
+    if !node.has_error() {
+        return;
+    }
+    let line = node.start_position().row as u32 + 1;
+    if node.is_missing() {
+        out.push(BeancountError::new(
+            file.to_path_buf(),
+            line,
+            format!("Syntax error: missing {}", node.kind()),
+        ));
+        return;
+    }
+    if node.is_error() {
+        out.push(BeancountError::new(
+            file.to_path_buf(),
+            line,
+            "Syntax error".to_string(),
+        ));
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        syntax_errors(&child, file, out);
+    }
+}
+
+/// Reports accounts used without an `open`, before it or after their `close`.
+fn account_errors(files: &[SourceFile], out: &mut Vec<BeancountError>) {
+    let mut opens: HashMap<String, String> = HashMap::new();
+    let mut closes: HashMap<String, String> = HashMap::new();
+    let mut uses = vec![];
+
+    for file in files {
+        let mut directives = vec![];
+        dated_directives(&file.tree.root_node(), &mut directives);
+        for directive in directives {
+            let Some(date) = directive
+                .child_by_field_name("date")
+                .map(|date| text_for_tree_sitter_node(&file.content, &date).replace('/', "-"))
+            else {
+                continue;
+            };
+            let line = directive.start_position().row as u32 + 1;
+            let account = directive
+                .child_by_field_name("account")
+                .map(|account| text_for_tree_sitter_node(&file.content, &account));
+
+            match (directive.kind(), account) {
+                ("open", Some(account)) => {
+                    if opens.insert(account.clone(), date).is_some() {
+                        out.push(BeancountError::new(
+                            file.path.clone(),
+                            line,
+                            format!("Duplicate open directive for {account}"),
+                        ));
+                    }
+                }
+                ("close", Some(account)) => {
+                    closes.insert(account.clone(), date.clone());
+                    uses.push(AccountUse {
+                        account,
+                        date,
+                        file: file.path.clone(),
+                        line,
+                    });
+                }
+                _ => {
+                    let mut accounts = vec![];
+                    descendant_accounts(&directive, &mut accounts);
+                    uses.extend(accounts.into_iter().map(|account| AccountUse {
+                        account: text_for_tree_sitter_node(&file.content, &account),
+                        date: date.clone(),
+                        file: file.path.clone(),
+                        line: account.start_position().row as u32 + 1,
+                    }));
+                }
+            }
+        }
+    }
+
+    for AccountUse {
+        account,
+        date,
+        file,
+        line,
+    } in uses
+    {
+        let message = match opens.get(&account) {
+            None => format!("Invalid reference to unknown account '{account}'"),
+            Some(opened) if date < *opened => {
+                format!("Invalid reference to inactive account '{account}'")
+            }
+            Some(_) if closes.get(&account).is_some_and(|closed| date > *closed) => {
+                format!("Invalid reference to inactive account '{account}'")
+            }
+            Some(_) => continue,
+        };
+        out.push(BeancountError::new(file, line, message));
+    }
+}
+
+/// Collects the directives with a date, looking into org-mode style sections.
+fn dated_directives<'a>(node: &tree_sitter::Node<'a>, out: &mut Vec<tree_sitter::Node<'a>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "section" {
+            dated_directives(&child, out);
+        } else if child.child_by_field_name("date").is_some() {
+            out.push(child);
+        }
+    }
+}
+
+fn descendant_accounts<'a>(node: &tree_sitter::Node<'a>, out: &mut Vec<tree_sitter::Node<'a>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "account" {
+            out.push(child);
+        } else {
+            descendant_accounts(&child, out);
+        }
+    }
+}
+
+/// Reports unbalanced transactions and failed balance assertions.
+fn balance_errors(data: &HashMap<PathBuf, Arc<BeancountData>>, out: &mut Vec<BeancountError>) {
+    for (file, data) in data {
+        for entry in data.get_ledger_entries().iter() {
+            let EntryKind::Transaction { postings, .. } = &entry.kind else {
+                continue;
+            };
+            // An elided amount takes whatever is left
+            if postings.iter().any(|posting| posting.amount.is_none()) {
+                continue;
+            }
+
+            let mut residual: BTreeMap<String, Decimal> = BTreeMap::new();
+            let mut tolerances: HashMap<String, Decimal> = HashMap::new();
+            for amount in postings
+                .iter()
+                .filter_map(|posting| posting.amount.as_ref())
+            {
+                let Some(weight) = amount.weight() else {
+                    continue;
+                };
+                let value = residual.entry(weight.currency).or_default();
+                let Some(sum) = value.checked_add(weight.value) else {
+                    continue;
+                };
+                *value = sum;
+
+                // Like beancount, tolerate half a unit of the coarsest precision used
+                let units = &amount.amount;
+                let Ok(tolerance) = Decimal::try_new(5, units.value.scale() + 1) else {
+                    continue;
+                };
+                let current = tolerances.entry(units.currency.clone()).or_default();
+                *current = (*current).max(tolerance);
+            }
+
+            let unbalanced: Vec<String> = residual
+                .iter()
+                .filter(|(currency, value)| {
+                    value.abs() > tolerances.get(*currency).copied().unwrap_or_default()
+                })
+                .map(|(currency, value)| format!("{} {currency}", value.normalize()))
+                .collect();
+            if !unbalanced.is_empty() {
+                out.push(BeancountError::new(
+                    file.clone(),
+                    entry.line + 1,
+                    format!("Transaction does not balance: ({})", unbalanced.join(", ")),
+                ));
+            }
+        }
+    }
+
+    for check in Ledger::new(data).balance_checks() {
+        if check.passed() {
+            continue;
+        }
+        let Some(difference) = check.actual.checked_sub(check.expected.value) else {
+            continue;
+        };
+        let direction = if difference.is_sign_positive() {
+            "too much"
+        } else {
+            "too little"
+        };
+        out.push(BeancountError::new(
+            check.file.clone(),
+            check.line + 1,
+            format!(
+                "Balance failed for '{}': expected {} {} != accumulated {} {} ({} {direction})",
+                check.account,
+                check.expected.value,
+                check.expected.currency,
+                check.actual,
+                check.expected.currency,
+                difference.abs(),
+            ),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn check(files: &[(&str, &str)]) -> Vec<(String, u32, String)> {
+        let dir = TempDir::new().unwrap();
+        for (name, content) in files {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        let journal = dir.path().join(files[0].0);
+        NativeChecker::new()
+            .check(&journal)
+            .unwrap()
+            .errors
+            .into_iter()
+            .map(|error| {
+                let name = error
+                    .file
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                (name, error.line, error.message)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_journal_has_no_errors() {
+        let errors = check(&[(
+            "main.beancount",
+            r#"2024-01-01 open Assets:Cash
+2024-01-01 open Expenses:Food
+
+2024-01-02 * "Lunch"
+  Expenses:Food   3.33 USD
+  Assets:Cash    -3.33 USD
+
+2024-01-03 * "Dinner"
+  Expenses:Food   10 USD
+  Assets:Cash
+
+2024-01-04 balance Assets:Cash -13.33 USD
+"#,
+        )]);
+        assert_eq!(errors, vec![]);
+    }
+
+    #[test]
+    fn test_reports_syntax_errors() {
+        let errors = check(&[(
+            "main.beancount",
+            "2024-01-01 open Assets:Cash\n2024-01-02 balance Assets:Cash\n",
+        )]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].1, 2);
+        assert!(errors[0].2.starts_with("Syntax error"));
+    }
+
+    #[test]
+    fn test_reports_account_lifetimes_across_includes() {
+        let errors = check(&[
+            (
+                "main.beancount",
+                "include \"accounts.beancount\"\n\n2023-12-31 note Assets:Cash \"early\"\n2024-03-01 note Assets:Cash \"late\"\n2024-01-05 note Assets:Bank \"unknown\"\n",
+            ),
+            (
+                "accounts.beancount",
+                "2024-01-01 open Assets:Cash\n2024-02-01 close Assets:Cash\n",
+            ),
+        ]);
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "main.beancount".to_string(),
+                    3,
+                    "Invalid reference to inactive account 'Assets:Cash'".to_string()
+                ),
+                (
+                    "main.beancount".to_string(),
+                    4,
+                    "Invalid reference to inactive account 'Assets:Cash'".to_string()
+                ),
+                (
+                    "main.beancount".to_string(),
+                    5,
+                    "Invalid reference to unknown account 'Assets:Bank'".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_unbalanced_transactions_and_failed_assertions() {
+        let errors = check(&[(
+            "main.beancount",
+            r#"2024-01-01 open Assets:Cash
+2024-01-01 open Expenses:Food
+
+2024-01-02 * "Lunch"
+  Expenses:Food   3.00 USD
+  Assets:Cash    -2.00 USD
+
+2024-01-03 balance Assets:Cash -5.00 USD
+"#,
+        )]);
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    "main.beancount".to_string(),
+                    4,
+                    "Transaction does not balance: (1 USD)".to_string()
+                ),
+                (
+                    "main.beancount".to_string(),
+                    8,
+                    "Balance failed for 'Assets:Cash': expected -5.00 USD != accumulated -2.00 USD (3.00 too much)"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_unreadable_include_and_continues() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("main.beancount"),
+            "include \"broken.beancount\"\n2024-01-01 * \"Test\"\n  Assets:Cash  1 USD\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("broken.beancount"), [0xff, 0xfe, 0x00]).unwrap();
+
+        let errors = NativeChecker::new()
+            .check(&dir.path().join("main.beancount"))
+            .unwrap()
+            .errors;
+        assert!(
+            errors
+                .iter()
+                .any(|error| error.file.ends_with("broken.beancount")
+                    && error.message.starts_with("Failed to read included file")),
+            "{errors:?}"
+        );
+        // The rest of the journal is still checked
+        assert!(
+            errors
+                .iter()
+                .any(|error| error.message.contains("Assets:Cash")),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn test_amounts_with_maximum_precision() {
+        let errors = check(&[(
+            "main.beancount",
+            "2024-01-01 open Assets:Cash\n2024-01-01 open Income:Other\n2024-01-02 * \"Test\"\n  Assets:Cash  0.1234567890123456789012345678 USD\n  Income:Other  -0.1234567890123456789012345678 USD\n",
+        )]);
+        assert!(errors.is_empty(), "{errors:?}");
+    }
+}
@@ -1,5 +1,5 @@
-use super::BeancountChecker;
 use super::types::*;
+use super::{BeancountChecker, insert_pythonpath, output_unless_cancelled};
 use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -115,6 +115,14 @@ impl SystemPythonChecker {
 
 impl BeancountChecker for SystemPythonChecker {
     fn check(&self, journal_file: &Path) -> Result<BeancountCheckResult> {
+        self.check_cancellable(journal_file, &|| false)
+    }
+
+    fn check_cancellable(
+        &self,
+        journal_file: &Path,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<BeancountCheckResult> {
         debug!(
             "SystemPythonChecker: executing python -c for {}",
             journal_file.display()
@@ -124,15 +132,20 @@ impl BeancountChecker for SystemPythonChecker {
             self.python_cmd.display()
         );
 
-        let output = Command::new(&self.python_cmd)
-            .arg("-c")
-            .arg(self.python_code_for_script())
-            .arg(journal_file)
-            .output()
-            .context(format!(
-                "Failed to execute python checker: {}",
-                self.python_cmd.display()
-            ))?;
+        let output = output_unless_cancelled(
+            insert_pythonpath(
+                Command::new(&self.python_cmd)
+                    .arg("-c")
+                    .arg(self.python_code_for_script())
+                    .arg(journal_file),
+                journal_file,
+            ),
+            cancelled,
+        )
+        .context(format!(
+            "Failed to execute python checker: {}",
+            self.python_cmd.display()
+        ))?;
 
         let (errors, flagged_entries) = self.parse_stdout(&output.stdout, journal_file);
 
@@ -1,5 +1,5 @@
-use super::BeancountChecker;
 use super::types::*;
+use super::{BeancountChecker, insert_pythonpath, output_unless_cancelled};
 use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -18,6 +18,50 @@ fn get_error_line_regex() -> &'static regex::Regex {
     })
 }
 
+/// Static regex for bean-check v3 error lines, which may carry a column after
+/// the line number ("file:line:column: error_message").
+static V3_ERROR_LINE_REGEX: OnceLock<regex::Regex> = OnceLock::new();
+
+fn get_v3_error_line_regex() -> &'static regex::Regex {
+    V3_ERROR_LINE_REGEX.get_or_init(|| {
+        regex::Regex::new(r"^(.*?):(\d+)(?::\d+)?:\s*(.*)$")
+            .expect("Failed to compile v3 error line regex")
+    })
+}
+
+/// Static regex finding the version number in `bean-check --version` output.
+static VERSION_REGEX: OnceLock<regex::Regex> = OnceLock::new();
+
+fn get_version_regex() -> &'static regex::Regex {
+    VERSION_REGEX.get_or_init(|| {
+        regex::Regex::new(r"\b(\d+)\.\d+").expect("Failed to compile version regex")
+    })
+}
+
+/// The major version of the installed beancount, which decides how bean-check
+/// output is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BeanCheckVersion {
+    /// Beancount 2: `file:line: message`, errors only reported with a failing exit status.
+    V2,
+    /// Beancount 3: the command line is built with click, which exits with status 2 on
+    /// usage errors such as a missing journal, and error lines may include a column.
+    V3,
+}
+
+impl BeanCheckVersion {
+    /// Reads the version from `bean-check --version` output, like
+    /// `bean-check, version 3.0.0` or `Beancount 2.3.6 (git:...)`.
+    pub fn from_version_output(output: &str) -> Option<Self> {
+        let major: u32 = get_version_regex().captures(output)?[1].parse().ok()?;
+        match major {
+            2 => Some(Self::V2),
+            3.. => Some(Self::V3),
+            _ => None,
+        }
+    }
+}
+
 /// Bean-check implementation using system calls to execute the bean-check binary.
 ///
 /// This is the traditional approach that executes bean-check as a subprocess
@@ -26,16 +70,63 @@ fn get_error_line_regex() -> &'static regex::Regex {
 pub struct SystemCallChecker {
     /// Path to the bean-check executable
     bean_check_cmd: PathBuf,
+    /// Arguments passed before the journal file, e.g. `["run", "bean-check"]` for `uv`
+    args: Vec<String>,
+    /// The beancount version, probed on the first check
+    version: OnceLock<BeanCheckVersion>,
 }
 
 impl SystemCallChecker {
     /// Create a new system call checker with the specified bean-check command path.
     pub fn new(bean_check_cmd: PathBuf) -> Self {
-        Self { bean_check_cmd }
+        Self {
+            bean_check_cmd,
+            args: Vec::new(),
+            version: OnceLock::new(),
+        }
+    }
+
+    /// Passes `args` before the journal file on every invocation.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// The bean-check command with the configured arguments.
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.bean_check_cmd);
+        command.args(&self.args);
+        command
+    }
+
+    /// The version of the installed bean-check, assuming v2 when it can't be told.
+    pub fn version(&self) -> BeanCheckVersion {
+        *self.version.get_or_init(|| {
+            let version = self
+                .command()
+                .arg("--version")
+                .output()
+                .ok()
+                .and_then(|output| {
+                    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+                    text.push_str(&String::from_utf8_lossy(&output.stderr));
+                    BeanCheckVersion::from_version_output(&text)
+                });
+            debug!(
+                "SystemCallChecker: detected bean-check version {:?}",
+                version
+            );
+            version.unwrap_or(BeanCheckVersion::V2)
+        })
     }
 
     /// Parse bean-check stderr output into structured errors.
-    fn parse_stderr_output(&self, stderr: &[u8], root_journal_file: &Path) -> Vec<BeancountError> {
+    fn parse_stderr_output(
+        &self,
+        stderr: &[u8],
+        root_journal_file: &Path,
+        version: BeanCheckVersion,
+    ) -> Vec<BeancountError> {
         let stderr_str = match std::str::from_utf8(stderr) {
             Ok(s) => s,
             Err(e) => {
@@ -46,7 +137,10 @@ impl SystemCallChecker {
 
         // Pre-allocate capacity for typical error counts
         let mut errors = Vec::with_capacity(32);
-        let regex = get_error_line_regex();
+        let regex = match version {
+            BeanCheckVersion::V2 => get_error_line_regex(),
+            BeanCheckVersion::V3 => get_v3_error_line_regex(),
+        };
 
         for line in stderr_str.lines() {
             // Skip empty lines early
@@ -125,22 +219,32 @@ impl SystemCallChecker {
 
 impl BeancountChecker for SystemCallChecker {
     fn check(&self, journal_file: &Path) -> Result<BeancountCheckResult> {
+        self.check_cancellable(journal_file, &|| false)
+    }
+
+    fn check_cancellable(
+        &self,
+        journal_file: &Path,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Result<BeancountCheckResult> {
         debug!(
             "SystemCallChecker: executing bean-check on {}",
             journal_file.display()
         );
         debug!(
-            "SystemCallChecker: using command {}",
-            self.bean_check_cmd.display()
+            "SystemCallChecker: using command {} {:?}",
+            self.bean_check_cmd.display(),
+            self.args
         );
 
-        let output = Command::new(&self.bean_check_cmd)
-            .arg(journal_file)
-            .output()
-            .context(format!(
-                "Failed to execute bean-check command: {}",
-                self.bean_check_cmd.display()
-            ))?;
+        let output = output_unless_cancelled(
+            insert_pythonpath(self.command().arg(journal_file), journal_file),
+            cancelled,
+        )
+        .context(format!(
+            "Failed to execute bean-check command: {}",
+            self.bean_check_cmd.display()
+        ))?;
 
         debug!(
             "SystemCallChecker: command executed, status: {}",
@@ -148,9 +252,21 @@ impl BeancountChecker for SystemCallChecker {
         );
         debug!("SystemCallChecker: stderr length: {}", output.stderr.len());
 
+        let version = self.version();
         let errors = if !output.status.success() {
             debug!("SystemCallChecker: parsing error output");
-            self.parse_stderr_output(&output.stderr, journal_file)
+            let errors = self.parse_stderr_output(&output.stderr, journal_file, version);
+            // v3 reports failures to run at all (status 2, or nothing parseable) on stderr
+            if version == BeanCheckVersion::V3
+                && (output.status.code() == Some(2) || errors.is_empty())
+            {
+                anyhow::bail!(
+                    "bean-check failed ({}): {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            errors
         } else {
             debug!("SystemCallChecker: no errors found");
             Vec::new()
@@ -170,7 +286,7 @@ impl BeancountChecker for SystemCallChecker {
 
     fn is_available(&self) -> bool {
         // Try to run bean-check with --help to see if it's available
-        Command::new(&self.bean_check_cmd)
+        self.command()
             .arg("--help")
             .output()
             .map(|output| output.status.success())
@@ -279,7 +395,7 @@ mod tests {
         let stderr = b"/path/to/file.beancount:123: Test error message\nanother/file.beancount:456: Another error";
         let root_file = PathBuf::from("/root/main.beancount");
 
-        let errors = checker.parse_stderr_output(stderr, &root_file);
+        let errors = checker.parse_stderr_output(stderr, &root_file, BeanCheckVersion::V2);
         assert_eq!(errors.len(), 2);
 
         assert_eq!(errors[0].line, 123);
@@ -294,7 +410,7 @@ mod tests {
         let stderr = b"<check_commodity>:0: Missing Commodity directive for 'USD'";
         let root_file = PathBuf::from("/root/main.beancount");
 
-        let errors = checker.parse_stderr_output(stderr, &root_file);
+        let errors = checker.parse_stderr_output(stderr, &root_file, BeanCheckVersion::V2);
         assert_eq!(errors.len(), 1);
         assert_eq!(errors[0].line, 0);
         assert_eq!(errors[0].file, root_file);
@@ -308,7 +424,7 @@ mod tests {
         let stderr = b"C:\\Users\\TestUser\\projects\\example\\2026\\main.bean:109: Balance failed for 'Liabilities:Card': expected -13954.35 CNY != accumulated -3954.35 CNY (10000.00 too much)\r\n\r\n   2026-01-10 balance Liabilities:Card                             -13954.35 CNY\r\n";
         let root_file = PathBuf::from("C:/Users/TestUser/projects/example/2026/main.bean");
 
-        let errors = checker.parse_stderr_output(stderr, &root_file);
+        let errors = checker.parse_stderr_output(stderr, &root_file, BeanCheckVersion::V2);
         assert_eq!(errors.len(), 1);
         assert_eq!(errors[0].line, 109);
         assert_eq!(
@@ -363,7 +479,7 @@ mod tests {
         let stderr = b"C:/weird:path/01.bean:12: extra colon path";
         let root_file = PathBuf::from("C:/weird:path/01.bean");
 
-        let errors = checker.parse_stderr_output(stderr, &root_file);
+        let errors = checker.parse_stderr_output(stderr, &root_file, BeanCheckVersion::V2);
         assert_eq!(errors.len(), 1);
         assert_eq!(errors[0].line, 12);
         assert_eq!(errors[0].file, PathBuf::from("C:/weird:path/01.bean"));
@@ -502,4 +618,79 @@ mod tests {
         let has_valid_line = check_result.errors.iter().any(|err| err.line > 0);
         assert!(has_valid_line, "Expected errors with valid line numbers");
     }
+
+    #[test]
+    fn test_bean_check_version_from_output() {
+        assert_eq!(
+            BeanCheckVersion::from_version_output("bean-check, version 3.0.0\n"),
+            Some(BeanCheckVersion::V3)
+        );
+        assert_eq!(
+            BeanCheckVersion::from_version_output("Beancount 2.3.6 (git:0ec9ed9b; 2023-03-23)"),
+            Some(BeanCheckVersion::V2)
+        );
+        assert_eq!(
+            BeanCheckVersion::from_version_output("usage: bean-check"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_v3_stderr_output_with_column() {
+        let checker = SystemCallChecker::new(PathBuf::from("bean-check"));
+        let stderr = b"/path/to/file.beancount:12:5: Invalid token\n\n   2024-01-01 open Assets:Cash\n/path/to/other.beancount:7: Balance failed";
+        let root_file = PathBuf::from("/root/main.beancount");
+
+        let errors = checker.parse_stderr_output(stderr, &root_file, BeanCheckVersion::V3);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].file, PathBuf::from("/path/to/file.beancount"));
+        assert_eq!(errors[0].line, 12);
+        assert_eq!(errors[0].message, "Invalid token");
+        assert_eq!(errors[1].line, 7);
+        assert_eq!(errors[1].message, "Balance failed");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_v3_usage_error_is_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (temp_dir, file_path) = create_temp_beancount_file("2023-01-01 open Assets:Cash");
+        let script = temp_dir.path().join("bean-check");
+        fs::write(
+            &script,
+            "#!/bin/sh\n\
+             if [ \"$1\" = --version ]; then echo 'bean-check, version 3.0.0'; exit 0; fi\n\
+             echo \"Error: Invalid value for 'FILENAME'\" >&2\n\
+             exit 2\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let checker = SystemCallChecker::new(script);
+        assert_eq!(checker.version(), BeanCheckVersion::V3);
+        let error = checker.check(&file_path).unwrap_err();
+        assert!(error.to_string().contains("Invalid value for 'FILENAME'"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_args_come_before_the_journal() {
+        let (_temp_dir, file_path) = create_temp_beancount_file("2023-01-01 open Assets:Cash");
+        // `sh -c '...' bean-check <journal>` echoes the journal as an error on line 1
+        let checker = SystemCallChecker::new(PathBuf::from("sh")).with_args(vec![
+            "-c".to_string(),
+            r#"echo "$1:1: checked" >&2; exit 1"#.to_string(),
+            "bean-check".to_string(),
+        ]);
+
+        let result = checker.check(&file_path).unwrap();
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].line, 1);
+        assert_eq!(result.errors[0].message, "checked");
+        assert_eq!(
+            result.errors[0].file,
+            file_path.canonicalize().unwrap_or(file_path)
+        );
+    }
 }
@@ -0,0 +1,1500 @@
+//! Command line subcommands
+//!
+//! These expose server functionality (formatting and diagnostics) without an editor, so
+//! the same rules can be enforced in CI and pre-commit hooks.
+
+use crate::beancount_data::BeancountData;
+use crate::checkers::{BeancountCheckResult, BeancountChecker};
+use crate::config::{Config, FormattingConfig, FormattingOptions};
+use crate::convert::ConvertOptions;
+use crate::importer::ImportRules;
+use crate::ledger_options::LedgerOptions;
+use crate::providers::{diagnostics, formatting};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tree_sitter_beancount::tree_sitter;
+
+/// Arguments for the `format` subcommand.
+#[derive(clap::Args, Debug)]
+pub struct FormatArgs {
+    /// Files, directories or glob patterns (e.g. `ledger/**/*.beancount`) to format.
+    /// Directories are searched recursively for `*.beancount` and `*.bean` files.
+    /// Reads from stdin when omitted.
+    pub files: Vec<String>,
+
+    /// Don't write anything; exit with status 1 if any input is not formatted.
+    #[arg(long)]
+    pub check: bool,
+
+    /// Write the formatted output back to the files instead of stdout.
+    #[arg(short = 'i', long)]
+    pub in_place: bool,
+
+    /// Print a unified diff of the changes instead of the formatted output.
+    /// Nothing is written; combine with `--check` to also fail on changes.
+    #[arg(long)]
+    pub diff: bool,
+
+    /// Path of the file being piped through stdin. Used to discover
+    /// `beancount-fmt.toml` and to name the input in messages.
+    #[arg(long, value_name = "PATH")]
+    pub stdin_filepath: Option<PathBuf>,
+
+    /// Glob pattern for files and directories to skip while searching directories,
+    /// e.g. `imports/**`. Hidden directories are always skipped. Can be repeated.
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// What to print: the formatted files, or the parsed directives as a JSON array.
+    #[arg(long, value_enum, default_value_t = Emit::Formatted)]
+    pub emit: Emit,
+
+    #[command(flatten)]
+    pub bean_format: BeanFormatArgs,
+}
+
+/// bean-format's options, which override `beancount-fmt.toml`.
+#[derive(clap::Args, Debug, Default)]
+pub struct BeanFormatArgs {
+    /// Accept bean-format's command line: a single file, or `-` or nothing for stdin,
+    /// formatted to stdout or `--output`. `beancount-fmt.toml` is ignored, so the
+    /// output only depends on the flags, like bean-format's.
+    #[arg(long, value_enum, value_name = "FORMATTER")]
+    pub compat: Option<Compat>,
+
+    /// Align currencies in this column (bean-format's `-c`).
+    #[arg(short = 'c', long, value_name = "COLUMN")]
+    pub currency_column: Option<usize>,
+
+    /// Use this prefix width instead of determining it (bean-format's `-w`).
+    #[arg(short = 'w', long, value_name = "WIDTH")]
+    pub prefix_width: Option<usize>,
+
+    /// Use this width for numbers instead of determining it (bean-format's `-W`).
+    #[arg(short = 'W', long, value_name = "WIDTH")]
+    pub num_width: Option<usize>,
+
+    /// Write the output to this file instead of stdout. Requires `--compat bean-format`.
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+/// Formatters whose command line `format --compat` accepts.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compat {
+    BeanFormat,
+}
+
+/// Output of the `format` subcommand.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Emit {
+    /// Formatted beancount.
+    #[default]
+    Formatted,
+    /// The parsed directives as JSON, without formatting anything.
+    Json,
+}
+
+/// Directive kinds listed by `--emit json`.
+const DIRECTIVE_KINDS: &[&str] = &[
+    "transaction",
+    "balance",
+    "open",
+    "close",
+    "pad",
+    "document",
+    "note",
+    "event",
+    "price",
+    "commodity",
+    "query",
+    "custom",
+    "option",
+    "include",
+    "plugin",
+    "pushtag",
+    "poptag",
+    "pushmeta",
+    "popmeta",
+];
+
+pub(crate) use crate::forest::JOURNAL_EXTENSIONS;
+
+/// Arguments for the `lint` subcommand.
+#[derive(clap::Args, Debug)]
+pub struct LintArgs {
+    /// Root journal file; included files are checked too.
+    pub journal: PathBuf,
+
+    /// Flag that marks transactions needing review (default: `!`). Can be repeated.
+    #[arg(long = "flag", value_name = "FLAG")]
+    pub flags: Vec<String>,
+
+    /// Only report findings from parsing the journal; don't run bean-check.
+    #[arg(long)]
+    pub no_bean_check: bool,
+
+    /// Exit with status 3 on warnings as well as errors.
+    #[arg(long)]
+    pub deny_warnings: bool,
+
+    /// How to print the findings.
+    #[arg(long, value_enum, default_value_t = LintFormat::Text)]
+    pub format: LintFormat,
+}
+
+/// Output of the `lint` subcommand.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LintFormat {
+    /// One `file:line:column: severity: message` line per finding.
+    #[default]
+    Text,
+    /// The findings as a JSON array.
+    Json,
+    /// A SARIF 2.1.0 log, e.g. for GitHub code scanning.
+    Sarif,
+}
+
+/// Arguments for the `import` subcommand.
+#[derive(clap::Args, Debug)]
+pub struct ImportArgs {
+    /// CSV export to turn into transactions.
+    pub csv: PathBuf,
+
+    /// TOML file mapping the CSV columns and matching payees to accounts.
+    #[arg(long, value_name = "FILE")]
+    pub rules: PathBuf,
+}
+
+/// Arguments for the `convert` subcommand.
+#[derive(clap::Args, Debug)]
+pub struct ConvertArgs {
+    /// ledger-cli or hledger journal to convert. Reads from stdin when omitted or `-`.
+    pub file: Option<PathBuf>,
+
+    /// Write the beancount journal to this file instead of stdout.
+    #[arg(short = 'o', long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Rename an account and its subaccounts before converting, e.g.
+    /// `Bank=Assets:Bank`. Can be repeated; the longest match wins.
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_rename)]
+    pub account: Vec<(String, String)>,
+
+    /// Rename a commodity, e.g. `€=EUR`, or `=USD` for amounts without one.
+    /// Can be repeated.
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_rename)]
+    pub commodity: Vec<(String, String)>,
+}
+
+fn parse_rename(value: &str) -> Result<(String, String), String> {
+    let (from, to) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected FROM=TO, got `{value}`"))?;
+    Ok((from.trim().to_string(), to.trim().to_string()))
+}
+
+/// Exit status when `--check` finds unformatted input.
+const EXIT_UNFORMATTED: u8 = 1;
+/// Exit status when reading, parsing or formatting failed.
+const EXIT_ERROR: u8 = 2;
+/// Exit status when `lint` reports errors (or warnings with `--deny-warnings`).
+const EXIT_LINT_FINDINGS: u8 = 3;
+
+/// Outcome of formatting a single file.
+#[derive(Debug)]
+enum FileStatus {
+    Unchanged,
+    /// The file needs formatting; carries the diff when one was requested.
+    Changed(Option<String>),
+    Failed(anyhow::Error),
+}
+
+/// Runs the `format` subcommand.
+pub fn format(args: FormatArgs) -> ExitCode {
+    match run_format(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_UNFORMATTED),
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Runs the `lint` subcommand.
+pub fn lint(args: LintArgs) -> ExitCode {
+    match run_lint(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_LINT_FINDINGS),
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Runs the `import` subcommand.
+pub fn import(args: ImportArgs) -> ExitCode {
+    match run_import(&args).and_then(|text| write_stdout(&text)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// The transactions of the CSV, by date and separated by blank lines.
+fn run_import(args: &ImportArgs) -> Result<String> {
+    let rules = ImportRules::from_file(&args.rules)?;
+    let csv = std::fs::read_to_string(&args.csv)
+        .with_context(|| format!("failed to read {}", args.csv.display()))?;
+    let mut transactions = rules.transactions(&csv)?;
+    transactions.sort_by(|a, b| a.date.cmp(&b.date));
+
+    let config = load_config(None)?;
+    let texts = transactions
+        .iter()
+        .map(|transaction| crate::providers::import::render(transaction, &config.formatting))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(texts.join("\n"))
+}
+
+/// Runs the `convert` subcommand.
+pub fn convert(args: ConvertArgs) -> ExitCode {
+    match run_convert(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Converts the journal, printing what did not convert cleanly to stderr.
+fn run_convert(args: &ConvertArgs) -> Result<()> {
+    let journal = match args.file.as_deref().filter(|file| *file != Path::new("-")) {
+        Some(file) => std::fs::read_to_string(file)
+            .with_context(|| format!("failed to read {}", file.display()))?,
+        None => {
+            let mut journal = String::new();
+            std::io::stdin()
+                .read_to_string(&mut journal)
+                .context("failed to read stdin")?;
+            journal
+        }
+    };
+    let options = ConvertOptions {
+        accounts: args.account.clone(),
+        commodities: args.commodity.iter().cloned().collect(),
+    };
+    let conversion = crate::convert::convert(&journal, &options);
+
+    let name = args
+        .file
+        .as_deref()
+        .map_or_else(|| "<stdin>".to_string(), |file| file.display().to_string());
+    for (line, message) in &conversion.warnings {
+        eprintln!("{name}:{line}: warning: {message}");
+    }
+    match &args.output {
+        Some(output) => std::fs::write(output, &conversion.text)
+            .with_context(|| format!("failed to write {}", output.display())),
+        None => write_stdout(&conversion.text),
+    }
+}
+
+/// Prints the diagnostics for the journal. Returns `Ok(false)` if any finding should
+/// fail the run.
+fn run_lint(args: &LintArgs) -> Result<bool> {
+    let mut config = load_config(Some(&args.journal))?;
+    if !args.flags.is_empty() {
+        config.diagnostic_flags = args.flags.clone();
+    }
+
+    let beancount_data = load_journal(&args.journal)?;
+    let checker: Box<dyn BeancountChecker> = if args.no_bean_check {
+        Box::new(NoopChecker)
+    } else {
+        match crate::checkers::create_checker(&config.bean_check, &config.root_dir) {
+            Some(checker) => checker,
+            None => {
+                eprintln!("warning: bean-check is not available; only reporting flagged entries");
+                Box::new(NoopChecker)
+            }
+        }
+    };
+
+    let options = std::fs::canonicalize(&args.journal)
+        .ok()
+        .and_then(|journal| beancount_data.get(&journal))
+        .map(|data| LedgerOptions::from_data(data))
+        .unwrap_or_default();
+    let diagnostics = diagnostics::diagnostics(
+        beancount_data,
+        checker.as_ref(),
+        &args.journal,
+        &config.diagnostic_flags,
+        &options,
+    );
+
+    let mut findings: Vec<(PathBuf, lsp_types::Diagnostic)> = diagnostics
+        .into_iter()
+        .flat_map(|(file, diagnostics)| diagnostics.into_iter().map(move |d| (file.clone(), d)))
+        .collect();
+    findings.sort_by_key(|(file, d)| (file.clone(), d.range.start.line, d.range.start.character));
+
+    let severities: Vec<&str> = findings.iter().map(|(_, d)| severity(d)).collect();
+    let errors = severities.iter().filter(|s| **s == "error").count();
+    let warnings = severities.iter().filter(|s| **s == "warning").count();
+    match args.format {
+        LintFormat::Text => {
+            for ((file, diagnostic), severity) in findings.iter().zip(&severities) {
+                println!(
+                    "{}:{}:{}: {severity}: {}",
+                    file.display(),
+                    diagnostic.range.start.line + 1,
+                    diagnostic.range.start.character + 1,
+                    diagnostic.message
+                );
+            }
+        }
+        LintFormat::Json => {
+            let findings: Vec<FindingJson> = findings
+                .iter()
+                .map(|(file, diagnostic)| FindingJson::new(file, diagnostic))
+                .collect();
+            let json = serde_json::to_string_pretty(&findings)?;
+            write_stdout(&format!("{json}\n"))?;
+        }
+        LintFormat::Sarif => {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            let json = serde_json::to_string_pretty(&sarif_log(&findings, &cwd))?;
+            write_stdout(&format!("{json}\n"))?;
+        }
+    }
+    eprintln!("{errors} error(s), {warnings} warning(s)");
+
+    Ok(errors == 0 && (warnings == 0 || !args.deny_warnings))
+}
+
+fn severity(diagnostic: &lsp_types::Diagnostic) -> &'static str {
+    match diagnostic.severity {
+        Some(lsp_types::DiagnosticSeverity::ERROR) | None => "error",
+        Some(lsp_types::DiagnosticSeverity::WARNING) => "warning",
+        Some(lsp_types::DiagnosticSeverity::INFORMATION) => "info",
+        Some(_) => "hint",
+    }
+}
+
+/// The diagnostic code, or else the tool that reported the diagnostic.
+fn rule_id(diagnostic: &lsp_types::Diagnostic) -> String {
+    match &diagnostic.code {
+        Some(lsp_types::NumberOrString::String(code)) => code.clone(),
+        Some(lsp_types::NumberOrString::Number(code)) => code.to_string(),
+        None => diagnostic
+            .source
+            .clone()
+            .unwrap_or_else(|| "beancount-lsp".to_string()),
+    }
+}
+
+/// The 1-based column of an LSP `character`, unless it stands for the end of the line.
+fn column(character: u32) -> Option<u32> {
+    character.checked_add(1).filter(|_| character != u32::MAX)
+}
+
+/// A diagnostic as printed by `lint --format json`. Lines and columns start at 1; the
+/// end column is missing for findings spanning to the end of the line.
+#[derive(Debug, Serialize, PartialEq)]
+struct FindingJson {
+    file: PathBuf,
+    line: u32,
+    column: u32,
+    end_line: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_column: Option<u32>,
+    severity: &'static str,
+    code: String,
+    message: String,
+}
+
+impl FindingJson {
+    fn new(file: &Path, diagnostic: &lsp_types::Diagnostic) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            line: diagnostic.range.start.line + 1,
+            column: diagnostic.range.start.character + 1,
+            end_line: diagnostic.range.end.line + 1,
+            end_column: column(diagnostic.range.end.character),
+            severity: severity(diagnostic),
+            code: rule_id(diagnostic),
+            message: diagnostic.message.clone(),
+        }
+    }
+}
+
+/// A SARIF 2.1.0 log with one run holding the findings. Files below `base` are named
+/// relative to it, as code scanning expects paths relative to the checkout.
+fn sarif_log(findings: &[(PathBuf, lsp_types::Diagnostic)], base: &Path) -> serde_json::Value {
+    let mut rules: Vec<String> = findings.iter().map(|(_, d)| rule_id(d)).collect();
+    rules.sort();
+    rules.dedup();
+
+    let results: Vec<serde_json::Value> = findings
+        .iter()
+        .map(|(file, diagnostic)| {
+            let uri = match file.strip_prefix(base) {
+                Ok(relative) => relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                Err(_) => crate::utils::file_path_to_uri(file)
+                    .map(|uri| uri.as_str().to_string())
+                    .unwrap_or_else(|()| file.display().to_string()),
+            };
+            let level = match severity(diagnostic) {
+                "error" => "error",
+                "warning" => "warning",
+                _ => "note",
+            };
+            // SARIF columns count UTF-16 code units by default, like LSP positions
+            let range = diagnostic.range;
+            let mut region = serde_json::json!({
+                "startLine": range.start.line + 1,
+                "startColumn": range.start.character + 1,
+                "endLine": range.end.line + 1,
+            });
+            if let Some(end_column) = column(range.end.character) {
+                region["endColumn"] = end_column.into();
+            }
+            serde_json::json!({
+                "ruleId": rule_id(diagnostic),
+                "level": level,
+                "message": { "text": diagnostic.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": uri },
+                        "region": region,
+                    }
+                }]
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules.iter().map(|id| serde_json::json!({ "id": id })).collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+/// Parses the journal and every file it includes.
+fn load_journal(journal: &Path) -> Result<HashMap<PathBuf, Arc<BeancountData>>> {
+    let journal = std::fs::canonicalize(journal)
+        .with_context(|| format!("failed to read {}", journal.display()))?;
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_beancount::language())?;
+
+    let mut data = HashMap::new();
+    let mut queue = vec![journal];
+    while let Some(file) = queue.pop() {
+        if data.contains_key(&file) {
+            continue;
+        }
+        let text = std::fs::read_to_string(&file)
+            .with_context(|| format!("failed to read {}", file.display()))?;
+        let tree = parser
+            .parse(&text, None)
+            .ok_or_else(|| anyhow::anyhow!("failed to parse {}", file.display()))?;
+
+        queue.extend(crate::forest::included_files(&tree, &text, &file));
+        let content = ropey::Rope::from_str(&text);
+        data.insert(file, Arc::new(BeancountData::new(&tree, &content)));
+    }
+
+    Ok(data)
+}
+
+/// Stands in for bean-check when it is disabled or unavailable.
+struct NoopChecker;
+
+impl BeancountChecker for NoopChecker {
+    fn check(&self, _journal_file: &Path) -> Result<BeancountCheckResult> {
+        Ok(BeancountCheckResult::new())
+    }
+
+    fn name(&self) -> &'static str {
+        "none"
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+}
+
+/// Returns `Ok(false)` if `--check` found input that is not formatted.
+fn run_format(args: &FormatArgs) -> Result<bool> {
+    if args.bean_format.compat == Some(Compat::BeanFormat) {
+        return run_bean_format(args);
+    }
+    if args.bean_format.output.is_some() {
+        anyhow::bail!("--output requires --compat bean-format");
+    }
+    if args.emit == Emit::Json {
+        emit_json(args)?;
+        return Ok(true);
+    }
+    if args.files.is_empty() {
+        return format_stdin(args);
+    }
+    if args.stdin_filepath.is_some() {
+        anyhow::bail!("--stdin-filepath cannot be combined with file arguments");
+    }
+
+    let files = expand_file_args(&args.files, &args.exclude)?;
+    let single_file = args.files.len() == 1 && !Path::new(&args.files[0]).is_dir();
+    if single_file && files.len() == 1 && !args.check && !args.in_place && !args.diff {
+        let file = &files[0];
+        let original = std::fs::read_to_string(file)
+            .with_context(|| format!("failed to read {}", file.display()))?;
+        let config = formatting_config(Some(file), &args.bean_format)?;
+        let formatted = formatting::format_text(&original, &config)?;
+        write_stdout(&formatted)?;
+        return Ok(true);
+    }
+    if !args.check && !args.in_place && !args.diff {
+        anyhow::bail!("formatting multiple files requires --in-place, --check or --diff");
+    }
+
+    let write = args.in_place && !args.check && !args.diff;
+    let results = format_files(&files, &args.bean_format, write, args.diff);
+
+    let mut changed = 0;
+    let mut failed = 0;
+    for (file, status) in &results {
+        match status {
+            FileStatus::Unchanged => {}
+            FileStatus::Changed(Some(diff)) => {
+                changed += 1;
+                print!("{diff}");
+            }
+            FileStatus::Changed(None) if !write => {
+                changed += 1;
+                println!("{}", file.display());
+            }
+            FileStatus::Changed(None) => {
+                changed += 1;
+                eprintln!("reformatted {}", file.display());
+            }
+            FileStatus::Failed(e) => {
+                failed += 1;
+                eprintln!("error: {}: {e:#}", file.display());
+            }
+        }
+    }
+
+    let verb = if write {
+        "reformatted"
+    } else {
+        "would be reformatted"
+    };
+    eprintln!(
+        "{changed} file(s) {verb}, {} unchanged",
+        results.len() - changed - failed
+    );
+
+    if failed > 0 {
+        anyhow::bail!("{failed} file(s) could not be formatted");
+    }
+    Ok(!(args.check && changed > 0))
+}
+
+fn format_stdin(args: &FormatArgs) -> Result<bool> {
+    let mut original = String::new();
+    std::io::stdin()
+        .read_to_string(&mut original)
+        .context("failed to read stdin")?;
+
+    let stdin_filepath = args.stdin_filepath.as_deref();
+    let config = formatting_config(stdin_filepath, &args.bean_format)?;
+    let formatted = formatting::format_text(&original, &config)?;
+    let name = stdin_filepath.map_or_else(
+        || "<stdin>".to_string(),
+        |path| path.to_string_lossy().to_string(),
+    );
+
+    if args.diff {
+        if formatted != original {
+            print!("{}", unified_diff(&original, &formatted, &name));
+        }
+        return Ok(!(args.check && formatted != original));
+    }
+
+    if args.check {
+        if formatted != original {
+            println!("{name}");
+            return Ok(false);
+        }
+        return Ok(true);
+    }
+
+    write_stdout(&formatted)?;
+    Ok(true)
+}
+
+/// A directive as printed by `--emit json`.
+#[derive(Debug, Serialize, PartialEq)]
+struct DirectiveJson {
+    file: String,
+    #[serde(rename = "type")]
+    kind: String,
+    date: Option<String>,
+    flag: Option<String>,
+    payee: Option<String>,
+    narration: Option<String>,
+    account: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    postings: Vec<PostingJson>,
+    /// First line of the directive, 1-based.
+    start_line: usize,
+    /// Last line of the directive, 1-based and inclusive.
+    end_line: usize,
+}
+
+/// A posting of a transaction as printed by `--emit json`.
+#[derive(Debug, Serialize, PartialEq)]
+struct PostingJson {
+    flag: Option<String>,
+    account: String,
+    number: Option<String>,
+    currency: Option<String>,
+}
+
+/// Prints the directives of every input as a single JSON array.
+fn emit_json(args: &FormatArgs) -> Result<()> {
+    if args.check || args.in_place || args.diff {
+        anyhow::bail!("--emit json cannot be combined with --check, --in-place or --diff");
+    }
+
+    let mut directives = Vec::new();
+    if args.files.is_empty() {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .context("failed to read stdin")?;
+        let name = args.stdin_filepath.as_deref().map_or_else(
+            || "<stdin>".to_string(),
+            |path| path.to_string_lossy().to_string(),
+        );
+        directives.extend(parse_directives(&content, &name)?);
+    } else {
+        for file in expand_file_args(&args.files, &args.exclude)? {
+            let content = std::fs::read_to_string(&file)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+            directives.extend(parse_directives(&content, &file.to_string_lossy())?);
+        }
+    }
+
+    let json = serde_json::to_string_pretty(&directives)?;
+    write_stdout(&format!("{json}\n"))
+}
+
+/// Parses `content` and describes its directives, including those inside org-mode sections.
+fn parse_directives(content: &str, file: &str) -> Result<Vec<DirectiveJson>> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_beancount::language())?;
+    let tree = parser
+        .parse(content, None)
+        .ok_or_else(|| anyhow::anyhow!("failed to parse {file}"))?;
+
+    let text = |node: tree_sitter::Node| content[node.byte_range()].to_string();
+    let field = |node: tree_sitter::Node, name: &str| node.child_by_field_name(name).map(text);
+    let unquote = |s: String| s.trim_matches('"').to_string();
+
+    let mut directives = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            if child.kind() == "section" {
+                stack.push(child);
+                continue;
+            }
+            if !DIRECTIVE_KINDS.contains(&child.kind()) {
+                continue;
+            }
+
+            let mut postings = Vec::new();
+            let mut posting_cursor = child.walk();
+            for posting in child
+                .named_children(&mut posting_cursor)
+                .filter(|n| n.kind() == "posting")
+            {
+                let amount = posting.child_by_field_name("amount");
+                postings.push(PostingJson {
+                    flag: field(posting, "optflag"),
+                    account: field(posting, "account").unwrap_or_default(),
+                    number: amount
+                        .and_then(|amount| amount.named_child(0))
+                        .filter(|n| n.kind() != "currency")
+                        .map(text),
+                    currency: amount
+                        .and_then(|amount| {
+                            let mut amount_cursor = amount.walk();
+                            amount
+                                .named_children(&mut amount_cursor)
+                                .find(|n| n.kind() == "currency")
+                        })
+                        .map(text),
+                });
+            }
+
+            let end = child.end_position();
+            let end_row = if end.column == 0 && end.row > child.start_position().row {
+                end.row - 1
+            } else {
+                end.row
+            };
+            let is_transaction = child.kind() == "transaction";
+            directives.push(DirectiveJson {
+                file: file.to_string(),
+                kind: child.kind().to_string(),
+                date: field(child, "date"),
+                flag: field(child, "txn").filter(|_| is_transaction),
+                payee: field(child, "payee").map(unquote),
+                narration: field(child, "narration").map(unquote),
+                account: field(child, "account"),
+                postings,
+                start_line: child.start_position().row + 1,
+                end_line: end_row + 1,
+            });
+        }
+    }
+
+    directives.sort_by_key(|d| d.start_line);
+    Ok(directives)
+}
+
+fn write_stdout(content: &str) -> Result<()> {
+    std::io::stdout()
+        .write_all(content.as_bytes())
+        .context("failed to write to stdout")
+}
+
+/// Expands glob patterns and directories in the file arguments. Plain paths are kept
+/// as-is so that missing files are reported as errors instead of silently matching nothing.
+fn expand_file_args(args: &[String], exclude: &[String]) -> Result<Vec<PathBuf>> {
+    let exclude = exclude
+        .iter()
+        .map(|pattern| {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("invalid exclude pattern: {pattern}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut files = Vec::new();
+    for arg in args {
+        let path = Path::new(arg);
+        if path.is_dir() {
+            let before = files.len();
+            find_journal_files(path, path, &exclude, &mut files)
+                .with_context(|| format!("failed to search {arg}"))?;
+            if files.len() == before {
+                anyhow::bail!("no beancount files found in {arg}");
+            }
+            continue;
+        }
+        if !arg.contains(['*', '?', '[']) {
+            files.push(PathBuf::from(arg));
+            continue;
+        }
+
+        let matches = glob::glob(arg)
+            .with_context(|| format!("invalid glob pattern: {arg}"))?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            anyhow::bail!("no files match pattern: {arg}");
+        }
+        files.extend(matches);
+    }
+
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Recursively collects journal files below `dir`, skipping hidden entries and paths
+/// matching `exclude` (relative to `root` or as given).
+fn find_journal_files(
+    root: &Path,
+    dir: &Path,
+    exclude: &[glob::Pattern],
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        if exclude
+            .iter()
+            .any(|pattern| pattern.matches_path(relative) || pattern.matches_path(&path))
+        {
+            continue;
+        }
+
+        if path.is_dir() {
+            find_journal_files(root, &path, exclude, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| JOURNAL_EXTENSIONS.iter().any(|e| ext == *e))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Renders a unified diff between the original and formatted content.
+fn unified_diff(original: &str, formatted: &str, name: &str) -> String {
+    let name = name.trim_start_matches('/');
+    similar::TextDiff::from_lines(original, formatted)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{name}"), &format!("b/{name}"))
+        .to_string()
+}
+
+/// Formats the files in parallel, writing changes back if `write` is set.
+fn format_files(
+    files: &[PathBuf],
+    bean_format: &BeanFormatArgs,
+    write: bool,
+    diff: bool,
+) -> Vec<(PathBuf, FileStatus)> {
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(files.len())
+        .max(1);
+    let chunk_size = files.len().div_ceil(workers);
+
+    std::thread::scope(|scope| {
+        let handles = files
+            .chunks(chunk_size.max(1))
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|file| (file.clone(), format_file(file, bean_format, write, diff)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("formatter thread panicked"))
+            .collect()
+    })
+}
+
+fn format_file(file: &Path, bean_format: &BeanFormatArgs, write: bool, diff: bool) -> FileStatus {
+    let result = (|| -> Result<Option<Option<String>>> {
+        let original = std::fs::read_to_string(file)
+            .with_context(|| format!("failed to read {}", file.display()))?;
+        let config = formatting_config(Some(file), bean_format)?;
+        let formatted = formatting::format_text(&original, &config)?;
+        if formatted == original {
+            return Ok(None);
+        }
+        let diff = diff.then(|| unified_diff(&original, &formatted, &file.to_string_lossy()));
+        if write {
+            std::fs::write(file, formatted)
+                .with_context(|| format!("failed to write {}", file.display()))?;
+        }
+        Ok(Some(diff))
+    })();
+
+    match result {
+        Ok(None) => FileStatus::Unchanged,
+        Ok(Some(diff)) => FileStatus::Changed(diff),
+        Err(e) => FileStatus::Failed(e),
+    }
+}
+
+/// Formats like bean-format: one input, to stdout or `--output`.
+fn run_bean_format(args: &FormatArgs) -> Result<bool> {
+    if args.check || args.in_place || args.diff || args.emit != Emit::Formatted {
+        anyhow::bail!(
+            "--compat bean-format cannot be combined with --check, --in-place, --diff or --emit"
+        );
+    }
+    let original = match args.files.as_slice() {
+        [] => None,
+        [file] if file == "-" => None,
+        [file] => {
+            Some(std::fs::read_to_string(file).with_context(|| format!("failed to read {file}"))?)
+        }
+        _ => anyhow::bail!("--compat bean-format formats a single file"),
+    };
+    let original = match original {
+        Some(original) => original,
+        None => {
+            let mut original = String::new();
+            std::io::stdin()
+                .read_to_string(&mut original)
+                .context("failed to read stdin")?;
+            original
+        }
+    };
+
+    let formatted = formatting::format_text(&original, &bean_format_config(&args.bean_format))?;
+    match &args.bean_format.output {
+        Some(output) => std::fs::write(output, formatted)
+            .with_context(|| format!("failed to write {}", output.display()))?,
+        None => write_stdout(&formatted)?,
+    }
+    Ok(true)
+}
+
+/// The formatting configuration for `file`, with the bean-format flags applied.
+fn formatting_config(file: Option<&Path>, args: &BeanFormatArgs) -> Result<FormattingConfig> {
+    let mut config = load_config(file)?.formatting;
+    config.apply(&FormattingOptions {
+        prefix_width: args.prefix_width,
+        num_width: args.num_width,
+        currency_column: args.currency_column,
+        ..Default::default()
+    });
+    Ok(config)
+}
+
+/// bean-format's defaults with the flags applied.
+fn bean_format_config(args: &BeanFormatArgs) -> FormattingConfig {
+    FormattingConfig {
+        prefix_width: args.prefix_width,
+        num_width: args.num_width,
+        currency_column: args.currency_column,
+        ..Default::default()
+    }
+}
+
+/// Builds the configuration for formatting `file`, picking up `beancount-fmt.toml`
+/// next to it or in the current directory.
+fn load_config(file: Option<&Path>) -> Result<Config> {
+    let mut config = Config::new(std::env::current_dir()?);
+    config.journal_root = file.map(Path::to_path_buf);
+    config.load_formatter_config_file()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNFORMATTED: &str =
+        "2024-01-01 * \"Test\"\n  Assets:Cash  1.00 USD\n  Expenses:Food    -1.00 USD\n";
+
+    #[test]
+    fn test_check_reports_unformatted_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("main.beancount");
+        std::fs::write(&file, UNFORMATTED).unwrap();
+
+        let args = FormatArgs {
+            files: vec![file.to_string_lossy().to_string()],
+            check: true,
+            in_place: false,
+            diff: false,
+            stdin_filepath: None,
+            exclude: vec![],
+            emit: Emit::Formatted,
+            bean_format: Default::default(),
+        };
+        assert!(!run_format(&args).unwrap());
+        // --check never writes
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), UNFORMATTED);
+    }
+
+    #[test]
+    fn test_check_accepts_formatted_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("main.beancount");
+        let formatted =
+            formatting::format_text(UNFORMATTED, &crate::config::FormattingConfig::default())
+                .unwrap();
+        std::fs::write(&file, formatted).unwrap();
+
+        let args = FormatArgs {
+            files: vec![file.to_string_lossy().to_string()],
+            check: true,
+            in_place: false,
+            diff: false,
+            stdin_filepath: None,
+            exclude: vec![],
+            emit: Emit::Formatted,
+            bean_format: Default::default(),
+        };
+        assert!(run_format(&args).unwrap());
+    }
+
+    #[test]
+    fn test_load_config_uses_formatter_config_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("main.beancount");
+        std::fs::write(
+            dir.path().join(crate::config::FORMATTER_CONFIG_FILE),
+            "currency_column = 50\n",
+        )
+        .unwrap();
+
+        let config = load_config(Some(&file)).unwrap();
+        assert_eq!(config.formatting.currency_column, Some(50));
+    }
+
+    #[test]
+    fn test_bean_format_compat_ignores_config_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("main.beancount");
+        let output = dir.path().join("formatted.beancount");
+        std::fs::write(&file, UNFORMATTED).unwrap();
+        std::fs::write(
+            dir.path().join(crate::config::FORMATTER_CONFIG_FILE),
+            "currency_column = 50\n",
+        )
+        .unwrap();
+
+        let mut args = FormatArgs {
+            files: vec![file.to_string_lossy().to_string()],
+            check: false,
+            in_place: false,
+            diff: false,
+            stdin_filepath: None,
+            exclude: vec![],
+            emit: Emit::Formatted,
+            bean_format: BeanFormatArgs {
+                compat: Some(Compat::BeanFormat),
+                prefix_width: Some(16),
+                output: Some(output.clone()),
+                ..Default::default()
+            },
+        };
+        assert!(run_format(&args).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "2024-01-01 * \"Test\"\n  Assets:Cash      1.00 USD\n  Expenses:Food   -1.00 USD\n"
+        );
+
+        args.check = true;
+        assert!(run_format(&args).is_err());
+        args.check = false;
+        args.bean_format.compat = None;
+        assert!(run_format(&args).is_err(), "--output needs --compat");
+    }
+
+    #[test]
+    fn test_flags_override_config_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("main.beancount");
+        std::fs::write(
+            dir.path().join(crate::config::FORMATTER_CONFIG_FILE),
+            "currency_column = 50\nnum_width = 10\n",
+        )
+        .unwrap();
+
+        let args = BeanFormatArgs {
+            currency_column: Some(60),
+            ..Default::default()
+        };
+        let config = formatting_config(Some(&file), &args).unwrap();
+        assert_eq!(config.currency_column, Some(60));
+        assert_eq!(config.num_width, Some(10));
+    }
+
+    #[test]
+    fn test_expand_file_args_with_glob() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("2024")).unwrap();
+        std::fs::write(dir.path().join("main.beancount"), "").unwrap();
+        std::fs::write(dir.path().join("2024/jan.beancount"), "").unwrap();
+        std::fs::write(dir.path().join("2024/notes.txt"), "").unwrap();
+
+        let pattern = dir.path().join("**/*.beancount");
+        let files = expand_file_args(&[pattern.to_string_lossy().to_string()], &[]).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("2024/jan.beancount"),
+                dir.path().join("main.beancount"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expand_file_args_no_match_is_error() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let pattern = dir.path().join("*.beancount");
+        assert!(expand_file_args(&[pattern.to_string_lossy().to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_in_place_formats_multiple_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let first = dir.path().join("a.beancount");
+        let second = dir.path().join("b.beancount");
+        std::fs::write(&first, UNFORMATTED).unwrap();
+        std::fs::write(&second, "2024-01-01 open Assets:Cash\n").unwrap();
+
+        let args = FormatArgs {
+            files: vec![dir.path().join("*.beancount").to_string_lossy().to_string()],
+            check: false,
+            in_place: true,
+            diff: false,
+            stdin_filepath: None,
+            exclude: vec![],
+            emit: Emit::Formatted,
+            bean_format: Default::default(),
+        };
+        assert!(run_format(&args).unwrap());
+        assert_ne!(std::fs::read_to_string(&first).unwrap(), UNFORMATTED);
+        assert_eq!(
+            std::fs::read_to_string(&second).unwrap(),
+            "2024-01-01 open Assets:Cash\n"
+        );
+    }
+
+    #[test]
+    fn test_multiple_files_require_mode() {
+        let args = FormatArgs {
+            files: vec!["a.beancount".to_string(), "b.beancount".to_string()],
+            check: false,
+            in_place: false,
+            diff: false,
+            stdin_filepath: None,
+            exclude: vec![],
+            emit: Emit::Formatted,
+            bean_format: Default::default(),
+        };
+        assert!(run_format(&args).is_err());
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let diff = unified_diff("a\nb\n", "a\nc\n", "main.beancount");
+        assert_eq!(
+            diff,
+            "--- a/main.beancount\n+++ b/main.beancount\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_does_not_write() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("main.beancount");
+        std::fs::write(&file, UNFORMATTED).unwrap();
+
+        match format_file(&file, &BeanFormatArgs::default(), false, true) {
+            FileStatus::Changed(Some(diff)) => {
+                assert!(diff.contains("-  Assets:Cash  1.00 USD"));
+            }
+            other => panic!("expected a diff, got {other:?}"),
+        }
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), UNFORMATTED);
+    }
+
+    #[test]
+    fn test_stdin_filepath_rejected_with_files() {
+        let args = FormatArgs {
+            files: vec!["a.beancount".to_string()],
+            check: true,
+            in_place: false,
+            diff: false,
+            stdin_filepath: Some(PathBuf::from("ledger/main.beancount")),
+            exclude: vec![],
+            emit: Emit::Formatted,
+            bean_format: Default::default(),
+        };
+        assert!(run_format(&args).is_err());
+    }
+
+    #[test]
+    fn test_expand_file_args_with_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("2024/imports")).unwrap();
+        std::fs::create_dir(dir.path().join(".git")).unwrap();
+        std::fs::write(dir.path().join("main.beancount"), "").unwrap();
+        std::fs::write(dir.path().join("2024/jan.bean"), "").unwrap();
+        std::fs::write(dir.path().join("2024/notes.txt"), "").unwrap();
+        std::fs::write(dir.path().join("2024/imports/raw.beancount"), "").unwrap();
+        std::fs::write(dir.path().join(".git/x.beancount"), "").unwrap();
+
+        let root = dir.path().to_string_lossy().to_string();
+        let files = expand_file_args(std::slice::from_ref(&root), &[]).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("2024/imports/raw.beancount"),
+                dir.path().join("2024/jan.bean"),
+                dir.path().join("main.beancount"),
+            ]
+        );
+
+        let files = expand_file_args(&[root], &["*/imports".to_string()]).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("2024/jan.bean"),
+                dir.path().join("main.beancount"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_requires_mode() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("main.beancount"), UNFORMATTED).unwrap();
+
+        let args = FormatArgs {
+            files: vec![dir.path().to_string_lossy().to_string()],
+            check: false,
+            in_place: false,
+            diff: false,
+            stdin_filepath: None,
+            exclude: vec![],
+            emit: Emit::Formatted,
+            bean_format: Default::default(),
+        };
+        assert!(run_format(&args).is_err());
+    }
+
+    #[test]
+    fn test_parse_directives() {
+        let content = "2024-01-01 open Assets:Cash USD\n\n* Food\n2024-01-02 * \"Shop\" \"Lunch\"\n  Expenses:Food  5.00 USD\n  ! Assets:Cash\n";
+        let directives = parse_directives(content, "main.beancount").unwrap();
+        assert_eq!(directives.len(), 2);
+
+        assert_eq!(directives[0].kind, "open");
+        assert_eq!(directives[0].account.as_deref(), Some("Assets:Cash"));
+        assert_eq!((directives[0].start_line, directives[0].end_line), (1, 1));
+
+        let transaction = &directives[1];
+        assert_eq!(transaction.date.as_deref(), Some("2024-01-02"));
+        assert_eq!(transaction.flag.as_deref(), Some("*"));
+        assert_eq!(transaction.payee.as_deref(), Some("Shop"));
+        assert_eq!(transaction.narration.as_deref(), Some("Lunch"));
+        assert_eq!((transaction.start_line, transaction.end_line), (4, 6));
+        assert_eq!(
+            transaction.postings,
+            vec![
+                PostingJson {
+                    flag: None,
+                    account: "Expenses:Food".to_string(),
+                    number: Some("5.00".to_string()),
+                    currency: Some("USD".to_string()),
+                },
+                PostingJson {
+                    flag: Some("!".to_string()),
+                    account: "Assets:Cash".to_string(),
+                    number: None,
+                    currency: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_emit_json_rejects_write_modes() {
+        let args = FormatArgs {
+            files: vec!["a.beancount".to_string()],
+            check: false,
+            in_place: true,
+            diff: false,
+            stdin_filepath: None,
+            exclude: vec![],
+            emit: Emit::Json,
+            bean_format: Default::default(),
+        };
+        assert!(run_format(&args).is_err());
+    }
+
+    #[test]
+    fn test_lint_reports_flagged_entries_in_includes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = dir.path().join("main.beancount");
+        std::fs::write(&journal, "include \"2024.beancount\"\n").unwrap();
+        std::fs::write(
+            dir.path().join("2024.beancount"),
+            "2024-01-01 ! \"Check me\"\n  Assets:Cash  1 USD\n  Expenses:Food\n",
+        )
+        .unwrap();
+
+        let data = load_journal(&journal).unwrap();
+        assert_eq!(data.len(), 2);
+
+        let mut args = LintArgs {
+            journal,
+            flags: vec![],
+            no_bean_check: true,
+            deny_warnings: false,
+            format: LintFormat::Text,
+        };
+        assert!(run_lint(&args).unwrap());
+        args.deny_warnings = true;
+        assert!(!run_lint(&args).unwrap());
+        args.flags = vec!["P".to_string()];
+        assert!(run_lint(&args).unwrap());
+    }
+
+    #[test]
+    fn test_lint_json_and_sarif_findings() {
+        let diagnostic = lsp_types::Diagnostic {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(2, 0),
+                lsp_types::Position::new(2, 10),
+            ),
+            severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+            code: Some(lsp_types::NumberOrString::String(
+                "flagged-entry".to_string(),
+            )),
+            source: Some("beancount-lsp".to_string()),
+            message: "Flagged entry".to_string(),
+            ..Default::default()
+        };
+        let base = Path::new("/repo");
+        let findings = vec![
+            (base.join("ledger/2024.beancount"), diagnostic.clone()),
+            (
+                PathBuf::from("/elsewhere/main.beancount"),
+                lsp_types::Diagnostic {
+                    severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+                    range: lsp_types::Range::new(
+                        lsp_types::Position::new(0, 0),
+                        lsp_types::Position::new(0, u32::MAX),
+                    ),
+                    code: None,
+                    source: Some("bean-check".to_string()),
+                    ..diagnostic.clone()
+                },
+            ),
+        ];
+
+        let json = FindingJson::new(&findings[0].0, &findings[0].1);
+        assert_eq!((json.line, json.column, json.end_column), (3, 1, Some(11)));
+        assert_eq!(
+            (json.severity, json.code.as_str()),
+            ("warning", "flagged-entry")
+        );
+
+        let log = sarif_log(&findings, base);
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(
+            run["tool"]["driver"]["rules"],
+            serde_json::json!([{ "id": "bean-check" }, { "id": "flagged-entry" }])
+        );
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results[0]["level"], "warning");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "ledger/2024.beancount");
+        assert_eq!(location["region"]["startLine"], 3);
+        assert_eq!(results[1]["ruleId"], "bean-check");
+        assert_eq!(results[1]["level"], "error");
+        let region = &results[1]["locations"][0]["physicalLocation"]["region"];
+        assert_eq!(
+            region,
+            &serde_json::json!({ "startLine": 1, "startColumn": 1, "endLine": 1 })
+        );
+        assert!(
+            results[1]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"]
+                .as_str()
+                .unwrap()
+                .starts_with("file://")
+        );
+    }
+
+    #[test]
+    fn test_import_prints_transactions_by_date() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let rules = dir.path().join("bank.toml");
+        std::fs::write(
+            &rules,
+            r#"account = "Assets:Bank"
+currency = "USD"
+default_account = "Expenses:Misc"
+
+[columns]
+date = "Date"
+amount = "Amount"
+payee = "Description"
+
+[[rules]]
+payee = "Coffee"
+account = "Expenses:Coffee"
+"#,
+        )
+        .unwrap();
+        let csv = dir.path().join("bank.csv");
+        std::fs::write(
+            &csv,
+            "Date,Description,Amount\n2024-01-03,Bookshop,\"-1,250.00\"\n2024-01-02,Coffee Bar,-3.50\n",
+        )
+        .unwrap();
+
+        let text = run_import(&ImportArgs { csv, rules }).unwrap();
+        assert_eq!(
+            text,
+            "2024-01-02 * \"Coffee Bar\" \"\"\n  Assets:Bank      -3.50 USD\n  Expenses:Coffee\n\n\
+             2024-01-03 * \"Bookshop\" \"\"\n  Assets:Bank    -1250.00 USD\n  Expenses:Misc\n"
+        );
+    }
+
+    #[test]
+    fn test_convert_writes_output() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = dir.path().join("main.ledger");
+        std::fs::write(
+            &journal,
+            "2024/01/02 Coffee\n    Expenses:Coffee  3.50 EUR\n    Cash\n",
+        )
+        .unwrap();
+        let output = dir.path().join("main.beancount");
+        let args = ConvertArgs {
+            file: Some(journal),
+            output: Some(output.clone()),
+            account: vec![parse_rename("Cash=Assets:Cash").unwrap()],
+            commodity: vec![],
+        };
+        run_convert(&args).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(output).unwrap(),
+            "2024-01-02 open Assets:Cash\n2024-01-02 open Expenses:Coffee\n\n\
+             2024-01-02 * \"Coffee\"\n  Expenses:Coffee  3.50 EUR\n  Assets:Cash\n"
+        );
+        assert!(parse_rename("Cash").is_err());
+    }
+}
@@ -1,8 +1,15 @@
-use crate::checkers::{BeancountCheckConfig, BeancountCheckMethod};
+use crate::checkers::{BeancountCheckConfig, BeancountCheckMethod, ExternalChecker};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_with::{DisplayFromStr, serde_as};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tracing::level_filters::LevelFilter;
+
+/// Name of the formatter configuration file looked up next to the journal.
+pub const FORMATTER_CONFIG_FILE: &str = "beancount-fmt.toml";
+
+/// Name of the project-local server configuration file looked up in the workspace root.
+pub const SERVER_CONFIG_FILE: &str = "beancount-language-server.toml";
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -11,12 +18,266 @@ pub struct Config {
     /// path to root journal file
     pub journal_root: Option<PathBuf>,
     pub formatting: FormattingConfig,
+    /// Format documents before they are saved, via `textDocument/willSaveWaitUntil`
+    pub format_on_save: bool,
     pub bean_check: BeancountCheckConfig,
     /// Flags that should generate diagnostics (e.g., ["!"] for only exclamation mark)
     pub diagnostic_flags: Vec<String>,
+    /// Log level overriding the `--log-level` command line flag
+    pub log_level: Option<LevelFilter>,
+    /// Log file overriding the `--log-file` command line flag
+    pub log_file: Option<PathBuf>,
+    /// Size limits above which expensive features are skipped for a file
+    pub large_file: LargeFileConfig,
+    /// The kinds of inlay hints shown
+    pub inlay_hints: InlayHintsConfig,
+    /// Providers that can be turned off for clients misbehaving with them
+    pub features: FeaturesConfig,
+    /// bean-query executable for `beancount.runQuery`, found like bean-check if unset
+    pub bean_query_cmd: Option<PathBuf>,
+    /// Fava instance serving the journal, like `http://localhost:5000/my-ledger`
+    pub fava_url: Option<String>,
+    /// bean-price executable for `beancount.updatePrices`, found like bean-check if unset
+    pub bean_price_cmd: Option<PathBuf>,
+    /// File receiving fetched prices, relative to the root directory; the journal if unset
+    pub prices_file: Option<PathBuf>,
+    /// Show code lenses counting the earlier transactions of a transaction's payee
+    pub payee_code_lens: bool,
+    /// Show code lenses totalling the transactions under `;;` comment headers
+    pub section_code_lens: bool,
+    /// Rename the subaccounts of a renamed account along with it
+    pub rename_subaccounts: bool,
+    /// Complete accounts one component at a time instead of by their full name
+    pub account_completion_by_component: bool,
+    /// Commands validating the journal after bean-check, in the order they run
+    pub external_checkers: Vec<ExternalChecker>,
+    /// How long a changed document has to be idle before it is re-indexed and checked
+    pub change_debounce: std::time::Duration,
+    /// What to do when a beancount file is created next to the journal
+    pub include_new_files: IncludeNewFiles,
+    /// Entries offered as completions at the start of a line
+    pub snippets: Vec<Snippet>,
+    /// Warn about directives dated more than this many days after today; off if unset
+    pub future_date_days: Option<u32>,
+    /// Opt-in checks of posting amounts, each reported at its own severity
+    pub posting_lints: PostingLintsConfig,
 }
 
-#[derive(Debug, Clone)]
+/// Whether a file created inside the journal's directory gets included in the journal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IncludeNewFiles {
+    /// Leave the journal alone.
+    Off,
+    /// Ask before adding the include.
+    #[default]
+    Ask,
+    /// Add the include right away.
+    Always,
+}
+
+/// A user-defined entry inserted through completion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub name: String,
+    /// Text in LSP snippet syntax, with placeholders like `$1` or `${2:Employer}`
+    pub body: String,
+    pub description: Option<String>,
+}
+
+/// Line counts above which expensive features are skipped for a file, so that a
+/// huge consolidated journal stays responsive. `None` means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LargeFileConfig {
+    pub semantic_tokens_max_lines: Option<usize>,
+    pub inlay_hints_max_lines: Option<usize>,
+    /// Limit for the lints computed by the server itself; bean-check still runs.
+    pub lints_max_lines: Option<usize>,
+}
+
+impl Default for LargeFileConfig {
+    fn default() -> Self {
+        Self {
+            semantic_tokens_max_lines: Some(50_000),
+            inlay_hints_max_lines: Some(50_000),
+            lints_max_lines: Some(100_000),
+        }
+    }
+}
+
+impl LargeFileConfig {
+    /// Apply the options that are set, leaving the others untouched. `0` removes a limit.
+    pub fn apply(&mut self, options: &LargeFileOptions) {
+        let limit = |max_lines: usize| (max_lines > 0).then_some(max_lines);
+        if let Some(max_lines) = options.semantic_tokens_max_lines {
+            self.semantic_tokens_max_lines = limit(max_lines);
+        }
+        if let Some(max_lines) = options.inlay_hints_max_lines {
+            self.inlay_hints_max_lines = limit(max_lines);
+        }
+        if let Some(max_lines) = options.lints_max_lines {
+            self.lints_max_lines = limit(max_lines);
+        }
+    }
+
+    pub fn semantic_tokens_enabled(&self, lines: usize) -> bool {
+        within(self.semantic_tokens_max_lines, lines)
+    }
+
+    pub fn inlay_hints_enabled(&self, lines: usize) -> bool {
+        within(self.inlay_hints_max_lines, lines)
+    }
+
+    pub fn lints_enabled(&self, lines: usize) -> bool {
+        within(self.lints_max_lines, lines)
+    }
+}
+
+/// The kinds of inlay hints shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InlayHintsConfig {
+    /// The amount of a posting without one
+    pub balancing_amounts: bool,
+    /// The total of a transaction that does not balance
+    pub unbalanced_totals: bool,
+    /// The value of foreign amounts in the operating currency
+    pub conversions: bool,
+    /// The balance of a posting's account after it
+    pub running_balances: bool,
+}
+
+impl Default for InlayHintsConfig {
+    fn default() -> Self {
+        Self {
+            balancing_amounts: true,
+            unbalanced_totals: true,
+            conversions: false,
+            running_balances: false,
+        }
+    }
+}
+
+impl InlayHintsConfig {
+    /// Apply the options that are set, leaving the others untouched.
+    pub fn apply(&mut self, options: &InlayHintsOptions) {
+        if let Some(balancing_amounts) = options.balancing_amounts {
+            self.balancing_amounts = balancing_amounts;
+        }
+        if let Some(unbalanced_totals) = options.unbalanced_totals {
+            self.unbalanced_totals = unbalanced_totals;
+        }
+        if let Some(conversions) = options.conversions {
+            self.conversions = conversions;
+        }
+        if let Some(running_balances) = options.running_balances {
+            self.running_balances = running_balances;
+        }
+    }
+
+    /// Whether hints depend on other entries of the journal, like prices or earlier
+    /// postings, so they change when another file changes.
+    pub fn use_ledger(&self) -> bool {
+        self.conversions || self.running_balances
+    }
+}
+
+/// The providers the server offers. A disabled one is left out of the capabilities
+/// sent on initialize and answers requests with nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FeaturesConfig {
+    pub semantic_tokens: bool,
+    pub code_lens: bool,
+    pub inlay_hints: bool,
+    pub formatting: bool,
+    /// Commit characters on completion items, like `:` accepting an account
+    pub commit_characters: bool,
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        Self {
+            semantic_tokens: true,
+            code_lens: true,
+            inlay_hints: true,
+            formatting: true,
+            commit_characters: true,
+        }
+    }
+}
+
+impl FeaturesConfig {
+    /// Apply the options that are set, leaving the others untouched.
+    pub fn apply(&mut self, options: &FeaturesOptions) {
+        if let Some(semantic_tokens) = options.semantic_tokens {
+            self.semantic_tokens = semantic_tokens;
+        }
+        if let Some(code_lens) = options.code_lens {
+            self.code_lens = code_lens;
+        }
+        if let Some(inlay_hints) = options.inlay_hints {
+            self.inlay_hints = inlay_hints;
+        }
+        if let Some(formatting) = options.formatting {
+            self.formatting = formatting;
+        }
+        if let Some(commit_characters) = options.commit_characters {
+            self.commit_characters = commit_characters;
+        }
+    }
+}
+
+/// The severity an opt-in lint is reported at.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    #[default]
+    Off,
+    Hint,
+    Information,
+    Warning,
+    Error,
+}
+
+impl LintSeverity {
+    /// The diagnostic severity, or `None` when the lint is off.
+    pub fn to_lsp(self) -> Option<lsp_types::DiagnosticSeverity> {
+        match self {
+            LintSeverity::Off => None,
+            LintSeverity::Hint => Some(lsp_types::DiagnosticSeverity::HINT),
+            LintSeverity::Information => Some(lsp_types::DiagnosticSeverity::INFORMATION),
+            LintSeverity::Warning => Some(lsp_types::DiagnosticSeverity::WARNING),
+            LintSeverity::Error => Some(lsp_types::DiagnosticSeverity::ERROR),
+        }
+    }
+}
+
+/// Checks of posting amounts that catch importer mapping mistakes. All are off unless
+/// given a severity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PostingLintsConfig {
+    /// Postings whose amount is zero
+    pub zero_amount: LintSeverity,
+    /// Negative postings to expenses and positive postings to income
+    pub sign_convention: LintSeverity,
+}
+
+impl PostingLintsConfig {
+    /// Apply the options that are set, leaving the others untouched.
+    pub fn apply(&mut self, options: &PostingLintsOptions) {
+        if let Some(zero_amount) = options.zero_amount {
+            self.zero_amount = zero_amount;
+        }
+        if let Some(sign_convention) = options.sign_convention {
+            self.sign_convention = sign_convention;
+        }
+    }
+}
+
+fn within(limit: Option<usize>, lines: usize) -> bool {
+    limit.is_none_or(|max_lines| lines <= max_lines)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FormattingConfig {
     /// Use this prefix width instead of determining an optimal value automatically.
     /// Corresponds to bean-format's --prefix-width (-w) option.
@@ -42,10 +303,82 @@ pub struct FormattingConfig {
     /// If specified, all indentation will be normalized to this number of spaces.
     /// If None, indentation is left unchanged.
     pub indent_width: Option<usize>,
+
+    /// Line ending to use for the formatted document (default: preserve each line's).
+    pub line_ending: LineEnding,
+
+    /// Align metadata values within a directive so they start at the same column.
+    pub align_metadata: bool,
+
+    /// Column at which trailing posting comments start. Comments are aligned to a
+    /// common column within each transaction, at least this one (default: no alignment).
+    pub comment_column: Option<usize>,
+
+    /// Check that formatting kept every directive intact and leave the document
+    /// unchanged if it did not.
+    pub verify: bool,
+
+    /// Write `txn` as `*` and use single spaces between the date, flag, strings and
+    /// tags of transaction header lines.
+    pub normalize_transaction_headers: bool,
+
+    /// Number of blank lines between consecutive dated directives (default: keep).
+    pub blank_lines_between_directives: BlankLines,
+
+    /// Maximum number of consecutive blank lines anywhere in the file (default: no limit).
+    pub max_blank_lines: Option<usize>,
+
+    /// Write dates spelled with slashes, like `2024/03/05`, as `2024-03-05`.
+    pub normalize_dates: bool,
 }
 
-impl FormattingConfig {
-    pub fn default() -> Self {
+/// Line ending policy for formatting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineEnding {
+    /// Keep every line's existing ending.
+    #[default]
+    Preserve,
+    /// Use the ending that occurs most often in the document.
+    Auto,
+    /// Force `\n`.
+    Lf,
+    /// Force `\r\n`.
+    Crlf,
+}
+
+/// Blank line policy between dated directives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum BlankLines {
+    /// Keep the existing spacing.
+    #[default]
+    Keep,
+    /// Enforce exactly this many blank lines.
+    Exactly(usize),
+}
+
+impl<'de> Deserialize<'de> for BlankLines {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Count(usize),
+            Keyword(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Count(count) => Ok(BlankLines::Exactly(count)),
+            Raw::Keyword(keyword) if keyword == "keep" => Ok(BlankLines::Keep),
+            Raw::Keyword(keyword) => Err(serde::de::Error::custom(format!(
+                "expected a number or \"keep\", found \"{keyword}\""
+            ))),
+        }
+    }
+}
+
+impl Default for FormattingConfig {
+    fn default() -> Self {
         Self {
             prefix_width: None,
             num_width: None,
@@ -53,6 +386,62 @@ impl FormattingConfig {
             account_amount_spacing: 2,  // Default spacing like bean-format
             number_currency_spacing: 1, // Default 1 space between number and currency
             indent_width: None,         // Default: no indent normalization
+            line_ending: LineEnding::Preserve,
+            align_metadata: false,
+            comment_column: None,
+            verify: false,
+            normalize_transaction_headers: false,
+            blank_lines_between_directives: BlankLines::Keep,
+            max_blank_lines: None,
+            normalize_dates: false,
+        }
+    }
+}
+
+impl FormattingConfig {
+    /// Apply the options that are set, leaving the others untouched.
+    pub fn apply(&mut self, options: &FormattingOptions) {
+        if let Some(prefix_width) = options.prefix_width {
+            self.prefix_width = Some(prefix_width);
+        }
+        if let Some(num_width) = options.num_width {
+            self.num_width = Some(num_width);
+        }
+        if let Some(currency_column) = options.currency_column {
+            self.currency_column = Some(currency_column);
+        }
+        if let Some(spacing) = options.account_amount_spacing {
+            self.account_amount_spacing = spacing;
+        }
+        if let Some(spacing) = options.number_currency_spacing {
+            self.number_currency_spacing = spacing;
+        }
+        if let Some(indent_width) = options.indent_width {
+            self.indent_width = Some(indent_width);
+        }
+        if let Some(line_ending) = options.line_ending {
+            self.line_ending = line_ending;
+        }
+        if let Some(align_metadata) = options.align_metadata {
+            self.align_metadata = align_metadata;
+        }
+        if let Some(comment_column) = options.comment_column {
+            self.comment_column = Some(comment_column);
+        }
+        if let Some(verify) = options.verify {
+            self.verify = verify;
+        }
+        if let Some(normalize_transaction_headers) = options.normalize_transaction_headers {
+            self.normalize_transaction_headers = normalize_transaction_headers;
+        }
+        if let Some(blank_lines_between_directives) = options.blank_lines_between_directives {
+            self.blank_lines_between_directives = blank_lines_between_directives;
+        }
+        if let Some(max_blank_lines) = options.max_blank_lines {
+            self.max_blank_lines = Some(max_blank_lines);
+        }
+        if let Some(normalize_dates) = options.normalize_dates {
+            self.normalize_dates = normalize_dates;
         }
     }
 }
@@ -63,10 +452,56 @@ impl Config {
             root_dir,
             journal_root: None,
             formatting: FormattingConfig::default(),
+            format_on_save: false,
             bean_check: BeancountCheckConfig::new(),
             diagnostic_flags: vec!["!".to_string()],
+            log_level: None,
+            log_file: None,
+            large_file: LargeFileConfig::default(),
+            features: FeaturesConfig::default(),
+            inlay_hints: InlayHintsConfig::default(),
+            bean_query_cmd: None,
+            fava_url: None,
+            bean_price_cmd: None,
+            prices_file: None,
+            payee_code_lens: true,
+            section_code_lens: false,
+            rename_subaccounts: false,
+            account_completion_by_component: false,
+            external_checkers: Vec::new(),
+            change_debounce: std::time::Duration::from_millis(300),
+            snippets: Vec::new(),
+            include_new_files: IncludeNewFiles::default(),
+            future_date_days: None,
+            posting_lints: PostingLintsConfig::default(),
         }
     }
+
+    /// Builds the configuration of a workspace from the project config file, the
+    /// client settings and the formatter config file, in increasing precedence.
+    pub fn load(root_dir: PathBuf, settings: Option<serde_json::Value>) -> Self {
+        let mut config = Self::new(root_dir);
+        match config.load_server_config_file() {
+            Ok(Some(path)) => tracing::info!("Using server config: {}", path.display()),
+            Ok(None) => tracing::debug!("No server config file found"),
+            Err(e) => tracing::warn!("Failed to load server config: {}", e),
+        }
+        if let Some(json) = settings {
+            tracing::info!("Applying client settings: {}", json);
+            if let Err(e) = config.update(json) {
+                tracing::warn!("Failed to update configuration: {}", e);
+            }
+        } else {
+            tracing::debug!("No client settings provided");
+        }
+        match config.load_formatter_config_file() {
+            Ok(Some(path)) => tracing::info!("Using formatter config: {}", path.display()),
+            Ok(None) => tracing::debug!("No formatter config file found"),
+            Err(e) => tracing::warn!("Failed to load formatter config: {}", e),
+        }
+        config
+    }
+
     pub fn update(&mut self, json: serde_json::Value) -> Result<()> {
         let result = serde_json::from_value::<BeancountLspOptions>(json.clone());
 
@@ -82,6 +517,12 @@ impl Config {
             }
         };
 
+        self.apply(beancount_lsp_settings);
+        Ok(())
+    }
+
+    /// Apply the options that are set, leaving the others untouched.
+    fn apply(&mut self, beancount_lsp_settings: BeancountLspOptions) {
         // Ignore non-BeancountLspOptions inputs here.
         // Example: "[]" is sent by nvim-lspconfig if no initialization options are specified in
         // Lua.
@@ -96,24 +537,11 @@ impl Config {
 
         // Update formatting configuration
         if let Some(formatting) = beancount_lsp_settings.formatting {
-            if let Some(prefix_width) = formatting.prefix_width {
-                self.formatting.prefix_width = Some(prefix_width);
-            }
-            if let Some(num_width) = formatting.num_width {
-                self.formatting.num_width = Some(num_width);
-            }
-            if let Some(currency_column) = formatting.currency_column {
-                self.formatting.currency_column = Some(currency_column);
-            }
-            if let Some(spacing) = formatting.account_amount_spacing {
-                self.formatting.account_amount_spacing = spacing;
-            }
-            if let Some(spacing) = formatting.number_currency_spacing {
-                self.formatting.number_currency_spacing = spacing;
-            }
-            if let Some(indent_width) = formatting.indent_width {
-                self.formatting.indent_width = Some(indent_width);
-            }
+            self.formatting.apply(&formatting);
+        }
+
+        if let Some(format_on_save) = beancount_lsp_settings.format_on_save {
+            self.format_on_save = format_on_save;
         }
 
         // Update bean-check configuration
@@ -121,20 +549,325 @@ impl Config {
             if let Some(method) = bean_check.method {
                 self.bean_check.method = Some(method);
             }
-            if let Some(bean_check_cmd) = bean_check.bean_check_cmd {
+            if let Some(bean_check_cmd) = bean_check.path.or(bean_check.bean_check_cmd) {
                 self.bean_check.bean_check_cmd = Some(PathBuf::from(bean_check_cmd));
             }
+            if let Some(args) = bean_check.args {
+                self.bean_check.args = args;
+            }
+            if let Some(timeout_secs) = bean_check.timeout_secs {
+                self.bean_check.timeout =
+                    (timeout_secs > 0).then(|| std::time::Duration::from_secs(timeout_secs));
+            }
             if let Some(python_cmd) = bean_check.python_cmd {
                 self.bean_check.python_cmd = Some(PathBuf::from(python_cmd));
             }
         }
 
+        if let Some(bean_query_cmd) = beancount_lsp_settings.bean_query_cmd {
+            self.bean_query_cmd = Some(PathBuf::from(bean_query_cmd));
+        }
+
+        if let Some(fava_url) = beancount_lsp_settings.fava_url {
+            self.fava_url = Some(fava_url.trim_end_matches('/').to_string());
+        }
+
+        if let Some(bean_price_cmd) = beancount_lsp_settings.bean_price_cmd {
+            self.bean_price_cmd = Some(PathBuf::from(bean_price_cmd));
+        }
+
+        if let Some(prices_file) = beancount_lsp_settings.prices_file
+            && !prices_file.trim().is_empty()
+        {
+            self.prices_file = Some(PathBuf::from(shellexpand::tilde(&prices_file).as_ref()));
+        }
+
+        if let Some(payee_code_lens) = beancount_lsp_settings.payee_code_lens {
+            self.payee_code_lens = payee_code_lens;
+        }
+
+        if let Some(section_code_lens) = beancount_lsp_settings.section_code_lens {
+            self.section_code_lens = section_code_lens;
+        }
+
+        if let Some(rename_subaccounts) = beancount_lsp_settings.rename_subaccounts {
+            self.rename_subaccounts = rename_subaccounts;
+        }
+
+        if let Some(by_component) = beancount_lsp_settings.account_completion_by_component {
+            self.account_completion_by_component = by_component;
+        }
+
+        if let Some(external_checkers) = beancount_lsp_settings.external_checkers {
+            self.external_checkers = external_checkers
+                .into_iter()
+                .filter(|options| !options.command.trim().is_empty())
+                .map(|options| {
+                    let command = PathBuf::from(shellexpand::tilde(&options.command).as_ref());
+                    // `./scripts/check.py` is relative to the workspace, `check-trips` is looked up in PATH
+                    let command = if command.is_relative() && command.components().count() > 1 {
+                        self.root_dir.join(command)
+                    } else {
+                        command
+                    };
+                    let mut checker = ExternalChecker::new(command);
+                    if let Some(name) = options.name {
+                        checker.name = name;
+                    }
+                    checker.args = options.args.unwrap_or_default();
+                    checker
+                })
+                .collect();
+        }
+
+        if let Some(change_debounce_ms) = beancount_lsp_settings.change_debounce_ms {
+            self.change_debounce = std::time::Duration::from_millis(change_debounce_ms);
+        }
+
+        if let Some(include_new_files) = beancount_lsp_settings.include_new_files {
+            self.include_new_files = include_new_files;
+        }
+
+        if let Some(future_date_days) = beancount_lsp_settings.future_date_days {
+            self.future_date_days = Some(future_date_days);
+        }
+
+        if let Some(posting_lints) = beancount_lsp_settings.posting_lints {
+            self.posting_lints.apply(&posting_lints);
+        }
+
+        if let Some(snippets) = beancount_lsp_settings.snippets {
+            self.snippets = snippets
+                .into_iter()
+                .filter(|options| !options.name.trim().is_empty())
+                .map(|options| Snippet {
+                    name: options.name,
+                    body: match options.body {
+                        SnippetBody::Text(text) => text,
+                        SnippetBody::Lines(lines) => lines.join("\n"),
+                    },
+                    description: options.description,
+                })
+                .collect();
+        }
+
         // Update diagnostic_flags configuration
         if let Some(diagnostic_flags) = beancount_lsp_settings.diagnostic_flags {
             self.diagnostic_flags = diagnostic_flags;
         }
 
-        Ok(())
+        if let Some(log_level) = beancount_lsp_settings.log_level {
+            match log_level.parse::<LevelFilter>() {
+                Ok(level) => self.log_level = Some(level),
+                Err(_) => tracing::warn!(
+                    "Invalid log_level '{}'; valid levels: trace, debug, info, warn, error, off",
+                    log_level
+                ),
+            }
+        }
+        if let Some(log_file) = beancount_lsp_settings.log_file
+            && !log_file.trim().is_empty()
+        {
+            self.log_file = Some(PathBuf::from(shellexpand::tilde(&log_file).as_ref()));
+        }
+
+        if let Some(large_file) = beancount_lsp_settings.large_file {
+            self.large_file.apply(&large_file);
+        }
+
+        if let Some(features) = beancount_lsp_settings.features {
+            self.features.apply(&features);
+        }
+
+        if let Some(inlay_hints) = beancount_lsp_settings.inlay_hints {
+            self.inlay_hints.apply(&inlay_hints);
+        }
+    }
+
+    /// Applies the settings of a `beancount-language-server.toml` in the workspace root.
+    ///
+    /// The file is meant to be committed with the ledger, so it comes with whatever
+    /// repository is opened: settings naming a program to run or a file to write are
+    /// ignored with a warning. It is applied before client settings, which override it.
+    /// Returns the path of the file that was applied.
+    pub fn load_server_config_file(&mut self) -> Result<Option<PathBuf>> {
+        let path = self.root_dir.join(SERVER_CONFIG_FILE);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        let mut table: toml::Table = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("invalid {}: {}", path.display(), e))?;
+        let ignored = remove_untrusted_settings(&mut table);
+        if !ignored.is_empty() {
+            tracing::warn!(
+                "Ignoring {} in {}; set them in the editor instead",
+                ignored.join(", "),
+                path.display()
+            );
+        }
+        let options: ServerConfigFileOptions = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| anyhow::anyhow!("invalid {}: {}", path.display(), e))?;
+        self.apply(options.into());
+        Ok(Some(path))
+    }
+
+    /// Returns the journal root resolved against the workspace root.
+    pub fn resolved_journal_root(&self) -> Option<PathBuf> {
+        self.journal_root.as_ref().map(|file| {
+            if file.is_relative() {
+                self.root_dir.join(file)
+            } else {
+                file.clone()
+            }
+        })
+    }
+
+    /// The configured prices file, relative to the root directory.
+    pub fn resolved_prices_file(&self) -> Option<PathBuf> {
+        self.prices_file
+            .as_ref()
+            .map(|file| self.root_dir.join(file))
+    }
+
+    /// Looks for a `beancount-fmt.toml` next to the journal file, falling back to the
+    /// workspace root, and applies its settings on top of the formatting config.
+    ///
+    /// The file takes precedence over client settings so that editor formatting and
+    /// command line runs agree. Returns the path of the file that was applied.
+    pub fn load_formatter_config_file(&mut self) -> Result<Option<PathBuf>> {
+        let journal_dir = self
+            .resolved_journal_root()
+            .and_then(|journal| journal.parent().map(Path::to_path_buf));
+        let candidates = journal_dir
+            .into_iter()
+            .chain(std::iter::once(self.root_dir.clone()));
+
+        for dir in candidates {
+            let path = dir.join(FORMATTER_CONFIG_FILE);
+            if !path.is_file() {
+                continue;
+            }
+            let content = std::fs::read_to_string(&path)?;
+            let options = parse_formatter_config(&content)
+                .map_err(|e| anyhow::anyhow!("invalid {}: {}", path.display(), e))?;
+            self.formatting.apply(&options);
+            return Ok(Some(path));
+        }
+
+        Ok(None)
+    }
+}
+
+/// Parses the contents of a formatter configuration file.
+///
+/// Settings may live at the top level or in a `[tool.beancount-fmt]` table.
+pub fn parse_formatter_config(content: &str) -> Result<FormattingOptions> {
+    let mut table: toml::Table = toml::from_str(content)?;
+    let section = table
+        .get_mut("tool")
+        .and_then(|tool| tool.as_table_mut())
+        .and_then(|tool| tool.remove("beancount-fmt"));
+
+    let options = match section {
+        Some(section) => section.try_into()?,
+        None => toml::Value::Table(table).try_into()?,
+    };
+    Ok(options)
+}
+
+/// Settings of `beancount-language-server.toml` that name a program to run or a file
+/// to write, as `key` or `table.key`.
+const UNTRUSTED_SERVER_CONFIG_SETTINGS: &[&str] = &[
+    "log_file",
+    "bean_query_cmd",
+    "bean_price_cmd",
+    "prices_file",
+    "external_checkers",
+    "bean_check.bean_check_cmd",
+    "bean_check.path",
+    "bean_check.args",
+    "bean_check.python_cmd",
+];
+
+/// Removes the [`UNTRUSTED_SERVER_CONFIG_SETTINGS`] from `table`, returning those found.
+fn remove_untrusted_settings(table: &mut toml::Table) -> Vec<&'static str> {
+    UNTRUSTED_SERVER_CONFIG_SETTINGS
+        .iter()
+        .copied()
+        .filter(|setting| match setting.split_once('.') {
+            Some((section, key)) => table
+                .get_mut(section)
+                .and_then(|section| section.as_table_mut())
+                .and_then(|section| section.remove(key))
+                .is_some(),
+            None => table.remove(*setting).is_some(),
+        })
+        .collect()
+}
+
+/// The settings `beancount-language-server.toml` may hold: those of
+/// [`BeancountLspOptions`] that don't run a program or write a file.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ServerConfigFileOptions {
+    journal_file: Option<String>,
+    formatting: Option<FormattingOptions>,
+    format_on_save: Option<bool>,
+    bean_check: Option<ServerConfigFileCheckOptions>,
+    diagnostic_flags: Option<Vec<String>>,
+    log_level: Option<String>,
+    large_file: Option<LargeFileOptions>,
+    inlay_hints: Option<InlayHintsOptions>,
+    features: Option<FeaturesOptions>,
+    fava_url: Option<String>,
+    payee_code_lens: Option<bool>,
+    section_code_lens: Option<bool>,
+    rename_subaccounts: Option<bool>,
+    account_completion_by_component: Option<bool>,
+    change_debounce_ms: Option<u64>,
+    snippets: Option<Vec<SnippetOptions>>,
+    include_new_files: Option<IncludeNewFiles>,
+    future_date_days: Option<u32>,
+    posting_lints: Option<PostingLintsOptions>,
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ServerConfigFileCheckOptions {
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    method: Option<BeancountCheckMethod>,
+    timeout_secs: Option<u64>,
+}
+
+impl From<ServerConfigFileOptions> for BeancountLspOptions {
+    fn from(options: ServerConfigFileOptions) -> Self {
+        Self {
+            journal_file: options.journal_file,
+            formatting: options.formatting,
+            format_on_save: options.format_on_save,
+            bean_check: options.bean_check.map(|check| BeancountCheckOptions {
+                method: check.method,
+                timeout_secs: check.timeout_secs,
+                ..Default::default()
+            }),
+            diagnostic_flags: options.diagnostic_flags,
+            log_level: options.log_level,
+            large_file: options.large_file,
+            inlay_hints: options.inlay_hints,
+            features: options.features,
+            fava_url: options.fava_url,
+            payee_code_lens: options.payee_code_lens,
+            section_code_lens: options.section_code_lens,
+            rename_subaccounts: options.rename_subaccounts,
+            account_completion_by_component: options.account_completion_by_component,
+            change_debounce_ms: options.change_debounce_ms,
+            snippets: options.snippets,
+            include_new_files: options.include_new_files,
+            future_date_days: options.future_date_days,
+            posting_lints: options.posting_lints,
+            ..Default::default()
+        }
     }
 }
 
@@ -142,9 +875,130 @@ impl Config {
 pub struct BeancountLspOptions {
     pub journal_file: Option<String>,
     pub formatting: Option<FormattingOptions>,
+    /// Format documents with the `formatting` settings before they are saved.
+    pub format_on_save: Option<bool>,
     pub bean_check: Option<BeancountCheckOptions>,
     /// Flags that should generate diagnostics (e.g., ["!"] for only exclamation mark)
     pub diagnostic_flags: Option<Vec<String>>,
+    /// Log level: "trace", "debug", "info", "warn", "error" or "off"
+    pub log_level: Option<String>,
+    /// File to write logs to
+    pub log_file: Option<String>,
+    /// Size limits above which expensive features are skipped for a file
+    pub large_file: Option<LargeFileOptions>,
+    /// The kinds of inlay hints shown
+    pub inlay_hints: Option<InlayHintsOptions>,
+    /// Providers to turn off, like `{"semantic_tokens": false}`
+    pub features: Option<FeaturesOptions>,
+    /// Path to the bean-query executable used by the `beancount.runQuery` command
+    pub bean_query_cmd: Option<String>,
+    /// URL of the Fava instance serving the journal, for the Fava link commands
+    pub fava_url: Option<String>,
+    /// Path to the bean-price executable used by the `beancount.updatePrices` command
+    pub bean_price_cmd: Option<String>,
+    /// File the `beancount.updatePrices` command adds prices to
+    pub prices_file: Option<String>,
+    /// Show "payee: N previous transactions" code lenses above transactions
+    pub payee_code_lens: Option<bool>,
+    /// Show "4 transactions: 1250.00 USD" lenses on `;; March 2024` section headers
+    pub section_code_lens: Option<bool>,
+    /// Rename `Assets:Bank:Checking:Sub` along with `Assets:Bank:Checking`
+    pub rename_subaccounts: Option<bool>,
+    /// Complete `Expenses:`, then `Expenses:Food:`, instead of full account names
+    pub account_completion_by_component: Option<bool>,
+    /// Commands printing JSON diagnostics for the journal, run after bean-check
+    pub external_checkers: Option<Vec<ExternalCheckerOptions>>,
+    /// Milliseconds a changed document has to be idle before it is re-indexed and checked
+    pub change_debounce_ms: Option<u64>,
+    /// Custom entries offered as completions, like a salary transaction
+    pub snippets: Option<Vec<SnippetOptions>>,
+    /// "off", "ask" or "always" include a beancount file created next to the journal
+    pub include_new_files: Option<IncludeNewFiles>,
+    /// Days after today a directive may be dated before it gets a warning
+    pub future_date_days: Option<u32>,
+    /// Severities of the posting lints
+    pub posting_lints: Option<PostingLintsOptions>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SnippetOptions {
+    /// Label of the completion item
+    pub name: String,
+    /// Text to insert, as one string or a list of lines
+    pub body: SnippetBody,
+    /// Shown next to the label
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SnippetBody {
+    Text(String),
+    Lines(Vec<String>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ExternalCheckerOptions {
+    /// Source shown with the diagnostics; the command's file name if unset
+    pub name: Option<String>,
+    /// Executable, looked up in PATH or relative to the workspace root
+    pub command: String,
+    /// Arguments passed before the journal file
+    pub args: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PostingLintsOptions {
+    /// Severity for postings whose amount is zero.
+    pub zero_amount: Option<LintSeverity>,
+
+    /// Severity for negative expenses and positive income.
+    pub sign_convention: Option<LintSeverity>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FeaturesOptions {
+    /// Offer `textDocument/semanticTokens`.
+    pub semantic_tokens: Option<bool>,
+
+    /// Offer `textDocument/codeLens`.
+    pub code_lens: Option<bool>,
+
+    /// Offer `textDocument/inlayHint`.
+    pub inlay_hints: Option<bool>,
+
+    /// Offer `textDocument/formatting`.
+    pub formatting: Option<bool>,
+
+    /// Set commit characters on completion items.
+    pub commit_characters: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct InlayHintsOptions {
+    /// Show the amount of a posting without one, e.g. "-45.23 USD".
+    pub balancing_amounts: Option<bool>,
+
+    /// Show "total = 500 USD ⚠" on transactions that do not balance.
+    pub unbalanced_totals: Option<bool>,
+
+    /// Show "≈ 28.05 USD" after amounts in other currencies than the operating currency.
+    pub conversions: Option<bool>,
+
+    /// Show "= 954.77 USD" at the end of postings, the balance of the account after them.
+    pub running_balances: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct LargeFileOptions {
+    /// Skip semantic tokens for files with more lines than this; 0 for no limit.
+    pub semantic_tokens_max_lines: Option<usize>,
+
+    /// Skip inlay hints for files with more lines than this; 0 for no limit.
+    pub inlay_hints_max_lines: Option<usize>,
+
+    /// Skip the server's own lints for files with more lines than this; 0 for no limit.
+    pub lints_max_lines: Option<usize>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -166,16 +1020,46 @@ pub struct FormattingOptions {
 
     /// Enforce consistent indentation width for postings and directives.
     pub indent_width: Option<usize>,
+
+    /// Line ending policy: "preserve", "auto", "lf" or "crlf".
+    pub line_ending: Option<LineEnding>,
+
+    /// Align metadata values within a directive.
+    pub align_metadata: Option<bool>,
+
+    /// Column at which trailing posting comments are aligned.
+    pub comment_column: Option<usize>,
+
+    /// Leave the document unchanged if formatting would alter any directive.
+    pub verify: Option<bool>,
+
+    /// Normalize the flag and spacing of transaction header lines.
+    pub normalize_transaction_headers: Option<bool>,
+
+    /// Blank lines between dated directives: a number or "keep".
+    pub blank_lines_between_directives: Option<BlankLines>,
+
+    /// Maximum number of consecutive blank lines.
+    pub max_blank_lines: Option<usize>,
+
+    /// Write dates spelled with slashes as `YYYY-MM-DD`.
+    pub normalize_dates: Option<bool>,
 }
 
 #[serde_as]
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct BeancountCheckOptions {
-    /// Method for bean-check execution: "system", "python-system", or "python-embedded"
+    /// Method for bean-check execution: "system", "python-system", "python-embedded" or "native"
     #[serde_as(as = "Option<DisplayFromStr>")]
     pub method: Option<BeancountCheckMethod>,
     /// Path to bean-check executable (for system method)
     pub bean_check_cmd: Option<String>,
+    /// Executable to run for the system method, taking precedence over `bean_check_cmd`
+    pub path: Option<String>,
+    /// Arguments passed before the journal file (for system method)
+    pub args: Option<Vec<String>>,
+    /// Seconds to wait for a check before publishing without its results; 0 for no limit
+    pub timeout_secs: Option<u64>,
     /// Path to Python executable (for python method)
     pub python_cmd: Option<String>,
 }
@@ -369,6 +1253,37 @@ mod tests {
         assert_eq!(config.bean_check.bean_check_cmd, None);
     }
 
+    #[test]
+    fn test_bean_check_path_and_args() {
+        let mut config = Config::new(PathBuf::new());
+        config
+            .update(
+                serde_json::from_str(
+                    r#"{"bean_check": {"bean_check_cmd": "bean-check", "path": "uv", "args": ["run", "bean-check"]}}"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(config.bean_check.bean_check_cmd, Some(PathBuf::from("uv")));
+        assert_eq!(config.bean_check.args, ["run", "bean-check"]);
+
+        assert_eq!(
+            config.bean_check.timeout,
+            Some(std::time::Duration::from_secs(120))
+        );
+
+        // Settings without args keep the configured ones
+        config
+            .update(serde_json::from_str(r#"{"bean_check": {"method": "system"}}"#).unwrap())
+            .unwrap();
+        assert_eq!(config.bean_check.args, ["run", "bean-check"]);
+
+        config
+            .update(serde_json::from_str(r#"{"bean_check": {"timeout_secs": 0}}"#).unwrap())
+            .unwrap();
+        assert_eq!(config.bean_check.timeout, None);
+    }
+
     #[test]
     fn test_bean_check_python_cmd() {
         let config = Config::new(PathBuf::new());
@@ -526,4 +1441,342 @@ mod tests {
             .unwrap();
         assert_eq!(config.diagnostic_flags, Vec::<String>::new());
     }
+
+    #[test]
+    fn test_parse_formatter_config_top_level() {
+        let options = parse_formatter_config("currency_column = 72\nindent_width = 4\n").unwrap();
+        assert_eq!(options.currency_column, Some(72));
+        assert_eq!(options.indent_width, Some(4));
+        assert_eq!(options.prefix_width, None);
+    }
+
+    #[test]
+    fn test_parse_formatter_config_tool_section() {
+        let options = parse_formatter_config(
+            r#"
+            [tool.beancount-fmt]
+            prefix_width = 40
+            "#,
+        )
+        .unwrap();
+        assert_eq!(options.prefix_width, Some(40));
+    }
+
+    #[test]
+    fn test_load_formatter_config_file_next_to_journal() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let ledger_dir = dir.path().join("ledger");
+        std::fs::create_dir(&ledger_dir).unwrap();
+        std::fs::write(
+            ledger_dir.join(FORMATTER_CONFIG_FILE),
+            "currency_column = 60\n",
+        )
+        .unwrap();
+
+        let mut config = Config::new(dir.path().to_path_buf());
+        config
+            .update(serde_json::json!({
+                "journal_file": "ledger/main.beancount",
+                "formatting": { "currency_column": 80, "num_width": 10 }
+            }))
+            .unwrap();
+
+        let loaded = config.load_formatter_config_file().unwrap();
+        assert_eq!(loaded, Some(ledger_dir.join(FORMATTER_CONFIG_FILE)));
+        // File settings win, unrelated client settings are kept
+        assert_eq!(config.formatting.currency_column, Some(60));
+        assert_eq!(config.formatting.num_width, Some(10));
+    }
+
+    #[test]
+    fn test_load_formatter_config_file_missing() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config::new(dir.path().to_path_buf());
+        assert_eq!(config.load_formatter_config_file().unwrap(), None);
+        assert_eq!(config.formatting.currency_column, None);
+    }
+
+    #[test]
+    fn test_load_server_config_file_is_overridden_by_client_settings() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(SERVER_CONFIG_FILE),
+            r#"
+journal_file = "main.beancount"
+diagnostic_flags = ["!", "P"]
+
+[formatting]
+currency_column = 60
+
+[bean_check]
+method = "python-system"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load(
+            dir.path().to_path_buf(),
+            Some(serde_json::json!({ "formatting": { "currency_column": 80 } })),
+        );
+        assert_eq!(config.journal_root, Some(PathBuf::from("main.beancount")));
+        assert_eq!(config.diagnostic_flags, vec!["!", "P"]);
+        assert_eq!(
+            config.bean_check.method,
+            Some(BeancountCheckMethod::PythonSystem)
+        );
+        assert_eq!(config.formatting.currency_column, Some(80));
+    }
+
+    #[test]
+    fn test_load_server_config_file_ignores_programs_and_written_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(SERVER_CONFIG_FILE),
+            r#"
+journal_file = "main.beancount"
+log_file = "/tmp/owned.log"
+bean_price_cmd = "./evil.sh"
+
+[[external_checkers]]
+command = "./evil.sh"
+
+[bean_check]
+method = "system"
+path = "./evil.sh"
+args = ["-c", "true"]
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config::new(dir.path().to_path_buf());
+        config.load_server_config_file().unwrap();
+        assert_eq!(config.journal_root, Some(PathBuf::from("main.beancount")));
+        assert_eq!(
+            config.bean_check.method,
+            Some(BeancountCheckMethod::SystemCall)
+        );
+        assert_eq!(config.bean_check.bean_check_cmd, None);
+        assert!(config.bean_check.args.is_empty());
+        assert_eq!(config.log_file, None);
+        assert_eq!(config.bean_price_cmd, None);
+        assert!(config.external_checkers.is_empty());
+    }
+
+    #[test]
+    fn test_load_server_config_file_invalid() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join(SERVER_CONFIG_FILE), "journal_file = 1\n").unwrap();
+
+        let mut config = Config::new(dir.path().to_path_buf());
+        assert!(config.load_server_config_file().is_err());
+        assert_eq!(config.journal_root, None);
+    }
+
+    #[test]
+    fn test_formatting_line_ending() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.formatting.line_ending, LineEnding::Preserve);
+        config
+            .update(serde_json::from_str(r#"{"formatting": {"line_ending": "crlf"}}"#).unwrap())
+            .unwrap();
+        assert_eq!(config.formatting.line_ending, LineEnding::Crlf);
+    }
+
+    #[test]
+    fn test_formatting_blank_lines_between_directives() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(
+            config.formatting.blank_lines_between_directives,
+            BlankLines::Keep
+        );
+        config
+            .update(
+                serde_json::from_str(r#"{"formatting": {"blank_lines_between_directives": 1}}"#)
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.formatting.blank_lines_between_directives,
+            BlankLines::Exactly(1)
+        );
+
+        let options =
+            parse_formatter_config("blank_lines_between_directives = \"keep\"\n").unwrap();
+        assert_eq!(
+            options.blank_lines_between_directives,
+            Some(BlankLines::Keep)
+        );
+        assert!(parse_formatter_config("blank_lines_between_directives = \"two\"\n").is_err());
+    }
+
+    #[test]
+    fn test_large_file_limits() {
+        let mut config = Config::new(PathBuf::new());
+        assert!(config.large_file.semantic_tokens_enabled(50_000));
+        assert!(!config.large_file.semantic_tokens_enabled(50_001));
+
+        config
+            .update(
+                serde_json::from_str(
+                    r#"{"large_file": {"inlay_hints_max_lines": 10, "lints_max_lines": 0}}"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert!(config.large_file.inlay_hints_enabled(10));
+        assert!(!config.large_file.inlay_hints_enabled(11));
+        assert!(config.large_file.lints_enabled(10_000_000));
+        assert_eq!(
+            config.large_file.semantic_tokens_max_lines,
+            LargeFileConfig::default().semantic_tokens_max_lines
+        );
+    }
+
+    #[test]
+    fn test_features_config() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.features, FeaturesConfig::default());
+
+        config
+            .update(
+                serde_json::from_str(
+                    r#"{"features": {"semantic_tokens": false, "formatting": false}}"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            config.features,
+            FeaturesConfig {
+                semantic_tokens: false,
+                code_lens: true,
+                inlay_hints: true,
+                formatting: false,
+                commit_characters: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_inlay_hints_config() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.inlay_hints, InlayHintsConfig::default());
+        assert!(!config.inlay_hints.use_ledger());
+
+        config
+            .update(
+                serde_json::from_str(
+                    r#"{"inlay_hints": {"unbalanced_totals": false, "running_balances": true}}"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        assert!(config.inlay_hints.balancing_amounts);
+        assert!(!config.inlay_hints.unbalanced_totals);
+        assert!(!config.inlay_hints.conversions);
+        assert!(config.inlay_hints.use_ledger());
+    }
+
+    #[test]
+    fn test_external_checkers() {
+        let mut config = Config::new(PathBuf::from("/workspace"));
+        config
+            .update(
+                serde_json::from_str(
+                    r#"{"external_checkers": [
+                        {"command": "./scripts/trips.py", "args": ["--strict"]},
+                        {"name": "org", "command": "org-lint"},
+                        {"command": ""}
+                    ]}"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        assert_eq!(config.external_checkers.len(), 2);
+        let trips = &config.external_checkers[0];
+        assert_eq!(trips.name, "trips");
+        assert_eq!(trips.command, PathBuf::from("/workspace/scripts/trips.py"));
+        assert_eq!(trips.args, vec!["--strict".to_string()]);
+        let org = &config.external_checkers[1];
+        assert_eq!(org.name, "org");
+        assert_eq!(org.command, PathBuf::from("org-lint"));
+    }
+
+    #[test]
+    fn test_posting_lints_config() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.posting_lints, PostingLintsConfig::default());
+        assert_eq!(config.posting_lints.zero_amount.to_lsp(), None);
+
+        config
+            .update(
+                serde_json::from_str(r#"{"posting_lints": {"sign_convention": "information"}}"#)
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(config.posting_lints.zero_amount, LintSeverity::Off);
+        assert_eq!(
+            config.posting_lints.sign_convention.to_lsp(),
+            Some(lsp_types::DiagnosticSeverity::INFORMATION)
+        );
+    }
+
+    #[test]
+    fn test_include_new_files() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(config.include_new_files, IncludeNewFiles::Ask);
+
+        config
+            .update(serde_json::from_str(r#"{"include_new_files": "always"}"#).unwrap())
+            .unwrap();
+        assert_eq!(config.include_new_files, IncludeNewFiles::Always);
+    }
+
+    #[test]
+    fn test_snippets() {
+        let mut config = Config::new(PathBuf::from("/workspace"));
+        config
+            .update(
+                serde_json::from_str(
+                    r#"{"snippets": [
+                        {"name": "salary", "body": ["$1 * \"Employer\"", "  Income:Salary  -${2:5000} EUR", "  Assets:Bank"]},
+                        {"name": "vat", "body": "$1 * \"${2:Client}\" ^vat", "description": "VAT invoice"},
+                        {"name": " ", "body": "ignored"}
+                    ]}"#,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            config.snippets,
+            vec![
+                Snippet {
+                    name: "salary".to_string(),
+                    body: "$1 * \"Employer\"\n  Income:Salary  -${2:5000} EUR\n  Assets:Bank"
+                        .to_string(),
+                    description: None,
+                },
+                Snippet {
+                    name: "vat".to_string(),
+                    body: "$1 * \"${2:Client}\" ^vat".to_string(),
+                    description: Some("VAT invoice".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_change_debounce() {
+        let mut config = Config::new(PathBuf::new());
+        assert_eq!(
+            config.change_debounce,
+            std::time::Duration::from_millis(300)
+        );
+        config
+            .update(serde_json::from_str(r#"{"change_debounce_ms": 0}"#).unwrap())
+            .unwrap();
+        assert!(config.change_debounce.is_zero());
+    }
 }
@@ -0,0 +1,817 @@
+//! Converts ledger-cli and hledger journals to beancount
+//!
+//! Transactions (with costs, prices and balance assertions), `P` prices, comments, tags
+//! and `key: value` metadata are converted line by line. Accounts are renamed with the
+//! journal's `alias` directives and the given mappings, moved under beancount's five
+//! root accounts and opened on the day of their first use; commodities are renamed to
+//! valid currencies, e.g. `$` to `USD`. Whatever has no beancount equivalent, like
+//! automated or periodic transactions, is kept as a comment and reported as a warning.
+
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// Renames applied on top of the journal's own `alias` directives.
+#[derive(Debug, Default)]
+pub struct ConvertOptions {
+    /// Account prefixes to replace, like `("Bank", "Assets:Bank")`; the longest wins.
+    pub accounts: Vec<(String, String)>,
+    /// Commodities to rename, like `("€", "EUR")`; `""` names amounts without one.
+    pub commodities: HashMap<String, String>,
+}
+
+/// The beancount journal and what did not convert cleanly.
+#[derive(Debug)]
+pub struct Conversion {
+    pub text: String,
+    /// Line numbers, counted from 1, and messages.
+    pub warnings: Vec<(usize, String)>,
+}
+
+/// Root accounts as commonly spelled in ledger journals.
+const ROOTS: &[(&str, &str)] = &[
+    ("asset", "Assets"),
+    ("assets", "Assets"),
+    ("liability", "Liabilities"),
+    ("liabilities", "Liabilities"),
+    ("equity", "Equity"),
+    ("income", "Income"),
+    ("revenue", "Income"),
+    ("revenues", "Income"),
+    ("expense", "Expenses"),
+    ("expenses", "Expenses"),
+];
+
+/// Currency symbols and their ISO codes.
+const SYMBOLS: &[(&str, &str)] = &[
+    ("$", "USD"),
+    ("US$", "USD"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("¥", "JPY"),
+    ("₹", "INR"),
+    ("₽", "RUB"),
+    ("₩", "KRW"),
+    ("₿", "BTC"),
+    ("R$", "BRL"),
+    ("C$", "CAD"),
+    ("A$", "AUD"),
+    ("CHF", "CHF"),
+];
+
+static HEADER_REGEX: OnceLock<Regex> = OnceLock::new();
+static POSTING_REGEX: OnceLock<Regex> = OnceLock::new();
+static AMOUNT_REGEX: OnceLock<Regex> = OnceLock::new();
+static PRICE_REGEX: OnceLock<Regex> = OnceLock::new();
+static METADATA_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Date, optional auxiliary date, state, code and description (with its comment).
+fn header_regex() -> &'static Regex {
+    HEADER_REGEX.get_or_init(|| {
+        Regex::new(
+            r"^(\d{4})[/.-](\d{1,2})[/.-](\d{1,2})(?:=\S+)?(?:\s+([*!]))?(?:\s+\(([^)]*)\))?\s*(.*)$",
+        )
+        .expect("Failed to compile transaction header regex")
+    })
+}
+
+/// State, account (single spaces allowed) and, after two spaces or a tab, the rest.
+fn posting_regex() -> &'static Regex {
+    POSTING_REGEX.get_or_init(|| {
+        Regex::new(r"^(?:([*!])\s+)?(\S(?:\S| \S)*)(?:(?: {2,}|\t)\s*(.*))?$")
+            .expect("Failed to compile posting regex")
+    })
+}
+
+/// Sign, commodity before, number and commodity after, e.g. `-$1,000.00` or `5 "ABC 1"`.
+fn amount_regex() -> &'static Regex {
+    AMOUNT_REGEX.get_or_init(|| {
+        Regex::new(
+            r#"^(-)?\s*(?:("[^"]+"|[^\s\d"+.,-][^\s\d"+-]*)\s*)?([+-]?(?:\d[\d.,]*|[.,]\d+))\s*("[^"]+"|[^\s\d"+.,-][^\s\d"]*)?$"#,
+        )
+        .expect("Failed to compile amount regex")
+    })
+}
+
+/// `P` directive: date, optional time, commodity and price.
+fn price_regex() -> &'static Regex {
+    PRICE_REGEX.get_or_init(|| {
+        Regex::new(
+            r#"^P\s+(\d{4})[/.-](\d{1,2})[/.-](\d{1,2})(?:\s+\d{1,2}:\d{2}(?::\d{2})?)?\s+("[^"]+"|\S+)\s+(.+)$"#,
+        )
+        .expect("Failed to compile price regex")
+    })
+}
+
+/// `key: value` comments, which ledger and hledger treat as metadata.
+fn metadata_regex() -> &'static Regex {
+    METADATA_REGEX.get_or_init(|| {
+        Regex::new(r"^([A-Za-z][\w-]*):\s+(.*)$").expect("Failed to compile metadata regex")
+    })
+}
+
+/// Converts the ledger or hledger `journal` to beancount.
+pub fn convert(journal: &str, options: &ConvertOptions) -> Conversion {
+    let mut converter = Converter {
+        options,
+        aliases: Vec::new(),
+        decimal_comma: false,
+        opened: HashMap::new(),
+        declared: Vec::new(),
+        unknown_roots: HashSet::new(),
+        body: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let lines: Vec<&str> = journal.lines().collect();
+    let mut index = 0;
+    while index < lines.len() {
+        index = converter.entry(&lines, index);
+    }
+    converter.finish()
+}
+
+struct Converter<'a> {
+    options: &'a ConvertOptions,
+    /// The journal's `alias` directives in effect.
+    aliases: Vec<(String, String)>,
+    /// Set by `decimal-mark ,`.
+    decimal_comma: bool,
+    /// First date each beancount account is used on.
+    opened: HashMap<String, String>,
+    /// Accounts from `account` directives.
+    declared: Vec<String>,
+    unknown_roots: HashSet<String>,
+    body: Vec<String>,
+    warnings: Vec<(usize, String)>,
+}
+
+/// What a `;` comment inside a transaction turns into.
+enum Comment {
+    Tags(Vec<String>),
+    Metadata(String, String),
+    Text(String),
+}
+
+impl Converter<'_> {
+    /// Converts the entry starting at `index`, returning the index after it.
+    fn entry(&mut self, lines: &[&str], index: usize) -> usize {
+        let line = lines[index];
+        let number = index + 1;
+        // Indented lines after an entry belong to it
+        let end = lines[index + 1..]
+            .iter()
+            .position(|line| line.trim().is_empty() || !line.starts_with([' ', '\t']))
+            .map_or(lines.len(), |offset| index + 1 + offset);
+        let block = &lines[index + 1..end];
+
+        if line.trim().is_empty() {
+            self.body.push(String::new());
+            return index + 1;
+        }
+        if line.starts_with([';', '#', '%', '|', '*']) {
+            self.body.push(format!(";{}", &line[1..]));
+            return index + 1;
+        }
+        if line.starts_with([' ', '\t']) {
+            let trimmed = line.trim();
+            if let Some(comment) = trimmed.strip_prefix(';') {
+                self.body.push(format!(";{comment}"));
+            } else {
+                self.keep(number, &[line], "indented line outside of a transaction");
+            }
+            return index + 1;
+        }
+        if line.starts_with(|c: char| c.is_ascii_digit()) {
+            self.transaction(number, line, block);
+            return end;
+        }
+
+        let (keyword, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(keyword, argument)| (keyword, argument.trim()));
+        // Directive arguments may end in a comment
+        let argument = argument
+            .split_once(" ;")
+            .map_or(argument, |(argument, _)| argument)
+            .trim();
+        match keyword {
+            "P" => self.price(number, line),
+            "account" => {
+                let account = self.account(number, argument);
+                self.declared.push(account);
+            }
+            "alias" => match argument.split_once('=') {
+                Some((from, to)) => self
+                    .aliases
+                    .push((from.trim().to_string(), to.trim().to_string())),
+                None => self.keep(number, &[line], "alias without `=`"),
+            },
+            "end" if argument == "aliases" => self.aliases.clear(),
+            "unalias" => self.aliases.retain(|(from, _)| from != argument),
+            "include" => self.body.push(format!("include {}", quote(argument))),
+            "decimal-mark" => self.decimal_comma = argument == ",",
+            // Declarations beancount does without
+            "commodity" | "payee" | "tag" => {}
+            "comment" | "test" => {
+                let close = format!("end {keyword}");
+                let end = lines[index + 1..]
+                    .iter()
+                    .position(|line| line.trim() == close)
+                    .map_or(lines.len(), |offset| index + 2 + offset);
+                for line in &lines[index..end] {
+                    self.body.push(format!("; {line}").trim_end().to_string());
+                }
+                return end;
+            }
+            _ => {
+                let mut kept = vec![line];
+                kept.extend_from_slice(block);
+                self.keep(number, &kept, &format!("`{keyword}` is not supported"));
+            }
+        }
+        end
+    }
+
+    fn transaction(&mut self, number: usize, header: &str, block: &[&str]) {
+        let Some(captures) = header_regex().captures(header) else {
+            let mut kept = vec![header];
+            kept.extend_from_slice(block);
+            self.keep(number, &kept, "unrecognized transaction date");
+            return;
+        };
+        let Some(date) = date(&captures[1], &captures[2], &captures[3]) else {
+            let mut kept = vec![header];
+            kept.extend_from_slice(block);
+            self.keep(number, &kept, "invalid date");
+            return;
+        };
+        let flag = captures.get(4).map_or("*", |flag| flag.as_str());
+        let (description, header_comment) = split_comment(&captures[6]);
+
+        let mut tags: BTreeSet<String> = BTreeSet::new();
+        let mut metadata = Vec::new();
+        if let Some(code) = captures.get(5).filter(|code| !code.as_str().is_empty()) {
+            metadata.push(format!("  code: {}", quote(code.as_str())));
+        }
+        let mut postings = Vec::new();
+        let mut balances = Vec::new();
+        let mut comments: Vec<&str> = header_comment.into_iter().collect();
+
+        for (line, number) in block.iter().zip(number + 1..) {
+            let trimmed = line.trim();
+            if let Some(comment) = trimmed.strip_prefix(';') {
+                comments.push(comment);
+                continue;
+            }
+            // Comments before the first posting belong to the transaction
+            let indent = if postings.is_empty() { "  " } else { "    " };
+            for comment in comments.drain(..) {
+                match parse_comment(comment) {
+                    Comment::Tags(new) => tags.extend(new),
+                    Comment::Metadata(key, value) if postings.is_empty() => {
+                        metadata.push(format!("{indent}{key}: {}", quote(&value)))
+                    }
+                    Comment::Metadata(key, value) => {
+                        postings.push(format!("{indent}{key}: {}", quote(&value)))
+                    }
+                    Comment::Text(text) if postings.is_empty() => {
+                        metadata.push(format!("{indent};{text}"))
+                    }
+                    Comment::Text(text) => postings.push(format!("{indent};{text}")),
+                }
+            }
+            match self.posting(number, trimmed, &date) {
+                Ok((posting, balance)) => {
+                    postings.push(posting);
+                    balances.extend(balance);
+                }
+                Err(message) => {
+                    self.warn(number, &format!("{message}, kept as a comment"));
+                    postings.push(format!("  ; {trimmed}"));
+                }
+            }
+        }
+        for comment in comments {
+            match parse_comment(comment) {
+                Comment::Tags(new) => tags.extend(new),
+                Comment::Metadata(key, value) => {
+                    postings.push(format!("    {key}: {}", quote(&value)))
+                }
+                Comment::Text(text) => postings.push(format!("  ;{text}")),
+            }
+        }
+
+        let mut header = format!("{date} {flag}");
+        match description.split_once('|') {
+            // hledger's `payee | note`
+            Some((payee, narration)) => {
+                header.push_str(&format!(
+                    " {} {}",
+                    quote(payee.trim()),
+                    quote(narration.trim())
+                ));
+            }
+            None => header.push_str(&format!(" {}", quote(description.trim()))),
+        }
+        for tag in &tags {
+            header.push_str(&format!(" #{tag}"));
+        }
+        self.body.push(header);
+        self.body.extend(metadata);
+        self.body.extend(postings);
+        if !balances.is_empty() {
+            self.body.push(String::new());
+            self.body.extend(balances);
+        }
+    }
+
+    /// The posting and the `balance` directive for its assertion, if it has one.
+    fn posting(
+        &mut self,
+        number: usize,
+        text: &str,
+        date: &str,
+    ) -> Result<(String, Option<String>), String> {
+        let (text, comment) = split_comment(text);
+        let captures = posting_regex()
+            .captures(text)
+            .ok_or_else(|| "unrecognized posting".to_string())?;
+        let name = &captures[2];
+        if name.starts_with('(') && name.ends_with(')') {
+            return Err("unbalanced virtual postings are not supported".to_string());
+        }
+        let name = name
+            .strip_prefix('[')
+            .and_then(|name| name.strip_suffix(']'))
+            .unwrap_or(name);
+        let account = self.account(number, name);
+        self.open(&account, date);
+
+        let mut posting = String::from("  ");
+        if let Some(flag) = captures.get(1) {
+            posting.push_str(flag.as_str());
+            posting.push(' ');
+        }
+        posting.push_str(&account);
+
+        let rest = captures.get(3).map_or("", |rest| rest.as_str()).trim();
+        let (rest, assertion) = match rest.split_once('=') {
+            Some((rest, assertion)) => (rest.trim(), Some(assertion.trim())),
+            None => (rest, None),
+        };
+        let (rest, price) = match rest.split_once('@') {
+            Some((rest, price)) => match price.strip_prefix('@') {
+                Some(total) => (rest.trim(), Some(("@@", total.trim()))),
+                None => (rest.trim(), Some(("@", price.trim()))),
+            },
+            None => (rest, None),
+        };
+        let (rest, cost) = match rest.split_once('{') {
+            Some((rest, cost)) => (rest.trim(), Some(cost)),
+            None => (rest, None),
+        };
+
+        if !rest.is_empty() {
+            posting.push_str("  ");
+            posting.push_str(&self.amount(number, rest)?);
+        }
+        if let Some(cost) = cost {
+            // `{{total}}`, `{=fixed}` and ledger's lot date and note after the braces
+            let total = cost.starts_with('{');
+            let cost = cost.trim_start_matches('{');
+            let cost = cost.split('}').next().unwrap_or_default();
+            let cost = cost.trim().trim_start_matches('=').trim();
+            let cost = self.amount(number, cost)?;
+            if total {
+                posting.push_str(&format!(" {{{{{cost}}}}}"));
+            } else {
+                posting.push_str(&format!(" {{{cost}}}"));
+            }
+        }
+        if let Some((operator, price)) = price {
+            posting.push_str(&format!(" {operator} {}", self.amount(number, price)?));
+        }
+        if let Some(comment) = comment {
+            match parse_comment(comment) {
+                Comment::Metadata(key, value) => {
+                    posting.push_str(&format!("\n    {key}: {}", quote(&value)))
+                }
+                Comment::Tags(_) | Comment::Text(_) => posting.push_str(&format!(" ;{comment}")),
+            }
+        }
+
+        let balance = match assertion {
+            Some(assertion) => {
+                let amount = self.amount(number, assertion)?;
+                // Beancount checks balances at the start of the day
+                let next_day = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.succ_opt())
+                    .map_or_else(|| date.to_string(), |date| date.to_string());
+                Some(format!("{next_day} balance {account}  {amount}"))
+            }
+            None => None,
+        };
+        Ok((posting, balance))
+    }
+
+    fn price(&mut self, number: usize, line: &str) {
+        let Some(captures) = price_regex().captures(line) else {
+            self.keep(number, &[line], "unrecognized price");
+            return;
+        };
+        let Some(date) = date(&captures[1], &captures[2], &captures[3]) else {
+            self.keep(number, &[line], "invalid date");
+            return;
+        };
+        let commodity = self.commodity(&captures[4]);
+        let (price, _) = split_comment(&captures[5]);
+        match self.amount(number, price.trim()) {
+            Ok(price) => self.body.push(format!("{date} price {commodity}  {price}")),
+            Err(message) => self.keep(number, &[line], &message),
+        }
+    }
+
+    /// `NUMBER CURRENCY` for a ledger amount like `$-1,000.50`.
+    fn amount(&mut self, number: usize, text: &str) -> Result<String, String> {
+        let captures = amount_regex()
+            .captures(text.trim())
+            .ok_or_else(|| format!("unsupported amount `{}`", text.trim()))?;
+        let (thousands, decimal) = if self.decimal_comma {
+            ('.', ',')
+        } else {
+            (',', '.')
+        };
+        let mut value: String = captures[3]
+            .chars()
+            .filter(|c| *c != thousands && *c != '+')
+            .map(|c| if c == decimal { '.' } else { c })
+            .collect();
+        if value.ends_with('.') {
+            value.pop();
+        }
+        if value.starts_with('.') || value.starts_with("-.") {
+            value = value.replacen('.', "0.", 1);
+        }
+        if captures.get(1).is_some() {
+            value = match value.strip_prefix('-') {
+                Some(positive) => positive.to_string(),
+                None => format!("-{value}"),
+            };
+        }
+
+        let commodity = match (captures.get(2), captures.get(4)) {
+            (Some(_), Some(_)) => return Err(format!("unsupported amount `{}`", text.trim())),
+            (Some(commodity), None) | (None, Some(commodity)) => self.commodity(commodity.as_str()),
+            (None, None) => match self.options.commodities.get("") {
+                Some(commodity) => commodity.clone(),
+                None => {
+                    self.warn(number, &format!("amount `{value}` has no commodity"));
+                    return Ok(value);
+                }
+            },
+        };
+        Ok(format!("{value} {commodity}"))
+    }
+
+    /// A valid beancount currency for a ledger commodity.
+    fn commodity(&self, commodity: &str) -> String {
+        let commodity = commodity.trim_matches('"');
+        if let Some(renamed) = self.options.commodities.get(commodity) {
+            return renamed.clone();
+        }
+        if let Some((_, code)) = SYMBOLS.iter().find(|(symbol, _)| *symbol == commodity) {
+            return code.to_string();
+        }
+        let mut currency: String = commodity
+            .to_uppercase()
+            .chars()
+            .map(|c| match c {
+                'A'..='Z' | '0'..='9' | '\'' | '.' | '_' | '-' => c,
+                _ => '-',
+            })
+            .collect();
+        currency = currency
+            .trim_end_matches(|c: char| !c.is_ascii_alphanumeric())
+            .to_string();
+        if !currency.starts_with(|c: char| c.is_ascii_uppercase()) {
+            currency.insert(0, 'X');
+        }
+        currency.truncate(24);
+        currency
+    }
+
+    /// The beancount account for a ledger account name.
+    fn account(&mut self, number: usize, name: &str) -> String {
+        let name = rename(name, &self.aliases).unwrap_or_else(|| name.to_string());
+        let name = rename(&name, &self.options.accounts).unwrap_or(name);
+
+        let mut components = name.split(':');
+        let root = components.next().unwrap_or_default();
+        let mut account = match ROOTS
+            .iter()
+            .find(|(spelling, _)| spelling.eq_ignore_ascii_case(root))
+        {
+            Some((_, root)) => root.to_string(),
+            None => {
+                if self.unknown_roots.insert(root.to_string()) {
+                    self.warn(
+                        number,
+                        &format!("`{root}` is not a beancount root account, moved under Equity"),
+                    );
+                }
+                format!("Equity:{}", component(root))
+            }
+        };
+        for name in components {
+            account.push(':');
+            account.push_str(&component(name));
+        }
+        account
+    }
+
+    fn open(&mut self, account: &str, date: &str) {
+        let opened = self
+            .opened
+            .entry(account.to_string())
+            .or_insert_with(|| date.to_string());
+        if date < opened.as_str() {
+            *opened = date.to_string();
+        }
+    }
+
+    /// Keeps `lines` as comments, warning about the first.
+    fn keep(&mut self, number: usize, lines: &[&str], reason: &str) {
+        self.warn(number, &format!("{reason}, kept as a comment"));
+        for line in lines {
+            self.body.push(format!("; {line}").trim_end().to_string());
+        }
+    }
+
+    fn warn(&mut self, number: usize, message: &str) {
+        self.warnings.push((number, message.to_string()));
+    }
+
+    /// The journal with `open` directives for every account in front.
+    fn finish(mut self) -> Conversion {
+        // Declared accounts never posted to are opened with the first of the others
+        if let Some(first) = self.opened.values().min().cloned() {
+            for account in std::mem::take(&mut self.declared) {
+                self.opened.entry(account).or_insert_with(|| first.clone());
+            }
+        }
+        let mut opens: Vec<(&String, &String)> = self
+            .opened
+            .iter()
+            .map(|(account, date)| (date, account))
+            .collect();
+        opens.sort();
+
+        let mut lines: Vec<String> = opens
+            .into_iter()
+            .map(|(date, account)| format!("{date} open {account}"))
+            .collect();
+        if !lines.is_empty() && self.body.first().is_some_and(|line| !line.is_empty()) {
+            lines.push(String::new());
+        }
+        lines.append(&mut self.body);
+        let mut text = lines.join("\n");
+        text.push('\n');
+        Conversion {
+            text,
+            warnings: self.warnings,
+        }
+    }
+}
+
+/// The ISO date, if it exists.
+fn date(year: &str, month: &str, day: &str) -> Option<String> {
+    chrono::NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?)
+        .map(|date| date.to_string())
+}
+
+/// Splits off a `;` comment, returning the text after the `;`.
+fn split_comment(text: &str) -> (&str, Option<&str>) {
+    match text.split_once(';') {
+        Some((text, comment)) => (text.trim_end(), Some(comment)),
+        None => (text, None),
+    }
+}
+
+fn parse_comment(comment: &str) -> Comment {
+    let trimmed = comment.trim();
+    // `:tag1:tag2:`
+    if trimmed.len() > 2 && trimmed.starts_with(':') && trimmed.ends_with(':') {
+        let tags = trimmed
+            .split(':')
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| {
+                tag.chars()
+                    .map(|c| {
+                        if c.is_alphanumeric() || "-_/.".contains(c) {
+                            c
+                        } else {
+                            '-'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        return Comment::Tags(tags);
+    }
+    if let Some(captures) = metadata_regex().captures(trimmed) {
+        let mut key =
+            captures[1].replace(|c: char| !c.is_alphanumeric() && c != '-' && c != '_', "-");
+        key[..1].make_ascii_lowercase();
+        return Comment::Metadata(key, captures[2].trim().to_string());
+    }
+    Comment::Text(comment.trim_end().to_string())
+}
+
+/// Replaces the longest prefix of `name` in `renames`, matching whole components.
+fn rename(name: &str, renames: &[(String, String)]) -> Option<String> {
+    renames
+        .iter()
+        .filter(|(from, _)| {
+            name.strip_prefix(from.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+        })
+        .max_by_key(|(from, _)| from.len())
+        .map(|(from, to)| format!("{to}{}", &name[from.len()..]))
+}
+
+/// An account component: capitalized, with runs of other characters as a dash.
+fn component(name: &str) -> String {
+    let mut component = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            component.push(c);
+        } else if !component.is_empty() && !component.ends_with('-') {
+            component.push('-');
+        }
+    }
+    let component = component.trim_end_matches('-');
+    let mut chars = component.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => "X".to_string(),
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_journal() {
+        let journal = "\
+; Personal finances
+alias checking=assets:bank:checking
+account expenses:food
+
+P 2024/01/01 AAPL $185.64
+
+2024/01/05 * (1042) Grocer | weekly shopping  ; :food:
+    ; receipt: scanned
+    expenses:food:groceries            $25.50
+    checking                           = $974.50
+    ; note: paid by card
+
+2024-01-07 ! Broker
+    assets:broker    10 AAPL @ $186.00
+    assets:bank:checking  -1,860.00 € @@ $2,024.00 ; fx
+";
+        let conversion = convert(journal, &ConvertOptions::default());
+        assert_eq!(
+            conversion.text,
+            "\
+2024-01-05 open Assets:Bank:Checking
+2024-01-05 open Expenses:Food
+2024-01-05 open Expenses:Food:Groceries
+2024-01-07 open Assets:Broker
+
+; Personal finances
+
+2024-01-01 price AAPL  185.64 USD
+
+2024-01-05 * \"Grocer\" \"weekly shopping\" #food
+  code: \"1042\"
+  receipt: \"scanned\"
+  Expenses:Food:Groceries  25.50 USD
+  Assets:Bank:Checking
+    note: \"paid by card\"
+
+2024-01-06 balance Assets:Bank:Checking  974.50 USD
+
+2024-01-07 ! \"Broker\"
+  Assets:Broker  10 AAPL @ 186.00 USD
+  Assets:Bank:Checking  -1860.00 EUR @@ 2024.00 USD ; fx
+"
+        );
+        assert!(conversion.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_decimal_mark_and_renames() {
+        let journal = "\
+decimal-mark ,
+2024-02-01 Rent
+    Bank:Giro  -1.200,00 EUR
+    Ausgaben:Miete  1.200,00 EUR
+";
+        let options = ConvertOptions {
+            accounts: vec![("Bank".to_string(), "Assets:Bank".to_string())],
+            commodities: HashMap::new(),
+        };
+        let conversion = convert(journal, &options);
+        assert!(
+            conversion
+                .text
+                .contains("  Assets:Bank:Giro  -1200.00 EUR\n")
+        );
+        assert!(
+            conversion
+                .text
+                .contains("  Equity:Ausgaben:Miete  1200.00 EUR\n")
+        );
+        assert_eq!(
+            conversion.warnings,
+            [(
+                4,
+                "`Ausgaben` is not a beancount root account, moved under Equity".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_entries_are_kept_as_comments() {
+        let journal = "\
+= expenses:food
+    (budget:food)  -1
+
+2024/13/01 Typo
+    expenses:misc  $1
+    assets:cash
+
+2024/03/01 Virtual
+    (budget:food)  $-5
+    assets:cash  $5 ; pocket money
+";
+        let conversion = convert(journal, &ConvertOptions::default());
+        assert_eq!(
+            conversion.text,
+            "\
+2024-03-01 open Assets:Cash
+
+; = expenses:food
+;     (budget:food)  -1
+
+; 2024/13/01 Typo
+;     expenses:misc  $1
+;     assets:cash
+
+2024-03-01 * \"Virtual\"
+  ; (budget:food)  $-5
+  Assets:Cash  5 USD ; pocket money
+"
+        );
+        assert_eq!(
+            conversion.warnings,
+            [
+                (1, "`=` is not supported, kept as a comment".to_string()),
+                (4, "invalid date, kept as a comment".to_string()),
+                (
+                    9,
+                    "unbalanced virtual postings are not supported, kept as a comment".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commodity() {
+        let options = ConvertOptions {
+            accounts: Vec::new(),
+            commodities: HashMap::from([("gold".to_string(), "AU".to_string())]),
+        };
+        let converter = Converter {
+            options: &options,
+            aliases: Vec::new(),
+            decimal_comma: false,
+            opened: HashMap::new(),
+            declared: Vec::new(),
+            unknown_roots: HashSet::new(),
+            body: Vec::new(),
+            warnings: Vec::new(),
+        };
+        assert_eq!(converter.commodity("$"), "USD");
+        assert_eq!(converter.commodity("gold"), "AU");
+        assert_eq!(converter.commodity("\"Vanguard 500\""), "VANGUARD-500");
+        assert_eq!(converter.commodity("h"), "H");
+        assert_eq!(converter.commodity("1INCH"), "X1INCH");
+    }
+}
@@ -1,7 +1,9 @@
 use crate::from_json;
+use crate::scheduler::Lane;
 use crate::server::LspServerState;
 use crate::server::LspServerStateSnapshot;
 use crate::server::Task;
+use crate::utils::ToFilePath;
 use anyhow::Result;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -26,6 +28,15 @@ where
     }
 }
 
+fn content_modified(id: lsp_server::RequestId) -> lsp_server::Response {
+    tracing::debug!("Dropping request {} computed for outdated content", id);
+    lsp_server::Response::new_err(
+        id,
+        lsp_server::ErrorCode::ContentModified as i32,
+        "content modified".to_string(),
+    )
+}
+
 // A helper struct to dispatch LSP requests to functions.
 pub(crate) struct RequestRouter {
     handlers: HashMap<String, DispatchHandler>,
@@ -98,16 +109,18 @@ impl RequestRouter {
                 move |state, req| match from_json::<R::Params>(R::METHOD, req.params) {
                     Ok(params) => {
                         let id = req.id;
-                        let snapshot = state.snapshot();
                         let sender = state.task_sender.clone();
-                        state.thread_pool.execute(move || {
-                            let result = f(snapshot, params);
-                            if let Err(e) =
-                                sender.send(Task::Response(result_to_response::<R>(id, result)))
-                            {
-                                tracing::error!("Failed to send response: {}", e);
-                            }
-                        });
+                        state.spawn(
+                            Lane::Interactive,
+                            Box::new(move |snapshot| {
+                                let result = f(snapshot, params);
+                                if let Err(e) =
+                                    sender.send(Task::Response(result_to_response::<R>(id, result)))
+                                {
+                                    tracing::error!("Failed to send response: {}", e);
+                                }
+                            }),
+                        );
                     }
                     Err(err) => {
                         let response = lsp_server::Response::new_err(
@@ -143,16 +156,87 @@ impl RequestRouter {
                         pre(state, &params);
 
                         let id = req.id;
-                        let snapshot = state.snapshot();
                         let sender = state.task_sender.clone();
-                        state.thread_pool.execute(move || {
-                            let result = f(snapshot, params);
-                            if let Err(e) =
-                                sender.send(Task::Response(result_to_response::<R>(id, result)))
-                            {
-                                tracing::error!("Failed to send response: {}", e);
-                            }
-                        });
+                        state.spawn(
+                            Lane::Interactive,
+                            Box::new(move |snapshot| {
+                                let result = f(snapshot, params);
+                                if let Err(e) =
+                                    sender.send(Task::Response(result_to_response::<R>(id, result)))
+                                {
+                                    tracing::error!("Failed to send response: {}", e);
+                                }
+                            }),
+                        );
+                    }
+                    Err(err) => {
+                        let response = lsp_server::Response::new_err(
+                            req.id,
+                            lsp_server::ErrorCode::InvalidParams as i32,
+                            err.to_string(),
+                        );
+                        state.respond(response);
+                    }
+                },
+            ),
+        )?;
+        Ok(self)
+    }
+
+    // Like `on_with`, for read requests whose result is only useful for the latest
+    // content of a document. If the document changes while the request waits on
+    // the thread pool or is being computed, `ContentModified` is returned instead,
+    // so the server does not fall behind a burst of edits.
+    pub fn on_latest<R>(
+        &mut self,
+        pre: fn(&mut LspServerState, &R::Params),
+        f: fn(LspServerStateSnapshot, R::Params) -> Result<R::Result>,
+        text_document: fn(&R::Params) -> &lsp_types::Uri,
+    ) -> Result<&mut Self>
+    where
+        R: lsp_types::request::Request + 'static,
+        R::Params: DeserializeOwned + Send + 'static,
+        R::Result: Serialize + 'static,
+    {
+        self.insert_handler(
+            R::METHOD,
+            Box::new(
+                move |state, req| match from_json::<R::Params>(R::METHOD, req.params) {
+                    Ok(params) => {
+                        pre(state, &params);
+
+                        let id = req.id;
+                        let document =
+                            text_document(&params).to_file_path().ok().and_then(|path| {
+                                let version = state.open_docs.get(&path)?.version;
+                                Some((path, version))
+                            });
+                        let versions = state.doc_versions.clone();
+                        let is_stale = move || {
+                            document
+                                .as_ref()
+                                .is_some_and(|(path, version)| versions.is_stale(path, *version))
+                        };
+
+                        let sender = state.task_sender.clone();
+                        state.spawn(
+                            Lane::Interactive,
+                            Box::new(move |snapshot| {
+                                let response = if is_stale() {
+                                    content_modified(id)
+                                } else {
+                                    let result = f(snapshot, params);
+                                    if is_stale() {
+                                        content_modified(id)
+                                    } else {
+                                        result_to_response::<R>(id, result)
+                                    }
+                                };
+                                if let Err(e) = sender.send(Task::Response(response)) {
+                                    tracing::error!("Failed to send response: {}", e);
+                                }
+                            }),
+                        );
                     }
                     Err(err) => {
                         let response = lsp_server::Response::new_err(
@@ -280,4 +364,81 @@ mod tests {
             "mismatched params should return InvalidParams"
         );
     }
+
+    fn completion_request(id: i32) -> lsp_server::Request {
+        lsp_server::Request {
+            id: lsp_server::RequestId::from(id),
+            method: lsp_types::request::Completion::METHOD.to_string(),
+            params: json!({
+                "textDocument": { "uri": "file:///test/main.bean" },
+                "position": { "line": 0, "character": 0 }
+            }),
+        }
+    }
+
+    fn latest_state() -> (LspServerState, RequestRouter) {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(PathBuf::from("/test")));
+        state.scheduler = crate::scheduler::Scheduler::new(1);
+
+        let path = PathBuf::from("/test/main.bean");
+        state.open_docs.insert(
+            path.clone(),
+            crate::document::Document {
+                content: ropey::Rope::from_str(""),
+                version: 1,
+            },
+        );
+        state.doc_versions.set(path, 1);
+
+        let mut router = RequestRouter::new();
+        router
+            .on_latest::<lsp_types::request::Completion>(
+                |_, _| {},
+                |_, _| Ok(None),
+                |params| &params.text_document_position.text_document.uri,
+            )
+            .unwrap();
+        (state, router)
+    }
+
+    fn task_response(state: &LspServerState) -> lsp_server::Response {
+        match state.task_receiver.recv().expect("response should be sent") {
+            Task::Response(response) => response,
+            other => panic!("expected response, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn latest_request_responds_for_current_content() {
+        let (mut state, router) = latest_state();
+        router.dispatch(&mut state, completion_request(1));
+
+        let response = task_response(&state);
+        assert!(response.error.is_none());
+    }
+
+    #[test]
+    fn latest_request_outdated_by_edit_returns_content_modified() {
+        let (mut state, router) = latest_state();
+
+        // Keep the only worker busy so the request stays queued during the edit
+        let (release, blocked) = crossbeam_channel::bounded::<()>(0);
+        state.spawn(
+            Lane::Interactive,
+            Box::new(move |_| {
+                let _ = blocked.recv();
+            }),
+        );
+        router.dispatch(&mut state, completion_request(1));
+        state.doc_versions.set(PathBuf::from("/test/main.bean"), 2);
+        release.send(()).unwrap();
+        let lane = state.scheduler.finished_receiver.recv().unwrap();
+        state.job_finished(lane);
+
+        let error = task_response(&state)
+            .error
+            .expect("expected error response");
+        assert_eq!(error.code, lsp_server::ErrorCode::ContentModified as i32);
+    }
 }
@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
 #[derive(Clone)]
 pub struct Document {
     /// The textual content of the document.
@@ -37,6 +41,34 @@ impl Document {
     }
 }
 
+/// Latest version of every open document.
+///
+/// Shared with request workers so that a request queued behind a burst of edits
+/// can notice that its snapshot is outdated.
+#[derive(Debug, Default)]
+pub(crate) struct DocumentVersions(RwLock<HashMap<PathBuf, i32>>);
+
+impl DocumentVersions {
+    pub fn set(&self, path: PathBuf, version: i32) {
+        if let Ok(mut versions) = self.0.write() {
+            versions.insert(path, version);
+        }
+    }
+
+    pub fn remove(&self, path: &Path) {
+        if let Ok(mut versions) = self.0.write() {
+            versions.remove(path);
+        }
+    }
+
+    /// Whether `path` changed since it was at `version`.
+    pub fn is_stale(&self, path: &Path, version: i32) -> bool {
+        self.0
+            .read()
+            .is_ok_and(|versions| versions.get(path).is_some_and(|latest| *latest != version))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -13,6 +13,7 @@
 //! This is more efficient and clearer than manual tree walking.
 
 use crate::beancount_data::BeancountData;
+use crate::include_graph::FileIncludes;
 use crate::server::LspServerStateSnapshot;
 use crate::server::ProgressMsg;
 use crate::server::Task;
@@ -21,7 +22,7 @@ use glob::glob;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::path;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::SystemTime;
 use tracing::error;
@@ -88,7 +89,6 @@ pub(crate) fn parse_initial_forest(
         processed += 1;
 
         let text = read_file_cached(&file, &mut file_cache)?;
-        let bytes = text.as_bytes();
 
         let mut parser = tree_sitter::Parser::new();
         parser.set_language(&tree_sitter_beancount::language())?;
@@ -103,6 +103,7 @@ pub(crate) fn parse_initial_forest(
 
         let content = ropey::Rope::from_str(text.as_str());
         let beancount_data = BeancountData::new(&tree_arc, &content);
+        let includes = file_includes(&tree_arc, &text, &file);
 
         // Always send data for the parsed file (server needs it)
         // But we could batch progress updates in the future if needed
@@ -112,100 +113,19 @@ pub(crate) fn parse_initial_forest(
             data: Box::new(Some((
                 file.clone(),
                 tree_arc.clone(),
+                content,
                 Arc::new(beancount_data),
+                includes.clone(),
             ))),
         })) {
             tracing::error!("Failed to send forest init progress with data: {}", e);
         }
 
-        // Extract include patterns using tree-sitter query
-        let include_query_string = r#"
-        (include (string) @string)
-        "#;
-        let include_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), include_query_string)
-                .unwrap_or_else(|_| panic!("Invalid query for includes: {include_query_string}"));
-        let mut cursor_qry = tree_sitter::QueryCursor::new();
-        let mut include_matches = cursor_qry.matches(&include_query, tree_arc.root_node(), bytes);
-
-        let include_patterns: Vec<String> = {
-            use tree_sitter::StreamingIterator;
-            let mut patterns = Vec::new();
-
-            while let Some(qmatch) = include_matches.next() {
-                for capture in qmatch.captures {
-                    let filename = match capture.node.utf8_text(bytes) {
-                        Ok(text) => text.trim_start_matches('"').trim_end_matches('"'),
-                        Err(e) => {
-                            tracing::warn!("Failed to extract include filename: {}", e);
-                            continue;
-                        }
-                    };
-
-                    let path = path::Path::new(filename);
-
-                    let path = if path.is_absolute() {
-                        path.to_path_buf()
-                    } else if file.is_absolute() {
-                        match file.parent() {
-                            Some(parent) => parent.join(path),
-                            None => {
-                                tracing::warn!("File has no parent directory: {:?}", file);
-                                path.to_path_buf()
-                            }
-                        }
-                    } else {
-                        path.to_path_buf()
-                    };
-
-                    patterns.push(path.to_string_lossy().to_string());
-                }
-            }
-
-            patterns
-        };
-
-        // Process all include patterns and deduplicate results
-        let mut discovered_files = HashSet::new();
-        for pattern in include_patterns {
-            match glob(&pattern) {
-                Ok(paths) => {
-                    for entry in paths {
-                        match entry {
-                            Ok(path) => {
-                                discovered_files.insert(path);
-                            }
-                            Err(e) => error!("Glob entry error: {:?}", e),
-                        }
-                    }
-                }
-                Err(e) => error!("Glob pattern error for '{}': {:?}", pattern, e),
-            }
-        }
-
-        // Convert discovered files to URLs and add to processing queue
-        for path in discovered_files {
-            // Use url crate for proper cross-platform file URI handling
-            let url = match url::Url::from_file_path(&path) {
-                Ok(url) => url,
-                Err(_) => {
-                    error!("Failed to convert path to URL: {:?}", path);
-                    continue;
-                }
-            };
-
-            let path_buf = match url.to_file_path() {
-                Ok(path_buf) => path_buf,
-                Err(_) => {
-                    error!("Failed to convert URL back to path: {}", url);
-                    continue;
-                }
-            };
-
-            if !snapshot.forest.contains_key(&path_buf) && !seen_files.contains(&path_buf) {
+        for path_buf in &includes.files {
+            if !snapshot.forest.contains_key(path_buf) && !seen_files.contains(path_buf) {
                 total += 1;
                 to_process.push_back(path_buf.clone());
-                seen_files.insert(path_buf);
+                seen_files.insert(path_buf.clone());
             }
         }
     }
@@ -221,6 +141,192 @@ pub(crate) fn parse_initial_forest(
     Ok(true)
 }
 
+/// File extensions picked up when searching directories.
+pub(crate) const JOURNAL_EXTENSIONS: &[&str] = &["beancount", "bean"];
+
+/// How many directory levels below the workspace root are searched when guessing
+/// the journal file.
+const DETECT_MAX_DEPTH: usize = 3;
+
+/// Guesses the root journal file below `root_dir` for when `journal_file` is not
+/// configured.
+///
+/// A file that includes others without being included itself is preferred, then
+/// one declaring `option "title"`. Ties go to the file closest to `root_dir`. A
+/// workspace with a single journal file uses that file.
+pub(crate) fn detect_journal_file(root_dir: &Path) -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    collect_journal_candidates(root_dir, DETECT_MAX_DEPTH, &mut candidates);
+    if candidates.len() == 1 {
+        return candidates.pop();
+    }
+
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_beancount::language())
+        .ok()?;
+
+    let mut included = HashSet::new();
+    let mut scored = Vec::new();
+    for file in candidates {
+        let Ok(text) = fs::read_to_string(&file) else {
+            continue;
+        };
+        let Some(tree) = parser.parse(&text, None) else {
+            continue;
+        };
+        let includes = included_files(&tree, &text, &file);
+        let has_title = text.lines().any(|line| {
+            let line = line.trim_start();
+            line.starts_with("option") && line.contains("\"title\"")
+        });
+        included.extend(includes.iter().cloned());
+        scored.push((file, !includes.is_empty(), has_title));
+    }
+
+    scored
+        .into_iter()
+        .map(|(file, includes_others, has_title)| {
+            let is_root = includes_others && !included.contains(&file);
+            ((is_root, has_title), file)
+        })
+        .filter(|((is_root, has_title), _)| *is_root || *has_title)
+        .max_by(|(a_score, a), (b_score, b)| {
+            a_score
+                .cmp(b_score)
+                .then_with(|| b.components().count().cmp(&a.components().count()))
+                .then_with(|| b.cmp(a))
+        })
+        .map(|(_, file)| file)
+}
+
+fn collect_journal_candidates(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            if depth > 0 {
+                collect_journal_candidates(&path, depth - 1, files);
+            }
+        } else if path
+            .extension()
+            .is_some_and(|ext| JOURNAL_EXTENSIONS.iter().any(|e| ext == *e))
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Returns the files included by `file`, with relative paths resolved against its
+/// directory and glob patterns expanded.
+pub(crate) fn included_files(tree: &tree_sitter::Tree, text: &str, file: &Path) -> Vec<PathBuf> {
+    file_includes(tree, text, file).files
+}
+
+/// Resolves an include pattern relative to the directory of the including `file`.
+pub(crate) fn resolve_include_pattern(filename: &str, file: &Path) -> PathBuf {
+    let path = path::Path::new(filename);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else if file.is_absolute() {
+        match file.parent() {
+            Some(parent) => parent.join(path),
+            None => {
+                tracing::warn!("File has no parent directory: {:?}", file);
+                path.to_path_buf()
+            }
+        }
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Returns the include patterns declared in `file` together with the files they
+/// currently resolve to.
+pub(crate) fn file_includes(tree: &tree_sitter::Tree, text: &str, file: &Path) -> FileIncludes {
+    let bytes = text.as_bytes();
+
+    let include_query = crate::queries::get_include_query();
+    let mut cursor_qry = tree_sitter::QueryCursor::new();
+    let mut include_matches = cursor_qry.matches(include_query, tree.root_node(), bytes);
+
+    let include_patterns: Vec<String> = {
+        use tree_sitter::StreamingIterator;
+        let mut patterns = Vec::new();
+
+        while let Some(qmatch) = include_matches.next() {
+            for capture in qmatch.captures {
+                let filename = match capture.node.utf8_text(bytes) {
+                    Ok(text) => text.trim_start_matches('"').trim_end_matches('"'),
+                    Err(e) => {
+                        tracing::warn!("Failed to extract include filename: {}", e);
+                        continue;
+                    }
+                };
+
+                let path = resolve_include_pattern(filename, file);
+                patterns.push(path.to_string_lossy().to_string());
+            }
+        }
+
+        patterns
+    };
+
+    // Process all include patterns and deduplicate results
+    let mut discovered_files = HashSet::new();
+    for pattern in &include_patterns {
+        match glob(pattern) {
+            Ok(paths) => {
+                for entry in paths {
+                    match entry {
+                        Ok(path) => {
+                            discovered_files.insert(path);
+                        }
+                        Err(e) => error!("Glob entry error: {:?}", e),
+                    }
+                }
+            }
+            Err(e) => error!("Glob pattern error for '{}': {:?}", pattern, e),
+        }
+    }
+
+    // Normalize discovered files through file URLs
+    let mut files = Vec::new();
+    for path in discovered_files {
+        // Use url crate for proper cross-platform file URI handling
+        let url = match url::Url::from_file_path(&path) {
+            Ok(url) => url,
+            Err(_) => {
+                error!("Failed to convert path to URL: {:?}", path);
+                continue;
+            }
+        };
+
+        let path_buf = match url.to_file_path() {
+            Ok(path_buf) => path_buf,
+            Err(_) => {
+                error!("Failed to convert URL back to path: {}", url);
+                continue;
+            }
+        };
+
+        files.push(path_buf);
+    }
+
+    FileIncludes {
+        patterns: include_patterns,
+        files,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -231,13 +337,7 @@ mod tests {
 
     /// Helper to create a test snapshot
     fn create_test_snapshot() -> LspServerStateSnapshot {
-        LspServerStateSnapshot {
-            beancount_data: HashMap::new(),
-            config: Config::new(PathBuf::from("/tmp/test.bean")),
-            forest: HashMap::new(),
-            open_docs: HashMap::new(),
-            checker: None,
-        }
+        LspServerStateSnapshot::for_test(Config::new(PathBuf::from("/tmp/test.bean")), &[])
     }
 
     /// Helper to create a temporary beancount file
@@ -369,7 +469,7 @@ mod tests {
         let mut parsed_files = HashSet::new();
         while let Ok(task) = receiver.try_recv() {
             if let Task::Progress(ProgressMsg::ForestInit { data, .. }) = task
-                && let Some((path, _, _)) = *data
+                && let Some((path, ..)) = *data
             {
                 parsed_files.insert(path);
             }
@@ -417,7 +517,7 @@ mod tests {
         let mut parsed_files = HashSet::new();
         while let Ok(task) = receiver.try_recv() {
             if let Task::Progress(ProgressMsg::ForestInit { data, .. }) = task
-                && let Some((path, _, _)) = *data
+                && let Some((path, ..)) = *data
             {
                 parsed_files.insert(path);
             }
@@ -457,7 +557,7 @@ mod tests {
         let mut parsed_files = HashSet::new();
         while let Ok(task) = receiver.try_recv() {
             if let Task::Progress(ProgressMsg::ForestInit { data, .. }) = task
-                && let Some((path, _, _)) = *data
+                && let Some((path, ..)) = *data
             {
                 parsed_files.insert(path);
             }
@@ -532,7 +632,7 @@ include "{}"
         let mut parsed_files = HashSet::new();
         while let Ok(task) = receiver.try_recv() {
             if let Task::Progress(ProgressMsg::ForestInit { data, .. }) = task
-                && let Some((path, _, _)) = *data
+                && let Some((path, ..)) = *data
             {
                 parsed_files.insert(path);
             }
@@ -589,7 +689,7 @@ include "{}"
         let mut parsed_files = HashSet::new();
         while let Ok(task) = receiver.try_recv() {
             if let Task::Progress(ProgressMsg::ForestInit { data, .. }) = task
-                && let Some((path, _, _)) = *data
+                && let Some((path, ..)) = *data
             {
                 parsed_files.insert(path);
             }
@@ -713,7 +813,7 @@ include "{}"
         let mut parsed_files = HashSet::new();
         while let Ok(task) = receiver.try_recv() {
             if let Task::Progress(ProgressMsg::ForestInit { data, .. }) = task
-                && let Some((path, _, _)) = *data
+                && let Some((path, ..)) = *data
             {
                 parsed_files.insert(path);
             }
@@ -722,6 +822,64 @@ include "{}"
         assert_eq!(parsed_files.len(), 2);
     }
 
+    #[test]
+    fn test_detect_journal_file_prefers_including_root() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("txns")).unwrap();
+        create_temp_file(
+            &temp_dir,
+            "txns/2024.beancount",
+            "2024-01-01 open Assets:A\n",
+        );
+        create_temp_file(
+            &temp_dir,
+            "ledger.beancount",
+            "include \"txns/*.beancount\"\n",
+        );
+        create_temp_file(
+            &temp_dir,
+            "prices.beancount",
+            "2024-01-01 price EUR 1.1 USD\n",
+        );
+
+        assert_eq!(
+            detect_journal_file(temp_dir.path()),
+            Some(temp_dir.path().join("ledger.beancount"))
+        );
+    }
+
+    #[test]
+    fn test_detect_journal_file_by_title_option() {
+        let temp_dir = TempDir::new().unwrap();
+        create_temp_file(&temp_dir, "a.bean", "2024-01-01 open Assets:A\n");
+        create_temp_file(&temp_dir, "b.bean", "option \"title\" \"Books\"\n");
+
+        assert_eq!(
+            detect_journal_file(temp_dir.path()),
+            Some(temp_dir.path().join("b.bean"))
+        );
+    }
+
+    #[test]
+    fn test_detect_journal_file_ambiguous() {
+        let temp_dir = TempDir::new().unwrap();
+        create_temp_file(&temp_dir, "a.bean", "2024-01-01 open Assets:A\n");
+        create_temp_file(&temp_dir, "b.bean", "2024-01-01 open Assets:B\n");
+
+        assert_eq!(detect_journal_file(temp_dir.path()), None);
+    }
+
+    #[test]
+    fn test_detect_journal_file_single_file() {
+        let temp_dir = TempDir::new().unwrap();
+        create_temp_file(&temp_dir, "only.beancount", "");
+
+        assert_eq!(
+            detect_journal_file(temp_dir.path()),
+            Some(temp_dir.path().join("only.beancount"))
+        );
+    }
+
     #[test]
     fn test_file_cache_preserves_across_multiple_reads() {
         let temp_dir = TempDir::new().unwrap();
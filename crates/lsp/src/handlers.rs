@@ -1,7 +1,8 @@
 pub mod workspace {
     use crate::providers::text_document;
-    use crate::server::LspServerState;
-    use anyhow::Result;
+    use crate::server::{LspServerState, LspServerStateSnapshot};
+    use crate::utils::{ToFilePath, is_virtual_document};
+    use anyhow::{Context, Result};
 
     /// handler for `workspace/didChangeWatchedFiles`.
     pub(crate) fn did_change_watched_files(
@@ -11,11 +12,343 @@ pub mod workspace {
         tracing::trace!("Watched files changed: {} changes", params.changes.len());
         text_document::did_change_watched_files(state, params)
     }
+
+    /// handler for `workspace/didCreateFiles`.
+    pub(crate) fn did_create_files(
+        state: &mut LspServerState,
+        params: lsp_types::CreateFilesParams,
+    ) -> Result<()> {
+        tracing::trace!("Files created: {}", params.files.len());
+        crate::providers::file_operations::did_create_files(state, params)
+    }
+
+    /// handler for `workspace/didChangeConfiguration`.
+    pub(crate) fn did_change_configuration(
+        state: &mut LspServerState,
+        params: lsp_types::DidChangeConfigurationParams,
+    ) -> Result<()> {
+        tracing::trace!("Configuration changed");
+        state.reload_config(params.settings);
+        Ok(())
+    }
+
+    /// handler for `workspace/didChangeWorkspaceFolders`.
+    pub(crate) fn did_change_workspace_folders(
+        state: &mut LspServerState,
+        params: lsp_types::DidChangeWorkspaceFoldersParams,
+    ) -> Result<()> {
+        tracing::trace!("Workspace folders changed");
+        let paths = |folders: Vec<lsp_types::WorkspaceFolder>| {
+            folders
+                .into_iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .filter(|path| !is_virtual_document(path))
+                .collect()
+        };
+        state.change_workspace_folders(paths(params.event.added), paths(params.event.removed));
+        Ok(())
+    }
+
+    /// handler for `beancount/status`.
+    pub(crate) fn status(
+        state: &mut LspServerState,
+        _params: Option<serde_json::Value>,
+    ) -> Result<crate::lsp_ext::StatusResult> {
+        tracing::trace!("Status requested");
+        crate::providers::status::status(state)
+    }
+
+    /// handler for `beancount/monthlyTotals`.
+    pub(crate) fn monthly_totals(
+        snapshot: LspServerStateSnapshot,
+        params: crate::lsp_ext::MonthlyTotalsParams,
+    ) -> Result<crate::lsp_ext::MonthlyTotalsResult> {
+        tracing::trace!("Monthly totals requested");
+        let journal = match params.text_document {
+            Some(document) => {
+                let file = document
+                    .uri
+                    .to_file_path()
+                    .map_err(|()| anyhow::anyhow!("not a file URI: {}", document.uri.as_str()))?;
+                snapshot.journal_for(&file)
+            }
+            None => snapshot.config.resolved_journal_root(),
+        };
+        let options = match &journal {
+            Some(journal) => snapshot.ledger_options_for(journal),
+            None => snapshot.ledger_options(),
+        };
+        Ok(crate::providers::monthly_totals::monthly_totals(
+            &snapshot.journal_files(journal.as_deref()),
+            &options,
+        ))
+    }
+
+    /// Runs before `workspace/executeCommand` on the main thread, for commands that
+    /// need the document indexed or send requests to the client.
+    pub(crate) fn prepare_command(
+        state: &mut LspServerState,
+        params: &lsp_types::ExecuteCommandParams,
+    ) {
+        let command = params.command.as_str();
+        if command != crate::lsp_ext::FAVA_LINK_COMMAND
+            && command != crate::lsp_ext::OPEN_FAVA_COMMAND
+        {
+            return;
+        }
+        let position = fava_position(params);
+        if let Some(position) = &position {
+            state.ensure_beancount_data_for_position(position);
+        }
+        if command != crate::lsp_ext::OPEN_FAVA_COMMAND {
+            return;
+        }
+
+        let url = crate::providers::fava::open_fava_url(&state.snapshot(), position.as_ref());
+        let Ok(url) = url else {
+            return;
+        };
+        // The command's result is the URL as well, but not every client shows it
+        if !state.show_document_support {
+            state.send_notification::<lsp_types::notification::ShowMessage>(
+                lsp_types::ShowMessageParams {
+                    typ: lsp_types::MessageType::INFO,
+                    message: format!("Fava: {url}"),
+                },
+            );
+            return;
+        }
+        let Ok(uri) = url.parse::<lsp_types::Uri>() else {
+            return;
+        };
+        state.send_request::<lsp_types::request::ShowDocument>(
+            lsp_types::ShowDocumentParams {
+                uri,
+                external: Some(true),
+                take_focus: None,
+                selection: None,
+            },
+            |_state, response| {
+                if let Some(error) = response.error {
+                    tracing::warn!("Failed to open Fava: {}", error.message);
+                }
+            },
+        );
+    }
+
+    /// The journal of the document `argument` is the URI of, or the configured one.
+    fn journal_argument(
+        snapshot: &LspServerStateSnapshot,
+        argument: Option<&serde_json::Value>,
+    ) -> Result<Option<std::path::PathBuf>> {
+        match argument {
+            Some(uri) => {
+                let uri: lsp_types::Uri = serde_json::from_value(uri.clone())?;
+                let file = uri
+                    .to_file_path()
+                    .map_err(|()| anyhow::anyhow!("not a file URI: {}", uri.as_str()))?;
+                Ok(snapshot.journal_for(&file))
+            }
+            None => Ok(snapshot.config.resolved_journal_root()),
+        }
+    }
+
+    fn fava_position(
+        params: &lsp_types::ExecuteCommandParams,
+    ) -> Option<lsp_types::TextDocumentPositionParams> {
+        params
+            .arguments
+            .first()
+            .and_then(|argument| serde_json::from_value(argument.clone()).ok())
+    }
+
+    /// handler for `workspace/executeCommand`.
+    pub(crate) fn execute_command(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        tracing::debug!("Execute command requested: {}", params.command);
+        match params.command.as_str() {
+            crate::lsp_ext::QUERY_COMMAND => {
+                let query = params
+                    .arguments
+                    .first()
+                    .and_then(|argument| argument.as_str())
+                    .context("beancount.query expects the query string as its argument")?;
+                let result = crate::providers::query::query(&snapshot, query)?;
+                Ok(Some(serde_json::to_value(result)?))
+            }
+            crate::lsp_ext::RUN_QUERY_COMMAND => {
+                let query = params
+                    .arguments
+                    .first()
+                    .and_then(|argument| argument.as_str())
+                    .context("beancount.runQuery expects the query string as its argument")?;
+                let journal = journal_argument(&snapshot, params.arguments.get(1))?
+                    .context("no journal file to query")?;
+                let result = crate::providers::bean_query::run_query(
+                    snapshot.config.bean_query_cmd.as_ref(),
+                    &snapshot.config.root_dir,
+                    &journal,
+                    query,
+                )?;
+                Ok(Some(serde_json::to_value(result)?))
+            }
+            crate::lsp_ext::IMPORT_TRANSACTIONS_COMMAND => {
+                let transactions: Vec<crate::lsp_ext::ImportedTransaction> = params
+                    .arguments
+                    .first()
+                    .cloned()
+                    .map(serde_json::from_value)
+                    .transpose()?
+                    .context("beancount.importTransactions expects a list of transactions")?;
+                let journal = journal_argument(&snapshot, params.arguments.get(1))?;
+                let edit = crate::providers::import::import_transactions(
+                    &snapshot,
+                    journal.as_deref(),
+                    transactions,
+                )?;
+                Ok(Some(serde_json::to_value(edit)?))
+            }
+            crate::lsp_ext::IMPORT_CSV_COMMAND => {
+                let path = |i: usize| {
+                    params
+                        .arguments
+                        .get(i)
+                        .and_then(|argument| argument.as_str())
+                        .map(std::path::PathBuf::from)
+                        .context("beancount.importCsv expects the CSV and rules file paths")
+                };
+                let (csv, rules) = (path(0)?, path(1)?);
+                let journal = journal_argument(&snapshot, params.arguments.get(2))?;
+                let edit = crate::providers::import::import_csv(
+                    &snapshot,
+                    journal.as_deref(),
+                    &csv,
+                    &rules,
+                )?;
+                Ok(Some(serde_json::to_value(edit)?))
+            }
+            crate::lsp_ext::FAVA_LINK_COMMAND => {
+                let position = fava_position(&params)
+                    .context("beancount.favaLink expects a text document position")?;
+                let url = crate::providers::fava::fava_link(&snapshot, &position)?;
+                Ok(Some(serde_json::to_value(url)?))
+            }
+            crate::lsp_ext::OPEN_FAVA_COMMAND => {
+                let url = crate::providers::fava::open_fava_url(
+                    &snapshot,
+                    fava_position(&params).as_ref(),
+                )?;
+                Ok(Some(serde_json::to_value(url)?))
+            }
+            crate::lsp_ext::UPDATE_PRICES_COMMAND => {
+                let date = match params.arguments.first() {
+                    Some(serde_json::Value::String(date)) => Some(date.as_str()),
+                    Some(serde_json::Value::Null) | None => None,
+                    Some(_) => anyhow::bail!("beancount.updatePrices expects a date string"),
+                };
+                let journal = journal_argument(&snapshot, params.arguments.get(1))?
+                    .context("no journal file to fetch prices for")?;
+                let edit = crate::providers::prices::update_prices(&snapshot, &journal, date)?;
+                Ok(Some(serde_json::to_value(edit)?))
+            }
+            crate::lsp_ext::PAYEE_HISTORY_COMMAND => {
+                let payee = params
+                    .arguments
+                    .first()
+                    .and_then(|argument| argument.as_str())
+                    .context("beancount.payeeHistory expects the payee as its argument")?;
+                let journal = journal_argument(&snapshot, params.arguments.get(1))?;
+                let history = crate::providers::code_lens::payee_history(
+                    &snapshot,
+                    payee,
+                    journal.as_deref(),
+                )?;
+                Ok(Some(serde_json::to_value(history)?))
+            }
+            crate::lsp_ext::INSERT_TRANSACTION_COMMAND => {
+                let date = params
+                    .arguments
+                    .first()
+                    .and_then(|argument| argument.as_str())
+                    .context("beancount.insertTransaction expects the date as its argument")?;
+                let file = match params.arguments.get(1) {
+                    Some(uri) => {
+                        let uri: lsp_types::Uri = serde_json::from_value(uri.clone())?;
+                        uri.to_file_path()
+                            .map_err(|()| anyhow::anyhow!("not a file URI: {}", uri.as_str()))?
+                    }
+                    None => snapshot
+                        .config
+                        .resolved_journal_root()
+                        .context("no journal file to add the transaction to")?,
+                };
+                let result = crate::providers::import::insert_transaction(&snapshot, &file, date)?;
+                Ok(Some(serde_json::to_value(result)?))
+            }
+            command => anyhow::bail!("unknown command `{command}`"),
+        }
+    }
+
+    /// handler for `$/setTrace`.
+    pub(crate) fn set_trace(
+        _state: &mut LspServerState,
+        params: lsp_types::SetTraceParams,
+    ) -> Result<()> {
+        tracing::trace!("Trace set to {:?}", params.value);
+        crate::logging::set_trace(params.value);
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::config::Config;
+        use std::path::PathBuf;
+
+        #[test]
+        fn test_open_fava_shows_url_without_show_document_support() {
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let mut config = Config::new(PathBuf::from("/test"));
+            config.fava_url = Some("http://localhost:5000/ledger".to_string());
+            let mut state = LspServerState::new(sender, config);
+            let params = lsp_types::ExecuteCommandParams {
+                command: crate::lsp_ext::OPEN_FAVA_COMMAND.to_string(),
+                arguments: vec![],
+                work_done_progress_params: Default::default(),
+            };
+
+            prepare_command(&mut state, &params);
+            match receiver.try_recv().unwrap() {
+                lsp_server::Message::Notification(notification) => {
+                    assert_eq!(notification.method, "window/showMessage");
+                    assert_eq!(
+                        notification.params["message"],
+                        "Fava: http://localhost:5000/ledger/"
+                    );
+                }
+                message => panic!("unexpected message: {message:?}"),
+            }
+
+            state.show_document_support = true;
+            prepare_command(&mut state, &params);
+            match receiver.try_recv().unwrap() {
+                lsp_server::Message::Request(request) => {
+                    assert_eq!(request.method, "window/showDocument");
+                }
+                message => panic!("unexpected message: {message:?}"),
+            }
+        }
+    }
 }
 
 pub mod text_document {
+    use crate::providers::code_action;
+    use crate::providers::code_lens;
     use crate::providers::completion;
     use crate::providers::definition;
+    use crate::providers::document_highlight;
     use crate::providers::document_symbol;
     use crate::providers::folding_range;
     use crate::providers::formatting;
@@ -167,6 +500,19 @@ pub mod text_document {
             }
         }
     }
+
+    pub(crate) fn will_save_wait_until(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::WillSaveTextDocumentParams,
+    ) -> Result<Option<Vec<lsp_types::TextEdit>>> {
+        tracing::trace!(
+            "Will save {} ({:?})",
+            params.text_document.uri.as_str(),
+            params.reason
+        );
+        formatting::will_save_wait_until(snapshot, params)
+    }
+
     pub(crate) fn hover(
         snapshot: LspServerStateSnapshot,
         params: lsp_types::HoverParams,
@@ -223,6 +569,23 @@ pub mod text_document {
         }
     }
 
+    pub(crate) fn handle_implementation(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::request::GotoImplementationParams,
+    ) -> Result<Option<lsp_types::request::GotoImplementationResponse>> {
+        tracing::trace!(
+            "Implementation requested for: {} at {}:{}",
+            params
+                .text_document_position_params
+                .text_document
+                .uri
+                .as_str(),
+            params.text_document_position_params.position.line,
+            params.text_document_position_params.position.character
+        );
+        definition::implementation(snapshot, params)
+    }
+
     pub(crate) fn handle_references(
         snapshot: LspServerStateSnapshot,
         params: lsp_types::ReferenceParams,
@@ -250,6 +613,53 @@ pub mod text_document {
         }
     }
 
+    pub(crate) fn document_highlight(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::DocumentHighlightParams,
+    ) -> Result<Option<Vec<lsp_types::DocumentHighlight>>> {
+        tracing::trace!(
+            "Document highlight requested for: {} at {}:{}",
+            params
+                .text_document_position_params
+                .text_document
+                .uri
+                .as_str(),
+            params.text_document_position_params.position.line,
+            params.text_document_position_params.position.character
+        );
+        document_highlight::document_highlight(snapshot, params)
+    }
+
+    pub(crate) fn completion_resolve(
+        snapshot: LspServerStateSnapshot,
+        item: lsp_types::CompletionItem,
+    ) -> Result<lsp_types::CompletionItem> {
+        tracing::debug!("Completion item resolve requested for: {}", item.label);
+        completion::resolve(snapshot, item)
+    }
+
+    pub(crate) fn code_action(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::CodeActionParams,
+    ) -> Result<Option<lsp_types::CodeActionResponse>> {
+        tracing::debug!(
+            "Code actions requested for: {}",
+            params.text_document.uri.as_str()
+        );
+        code_action::code_action(&snapshot, params)
+    }
+
+    pub(crate) fn code_lens(
+        snapshot: LspServerStateSnapshot,
+        params: lsp_types::CodeLensParams,
+    ) -> Result<Option<Vec<lsp_types::CodeLens>>> {
+        tracing::debug!(
+            "Code lenses requested for: {}",
+            params.text_document.uri.as_str()
+        );
+        code_lens::code_lens(snapshot, params)
+    }
+
     pub(crate) fn handle_rename(
         snapshot: LspServerStateSnapshot,
         params: lsp_types::RenameParams,
@@ -264,11 +674,10 @@ pub mod text_document {
 
         match references::rename(snapshot, params) {
             Ok(Some(workspace_edit)) => {
-                let change_count = workspace_edit
-                    .changes
-                    .as_ref()
-                    .map(|changes| changes.values().map(|edits| edits.len()).sum::<usize>())
-                    .unwrap_or(0);
+                let change_count = crate::utils::text_edits(&workspace_edit)
+                    .values()
+                    .map(|edits| edits.len())
+                    .sum::<usize>();
                 tracing::trace!("Rename will make {} text edits", change_count);
                 Ok(Some(workspace_edit))
             }
@@ -380,14 +789,9 @@ pub mod text_document {
     #[cfg(test)]
     mod tests {
         use super::*;
-        use crate::beancount_data::BeancountData;
         use crate::config::Config;
-        use crate::document::Document;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::str::FromStr;
-        use std::sync::Arc;
-        use tree_sitter_beancount::tree_sitter;
         use url::Url;
 
         struct TestState {
@@ -398,40 +802,10 @@ pub mod text_document {
         impl TestState {
             fn new(content: &str) -> anyhow::Result<Self> {
                 let path = std::env::current_dir()?.join("test.beancount");
-                let rope_content = ropey::Rope::from_str(content);
-
-                let mut parser = tree_sitter::Parser::new();
-                parser.set_language(&tree_sitter_beancount::language())?;
-                let tree = parser.parse(content, None).unwrap();
-
-                let mut forest = HashMap::new();
-                forest.insert(path.clone(), Arc::new(tree.clone()));
-
-                let mut open_docs = HashMap::new();
-                open_docs.insert(
-                    path.clone(),
-                    Document {
-                        content: rope_content.clone(),
-                        version: 0,
-                    },
-                );
-
-                let mut beancount_data = HashMap::new();
-                beancount_data.insert(
-                    path.clone(),
-                    Arc::new(BeancountData::new(&tree, &rope_content)),
-                );
-
                 let config = Config::new(path.clone());
 
                 Ok(Self {
-                    snapshot: LspServerStateSnapshot {
-                        forest,
-                        open_docs,
-                        beancount_data,
-                        config,
-                        checker: None,
-                    },
+                    snapshot: LspServerStateSnapshot::for_test(config, &[(&path, content)]),
                     path,
                 })
             }
@@ -548,8 +922,7 @@ pub mod text_document {
             assert!(result.is_ok());
             let edit = result.unwrap();
             assert!(edit.is_some());
-            let changes = edit.unwrap().changes;
-            assert!(changes.is_some());
+            assert!(!crate::utils::text_edits(&edit.unwrap()).is_empty());
         }
 
         #[test]
@@ -0,0 +1,275 @@
+//! Turns bank CSV exports into transactions, driven by a TOML rules file
+//!
+//! ```toml
+//! account = "Assets:Bank:Checking"  # the account the CSV belongs to
+//! currency = "EUR"
+//! delimiter = ";"                   # default ","
+//! skip_rows = 4                     # lines before the header row
+//! date_format = "%d.%m.%Y"          # default "%Y-%m-%d"
+//! decimal_comma = true              # amounts like "-1.234,56"
+//! default_account = "Expenses:Uncategorized"
+//!
+//! [columns]                         # header names
+//! date = "Buchungstag"
+//! amount = "Betrag"
+//! payee = "Empfänger"               # optional
+//! narration = "Verwendungszweck"    # optional
+//!
+//! [[rules]]                         # the first matching rule wins
+//! payee = "(?i)rewe|edeka"          # regexes; all that are given must match
+//! account = "Expenses:Food"
+//! set_payee = "Supermarket"         # optional
+//! ```
+//!
+//! Each row becomes a transaction with a posting of the amount to `account` and an
+//! elided posting to the rule's account, or `default_account` when no rule matches.
+
+use crate::lsp_ext::{ImportedPosting, ImportedTransaction};
+use crate::utils::parse_csv;
+use anyhow::{Context, Result, anyhow, bail};
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportRules {
+    pub account: String,
+    pub currency: String,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default)]
+    pub skip_rows: usize,
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+    #[serde(default)]
+    pub decimal_comma: bool,
+    pub flag: Option<String>,
+    pub default_account: String,
+    pub columns: Columns,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Columns {
+    pub date: String,
+    pub amount: String,
+    pub payee: Option<String>,
+    pub narration: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(default, with = "serde_regex")]
+    pub payee: Option<Regex>,
+    #[serde(default, with = "serde_regex")]
+    pub narration: Option<Regex>,
+    pub account: String,
+    pub set_payee: Option<String>,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+impl ImportRules {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&content).with_context(|| format!("invalid rules in {}", path.display()))
+    }
+
+    /// The transactions for the rows of `csv`.
+    pub fn transactions(&self, csv: &str) -> Result<Vec<ImportedTransaction>> {
+        // Numbered from 1, as shown by spreadsheets
+        let mut records = parse_csv(csv, self.delimiter)
+            .into_iter()
+            .zip(1..)
+            .skip(self.skip_rows)
+            .filter(|(record, _)| record.iter().any(|cell| !cell.trim().is_empty()));
+        let (header, _) = records.next().context("the CSV has no header row")?;
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|cell| cell.trim() == name)
+                .ok_or_else(|| anyhow!("no column `{name}` in the CSV header"))
+        };
+        let date_column = column(&self.columns.date)?;
+        let amount_column = column(&self.columns.amount)?;
+        let payee_column = self.columns.payee.as_deref().map(column).transpose()?;
+        let narration_column = self.columns.narration.as_deref().map(column).transpose()?;
+
+        records
+            .map(|(record, row)| {
+                let cell = |column: usize| record.get(column).map_or("", |cell| cell.trim());
+                let date = chrono::NaiveDate::parse_from_str(cell(date_column), &self.date_format)
+                    .with_context(|| format!("row {row}: invalid date `{}`", cell(date_column)))?;
+                let amount = self.parse_amount(cell(amount_column)).with_context(|| {
+                    format!("row {row}: invalid amount `{}`", cell(amount_column))
+                })?;
+                let payee = payee_column.map(cell).filter(|payee| !payee.is_empty());
+                let narration = narration_column.map_or("", cell);
+                Ok(self.transaction(date, amount, payee, narration))
+            })
+            .collect()
+    }
+
+    fn transaction(
+        &self,
+        date: chrono::NaiveDate,
+        amount: Decimal,
+        payee: Option<&str>,
+        narration: &str,
+    ) -> ImportedTransaction {
+        let rule = self.rules.iter().find(|rule| {
+            let matches = |pattern: &Option<Regex>, value: &str| {
+                pattern
+                    .as_ref()
+                    .is_none_or(|pattern| pattern.is_match(value))
+            };
+            matches(&rule.payee, payee.unwrap_or("")) && matches(&rule.narration, narration)
+        });
+        let payee = rule
+            .and_then(|rule| rule.set_payee.as_deref())
+            .or(payee)
+            .map(str::to_string);
+        let counter_account = rule.map_or(&self.default_account, |rule| &rule.account);
+
+        ImportedTransaction {
+            date: date.format("%Y-%m-%d").to_string(),
+            flag: self.flag.clone(),
+            payee,
+            narration: narration.to_string(),
+            tags: vec![],
+            links: vec![],
+            metadata: Default::default(),
+            postings: vec![
+                ImportedPosting {
+                    account: self.account.clone(),
+                    amount: Some(format!("{amount} {}", self.currency)),
+                },
+                ImportedPosting {
+                    account: counter_account.clone(),
+                    amount: None,
+                },
+            ],
+        }
+    }
+
+    /// Parses `1,234.56`, or `1.234,56` with `decimal_comma`.
+    fn parse_amount(&self, text: &str) -> Result<Decimal> {
+        let (thousands, decimal) = if self.decimal_comma {
+            ('.', ',')
+        } else {
+            (',', '.')
+        };
+        let normalized: String = text
+            .chars()
+            .filter(|c| *c != thousands && !c.is_whitespace())
+            .map(|c| if c == decimal { '.' } else { c })
+            .collect();
+        if normalized.is_empty() {
+            bail!("empty amount");
+        }
+        Ok(Decimal::from_str(&normalized)?)
+    }
+}
+
+/// Deserializes optional regexes from strings.
+mod serde_regex {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer};
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Regex>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|pattern| Regex::new(&pattern).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+account = "Assets:Bank"
+currency = "EUR"
+delimiter = ";"
+skip_rows = 1
+date_format = "%d.%m.%Y"
+decimal_comma = true
+default_account = "Expenses:Uncategorized"
+
+[columns]
+date = "Datum"
+amount = "Betrag"
+payee = "Empfänger"
+narration = "Verwendungszweck"
+
+[[rules]]
+payee = "(?i)^rewe"
+account = "Expenses:Food"
+set_payee = "Supermarket"
+
+[[rules]]
+narration = "Miete"
+account = "Expenses:Rent"
+"#;
+
+    const CSV: &str = "Kontoauszug Januar\n\
+Datum;Empfänger;Verwendungszweck;Betrag\n\
+02.01.2024;REWE Markt;Einkauf;-23,45\n\
+03.01.2024;Hausverwaltung;Miete Januar;-1.200,00\n\
+\n\
+05.01.2024;Kiosk;;-2,50\n";
+
+    #[test]
+    fn test_transactions_from_csv() {
+        let rules: ImportRules = toml::from_str(RULES).unwrap();
+        let transactions = rules.transactions(CSV).unwrap();
+        assert_eq!(transactions.len(), 3);
+
+        let accounts: Vec<_> = transactions
+            .iter()
+            .map(|t| t.postings[1].account.as_str())
+            .collect();
+        assert_eq!(
+            accounts,
+            ["Expenses:Food", "Expenses:Rent", "Expenses:Uncategorized"]
+        );
+
+        let rent = &transactions[1];
+        assert_eq!(rent.date, "2024-01-03");
+        assert_eq!(rent.payee.as_deref(), Some("Hausverwaltung"));
+        assert_eq!(rent.narration, "Miete Januar");
+        assert_eq!(rent.postings[0].account, "Assets:Bank");
+        assert_eq!(rent.postings[0].amount.as_deref(), Some("-1200.00 EUR"));
+        assert_eq!(rent.postings[1].amount, None);
+        assert_eq!(transactions[0].payee.as_deref(), Some("Supermarket"));
+    }
+
+    #[test]
+    fn test_errors_name_the_row() {
+        let rules: ImportRules = toml::from_str(RULES).unwrap();
+        let error = rules
+            .transactions("\nDatum;Empfänger;Verwendungszweck;Betrag\n2024-01-02;A;B;1\n")
+            .unwrap_err();
+        assert_eq!(error.to_string(), "row 3: invalid date `2024-01-02`");
+
+        let error = rules.transactions("\nDate;Amount\n").unwrap_err();
+        assert_eq!(error.to_string(), "no column `Datum` in the CSV header");
+
+        assert!(toml::from_str::<ImportRules>("account = 1").is_err());
+    }
+}
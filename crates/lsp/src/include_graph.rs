@@ -0,0 +1,318 @@
+//! Include graph of the journal
+//!
+//! Records, for every parsed file, the `include` patterns it declares and the
+//! files those patterns resolved to. The graph is used to keep the forest in
+//! sync when includes are added or removed:
+//! - Newly included files are parsed and added to the forest
+//! - Files that are no longer reachable from the journal file or an open
+//!   document are dropped from the forest
+//! - Files created on disk are picked up when they match an include glob
+
+use crate::beancount_data::BeancountData;
+use crate::forest;
+use crate::server::LspServerState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::debug;
+use tree_sitter_beancount::tree_sitter;
+
+/// The includes declared by a single file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FileIncludes {
+    /// Include patterns with relative paths resolved against the including file.
+    pub patterns: Vec<String>,
+    /// Files the patterns resolved to when the file was last parsed.
+    pub files: Vec<PathBuf>,
+}
+
+/// Tracks which file included which.
+#[derive(Debug, Default)]
+pub(crate) struct IncludeGraph {
+    edges: HashMap<PathBuf, FileIncludes>,
+}
+
+impl IncludeGraph {
+    /// Records the includes of `file`, replacing the previous ones.
+    pub fn insert(&mut self, file: PathBuf, includes: FileIncludes) {
+        self.edges.insert(file, includes);
+    }
+
+    pub fn remove(&mut self, file: &Path) -> Option<FileIncludes> {
+        self.edges.remove(file)
+    }
+
+    pub fn contains(&self, file: &Path) -> bool {
+        self.edges.contains_key(file)
+    }
+
+    /// Files directly included by `file`.
+    pub fn includes(&self, file: &Path) -> &[PathBuf] {
+        self.edges
+            .get(file)
+            .map(|includes| includes.files.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Files that directly include `file`.
+    pub fn included_by(&self, file: &Path) -> Vec<&Path> {
+        let mut includers: Vec<&Path> = self
+            .edges
+            .iter()
+            .filter(|(_, includes)| includes.files.iter().any(|f| f == file))
+            .map(|(includer, _)| includer.as_path())
+            .collect();
+        includers.sort();
+        includers
+    }
+
+    /// Files with an include pattern that matches `file`, whether or not it
+    /// existed when the pattern was last expanded.
+    pub fn includers_matching(&self, file: &Path) -> Vec<PathBuf> {
+        let mut includers: Vec<PathBuf> = self
+            .edges
+            .iter()
+            .filter(|(_, includes)| {
+                includes.patterns.iter().any(|pattern| {
+                    glob::Pattern::new(pattern)
+                        .map(|pattern| pattern.matches_path(file))
+                        .unwrap_or(false)
+                })
+            })
+            .map(|(includer, _)| includer.clone())
+            .collect();
+        includers.sort();
+        includers
+    }
+
+    /// All files reachable from `roots` by following includes, roots included.
+    pub fn reachable<'a>(&self, roots: impl IntoIterator<Item = &'a Path>) -> HashSet<PathBuf> {
+        let mut seen = HashSet::new();
+        let mut queue: VecDeque<PathBuf> = roots.into_iter().map(Path::to_path_buf).collect();
+        while let Some(file) = queue.pop_front() {
+            if !seen.insert(file.clone()) {
+                continue;
+            }
+            queue.extend(self.includes(&file).iter().cloned());
+        }
+        seen
+    }
+
+    /// All files with recorded includes.
+    pub fn files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.edges.keys()
+    }
+}
+
+/// Re-resolves the includes of `file` and of any file that becomes newly included
+/// through it, parsing files from disk that are not in the forest yet. Files that
+/// are no longer reachable afterwards are dropped.
+pub(crate) fn update_includes(state: &mut LspServerState, file: &Path) {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([file.to_path_buf()]);
+
+    while let Some(file) = queue.pop_front() {
+        if !seen.insert(file.clone()) {
+            continue;
+        }
+
+        let source = state
+            .open_docs
+            .get(&file)
+            .map(|doc| &doc.content)
+            .or_else(|| state.sources.get(&file));
+        let text = match source {
+            Some(text) => text.to_string(),
+            None => match fs::read_to_string(&file) {
+                Ok(text) => text,
+                Err(e) => {
+                    debug!("Failed to read included file {:?}: {}", file, e);
+                    continue;
+                }
+            },
+        };
+
+        let tree = match state.forest.get(&file) {
+            Some(tree) => tree.clone(),
+            None => {
+                let Some(tree) = parse(&text) else {
+                    debug!("Failed to parse included file {:?}", file);
+                    continue;
+                };
+                let tree = Arc::new(tree);
+                let content = ropey::Rope::from_str(&text);
+                state.insert_beancount_data(
+                    file.clone(),
+                    Arc::new(BeancountData::new(&tree, &content)),
+                );
+                state.insert_tree(file.clone(), tree.clone(), content);
+                debug!("Processed included file: {:?}", file);
+                tree
+            }
+        };
+
+        let includes = forest::file_includes(&tree, &text, &file);
+        queue.extend(
+            includes
+                .files
+                .iter()
+                .filter(|included| !state.forest.contains_key(*included))
+                .cloned(),
+        );
+        state.include_graph.insert(file, includes);
+    }
+
+    prune_unreachable(state);
+}
+
+/// Drops files from the forest that are neither open nor reachable from a
+/// journal file or an open document, and returns the dropped files.
+pub(crate) fn prune_unreachable(state: &mut LspServerState) -> Vec<PathBuf> {
+    let journal_roots = state.journal_roots();
+    let roots = state
+        .open_docs
+        .keys()
+        .map(PathBuf::as_path)
+        .chain(journal_roots.iter().map(PathBuf::as_path));
+    let reachable = state.include_graph.reachable(roots);
+
+    let stale: Vec<PathBuf> = state
+        .include_graph
+        .files()
+        .filter(|file| !reachable.contains(*file))
+        .cloned()
+        .collect();
+    for file in &stale {
+        debug!(
+            "Dropping file no longer included by the journal: {:?}",
+            file
+        );
+        state.include_graph.remove(file);
+        state.remove_tree(file);
+        state.remove_beancount_data(file);
+    }
+    stale
+}
+
+fn parse(text: &str) -> Option<tree_sitter::Tree> {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_beancount::language())
+        .ok()?;
+    parser.parse(text, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::document::Document;
+    use tempfile::TempDir;
+
+    fn open(state: &mut LspServerState, path: &Path, text: &str) {
+        state.open_docs.insert(
+            path.to_path_buf(),
+            Document {
+                content: ropey::Rope::from_str(text),
+                version: 0,
+            },
+        );
+        state
+            .forest
+            .insert(path.to_path_buf(), Arc::new(parse(text).unwrap()));
+    }
+
+    fn includes(files: &[&str]) -> FileIncludes {
+        FileIncludes {
+            patterns: files.iter().map(|f| f.to_string()).collect(),
+            files: files.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn test_included_by() {
+        let mut graph = IncludeGraph::default();
+        graph.insert("/j/main.bean".into(), includes(&["/j/a.bean", "/j/b.bean"]));
+        graph.insert("/j/a.bean".into(), includes(&["/j/b.bean"]));
+
+        assert_eq!(
+            graph.included_by(Path::new("/j/b.bean")),
+            vec![Path::new("/j/a.bean"), Path::new("/j/main.bean")]
+        );
+        assert!(graph.included_by(Path::new("/j/main.bean")).is_empty());
+    }
+
+    #[test]
+    fn test_reachable_handles_cycles() {
+        let mut graph = IncludeGraph::default();
+        graph.insert("/j/main.bean".into(), includes(&["/j/a.bean"]));
+        graph.insert("/j/a.bean".into(), includes(&["/j/main.bean"]));
+        graph.insert("/j/orphan.bean".into(), includes(&[]));
+
+        let reachable = graph.reachable([Path::new("/j/main.bean")]);
+        assert_eq!(reachable.len(), 2);
+        assert!(!reachable.contains(Path::new("/j/orphan.bean")));
+    }
+
+    #[test]
+    fn test_includers_matching_glob() {
+        let mut graph = IncludeGraph::default();
+        graph.insert(
+            "/j/main.bean".into(),
+            FileIncludes {
+                patterns: vec!["/j/txns/*.beancount".to_string()],
+                files: vec![],
+            },
+        );
+
+        assert_eq!(
+            graph.includers_matching(Path::new("/j/txns/2024.beancount")),
+            vec![PathBuf::from("/j/main.bean")]
+        );
+        assert!(
+            graph
+                .includers_matching(Path::new("/j/other/2024.beancount"))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_update_includes_keeps_forest_in_sync() {
+        let temp_dir = TempDir::new().unwrap();
+        let txns = temp_dir.path().join("txns");
+        fs::create_dir(&txns).unwrap();
+        fs::write(txns.join("a.beancount"), "2024-01-01 open Assets:A\n").unwrap();
+        let main = temp_dir.path().join("main.bean");
+
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(temp_dir.path().to_path_buf()));
+
+        open(&mut state, &main, "include \"txns/*.beancount\"\n");
+        update_includes(&mut state, &main);
+        assert!(state.forest.contains_key(&txns.join("a.beancount")));
+        assert_eq!(state.include_graph.includes(&main).len(), 1);
+
+        // A new file matching the glob is picked up through its includer
+        let created = txns.join("b.beancount");
+        fs::write(&created, "2024-01-01 open Assets:B\n").unwrap();
+        assert_eq!(
+            state.include_graph.includers_matching(&created),
+            vec![main.clone()]
+        );
+        update_includes(&mut state, &main);
+        assert!(state.forest.contains_key(&created));
+        // Closed files keep the text their tree was parsed from
+        assert_eq!(
+            state.sources[&created].to_string(),
+            "2024-01-01 open Assets:B\n"
+        );
+
+        // Removing the include drops the files that were only reachable through it
+        open(&mut state, &main, "2024-01-01 open Assets:Cash\n");
+        update_includes(&mut state, &main);
+        assert_eq!(state.forest.len(), 1);
+        assert!(!state.sources.contains_key(&created));
+        assert!(!state.include_graph.contains(&created));
+    }
+}
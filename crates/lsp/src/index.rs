@@ -0,0 +1,221 @@
+//! Workspace symbol index
+//!
+//! Aggregates the symbols of every file in the forest (accounts, payees,
+//! narrations, tags, links and commodities) together with their occurrence
+//! counts and the locations where accounts are opened.
+//!
+//! The index is updated per file from its [`BeancountData`] whenever that file
+//! is (re-)extracted, so providers can answer requests without running
+//! tree-sitter queries over the whole forest.
+//!
+//! Symbol names are interned: an account used in thousands of postings across
+//! many files is allocated once, and cloning the index for a snapshot only
+//! bumps reference counts.
+
+use crate::beancount_data::BeancountData;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The kinds of symbols tracked by the index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SymbolKind {
+    Account,
+    Payee,
+    Narration,
+    Tag,
+    Link,
+    Commodity,
+}
+
+/// Hands out one shared allocation per distinct name, counting the uses of each.
+///
+/// The count is kept explicitly rather than read from the `Arc`, since snapshots of
+/// the index hold on to names the current index no longer uses.
+#[derive(Clone, Debug, Default)]
+struct Interner(HashMap<Arc<str>, usize>);
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Arc<str> {
+        let interned = match self.0.get_key_value(name) {
+            Some((interned, _)) => interned.clone(),
+            None => Arc::from(name),
+        };
+        *self.0.entry(interned.clone()).or_insert(0) += 1;
+        interned
+    }
+
+    /// Forgets `name` once every use of it has been released.
+    fn release(&mut self, name: &str) {
+        if let Some(uses) = self.0.get_mut(name) {
+            *uses = uses.saturating_sub(1);
+            if *uses == 0 {
+                self.0.remove(name);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WorkspaceIndex {
+    /// The data each file contributed, used to retract it on update.
+    files: HashMap<PathBuf, Arc<BeancountData>>,
+    /// Occurrence counts per symbol, sorted by name.
+    symbols: HashMap<SymbolKind, BTreeMap<Arc<str>, usize>>,
+    /// Open directives per account.
+    account_definitions: HashMap<Arc<str>, Vec<(PathBuf, lsp_types::Range)>>,
+    names: Interner,
+}
+
+impl WorkspaceIndex {
+    /// Replaces the symbols contributed by `path` with those from `data`.
+    pub fn update_file(&mut self, path: PathBuf, data: Arc<BeancountData>) {
+        self.remove_file(&path);
+
+        for (kind, name, count) in data.get_symbol_counts().iter() {
+            let name = self.names.intern(name);
+            *self
+                .symbols
+                .entry(*kind)
+                .or_default()
+                .entry(name)
+                .or_insert(0) += count;
+        }
+        for (account, range) in data.get_account_definitions().iter() {
+            let account = self.names.intern(account);
+            self.account_definitions
+                .entry(account)
+                .or_default()
+                .push((path.clone(), *range));
+        }
+
+        self.files.insert(path, data);
+    }
+
+    /// Retracts all symbols contributed by `path`.
+    pub fn remove_file(&mut self, path: &Path) {
+        let Some(data) = self.files.remove(path) else {
+            return;
+        };
+
+        for (kind, name, count) in data.get_symbol_counts().iter() {
+            let Some(symbols) = self.symbols.get_mut(kind) else {
+                continue;
+            };
+            if let Some(total) = symbols.get_mut(name.as_str()) {
+                *total = total.saturating_sub(*count);
+                if *total == 0 {
+                    symbols.remove(name.as_str());
+                }
+            }
+            self.names.release(name);
+        }
+        for (account, _) in data.get_account_definitions().iter() {
+            if let Some(definitions) = self.account_definitions.get_mut(account.as_str()) {
+                definitions.retain(|(file, _)| file != path);
+                if definitions.is_empty() {
+                    self.account_definitions.remove(account.as_str());
+                }
+            }
+            self.names.release(account);
+        }
+    }
+
+    /// Symbols of `kind` with their workspace-wide occurrence count, sorted by name.
+    pub fn symbols(&self, kind: SymbolKind) -> impl Iterator<Item = (&str, usize)> {
+        self.symbols
+            .get(&kind)
+            .into_iter()
+            .flat_map(|symbols| symbols.iter().map(|(name, count)| (&**name, *count)))
+    }
+
+    /// Files and ranges of the open directives for `account`.
+    pub fn account_definitions(&self, account: &str) -> &[(PathBuf, lsp_types::Range)] {
+        self.account_definitions
+            .get(account)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(text: &str) -> Arc<BeancountData> {
+        let mut parser = tree_sitter_beancount::tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        Arc::new(BeancountData::new(&tree, &ropey::Rope::from_str(text)))
+    }
+
+    #[test]
+    fn test_counts_aggregate_across_files() {
+        let mut index = WorkspaceIndex::default();
+        index.update_file(
+            "a.bean".into(),
+            data("2024-01-01 * \"Shop\" \"Food\" #trip\n  Assets:Cash  -1 EUR\n"),
+        );
+        index.update_file("b.bean".into(), data("2024-01-02 * \"Shop\" \"Food\"\n"));
+
+        let payees: Vec<_> = index.symbols(SymbolKind::Payee).collect();
+        assert_eq!(payees, vec![("\"Shop\"", 2)]);
+        let tags: Vec<_> = index.symbols(SymbolKind::Tag).collect();
+        assert_eq!(tags, vec![("#trip", 1)]);
+    }
+
+    #[test]
+    fn test_update_file_replaces_previous_contribution() {
+        let mut index = WorkspaceIndex::default();
+        index.update_file("a.bean".into(), data("2024-01-01 open Assets:Cash\n"));
+        index.update_file("b.bean".into(), data("2024-01-01 open Assets:Cash\n"));
+        assert_eq!(index.account_definitions("Assets:Cash").len(), 2);
+
+        index.update_file("a.bean".into(), data("2024-01-01 open Assets:Bank\n"));
+        let accounts: Vec<_> = index.symbols(SymbolKind::Account).collect();
+        assert_eq!(accounts, vec![("Assets:Bank", 1), ("Assets:Cash", 1)]);
+        assert_eq!(
+            index.account_definitions("Assets:Cash"),
+            &[(
+                PathBuf::from("b.bean"),
+                lsp_types::Range::new(
+                    lsp_types::Position::new(0, 16),
+                    lsp_types::Position::new(0, 27)
+                )
+            )]
+        );
+
+        index.remove_file(Path::new("b.bean"));
+        assert!(index.account_definitions("Assets:Cash").is_empty());
+        assert_eq!(index.symbols(SymbolKind::Account).count(), 1);
+    }
+
+    #[test]
+    fn test_names_are_shared_and_released() {
+        let mut index = WorkspaceIndex::default();
+        index.update_file("a.bean".into(), data("2024-01-01 open Assets:Cash\n"));
+        index.update_file("b.bean".into(), data("2024-01-01 open Assets:Cash\n"));
+
+        let symbol = index.symbols[&SymbolKind::Account]
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        let (definition, _) = index
+            .account_definitions
+            .get_key_value("Assets:Cash")
+            .unwrap();
+        assert!(Arc::ptr_eq(&symbol, definition));
+
+        // Snapshots still holding a name do not keep it in the index
+        let snapshot = index.clone();
+        index.remove_file(Path::new("a.bean"));
+        assert!(index.names.0.contains_key("Assets:Cash"));
+        index.remove_file(Path::new("b.bean"));
+        assert!(index.names.0.is_empty());
+        assert_eq!(snapshot.symbols(SymbolKind::Account).count(), 1);
+        drop(symbol);
+    }
+}
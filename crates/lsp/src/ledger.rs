@@ -0,0 +1,1203 @@
+//! Ledger evaluation
+//!
+//! Replays the transactions, pads and balance assertions of every indexed file in
+//! date order and keeps per-account, per-commodity running balances, booking them
+//! the way beancount does:
+//! - a posting without an amount receives what is left to balance the other
+//!   postings, one amount per currency
+//! - a `pad` inserts the amount that makes the next balance assertion of its
+//!   account pass, taken from the pad's source account, on the pad's date
+//! - balance assertions are checked at the start of their day, before the
+//!   transactions of that date
+//!
+//! `price` directives are collected alongside into a per-commodity price history, and
+//! transactions are summarized per tag and link, including tags set by `pushtag`.
+//!
+//! The directives are extracted per file into [`LedgerEntry`] values together with
+//! the file's [`BeancountData`], so evaluating the ledger needs no syntax trees.
+
+use crate::beancount_data::BeancountData;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tree_sitter_beancount::tree_sitter;
+
+/// Balances of one account by currency.
+pub type Inventory = BTreeMap<String, Decimal>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Amount {
+    pub value: Decimal,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Price {
+    pub amount: Amount,
+    pub is_total: bool, // true for @@, false for @
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cost {
+    pub amount: Amount,
+    pub is_total: bool, // true for {{total}}, false for {unit}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostingAmount {
+    pub amount: Amount,
+    pub price: Option<Price>,
+    pub cost: Option<Cost>,
+}
+
+impl PostingAmount {
+    /// Convert this posting amount to a specific currency if price or cost is specified.
+    ///
+    /// Returns `None` without a price or cost, or when the conversion overflows.
+    pub fn convert_to_currency(&self) -> Option<(Decimal, String)> {
+        // Cost basis takes precedence over price for balance calculations
+        if let Some(cost) = &self.cost {
+            // Cost is specified, convert to the cost currency
+            let converted_value = if cost.is_total {
+                // {{total}} means total cost, use the cost amount directly
+                cost.amount.value
+            } else {
+                // {unit} means unit cost, multiply by quantity
+                self.amount.value.checked_mul(cost.amount.value)?
+            };
+            Some((converted_value, cost.amount.currency.clone()))
+        } else if let Some(price) = &self.price {
+            // Price is specified, convert to the price currency
+            let converted_value = if price.is_total {
+                // @@ means total cost, use the price amount directly
+                price.amount.value
+            } else {
+                // @ means unit price, multiply by quantity
+                self.amount.value.checked_mul(price.amount.value)?
+            };
+            Some((converted_value, price.amount.currency.clone()))
+        } else {
+            // No price or cost, return original
+            None
+        }
+    }
+
+    /// The amount this posting contributes to the transaction's balance, or `None` if
+    /// converting it at its price or cost overflows.
+    pub fn weight(&self) -> Option<Amount> {
+        match self.convert_to_currency() {
+            Some((value, currency)) => Some(Amount { value, currency }),
+            None if self.cost.is_none() && self.price.is_none() => Some(self.amount.clone()),
+            None => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerPosting {
+    pub account: String,
+    /// `None` for a posting whose amount is left for beancount to fill in.
+    pub amount: Option<PostingAmount>,
+    pub line: u32,
+}
+
+/// A directive that changes or asserts account balances, or records a price.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    Transaction {
+        /// Unquoted payee, if the transaction has one.
+        payee: Option<String>,
+        /// Unquoted narration, if the transaction has one.
+        narration: Option<String>,
+        /// Tags without `#`, including the ones pushed with `pushtag`.
+        tags: Vec<String>,
+        /// Links without `^`.
+        links: Vec<String>,
+        postings: Vec<LedgerPosting>,
+    },
+    Pad {
+        account: String,
+        source: String,
+    },
+    Balance {
+        account: String,
+        amount: Amount,
+    },
+    /// One unit of `currency` is worth `amount`.
+    Price {
+        currency: String,
+        amount: Amount,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    /// `YYYY-MM-DD`, so that dates sort as strings.
+    pub date: String,
+    pub line: u32,
+    pub kind: EntryKind,
+}
+
+impl LedgerEntry {
+    /// Beancount books balance assertions before anything else of the same day.
+    fn sort_order(&self) -> u8 {
+        match self.kind {
+            EntryKind::Balance { .. } => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// Extracts the transactions, pads and balance assertions of a file.
+pub(crate) fn extract_entries(tree: &tree_sitter::Tree, content: &ropey::Rope) -> Vec<LedgerEntry> {
+    let mut entries = vec![];
+    collect_entries(&tree.root_node(), content, &mut vec![], &mut entries);
+    entries
+}
+
+fn collect_entries(
+    node: &tree_sitter::Node,
+    content: &ropey::Rope,
+    pushed_tags: &mut Vec<String>,
+    out: &mut Vec<LedgerEntry>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let kind = match child.kind() {
+            // Org-mode style headlines nest directives
+            "section" => {
+                collect_entries(&child, content, pushed_tags, out);
+                continue;
+            }
+            "pushtag" | "poptag" => {
+                let mut tag_cursor = child.walk();
+                let Some(tag) = child
+                    .named_children(&mut tag_cursor)
+                    .find(|tag| tag.kind() == "tag")
+                else {
+                    continue;
+                };
+                let tag = text_for_tree_sitter_node(content, &tag)[1..].to_string();
+                if child.kind() == "pushtag" {
+                    pushed_tags.push(tag);
+                } else if let Some(i) = pushed_tags.iter().rposition(|pushed| *pushed == tag) {
+                    pushed_tags.remove(i);
+                }
+                continue;
+            }
+            "transaction" => {
+                let mut postings_cursor = child.walk();
+                let postings = child
+                    .named_children(&mut postings_cursor)
+                    .filter(|posting| posting.kind() == "posting")
+                    .filter_map(|posting| {
+                        Some(LedgerPosting {
+                            account: field_text(&posting, "account", content)?,
+                            amount: extract_amount(&posting, content),
+                            line: posting.start_position().row as u32,
+                        })
+                    })
+                    .collect();
+                let mut tags = pushed_tags.clone();
+                let mut links = vec![];
+                let mut tags_cursor = child.walk();
+                for tags_links in child
+                    .named_children(&mut tags_cursor)
+                    .filter(|node| node.kind() == "tags_links")
+                {
+                    let mut cursor = tags_links.walk();
+                    for node in tags_links.named_children(&mut cursor) {
+                        let text = text_for_tree_sitter_node(content, &node)[1..].to_string();
+                        let list = if node.kind() == "tag" {
+                            &mut tags
+                        } else {
+                            &mut links
+                        };
+                        if !list.contains(&text) {
+                            list.push(text);
+                        }
+                    }
+                }
+                EntryKind::Transaction {
+                    payee: field_text(&child, "payee", content).map(unquote),
+                    narration: field_text(&child, "narration", content).map(unquote),
+                    tags,
+                    links,
+                    postings,
+                }
+            }
+            "pad" => {
+                let (Some(account), Some(source)) = (
+                    field_text(&child, "account", content),
+                    field_text(&child, "from_account", content),
+                ) else {
+                    continue;
+                };
+                EntryKind::Pad { account, source }
+            }
+            "balance" => {
+                let (Some(account), Some(amount)) = (
+                    field_text(&child, "account", content),
+                    child
+                        .child_by_field_name("amount")
+                        .and_then(|amount| extract_simple_amount(&amount, content)),
+                ) else {
+                    continue;
+                };
+                EntryKind::Balance { account, amount }
+            }
+            "price" => {
+                let (Some(currency), Some(amount)) = (
+                    field_text(&child, "currency", content),
+                    child
+                        .child_by_field_name("amount")
+                        .and_then(|amount| extract_simple_amount(&amount, content)),
+                ) else {
+                    continue;
+                };
+                EntryKind::Price { currency, amount }
+            }
+            _ => continue,
+        };
+        let Some(date) = field_text(&child, "date", content) else {
+            continue;
+        };
+        out.push(LedgerEntry {
+            date: date.replace('/', "-"),
+            line: child.start_position().row as u32,
+            kind,
+        });
+    }
+}
+
+fn field_text(node: &tree_sitter::Node, field: &str, content: &ropey::Rope) -> Option<String> {
+    node.child_by_field_name(field)
+        .map(|child| text_for_tree_sitter_node(content, &child))
+}
+
+fn unquote(text: String) -> String {
+    text.trim().trim_matches('"').to_string()
+}
+
+/// The number and currency of an `amount` node, ignoring a balance's `~ tolerance`.
+pub(crate) fn extract_simple_amount(
+    node: &tree_sitter::Node,
+    content: &ropey::Rope,
+) -> Option<Amount> {
+    let mut cursor = node.walk();
+    let mut children = node.named_children(&mut cursor);
+    let number = children.find(|child| child.kind() != "currency")?;
+    let currency = children.find(|child| child.kind() == "currency")?;
+    let number = text_for_tree_sitter_node(content, &number);
+    Some(Amount {
+        value: evaluate_expression(&number)
+            .or_else(|| Decimal::from_str_exact(number.trim()).ok())?,
+        currency: text_for_tree_sitter_node(content, &currency),
+    })
+}
+
+/// A change to an account's balance in one currency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceChange {
+    pub date: String,
+    pub file: PathBuf,
+    pub line: u32,
+    pub amount: Amount,
+    /// The account's balance in `amount.currency` after the change.
+    pub balance: Decimal,
+}
+
+/// The outcome of a balance assertion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceCheck {
+    pub date: String,
+    pub file: PathBuf,
+    pub line: u32,
+    pub account: String,
+    pub expected: Amount,
+    /// The account's balance in the expected currency at the start of the day.
+    pub actual: Decimal,
+}
+
+impl BalanceCheck {
+    pub fn passed(&self) -> bool {
+        self.actual == self.expected.value
+    }
+}
+
+/// A `price` directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PricePoint {
+    pub date: String,
+    pub file: PathBuf,
+    pub line: u32,
+    /// The price of one unit.
+    pub amount: Amount,
+}
+
+/// The transactions carrying a tag or link.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagSummary {
+    pub transactions: usize,
+    pub first_date: String,
+    pub last_date: String,
+    /// What the transactions booked to each account.
+    pub accounts: BTreeMap<String, Inventory>,
+}
+
+impl TagSummary {
+    fn add(&mut self, date: &str, booked: &[(String, u32, Amount)]) {
+        if self.transactions == 0 {
+            self.first_date = date.to_string();
+        }
+        self.transactions += 1;
+        self.last_date = date.to_string();
+        for (account, _, amount) in booked {
+            let total = self
+                .accounts
+                .entry(account.clone())
+                .or_default()
+                .entry(amount.currency.clone())
+                .or_default();
+            if let Some(sum) = total.checked_add(amount.value) {
+                *total = sum;
+            }
+        }
+    }
+}
+
+/// The ledger of each journal, evaluated when first needed.
+///
+/// The server starts a new cache whenever the indexed data changes, so the ledgers in
+/// a cache always match the data of the snapshots sharing it.
+#[derive(Debug, Default)]
+pub struct LedgerCache {
+    ledgers: Mutex<HashMap<Option<PathBuf>, Arc<Ledger>>>,
+}
+
+impl LedgerCache {
+    /// The ledger of `journal`, evaluated by `evaluate` unless it is cached.
+    pub fn get_or_evaluate(
+        &self,
+        journal: Option<PathBuf>,
+        evaluate: impl FnOnce() -> Ledger,
+    ) -> Arc<Ledger> {
+        let mut ledgers = self.ledgers.lock().unwrap_or_else(|e| e.into_inner());
+        Arc::clone(
+            ledgers
+                .entry(journal)
+                .or_insert_with(|| Arc::new(evaluate())),
+        )
+    }
+}
+
+/// Running balances of every account, computed from the indexed directives.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    balances: BTreeMap<String, Inventory>,
+    changes: BTreeMap<String, Vec<BalanceChange>>,
+    checks: Vec<BalanceCheck>,
+    prices: BTreeMap<String, Vec<PricePoint>>,
+    tags: BTreeMap<String, TagSummary>,
+    links: BTreeMap<String, TagSummary>,
+}
+
+impl Ledger {
+    /// Evaluates the directives of all `files` in date order.
+    pub fn new(files: &HashMap<PathBuf, Arc<BeancountData>>) -> Self {
+        let per_file: Vec<(&Path, Arc<Vec<LedgerEntry>>)> = files
+            .iter()
+            .map(|(file, data)| (file.as_path(), data.get_ledger_entries()))
+            .collect();
+        let mut entries: Vec<(&Path, &LedgerEntry)> = per_file
+            .iter()
+            .flat_map(|(file, entries)| entries.iter().map(move |entry| (*file, entry)))
+            .collect();
+        entries.sort_by(|(a_file, a), (b_file, b)| {
+            (&a.date, a.sort_order(), a_file, a.line).cmp(&(
+                &b.date,
+                b.sort_order(),
+                b_file,
+                b.line,
+            ))
+        });
+
+        // A pad's amount depends on the balance assertion that follows it, so the
+        // first pass only settles the pads and the second one books everything.
+        let pads = settle_pads(&entries);
+
+        let mut ledger = Ledger::default();
+        for (index, (file, entry)) in entries.iter().enumerate() {
+            match &entry.kind {
+                EntryKind::Transaction {
+                    tags,
+                    links,
+                    postings,
+                    ..
+                } => {
+                    let booked = book_postings(postings);
+                    for tag in tags {
+                        let summary = ledger.tags.entry(tag.clone()).or_default();
+                        summary.add(&entry.date, &booked);
+                    }
+                    for link in links {
+                        let summary = ledger.links.entry(link.clone()).or_default();
+                        summary.add(&entry.date, &booked);
+                    }
+                    for (account, line, amount) in booked {
+                        ledger.post(&account, amount, &entry.date, file, line);
+                    }
+                }
+                EntryKind::Pad { account, source } => {
+                    for amount in pads.get(&index).into_iter().flatten() {
+                        let negated = Amount {
+                            value: -amount.value,
+                            currency: amount.currency.clone(),
+                        };
+                        ledger.post(account, amount.clone(), &entry.date, file, entry.line);
+                        ledger.post(source, negated, &entry.date, file, entry.line);
+                    }
+                }
+                EntryKind::Balance { account, amount } => {
+                    ledger.checks.push(BalanceCheck {
+                        date: entry.date.clone(),
+                        file: file.to_path_buf(),
+                        line: entry.line,
+                        account: account.clone(),
+                        expected: amount.clone(),
+                        actual: ledger.units(account, &amount.currency),
+                    });
+                }
+                EntryKind::Price { currency, amount } => {
+                    ledger
+                        .prices
+                        .entry(currency.clone())
+                        .or_default()
+                        .push(PricePoint {
+                            date: entry.date.clone(),
+                            file: file.to_path_buf(),
+                            line: entry.line,
+                            amount: amount.clone(),
+                        });
+                }
+            }
+        }
+        ledger
+    }
+
+    /// The final balance of `account`, without its sub-accounts.
+    pub fn balance(&self, account: &str) -> Option<&Inventory> {
+        self.balances.get(account)
+    }
+
+    /// The balance of `account` after everything booked up to and including `date`.
+    pub fn balance_at(&self, account: &str, date: &str) -> Inventory {
+        let mut inventory = Inventory::new();
+        for change in self.changes(account) {
+            if change.date.as_str() > date {
+                break;
+            }
+            inventory.insert(change.amount.currency.clone(), change.balance);
+        }
+        inventory.retain(|_, value| !value.is_zero());
+        inventory
+    }
+
+    /// The balance of `account` right after the posting at `line` of `file`, or `None`
+    /// if that posting changed nothing.
+    pub fn balance_after(&self, account: &str, file: &Path, line: u32) -> Option<Inventory> {
+        let changes = self.changes(account);
+        let last = changes
+            .iter()
+            .rposition(|change| change.file == file && change.line == line)?;
+        let mut inventory = Inventory::new();
+        for change in &changes[..=last] {
+            inventory.insert(change.amount.currency.clone(), change.balance);
+        }
+        inventory.retain(|_, value| !value.is_zero());
+        Some(inventory)
+    }
+
+    /// Every change to `account`'s balance, in booking order.
+    pub fn changes(&self, account: &str) -> &[BalanceChange] {
+        self.changes.get(account).map_or(&[], Vec::as_slice)
+    }
+
+    /// All accounts that were booked to.
+    pub fn accounts(&self) -> impl Iterator<Item = &String> {
+        self.balances.keys()
+    }
+
+    /// The prices of `currency` in date order, in any quote currency.
+    pub fn prices(&self, currency: &str) -> &[PricePoint] {
+        self.prices.get(currency).map_or(&[], Vec::as_slice)
+    }
+
+    /// The price of one `currency` in `quote` closest to `date`, with the date of that
+    /// price. Prices of `quote` in `currency` are inverted.
+    pub fn nearest_price(
+        &self,
+        currency: &str,
+        quote: &str,
+        date: &str,
+    ) -> Option<(Decimal, &str)> {
+        let direct = self
+            .prices(currency)
+            .iter()
+            .filter(|price| price.amount.currency == quote)
+            .map(|price| (price.amount.value, price.date.as_str()));
+        let inverse = self
+            .prices(quote)
+            .iter()
+            .filter(|price| price.amount.currency == currency && !price.amount.value.is_zero())
+            .map(|price| (Decimal::ONE / price.amount.value, price.date.as_str()));
+        let target = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+        direct.chain(inverse).min_by_key(|(_, price_date)| {
+            let price_date = chrono::NaiveDate::parse_from_str(price_date, "%Y-%m-%d").ok();
+            match (target, price_date) {
+                (Some(target), Some(price_date)) => (target - price_date).num_days().abs(),
+                _ => i64::MAX,
+            }
+        })
+    }
+
+    /// The transactions tagged `tag`, given without `#`.
+    pub fn tag(&self, tag: &str) -> Option<&TagSummary> {
+        self.tags.get(tag)
+    }
+
+    /// The transactions linked with `link`, given without `^`.
+    pub fn link(&self, link: &str) -> Option<&TagSummary> {
+        self.links.get(link)
+    }
+
+    /// The outcome of every balance assertion, in booking order.
+    pub fn balance_checks(&self) -> &[BalanceCheck] {
+        &self.checks
+    }
+
+    fn units(&self, account: &str, currency: &str) -> Decimal {
+        self.balances
+            .get(account)
+            .and_then(|inventory| inventory.get(currency))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn post(&mut self, account: &str, amount: Amount, date: &str, file: &Path, line: u32) {
+        let balance = {
+            let units = self
+                .balances
+                .entry(account.to_string())
+                .or_default()
+                .entry(amount.currency.clone())
+                .or_default();
+            // A balance beyond what a decimal holds is meaningless; leave the posting out
+            let Some(balance) = units.checked_add(amount.value) else {
+                return;
+            };
+            *units = balance;
+            balance
+        };
+        self.changes
+            .entry(account.to_string())
+            .or_default()
+            .push(BalanceChange {
+                date: date.to_string(),
+                file: file.to_path_buf(),
+                line,
+                amount,
+                balance,
+            });
+    }
+}
+
+/// The amounts each posting adds to its account, filling in an elided amount with
+/// what is left to balance the transaction.
+///
+/// When a weight overflows, the residual is unknown and an elided posting is left out.
+pub(crate) fn book_postings(postings: &[LedgerPosting]) -> Vec<(String, u32, Amount)> {
+    let mut booked = vec![];
+    let mut residual = Inventory::new();
+    let mut elided = None;
+    let mut overflowed = false;
+
+    for posting in postings {
+        match &posting.amount {
+            Some(amount) => {
+                match amount.weight() {
+                    Some(weight) => {
+                        let value = residual.entry(weight.currency).or_default();
+                        match value.checked_add(weight.value) {
+                            Some(sum) => *value = sum,
+                            None => overflowed = true,
+                        }
+                    }
+                    None => overflowed = true,
+                }
+                booked.push((posting.account.clone(), posting.line, amount.amount.clone()));
+            }
+            // Beancount rejects more than one; the first one gets the residual
+            None => {
+                elided.get_or_insert(posting);
+            }
+        }
+    }
+
+    if let Some(posting) = elided.filter(|_| !overflowed) {
+        for (currency, value) in residual {
+            if !value.is_zero() {
+                booked.push((
+                    posting.account.clone(),
+                    posting.line,
+                    Amount {
+                        value: -value,
+                        currency,
+                    },
+                ));
+            }
+        }
+    }
+    booked
+}
+
+/// Computes the amounts each pad inserts, keyed by the pad's index in `entries`.
+fn settle_pads(entries: &[(&Path, &LedgerEntry)]) -> HashMap<usize, Vec<Amount>> {
+    let mut balances: HashMap<String, Inventory> = HashMap::new();
+    let mut pending: HashMap<&str, (usize, &str)> = HashMap::new();
+    let mut pads: HashMap<usize, Vec<Amount>> = HashMap::new();
+
+    let add = |balances: &mut HashMap<String, Inventory>, account: &str, currency: &str, value| {
+        let total = balances
+            .entry(account.to_string())
+            .or_default()
+            .entry(currency.to_string())
+            .or_default();
+        if let Some(sum) = total.checked_add(value) {
+            *total = sum;
+        }
+    };
+
+    for (index, (_, entry)) in entries.iter().enumerate() {
+        match &entry.kind {
+            EntryKind::Transaction { postings, .. } => {
+                for (account, _, amount) in book_postings(postings) {
+                    add(&mut balances, &account, &amount.currency, amount.value);
+                }
+            }
+            EntryKind::Pad { account, source } => {
+                pending.insert(account, (index, source));
+            }
+            EntryKind::Balance { account, amount } => {
+                let Some((pad, source)) = pending.remove(account.as_str()) else {
+                    continue;
+                };
+                let current = balances
+                    .get(account)
+                    .and_then(|inventory| inventory.get(&amount.currency))
+                    .copied()
+                    .unwrap_or_default();
+                let Some(difference) = amount.value.checked_sub(current) else {
+                    continue;
+                };
+                if difference.is_zero() {
+                    continue;
+                }
+                add(&mut balances, account, &amount.currency, difference);
+                add(&mut balances, source, &amount.currency, -difference);
+                pads.entry(pad).or_default().push(Amount {
+                    value: difference,
+                    currency: amount.currency.clone(),
+                });
+            }
+            EntryKind::Price { .. } => {}
+        }
+    }
+    pads
+}
+
+/// Extract amount and optional price/cost from a posting node
+pub(crate) fn extract_amount(
+    posting_node: &tree_sitter::Node,
+    content: &ropey::Rope,
+) -> Option<PostingAmount> {
+    let mut cursor = posting_node.walk();
+    let mut amount_opt: Option<Amount> = None;
+    let mut is_price_total = false;
+    let mut price_amount_opt: Option<Amount> = None;
+    let mut cost_opt: Option<Cost> = None;
+
+    for child in posting_node.children(&mut cursor) {
+        match child.kind() {
+            "incomplete_amount" | "amount" => {
+                if amount_opt.is_none() {
+                    // First amount is the posting amount - parse it from structure
+                    amount_opt = extract_amount_from_node(&child, content);
+                }
+            }
+            "at" => {
+                // @ means unit price
+                is_price_total = false;
+            }
+            "atat" => {
+                // @@ means total cost
+                is_price_total = true;
+            }
+            "price_annotation" => {
+                // Parse the price amount from price_annotation
+                price_amount_opt = extract_price_annotation(&child, content);
+            }
+            "cost_spec" => {
+                // Parse cost basis from cost_spec
+                cost_opt = extract_cost_spec(&child, content);
+            }
+            _ => {}
+        }
+    }
+
+    amount_opt.map(|amount| PostingAmount {
+        amount,
+        price: price_amount_opt.map(|price_amt| Price {
+            amount: price_amt,
+            is_total: is_price_total,
+        }),
+        cost: cost_opt,
+    })
+}
+
+/// Extract amount from an incomplete_amount or amount node by parsing its children
+fn extract_amount_from_node(
+    amount_node: &tree_sitter::Node,
+    content: &ropey::Rope,
+) -> Option<Amount> {
+    let mut cursor = amount_node.walk();
+    let mut number_str = String::new();
+    let mut currency_str = String::new();
+
+    for child in amount_node.children(&mut cursor) {
+        match child.kind() {
+            "number" | "unary_number_expr" | "binary_number_expr" => {
+                number_str = text_for_tree_sitter_node(content, &child);
+            }
+            "currency" => {
+                currency_str = text_for_tree_sitter_node(content, &child);
+            }
+            _ => {}
+        }
+    }
+
+    if !number_str.is_empty() && !currency_str.is_empty() {
+        // Try to evaluate the expression if it's a calculation
+        let value = if number_str.contains('*')
+            || number_str.contains('+')
+            || (number_str.contains('-') && number_str.matches('-').count() > 1)
+        {
+            // For binary expressions, try to evaluate them
+            evaluate_expression(&number_str)
+                .or_else(|| Decimal::from_str_exact(&number_str).ok())?
+        } else {
+            Decimal::from_str_exact(&number_str).ok()?
+        };
+        Some(Amount {
+            value,
+            currency: currency_str,
+        })
+    } else {
+        None
+    }
+}
+
+/// Extract price amount from a price_annotation node
+fn extract_price_annotation(
+    price_annotation_node: &tree_sitter::Node,
+    content: &ropey::Rope,
+) -> Option<Amount> {
+    let mut cursor = price_annotation_node.walk();
+
+    for child in price_annotation_node.children(&mut cursor) {
+        if child.kind() == "incomplete_amount" || child.kind() == "amount" {
+            return extract_amount_from_node(&child, content);
+        }
+    }
+
+    None
+}
+
+/// Extract cost basis from a cost_spec node
+fn extract_cost_spec(cost_spec_node: &tree_sitter::Node, content: &ropey::Rope) -> Option<Cost> {
+    let mut cursor = cost_spec_node.walk();
+    let mut is_total = false;
+    let mut cost_amount: Option<Amount> = None;
+
+    // Check for {{ or {
+    for child in cost_spec_node.children(&mut cursor) {
+        match child.kind() {
+            "{{" => is_total = true,
+            "{" => is_total = false,
+            "cost_comp" => {
+                // Extract the amount from cost_comp -> compound_amount
+                cost_amount = extract_cost_comp(&child, content);
+            }
+            _ => {}
+        }
+    }
+
+    cost_amount.map(|amount| Cost { amount, is_total })
+}
+
+/// Extract amount from a cost_comp node
+fn extract_cost_comp(cost_comp_node: &tree_sitter::Node, content: &ropey::Rope) -> Option<Amount> {
+    let mut cursor = cost_comp_node.walk();
+
+    for child in cost_comp_node.children(&mut cursor) {
+        if child.kind() == "compound_amount" {
+            return extract_compound_amount(&child, content);
+        }
+    }
+
+    None
+}
+
+/// Extract amount from a compound_amount node (has number and currency children)
+fn extract_compound_amount(
+    compound_amount_node: &tree_sitter::Node,
+    content: &ropey::Rope,
+) -> Option<Amount> {
+    let mut cursor = compound_amount_node.walk();
+    let mut number_str = String::new();
+    let mut currency_str = String::new();
+
+    for child in compound_amount_node.children(&mut cursor) {
+        match child.kind() {
+            "number" | "unary_number_expr" | "binary_number_expr" => {
+                number_str = text_for_tree_sitter_node(content, &child);
+            }
+            "currency" => {
+                currency_str = text_for_tree_sitter_node(content, &child);
+            }
+            _ => {}
+        }
+    }
+
+    if !number_str.is_empty() && !currency_str.is_empty() {
+        // Try to evaluate the expression if it's a calculation
+        let value = if number_str.contains('*')
+            || number_str.contains('+')
+            || number_str.contains('-') && number_str.matches('-').count() > 1
+        {
+            // For binary expressions, try to evaluate them
+            evaluate_expression(&number_str)
+                .or_else(|| Decimal::from_str_exact(&number_str).ok())?
+        } else {
+            Decimal::from_str_exact(&number_str).ok()?
+        };
+        Some(Amount {
+            value,
+            currency: currency_str,
+        })
+    } else {
+        None
+    }
+}
+
+/// Simple expression evaluator for basic arithmetic
+fn evaluate_expression(expr: &str) -> Option<Decimal> {
+    let expr = expr.trim();
+
+    // Handle simple binary operations: a * b, a + b, a - b
+    if let Some(pos) = expr.rfind('*') {
+        let left = expr[..pos].trim();
+        let right = expr[pos + 1..].trim();
+        let left_val = Decimal::from_str_exact(left).ok()?;
+        let right_val = Decimal::from_str_exact(right).ok()?;
+        return left_val.checked_mul(right_val);
+    }
+
+    if let Some(pos) = expr.rfind('+') {
+        let left = expr[..pos].trim();
+        let right = expr[pos + 1..].trim();
+        let left_val = Decimal::from_str_exact(left).ok()?;
+        let right_val = Decimal::from_str_exact(right).ok()?;
+        return left_val.checked_add(right_val);
+    }
+
+    // Handle subtraction (but not unary minus)
+    if let Some(pos) = expr.rfind('-')
+        && pos > 0
+    {
+        let left = expr[..pos].trim();
+        let right = expr[pos + 1..].trim();
+        if let (Ok(left_val), Ok(right_val)) = (
+            Decimal::from_str_exact(left),
+            Decimal::from_str_exact(right),
+        ) {
+            return left_val.checked_sub(right_val);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ledger(files: &[(&str, &str)]) -> Ledger {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let files = files
+            .iter()
+            .map(|(path, text)| {
+                let tree = parser.parse(text, None).unwrap();
+                let data = BeancountData::new(&tree, &ropey::Rope::from_str(text));
+                (PathBuf::from(path), Arc::new(data))
+            })
+            .collect();
+        Ledger::new(&files)
+    }
+
+    fn dec(value: &str) -> Decimal {
+        Decimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_overflowing_amounts_are_skipped() {
+        let ledger = ledger(&[(
+            "/main.beancount",
+            r#"2024-01-01 * "Product"
+  Assets:Cash  1000000000000000 * 1000000000000000 USD
+  Equity:Opening
+
+2024-01-02 * "Huge price"
+  Assets:Stock  70000000000000000000000000000 ABC @ 70000000000000000000000000000 USD
+  Assets:Cash
+
+2024-01-03 * "Huge sum"
+  Assets:Big  70000000000000000000000000000 USD
+  Assets:Big  70000000000000000000000000000 USD
+  Equity:Opening
+"#,
+        )]);
+
+        assert_eq!(
+            ledger.balance("Assets:Stock").unwrap().get("ABC"),
+            Some(&dec("70000000000000000000000000000"))
+        );
+        // The residual of the converted posting is unknown, so nothing is elided
+        assert!(ledger.balance("Assets:Cash").is_none());
+        assert_eq!(ledger.changes("Assets:Big").len(), 1);
+        assert!(ledger.balance("Equity:Opening").is_none());
+    }
+
+    #[test]
+    fn test_running_balances_with_elided_amount() {
+        let ledger = ledger(&[(
+            "/main.beancount",
+            r#"2024-01-02 * "Groceries"
+  Expenses:Food  25.50 USD
+  Assets:Cash
+
+2024-01-01 * "Salary"
+  Assets:Cash  100 USD
+  Income:Salary  -100 USD
+"#,
+        )]);
+
+        let cash = ledger.balance("Assets:Cash").unwrap();
+        assert_eq!(cash.get("USD"), Some(&dec("74.50")));
+
+        let changes = ledger.changes("Assets:Cash");
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].date, "2024-01-01");
+        assert_eq!(changes[0].balance, dec("100"));
+        assert_eq!(changes[1].amount.value, dec("-25.50"));
+        assert_eq!(changes[1].line, 2);
+
+        assert_eq!(
+            ledger.balance_at("Assets:Cash", "2024-01-01")["USD"],
+            dec("100")
+        );
+        assert!(ledger.balance_at("Assets:Cash", "2023-12-31").is_empty());
+    }
+
+    #[test]
+    fn test_balance_after_posting() {
+        let ledger = ledger(&[(
+            "/main.beancount",
+            r#"2024-01-01 * "Salary"
+  Assets:Cash  100 USD
+  Income:Salary
+
+2024-01-01 * "Exchange"
+  Assets:Cash  -50 USD
+  Assets:Cash  45 EUR
+"#,
+        )]);
+
+        let file = Path::new("/main.beancount");
+        let after_salary = ledger.balance_after("Assets:Cash", file, 1).unwrap();
+        assert_eq!(
+            after_salary,
+            Inventory::from([("USD".to_string(), dec("100"))])
+        );
+        let after_exchange = ledger.balance_after("Assets:Cash", file, 6).unwrap();
+        assert_eq!(after_exchange.len(), 2);
+        assert_eq!(after_exchange["USD"], dec("50"));
+        assert!(ledger.balance_after("Assets:Cash", file, 2).is_none());
+    }
+
+    #[test]
+    fn test_elided_amount_uses_cost_and_price_weight() {
+        let ledger = ledger(&[(
+            "/main.beancount",
+            r#"2024-01-01 * "Buy"
+  Assets:Stock  10 ABC {5 USD}
+  Assets:Cash
+
+2024-01-02 * "Exchange"
+  Assets:Euro  100 EUR @ 1.1 USD
+  Assets:Cash
+"#,
+        )]);
+
+        assert_eq!(ledger.balance("Assets:Stock").unwrap()["ABC"], dec("10"));
+        assert_eq!(ledger.balance("Assets:Cash").unwrap()["USD"], dec("-160.0"));
+    }
+
+    #[test]
+    fn test_pad_fills_up_to_next_balance() {
+        let ledger = ledger(&[
+            (
+                "/main.beancount",
+                r#"2024-01-01 pad Assets:Bank Equity:Opening-Balances
+
+2024-01-05 * "Coffee"
+  Expenses:Coffee  5 USD
+  Assets:Bank
+"#,
+            ),
+            (
+                "/balances.beancount",
+                r#"2024-01-03 balance Assets:Bank  1000 USD
+2024-01-06 balance Assets:Bank  990 USD
+"#,
+            ),
+        ]);
+
+        let bank = ledger.changes("Assets:Bank");
+        assert_eq!(bank[0].date, "2024-01-01");
+        assert_eq!(bank[0].amount.value, dec("1000"));
+        assert_eq!(
+            ledger.balance("Equity:Opening-Balances").unwrap()["USD"],
+            dec("-1000")
+        );
+
+        let checks = ledger.balance_checks();
+        assert_eq!(checks.len(), 2);
+        assert!(checks[0].passed());
+        assert!(!checks[1].passed());
+        assert_eq!(checks[1].actual, dec("995"));
+        assert_eq!(checks[1].file, PathBuf::from("/balances.beancount"));
+        assert_eq!(checks[1].line, 1);
+    }
+
+    #[test]
+    fn test_tag_and_link_summaries() {
+        let ledger = ledger(&[(
+            "/main.beancount",
+            r#"pushtag #trip
+
+2024-03-02 * "Hotel" ^booking-1
+  Expenses:Travel  200 EUR
+  Assets:Cash
+
+poptag #trip
+
+2024-03-01 * "Flight" #trip #work ^booking-1
+  Expenses:Travel  300 EUR
+  Assets:Cash
+
+2024-03-05 * "Groceries"
+  Expenses:Food  20 EUR
+  Assets:Cash
+"#,
+        )]);
+
+        let trip = ledger.tag("trip").unwrap();
+        assert_eq!(trip.transactions, 2);
+        assert_eq!(trip.first_date, "2024-03-01");
+        assert_eq!(trip.last_date, "2024-03-02");
+        assert_eq!(trip.accounts["Expenses:Travel"]["EUR"], dec("500"));
+        assert_eq!(trip.accounts["Assets:Cash"]["EUR"], dec("-500"));
+        assert_eq!(ledger.tag("work").unwrap().transactions, 1);
+        assert_eq!(ledger.link("booking-1").unwrap().transactions, 2);
+        assert!(ledger.tag("booking-1").is_none());
+    }
+
+    #[test]
+    fn test_price_history_in_date_order() {
+        let ledger = ledger(&[(
+            "/prices.beancount",
+            r#"2024-02-01 price ABC  5.20 USD
+2024-01-01 price ABC  5 USD
+2024-01-15 price XYZ  2 EUR
+"#,
+        )]);
+
+        let prices = ledger.prices("ABC");
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[0].date, "2024-01-01");
+        assert_eq!(prices[1].amount.value, dec("5.20"));
+        assert_eq!(prices[1].amount.currency, "USD");
+        assert_eq!(prices[1].line, 0);
+        assert!(ledger.prices("USD").is_empty());
+    }
+
+    #[test]
+    fn test_nearest_price() {
+        let ledger = ledger(&[(
+            "/prices.beancount",
+            r#"2024-01-01 price CHF  1.10 USD
+2024-03-01 price CHF  1.20 USD
+2024-02-10 price USD  0.5 CHF
+"#,
+        )]);
+
+        assert_eq!(
+            ledger.nearest_price("CHF", "USD", "2024-01-10"),
+            Some((dec("1.10"), "2024-01-01"))
+        );
+        assert_eq!(
+            ledger.nearest_price("CHF", "USD", "2024-02-09"),
+            Some((dec("2"), "2024-02-10"))
+        );
+        assert_eq!(
+            ledger.nearest_price("CHF", "USD", "2025-01-01"),
+            Some((dec("1.20"), "2024-03-01"))
+        );
+        assert!(ledger.nearest_price("CHF", "EUR", "2024-01-01").is_none());
+    }
+
+    #[test]
+    fn test_balance_is_checked_before_same_day_transactions() {
+        let ledger = ledger(&[(
+            "/main.beancount",
+            r#"2024-01-01 * "Deposit"
+  Assets:Bank  50 USD
+  Income:Other
+
+2024-01-01 balance Assets:Bank  0 ~ 0.01 USD
+"#,
+        )]);
+
+        let checks = ledger.balance_checks();
+        assert_eq!(checks.len(), 1);
+        assert!(checks[0].passed());
+        assert_eq!(checks[0].expected.value, dec("0"));
+    }
+}
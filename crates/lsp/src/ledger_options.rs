@@ -0,0 +1,326 @@
+//! Options declared by the journal
+//!
+//! Beancount reads `option` directives from the top-level journal file only.
+//! The options that change how the ledger should be read are collected here so
+//! providers can follow the ledger's own configuration:
+//! - `operating_currency` ranks the operating currencies first in completions
+//! - `name_assets`, `name_liabilities`, `name_equity`, `name_income` and
+//!   `name_expenses` rename the root accounts used for completions and for
+//!   validating opened accounts
+//! - `insert_pythonpath` puts the journal's directory on the Python path of the
+//!   bean-check process, so plugins next to the journal can be loaded
+//! - Fava's `custom "fava-option" "currency-column"` is the default currency
+//!   column for formatting
+//!
+//! [`option_doc`] documents every option beancount accepts, for hovers.
+
+use crate::beancount_data::BeancountData;
+
+/// Root account options and their default names, in beancount's order.
+const ROOT_ACCOUNTS: [(&str, &str); 5] = [
+    ("name_assets", "Assets"),
+    ("name_liabilities", "Liabilities"),
+    ("name_equity", "Equity"),
+    ("name_income", "Income"),
+    ("name_expenses", "Expenses"),
+];
+
+/// Documentation of a beancount option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OptionDoc {
+    pub name: &'static str,
+    /// The kind of value, e.g. `string` or `list of currencies`.
+    pub value_type: &'static str,
+    /// The value in effect when the option is not declared.
+    pub default: &'static str,
+    pub description: &'static str,
+}
+
+const fn doc(
+    name: &'static str,
+    value_type: &'static str,
+    default: &'static str,
+    description: &'static str,
+) -> OptionDoc {
+    OptionDoc {
+        name,
+        value_type,
+        default,
+        description,
+    }
+}
+
+/// The options beancount accepts, except deprecated ones.
+const OPTION_DOCS: &[OptionDoc] = &[
+    doc(
+        "title",
+        "string",
+        "Beancount",
+        "The title of this ledger, shown by reporting tools.",
+    ),
+    doc(
+        "name_assets",
+        "string",
+        "Assets",
+        "The name of the root account for assets.",
+    ),
+    doc(
+        "name_liabilities",
+        "string",
+        "Liabilities",
+        "The name of the root account for liabilities.",
+    ),
+    doc(
+        "name_equity",
+        "string",
+        "Equity",
+        "The name of the root account for equity.",
+    ),
+    doc(
+        "name_income",
+        "string",
+        "Income",
+        "The name of the root account for income.",
+    ),
+    doc(
+        "name_expenses",
+        "string",
+        "Expenses",
+        "The name of the root account for expenses.",
+    ),
+    doc(
+        "account_previous_balances",
+        "account name under the equity root",
+        "Opening-Balances",
+        "Receives the balances of the income statement accounts from before the reporting period when a period is summarized.",
+    ),
+    doc(
+        "account_previous_earnings",
+        "account name under the equity root",
+        "Earnings:Previous",
+        "Receives the net income from before the reporting period when the income statement accounts are cleared.",
+    ),
+    doc(
+        "account_previous_conversions",
+        "account name under the equity root",
+        "Conversions:Previous",
+        "Receives the currency conversions from before the reporting period.",
+    ),
+    doc(
+        "account_current_earnings",
+        "account name under the equity root",
+        "Earnings:Current",
+        "Receives the net income of the reporting period when the income statement accounts are cleared.",
+    ),
+    doc(
+        "account_current_conversions",
+        "account name under the equity root",
+        "Conversions:Current",
+        "Receives the currency conversions of the reporting period.",
+    ),
+    doc(
+        "account_unrealized_gains",
+        "account name under the income root",
+        "none",
+        "Receives unrealized gains when the `unrealized` plugin is enabled.",
+    ),
+    doc(
+        "account_rounding",
+        "account name",
+        "none",
+        "When set, the rounding residual of each transaction is posted to this account.",
+    ),
+    doc(
+        "conversion_currency",
+        "currency",
+        "NOTHING",
+        "The currency used to balance currency conversions at cost when the income statement is cleared.",
+    ),
+    doc(
+        "inferred_tolerance_default",
+        "CURRENCY:TOLERANCE, may be repeated",
+        "none",
+        "The tolerance for amounts of a currency whose tolerance cannot be inferred from the number of digits, e.g. integer amounts. `*` matches any currency.",
+    ),
+    doc(
+        "inferred_tolerance_multiplier",
+        "number",
+        "0.5",
+        "Multiplies the last digit of a number to infer its tolerance, e.g. 0.005 for `1.23 USD`.",
+    ),
+    doc(
+        "infer_tolerance_from_cost",
+        "boolean",
+        "FALSE",
+        "Widens the inferred tolerance of postings held at cost by the precision of the cost.",
+    ),
+    doc(
+        "documents",
+        "directory, may be repeated",
+        "none",
+        "Directories searched for documents; files named `YYYY-MM-DD.description` below a folder named after an account create `document` directives.",
+    ),
+    doc(
+        "operating_currency",
+        "currency, may be repeated",
+        "none",
+        "The main currencies of the ledger, which reporting tools show in their own columns.",
+    ),
+    doc(
+        "render_commas",
+        "boolean",
+        "FALSE",
+        "Whether reporting tools render numbers with thousands separators.",
+    ),
+    doc(
+        "plugin_processing_mode",
+        "`default` or `raw`",
+        "default",
+        "`raw` skips the built-in plugins, such as the ones inserting pad and balance entries.",
+    ),
+    doc(
+        "long_string_maxlines",
+        "integer",
+        "64",
+        "The number of lines a string may span before the parser warns.",
+    ),
+    doc(
+        "booking_method",
+        "`STRICT`, `FIFO`, `LIFO`, `HIFO`, `AVERAGE` or `NONE`",
+        "STRICT",
+        "The default booking method for reducing positions held at cost; `open` directives can override it per account.",
+    ),
+    doc(
+        "insert_pythonpath",
+        "boolean",
+        "FALSE",
+        "Adds the directory of the journal file to the Python path, so plugins can be loaded from it.",
+    ),
+];
+
+/// The documentation of the option named `name`.
+pub fn option_doc(name: &str) -> Option<&'static OptionDoc> {
+    OPTION_DOCS.iter().find(|doc| doc.name == name)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedgerOptions {
+    /// Currencies declared with `option "operating_currency"`, in declaration order.
+    pub operating_currencies: Vec<String>,
+    /// Names of the five root accounts.
+    pub root_accounts: [String; 5],
+    /// Declared with `option "title"`.
+    pub title: Option<String>,
+    /// Declared with `option "insert_pythonpath"`.
+    pub insert_pythonpath: bool,
+    /// Declared with `custom "fava-option" "currency-column"`.
+    pub currency_column: Option<usize>,
+}
+
+impl Default for LedgerOptions {
+    fn default() -> Self {
+        Self {
+            operating_currencies: vec![],
+            root_accounts: ROOT_ACCOUNTS.map(|(_, name)| name.to_string()),
+            title: None,
+            insert_pythonpath: false,
+            currency_column: None,
+        }
+    }
+}
+
+impl LedgerOptions {
+    /// Collects the options declared by the journal file.
+    pub fn from_data(data: &BeancountData) -> Self {
+        let mut options = Self::default();
+        for (key, value) in data.get_options().iter() {
+            if key == "operating_currency" {
+                if !options.operating_currencies.contains(value) {
+                    options.operating_currencies.push(value.clone());
+                }
+            } else if key == "title" {
+                options.title = Some(value.clone());
+            } else if key == "insert_pythonpath" {
+                // Like beancount's boolean options
+                options.insert_pythonpath =
+                    ["1", "true", "yes"].contains(&value.to_lowercase().as_str());
+            } else if let Some(i) = ROOT_ACCOUNTS.iter().position(|(name, _)| name == key) {
+                options.root_accounts[i] = value.clone();
+            }
+        }
+        for (key, value) in data.get_fava_options().iter() {
+            if key == "currency-column" {
+                options.currency_column = value.trim().parse().ok();
+            }
+        }
+        options
+    }
+
+    /// Whether `account` starts with one of the root account names.
+    pub fn has_valid_root(&self, account: &str) -> bool {
+        let root = account.split(':').next().unwrap_or(account);
+        self.root_accounts.iter().any(|name| name == root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(text: &str) -> LedgerOptions {
+        let mut parser = tree_sitter_beancount::tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        LedgerOptions::from_data(&BeancountData::new(&tree, &ropey::Rope::from_str(text)))
+    }
+
+    #[test]
+    fn test_defaults_without_options() {
+        let options = options("2024-01-01 open Assets:Cash\n");
+        assert_eq!(options, LedgerOptions::default());
+        assert!(options.has_valid_root("Assets:Cash"));
+        assert!(!options.has_valid_root("Vermoegen:Bar"));
+    }
+
+    #[test]
+    fn test_declared_options() {
+        let options = options(
+            r#"option "operating_currency" "EUR"
+option "operating_currency" "CHF"
+option "operating_currency" "EUR"
+option "name_assets" "Vermoegen"
+option "title" "Haushalt"
+option "insert_pythonpath" "TRUE"
+2024-01-01 custom "fava-option" "currency-column" "61"
+2024-01-01 custom "budget" "currency-column" "80"
+"#,
+        );
+        assert_eq!(options.operating_currencies, vec!["EUR", "CHF"]);
+        assert_eq!(options.root_accounts[0], "Vermoegen");
+        assert_eq!(options.root_accounts[4], "Expenses");
+        assert_eq!(options.title.as_deref(), Some("Haushalt"));
+        assert!(options.insert_pythonpath);
+        assert_eq!(options.currency_column, Some(61));
+        assert!(options.has_valid_root("Vermoegen:Bar"));
+        assert!(!options.has_valid_root("Assets:Cash"));
+    }
+
+    #[test]
+    fn test_option_docs() {
+        let doc = option_doc("booking_method").unwrap();
+        assert_eq!(doc.default, "STRICT");
+        assert!(option_doc("no_such_option").is_none());
+        for (i, doc) in OPTION_DOCS.iter().enumerate() {
+            assert!(
+                OPTION_DOCS[..i].iter().all(|other| other.name != doc.name),
+                "{} is documented twice",
+                doc.name
+            );
+        }
+        for (name, default) in ROOT_ACCOUNTS {
+            assert_eq!(option_doc(name).unwrap().default, default);
+        }
+    }
+}
@@ -1,34 +1,48 @@
 pub mod beancount_data;
 mod capabilities;
 pub mod checkers;
-mod config;
+pub mod cli;
+pub mod config;
+pub mod convert;
 mod dispatcher;
 pub mod document;
 //pub mod error;
 pub mod forest;
 pub mod handlers;
+pub mod importer;
+mod include_graph;
+pub mod index;
+pub mod ledger;
+pub mod ledger_options;
+pub mod logging;
+pub mod lsp_ext;
+mod pending_changes;
 pub mod progress;
 pub mod providers;
-mod query_utils;
+mod queries;
+mod scheduler;
 pub mod server;
 //pub mod session;
+pub mod transport;
 mod treesitter_utils;
 mod utils;
 
 use crate::config::Config;
 use crate::server::LspServerState;
+use crate::transport::Transport;
 use anyhow::Result;
 use lsp_server::Connection;
 use lsp_types::InitializeParams;
 use serde::{Serialize, de::DeserializeOwned};
+use std::path::PathBuf;
 use utils::ToFilePath;
 
-pub fn run_server() -> Result<()> {
+pub fn run_server(transport: Transport) -> Result<()> {
     tracing::info!("beancount-language-server started");
 
     //Setup IO connections
-    tracing::debug!("Setting up stdio connections");
-    let (connection, io_threads) = lsp_server::Connection::stdio();
+    tracing::debug!("Setting up {:?} connection", transport);
+    let (connection, io_threads) = transport.connect()?;
 
     //wait for client to connection
     tracing::debug!("Waiting for client initialization");
@@ -56,12 +70,21 @@ pub fn run_server() -> Result<()> {
         tracing::warn!("Client did not provide client info");
     }
 
+    let workspace_folders: Vec<PathBuf> = initialize_params
+        .workspace_folders
+        .iter()
+        .flatten()
+        .filter_map(|folder| folder.uri.to_file_path().ok())
+        .filter(|folder| !utils::is_virtual_document(folder))
+        .collect();
+
     // Parse config first so we can conditionally register capabilities
     let config = {
         let root_file = if let Some(workspace_folders) = &initialize_params.workspace_folders {
             let root = workspace_folders
                 .first()
                 .and_then(|folder| folder.uri.to_file_path().ok())
+                .filter(|root| !utils::is_virtual_document(root))
                 .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
             tracing::info!("Using workspace folder as root: {}", root.display());
             root
@@ -70,6 +93,7 @@ pub fn run_server() -> Result<()> {
             let root = match initialize_params
                 .root_uri
                 .and_then(|it| it.to_file_path().ok())
+                .filter(|root| !utils::is_virtual_document(root))
             {
                 Some(it) => it,
                 None => std::env::current_dir()?,
@@ -78,23 +102,21 @@ pub fn run_server() -> Result<()> {
             root
         };
 
-        let mut config = Config::new(root_file);
-        if let Some(json) = initialize_params.initialization_options {
-            tracing::info!("Applying initialization options: {}", json);
-            match config.update(json) {
-                Ok(()) => tracing::debug!("Configuration updated successfully"),
-                Err(e) => {
-                    tracing::warn!("Failed to update configuration: {}", e);
-                    return Err(e);
-                }
-            }
-        } else {
-            tracing::debug!("No initialization options provided, using default config");
-        }
-        config
+        Config::load(root_file, initialize_params.initialization_options)
     };
 
-    let server_capabilities = capabilities::server_capabilities();
+    let position_encoding = treesitter_utils::PositionEncoding::negotiate(
+        initialize_params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_deref()),
+    );
+    treesitter_utils::set_position_encoding(position_encoding);
+    tracing::info!("Using position encoding {:?}", position_encoding);
+
+    let server_capabilities =
+        capabilities::server_capabilities(&config.features, config.format_on_save);
     tracing::debug!("Server capabilities configured");
 
     let initialize_result = lsp_types::InitializeResult {
@@ -109,10 +131,19 @@ pub fn run_server() -> Result<()> {
         serde_json::to_value(initialize_result).expect("Failed to serialize InitializeResult");
 
     connection.initialize_finish(request_id, initialize_result)?;
+    logging::forward_to_client(
+        connection.sender.clone(),
+        initialize_params.trace.unwrap_or_default(),
+    );
     tracing::info!("Initialization completed successfully");
 
     tracing::debug!("Starting main loop");
-    main_loop(connection, config)?;
+    main_loop(
+        connection,
+        config,
+        workspace_folders,
+        &initialize_params.capabilities,
+    )?;
 
     tracing::debug!("Waiting for IO threads to complete");
     io_threads.join()?;
@@ -121,9 +152,39 @@ pub fn run_server() -> Result<()> {
     Ok(())
 }
 
-pub fn main_loop(connection: Connection, config: Config) -> Result<()> {
+/// Runs the server until the client exits. `workspace_folders` are indexed next to
+/// `config.root_dir`.
+pub fn main_loop(
+    connection: Connection,
+    config: Config,
+    workspace_folders: Vec<PathBuf>,
+    client_capabilities: &lsp_types::ClientCapabilities,
+) -> Result<()> {
     tracing::info!("initial config: {:#?}", config);
-    LspServerState::new(connection.sender, config).run(connection.receiver)
+    let mut state = LspServerState::new(connection.sender, config);
+    state.inlay_hint_refresh_support = client_capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.inlay_hint.as_ref())
+        .and_then(|inlay_hint| inlay_hint.refresh_support)
+        .unwrap_or(false);
+    state.document_changes_support = client_capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.workspace_edit.as_ref())
+        .and_then(|workspace_edit| workspace_edit.document_changes)
+        .unwrap_or(false);
+    state.show_document_support = client_capabilities
+        .window
+        .as_ref()
+        .and_then(|window| window.show_document.as_ref())
+        .is_some_and(|show_document| show_document.support);
+    for folder in workspace_folders {
+        if folder != state.config.root_dir {
+            state.workspace_folders.insert(folder, None);
+        }
+    }
+    state.run(connection.receiver)
 }
 
 pub fn from_json<T: DeserializeOwned>(what: &'static str, json: serde_json::Value) -> Result<T> {
@@ -0,0 +1,356 @@
+//! Logging setup
+//!
+//! Log output goes to stderr or to a file that is rotated once it grows past
+//! [`LOG_FILE_MAX_BYTES`]. Both the level and the file can be changed while the
+//! server is running, so a user can turn on debug logs from the editor settings
+//! when reporting an issue.
+//!
+//! While the client enabled tracing with `$/setTrace`, the server's own log
+//! events are also forwarded to it as `$/logTrace` notifications.
+
+use crossbeam_channel::Sender;
+use lsp_types::TraceValue;
+use lsp_types::notification::Notification;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry, reload};
+
+/// Size at which the log file is rotated.
+pub const LOG_FILE_MAX_BYTES: u64 = 10 * 1024 * 1024;
+/// Number of rotated log files kept next to the current one (`<file>.1` is the newest).
+pub const LOG_FILE_BACKUPS: usize = 3;
+
+static LEVEL_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+static OUTPUT: OnceLock<Mutex<Output>> = OnceLock::new();
+static CLIENT_TRACE: OnceLock<Mutex<ClientTrace>> = OnceLock::new();
+
+fn output() -> &'static Mutex<Output> {
+    OUTPUT.get_or_init(|| Mutex::new(Output::Stderr))
+}
+
+fn client_trace() -> &'static Mutex<ClientTrace> {
+    CLIENT_TRACE.get_or_init(|| {
+        Mutex::new(ClientTrace {
+            value: TraceValue::Off,
+            sender: None,
+        })
+    })
+}
+
+/// Installs the global subscriber, logging to stderr at `level`.
+pub fn init(level: LevelFilter) {
+    let (filter, handle) = reload::Layer::new(level_filter(level));
+    let _ = LEVEL_HANDLE.set(handle);
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(LogWriter)
+                .with_ansi(false)
+                .with_target(false)
+                .with_thread_ids(true)
+                .with_level(true),
+        )
+        .with(LogTraceLayer)
+        .init();
+}
+
+/// Changes the level of the running subscriber. Does nothing before [`init`].
+pub fn set_level(level: LevelFilter) {
+    if let Some(handle) = LEVEL_HANDLE.get()
+        && let Err(e) = handle.reload(level_filter(level))
+    {
+        eprintln!("Failed to change log level: {e}");
+    }
+}
+
+/// Sends log output to `path`, or back to stderr when `None`.
+pub fn set_log_file(path: Option<&Path>) -> io::Result<()> {
+    let new_output = match path {
+        Some(path) => Output::File(RotatingFile::open(
+            path,
+            LOG_FILE_MAX_BYTES,
+            LOG_FILE_BACKUPS,
+        )?),
+        None => Output::Stderr,
+    };
+    *lock(output()) = new_output;
+    Ok(())
+}
+
+/// Path of the current log file, if logging to a file.
+pub fn log_file() -> Option<PathBuf> {
+    match &*lock(output()) {
+        Output::File(file) => Some(file.path.clone()),
+        Output::Stderr => None,
+    }
+}
+
+/// Forwards log events to the client through `sender` at the given trace level.
+pub(crate) fn forward_to_client(sender: Sender<lsp_server::Message>, value: TraceValue) {
+    let mut trace = lock(client_trace());
+    trace.sender = Some(sender);
+    trace.value = value;
+}
+
+/// Handles `$/setTrace`.
+pub(crate) fn set_trace(value: TraceValue) {
+    lock(client_trace()).value = value;
+}
+
+fn level_filter(level: LevelFilter) -> EnvFilter {
+    EnvFilter::default().add_directive(level.into())
+}
+
+/// Logging must keep working after a panic on another thread.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+enum Output {
+    Stderr,
+    File(RotatingFile),
+}
+
+/// Writes to the current [`Output`].
+struct LogWriter;
+
+struct LogWriterGuard(MutexGuard<'static, Output>);
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogWriterGuard;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogWriterGuard(lock(output()))
+    }
+}
+
+impl Write for LogWriterGuard {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *self.0 {
+            Output::Stderr => io::stderr().write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *self.0 {
+            Output::Stderr => io::stderr().flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
+
+/// An append-only file that is moved to `<path>.1` once it reaches `max_bytes`,
+/// shifting older files up to `<path>.<backups>`.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    backups: usize,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, max_bytes: u64, backups: usize) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_bytes,
+            backups,
+        })
+    }
+
+    fn backup_path(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.backups > 0 {
+            for n in (1..self.backups).rev() {
+                let from = self.backup_path(n);
+                if from.exists() {
+                    fs::rename(&from, self.backup_path(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.backup_path(1))?;
+        }
+        self.file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+struct ClientTrace {
+    value: TraceValue,
+    sender: Option<Sender<lsp_server::Message>>,
+}
+
+/// Forwards the server's log events as `$/logTrace` notifications.
+struct LogTraceLayer;
+
+impl<S: tracing::Subscriber> Layer<S> for LogTraceLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // Dependencies log about the connection itself; forwarding those would loop.
+        if !metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+            return;
+        }
+
+        let trace = lock(client_trace());
+        let Some(sender) = &trace.sender else {
+            return;
+        };
+        if trace.value == TraceValue::Off {
+            return;
+        }
+
+        let params = log_trace_params(event, trace.value);
+        let _ = sender.send(lsp_server::Message::Notification(
+            lsp_server::Notification::new(
+                lsp_types::notification::LogTrace::METHOD.to_string(),
+                params,
+            ),
+        ));
+    }
+}
+
+fn log_trace_params(event: &tracing::Event<'_>, value: TraceValue) -> lsp_types::LogTraceParams {
+    let mut visitor = EventVisitor::default();
+    event.record(&mut visitor);
+
+    let metadata = event.metadata();
+    let verbose = (value == TraceValue::Verbose).then(|| {
+        let location = match (metadata.file(), metadata.line()) {
+            (Some(file), Some(line)) => format!("{file}:{line}"),
+            _ => metadata.target().to_string(),
+        };
+        if visitor.fields.is_empty() {
+            location
+        } else {
+            format!("{location} {}", visitor.fields.join(" "))
+        }
+    });
+
+    lsp_types::LogTraceParams {
+        message: format!("[{}] {}", metadata.level(), visitor.message),
+        verbose,
+    }
+}
+
+#[derive(Default)]
+struct EventVisitor {
+    message: String,
+    fields: Vec<String>,
+}
+
+impl tracing::field::Visit for EventVisitor {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields.push(format!("{}={:?}", field.name(), value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotating_file_keeps_backups() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("server.log");
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("server.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("server.log.2")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.path().join("server.log.3").exists());
+    }
+
+    #[test]
+    fn test_rotating_file_appends_to_existing_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("server.log");
+        fs::write(&path, "old\n").unwrap();
+
+        let mut file = RotatingFile::open(&path, 100, 1).unwrap();
+        file.write_all(b"new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\nnew\n");
+    }
+
+    #[test]
+    fn test_log_trace_params() {
+        struct Capture(Mutex<Vec<lsp_types::LogTraceParams>>);
+        impl<S: tracing::Subscriber> Layer<S> for &'static Capture {
+            fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+                let mut params = lock(&self.0);
+                params.push(log_trace_params(event, TraceValue::Messages));
+                params.push(log_trace_params(event, TraceValue::Verbose));
+            }
+        }
+
+        let capture: &'static Capture = Box::leak(Box::new(Capture(Mutex::new(vec![]))));
+        let subscriber = tracing_subscriber::registry().with(capture);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(file = "main.bean", "Failed to parse");
+        });
+
+        let params = lock(&capture.0);
+        assert_eq!(params[0].message, "[WARN] Failed to parse");
+        assert_eq!(params[0].verbose, None);
+        let verbose = params[1].verbose.as_deref().unwrap();
+        assert!(verbose.contains("logging.rs:"), "{verbose}");
+        assert!(verbose.ends_with("file=\"main.bean\""), "{verbose}");
+    }
+}
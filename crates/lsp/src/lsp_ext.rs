@@ -0,0 +1,277 @@
+//! Protocol extensions
+//!
+//! Requests the server answers beyond the language server protocol, for client
+//! extensions that want to show more than the standard features.
+
+use crate::config::FormattingConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// `beancount/status`: health of the server, e.g. for a status bar item.
+pub enum Status {}
+
+impl lsp_types::request::Request for Status {
+    /// Any params are ignored.
+    type Params = Option<serde_json::Value>;
+    type Result = StatusResult;
+    const METHOD: &'static str = "beancount/status";
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusResult {
+    /// Version of the server.
+    pub version: String,
+    /// Number of files in the forest.
+    pub indexed_files: usize,
+    /// Number of documents open in the editor.
+    pub open_documents: usize,
+    /// Number of directives per kind (`transaction`, `open`, ...) across the forest.
+    pub directives: BTreeMap<String, usize>,
+    pub index: IndexStatus,
+    /// The last bean-check run, if any finished yet.
+    pub bean_check: Option<BeanCheckStatus>,
+    pub config: ConfigStatus,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStatus {
+    /// Whether the journal is still being indexed.
+    pub in_progress: bool,
+    /// Files indexed so far by the current or last indexing run.
+    pub done: usize,
+    /// Files to index in the current or last indexing run.
+    pub total: usize,
+    /// When the index last changed, in milliseconds since the Unix epoch.
+    pub updated_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BeanCheckStatus {
+    /// Name of the checker that ran.
+    pub checker: String,
+    pub duration_ms: u64,
+    /// Number of error diagnostics reported.
+    pub errors: usize,
+    /// Number of warning diagnostics reported.
+    pub warnings: usize,
+    /// When the run finished, in milliseconds since the Unix epoch.
+    pub finished_at: u64,
+}
+
+/// The configuration in effect, after merging all sources.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigStatus {
+    pub root_dir: PathBuf,
+    /// The configured or detected journal file.
+    pub journal_file: Option<PathBuf>,
+    /// The configured checker method, if any.
+    pub bean_check_method: Option<String>,
+    pub diagnostic_flags: Vec<String>,
+    /// Formatting options, named like the `formatting` settings.
+    pub formatting: FormattingConfig,
+    pub log_level: Option<String>,
+    pub log_file: Option<PathBuf>,
+}
+
+/// `beancount/monthlyTotals`: what went through the income and expense accounts each
+/// month, e.g. for sparkline charts next to the ledger.
+pub enum MonthlyTotals {}
+
+impl lsp_types::request::Request for MonthlyTotals {
+    type Params = MonthlyTotalsParams;
+    type Result = MonthlyTotalsResult;
+    const METHOD: &'static str = "beancount/monthlyTotals";
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthlyTotalsParams {
+    /// A document whose journal is summed instead of the configured one.
+    pub text_document: Option<lsp_types::TextDocumentIdentifier>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthlyTotalsResult {
+    /// `YYYY-MM`, every month from the first to the last transaction.
+    pub months: Vec<String>,
+    pub series: Vec<MonthlyTotalsSeries>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MonthlyTotalsSeries {
+    /// The root account, like `Income` or `Expenses`.
+    pub account: String,
+    pub currency: String,
+    /// The sum of the postings of each month of `months`, as strings so they keep
+    /// their precision. Income is negative, as in the ledger.
+    pub totals: Vec<String>,
+}
+
+/// `workspace/executeCommand` command running a bean-query subset against the index.
+///
+/// The only argument is the query string; the result is a [`QueryResult`].
+pub const QUERY_COMMAND: &str = "beancount.query";
+
+/// `workspace/executeCommand` command running a query with the external `bean-query`.
+///
+/// Arguments are the query string and optionally the URI of a document, whose journal
+/// is queried instead of the configured one; the result is a [`RunQueryResult`].
+pub const RUN_QUERY_COMMAND: &str = "beancount.runQuery";
+
+/// `workspace/executeCommand` command placing transactions from importer scripts.
+///
+/// Arguments are a list of [`ImportedTransaction`]s and optionally the URI of a
+/// document, whose journal receives them instead of the configured one; the result is
+/// a `WorkspaceEdit` inserting each transaction by date into the file it belongs to.
+pub const IMPORT_TRANSACTIONS_COMMAND: &str = "beancount.importTransactions";
+
+/// `workspace/executeCommand` command importing a bank CSV export with a rules file.
+///
+/// Arguments are the paths of the CSV and the TOML rules file, relative to the
+/// workspace root, and optionally the URI of a document whose journal receives the
+/// transactions; the result is a `WorkspaceEdit` like [`IMPORT_TRANSACTIONS_COMMAND`]'s.
+pub const IMPORT_CSV_COMMAND: &str = "beancount.importCsv";
+
+/// `workspace/executeCommand` command returning the Fava URL of a symbol.
+///
+/// The argument is a `TextDocumentPositionParams`; the result is the URL of the page
+/// for the account, tag or link there, or `null`.
+pub const FAVA_LINK_COMMAND: &str = "beancount.favaLink";
+
+/// `workspace/executeCommand` command opening Fava in the browser.
+///
+/// The optional argument is a `TextDocumentPositionParams`, whose symbol's page is
+/// opened instead of the start page. The server asks the client to open the URL with
+/// `window/showDocument`, or shows it in a message if the client doesn't support that,
+/// and returns it.
+pub const OPEN_FAVA_COMMAND: &str = "beancount.openFava";
+
+/// `workspace/executeCommand` command adding prices fetched by `bean-price`.
+///
+/// Arguments are optionally the date to fetch prices for, `YYYY-MM-DD` (default:
+/// today), and the URI of a document whose journal is priced instead of the
+/// configured one; the result is a `WorkspaceEdit` adding the new prices to the
+/// prices file.
+pub const UPDATE_PRICES_COMMAND: &str = "beancount.updatePrices";
+
+/// `workspace/executeCommand` command listing the transactions of a payee, run by the
+/// payee code lenses.
+///
+/// Arguments are the payee and optionally the URI of a document whose journal is
+/// searched instead of the configured one; the result is a [`PayeeHistory`].
+pub const PAYEE_HISTORY_COMMAND: &str = "beancount.payeeHistory";
+
+/// `workspace/executeCommand` command finding where a new transaction belongs.
+///
+/// Arguments are the date, `YYYY-MM-DD`, and optionally the URI of the file to add it
+/// to instead of the configured journal; the result is an [`InsertTransactionResult`].
+pub const INSERT_TRANSACTION_COMMAND: &str = "beancount.insertTransaction";
+
+/// Commands the server executes, announced in its capabilities.
+pub const COMMANDS: &[&str] = &[
+    QUERY_COMMAND,
+    RUN_QUERY_COMMAND,
+    IMPORT_TRANSACTIONS_COMMAND,
+    IMPORT_CSV_COMMAND,
+    FAVA_LINK_COMMAND,
+    OPEN_FAVA_COMMAND,
+    UPDATE_PRICES_COMMAND,
+    PAYEE_HISTORY_COMMAND,
+    INSERT_TRANSACTION_COMMAND,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryResult {
+    /// Column names, e.g. `account` or `sum(position)`.
+    pub columns: Vec<String>,
+    /// One value per column. Numbers are strings so they keep their precision, and
+    /// summed positions are arrays of `"<number> <currency>"` strings.
+    pub rows: Vec<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunQueryResult {
+    /// Column names as printed by bean-query.
+    pub columns: Vec<String>,
+    /// Cells as printed by bean-query.
+    pub rows: Vec<Vec<String>>,
+    /// The result as a markdown table.
+    pub markdown: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedTransaction {
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    /// `*` when omitted.
+    pub flag: Option<String>,
+    pub payee: Option<String>,
+    #[serde(default)]
+    pub narration: String,
+    /// Tag names, without `#`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Link names, without `^`.
+    #[serde(default)]
+    pub links: Vec<String>,
+    /// Written as string metadata, e.g. an id of the imported record.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    pub postings: Vec<ImportedPosting>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedPosting {
+    pub account: String,
+    /// Like `-12.50 USD`; omitted to let beancount fill in the balancing amount.
+    pub amount: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertTransactionResult {
+    pub uri: lsp_types::Uri,
+    /// Where a transaction of the date goes in the file as it is, keeping it sorted.
+    pub position: lsp_types::Position,
+    /// Inserts a transaction skeleton there, set apart by blank lines.
+    pub edit: lsp_types::WorkspaceEdit,
+    /// Inside the skeleton's narration once `edit` is applied.
+    pub cursor: lsp_types::Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayeeHistory {
+    pub payee: String,
+    /// Newest first.
+    pub transactions: Vec<PayeeTransaction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PayeeTransaction {
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    pub narration: Option<String>,
+    /// The start of the transaction's first line.
+    pub location: lsp_types::Location,
+}
+
+/// Milliseconds since the Unix epoch.
+pub(crate) fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
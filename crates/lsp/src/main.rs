@@ -1,18 +1,36 @@
-use clap::Parser;
-use std::fs;
-use std::io;
+use beancount_language_server::transport::Transport;
+use beancount_language_server::{cli, logging};
+use clap::{Parser, Subcommand};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::str::FromStr;
-use std::sync::Arc;
 use tracing::level_filters::LevelFilter;
-use tracing_subscriber::fmt::writer::BoxMakeWriter;
-use tracing_subscriber::{EnvFilter, filter::Directive};
 
 #[derive(Parser, Debug)]
 #[command(name = "beancount-language-server", about = "Beancount LSP", version, long_about = None)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(long, help = "Use stdio to communicate with the LSP")]
     stdio: bool,
 
+    #[arg(
+        long,
+        value_name = "ADDR",
+        conflicts_with_all = ["stdio", "pipe"],
+        help = "Wait for a client to connect over TCP on the given address, e.g. 127.0.0.1:9257"
+    )]
+    listen: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "stdio",
+        help = "Connect to a Unix domain socket or Windows named pipe created by the client"
+    )]
+    pipe: Option<PathBuf>,
+
     #[arg(
         long,
         value_name = "LOG_LEVEL",
@@ -25,7 +43,7 @@ struct Cli {
         long = "log-file",
         value_name = "LOG_FILE",
         default_value = None,
-        help = "Write log output to the specified file instead of stderr"
+        help = "Write log output to the specified file instead of stderr, rotated at 10 MiB"
     )]
     log_file: Option<String>,
 
@@ -38,9 +56,31 @@ struct Cli {
     log_level: Option<String>,
 }
 
-fn main() {
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Format beancount files without starting the language server
+    Format(cli::FormatArgs),
+    /// Report diagnostics for a journal, e.g. in CI
+    #[command(visible_alias = "check")]
+    Lint(cli::LintArgs),
+    /// Turn a bank CSV export into transactions using a rules file
+    Import(cli::ImportArgs),
+    /// Convert a ledger-cli or hledger journal to beancount
+    Convert(cli::ConvertArgs),
+}
+
+fn main() -> ExitCode {
     let cli = Cli::parse_from(std::env::args_os());
 
+    if let Some(command) = cli.command {
+        return match command {
+            Command::Format(args) => cli::format(args),
+            Command::Lint(args) => cli::lint(args),
+            Command::Import(args) => cli::import(args),
+            Command::Convert(args) => cli::convert(args),
+        };
+    }
+
     let deprecated_log_used = cli.log.is_some();
 
     if deprecated_log_used {
@@ -64,19 +104,27 @@ fn main() {
         env!("CARGO_PKG_VERSION")
     );
     tracing::debug!(
-        "Command line args: stdio={}, log_target={}, log_level={:?}",
+        "Command line args: stdio={}, listen={:?}, pipe={:?}, log_target={}, log_level={:?}",
         cli.stdio,
+        cli.listen,
+        cli.pipe,
         log_file.as_deref().unwrap_or("/dev/stderr"),
         log_level
     );
 
-    match beancount_language_server::run_server() {
+    let transport = match (cli.listen, cli.pipe) {
+        (Some(addr), _) => Transport::Listen(addr),
+        (None, Some(name)) => Transport::Pipe(name),
+        (None, None) => Transport::Stdio,
+    };
+    match beancount_language_server::run_server(transport) {
         Ok(()) => {
             tracing::info!("Language server shutdown gracefully");
+            ExitCode::SUCCESS
         }
         Err(e) => {
             tracing::error!("Language server failed with error: {}", e);
-            std::process::exit(1);
+            ExitCode::FAILURE
         }
     }
 }
@@ -95,33 +143,14 @@ fn setup_logging(log_file: Option<&str>, log_level_arg: Option<&str>) {
         }
     };
 
-    let file = match log_file {
-        Some(path) => match fs::OpenOptions::new().create(true).append(true).open(path) {
-            Ok(f) => {
-                eprintln!("Logging to file: {path}");
-                Some(f)
-            }
-            Err(e) => {
-                eprintln!("Failed to open log file '{path}': {e}. Falling back to stderr.");
-                None
-            }
-        },
-        None => None,
-    };
-
-    let writer = match file {
-        Some(file) => BoxMakeWriter::new(Arc::new(file)),
-        None => BoxMakeWriter::new(io::stderr),
-    };
+    if let Some(path) = log_file {
+        match logging::set_log_file(Some(Path::new(path))) {
+            Ok(()) => eprintln!("Logging to file: {path}"),
+            Err(e) => eprintln!("Failed to open log file '{path}': {e}. Falling back to stderr."),
+        }
+    }
 
-    let filter = EnvFilter::default().add_directive(Directive::from(level));
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_writer(writer)
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_level(true)
-        .init();
+    logging::init(level);
 }
 
 fn parse_log_level(level_str: Option<&str>) -> Option<LevelFilter> {
@@ -143,6 +172,22 @@ fn parse_log_level(level_str: Option<&str>) -> Option<LevelFilter> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_transport_args_conflict() {
+        let cli = Cli::try_parse_from(["beancount-language-server", "--listen", "127.0.0.1:9257"])
+            .unwrap();
+        assert_eq!(cli.listen.as_deref(), Some("127.0.0.1:9257"));
+        assert!(
+            Cli::try_parse_from([
+                "beancount-language-server",
+                "--stdio",
+                "--pipe",
+                "/tmp/lsp.sock"
+            ])
+            .is_err()
+        );
+    }
+
     #[test]
     fn test_parse_log_level_valid_lowercase() {
         assert_eq!(parse_log_level(Some("trace")), Some(LevelFilter::TRACE));
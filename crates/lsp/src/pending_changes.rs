@@ -0,0 +1,112 @@
+//! Coalescing of `textDocument/didChange` notifications.
+//!
+//! Typing sends a change per keystroke. Each change only updates the document text
+//! and edits its tree; the reparse waits until another message needs the tree, and
+//! re-indexing and diagnostics wait until the document was idle for a while.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+#[derive(Debug, Default)]
+pub(crate) struct PendingChanges {
+    /// Documents whose tree was edited but not reparsed yet
+    unparsed: HashSet<PathBuf>,
+    /// When each changed document is due for re-indexing and diagnostics
+    due: HashMap<PathBuf, (lsp_types::Uri, Instant)>,
+}
+
+impl PendingChanges {
+    /// Records a change of `path`, postponing its re-indexing to `deadline`.
+    pub fn record(&mut self, path: PathBuf, uri: lsp_types::Uri, deadline: Instant) {
+        self.unparsed.insert(path.clone());
+        self.due.insert(path, (uri, deadline));
+    }
+
+    /// Forgets the pending work of `path`, e.g. because it was closed.
+    pub fn remove(&mut self, path: &Path) {
+        self.unparsed.remove(path);
+        self.due.remove(path);
+    }
+
+    /// The earliest time a changed document is due.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.due.values().map(|(_, deadline)| *deadline).min()
+    }
+
+    /// The documents that need a reparse before their tree can be used.
+    pub fn take_unparsed(&mut self) -> Vec<PathBuf> {
+        self.unparsed.drain().collect()
+    }
+
+    /// The documents idle since their deadline, which is at or before `now`.
+    pub fn take_due(&mut self, now: Instant) -> Vec<(PathBuf, lsp_types::Uri)> {
+        let due: Vec<PathBuf> = self
+            .due
+            .iter()
+            .filter(|(_, (_, deadline))| *deadline <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        due.into_iter()
+            .filter_map(|path| {
+                let (uri, _) = self.due.remove(&path)?;
+                Some((path, uri))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_changes_are_due_after_the_last_one() {
+        let mut pending = PendingChanges::default();
+        let path = PathBuf::from("/test/main.beancount");
+        let uri = lsp_types::Uri::from_str("file:///test/main.beancount").unwrap();
+        let start = Instant::now();
+
+        pending.record(
+            path.clone(),
+            uri.clone(),
+            start + Duration::from_millis(100),
+        );
+        pending.record(
+            path.clone(),
+            uri.clone(),
+            start + Duration::from_millis(200),
+        );
+        assert_eq!(
+            pending.next_deadline(),
+            Some(start + Duration::from_millis(200))
+        );
+        assert!(
+            pending
+                .take_due(start + Duration::from_millis(150))
+                .is_empty()
+        );
+        assert_eq!(pending.take_unparsed(), vec![path.clone()]);
+        assert!(pending.take_unparsed().is_empty());
+
+        assert_eq!(
+            pending.take_due(start + Duration::from_millis(200)),
+            vec![(path, uri)]
+        );
+        assert_eq!(pending.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_remove_forgets_pending_work() {
+        let mut pending = PendingChanges::default();
+        let path = PathBuf::from("/test/main.beancount");
+        let uri = lsp_types::Uri::from_str("file:///test/main.beancount").unwrap();
+        pending.record(path.clone(), uri, Instant::now());
+
+        pending.remove(&path);
+        assert!(pending.take_unparsed().is_empty());
+        assert_eq!(pending.next_deadline(), None);
+    }
+}
@@ -1,21 +1,43 @@
+/// Provider definitions for the `beancount.runQuery` command.
+pub mod bean_query;
+/// Provider definitions for LSP `textDocument/codeAction`.
+pub mod code_action;
+/// Provider definitions for LSP `textDocument/codeLens` and `beancount.payeeHistory`.
+pub mod code_lens;
 pub mod completion;
 /// Provider definitions for LSP `textDocument/definition`.
 pub mod definition;
 /// Provider definitions for LSP `textDocument/publishDiagnostics`.
 pub mod diagnostics;
+/// Provider definitions for LSP `textDocument/documentHighlight`.
+pub mod document_highlight;
 /// Provider definitions for LSP `textDocument/documentSymbol`.
 pub mod document_symbol;
+/// Provider definitions for the Fava link commands.
+pub mod fava;
+/// Provider definitions for LSP `workspace/didCreateFiles`.
+pub mod file_operations;
 /// Provider definitions for LSP `textDocument/foldingRange`.
 pub mod folding_range;
 pub mod formatting;
 /// Provider definitions for LSP `textDocument/hover`.
 pub mod hover;
+/// Provider definitions for the `beancount.importTransactions` command.
+pub mod import;
 /// Provider definitions for LSP `textDocument/inlayHint`.
 pub mod inlay_hints;
+/// Provider definitions for the `beancount/monthlyTotals` request.
+pub mod monthly_totals;
+/// Provider definitions for the `beancount.updatePrices` command.
+pub mod prices;
+/// Provider definitions for the `beancount.query` command.
+pub mod query;
 /// Provider definitions for LSP `textDocument/references` and `textDocument/rename`.
 pub mod references;
 /// Provider definitions for LSP semantic tokens (syntax highlighting).
 pub mod semantic_tokens;
+/// Provider definitions for the `beancount/status` request.
+pub mod status;
 /// Provider definitions for LSP text document lifecycle events.
 pub mod text_document;
 /// Utilities for cross-platform URI handling.
@@ -0,0 +1,126 @@
+//! Runs queries with the external `bean-query` tool
+//!
+//! Unlike `beancount.query`, this supports the full query language, at the cost of
+//! needing beancount installed. `bean-query` is asked for CSV output, which is parsed
+//! into columns and rows and rendered as a markdown table for clients that only want
+//! to show the result.
+
+use crate::checkers::resolve_bean_query_cmd;
+use crate::lsp_ext::RunQueryResult;
+use crate::utils::parse_csv;
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+use std::process::Command;
+
+/// Provider function for the `beancount.runQuery` command.
+pub(crate) fn run_query(
+    bean_query_cmd: Option<&std::path::PathBuf>,
+    root_dir: &Path,
+    journal: &Path,
+    query: &str,
+) -> Result<RunQueryResult> {
+    let cmd = resolve_bean_query_cmd(bean_query_cmd, root_dir)
+        .context("bean-query not found; install beancount or set bean_query_cmd")?;
+    tracing::debug!("Running {} on {}", cmd.display(), journal.display());
+
+    let output = Command::new(&cmd)
+        .arg("-f")
+        .arg("csv")
+        .arg(journal)
+        .arg(query)
+        .output()
+        .with_context(|| format!("failed to run {}", cmd.display()))?;
+    if !output.status.success() {
+        bail!(
+            "bean-query failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let mut records = parse_csv(&String::from_utf8_lossy(&output.stdout), ',').into_iter();
+    let columns = records.next().unwrap_or_default();
+    let rows: Vec<Vec<String>> = records.collect();
+    let markdown = to_markdown(&columns, &rows);
+    Ok(RunQueryResult {
+        columns,
+        rows,
+        markdown,
+    })
+}
+
+fn to_markdown(columns: &[String], rows: &[Vec<String>]) -> String {
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .map(|cell| cell.trim().replace('|', "\\|").replace('\n', " "))
+            .collect();
+        format!("| {} |\n", cells.join(" | "))
+    };
+    if columns.is_empty() {
+        return String::new();
+    }
+    let mut markdown = line(columns);
+    markdown.push_str(&format!("|{}\n", " --- |".repeat(columns.len())));
+    for row in rows {
+        markdown.push_str(&line(row));
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_markdown() {
+        let columns = vec!["account".to_string(), "narration".to_string()];
+        let rows = vec![vec!["Assets:Cash".to_string(), "a | b".to_string()]];
+        assert_eq!(
+            to_markdown(&columns, &rows),
+            "| account | narration |\n| --- | --- |\n| Assets:Cash | a \\| b |\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_query_with_mock_bean_query() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("bean-query");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\nprintf 'journal,query\\n%s,\"%s\"\\n' \"$(basename \"$3\")\" \"$4\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let result = run_query(
+            Some(&script),
+            dir.path(),
+            &dir.path().join("main.beancount"),
+            "SELECT account, sum(position)",
+        )
+        .unwrap();
+        assert_eq!(result.columns, ["journal", "query"]);
+        assert_eq!(
+            result.rows,
+            [["main.beancount", "SELECT account, sum(position)"]]
+        );
+        assert!(result.markdown.starts_with("| journal | query |\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_query_reports_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = run_query(
+            Some(&std::path::PathBuf::from("/bin/false")),
+            dir.path(),
+            &dir.path().join("main.beancount"),
+            "SELECT",
+        )
+        .unwrap_err();
+        assert!(error.to_string().starts_with("bean-query failed"));
+    }
+}
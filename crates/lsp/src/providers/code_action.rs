@@ -0,0 +1,232 @@
+//! Quick fixes for the server's own diagnostics
+//!
+//! An include of a file that does not exist gets an action creating the file, so a
+//! journal can be split by writing the include first. An unbalanced `pushtag` or
+//! `poptag` gets an action inserting its missing counterpart.
+
+use crate::providers::diagnostics::{MISSING_INCLUDE, UNBALANCED_TAG, unbalanced_tags};
+use crate::server::LspServerStateSnapshot;
+use crate::utils::file_path_to_uri;
+use anyhow::Result;
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CodeActionResponse,
+    CreateFile, CreateFileOptions, DocumentChangeOperation, DocumentChanges,
+    OptionalVersionedTextDocumentIdentifier, Position, Range, ResourceOp, TextDocumentEdit,
+    TextEdit, WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// First line of a file created for an include.
+const HEADER: &str = ";; -*- mode: beancount -*-\n";
+
+/// Provider function for `textDocument/codeAction`.
+pub(crate) fn code_action(
+    snapshot: &LspServerStateSnapshot,
+    params: CodeActionParams,
+) -> Result<Option<CodeActionResponse>> {
+    let actions: CodeActionResponse = params
+        .context
+        .diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let Some(lsp_types::NumberOrString::String(code)) = &diagnostic.code else {
+                return None;
+            };
+            let action = match code.as_str() {
+                MISSING_INCLUDE => {
+                    let path = diagnostic.data.as_ref()?.get("path")?.as_str()?;
+                    create_include_target(Path::new(path), diagnostic.clone())?
+                }
+                UNBALANCED_TAG => {
+                    let uri = params.text_document.uri.clone();
+                    let edit = tag_counterpart(snapshot, &uri, diagnostic)?;
+                    CodeAction {
+                        title: format!("Insert {}", edit.new_text.trim()),
+                        kind: Some(CodeActionKind::QUICKFIX),
+                        diagnostics: Some(vec![diagnostic.clone()]),
+                        edit: Some(snapshot.workspace_edit(HashMap::from([(uri, vec![edit])]))),
+                        is_preferred: Some(true),
+                        ..Default::default()
+                    }
+                }
+                _ => return None,
+            };
+            Some(CodeActionOrCommand::CodeAction(action))
+        })
+        .collect();
+    Ok((!actions.is_empty()).then_some(actions))
+}
+
+/// The edit inserting the counterpart of the tag `diagnostic` reports, computed from
+/// the current document. The edit in the diagnostic's data was made for the version
+/// the diagnostic was published for, so it is not used; a diagnostic that no longer
+/// matches the document gets no edit until it is published again.
+fn tag_counterpart(
+    snapshot: &LspServerStateSnapshot,
+    uri: &lsp_types::Uri,
+    diagnostic: &lsp_types::Diagnostic,
+) -> Option<TextEdit> {
+    let (tree, doc) = snapshot.tree_and_document_for_uri(uri).ok()?;
+    let current = unbalanced_tags(tree, &doc.content)
+        .into_iter()
+        .find(|current| {
+            current.range == diagnostic.range && current.message == diagnostic.message
+        })?;
+    serde_json::from_value(current.data?.get("edit")?.clone()).ok()
+}
+
+/// Creates the file at `path` with a header.
+fn create_include_target(path: &Path, diagnostic: lsp_types::Diagnostic) -> Option<CodeAction> {
+    let uri = file_path_to_uri(path).ok()?;
+    let name = path.file_name()?.to_string_lossy();
+    let start = Position::new(0, 0);
+    Some(CodeAction {
+        title: format!("Create {name}"),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic]),
+        edit: Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(vec![
+                DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+                    uri: uri.clone(),
+                    options: Some(CreateFileOptions {
+                        overwrite: Some(false),
+                        ignore_if_exists: Some(true),
+                    }),
+                    annotation_id: None,
+                })),
+                DocumentChangeOperation::Edit(TextDocumentEdit {
+                    text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+                    edits: vec![lsp_types::OneOf::Left(TextEdit {
+                        range: Range::new(start, start),
+                        new_text: HEADER.to_string(),
+                    })],
+                }),
+            ])),
+            ..Default::default()
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn snapshot() -> LspServerStateSnapshot {
+        LspServerStateSnapshot::for_test(Config::new(std::env::temp_dir()), &[])
+    }
+
+    fn params(diagnostics: Vec<lsp_types::Diagnostic>) -> CodeActionParams {
+        CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(
+                "file:///ledger/main.beancount".parse().unwrap(),
+            ),
+            range: Range::default(),
+            context: lsp_types::CodeActionContext {
+                diagnostics,
+                ..Default::default()
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }
+    }
+
+    fn missing_include(data: Option<serde_json::Value>) -> lsp_types::Diagnostic {
+        lsp_types::Diagnostic {
+            code: Some(lsp_types::NumberOrString::String(
+                MISSING_INCLUDE.to_string(),
+            )),
+            data,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_creates_missing_include_target() {
+        let path = std::env::temp_dir().join("2024.beancount");
+        let diagnostic = missing_include(Some(serde_json::json!({ "path": path })));
+        let actions = code_action(&snapshot(), params(vec![diagnostic]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(actions.len(), 1);
+
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+        assert_eq!(action.title, "Create 2024.beancount");
+        assert_eq!(action.kind, Some(CodeActionKind::QUICKFIX));
+        let Some(DocumentChanges::Operations(operations)) = action
+            .edit
+            .as_ref()
+            .and_then(|edit| edit.document_changes.as_ref())
+        else {
+            panic!("expected document change operations");
+        };
+        let uri = file_path_to_uri(&path).unwrap();
+        assert!(matches!(
+            &operations[0],
+            DocumentChangeOperation::Op(ResourceOp::Create(create)) if create.uri == uri
+        ));
+        assert!(matches!(
+            &operations[1],
+            DocumentChangeOperation::Edit(edit) if edit.text_document.uri == uri
+        ));
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_inserts_missing_tag_counterpart() {
+        let content = "2024-01-01 open Assets:Cash\npoptag #trip\n";
+        let snapshot = LspServerStateSnapshot::for_test(
+            Config::new(std::env::temp_dir()),
+            &[(Path::new("/ledger/main.beancount"), content)],
+        );
+        let (tree, doc) = snapshot
+            .tree_and_document_for_uri(&"file:///ledger/main.beancount".parse().unwrap())
+            .unwrap();
+        let diagnostic = unbalanced_tags(tree, &doc.content).remove(0);
+        let actions = code_action(&snapshot, params(vec![diagnostic.clone()]))
+            .unwrap()
+            .unwrap();
+
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+        assert_eq!(action.title, "Insert pushtag #trip");
+        let edits = crate::utils::text_edits(action.edit.as_ref().unwrap());
+        let uri = "file:///ledger/main.beancount".parse().unwrap();
+        let start = Position::new(0, 0);
+        assert_eq!(
+            edits.get(&uri),
+            Some(&vec![TextEdit::new(
+                Range::new(start, start),
+                "pushtag #trip\n".to_string()
+            )])
+        );
+
+        // A diagnostic published for an older version no longer applies
+        let mut stale = diagnostic;
+        stale.range.start.line += 1;
+        stale.range.end.line += 1;
+        assert!(
+            code_action(&snapshot, params(vec![stale]))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_no_action_for_glob_patterns_or_other_diagnostics() {
+        let other = lsp_types::Diagnostic {
+            code: Some(lsp_types::NumberOrString::String(
+                "flagged-entry".to_string(),
+            )),
+            ..Default::default()
+        };
+        let actions = code_action(&snapshot(), params(vec![missing_include(None), other])).unwrap();
+        assert!(actions.is_none());
+    }
+}
@@ -0,0 +1,410 @@
+//! Code lenses counting a payee's earlier transactions
+//!
+//! A transaction whose payee was used before gets a lens like "Grocer: 27 previous
+//! transactions" above it. Invoking it runs `beancount.payeeHistory`, which lists all
+//! transactions of the payee in the journal, newest first, for the client to show in a
+//! peek window or quick pick.
+//!
+//! With `section_code_lens`, a comment header like `;; March 2024` gets a lens totalling
+//! the transactions up to the next header, like "4 transactions: 1250.00 USD".
+
+use crate::beancount_data::BeancountData;
+use crate::ledger::{EntryKind, Inventory, book_postings};
+use crate::ledger_options::LedgerOptions;
+use crate::lsp_ext::{PAYEE_HISTORY_COMMAND, PayeeHistory, PayeeTransaction};
+use crate::server::LspServerStateSnapshot;
+use crate::utils::{ToFilePath, file_path_to_uri};
+use anyhow::Result;
+use lsp_types::{CodeLens, CodeLensParams, Command, Location, Position, Range};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A transaction of a payee, ordered by date and then position.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Occurrence {
+    date: String,
+    file: PathBuf,
+    line: u32,
+    narration: Option<String>,
+}
+
+/// Provider function for `textDocument/codeLens`.
+pub(crate) fn code_lens(
+    snapshot: LspServerStateSnapshot,
+    params: CodeLensParams,
+) -> Result<Option<Vec<CodeLens>>> {
+    let config = &snapshot.config;
+    if !config.features.code_lens || !(config.payee_code_lens || config.section_code_lens) {
+        return Ok(None);
+    }
+    let Ok(file) = params.text_document.uri.to_file_path() else {
+        return Ok(None);
+    };
+    let Some(data) = snapshot.beancount_data.get(&file) else {
+        return Ok(None);
+    };
+
+    let mut lenses = Vec::new();
+    if config.section_code_lens
+        && let Some((_, content)) = snapshot.tree_and_text(&file)
+    {
+        let options = snapshot.ledger_options_for(&file);
+        lenses.extend(section_lenses(&content, data, &options));
+    }
+    if !config.payee_code_lens {
+        return Ok(Some(lenses));
+    }
+
+    // Only the payees of this file get lenses, so the others are not collected
+    let entries = data.get_ledger_entries();
+    let payees: HashSet<&str> = entries
+        .iter()
+        .filter_map(|entry| match &entry.kind {
+            EntryKind::Transaction {
+                payee: Some(payee), ..
+            } => Some(payee.as_str()),
+            _ => None,
+        })
+        .collect();
+    let by_payee = transactions_by_payee(
+        &snapshot.journal_files(snapshot.journal_for(&file).as_deref()),
+        &payees,
+    );
+    for entry in entries.iter() {
+        let EntryKind::Transaction {
+            payee: Some(payee), ..
+        } = &entry.kind
+        else {
+            continue;
+        };
+        let Some(occurrences) = by_payee.get(payee) else {
+            continue;
+        };
+        let previous = occurrences.partition_point(|occurrence| {
+            (&occurrence.date, &occurrence.file, occurrence.line) < (&entry.date, &file, entry.line)
+        });
+        if previous == 0 {
+            continue;
+        }
+        let plural = if previous == 1 { "" } else { "s" };
+        let start = Position::new(entry.line, 0);
+        lenses.push(CodeLens {
+            range: Range::new(start, start),
+            command: Some(Command {
+                title: format!("{payee}: {previous} previous transaction{plural}"),
+                command: PAYEE_HISTORY_COMMAND.to_string(),
+                arguments: Some(vec![
+                    serde_json::Value::String(payee.clone()),
+                    serde_json::to_value(&params.text_document.uri)?,
+                ]),
+            }),
+            data: None,
+        });
+    }
+    Ok(Some(lenses))
+}
+
+/// Lenses on `;;` comment headers with the count and total of the transactions up to
+/// the next header. A transaction's total is what it moves, the sum of its positive
+/// amounts, in the operating currencies if the ledger declares any.
+fn section_lenses(
+    content: &ropey::Rope,
+    data: &BeancountData,
+    options: &LedgerOptions,
+) -> Vec<CodeLens> {
+    let headers: Vec<usize> = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            let line = line.to_string();
+            line.starts_with(";;") && !line.trim_start_matches(';').trim().is_empty()
+        })
+        .map(|(index, _)| index)
+        .collect();
+
+    // The transaction count and totals of the section below each header
+    let mut sections: Vec<(usize, Inventory)> = vec![(0, Inventory::new()); headers.len()];
+    for entry in data.get_ledger_entries().iter() {
+        let EntryKind::Transaction { postings, .. } = &entry.kind else {
+            continue;
+        };
+        let Some(section) = headers
+            .partition_point(|&header| header < entry.line as usize)
+            .checked_sub(1)
+        else {
+            continue;
+        };
+        let (count, totals) = &mut sections[section];
+        *count += 1;
+        for (_, _, amount) in book_postings(postings) {
+            let operating = options.operating_currencies.is_empty()
+                || options.operating_currencies.contains(&amount.currency);
+            if operating && amount.value.is_sign_positive() {
+                let total = totals.entry(amount.currency).or_default();
+                if let Some(sum) = total.checked_add(amount.value) {
+                    *total = sum;
+                }
+            }
+        }
+    }
+
+    let mut lenses = Vec::new();
+    for (&header, (count, totals)) in headers.iter().zip(sections) {
+        if count == 0 {
+            continue;
+        }
+
+        let plural = if count == 1 { "" } else { "s" };
+        let mut title = format!("{count} transaction{plural}");
+        if !totals.is_empty() {
+            let totals: Vec<String> = totals
+                .iter()
+                .map(|(currency, value)| format!("{value} {currency}"))
+                .collect();
+            title.push_str(&format!(": {}", totals.join(", ")));
+        }
+        let start = Position::new(header as u32, 0);
+        lenses.push(CodeLens {
+            range: Range::new(start, start),
+            command: Some(Command {
+                title,
+                command: String::new(),
+                arguments: None,
+            }),
+            data: None,
+        });
+    }
+    lenses
+}
+
+/// Provider function for the `beancount.payeeHistory` command.
+pub(crate) fn payee_history(
+    snapshot: &LspServerStateSnapshot,
+    payee: &str,
+    journal: Option<&Path>,
+) -> Result<PayeeHistory> {
+    let mut occurrences =
+        transactions_by_payee(&snapshot.journal_files(journal), &HashSet::from([payee]))
+            .remove(payee)
+            .unwrap_or_default();
+    occurrences.reverse();
+
+    let transactions = occurrences
+        .into_iter()
+        .filter_map(|occurrence| {
+            let uri = file_path_to_uri(&occurrence.file).ok()?;
+            let start = Position::new(occurrence.line, 0);
+            Some(PayeeTransaction {
+                date: occurrence.date,
+                narration: occurrence.narration,
+                location: Location::new(uri, Range::new(start, start)),
+            })
+        })
+        .collect();
+    Ok(PayeeHistory {
+        payee: payee.to_string(),
+        transactions,
+    })
+}
+
+/// The transactions of each of `payees` in `files`, sorted.
+fn transactions_by_payee(
+    files: &HashMap<PathBuf, Arc<BeancountData>>,
+    payees: &HashSet<&str>,
+) -> HashMap<String, Vec<Occurrence>> {
+    let mut by_payee: HashMap<String, Vec<Occurrence>> = HashMap::new();
+    for (file, data) in files {
+        for entry in data.get_ledger_entries().iter() {
+            if let EntryKind::Transaction {
+                payee: Some(payee),
+                narration,
+                ..
+            } = &entry.kind
+                && payees.contains(payee.as_str())
+            {
+                by_payee.entry(payee.clone()).or_default().push(Occurrence {
+                    date: entry.date.clone(),
+                    file: file.clone(),
+                    line: entry.line,
+                    narration: narration.clone(),
+                });
+            }
+        }
+    }
+    for occurrences in by_payee.values_mut() {
+        occurrences.sort();
+    }
+    by_payee
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn snapshot(files: &[(&str, &str)]) -> (LspServerStateSnapshot, Vec<PathBuf>) {
+        let dir = std::env::temp_dir();
+        let paths: Vec<PathBuf> = files.iter().map(|(name, _)| dir.join(name)).collect();
+        let mut config = Config::new(dir);
+        config.journal_root = Some(paths[0].clone());
+        let files: Vec<(&Path, &str)> = paths
+            .iter()
+            .zip(files)
+            .map(|(path, (_, content))| (path.as_path(), *content))
+            .collect();
+        (LspServerStateSnapshot::for_test(config, &files), paths)
+    }
+
+    const MAIN: &str = r#"include "2023.beancount"
+
+2024-01-05 * "Grocer" "Weekly shopping"
+  Expenses:Food  25.00 USD
+  Assets:Cash
+
+2024-01-06 * "Bakery" "Bread"
+  Expenses:Food  3.00 USD
+  Assets:Cash
+
+2024-01-12 * "Grocer" "Weekly shopping"
+  Expenses:Food  30.00 USD
+  Assets:Cash
+"#;
+
+    const OLD: &str = r#"2023-12-29 * "Grocer" "New year's eve"
+  Expenses:Food  80.00 USD
+  Assets:Cash
+"#;
+
+    fn lenses(snapshot: LspServerStateSnapshot, path: &Path) -> Option<Vec<(u32, String)>> {
+        let params = CodeLensParams {
+            text_document: lsp_types::TextDocumentIdentifier::new(file_path_to_uri(path).unwrap()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        code_lens(snapshot, params).unwrap().map(|lenses| {
+            lenses
+                .into_iter()
+                .map(|lens| (lens.range.start.line, lens.command.unwrap().title))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_lenses_count_earlier_transactions_across_files() {
+        let (snapshot, paths) = snapshot(&[("main.beancount", MAIN), ("2023.beancount", OLD)]);
+        assert_eq!(
+            lenses(snapshot, &paths[0]),
+            Some(vec![
+                (2, "Grocer: 1 previous transaction".to_string()),
+                (10, "Grocer: 2 previous transactions".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_section_lenses_total_transactions_until_next_header() {
+        let content = r#"option "operating_currency" "USD"
+
+;; January 2024
+
+2024-01-05 * "Grocer" "Weekly shopping"
+  Expenses:Food  25.00 USD
+  Assets:Cash
+
+2024-01-06 * "Bakery" "Bread"
+  Expenses:Food  3.00 EUR
+  Assets:Cash
+
+2024-01-12 * "Grocer" "Weekly shopping"
+  Expenses:Food  30.00 USD
+  Assets:Cash
+
+;;;;;;;;
+
+;; February 2024
+2024-02-01 open Assets:Bank
+
+;; March 2024
+"#;
+        let (mut snapshot, paths) = snapshot(&[("sections.beancount", content)]);
+        snapshot.config.payee_code_lens = false;
+        snapshot.config.section_code_lens = true;
+        assert_eq!(
+            lenses(snapshot, &paths[0]),
+            Some(vec![(2, "3 transactions: 55.00 USD".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_section_totals_skip_overflowing_amounts() {
+        let content = r#";; Large
+2024-01-05 * "Bank"
+  Assets:Bank  60000000000000000000000000000 USD
+  Equity:Opening
+
+2024-01-06 * "Bank"
+  Assets:Bank  60000000000000000000000000000 USD
+  Equity:Opening
+"#;
+        let (mut snapshot, paths) = snapshot(&[("sections.beancount", content)]);
+        snapshot.config.payee_code_lens = false;
+        snapshot.config.section_code_lens = true;
+        assert_eq!(
+            lenses(snapshot, &paths[0]),
+            Some(vec![(
+                0,
+                "2 transactions: 60000000000000000000000000000 USD".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn test_only_requested_payees_are_collected() {
+        let (snapshot, paths) = snapshot(&[("main.beancount", MAIN), ("2023.beancount", OLD)]);
+        let by_payee = transactions_by_payee(
+            &snapshot.journal_files(Some(&paths[0])),
+            &HashSet::from(["Bakery"]),
+        );
+        assert_eq!(by_payee.keys().collect::<Vec<_>>(), ["Bakery"]);
+    }
+
+    #[test]
+    fn test_lenses_can_be_disabled() {
+        let (mut snapshot, paths) = snapshot(&[("main.beancount", MAIN)]);
+        snapshot.config.payee_code_lens = false;
+        assert_eq!(lenses(snapshot, &paths[0]), None);
+    }
+
+    #[test]
+    fn test_payee_history_is_newest_first() {
+        let (snapshot, paths) = snapshot(&[("main.beancount", MAIN), ("2023.beancount", OLD)]);
+        let history = payee_history(&snapshot, "Grocer", Some(&paths[0])).unwrap();
+        let transactions: Vec<_> = history
+            .transactions
+            .iter()
+            .map(|t| {
+                (
+                    t.date.as_str(),
+                    t.narration.as_deref(),
+                    t.location.uri.to_file_path().unwrap(),
+                    t.location.range.start.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            transactions,
+            [
+                ("2024-01-12", Some("Weekly shopping"), paths[0].clone(), 10),
+                ("2024-01-05", Some("Weekly shopping"), paths[0].clone(), 2),
+                ("2023-12-29", Some("New year's eve"), paths[1].clone(), 0),
+            ]
+        );
+        assert!(
+            payee_history(&snapshot, "Nobody", Some(&paths[0]))
+                .unwrap()
+                .transactions
+                .is_empty()
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use crate::beancount_data::BeancountData;
+use crate::config::Snippet;
+use crate::index::{SymbolKind, WorkspaceIndex};
+use crate::ledger::{EntryKind, book_postings};
+use crate::ledger_options::LedgerOptions;
 use crate::server::LspServerStateSnapshot;
-use crate::treesitter_utils::lsp_position_to_tree_sitter_point;
+use crate::treesitter_utils::{lsp_position_to_tree_sitter_point, position_encoding};
+use crate::utils::ToFilePath;
 use anyhow::Result;
 use chrono::Datelike;
 use lsp_types::{CompletionItem, CompletionItemKind, Position, Range, TextEdit};
@@ -8,7 +13,8 @@ use nucleo::{
     Config, Matcher, Utf32Str,
     pattern::{CaseMatching, Normalization, Pattern},
 };
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::debug;
@@ -102,7 +108,184 @@ pub(crate) fn completion(
     debug!("Determined context: {:?}", context);
 
     // Generate completions based on context
-    generate_completions(&snapshot.beancount_data, &context, content, cursor.position)
+    let options = match cursor.text_document.uri.to_file_path() {
+        Ok(file) => snapshot.ledger_options_for(&file),
+        Err(()) => snapshot.ledger_options(),
+    };
+    let mut items = generate_completions(
+        &snapshot.index,
+        &options,
+        &snapshot.config.snippets,
+        snapshot.config.account_completion_by_component,
+        &context,
+        content,
+        cursor.position,
+    )?;
+
+    // Let `completionItem/resolve` look up where accounts and payees were used
+    let usage: Option<fn(String) -> Usage> = match &context {
+        CompletionContext::PostingAccount { .. }
+        | CompletionContext::OpenAccount { .. }
+        | CompletionContext::BalanceAccount { .. } => Some(Usage::Account),
+        CompletionContext::AfterFlag | CompletionContext::InsideString { is_payee: true, .. } => {
+            Some(Usage::Payee)
+        }
+        _ => None,
+    };
+    if !snapshot.config.features.commit_characters {
+        for item in items.iter_mut().flatten() {
+            item.commit_characters = None;
+        }
+    }
+
+    if let (Some(usage), Some(items)) = (usage, items.as_mut()) {
+        for item in items {
+            let data = ResolveData {
+                uri: cursor.text_document.uri.clone(),
+                usage: usage(item.label.clone()),
+            };
+            item.data = serde_json::to_value(data).ok();
+        }
+    }
+    Ok(items)
+}
+
+/// What a completion item names, kept in its `data` until it is resolved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Usage {
+    /// An account, or with a trailing `:` an account component and the accounts below it
+    Account(String),
+    Payee(String),
+}
+
+/// Whether `account` is the account named by a completion label, see [`Usage::Account`].
+fn is_named_account(account: &str, name: &str) -> bool {
+    match name.strip_suffix(':') {
+        Some(parent) => account == parent || account.starts_with(name),
+        None => account == name,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ResolveData {
+    uri: lsp_types::Uri,
+    usage: Usage,
+}
+
+/// A transaction using an account or payee.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct RecentUse {
+    date: String,
+    file: PathBuf,
+    line: u32,
+    amount: Option<String>,
+    account: Option<String>,
+}
+
+/// Provider function for `completionItem/resolve`.
+///
+/// Documents account and payee items with the latest transactions using them.
+pub(crate) fn resolve(
+    snapshot: LspServerStateSnapshot,
+    mut item: CompletionItem,
+) -> Result<CompletionItem> {
+    let Some(data) = item
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value::<ResolveData>(data).ok())
+    else {
+        return Ok(item);
+    };
+    let Ok(file) = data.uri.to_file_path() else {
+        return Ok(item);
+    };
+    let files = snapshot.journal_files(snapshot.journal_for(&file).as_deref());
+    let uses = recent_uses(&files, &data.usage, 3);
+    if uses.is_empty() {
+        return Ok(item);
+    }
+
+    let mut value = "**Recent transactions**\n\n".to_string();
+    for recent in uses {
+        let details: Vec<String> = [Some(recent.date), recent.amount, recent.account]
+            .into_iter()
+            .flatten()
+            .collect();
+        value.push_str(&format!("- {}\n", details.join(" · ")));
+    }
+    item.documentation = Some(lsp_types::Documentation::MarkupContent(
+        lsp_types::MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value,
+        },
+    ));
+    Ok(item)
+}
+
+/// The latest `limit` transactions using `usage`, newest first.
+///
+/// Each shows the amount of the account's posting and the account on the other
+/// side, or for a payee the first posting's amount and account.
+fn recent_uses(
+    files: &HashMap<PathBuf, Arc<BeancountData>>,
+    usage: &Usage,
+    limit: usize,
+) -> Vec<RecentUse> {
+    let mut uses = Vec::new();
+    for (file, data) in files {
+        for entry in data.get_ledger_entries().iter() {
+            let EntryKind::Transaction {
+                payee, postings, ..
+            } = &entry.kind
+            else {
+                continue;
+            };
+            let booked = book_postings(postings);
+            let (amount, account) = match usage {
+                Usage::Account(name) => {
+                    if !postings
+                        .iter()
+                        .any(|posting| is_named_account(&posting.account, name))
+                    {
+                        continue;
+                    }
+                    let amount = booked
+                        .iter()
+                        .find(|(account, ..)| is_named_account(account, name));
+                    let counter = postings
+                        .iter()
+                        .find(|posting| !is_named_account(&posting.account, name));
+                    (
+                        amount.map(|(_, _, amount)| amount),
+                        counter.map(|posting| posting.account.clone()),
+                    )
+                }
+                Usage::Payee(name) => {
+                    if payee.as_ref() != Some(name) {
+                        continue;
+                    }
+                    let first = booked.first();
+                    (
+                        first.map(|(_, _, amount)| amount),
+                        first
+                            .map(|(account, ..)| account.clone())
+                            .or_else(|| postings.first().map(|p| p.account.clone())),
+                    )
+                }
+            };
+            uses.push(RecentUse {
+                date: entry.date.clone(),
+                file: file.clone(),
+                line: entry.line,
+                amount: amount.map(|amount| format!("{} {}", amount.value, amount.currency)),
+                account,
+            });
+        }
+    }
+    uses.sort_by(|a, b| b.cmp(a));
+    uses.truncate(limit);
+    uses
 }
 
 /// Determine completion context using left-context-aware traversal.
@@ -170,7 +353,7 @@ fn determine_completion_context(
         // Step out of ERROR and MISSING nodes
         while current_node.kind() == "ERROR" || current_node.is_missing() {
             let start_pos = current_node.start_position();
-            if content.line(start_pos.row).char(start_pos.column) == '"' {
+            if content.byte(content.line_to_byte(start_pos.row) + start_pos.column) == b'"' {
                 debug!("Found an ERROR node starting with '\"'. Assuming unterminated string.");
                 return analyze_string_context(content, cursor);
             }
@@ -481,8 +664,9 @@ fn analyze_string_node_context(
 
         let line = content.line(cursor.row).to_string();
         let prefix = extract_string_prefix(&line, cursor.column);
-        let has_opening = line.chars().take(cursor.column).any(|c| c == '"');
-        let has_closing = line.chars().skip(cursor.column).any(|c| c == '"');
+        let before_cursor = safe_substring_to_byte(&line, cursor.column);
+        let has_opening = before_cursor.contains('"');
+        let has_closing = line[before_cursor.len()..].contains('"');
 
         return CompletionContext::InsideString {
             prefix,
@@ -516,7 +700,7 @@ fn analyze_string_context(content: &ropey::Rope, cursor: Point) -> CompletionCon
     let is_payee = quote_count < 3;
 
     // Check for closing quote after cursor
-    let has_closing = line.chars().skip(cursor.column).any(|c| c == '"');
+    let has_closing = line[before_cursor.len()..].contains('"');
 
     CompletionContext::InsideString {
         prefix,
@@ -541,47 +725,34 @@ fn safe_substring_to_byte(s: &str, byte_offset: usize) -> &str {
     &s[..idx]
 }
 
-/// Extract account prefix from line text up to cursor position
+/// Extract account prefix from line text up to the cursor's byte column
 fn extract_account_prefix(line: &str, cursor_col: usize) -> String {
-    let chars: Vec<char> = line.chars().collect();
-    if cursor_col == 0 || cursor_col > chars.len() {
+    if cursor_col == 0 || cursor_col > line.len() {
         return String::new();
     }
 
-    // Find start of account (after whitespace or start of line)
-    let mut start = 0;
-    for i in (0..cursor_col).rev() {
-        let c = chars[i];
-        if c.is_whitespace() {
-            start = i + 1;
-            break;
-        }
-    }
-
-    // Extract prefix
-    let end = cursor_col.min(chars.len());
-    chars[start..end].iter().collect()
+    // The account starts after the last whitespace (or at the start of the line)
+    let before_cursor = safe_substring_to_byte(line, cursor_col);
+    before_cursor
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or_default()
+        .to_string()
 }
 
-/// Extract string prefix from line text up to cursor position
+/// Extract string prefix from line text up to the cursor's byte column
 fn extract_string_prefix(line: &str, cursor_col: usize) -> String {
-    let chars: Vec<char> = line.chars().collect();
-    if cursor_col == 0 || cursor_col > chars.len() {
+    if cursor_col == 0 || cursor_col > line.len() {
         return String::new();
     }
 
-    // Find start of string content (after quote)
-    let mut start = 0;
-    for i in (0..cursor_col).rev() {
-        if chars[i] == '"' {
-            start = i + 1;
-            break;
-        }
-    }
-
-    // Extract prefix, but not if we are at the opening quote
-    let end = cursor_col.min(chars.len());
-    chars[start..end].iter().collect()
+    // The string content starts after the last quote
+    let before_cursor = safe_substring_to_byte(line, cursor_col);
+    before_cursor
+        .rsplit('"')
+        .next()
+        .unwrap_or_default()
+        .to_string()
 }
 
 fn extract_tag_prefix(line: &str, cursor_col: usize) -> Option<String> {
@@ -628,7 +799,10 @@ fn extract_link_prefix(line: &str, cursor_col: usize) -> Option<String> {
 
 /// Generate completions based on context with LSP 3.17 InsertReplaceEdit support
 fn generate_completions(
-    data: &HashMap<PathBuf, Arc<BeancountData>>,
+    index: &WorkspaceIndex,
+    options: &LedgerOptions,
+    snippets: &[Snippet],
+    by_component: bool,
     context: &CompletionContext,
     content: &ropey::Rope,
     position: Position,
@@ -637,38 +811,60 @@ fn generate_completions(
         CompletionContext::DocumentRoot => {
             let mut items = complete_date(content, position)?;
             items.extend(complete_directive_keywords()?);
+            items.extend(complete_snippets(snippets, content, position));
             Ok(Some(items))
         }
 
         CompletionContext::AfterDate => Ok(Some(complete_directive_keywords()?)),
 
         CompletionContext::AfterFlag => {
-            Ok(Some(complete_payee(data, "", content, position, false)?))
+            Ok(Some(complete_payee(index, "", content, position, false)?))
         }
 
         CompletionContext::AfterPayee => Ok(Some(complete_narration(
-            data, "", content, position, false,
+            index, "", content, position, false,
         )?)),
 
-        CompletionContext::PostingAccount { prefix } => {
-            Ok(Some(complete_account(data, prefix, content, position)?))
-        }
+        CompletionContext::PostingAccount { prefix } => Ok(Some(complete_account(
+            index,
+            options,
+            prefix,
+            by_component,
+            content,
+            position,
+        )?)),
 
         CompletionContext::PostingAmount => Ok(Some(complete_amount()?)),
 
-        CompletionContext::PostingCurrency => Ok(Some(complete_currency(data, content, position)?)),
-
-        CompletionContext::OpenAccount { prefix } => {
-            Ok(Some(complete_account(data, prefix, content, position)?))
+        CompletionContext::PostingCurrency => {
+            Ok(Some(complete_currency(index, options, content, position)?))
         }
 
-        CompletionContext::OpenCurrency => Ok(Some(complete_currency(data, content, position)?)),
+        CompletionContext::OpenAccount { prefix } => Ok(Some(complete_account(
+            index,
+            options,
+            prefix,
+            by_component,
+            content,
+            position,
+        )?)),
 
-        CompletionContext::BalanceAccount { prefix } => {
-            Ok(Some(complete_account(data, prefix, content, position)?))
+        CompletionContext::OpenCurrency => {
+            Ok(Some(complete_currency(index, options, content, position)?))
         }
 
-        CompletionContext::PriceContext => Ok(Some(complete_currency(data, content, position)?)),
+        CompletionContext::BalanceAccount { prefix } => Ok(Some(complete_account(
+            index,
+            options,
+            prefix,
+            by_component,
+            content,
+            position,
+        )?)),
+
+        CompletionContext::PriceContext => {
+            Ok(Some(complete_currency(index, options, content, position)?))
+        }
 
         CompletionContext::InsideString {
             prefix,
@@ -678,7 +874,7 @@ fn generate_completions(
         } => {
             if *is_payee {
                 Ok(Some(complete_payee(
-                    data,
+                    index,
                     prefix,
                     content,
                     position,
@@ -686,7 +882,7 @@ fn generate_completions(
                 )?))
             } else {
                 Ok(Some(complete_narration(
-                    data,
+                    index,
                     prefix,
                     content,
                     position,
@@ -695,12 +891,12 @@ fn generate_completions(
             }
         }
 
-        CompletionContext::TagContext { prefix } => Ok(Some(complete_tag(data, prefix)?)),
+        CompletionContext::TagContext { prefix } => Ok(Some(complete_tag(index, prefix)?)),
 
-        CompletionContext::LinkContext { prefix } => Ok(Some(complete_link(data, prefix)?)),
+        CompletionContext::LinkContext { prefix } => Ok(Some(complete_link(index, prefix)?)),
 
         CompletionContext::ColonTriggeredAccount { parent_path } => {
-            Ok(Some(complete_subaccounts(data, parent_path)?))
+            Ok(Some(complete_subaccounts(index, parent_path)?))
         }
     }
 }
@@ -737,6 +933,37 @@ fn complete_directive_keywords() -> Result<Vec<CompletionItem>> {
         .collect())
 }
 
+/// Complete the snippets from the configuration
+fn complete_snippets(
+    snippets: &[Snippet],
+    content: &ropey::Rope,
+    position: Position,
+) -> Vec<CompletionItem> {
+    let line = content.line(position.line as usize).to_string();
+    let (_, replace_range) = calculate_word_ranges(&line, position);
+
+    snippets
+        .iter()
+        .map(|snippet| CompletionItem {
+            label: snippet.name.clone(),
+            kind: Some(CompletionItemKind::SNIPPET),
+            detail: Some(
+                snippet
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| "Snippet".to_string()),
+            ),
+            insert_text_format: Some(lsp_types::InsertTextFormat::SNIPPET),
+            text_edit: Some(lsp_types::CompletionTextEdit::Edit(TextEdit {
+                new_text: snippet.body.clone(),
+                range: replace_range,
+            })),
+            filter_text: Some(snippet.name.clone()),
+            ..Default::default()
+        })
+        .collect()
+}
+
 /// Complete date with current/previous/next month
 fn complete_date(content: &ropey::Rope, position: Position) -> Result<Vec<CompletionItem>> {
     let today = chrono::Local::now().naive_local().date();
@@ -791,20 +1018,37 @@ fn complete_date(content: &ropey::Rope, position: Position) -> Result<Vec<Comple
 
 /// Complete account names with fuzzy matching and InsertReplaceEdit
 fn complete_account(
-    data: &HashMap<PathBuf, Arc<BeancountData>>,
+    index: &WorkspaceIndex,
+    options: &LedgerOptions,
     prefix: &str,
+    by_component: bool,
     content: &ropey::Rope,
     position: Position,
 ) -> Result<Vec<CompletionItem>> {
-    let mut all_accounts: Vec<String> = Vec::new();
+    // The index keeps accounts unique and sorted
+    let mut all_accounts: Vec<String> = index
+        .symbols(SymbolKind::Account)
+        .map(|(account, _)| account.to_string())
+        .collect();
 
-    for bean_data in data.values() {
-        all_accounts.extend(bean_data.get_accounts().iter().cloned());
+    // Offer the ledger's root accounts even before anything is opened under them
+    for root in &options.root_accounts {
+        if !all_accounts
+            .iter()
+            .any(|account| account.split(':').next() == Some(root.as_str()))
+        {
+            all_accounts.push(root.clone());
+        }
     }
 
-    // Remove duplicates
-    all_accounts.sort();
-    all_accounts.dedup();
+    if by_component {
+        return Ok(complete_account_components(
+            &all_accounts,
+            prefix,
+            content,
+            position,
+        ));
+    }
 
     // Fuzzy search
     let matches = fuzzy_search_accounts(&all_accounts, prefix);
@@ -830,28 +1074,90 @@ fn complete_account(
         .collect())
 }
 
+/// Complete the next component of `prefix`, so `Expenses:Fo` offers `Expenses:Food:`.
+///
+/// Components with subaccounts are inserted with a trailing colon and ask the
+/// client to complete again, drilling down one level per accepted completion.
+fn complete_account_components(
+    accounts: &[String],
+    prefix: &str,
+    content: &ropey::Rope,
+    position: Position,
+) -> Vec<CompletionItem> {
+    let (parent, partial) = match prefix.rfind(':') {
+        Some(colon) => (&prefix[..=colon], &prefix[colon + 1..]),
+        None => ("", prefix),
+    };
+
+    // Each next component, and whether any account continues below it
+    let mut components: std::collections::BTreeMap<&str, bool> = Default::default();
+    for account in accounts {
+        let Some(rest) = account.strip_prefix(parent) else {
+            continue;
+        };
+        let (component, has_children) = match rest.split_once(':') {
+            Some((component, _)) => (component, true),
+            None => (rest, false),
+        };
+        if !component.is_empty() {
+            *components.entry(component).or_default() |= has_children;
+        }
+    }
+
+    let names: Vec<String> = components.keys().map(|c| c.to_string()).collect();
+    let matches = fuzzy_search_strings(&names, partial);
+
+    let line = content.line(position.line as usize).to_string();
+    let (insert_range, replace_range) = calculate_word_ranges(&line, position);
+
+    matches
+        .into_iter()
+        .take(50)
+        .map(|(component, score)| {
+            let has_children = components[component.as_str()];
+            let label = if has_children {
+                format!("{parent}{component}:")
+            } else {
+                format!("{parent}{component}")
+            };
+            let mut item = create_completion_with_insert_replace(
+                label,
+                "Beancount Account".to_string(),
+                CompletionItemKind::ENUM,
+                insert_range,
+                replace_range,
+                score,
+                vec![],
+            );
+            if has_children {
+                item.command = Some(lsp_types::Command {
+                    title: "Complete subaccounts".to_string(),
+                    command: "editor.action.triggerSuggest".to_string(),
+                    arguments: None,
+                });
+            }
+            item
+        })
+        .collect()
+}
+
 /// Complete sub-accounts when colon is typed (e.g., "Assets:" shows "Checking", "Savings")
-fn complete_subaccounts(
-    data: &HashMap<PathBuf, Arc<BeancountData>>,
-    parent_path: &str,
-) -> Result<Vec<CompletionItem>> {
+fn complete_subaccounts(index: &WorkspaceIndex, parent_path: &str) -> Result<Vec<CompletionItem>> {
     let mut subaccounts: Vec<String> = Vec::new();
 
-    for bean_data in data.values() {
-        for account in bean_data.get_accounts().iter() {
-            if let Some(suffix) = account.strip_prefix(parent_path) {
-                let suffix = suffix.strip_prefix(':').unwrap_or(suffix);
+    for (account, _) in index.symbols(SymbolKind::Account) {
+        if let Some(suffix) = account.strip_prefix(parent_path) {
+            let suffix = suffix.strip_prefix(':').unwrap_or(suffix);
 
-                // Extract only the next segment
-                let next_segment = if let Some(colon_pos) = suffix.find(':') {
-                    &suffix[..colon_pos]
-                } else {
-                    suffix
-                };
+            // Extract only the next segment
+            let next_segment = if let Some(colon_pos) = suffix.find(':') {
+                &suffix[..colon_pos]
+            } else {
+                suffix
+            };
 
-                if !next_segment.is_empty() {
-                    subaccounts.push(next_segment.to_string());
-                }
+            if !next_segment.is_empty() {
+                subaccounts.push(next_segment.to_string());
             }
         }
     }
@@ -875,17 +1181,16 @@ fn complete_subaccounts(
 
 /// Complete currency codes
 fn complete_currency(
-    data: &HashMap<PathBuf, Arc<BeancountData>>,
+    index: &WorkspaceIndex,
+    options: &LedgerOptions,
     content: &ropey::Rope,
     position: Position,
 ) -> Result<Vec<CompletionItem>> {
-    // Collect commodities from all beancount files
-    let mut commodities_set: HashSet<String> = HashSet::new();
-    for bean_data in data.values() {
-        for commodity in bean_data.get_commodities().iter() {
-            commodities_set.insert(commodity.clone());
-        }
-    }
+    // Commodities used anywhere in the workspace, sorted by the index
+    let commodities: Vec<String> = index
+        .symbols(SymbolKind::Commodity)
+        .map(|(commodity, _)| commodity.to_string())
+        .collect();
 
     // If no commodities found in the files, fall back to common currencies
     let fallback_currencies = vec![
@@ -893,28 +1198,37 @@ fn complete_currency(
         "HUF", "CNY", "INR", "BRL", "MXN", "ZAR", "RUB", "KRW", "SGD", "HKD", "THB",
     ];
 
-    let currencies: Vec<String> = if commodities_set.is_empty() {
+    let mut currencies: Vec<String> = if commodities.is_empty() {
         fallback_currencies.iter().map(|s| s.to_string()).collect()
     } else {
-        let mut commodities: Vec<String> = commodities_set.into_iter().collect();
-        commodities.sort();
         commodities
     };
 
+    // Operating currencies declared by the journal come first
+    currencies.retain(|currency| !options.operating_currencies.contains(currency));
+    let operating = options.operating_currencies.len();
+    currencies.splice(0..0, options.operating_currencies.iter().cloned());
+
     let line = content.line(position.line as usize).to_string();
     let (insert_range, replace_range) = calculate_word_ranges(&line, position);
 
     Ok(currencies
         .iter()
-        .map(|currency| {
+        .enumerate()
+        .map(|(i, currency)| {
+            let (detail, score) = if i < operating {
+                ("Operating currency", 2.0)
+            } else {
+                ("Currency", 1.0)
+            };
             create_completion_with_insert_replace(
                 currency.to_string(),
-                "Currency".to_string(),
+                detail.to_string(),
                 CompletionItemKind::UNIT,
                 insert_range,
                 replace_range,
-                1.0,
-                vec![],
+                score,
+                vec![" ".to_string()],
             )
         })
         .collect())
@@ -927,24 +1241,18 @@ fn complete_amount() -> Result<Vec<CompletionItem>> {
 
 /// Complete payee names
 fn complete_payee(
-    data: &HashMap<PathBuf, Arc<BeancountData>>,
+    index: &WorkspaceIndex,
     prefix: &str,
     content: &ropey::Rope,
     position: Position,
     has_closing_quote: bool,
 ) -> Result<Vec<CompletionItem>> {
-    let mut payees: Vec<String> = Vec::new();
-
-    for bean_data in data.values() {
-        for payee in bean_data.get_payees().iter() {
-            let clean = payee.trim_matches('"');
-            if !clean.is_empty() {
-                payees.push(clean.to_string());
-            }
-        }
-    }
-
-    payees.sort();
+    let mut payees: Vec<String> = index
+        .symbols(SymbolKind::Payee)
+        .map(|(payee, _)| payee.trim_matches('"'))
+        .filter(|payee| !payee.is_empty())
+        .map(str::to_string)
+        .collect();
     payees.dedup();
 
     let matches = fuzzy_search_strings(&payees, prefix);
@@ -952,6 +1260,14 @@ fn complete_payee(
     let line = content.line(position.line as usize).to_string();
     let (insert_range, replace_range) = calculate_string_ranges(&line, position, has_closing_quote);
 
+    // Typing `"` accepts a payee and steps over the closing quote. Without one, the
+    // inserted text closes the string itself, and a typed quote would double it.
+    let commit_characters = if has_closing_quote {
+        vec!["\"".to_string()]
+    } else {
+        vec![]
+    };
+
     Ok(matches
         .into_iter()
         .map(|(payee, score)| {
@@ -968,7 +1284,7 @@ fn complete_payee(
                 insert_range,
                 replace_range,
                 score,
-                vec![],
+                commit_characters.clone(),
             )
             .with_insert_text(insert_text)
         })
@@ -977,21 +1293,16 @@ fn complete_payee(
 
 /// Complete narration strings
 fn complete_narration(
-    data: &HashMap<PathBuf, Arc<BeancountData>>,
+    index: &WorkspaceIndex,
     prefix: &str,
     content: &ropey::Rope,
     position: Position,
     has_closing_quote: bool,
 ) -> Result<Vec<CompletionItem>> {
-    let mut narrations: Vec<String> = Vec::new();
-
-    for bean_data in data.values() {
-        for narration in bean_data.get_narration().iter() {
-            narrations.push(narration.trim_matches('"').to_string());
-        }
-    }
-
-    narrations.sort();
+    let mut narrations: Vec<String> = index
+        .symbols(SymbolKind::Narration)
+        .map(|(narration, _)| narration.trim_matches('"').to_string())
+        .collect();
     narrations.dedup();
 
     let matches = fuzzy_search_strings(&narrations, prefix);
@@ -1023,23 +1334,11 @@ fn complete_narration(
 }
 
 /// Complete tags
-fn complete_tag(
-    data: &HashMap<PathBuf, Arc<BeancountData>>,
-    prefix: &str,
-) -> Result<Vec<CompletionItem>> {
-    let mut tags: Vec<String> = Vec::new();
-
-    for bean_data in data.values() {
-        tags.extend(
-            bean_data
-                .get_tags()
-                .iter()
-                .map(|t| t.trim_start_matches('#').to_string()),
-        );
-    }
-
-    tags.sort();
-    tags.dedup();
+fn complete_tag(index: &WorkspaceIndex, prefix: &str) -> Result<Vec<CompletionItem>> {
+    let tags: Vec<String> = index
+        .symbols(SymbolKind::Tag)
+        .map(|(tag, _)| tag.trim_start_matches('#').to_string())
+        .collect();
 
     let matches = fuzzy_search_strings(&tags, prefix);
 
@@ -1055,23 +1354,11 @@ fn complete_tag(
 }
 
 /// Complete links
-fn complete_link(
-    data: &HashMap<PathBuf, Arc<BeancountData>>,
-    prefix: &str,
-) -> Result<Vec<CompletionItem>> {
-    let mut links: Vec<String> = Vec::new();
-
-    for bean_data in data.values() {
-        links.extend(
-            bean_data
-                .get_links()
-                .iter()
-                .map(|l| l.trim_start_matches('^').to_string()),
-        );
-    }
-
-    links.sort();
-    links.dedup();
+fn complete_link(index: &WorkspaceIndex, prefix: &str) -> Result<Vec<CompletionItem>> {
+    let links: Vec<String> = index
+        .symbols(SymbolKind::Link)
+        .map(|(link, _)| link.trim_start_matches('^').to_string())
+        .collect();
 
     let matches = fuzzy_search_strings(&links, prefix);
 
@@ -1121,49 +1408,25 @@ fn create_completion_with_insert_replace(
 
 /// Calculate word ranges for InsertReplaceEdit
 fn calculate_word_ranges(line: &str, position: Position) -> (Range, Range) {
-    let chars: Vec<char> = line.chars().collect();
-    let cursor_col = position.character as usize;
+    let is_word_char = |c: char| c.is_alphanumeric() || c == ':' || c == '-' || c == '_';
+    let encoding = position_encoding();
+    let cursor_col = encoding.byte_column(line, position.character);
 
     // Find start of word
-    let mut start = cursor_col;
-    while start > 0 {
-        let c = chars[start - 1];
-        if !c.is_alphanumeric() && c != ':' && c != '-' && c != '_' {
-            break;
-        }
-        start -= 1;
-    }
+    let start = line[..cursor_col]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word_char(c))
+        .last()
+        .map_or(cursor_col, |(idx, _)| idx);
 
     // Find end of word
-    let mut end = cursor_col;
-    while end < chars.len() {
-        let c = chars[end];
-        if !c.is_alphanumeric() && c != ':' && c != '-' && c != '_' {
-            break;
-        }
-        end += 1;
-    }
-
-    let insert_range = Range {
-        start: Position {
-            line: position.line,
-            character: start as u32,
-        },
-        end: position,
-    };
+    let end = line[cursor_col..]
+        .char_indices()
+        .find(|&(_, c)| !is_word_char(c))
+        .map_or(line.len(), |(idx, _)| cursor_col + idx);
 
-    let replace_range = Range {
-        start: Position {
-            line: position.line,
-            character: start as u32,
-        },
-        end: Position {
-            line: position.line,
-            character: end as u32,
-        },
-    };
-
-    (insert_range, replace_range)
+    edit_ranges(line, position, start, end)
 }
 
 /// Calculate string ranges for InsertReplaceEdit (handles quotes)
@@ -1172,47 +1435,42 @@ fn calculate_string_ranges(
     position: Position,
     has_closing_quote: bool,
 ) -> (Range, Range) {
-    let chars: Vec<char> = line.chars().collect();
-    let cursor_col = position.character as usize;
+    let encoding = position_encoding();
+    let cursor_col = encoding.byte_column(line, position.character);
 
     // Find opening quote
-    let mut start = cursor_col;
-    while start > 0 {
-        if chars[start - 1] == '"' {
-            break;
-        }
-        start -= 1;
-    }
+    let start = line[..cursor_col].rfind('"').map_or(0, |idx| idx + 1);
 
     // Find closing quote (if exists)
-    let mut end = cursor_col;
-    if has_closing_quote {
-        while end < chars.len() {
-            if chars[end] == '"' {
-                break;
-            }
-            end += 1;
-        }
-    }
+    let end = if has_closing_quote {
+        line[cursor_col..]
+            .find('"')
+            .map_or(line.len(), |idx| cursor_col + idx)
+    } else {
+        cursor_col
+    };
 
-    let insert_range = Range {
-        start: Position {
-            line: position.line,
-            character: start as u32,
-        },
-        end: position,
+    edit_ranges(line, position, start, end)
+}
+
+/// Builds the insert range (`start..position`) and replace range (`start..end`)
+/// from byte columns within `line`.
+fn edit_ranges(line: &str, position: Position, start: usize, end: usize) -> (Range, Range) {
+    let encoding = position_encoding();
+    let start = Position {
+        line: position.line,
+        character: encoding.character(line, start),
+    };
+    let end = Position {
+        line: position.line,
+        character: encoding.character(line, end),
     };
 
-    let replace_range = Range {
-        start: Position {
-            line: position.line,
-            character: start as u32,
-        },
-        end: Position {
-            line: position.line,
-            character: end as u32,
-        },
+    let insert_range = Range {
+        start,
+        end: position,
     };
+    let replace_range = Range { start, end };
 
     (insert_range, replace_range)
 }
@@ -1478,6 +1736,147 @@ mod tests {
         assert!(labels.contains(&"price"));
     }
 
+    #[test]
+    fn test_complete_snippets() {
+        let snippets = vec![Snippet {
+            name: "salary".to_string(),
+            body: "$1 * \"Employer\"\n  Income:Salary  -${2:5000} EUR\n  Assets:Bank".to_string(),
+            description: None,
+        }];
+        let content = ropey::Rope::from_str("sal\n");
+        let position = Position {
+            line: 0,
+            character: 3,
+        };
+        let items = complete_snippets(&snippets, &content, position);
+        assert_eq!(items.len(), 1);
+
+        let item = &items[0];
+        assert_eq!(item.label, "salary");
+        assert_eq!(item.kind, Some(CompletionItemKind::SNIPPET));
+        assert_eq!(item.detail.as_deref(), Some("Snippet"));
+        assert_eq!(
+            item.insert_text_format,
+            Some(lsp_types::InsertTextFormat::SNIPPET)
+        );
+        let Some(lsp_types::CompletionTextEdit::Edit(edit)) = &item.text_edit else {
+            panic!("Snippet completion should have text_edit");
+        };
+        assert_eq!(edit.new_text, snippets[0].body);
+        assert_eq!(edit.range.start.character, 0);
+        assert_eq!(edit.range.end.character, 3);
+    }
+
+    #[test]
+    fn test_complete_account_components() {
+        let accounts = vec![
+            "Assets:Bank".to_string(),
+            "Expenses:Food:Groceries".to_string(),
+            "Expenses:Food:Restaurant".to_string(),
+            "Expenses:Rent".to_string(),
+        ];
+        let complete = |line: &str| {
+            let content = ropey::Rope::from_str(line);
+            let position = Position {
+                line: 0,
+                character: line.len() as u32,
+            };
+            let prefix = line.trim_start();
+            complete_account_components(&accounts, prefix, &content, position)
+        };
+
+        let labels =
+            |items: &[CompletionItem]| items.iter().map(|i| i.label.clone()).collect::<Vec<_>>();
+
+        let roots = complete("  ");
+        assert_eq!(labels(&roots), vec!["Assets:", "Expenses:"]);
+        assert!(roots.iter().all(|item| item.command.is_some()));
+
+        let expenses = complete("  Expenses:");
+        assert_eq!(labels(&expenses), vec!["Expenses:Food:", "Expenses:Rent"]);
+        assert!(expenses[0].command.is_some());
+        assert!(expenses[1].command.is_none());
+
+        let food = complete("  Expenses:Food:Gro");
+        assert_eq!(labels(&food), vec!["Expenses:Food:Groceries"]);
+        let Some(lsp_types::CompletionTextEdit::Edit(edit)) = &food[0].text_edit else {
+            panic!("Account completion should have text_edit");
+        };
+        assert_eq!(edit.range.start.character, 2);
+        assert_eq!(edit.range.end.character, 19);
+    }
+
+    #[test]
+    fn test_recent_uses() {
+        let content = r#"2024-01-05 * "Grocer" "Weekly shopping"
+  Expenses:Food  25.00 USD
+  Assets:Cash
+
+2024-01-06 * "Bakery" "Bread"
+  Expenses:Food  3.00 USD
+  Assets:Cash
+
+2024-01-07 * "Employer" "Salary"
+  Assets:Cash  1000.00 USD
+  Income:Salary
+
+2024-01-12 * "Grocer" "Weekly shopping"
+  Expenses:Food  30.00 USD
+  Assets:Cash
+"#;
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let data = BeancountData::new(&tree, &ropey::Rope::from_str(content));
+        let files = HashMap::from([(PathBuf::from("/main.beancount"), Arc::new(data))]);
+
+        let summary = |usage: Usage| {
+            recent_uses(&files, &usage, 3)
+                .into_iter()
+                .map(|recent| {
+                    (
+                        recent.date,
+                        recent.amount.unwrap_or_default(),
+                        recent.account.unwrap_or_default(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let row = |date: &str, amount: &str, account: &str| {
+            (date.to_string(), amount.to_string(), account.to_string())
+        };
+
+        assert_eq!(
+            summary(Usage::Account("Assets:Cash".to_string())),
+            vec![
+                row("2024-01-12", "-30.00 USD", "Expenses:Food"),
+                row("2024-01-07", "1000.00 USD", "Income:Salary"),
+                row("2024-01-06", "-3.00 USD", "Expenses:Food"),
+            ]
+        );
+        assert_eq!(
+            summary(Usage::Payee("Grocer".to_string())),
+            vec![
+                row("2024-01-12", "30.00 USD", "Expenses:Food"),
+                row("2024-01-05", "25.00 USD", "Expenses:Food"),
+            ]
+        );
+        assert!(summary(Usage::Payee("Nobody".to_string())).is_empty());
+
+        // Component items from `account_completion_by_component` end with a colon
+        assert_eq!(
+            summary(Usage::Account("Expenses:".to_string())),
+            vec![
+                row("2024-01-12", "30.00 USD", "Assets:Cash"),
+                row("2024-01-06", "3.00 USD", "Assets:Cash"),
+                row("2024-01-05", "25.00 USD", "Assets:Cash"),
+            ]
+        );
+        assert!(summary(Usage::Account("Expenses:Fo".to_string())).is_empty());
+    }
+
     #[test]
     fn test_complete_date() {
         let content = ropey::Rope::from_str("2026-01-");
@@ -1535,6 +1934,40 @@ mod tests {
         assert_eq!(replace_range.end.character, 11);
     }
 
+    #[test]
+    fn test_calculate_word_ranges_with_non_ascii_account() {
+        let line = "  Vermögen:Bank\n";
+        // UTF-16 column after "Vermögen:Ba"
+        let position = Position::new(0, 13);
+
+        let (insert_range, replace_range) = calculate_word_ranges(line, position);
+
+        assert_eq!(insert_range.start.character, 2);
+        assert_eq!(insert_range.end.character, 13);
+        assert_eq!(replace_range.end.character, 15);
+    }
+
+    #[test]
+    fn test_calculate_string_ranges_after_non_ascii_payee() {
+        let line = "2024-01-01 * \"Café ☕\" \"Lunch\"\n";
+        // UTF-16 column after "Lu"; the byte column would be 28
+        let position = Position::new(0, 25);
+
+        let (insert_range, replace_range) = calculate_string_ranges(line, position, true);
+
+        assert_eq!(insert_range.start.character, 23);
+        assert_eq!(insert_range.end.character, 25);
+        assert_eq!(replace_range.end.character, 28);
+    }
+
+    #[test]
+    fn test_extract_string_prefix_after_non_ascii_payee() {
+        let line = "2024-01-01 * \"Café ☕\" \"Lunch\"";
+        let cursor_col = line.find("nch").unwrap();
+        assert_eq!(extract_string_prefix(line, cursor_col), "Lu");
+        assert_eq!(extract_account_prefix("  Vermögen:Ba", 14), "Vermögen:Ba");
+    }
+
     #[test]
     fn test_calculate_string_ranges_no_closing_quote() {
         let line = r#"2024-01-01 * "Grocery store"#;
@@ -2122,7 +2555,6 @@ A"#;
     #[test]
     fn test_complete_payee_empty_prefix() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2132,9 +2564,9 @@ A"#;
 2026-01-03 * "Target" "Clothes"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * ""#);
         let position = Position {
@@ -2142,7 +2574,7 @@ A"#;
             character: 14,
         };
 
-        let items = complete_payee(&data_map, "", &content, position, false).unwrap();
+        let items = complete_payee(&index, "", &content, position, false).unwrap();
 
         assert!(items.len() >= 3, "Should return all payees when no prefix");
 
@@ -2155,7 +2587,6 @@ A"#;
     #[test]
     fn test_complete_payee_with_prefix() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2165,9 +2596,9 @@ A"#;
 2026-01-03 * "Walmart" "Test"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * "K"#);
         let position = Position {
@@ -2175,7 +2606,7 @@ A"#;
             character: 15,
         };
 
-        let items = complete_payee(&data_map, "K", &content, position, false).unwrap();
+        let items = complete_payee(&index, "K", &content, position, false).unwrap();
 
         // Should fuzzy match Kroger and King Soopers
         assert!(items.len() >= 2, "Should match payees starting with K");
@@ -2188,7 +2619,6 @@ A"#;
     #[test]
     fn test_complete_payee_adds_closing_quote() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2196,9 +2626,9 @@ A"#;
 2026-01-01 * "Kroger" "Test"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * "Kr"#);
         let position = Position {
@@ -2207,8 +2637,9 @@ A"#;
         };
 
         // No closing quote
-        let items = complete_payee(&data_map, "Kr", &content, position, false).unwrap();
+        let items = complete_payee(&index, "Kr", &content, position, false).unwrap();
         assert!(!items.is_empty());
+        assert_eq!(items[0].commit_characters, None);
 
         // Should add closing quote in insert_text
         if let Some(lsp_types::CompletionTextEdit::Edit(edit)) = &items[0].text_edit {
@@ -2223,7 +2654,6 @@ A"#;
     #[test]
     fn test_complete_payee_no_extra_quote_when_present() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2231,9 +2661,9 @@ A"#;
 2026-01-01 * "Kroger" "Test"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * "Kr""#);
         let position = Position {
@@ -2242,8 +2672,9 @@ A"#;
         };
 
         // Has closing quote
-        let items = complete_payee(&data_map, "Kr", &content, position, true).unwrap();
+        let items = complete_payee(&index, "Kr", &content, position, true).unwrap();
         assert!(!items.is_empty());
+        assert_eq!(items[0].commit_characters, Some(vec!["\"".to_string()]));
 
         // Should NOT add closing quote
         if let Some(lsp_types::CompletionTextEdit::Edit(edit)) = &items[0].text_edit {
@@ -2258,7 +2689,6 @@ A"#;
     #[test]
     fn test_complete_payee_deduplication() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2268,9 +2698,9 @@ A"#;
 2026-01-03 * "Kroger" "Test3"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * ""#);
         let position = Position {
@@ -2278,7 +2708,7 @@ A"#;
             character: 14,
         };
 
-        let items = complete_payee(&data_map, "", &content, position, false).unwrap();
+        let items = complete_payee(&index, "", &content, position, false).unwrap();
 
         // Should deduplicate
         assert_eq!(items.len(), 1, "Should deduplicate payees");
@@ -2390,7 +2820,6 @@ A"#;
     #[test]
     fn test_complete_narration_empty_prefix() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2400,9 +2829,9 @@ A"#;
 2026-01-03 * "Restaurant" "Dinner"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * "Kroger" ""#);
         let position = Position {
@@ -2410,7 +2839,7 @@ A"#;
             character: 23,
         };
 
-        let items = complete_narration(&data_map, "", &content, position, false).unwrap();
+        let items = complete_narration(&index, "", &content, position, false).unwrap();
 
         assert!(
             items.len() >= 3,
@@ -2431,7 +2860,6 @@ A"#;
     #[test]
     fn test_complete_narration_with_prefix() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2441,9 +2869,9 @@ A"#;
 2026-01-03 * "Shop" "Gift"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * "Store" "G"#);
         let position = Position {
@@ -2451,7 +2879,7 @@ A"#;
             character: 23, // Position at 'G'
         };
 
-        let items = complete_narration(&data_map, "G", &content, position, false).unwrap();
+        let items = complete_narration(&index, "G", &content, position, false).unwrap();
 
         // Should fuzzy match all items starting with G
         assert!(items.len() >= 3, "Should match narrations starting with G");
@@ -2465,7 +2893,6 @@ A"#;
     #[test]
     fn test_complete_narration_adds_closing_quote() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2473,9 +2900,9 @@ A"#;
 2026-01-01 * "Store" "Groceries"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * "Store" "Groc"#);
         let position = Position {
@@ -2484,7 +2911,7 @@ A"#;
         };
 
         // No closing quote
-        let items = complete_narration(&data_map, "Groc", &content, position, false).unwrap();
+        let items = complete_narration(&index, "Groc", &content, position, false).unwrap();
         assert!(!items.is_empty());
 
         // Should add closing quote in insert_text
@@ -2500,7 +2927,6 @@ A"#;
     #[test]
     fn test_complete_narration_no_extra_quote_when_present() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2508,9 +2934,9 @@ A"#;
 2026-01-01 * "Store" "Groceries"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * "Store" "Groc""#);
         let position = Position {
@@ -2519,7 +2945,7 @@ A"#;
         };
 
         // Has closing quote
-        let items = complete_narration(&data_map, "Groc", &content, position, true).unwrap();
+        let items = complete_narration(&index, "Groc", &content, position, true).unwrap();
         assert!(!items.is_empty());
 
         // Should NOT add closing quote
@@ -2535,7 +2961,6 @@ A"#;
     #[test]
     fn test_complete_narration_deduplication() {
         use ropey::Rope;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::sync::Arc;
 
@@ -2545,9 +2970,9 @@ A"#;
 2026-01-03 * "Shop" "Groceries"
 "#;
 
-        let mut data_map = HashMap::new();
+        let mut index = WorkspaceIndex::default();
         let bean_data = create_test_beancount_data(test_data);
-        data_map.insert(PathBuf::from("test.bean"), Arc::new(bean_data));
+        index.update_file(PathBuf::from("test.bean"), Arc::new(bean_data));
 
         let content = Rope::from_str(r#"2026-01-06 * "Store" ""#);
         let position = Position {
@@ -2555,7 +2980,7 @@ A"#;
             character: 22, // Position inside empty narration string
         };
 
-        let items = complete_narration(&data_map, "", &content, position, false).unwrap();
+        let items = complete_narration(&index, "", &content, position, false).unwrap();
 
         // Should deduplicate
         assert_eq!(items.len(), 1, "Should deduplicate narrations");
@@ -2814,36 +3239,17 @@ A"#;
             let uri = lsp_types::Uri::from_str(url.as_str()).unwrap();
             (path, uri)
         };
-        beancount_data.insert(path.clone(), Arc::new(bean_data));
+        let bean_data = Arc::new(bean_data);
+        beancount_data.insert(path.clone(), bean_data.clone());
+        let mut index = WorkspaceIndex::default();
+        index.update_file(path.clone(), bean_data);
 
         // Parse the document being edited - use partial narration to test completion
         let edit_text = r#"2026-01-06 * "NewPayee" "Nar"#;
-        let edit_rope = Rope::from_str(edit_text);
-        let mut edit_parser = Parser::new();
-        edit_parser
-            .set_language(&tree_sitter_beancount::language())
-            .unwrap();
-        let edit_tree = edit_parser.parse(edit_text, None).unwrap();
-
-        let mut forest = HashMap::new();
-        forest.insert(path.clone(), Arc::new(edit_tree));
-
-        let mut open_docs = HashMap::new();
-        open_docs.insert(
-            path.clone(),
-            crate::document::Document {
-                content: edit_rope.clone(),
-                version: 0,
-            },
-        );
-
-        let snapshot = LspServerStateSnapshot {
-            beancount_data,
-            config: crate::config::Config::new(PathBuf::from("/test")),
-            forest,
-            open_docs,
-            checker: None,
-        };
+        let config = crate::config::Config::new(PathBuf::from("/test"));
+        let mut snapshot = LspServerStateSnapshot::for_test(config, &[(&path, edit_text)]);
+        snapshot.beancount_data = beancount_data;
+        snapshot.index = Arc::new(index);
 
         // Cursor position inside second string after "Nar"
         // Text: '2026-01-06 * "NewPayee" "Nar"'
@@ -2939,36 +3345,21 @@ A"#;
             let uri = lsp_types::Uri::from_str(url.as_str()).unwrap();
             (path, uri)
         };
-        beancount_data.insert(path.clone(), Arc::new(bean_data));
+        let bean_data = Arc::new(bean_data);
+        beancount_data.insert(path.clone(), bean_data.clone());
+        let mut index = WorkspaceIndex::default();
+        index.update_file(path.clone(), bean_data);
 
         // Parse the document being edited - balance directive with lowercase prefix
         let edit_text = r#"2026-01-06 balance lia"#;
-        let edit_rope = Rope::from_str(edit_text);
-        let mut edit_parser = Parser::new();
-        edit_parser
-            .set_language(&tree_sitter_beancount::language())
-            .unwrap();
-        let edit_tree = edit_parser.parse(edit_text, None).unwrap();
-
-        let mut forest = HashMap::new();
-        forest.insert(path.clone(), Arc::new(edit_tree));
-
-        let mut open_docs = HashMap::new();
-        open_docs.insert(
-            path.clone(),
-            crate::document::Document {
-                content: edit_rope.clone(),
-                version: 0,
-            },
-        );
-
-        let snapshot = LspServerStateSnapshot {
-            beancount_data,
-            config: crate::config::Config::new(PathBuf::from("/test")),
-            forest,
-            open_docs,
-            checker: None,
+        let index = Arc::new(index);
+        let snapshot = |config| {
+            let mut snapshot = LspServerStateSnapshot::for_test(config, &[(&path, edit_text)]);
+            snapshot.beancount_data = beancount_data.clone();
+            snapshot.index = index.clone();
+            snapshot
         };
+        let config = crate::config::Config::new(PathBuf::from("/test"));
 
         // Cursor position after "lia"
         // Text: '2026-01-06 balance lia'
@@ -2983,7 +3374,7 @@ A"#;
         };
 
         // Call the completion function
-        let result = completion(snapshot, None, position).unwrap();
+        let result = completion(snapshot(config.clone()), None, position.clone()).unwrap();
         assert!(
             result.is_some(),
             "Should return completion items for lowercase prefix"
@@ -3015,5 +3406,66 @@ A"#;
                 labels
             );
         }
+        assert_eq!(items[0].commit_characters, Some(vec![":".to_string()]));
+
+        // Clients handling commit characters poorly can turn them off
+        let mut config = config;
+        config.features.commit_characters = false;
+        let items = completion(snapshot(config), None, position)
+            .unwrap()
+            .unwrap();
+        assert!(items.iter().all(|item| item.commit_characters.is_none()));
+    }
+
+    #[test]
+    fn test_complete_currency_ranks_operating_currencies_first() {
+        use ropey::Rope;
+        use std::path::PathBuf;
+        use std::sync::Arc;
+
+        let test_data = r#"
+option "operating_currency" "CHF"
+2024-01-01 open Assets:Cash USD
+2024-01-01 open Assets:Bank EUR
+"#;
+        let bean_data = create_test_beancount_data(test_data);
+        let options = LedgerOptions::from_data(&bean_data);
+        let mut index = WorkspaceIndex::default();
+        index.update_file(PathBuf::from("main.bean"), Arc::new(bean_data));
+
+        let content = Rope::from_str("  Assets:Cash  10 ");
+        let position = Position::new(0, 18);
+        let mut items = complete_currency(&index, &options, &content, position).unwrap();
+        items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["CHF", "EUR", "USD"]);
+        assert_eq!(items[0].detail.as_deref(), Some("Operating currency"));
+    }
+
+    #[test]
+    fn test_complete_account_offers_renamed_root_accounts() {
+        use ropey::Rope;
+        use std::path::PathBuf;
+        use std::sync::Arc;
+
+        let test_data = r#"
+option "name_assets" "Vermoegen"
+2024-01-01 open Vermoegen:Bar
+"#;
+        let bean_data = create_test_beancount_data(test_data);
+        let options = LedgerOptions::from_data(&bean_data);
+        let mut index = WorkspaceIndex::default();
+        index.update_file(PathBuf::from("main.bean"), Arc::new(bean_data));
+
+        let content = Rope::from_str("  ");
+        let items =
+            complete_account(&index, &options, "", false, &content, Position::new(0, 2)).unwrap();
+
+        let labels: Vec<&str> = items.iter().map(|i| i.label.as_str()).collect();
+        assert!(labels.contains(&"Vermoegen:Bar"));
+        assert!(labels.contains(&"Expenses"));
+        assert!(!labels.contains(&"Vermoegen"));
+        assert!(!labels.contains(&"Assets"));
     }
 }
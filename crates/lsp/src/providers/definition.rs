@@ -1,22 +1,18 @@
-use crate::beancount_data::get_unified_query;
-use crate::document::Document;
+use crate::beancount_data::BeancountData;
+use crate::forest;
+use crate::index::WorkspaceIndex;
+use crate::ledger::EntryKind;
 use crate::server::LspServerStateSnapshot;
-use crate::treesitter_utils::{
-    lsp_position_to_tree_sitter_point_range, text_for_tree_sitter_node,
-    tree_sitter_node_to_lsp_range,
-};
-use crate::utils::file_path_to_uri;
+use crate::treesitter_utils::{lsp_position_to_tree_sitter_point_range, text_for_tree_sitter_node};
+use crate::utils::{ToFilePath, file_path_to_uri};
 use anyhow::Context;
 use anyhow::Result;
 use lsp_types::GotoDefinitionResponse;
 use lsp_types::Location;
-use ropey::Rope;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tree_sitter::StreamingIterator;
-use tree_sitter_beancount::NodeKind;
-use tree_sitter_beancount::tree_sitter;
+use tree_sitter_beancount::{NodeKind, tree_sitter};
 
 /// Provider function for `textDocument/definition`.
 pub(crate) fn definition(
@@ -40,111 +36,302 @@ pub(crate) fn definition(
         return Ok(None);
     };
 
+    if NodeKind::String == node.kind().into()
+        && node
+            .parent()
+            .is_some_and(|parent| NodeKind::Include == parent.kind().into())
+    {
+        let Ok(file) = doc_uri.to_file_path() else {
+            return Ok(None);
+        };
+        let filename = text_for_tree_sitter_node(&content, &node);
+        let locs = find_included_files(filename.trim_matches('"'), &file);
+        if locs.is_empty() {
+            return Ok(None);
+        }
+        return Ok(Some(GotoDefinitionResponse::Array(locs)));
+    }
+
+    if NodeKind::String == node.kind().into() && names_document_file(&node, &content) {
+        let Ok(file) = doc_uri.to_file_path() else {
+            return Ok(None);
+        };
+        let filename = text_for_tree_sitter_node(&content, &node);
+        return Ok(find_document_file(filename.trim_matches('"'), &file)
+            .map(GotoDefinitionResponse::Scalar));
+    }
+
     if NodeKind::Account != node.kind().into() {
         return Ok(None);
     }
 
     let node_text = text_for_tree_sitter_node(&content, &node);
-    let locs = find_account_open_definitions(&snapshot.forest, &snapshot.open_docs, node_text);
+    let locs = find_account_open_definitions(&snapshot.index, &node_text);
     if locs.is_empty() {
         return Ok(None);
     }
     Ok(Some(GotoDefinitionResponse::Array(locs)))
 }
 
-fn find_account_open_definitions(
-    forest: &HashMap<PathBuf, Arc<tree_sitter::Tree>>,
-    open_docs: &HashMap<PathBuf, Document>,
-    node_text: String,
-) -> Vec<Location> {
-    forest
-        .iter()
-        .flat_map(|(url, tree)| {
-            let query = get_unified_query();
-            let capture_account = match query.capture_index_for_name("account") {
-                Some(index) => index,
-                None => {
-                    tracing::warn!("Query missing capture 'account'");
-                    return vec![];
-                }
-            };
+/// Provider function for `textDocument/implementation`: the `balance` directives
+/// asserting the account under the cursor, oldest first.
+pub(crate) fn implementation(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::request::GotoImplementationParams,
+) -> Result<Option<lsp_types::request::GotoImplementationResponse>> {
+    let doc_uri = &params.text_document_position_params.text_document.uri;
+    let position = params.text_document_position_params.position;
 
-            let (text, rope) = if let Some(doc) = open_docs.get(url) {
-                (doc.text().to_string(), doc.content.clone())
-            } else {
-                let Ok(content) = std::fs::read_to_string(url) else {
-                    tracing::debug!("Failed to read file: {:?}", url);
-                    return vec![];
-                };
-                let rope = Rope::from_str(&content);
-                (content, rope)
-            };
+    let (tree, doc) = snapshot
+        .tree_and_document_for_uri(doc_uri)
+        .context("Failed to get tree/document for implementation")?;
+    let content = doc.content.clone();
 
-            let Ok(uri) = file_path_to_uri(url) else {
-                tracing::debug!("Failed to convert file path to URI: {}", url.display());
-                return vec![];
-            };
+    let (start, end) = lsp_position_to_tree_sitter_point_range(&content, position)?;
+    let Some(node) = tree
+        .root_node()
+        .named_descendant_for_point_range(start, end)
+    else {
+        return Ok(None);
+    };
+    if NodeKind::Account != node.kind().into() {
+        return Ok(None);
+    }
+    let Ok(file) = doc_uri.to_file_path() else {
+        return Ok(None);
+    };
 
-            let source = text.as_bytes();
-            let mut query_cursor = tree_sitter::QueryCursor::new();
-            let mut matches = query_cursor.matches(query, tree.root_node(), source);
-            let mut results = Vec::new();
-            while let Some(m) = matches.next() {
-                if let Some(node) = m.nodes_for_capture_index(capture_account).next() {
-                    let m_text = match node.utf8_text(source) {
-                        Ok(text) => text,
-                        Err(err) => {
-                            tracing::debug!("Failed to read node text: {err}");
-                            continue;
-                        }
-                    };
-                    if m_text == node_text {
-                        results.push(Location::new(
-                            uri.clone(),
-                            tree_sitter_node_to_lsp_range(&rope, &node),
-                        ));
-                    }
-                }
+    let account = text_for_tree_sitter_node(&content, &node);
+    let files = snapshot.journal_files(snapshot.journal_for(&file).as_deref());
+    let locs = find_balance_assertions(&files, &account);
+    if locs.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(GotoDefinitionResponse::Array(locs)))
+}
+
+/// The `balance` directives of `account` in `files`, sorted by date.
+fn find_balance_assertions(
+    files: &HashMap<PathBuf, Arc<BeancountData>>,
+    account: &str,
+) -> Vec<Location> {
+    let mut assertions: Vec<(String, &Path, u32)> = Vec::new();
+    for (path, data) in files {
+        for entry in data.get_ledger_entries().iter() {
+            if matches!(&entry.kind, EntryKind::Balance { account: asserted, .. } if asserted == account)
+            {
+                assertions.push((entry.date.clone(), path, entry.line));
             }
-            results
+        }
+    }
+    assertions.sort();
+    assertions
+        .into_iter()
+        .filter_map(|(_, path, line)| {
+            let uri = file_path_to_uri(path).ok()?;
+            let start = lsp_types::Position::new(line, 0);
+            Some(Location::new(uri, lsp_types::Range::new(start, start)))
+        })
+        .collect()
+}
+
+/// Whether the string `node` is the file of a `document` directive or the value of
+/// `document:` or `receipt:` metadata.
+fn names_document_file(node: &tree_sitter::Node, content: &ropey::Rope) -> bool {
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+    match parent.kind() {
+        "filename" => true,
+        "value" => parent
+            .parent()
+            .filter(|key_value| key_value.kind() == "key_value")
+            .and_then(|key_value| key_value.named_child(0))
+            .filter(|key| key.kind() == "key")
+            .is_some_and(|key| {
+                matches!(
+                    text_for_tree_sitter_node(content, &key).as_str(),
+                    "document" | "receipt"
+                )
+            }),
+        _ => false,
+    }
+}
+
+/// The statement or receipt at `filename`, which beancount resolves relative to the
+/// directory of the `file` naming it.
+fn find_document_file(filename: &str, file: &Path) -> Option<Location> {
+    let path = PathBuf::from(shellexpand::tilde(filename).as_ref());
+    let path = match file.parent() {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path,
+    };
+    if !path.is_file() {
+        return None;
+    }
+    let uri = file_path_to_uri(&path).ok()?;
+    Some(Location::new(uri, lsp_types::Range::default()))
+}
+
+/// The files an include of `filename` in `file` points to, every match of a glob
+/// pattern like `imports/*.beancount` in order.
+fn find_included_files(filename: &str, file: &Path) -> Vec<Location> {
+    let pattern = forest::resolve_include_pattern(filename, file);
+    let mut paths: Vec<_> = glob::glob(&pattern.to_string_lossy())
+        .map(|paths| paths.filter_map(|path| path.ok()).collect())
+        .unwrap_or_default();
+    paths.sort();
+    paths
+        .iter()
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let uri = file_path_to_uri(path).ok()?;
+            Some(Location::new(uri, lsp_types::Range::default()))
         })
-        .collect::<Vec<_>>()
+        .collect()
+}
+
+fn find_account_open_definitions(index: &WorkspaceIndex, node_text: &str) -> Vec<Location> {
+    index
+        .account_definitions(node_text)
+        .iter()
+        .filter_map(|(path, range)| {
+            let Ok(uri) = file_path_to_uri(path) else {
+                tracing::debug!("Failed to convert file path to URI: {}", path.display());
+                return None;
+            };
+            Some(Location::new(uri, *range))
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use ropey::Rope;
-    use tree_sitter::Parser;
+    use tree_sitter_beancount::tree_sitter::Parser;
 
-    fn make_tree(text: &str) -> tree_sitter::Tree {
+    fn index_with(files: &[(&PathBuf, &str)]) -> WorkspaceIndex {
+        let mut index = WorkspaceIndex::default();
+        for (path, text) in files {
+            let mut parser = Parser::new();
+            parser
+                .set_language(&tree_sitter_beancount::language())
+                .unwrap();
+            let tree = parser.parse(text, None).unwrap();
+            let data = BeancountData::new(&tree, &Rope::from_str(text));
+            index.update_file(path.to_path_buf(), Arc::new(data));
+        }
+        index
+    }
+
+    #[test]
+    fn test_find_included_files_expands_globs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let imports = dir.path().join("imports");
+        std::fs::create_dir(&imports).unwrap();
+        for name in ["b.beancount", "a.beancount", "notes.txt"] {
+            std::fs::write(imports.join(name), "").unwrap();
+        }
+        let main = dir.path().join("main.beancount");
+
+        let locs = find_included_files("imports/*.beancount", &main);
+        let expected: Vec<_> = ["a.beancount", "b.beancount"]
+            .iter()
+            .map(|name| file_path_to_uri(&imports.join(name)).unwrap())
+            .collect();
+        assert_eq!(
+            locs.iter().map(|loc| loc.uri.clone()).collect::<Vec<_>>(),
+            expected
+        );
+
+        let locs = find_included_files("imports/a.beancount", &main);
+        assert_eq!(locs.len(), 1);
+        assert!(find_included_files("missing/*.beancount", &main).is_empty());
+    }
+
+    #[test]
+    fn test_document_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("statements")).unwrap();
+        std::fs::write(dir.path().join("statements/jan.pdf"), "").unwrap();
+        let main = dir.path().join("main.beancount");
+
+        let content = "2024-01-31 document Assets:Bank \"statements/jan.pdf\"\n2024-02-01 * \"Shop\"\n  receipt: \"statements/feb.pdf\"\n  note: \"statements/jan.pdf\"\n  Expenses:Food  5 USD\n  Assets:Bank\n";
         let mut parser = Parser::new();
         parser
             .set_language(&tree_sitter_beancount::language())
             .unwrap();
-        parser.parse(text, None).unwrap()
+        let tree = parser.parse(content, None).unwrap();
+        let rope = Rope::from_str(content);
+        let string_at = |row, column| {
+            let point = tree_sitter::Point::new(row, column);
+            tree.root_node()
+                .named_descendant_for_point_range(point, point)
+                .unwrap()
+        };
+
+        assert!(names_document_file(&string_at(0, 35), &rope));
+        assert!(names_document_file(&string_at(2, 14), &rope));
+        assert!(!names_document_file(&string_at(3, 14), &rope));
+
+        let loc = find_document_file("statements/jan.pdf", &main).unwrap();
+        let expected = dir.path().join("statements/jan.pdf");
+        assert_eq!(loc.uri, file_path_to_uri(&expected).unwrap());
+        assert!(find_document_file(&expected.to_string_lossy(), &main).is_some());
+        assert!(find_document_file("statements/feb.pdf", &main).is_none());
     }
 
-    fn make_doc(text: &str) -> Document {
-        Document {
-            content: Rope::from_str(text),
-            version: 1,
-        }
+    #[test]
+    fn test_find_balance_assertions_sorted_by_date() {
+        let main = std::env::temp_dir().join("implementation_test_main.bean");
+        let other = std::env::temp_dir().join("implementation_test_other.bean");
+        let files: HashMap<PathBuf, Arc<BeancountData>> = [
+            (
+                &main,
+                "2024-03-01 balance Assets:Bank 10 USD\n2024-03-01 balance Assets:Cash 5 USD\n",
+            ),
+            (
+                &other,
+                "2024-01-01 open Assets:Bank\n\n2024-02-01 balance Assets:Bank 0 USD\n",
+            ),
+        ]
+        .into_iter()
+        .map(|(path, text)| {
+            let mut parser = Parser::new();
+            parser
+                .set_language(&tree_sitter_beancount::language())
+                .unwrap();
+            let tree = parser.parse(text, None).unwrap();
+            (
+                path.clone(),
+                Arc::new(BeancountData::new(&tree, &Rope::from_str(text))),
+            )
+        })
+        .collect();
+
+        let locs = find_balance_assertions(&files, "Assets:Bank");
+        let found: Vec<_> = locs
+            .iter()
+            .map(|loc| (loc.uri.clone(), loc.range.start.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (file_path_to_uri(&other).unwrap(), 2),
+                (file_path_to_uri(&main).unwrap(), 0),
+            ]
+        );
+        assert!(find_balance_assertions(&files, "Liabilities:Card").is_empty());
     }
 
     #[test]
     fn test_find_account_open_definitions_single_match() {
         let text = "2024-01-01 open Assets:Cash\n";
         let path = std::env::temp_dir().join("definition_test.bean");
-        let tree = Arc::new(make_tree(text));
-
-        let mut forest = HashMap::new();
-        forest.insert(path.clone(), tree);
-
-        let mut open_docs = HashMap::new();
-        open_docs.insert(path.clone(), make_doc(text));
+        let index = index_with(&[(&path, text)]);
 
-        let locs = find_account_open_definitions(&forest, &open_docs, "Assets:Cash".to_string());
+        let locs = find_account_open_definitions(&index, "Assets:Cash");
 
         assert_eq!(locs.len(), 1);
         let loc = &locs[0];
@@ -163,16 +350,9 @@ mod tests {
         let text_b = "2024-01-02 open Assets:Cash\n";
         let path_a = std::env::temp_dir().join("definition_test_a.bean");
         let path_b = std::env::temp_dir().join("definition_test_b.bean");
+        let index = index_with(&[(&path_a, text_a), (&path_b, text_b)]);
 
-        let mut forest = HashMap::new();
-        forest.insert(path_a.clone(), Arc::new(make_tree(text_a)));
-        forest.insert(path_b.clone(), Arc::new(make_tree(text_b)));
-
-        let mut open_docs = HashMap::new();
-        open_docs.insert(path_a, make_doc(text_a));
-        open_docs.insert(path_b, make_doc(text_b));
-
-        let locs = find_account_open_definitions(&forest, &open_docs, "Assets:Cash".to_string());
+        let locs = find_account_open_definitions(&index, "Assets:Cash");
 
         assert_eq!(locs.len(), 2);
     }
@@ -181,16 +361,9 @@ mod tests {
     fn test_find_account_open_definitions_no_match() {
         let text = "2024-01-01 open Assets:Cash\n";
         let path = std::env::temp_dir().join("definition_test_none.bean");
-        let tree = Arc::new(make_tree(text));
-
-        let mut forest = HashMap::new();
-        forest.insert(path.clone(), tree);
-
-        let mut open_docs = HashMap::new();
-        open_docs.insert(path, make_doc(text));
+        let index = index_with(&[(&path, text)]);
 
-        let locs =
-            find_account_open_definitions(&forest, &open_docs, "Liabilities:Card".to_string());
+        let locs = find_account_open_definitions(&index, "Liabilities:Card");
 
         assert!(locs.is_empty());
     }
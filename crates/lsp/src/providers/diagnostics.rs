@@ -1,11 +1,23 @@
 use crate::beancount_data::BeancountData;
 use crate::checkers::{BeancountChecker, BeancountError, FlaggedEntry};
+use crate::config::PostingLintsConfig;
+use crate::forest;
+use crate::ledger::EntryKind;
+use crate::ledger_options::LedgerOptions;
+use crate::treesitter_utils::{tree_sitter_node_to_lsp_range, tree_sitter_point_to_lsp_position};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 #[cfg(test)]
 use tempfile;
 use tracing::debug;
+use tree_sitter_beancount::tree_sitter;
+
+/// Code of the diagnostic for an include matching no file.
+pub(crate) const MISSING_INCLUDE: &str = "missing-include";
+
+/// Code of the diagnostic for a `pushtag` never popped or a `poptag` of a tag not pushed.
+pub(crate) const UNBALANCED_TAG: &str = "unbalanced-tag";
 
 /// Container for diagnostic data management.
 /// Currently unused but reserved for future caching and state management.
@@ -31,11 +43,13 @@ impl Default for DiagnosticData {
 /// This function collects diagnostics from two sources:
 /// 1. Bean-check validation (via configurable checker implementation)
 /// 2. Internal flagged entries from parsed beancount data (warnings)
+/// 3. Opened accounts whose root is not one of the ledger's root accounts (errors)
 ///
 /// # Arguments
 /// * `beancount_data` - Parsed beancount data containing flagged entries
 /// * `checker` - Bean-check implementation (system call or Python)
 /// * `root_journal_file` - Main beancount file to validate
+/// * `options` - Options declared by the journal, for the root account names
 ///
 /// # Returns
 /// HashMap mapping file paths to their diagnostic messages
@@ -49,14 +63,35 @@ pub fn diagnostics(
     checker: &dyn BeancountChecker,
     root_journal_file: &Path,
     diagnostic_flags: &[String],
+    options: &LedgerOptions,
 ) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
     tracing::info!("Starting diagnostics for: {}", root_journal_file.display());
-    tracing::debug!("Using checker: {}", checker.name());
     tracing::debug!(
         "Processing beancount data for {} files",
         beancount_data.len()
     );
 
+    let mut diagnostics_map = checker_diagnostics(checker, root_journal_file);
+
+    // Add diagnostics for accounts outside the ledger's root accounts
+    merge_invalid_account_roots(&mut diagnostics_map, &beancount_data, options);
+
+    // Add diagnostics for flagged entries from parsed beancount data
+    // (These are additional to any flagged entries returned by the checker)
+    merge_flagged_entries_from_parsed_data(&mut diagnostics_map, beancount_data, diagnostic_flags);
+
+    debug!("Generated diagnostics for {} files", diagnostics_map.len());
+    diagnostics_map
+}
+
+/// The diagnostics of running `checker` on the journal: its errors and flagged entries,
+/// or none when it failed.
+pub fn checker_diagnostics(
+    checker: &dyn BeancountChecker,
+    root_journal_file: &Path,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    tracing::debug!("Using checker: {}", checker.name());
+
     // Execute bean-check validation using the configured checker
     tracing::debug!(
         "Calling checker.check() with file: {}",
@@ -75,24 +110,7 @@ pub fn diagnostics(
         Err(e) => {
             tracing::error!("Bean-check {} execution failed: {}", checker.name(), e);
             tracing::warn!("Continuing with flagged entries from parsed data only");
-
-            // Continue processing in tests to allow testing of flagged entries
-            #[cfg(not(test))]
-            {
-                let mut diagnostics_map = HashMap::new();
-                merge_flagged_entries_from_parsed_data(
-                    &mut diagnostics_map,
-                    beancount_data,
-                    diagnostic_flags,
-                );
-                return diagnostics_map;
-            }
-
-            #[cfg(test)]
-            {
-                // In tests, create an empty result so we can test flagged entries
-                Default::default()
-            }
+            return HashMap::new();
         }
     };
 
@@ -101,12 +119,6 @@ pub fn diagnostics(
 
     // Add flagged entries from checker (if supported by implementation)
     merge_flagged_entries_from_checker(&mut diagnostics_map, check_result.flagged_entries);
-
-    // Add diagnostics for flagged entries from parsed beancount data
-    // (These are additional to any flagged entries returned by the checker)
-    merge_flagged_entries_from_parsed_data(&mut diagnostics_map, beancount_data, diagnostic_flags);
-
-    debug!("Generated diagnostics for {} files", diagnostics_map.len());
     diagnostics_map
 }
 
@@ -176,6 +188,130 @@ fn merge_flagged_entries_from_checker(
     }
 }
 
+/// The diagnostics the server derives from the indexed data alone, without a checker:
+/// invalid root accounts and flagged entries.
+pub(crate) fn parsed_data_lints(
+    beancount_data: &HashMap<PathBuf, Arc<BeancountData>>,
+    diagnostic_flags: &[String],
+    options: &LedgerOptions,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let mut diagnostics_map = HashMap::new();
+    merge_invalid_account_roots(&mut diagnostics_map, beancount_data, options);
+    merge_flagged_entries_from_parsed_data(
+        &mut diagnostics_map,
+        beancount_data.clone(),
+        diagnostic_flags,
+    );
+    diagnostics_map
+}
+
+/// Reports open directives for accounts that do not start with one of the root
+/// account names, which `name_*` options may have renamed.
+fn merge_invalid_account_roots(
+    diagnostics_map: &mut HashMap<PathBuf, Vec<lsp_types::Diagnostic>>,
+    beancount_data: &HashMap<PathBuf, Arc<BeancountData>>,
+    options: &LedgerOptions,
+) {
+    for (file_path, data) in beancount_data.iter() {
+        for (account, range) in data.get_account_definitions().iter() {
+            if options.has_valid_root(account) {
+                continue;
+            }
+
+            let diagnostic = lsp_types::Diagnostic {
+                range: *range,
+                message: format!(
+                    "Invalid root account in '{}' (expected one of {})",
+                    account,
+                    options.root_accounts.join(", ")
+                ),
+                severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+                source: Some("beancount-lsp".to_string()),
+                code: Some(lsp_types::NumberOrString::String(
+                    "invalid-account-root".to_string(),
+                )),
+                ..lsp_types::Diagnostic::default()
+            };
+
+            diagnostics_map
+                .entry(file_path.clone())
+                .or_default()
+                .push(diagnostic);
+        }
+    }
+}
+
+/// Reports the postings the enabled posting lints object to: zero amounts, negative
+/// amounts posted to expenses and positive amounts posted to income. Refunds and the
+/// like break the sign convention on purpose, which is why each lint is opt-in.
+pub(crate) fn posting_lints(
+    beancount_data: &HashMap<PathBuf, Arc<BeancountData>>,
+    options: &LedgerOptions,
+    lints: &PostingLintsConfig,
+) -> HashMap<PathBuf, Vec<lsp_types::Diagnostic>> {
+    let zero_amount = lints.zero_amount.to_lsp();
+    let sign_convention = lints.sign_convention.to_lsp();
+    let [_, _, _, income, expenses] = &options.root_accounts;
+
+    let mut diagnostics_map: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
+    if zero_amount.is_none() && sign_convention.is_none() {
+        return diagnostics_map;
+    }
+    let diagnostic = |line, message, severity, code: &str| lsp_types::Diagnostic {
+        range: full_line_range(line),
+        message,
+        severity: Some(severity),
+        source: Some("beancount-lsp".to_string()),
+        code: Some(lsp_types::NumberOrString::String(code.to_string())),
+        ..lsp_types::Diagnostic::default()
+    };
+
+    for (file_path, data) in beancount_data.iter() {
+        for entry in data.get_ledger_entries().iter() {
+            let EntryKind::Transaction { postings, .. } = &entry.kind else {
+                continue;
+            };
+            for posting in postings {
+                let Some(amount) = &posting.amount else {
+                    continue;
+                };
+                let value = amount.amount.value;
+                let root = posting.account.split(':').next().unwrap_or_default();
+                let found = if value.is_zero() {
+                    zero_amount.map(|severity| {
+                        let message = format!("Zero amount posted to {}", posting.account);
+                        diagnostic(posting.line, message, severity, "zero-amount")
+                    })
+                } else if (root == expenses && value.is_sign_negative())
+                    || (root == income && value.is_sign_positive())
+                {
+                    sign_convention.map(|severity| {
+                        let (sign, usual) = if root == expenses {
+                            ("Negative", "positive")
+                        } else {
+                            ("Positive", "negative")
+                        };
+                        let message = format!(
+                            "{sign} amount posted to {}, which is usually {usual}",
+                            posting.account
+                        );
+                        diagnostic(posting.line, message, severity, "sign-convention")
+                    })
+                } else {
+                    None
+                };
+                if let Some(found) = found {
+                    diagnostics_map
+                        .entry(file_path.clone())
+                        .or_default()
+                        .push(found);
+                }
+            }
+        }
+    }
+    diagnostics_map
+}
+
 /// Merge flagged entries from parsed beancount data into diagnostics map.
 /// Only includes entries whose flags are in the diagnostic_flags list.
 fn merge_flagged_entries_from_parsed_data(
@@ -215,6 +351,281 @@ fn merge_flagged_entries_from_parsed_data(
     }
 }
 
+/// Reports the includes of `file` that match no file. When the include names a single
+/// file rather than a glob pattern, the diagnostic's `data` holds its `path`, for the
+/// quick fix creating it.
+pub(crate) fn missing_includes(
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+    file: &Path,
+) -> Vec<lsp_types::Diagnostic> {
+    use tree_sitter::StreamingIterator;
+
+    let text = content.to_string();
+    let mut cursor = tree_sitter::QueryCursor::new();
+    let mut matches = cursor.matches(
+        crate::queries::get_include_query(),
+        tree.root_node(),
+        text.as_bytes(),
+    );
+
+    let mut diagnostics = Vec::new();
+    while let Some(qmatch) = matches.next() {
+        for capture in qmatch.captures {
+            let Ok(filename) = capture.node.utf8_text(text.as_bytes()) else {
+                continue;
+            };
+            let filename = filename.trim_matches('"');
+            let pattern = forest::resolve_include_pattern(filename, file);
+            let pattern = pattern.to_string_lossy();
+            let found = glob::glob(&pattern)
+                .map(|mut paths| paths.any(|path| path.is_ok()))
+                .unwrap_or(false);
+            if found {
+                continue;
+            }
+
+            let is_glob = filename.contains(['*', '?', '[']);
+            diagnostics.push(lsp_types::Diagnostic {
+                range: tree_sitter_node_to_lsp_range(content, &capture.node),
+                message: format!("Included file not found: {filename}"),
+                severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+                source: Some("beancount-lsp".to_string()),
+                code: Some(lsp_types::NumberOrString::String(
+                    MISSING_INCLUDE.to_string(),
+                )),
+                data: (!is_glob).then(|| serde_json::json!({ "path": pattern })),
+                ..lsp_types::Diagnostic::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Reports the `pushtag`s of a file that are never popped and the `poptag`s of tags
+/// not pushed before, which includes popping a different tag than the one pushed.
+/// Beancount balances them per file, so included files don't inherit pushed tags.
+///
+/// The diagnostic's `data` holds the `edit` inserting the missing counterpart: a
+/// `poptag` at the end of the file, or a `pushtag` after the previous `poptag` of the
+/// tag, or else at the start of the file.
+pub(crate) fn unbalanced_tags(
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+) -> Vec<lsp_types::Diagnostic> {
+    fn tag_directives<'a>(node: tree_sitter::Node<'a>, out: &mut Vec<tree_sitter::Node<'a>>) {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            match child.kind() {
+                // Org-mode style headlines nest directives
+                "section" => tag_directives(child, out),
+                "pushtag" | "poptag" => out.push(child),
+                _ => {}
+            }
+        }
+    }
+
+    let diagnostic = |node: &tree_sitter::Node, message: String, edit: lsp_types::TextEdit| {
+        lsp_types::Diagnostic {
+            range: tree_sitter_node_to_lsp_range(content, node),
+            message,
+            severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+            source: Some("beancount-lsp".to_string()),
+            code: Some(lsp_types::NumberOrString::String(
+                UNBALANCED_TAG.to_string(),
+            )),
+            data: Some(serde_json::json!({ "edit": edit })),
+            ..lsp_types::Diagnostic::default()
+        }
+    };
+
+    let mut directives = Vec::new();
+    tag_directives(tree.root_node(), &mut directives);
+
+    let mut diagnostics = Vec::new();
+    let mut pushed: Vec<(String, tree_sitter::Node)> = Vec::new();
+    // The line after the last `poptag` of each tag
+    let mut after_pop: HashMap<String, usize> = HashMap::new();
+    for directive in directives {
+        let mut cursor = directive.walk();
+        let Some(tag) = directive
+            .named_children(&mut cursor)
+            .find(|tag| tag.kind() == "tag")
+        else {
+            continue;
+        };
+        let tag = crate::treesitter_utils::text_for_tree_sitter_node(content, &tag);
+        if directive.kind() == "pushtag" {
+            pushed.push((tag, directive));
+            continue;
+        }
+        match pushed.iter().rposition(|(pushed, _)| *pushed == tag) {
+            Some(i) => {
+                pushed.remove(i);
+            }
+            None => {
+                let line = after_pop.get(&tag).copied().unwrap_or(0);
+                let edit = crate::providers::import::insert_at_line(
+                    content,
+                    line,
+                    format!("pushtag {tag}\n"),
+                );
+                diagnostics.push(diagnostic(
+                    &directive,
+                    format!("poptag {tag} without a matching pushtag"),
+                    edit,
+                ));
+            }
+        }
+        // The directive's node may end at the start of the next line
+        let end = directive.end_position();
+        after_pop.insert(tag, end.row + usize::from(end.column > 0));
+    }
+
+    for (tag, directive) in pushed {
+        let edit = crate::providers::import::insert_at_line(
+            content,
+            content.len_lines(),
+            format!("poptag {tag}\n"),
+        );
+        diagnostics.push(diagnostic(
+            &directive,
+            format!("pushtag {tag} is never popped"),
+            edit,
+        ));
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.range.start);
+    diagnostics
+}
+
+/// Reports the dates of a file that are not on the calendar, like `2024-02-30`. The
+/// grammar accepts any day up to 31, and bean-check's errors for these don't say what
+/// is wrong. A directive dated in a month past 12 fails to parse, so the start of each
+/// syntax error is checked for such a date too.
+pub(crate) fn invalid_dates(
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+) -> Vec<lsp_types::Diagnostic> {
+    fn candidates<'a>(node: tree_sitter::Node<'a>, out: &mut Vec<tree_sitter::Node<'a>>) {
+        if node.kind() == "date" || node.is_error() {
+            out.push(node);
+            return;
+        }
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            candidates(child, out);
+        }
+    }
+
+    let mut nodes = Vec::new();
+    candidates(tree.root_node(), &mut nodes);
+
+    let mut diagnostics = Vec::new();
+    for node in nodes {
+        let start = node.start_position();
+        let Some(line) = content.get_line(start.row) else {
+            continue;
+        };
+        let line = line.to_string();
+        let Some(date) = line.get(start.column..start.column + 10) else {
+            continue;
+        };
+        let is_date_shaped = date.char_indices().all(|(i, c)| match i {
+            4 | 7 => c == '-' || c == '/',
+            _ => c.is_ascii_digit(),
+        });
+        if !is_date_shaped {
+            continue;
+        }
+        let Some(problem) = date_problem(date) else {
+            continue;
+        };
+        let end = tree_sitter::Point::new(start.row, start.column + date.len());
+        diagnostics.push(lsp_types::Diagnostic {
+            range: lsp_types::Range::new(
+                tree_sitter_point_to_lsp_position(content, start),
+                tree_sitter_point_to_lsp_position(content, end),
+            ),
+            message: format!("Invalid date {date}: {problem}"),
+            severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+            source: Some("beancount-lsp".to_string()),
+            ..lsp_types::Diagnostic::default()
+        });
+    }
+    diagnostics
+}
+
+/// Warns about the directives of a file dated more than `horizon_days` after `today`,
+/// which catches a year typed wrong in January.
+pub(crate) fn future_dates(
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+    today: chrono::NaiveDate,
+    horizon_days: u32,
+) -> Vec<lsp_types::Diagnostic> {
+    fn directives<'a>(node: tree_sitter::Node<'a>, out: &mut Vec<tree_sitter::Node<'a>>) {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            if child.kind() == "section" {
+                directives(child, out);
+            } else if let Some(date) = child.child_by_field_name("date") {
+                out.push(date);
+            }
+        }
+    }
+
+    let Some(horizon) = today.checked_add_days(chrono::Days::new(horizon_days.into())) else {
+        return Vec::new();
+    };
+    let mut dates = Vec::new();
+    directives(tree.root_node(), &mut dates);
+
+    dates
+        .into_iter()
+        .filter_map(|node| {
+            let text = crate::treesitter_utils::text_for_tree_sitter_node(content, &node);
+            let date =
+                chrono::NaiveDate::parse_from_str(&text.replace('/', "-"), "%Y-%m-%d").ok()?;
+            if date <= horizon {
+                return None;
+            }
+            let days = (date - today).num_days();
+            Some(lsp_types::Diagnostic {
+                range: tree_sitter_node_to_lsp_range(content, &node),
+                message: format!("Dated {days} days in the future"),
+                severity: Some(lsp_types::DiagnosticSeverity::WARNING),
+                source: Some("beancount-lsp".to_string()),
+                ..lsp_types::Diagnostic::default()
+            })
+        })
+        .collect()
+}
+
+/// What makes `date`, `YYYY-MM-DD` or `YYYY/MM/DD`, not a day of the calendar.
+fn date_problem(date: &str) -> Option<String> {
+    use chrono::Datelike;
+
+    let mut parts = date.split(['-', '/']).map(|part| part.parse::<u32>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    let year = i32::try_from(year).ok()?;
+    let Some(first) = chrono::NaiveDate::from_ymd_opt(year, month, 1) else {
+        return Some(format!("there is no month {month}"));
+    };
+    if chrono::NaiveDate::from_ymd_opt(year, month, day).is_some() {
+        return None;
+    }
+    let days = first
+        .checked_add_months(chrono::Months::new(1))
+        .and_then(|next| next.pred_opt())
+        .map_or(31, |last| last.day());
+    let name = first.format("%B %Y");
+    Some(format!("{name} has {days} days"))
+}
+
 /// Build a full-line range starting at column 0 to a very large column value.
 fn full_line_range(line: u32) -> lsp_types::Range {
     lsp_types::Range {
@@ -235,6 +646,204 @@ mod tests {
     use tempfile::TempDir;
     use tree_sitter_beancount;
 
+    #[test]
+    fn test_missing_includes() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("accounts.beancount"), "").unwrap();
+        let main = temp_dir.path().join("main.beancount");
+        let content = "include \"accounts.beancount\"\ninclude \"2024.beancount\"\ninclude \"imports/*.beancount\"\n";
+
+        let mut parser = tree_sitter_beancount::tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let diagnostics = missing_includes(&tree, &ropey::Rope::from_str(content), &main);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].message,
+            "Included file not found: 2024.beancount"
+        );
+        assert_eq!(diagnostics[0].range.start, lsp_types::Position::new(1, 8));
+        assert_eq!(diagnostics[0].range.end, lsp_types::Position::new(1, 24));
+        assert_eq!(
+            diagnostics[0].data,
+            Some(serde_json::json!({
+                "path": temp_dir.path().join("2024.beancount").to_string_lossy()
+            }))
+        );
+        assert_eq!(
+            diagnostics[1].message,
+            "Included file not found: imports/*.beancount"
+        );
+        assert_eq!(diagnostics[1].data, None);
+    }
+
+    #[test]
+    fn test_unbalanced_tags() {
+        let content = "pushtag #trip\n\n2024-01-01 * \"Taxi\"\n  Expenses:Taxi  10 USD\n  Assets:Cash\n\npoptag #trip\npoptag #trip\npushtag #work\npoptag #home";
+        let mut parser = tree_sitter_beancount::tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let diagnostics = unbalanced_tags(&tree, &ropey::Rope::from_str(content));
+
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (7, "poptag #trip without a matching pushtag"),
+                (8, "pushtag #work is never popped"),
+                (9, "poptag #home without a matching pushtag"),
+            ]
+        );
+
+        let edit = |diagnostic: &lsp_types::Diagnostic| {
+            serde_json::from_value::<lsp_types::TextEdit>(
+                diagnostic.data.as_ref().unwrap()["edit"].clone(),
+            )
+            .unwrap()
+        };
+        // After the earlier poptag of the tag, or at the start of the file
+        assert_eq!(
+            edit(&diagnostics[0]).range.start,
+            lsp_types::Position::new(7, 0)
+        );
+        assert_eq!(edit(&diagnostics[0]).new_text, "pushtag #trip\n");
+        assert_eq!(
+            edit(&diagnostics[2]).range.start,
+            lsp_types::Position::new(0, 0)
+        );
+        // At the end of the file, which lacks a final newline
+        assert_eq!(
+            edit(&diagnostics[1]).range.start,
+            lsp_types::Position::new(9, 12)
+        );
+        assert_eq!(edit(&diagnostics[1]).new_text, "\npoptag #work\n");
+    }
+
+    #[test]
+    fn test_invalid_dates() {
+        let content = "2024-02-29 open Assets:Cash\n2023-02-29 open Assets:Bank\n2024-13-01 close Assets:Cash\n2024/04/31 * \"Rent\"\n  Assets:Bank  -10 USD\n  Expenses:Rent\n";
+        let mut parser = tree_sitter_beancount::tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let diagnostics = invalid_dates(&tree, &ropey::Rope::from_str(content));
+
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (1, "Invalid date 2023-02-29: February 2023 has 28 days"),
+                (2, "Invalid date 2024-13-01: there is no month 13"),
+                (3, "Invalid date 2024/04/31: April 2024 has 30 days"),
+            ]
+        );
+        assert_eq!(diagnostics[1].range.start, lsp_types::Position::new(2, 0));
+        assert_eq!(diagnostics[1].range.end, lsp_types::Position::new(2, 10));
+    }
+
+    #[test]
+    fn test_posting_lints() {
+        let content = r#"2024-01-05 * "Shop" "Refund"
+  Expenses:Food  -5.00 USD
+  Assets:Cash
+2024-01-06 * "Employer" "Salary"
+  Income:Salary  -100 USD
+  Assets:Bank  100 USD
+  Expenses:Fees  0 USD
+2024-01-07 * "Employer" "Correction"
+  Income:Salary  3 USD
+  Assets:Bank
+"#;
+        let path = PathBuf::from("/tmp/lints.beancount");
+        let mut parser = tree_sitter_beancount::tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let data = BeancountData::new(&tree, &ropey::Rope::from_str(content));
+        let beancount_data = HashMap::from([(path.clone(), Arc::new(data))]);
+        let options = LedgerOptions::default();
+
+        let mut lints = PostingLintsConfig::default();
+        assert!(posting_lints(&beancount_data, &options, &lints).is_empty());
+
+        lints.zero_amount = crate::config::LintSeverity::Warning;
+        lints.sign_convention = crate::config::LintSeverity::Hint;
+        let mut diagnostics = posting_lints(&beancount_data, &options, &lints)
+            .remove(&path)
+            .unwrap();
+        diagnostics.sort_by_key(|diagnostic| diagnostic.range.start);
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.range.start.line,
+                    diagnostic.message.as_str(),
+                    diagnostic.severity.unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    1,
+                    "Negative amount posted to Expenses:Food, which is usually positive",
+                    lsp_types::DiagnosticSeverity::HINT
+                ),
+                (
+                    6,
+                    "Zero amount posted to Expenses:Fees",
+                    lsp_types::DiagnosticSeverity::WARNING
+                ),
+                (
+                    8,
+                    "Positive amount posted to Income:Salary, which is usually negative",
+                    lsp_types::DiagnosticSeverity::HINT
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_future_dates() {
+        let content = "2024-01-01 open Assets:Cash\n* Later\n2024-01-31 balance Assets:Cash 0 USD\n2025-01-05 * \"Typo\"\n  Assets:Cash  -5 USD\n  Expenses:Food\n";
+        let mut parser = tree_sitter_beancount::tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let content = ropey::Rope::from_str(content);
+
+        let diagnostics = future_dates(&tree, &content, today, 30);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Dated 361 days in the future");
+        assert_eq!(diagnostics[0].range.start, lsp_types::Position::new(3, 0));
+        assert_eq!(
+            diagnostics[0].severity,
+            Some(lsp_types::DiagnosticSeverity::WARNING)
+        );
+
+        let found: Vec<_> = future_dates(&tree, &content, today, 0)
+            .iter()
+            .map(|diagnostic| diagnostic.range.start.line)
+            .collect();
+        assert_eq!(found, [2, 3]);
+    }
+
     /// Helper to create a temporary beancount file for testing
     fn create_temp_beancount_file(content: &str) -> (TempDir, PathBuf) {
         let temp_dir = TempDir::new().expect("Failed to create temp directory");
@@ -305,7 +914,13 @@ mod tests {
         let mock_bean_check = create_mock_bean_check_success();
         let checker = SystemCallChecker::new(mock_bean_check);
 
-        let result = diagnostics(beancount_data, &checker, &file_path, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         assert!(
             result.is_empty(),
@@ -322,7 +937,13 @@ mod tests {
         let mock_bean_check = create_mock_bean_check_with_errors();
         let checker = SystemCallChecker::new(mock_bean_check);
 
-        let result = diagnostics(beancount_data, &checker, &file_path, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         // Since /bin/false doesn't output structured errors, we expect empty result
         // but the test verifies that the function handles command failures gracefully
@@ -343,7 +964,13 @@ mod tests {
         let mock_bean_check = create_mock_bean_check_success();
         let checker = SystemCallChecker::new(mock_bean_check);
 
-        let result = diagnostics(beancount_data, &checker, &file_path, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         assert!(
             !result.is_empty(),
@@ -389,7 +1016,13 @@ mod tests {
         let mock_bean_check = create_mock_bean_check_with_errors();
         let checker = SystemCallChecker::new(mock_bean_check);
 
-        let result = diagnostics(beancount_data, &checker, &file_path, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         assert!(
             !result.is_empty(),
@@ -425,7 +1058,13 @@ mod tests {
         let invalid_command = PathBuf::from("/nonexistent/command/that/does/not/exist");
         let checker = SystemCallChecker::new(invalid_command);
 
-        let result = diagnostics(beancount_data, &checker, &file_path, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         assert!(
             result.is_empty(),
@@ -442,7 +1081,13 @@ mod tests {
         let mock_bean_check = create_mock_bean_check_with_errors();
         let checker = SystemCallChecker::new(mock_bean_check);
 
-        let result = diagnostics(beancount_data, &checker, &file_path, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         // Should handle command failures gracefully (no panics)
         assert!(
@@ -469,7 +1114,13 @@ mod tests {
         let mock_bean_check = create_mock_bean_check_success();
         let checker = SystemCallChecker::new(mock_bean_check);
 
-        let result = diagnostics(beancount_data, &checker, &file_path1, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path1,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         // Should have diagnostics for files with flagged entries
         assert!(
@@ -520,7 +1171,13 @@ mod tests {
         let mock_bean_check = create_mock_bean_check_success();
         let checker = SystemCallChecker::new(mock_bean_check);
 
-        let result = diagnostics(beancount_data, &checker, &file_path, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         assert!(
             result.is_empty(),
@@ -537,7 +1194,13 @@ mod tests {
         let mock_bean_check = create_mock_bean_check_success();
         let checker = SystemCallChecker::new(mock_bean_check);
 
-        let result = diagnostics(beancount_data, &checker, &file_path, &["!".to_string()]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &["!".to_string()],
+            &LedgerOptions::default(),
+        );
 
         // Since we're not testing actual bean-check error parsing here,
         // we just verify that the function works without crashing
@@ -590,6 +1253,7 @@ mod tests {
             &checker,
             &file_path,
             &["!".to_string()],
+            &LedgerOptions::default(),
         );
 
         // Should have diagnostic for '!' but not for 'P'
@@ -632,6 +1296,7 @@ mod tests {
             &checker,
             &file_path,
             &["!".to_string(), "P".to_string()],
+            &LedgerOptions::default(),
         );
 
         // Should have diagnostics for both flags
@@ -661,7 +1326,13 @@ mod tests {
         let checker = SystemCallChecker::new(mock_bean_check);
 
         // Test with empty diagnostic_flags
-        let result = diagnostics(beancount_data, &checker, &file_path, &[]);
+        let result = diagnostics(
+            beancount_data,
+            &checker,
+            &file_path,
+            &[],
+            &LedgerOptions::default(),
+        );
 
         // Should have no diagnostics when diagnostic_flags is empty
         assert!(
@@ -669,4 +1340,27 @@ mod tests {
             "Should have no diagnostics with empty diagnostic_flags"
         );
     }
+
+    #[test]
+    fn test_invalid_account_root_follows_name_options() {
+        use crate::checkers::SystemCallChecker;
+
+        let content = r#"option "name_assets" "Vermoegen"
+2023-01-01 open Vermoegen:Bar
+2023-01-01 open Assets:Cash"#;
+
+        let (_temp_dir, file_path) = create_temp_beancount_file(content);
+        let beancount_data = create_mock_beancount_data_with_flags(&file_path, content);
+        let options = LedgerOptions::from_data(&beancount_data[&file_path]);
+        let mock_bean_check = create_mock_bean_check_success();
+        let checker = SystemCallChecker::new(mock_bean_check);
+
+        let result = diagnostics(beancount_data, &checker, &file_path, &[], &options);
+
+        let diags = &result[&file_path];
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].range.start.line, 2);
+        assert!(diags[0].message.contains("Assets:Cash"));
+        assert!(diags[0].message.contains("Vermoegen"));
+    }
 }
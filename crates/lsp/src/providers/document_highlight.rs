@@ -0,0 +1,153 @@
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::{
+    lsp_position_to_tree_sitter_point_range, text_for_tree_sitter_node,
+    tree_sitter_node_to_lsp_range,
+};
+use anyhow::Result;
+use lsp_types::{DocumentHighlight, DocumentHighlightKind, DocumentHighlightParams};
+use ropey::Rope;
+use tree_sitter_beancount::{NodeKind, tree_sitter};
+
+/// Provider function for `textDocument/documentHighlight`.
+///
+/// Highlights the other occurrences in the file of the account, tag or link under the
+/// cursor. The `open` of an account and the `pushtag`/`poptag` pair scoping a tag are
+/// marked as writes, everything else as reads.
+pub(crate) fn document_highlight(
+    snapshot: LspServerStateSnapshot,
+    params: DocumentHighlightParams,
+) -> Result<Option<Vec<DocumentHighlight>>> {
+    let position = params.text_document_position_params;
+    let (tree, doc) = match snapshot.tree_and_document_for_uri(&position.text_document.uri) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::debug!("Document highlight: failed to get tree/document for uri: {e}");
+            return Ok(None);
+        }
+    };
+    let content = &doc.content;
+    let (start, end) = lsp_position_to_tree_sitter_point_range(content, position.position)?;
+    let Some(node) = tree
+        .root_node()
+        .named_descendant_for_point_range(start, end)
+    else {
+        return Ok(None);
+    };
+    if !matches!(
+        NodeKind::from(node.kind()),
+        NodeKind::Account | NodeKind::Tag | NodeKind::Link
+    ) {
+        return Ok(None);
+    }
+
+    let text = text_for_tree_sitter_node(content, &node);
+    let mut highlights = Vec::new();
+    collect_highlights(
+        tree.root_node(),
+        node.kind(),
+        &text,
+        content,
+        &mut highlights,
+    );
+    Ok(Some(highlights))
+}
+
+fn collect_highlights(
+    node: tree_sitter::Node,
+    kind: &str,
+    text: &str,
+    content: &Rope,
+    highlights: &mut Vec<DocumentHighlight>,
+) {
+    if node.kind() == kind {
+        if text_for_tree_sitter_node(content, &node) == text {
+            let declaration = node
+                .parent()
+                .is_some_and(|parent| matches!(parent.kind(), "open" | "pushtag" | "poptag"));
+            highlights.push(DocumentHighlight {
+                range: tree_sitter_node_to_lsp_range(content, &node),
+                kind: Some(if declaration {
+                    DocumentHighlightKind::WRITE
+                } else {
+                    DocumentHighlightKind::READ
+                }),
+            });
+        }
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        collect_highlights(child, kind, text, content, highlights);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::utils::file_path_to_uri;
+
+    const CONTENT: &str = r#"2024-01-01 open Assets:Cash
+pushtag #trip
+2024-01-02 * "Hotel" #trip ^booking-1
+  Expenses:Travel  80 EUR
+  Assets:Cash
+poptag #trip
+2024-01-03 * "Refund" #tripod ^booking-1
+  Assets:Cash  10 EUR
+  Expenses:Travel
+"#;
+
+    fn highlights(line: u32, character: u32) -> Option<Vec<(u32, u32, DocumentHighlightKind)>> {
+        let path = std::env::temp_dir().join("highlight.beancount");
+        let snapshot =
+            LspServerStateSnapshot::for_test(Config::new(path.clone()), &[(&path, CONTENT)]);
+        let params = DocumentHighlightParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams::new(
+                lsp_types::TextDocumentIdentifier::new(file_path_to_uri(&path).unwrap()),
+                lsp_types::Position::new(line, character),
+            ),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        document_highlight(snapshot, params)
+            .unwrap()
+            .map(|highlights| {
+                highlights
+                    .into_iter()
+                    .map(|h| (h.range.start.line, h.range.start.character, h.kind.unwrap()))
+                    .collect()
+            })
+    }
+
+    #[test]
+    fn test_highlight_tag_with_pushtag_and_poptag() {
+        let expected = vec![
+            (1, 8, DocumentHighlightKind::WRITE),
+            (2, 21, DocumentHighlightKind::READ),
+            (5, 7, DocumentHighlightKind::WRITE),
+        ];
+        assert_eq!(highlights(2, 23), Some(expected.clone()));
+        assert_eq!(highlights(1, 10), Some(expected));
+    }
+
+    #[test]
+    fn test_highlight_link_and_account() {
+        assert_eq!(
+            highlights(6, 33),
+            Some(vec![
+                (2, 27, DocumentHighlightKind::READ),
+                (6, 30, DocumentHighlightKind::READ),
+            ])
+        );
+        assert_eq!(
+            highlights(4, 4),
+            Some(vec![
+                (0, 16, DocumentHighlightKind::WRITE),
+                (4, 2, DocumentHighlightKind::READ),
+                (7, 2, DocumentHighlightKind::READ),
+            ])
+        );
+        assert_eq!(highlights(2, 13), None);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::server::LspServerStateSnapshot;
-use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::{text_for_tree_sitter_node, tree_sitter_node_to_lsp_range};
 use crate::utils::ToFilePath;
 use anyhow::Result;
 use lsp_types::{DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, SymbolKind};
@@ -113,8 +113,8 @@ fn extract_transaction_symbol(node: &Node, content: &Rope) -> Option<DocumentSym
         name,
         detail: Some("Transaction".to_string()),
         kind: SymbolKind::STRUCT,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: if postings.is_empty() {
             None
         } else {
@@ -155,8 +155,8 @@ fn extract_posting_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbol>
         name,
         detail: Some("Posting".to_string()),
         kind: SymbolKind::PROPERTY,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: None,
         #[allow(deprecated)]
         deprecated: None,
@@ -192,8 +192,8 @@ fn extract_open_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbol> {
         name: account,
         detail: Some(detail),
         kind: SymbolKind::FILE,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: None,
         #[allow(deprecated)]
         deprecated: None,
@@ -217,8 +217,8 @@ fn extract_close_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbol> {
         name: account,
         detail: Some("Close".to_string()),
         kind: SymbolKind::FILE,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: None,
         #[allow(deprecated)]
         deprecated: None,
@@ -254,8 +254,8 @@ fn extract_balance_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbol>
         name,
         detail: Some("Balance".to_string()),
         kind: SymbolKind::CONSTANT,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: None,
         #[allow(deprecated)]
         deprecated: None,
@@ -293,8 +293,8 @@ fn extract_price_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbol> {
         name,
         detail: Some("Price".to_string()),
         kind: SymbolKind::NUMBER,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: None,
         #[allow(deprecated)]
         deprecated: None,
@@ -318,8 +318,8 @@ fn extract_commodity_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbo
         name: currency,
         detail: Some("Commodity".to_string()),
         kind: SymbolKind::CLASS,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: None,
         #[allow(deprecated)]
         deprecated: None,
@@ -356,8 +356,8 @@ fn extract_event_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbol> {
         name,
         detail: Some("Event".to_string()),
         kind: SymbolKind::EVENT,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: None,
         #[allow(deprecated)]
         deprecated: None,
@@ -387,8 +387,8 @@ fn extract_option_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbol>
         name,
         detail: Some("Option".to_string()),
         kind: SymbolKind::PROPERTY,
-        range: node_to_range(node),
-        selection_range: node_to_range(node),
+        range: tree_sitter_node_to_lsp_range(content, node),
+        selection_range: tree_sitter_node_to_lsp_range(content, node),
         children: None,
         #[allow(deprecated)]
         deprecated: None,
@@ -396,31 +396,12 @@ fn extract_option_symbol(node: &Node, content: &Rope) -> Option<DocumentSymbol>
     })
 }
 
-/// Convert a tree-sitter node to an LSP Range.
-fn node_to_range(node: &Node) -> lsp_types::Range {
-    lsp_types::Range {
-        start: lsp_types::Position {
-            line: node.start_position().row as u32,
-            character: node.start_position().column as u32,
-        },
-        end: lsp_types::Position {
-            line: node.end_position().row as u32,
-            character: node.end_position().column as u32,
-        },
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::beancount_data::BeancountData;
     use crate::config::Config;
-    use crate::document::Document;
-    use std::collections::HashMap;
     use std::path::PathBuf;
     use std::str::FromStr;
-    use std::sync::Arc;
-    use tree_sitter_beancount::tree_sitter;
     use url::Url;
 
     struct TestState {
@@ -431,40 +412,10 @@ mod tests {
     impl TestState {
         fn new(content: &str) -> anyhow::Result<Self> {
             let path = std::env::current_dir()?.join("test.beancount");
-            let rope_content = ropey::Rope::from_str(content);
-
-            let mut parser = tree_sitter::Parser::new();
-            parser.set_language(&tree_sitter_beancount::language())?;
-            let tree = parser.parse(content, None).unwrap();
-
-            let mut forest = HashMap::new();
-            forest.insert(path.clone(), Arc::new(tree.clone()));
-
-            let mut open_docs = HashMap::new();
-            open_docs.insert(
-                path.clone(),
-                Document {
-                    content: rope_content.clone(),
-                    version: 0,
-                },
-            );
-
-            let mut beancount_data = HashMap::new();
-            beancount_data.insert(
-                path.clone(),
-                Arc::new(BeancountData::new(&tree, &rope_content)),
-            );
-
             let config = Config::new(path.clone());
 
             Ok(Self {
-                snapshot: LspServerStateSnapshot {
-                    forest,
-                    open_docs,
-                    beancount_data,
-                    config,
-                    checker: None,
-                },
+                snapshot: LspServerStateSnapshot::for_test(config, &[(&path, content)]),
                 path,
             })
         }
@@ -0,0 +1,206 @@
+//! Links into Fava, the web interface for beancount
+//!
+//! The base URL is the configured `fava_url`, or Fava's default address with the slug
+//! Fava derives from the journal title. Accounts link to their account page, tags and
+//! links (also from anywhere in a transaction carrying one) to the journal filtered by
+//! them.
+
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::{lsp_position_to_tree_sitter_point_range, text_for_tree_sitter_node};
+use crate::utils::ToFilePath;
+use anyhow::Result;
+use lsp_types::TextDocumentPositionParams;
+use tree_sitter_beancount::{NodeKind, tree_sitter};
+
+/// Address `fava` serves on when started without `--port`.
+const DEFAULT_FAVA_ADDRESS: &str = "http://localhost:5000";
+
+/// Provider function for the `beancount.favaLink` command: the Fava page of the symbol
+/// at `position`, if there is one.
+pub(crate) fn fava_link(
+    snapshot: &LspServerStateSnapshot,
+    position: &TextDocumentPositionParams,
+) -> Result<Option<String>> {
+    let (tree, doc) = snapshot.tree_and_document_for_uri(&position.text_document.uri)?;
+    let (start, end) = lsp_position_to_tree_sitter_point_range(&doc.content, position.position)?;
+    let Some(node) = tree
+        .root_node()
+        .named_descendant_for_point_range(start, end)
+    else {
+        return Ok(None);
+    };
+    Ok(page_for_node(&doc.content, node)
+        .map(|page| format!("{}/{page}", base_url(snapshot, Some(position)))))
+}
+
+/// The URL opened by the `beancount.openFava` command: the Fava page of the symbol at
+/// `position`, or else the start page.
+pub(crate) fn open_fava_url(
+    snapshot: &LspServerStateSnapshot,
+    position: Option<&TextDocumentPositionParams>,
+) -> Result<String> {
+    if let Some(position) = position
+        && let Some(url) = fava_link(snapshot, position)?
+    {
+        return Ok(url);
+    }
+    Ok(format!("{}/", base_url(snapshot, position)))
+}
+
+fn base_url(
+    snapshot: &LspServerStateSnapshot,
+    position: Option<&TextDocumentPositionParams>,
+) -> String {
+    if let Some(url) = &snapshot.config.fava_url {
+        return url.clone();
+    }
+    let options = match position.and_then(|p| p.text_document.uri.to_file_path().ok()) {
+        Some(file) => snapshot.ledger_options_for(&file),
+        None => snapshot.ledger_options(),
+    };
+    // Beancount's default title
+    let title = options.title.as_deref().unwrap_or("Beancount");
+    format!("{DEFAULT_FAVA_ADDRESS}/{}", slugify(title))
+}
+
+/// The page path, relative to the ledger's base URL, for the symbol `node` is part of.
+fn page_for_node(content: &ropey::Rope, node: tree_sitter::Node) -> Option<String> {
+    let mut current = Some(node);
+    while let Some(node) = current {
+        match NodeKind::from(node.kind()) {
+            NodeKind::Account => {
+                let account = text_for_tree_sitter_node(content, &node);
+                return Some(format!("account/{}/", encode(&account)));
+            }
+            NodeKind::Tag | NodeKind::Link => {
+                let filter = text_for_tree_sitter_node(content, &node);
+                return Some(format!("journal/?filter={}", encode(&filter)));
+            }
+            NodeKind::Transaction => return transaction_link(content, node),
+            _ => current = node.parent(),
+        }
+    }
+    None
+}
+
+/// The journal filtered by the first link of `transaction`, which usually is the
+/// transaction alone.
+fn transaction_link(content: &ropey::Rope, transaction: tree_sitter::Node) -> Option<String> {
+    let mut cursor = transaction.walk();
+    let tags_links = transaction
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "tags_links")?;
+    let mut cursor = tags_links.walk();
+    let link = tags_links
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "link")?;
+    let filter = text_for_tree_sitter_node(content, &link);
+    Some(format!("journal/?filter={}", encode(&filter)))
+}
+
+/// Fava's slug of a ledger title: lowercase words joined by dashes.
+fn slugify(title: &str) -> String {
+    let kept: String = title
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-' || c.is_whitespace())
+        .collect();
+    kept.trim()
+        .to_lowercase()
+        .split(|c: char| c == '-' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Percent-encodes everything but unreserved characters and the `:` of account names.
+fn encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::utils::file_path_to_uri;
+    use std::path::PathBuf;
+
+    const JOURNAL: &str = r#"option "title" "Family Ledger (2024)"
+
+2024-01-05 * "Grocer" "Weekly shopping" #food ^receipt-1
+  Expenses:Food  25.50 USD
+  Assets:Cash
+"#;
+
+    fn snapshot(fava_url: Option<&str>) -> (LspServerStateSnapshot, lsp_types::Uri) {
+        let path = std::env::temp_dir().join("main.beancount");
+        let uri = file_path_to_uri(&path).unwrap();
+        let mut config = Config::new(PathBuf::from("/"));
+        config.journal_root = Some(path.clone());
+        config.fava_url = fava_url.map(str::to_string);
+        let snapshot = LspServerStateSnapshot::for_test(config, &[(&path, JOURNAL)]);
+        (snapshot, uri)
+    }
+
+    fn at(uri: &lsp_types::Uri, line: u32, character: u32) -> TextDocumentPositionParams {
+        TextDocumentPositionParams::new(
+            lsp_types::TextDocumentIdentifier::new(uri.clone()),
+            lsp_types::Position::new(line, character),
+        )
+    }
+
+    #[test]
+    fn test_links_for_symbols() {
+        let (snapshot, uri) = snapshot(None);
+        let link = |line, character| fava_link(&snapshot, &at(&uri, line, character)).unwrap();
+        assert_eq!(
+            link(3, 5).as_deref(),
+            Some("http://localhost:5000/family-ledger-2024/account/Expenses:Food/")
+        );
+        assert_eq!(
+            link(2, 42).as_deref(),
+            Some("http://localhost:5000/family-ledger-2024/journal/?filter=%23food")
+        );
+        // The link of the transaction, from its header and postings
+        for (line, character) in [(2, 50), (2, 0), (3, 20)] {
+            assert_eq!(
+                link(line, character).as_deref(),
+                Some("http://localhost:5000/family-ledger-2024/journal/?filter=%5Ereceipt-1"),
+                "at {line}:{character}"
+            );
+        }
+        assert_eq!(link(0, 3), None);
+    }
+
+    #[test]
+    fn test_open_uses_configured_url() {
+        let (snapshot, uri) = snapshot(Some("http://fava.local/ledger"));
+        assert_eq!(
+            open_fava_url(&snapshot, Some(&at(&uri, 4, 4))).unwrap(),
+            "http://fava.local/ledger/account/Assets:Cash/"
+        );
+        assert_eq!(
+            open_fava_url(&snapshot, Some(&at(&uri, 0, 3))).unwrap(),
+            "http://fava.local/ledger/"
+        );
+        assert_eq!(
+            open_fava_url(&snapshot, None).unwrap(),
+            "http://fava.local/ledger/"
+        );
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Beancount"), "beancount");
+        assert_eq!(slugify("  Family Ledger (2024) "), "family-ledger-2024");
+        assert_eq!(slugify("Haushalt -- Müller"), "haushalt-müller");
+    }
+}
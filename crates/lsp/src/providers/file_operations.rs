@@ -0,0 +1,249 @@
+//! Including files created next to the journal
+//!
+//! A beancount file created inside the journal's directory that no include of the
+//! journal matches yet gets an `include` in the journal, between the includes sorting
+//! around it. With `include_new_files` set to `ask` the user confirms first, with
+//! `always` the edit is applied right away.
+
+use crate::config::IncludeNewFiles;
+use crate::providers::import::insert_at_line;
+use crate::server::LspServerState;
+use crate::utils::{ToFilePath, file_path_to_uri, versioned_workspace_edit};
+use anyhow::Result;
+use lsp_types::{MessageActionItem, TextEdit, WorkspaceEdit};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tree_sitter_beancount::tree_sitter;
+
+const INCLUDE_ACTION: &str = "Include";
+
+/// Handles `workspace/didCreateFiles`.
+pub(crate) fn did_create_files(
+    state: &mut LspServerState,
+    params: lsp_types::CreateFilesParams,
+) -> Result<()> {
+    if state.config.include_new_files == IncludeNewFiles::Off {
+        return Ok(());
+    }
+
+    // The new files of each journal, relative to its directory
+    let mut new_files: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    for file in params.files {
+        let Some(path) = file
+            .uri
+            .parse::<lsp_types::Uri>()
+            .ok()
+            .and_then(|uri| uri.to_file_path().ok())
+        else {
+            continue;
+        };
+        let Some(journal) = state.journal_for(&path) else {
+            continue;
+        };
+        if journal == path || !state.include_graph.includers_matching(&path).is_empty() {
+            continue;
+        }
+        let Some(relative) = journal.parent().and_then(|dir| path.strip_prefix(dir).ok()) else {
+            continue;
+        };
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        new_files.entry(journal).or_default().push(relative);
+    }
+
+    for (journal, mut files) in new_files {
+        files.sort();
+        let Some(content) = journal_content(state, &journal) else {
+            continue;
+        };
+        let Ok(uri) = file_path_to_uri(&journal) else {
+            continue;
+        };
+        let edits = include_edits(&content, &files);
+        let edit = versioned_workspace_edit(
+            &state.open_docs,
+            HashMap::from([(uri, edits)]),
+            state.document_changes_support,
+        );
+
+        match state.config.include_new_files {
+            IncludeNewFiles::Off => {}
+            IncludeNewFiles::Always => apply_edit(state, edit),
+            IncludeNewFiles::Ask => {
+                let name = journal.file_name().map_or_else(
+                    || journal.display().to_string(),
+                    |name| name.to_string_lossy().to_string(),
+                );
+                let message = match files.as_slice() {
+                    [file] => format!("Include {file} in {name}?"),
+                    files => format!("Include {} new files in {name}?", files.len()),
+                };
+                let id = state.send_request::<lsp_types::request::ShowMessageRequest>(
+                    lsp_types::ShowMessageRequestParams {
+                        typ: lsp_types::MessageType::INFO,
+                        message,
+                        actions: Some(vec![MessageActionItem {
+                            title: INCLUDE_ACTION.to_string(),
+                            properties: HashMap::new(),
+                        }]),
+                    },
+                    |state, response| {
+                        let Some(edit) = state.pending_includes.remove(&response.id) else {
+                            return;
+                        };
+                        let choice = response
+                            .result
+                            .and_then(|result| {
+                                serde_json::from_value::<Option<MessageActionItem>>(result).ok()
+                            })
+                            .flatten();
+                        if choice.is_some_and(|choice| choice.title == INCLUDE_ACTION) {
+                            apply_edit(state, edit);
+                        }
+                    },
+                );
+                state.pending_includes.insert(id, edit);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The text of the journal as the client sees it.
+fn journal_content(state: &LspServerState, journal: &Path) -> Option<ropey::Rope> {
+    if let Some(doc) = state.open_docs.get(journal) {
+        return Some(doc.content.clone());
+    }
+    match state.sources.get(journal) {
+        Some(text) => Some(text.clone()),
+        None => std::fs::read_to_string(journal)
+            .ok()
+            .map(|text| ropey::Rope::from_str(&text)),
+    }
+}
+
+fn apply_edit(state: &mut LspServerState, edit: WorkspaceEdit) {
+    state.send_request::<lsp_types::request::ApplyWorkspaceEdit>(
+        lsp_types::ApplyWorkspaceEditParams {
+            label: Some("Include new file".to_string()),
+            edit,
+        },
+        |_state, response| {
+            if let Some(error) = response.error {
+                tracing::warn!("Failed to include new file: {}", error.message);
+            }
+        },
+    );
+}
+
+/// Edits adding an include of each of `files` to the journal `content`, before the
+/// first include sorting after it, after the last include, or at the end of the file.
+fn include_edits(content: &ropey::Rope, files: &[String]) -> Vec<TextEdit> {
+    use tree_sitter::StreamingIterator;
+
+    let text = content.to_string();
+    let mut parser = tree_sitter::Parser::new();
+    if parser
+        .set_language(&tree_sitter_beancount::language())
+        .is_err()
+    {
+        return Vec::new();
+    }
+    let Some(tree) = parser.parse(&text, None) else {
+        return Vec::new();
+    };
+
+    // Each include's file name with the lines it spans
+    let mut includes: Vec<(String, usize, usize)> = Vec::new();
+    let mut cursor = tree_sitter::QueryCursor::new();
+    let mut matches = cursor.matches(
+        crate::queries::get_include_query(),
+        tree.root_node(),
+        text.as_bytes(),
+    );
+    while let Some(qmatch) = matches.next() {
+        for capture in qmatch.captures {
+            let Some(include) = capture.node.parent() else {
+                continue;
+            };
+            let Ok(filename) = capture.node.utf8_text(text.as_bytes()) else {
+                continue;
+            };
+            let end = include.end_position();
+            let end_line = if end.column == 0 {
+                end.row
+            } else {
+                end.row + 1
+            };
+            includes.push((
+                filename.trim_matches('"').to_string(),
+                include.start_position().row,
+                end_line,
+            ));
+        }
+    }
+
+    files
+        .iter()
+        .map(|file| {
+            let line = format!("include \"{file}\"\n");
+            match includes.iter().find(|(included, ..)| included > file) {
+                Some((_, start, _)) => insert_at_line(content, *start, line),
+                None => match includes.last() {
+                    Some((_, _, end)) => insert_at_line(content, *end, line),
+                    None if content.len_chars() == 0 => insert_at_line(content, 0, line),
+                    None => insert_at_line(content, content.len_lines(), format!("\n{line}")),
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(content: &str, files: &[&str]) -> String {
+        let rope = ropey::Rope::from_str(content);
+        let files: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+        let mut edits = include_edits(&rope, &files);
+        // Apply from the end so earlier positions stay valid
+        edits.reverse();
+        let mut result = rope.clone();
+        for edit in edits {
+            let line = edit.range.start.line as usize;
+            let idx = if line < result.len_lines() {
+                result.line_to_char(line) + edit.range.start.character as usize
+            } else {
+                result.len_chars()
+            };
+            result.insert(idx, &edit.new_text);
+        }
+        result.to_string()
+    }
+
+    #[test]
+    fn test_include_sorted_among_includes() {
+        let content = "option \"title\" \"Ledger\"\n\ninclude \"2023.beancount\"\ninclude \"2025.beancount\"\n\n2024-01-01 open Assets:Cash\n";
+        assert_eq!(
+            apply(content, &["2024.beancount"]),
+            "option \"title\" \"Ledger\"\n\ninclude \"2023.beancount\"\ninclude \"2024.beancount\"\ninclude \"2025.beancount\"\n\n2024-01-01 open Assets:Cash\n"
+        );
+        assert_eq!(
+            apply(content, &["2026.beancount"]),
+            "option \"title\" \"Ledger\"\n\ninclude \"2023.beancount\"\ninclude \"2025.beancount\"\ninclude \"2026.beancount\"\n\n2024-01-01 open Assets:Cash\n"
+        );
+    }
+
+    #[test]
+    fn test_include_appended_without_includes() {
+        assert_eq!(
+            apply("2024-01-01 open Assets:Cash", &["accounts/bank.beancount"]),
+            "2024-01-01 open Assets:Cash\n\ninclude \"accounts/bank.beancount\"\n"
+        );
+        assert_eq!(apply("", &["a.beancount"]), "include \"a.beancount\"\n");
+    }
+}
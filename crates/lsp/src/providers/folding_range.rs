@@ -57,6 +57,9 @@ pub(crate) fn folding_ranges(
     // Process directive groups (consecutive similar directives)
     ranges.extend(fold_directive_groups(&children));
 
+    // Process calendar periods (years and months of a date-sorted file)
+    ranges.extend(fold_periods(&children, &content));
+
     // Sort ranges by start line for better client handling
     ranges.sort_by_key(|r| r.start_line);
 
@@ -300,6 +303,62 @@ fn fold_directive_groups(nodes: &[Node]) -> Vec<FoldingRange> {
     ranges
 }
 
+/// Fold the years and months of a file whose directives are sorted by date.
+/// Years are only folded when the file spans several, and months likewise, so
+/// a journal covering a single month gets no extra ranges.
+fn fold_periods(nodes: &[Node], content: &Rope) -> Vec<FoldingRange> {
+    let dated: Vec<(String, usize, usize)> = nodes
+        .iter()
+        .filter_map(|node| {
+            let date = node.child_by_field_name("date")?;
+            let date = text_for_tree_sitter_node(content, &date).replace('/', "-");
+            (date.len() >= 7).then(|| (date, node.start_position().row, last_line(node)))
+        })
+        .collect();
+    if !dated.is_sorted_by(|a, b| a.0 <= b.0) {
+        return Vec::new();
+    }
+
+    let mut ranges = fold_period(&dated, 4);
+    ranges.extend(fold_period(&dated, 7));
+    ranges
+}
+
+/// Fold runs of `dated` entries sharing the first `prefix` characters of their date.
+fn fold_period(dated: &[(String, usize, usize)], prefix: usize) -> Vec<FoldingRange> {
+    let groups: Vec<&[(String, usize, usize)]> = dated
+        .chunk_by(|a, b| a.0[..prefix] == b.0[..prefix])
+        .collect();
+    if groups.len() < 2 {
+        return Vec::new();
+    }
+    groups
+        .into_iter()
+        .filter_map(|group| {
+            let (date, start_line, _) = group.first()?;
+            let (_, _, end_line) = group.last()?;
+            (end_line > start_line).then(|| FoldingRange {
+                start_line: *start_line as u32,
+                end_line: *end_line as u32,
+                kind: Some(FoldingRangeKind::Region),
+                collapsed_text: Some(date[..prefix].to_string()),
+                start_character: None,
+                end_character: None,
+            })
+        })
+        .collect()
+}
+
+/// The last line holding text of `node`, which may end at the start of the next line.
+fn last_line(node: &Node) -> usize {
+    let end = node.end_position();
+    if end.column == 0 && end.row > node.start_position().row {
+        end.row - 1
+    } else {
+        end.row
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -759,4 +818,52 @@ option "operating_currency" "USD"
         assert_eq!(ranges.len(), 2);
         assert!(ranges[0].start_line <= ranges[1].start_line);
     }
+
+    #[test]
+    fn test_fold_periods() {
+        let content = r#"2023-11-30 open Assets:Cash
+2023-12-01 * "A"
+  Assets:Cash  1 EUR
+  Equity:Opening
+2024-01-05 * "B"
+  Assets:Cash  1 EUR
+  Equity:Opening
+2024-01-06 balance Assets:Cash 2 EUR
+2024-02-01 close Assets:Cash
+"#;
+        let rope = Rope::from_str(content);
+        let tree = parse_beancount(content);
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let children: Vec<Node> = root.children(&mut cursor).collect();
+
+        let ranges: Vec<_> = fold_periods(&children, &rope)
+            .into_iter()
+            .map(|r| (r.start_line, r.end_line, r.collapsed_text.unwrap()))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (0, 3, "2023".to_string()),
+                (4, 8, "2024".to_string()),
+                (1, 3, "2023-12".to_string()),
+                (4, 7, "2024-01".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fold_periods_requires_sorted_dates() {
+        let content = r#"2024-01-05 open Assets:Cash
+2023-12-01 open Assets:Bank
+2024-02-01 open Assets:Card
+"#;
+        let rope = Rope::from_str(content);
+        let tree = parse_beancount(content);
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let children: Vec<Node> = root.children(&mut cursor).collect();
+
+        assert!(fold_periods(&children, &rope).is_empty());
+    }
 }
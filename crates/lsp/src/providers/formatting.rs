@@ -1,5 +1,13 @@
+use crate::config::{BlankLines, FormattingConfig, LineEnding};
+use crate::queries::{
+    get_comment_query, get_date_query, get_format_query, get_metadata_query, get_transaction_query,
+};
 use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::{PositionEncoding, position_encoding};
+use crate::utils::ToFilePath;
 use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::OnceLock;
 use tracing::debug;
 use tree_sitter::StreamingIterator;
 use tree_sitter_beancount::tree_sitter;
@@ -27,42 +35,14 @@ struct FormatConfig {
     final_num_width: usize,
 }
 
-const QUERY_STR: &str = r#"
-( posting
-    (account) @prefix
-    amount: (incomplete_amount
-        [
-            (number)
-            (unary_number_expr)
-            (binary_number_expr)
-        ] @number
-    )?
-)
-( balance
-    (account) @prefix
-    (amount_tolerance
-        ([
-            (number)
-            (unary_number_expr)
-            (binary_number_expr)
-        ] @number)
-    )
-)
-( price
-    currency: (_) @prefix
-    amount: (amount
-        ([
-            (number)
-            (unary_number_expr)
-            (binary_number_expr)
-        ] @number)
-    )
-)
-( open
-    (account) @prefix
-    (currency) @number
-)
-"#;
+static SLASH_DATE_REGEX: OnceLock<regex::Regex> = OnceLock::new();
+
+/// Get or compile the regex matching dates written with slashes
+fn get_slash_date_regex() -> &'static regex::Regex {
+    SLASH_DATE_REGEX.get_or_init(|| {
+        regex::Regex::new(r"(\d{4})/(\d{2})/(\d{2})").expect("Failed to compile date regex")
+    })
+}
 
 // Adapter to convert rope chunks to bytes
 struct ChunksBytes<'a> {
@@ -101,6 +81,9 @@ pub(crate) fn formatting(
     snapshot: LspServerStateSnapshot,
     params: lsp_types::DocumentFormattingParams,
 ) -> Result<Option<Vec<lsp_types::TextEdit>>> {
+    if !snapshot.config.features.formatting {
+        return Ok(None);
+    }
     tracing::info!(
         "Starting formatting for document: {}",
         params.text_document.uri.as_str()
@@ -110,9 +93,34 @@ pub(crate) fn formatting(
         params.options.insert_spaces,
         params.options.tab_size
     );
+    format_document(&snapshot, &params.text_document.uri)
+}
+
+/// Provider function for LSP `textDocument/willSaveWaitUntil`.
+///
+/// With `format_on_save`, formats the document like [`formatting`] before it is saved.
+/// Saves after a delay are left alone, so autosave does not reformat while typing.
+pub(crate) fn will_save_wait_until(
+    snapshot: LspServerStateSnapshot,
+    params: lsp_types::WillSaveTextDocumentParams,
+) -> Result<Option<Vec<lsp_types::TextEdit>>> {
+    if !snapshot.config.features.formatting
+        || !snapshot.config.format_on_save
+        || params.reason == lsp_types::TextDocumentSaveReason::AFTER_DELAY
+    {
+        return Ok(None);
+    }
+    format_document(&snapshot, &params.text_document.uri)
+}
 
-    // Get document and tree from the snapshot
-    let (tree, doc) = match snapshot.tree_and_document_for_uri(&params.text_document.uri) {
+/// The formatting edits for the document at `uri`, in the negotiated encoding. The
+/// layout comes from the `formatting` config, with the currency column the journal
+/// declares for Fava as default; client formatting options don't apply.
+fn format_document(
+    snapshot: &LspServerStateSnapshot,
+    uri: &lsp_types::Uri,
+) -> Result<Option<Vec<lsp_types::TextEdit>>> {
+    let (tree, doc) = match snapshot.tree_and_document_for_uri(uri) {
         Ok(v) => {
             tracing::debug!("Found document and parsed tree");
             v
@@ -123,6 +131,62 @@ pub(crate) fn formatting(
         }
     };
 
+    let mut config = snapshot.config.formatting.clone();
+    if config.currency_column.is_none()
+        && let Ok(file) = uri.to_file_path()
+    {
+        config.currency_column = snapshot.ledger_options_for(&file).currency_column;
+    }
+
+    // Edits address columns by character; clients expect the negotiated encoding
+    let final_text_edits: Vec<_> = format_edits(doc, tree, &config)?
+        .into_iter()
+        .map(|edit| lsp_types::TextEdit {
+            range: lsp_types::Range {
+                start: char_column_to_lsp_position(&doc.content, edit.range.start),
+                end: char_column_to_lsp_position(&doc.content, edit.range.end),
+            },
+            new_text: edit.new_text,
+        })
+        .collect();
+
+    debug!(
+        "Generated {} text edits for formatting",
+        final_text_edits.len()
+    );
+    Ok(Some(final_text_edits))
+}
+
+/// Converts a position whose column counts characters into the negotiated encoding.
+///
+/// Columns past the end of the line are kept, since they only mean "end of line".
+fn char_column_to_lsp_position(
+    content: &ropey::Rope,
+    position: lsp_types::Position,
+) -> lsp_types::Position {
+    let Some(line) = content.get_line(position.line as usize) else {
+        return position;
+    };
+    let character = position.character as usize;
+    if character > line.len_chars() {
+        return position;
+    }
+    let character = match position_encoding() {
+        PositionEncoding::Utf8 => line.char_to_byte(character),
+        PositionEncoding::Utf16 => line.char_to_utf16_cu(character),
+    };
+    lsp_types::Position::new(position.line, character as u32)
+}
+
+/// Computes the formatting edits for a parsed document.
+///
+/// This is the editor-independent core of [`formatting`], shared with the command line.
+/// Edit columns count characters rather than LSP position units.
+pub fn format_edits(
+    doc: &crate::document::Document,
+    tree: &tree_sitter::Tree,
+    config: &FormattingConfig,
+) -> Result<Vec<lsp_types::TextEdit>> {
     // Extract formateable lines using tree-sitter
     let formateable_lines = match extract_formateable_lines(doc, tree) {
         Ok(lines) => {
@@ -141,39 +205,316 @@ pub(crate) fn formatting(
         vec![]
     } else {
         // Calculate formatting configuration
-        let format_config =
-            calculate_format_config(&formateable_lines, &snapshot.config.formatting);
+        let format_config = calculate_format_config(&formateable_lines, config);
 
-        if let Some(currency_col) = snapshot.config.formatting.currency_column {
+        if let Some(currency_col) = config.currency_column {
             generate_currency_column_edits(
                 &formateable_lines,
                 currency_col,
                 doc,
-                snapshot.config.formatting.indent_width,
+                config.indent_width,
             )
         } else {
             generate_template_edits(
                 &formateable_lines,
                 &format_config,
-                snapshot.config.formatting.number_currency_spacing,
-                snapshot.config.formatting.indent_width,
+                config.number_currency_spacing,
+                config.indent_width,
                 doc,
             )
         }
     };
 
+    let text_edits = if config.normalize_transaction_headers {
+        apply_header_normalization(doc, tree, text_edits)?
+    } else {
+        text_edits
+    };
+
+    // Align metadata values before indent normalization so those lines are skipped there
+    let text_edits = if config.align_metadata {
+        apply_metadata_alignment(doc, tree, config.indent_width, text_edits)?
+    } else {
+        text_edits
+    };
+
     // Apply indent normalization to remaining lines if configured
-    let final_text_edits = if let Some(indent_width) = snapshot.config.formatting.indent_width {
+    let final_text_edits = if let Some(indent_width) = config.indent_width {
         apply_indent_normalization_to_remaining_lines(doc, tree, indent_width, text_edits)?
     } else {
         text_edits
     };
 
-    debug!(
-        "Generated {} text edits for formatting",
-        final_text_edits.len()
-    );
-    Ok(Some(final_text_edits))
+    // Align comments once every other pass has settled the content before them
+    let final_text_edits = if let Some(comment_column) = config.comment_column {
+        apply_comment_alignment(doc, tree, comment_column, final_text_edits)?
+    } else {
+        final_text_edits
+    };
+
+    // Blank line edits add and remove whole lines, so they come after all line content
+    let line_ending = target_line_ending(doc, config.line_ending);
+    let final_text_edits = if config.blank_lines_between_directives != BlankLines::Keep
+        || config.max_blank_lines.is_some()
+    {
+        apply_blank_line_policy(
+            doc,
+            tree,
+            config.blank_lines_between_directives,
+            config.max_blank_lines,
+            line_ending.unwrap_or_else(|| dominant_line_break(doc)),
+            final_text_edits,
+        )
+    } else {
+        final_text_edits
+    };
+
+    // Normalize line endings last; these edits only cover the line breaks themselves
+    let final_text_edits = match line_ending {
+        Some(line_ending) => apply_line_ending_normalization(doc, line_ending, final_text_edits),
+        None => final_text_edits,
+    };
+
+    // Date separators sit inside text the other passes keep verbatim
+    let final_text_edits = if config.normalize_dates {
+        apply_date_normalization(doc, tree, final_text_edits)?
+    } else {
+        final_text_edits
+    };
+
+    // Lines inside syntax errors may be misparsed, so keep them exactly as written
+    let errors = syntax_error_lines(tree);
+    let final_text_edits = if errors.is_empty() {
+        final_text_edits
+    } else {
+        let before = final_text_edits.len();
+        let kept: Vec<_> = final_text_edits
+            .into_iter()
+            .filter(|edit| {
+                let (start, end) = (edit.range.start.line as usize, edit.range.end.line as usize);
+                !errors
+                    .iter()
+                    .any(|error| start <= error.end_line && error.line <= end)
+            })
+            .collect();
+        if kept.len() < before {
+            tracing::warn!(
+                "Skipped {} formatting edits around syntax errors (first at line {})",
+                before - kept.len(),
+                errors[0].line + 1
+            );
+        }
+        kept
+    };
+
+    // Always check the result in debug builds; in strict mode fall back to no edits
+    if config.verify || cfg!(debug_assertions) {
+        let original = doc.content.to_string();
+        let formatted = apply_text_edits(&original, &final_text_edits);
+        if let Err(e) = verify(&original, &formatted) {
+            if config.verify {
+                tracing::error!("Formatting would alter the document, skipping: {}", e);
+                return Ok(vec![]);
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(final_text_edits)
+}
+
+/// A part of the parse tree that tree-sitter could not make sense of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// First line of the error, zero-based
+    pub line: usize,
+    /// Last line of the error, zero-based
+    pub end_line: usize,
+    /// The token tree-sitter expected, when it inserted a missing one
+    pub missing: Option<String>,
+}
+
+impl std::fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.missing {
+            Some(kind) => write!(f, "line {}: missing {kind}", self.line + 1),
+            None => write!(f, "line {}: syntax error", self.line + 1),
+        }
+    }
+}
+
+/// Returns the `ERROR` and missing nodes of `tree` in document order.
+pub fn syntax_error_lines(tree: &tree_sitter::Tree) -> Vec<SyntaxError> {
+    fn collect(node: tree_sitter::Node, out: &mut Vec<SyntaxError>) {
+        if !node.has_error() {
+            return;
+        }
+        if node.is_error() || node.is_missing() {
+            let end = node.end_position();
+            // An error ending at column 0 stops before that line
+            let end_line = if end.column == 0 && end.row > node.start_position().row {
+                end.row - 1
+            } else {
+                end.row
+            };
+            out.push(SyntaxError {
+                line: node.start_position().row,
+                end_line,
+                missing: node.is_missing().then(|| node.kind().to_string()),
+            });
+            return;
+        }
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            collect(child, out);
+        }
+    }
+
+    let mut errors = Vec::new();
+    collect(tree.root_node(), &mut errors);
+    errors
+}
+
+/// A top-level entry as compared by [`verify`].
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Fingerprint {
+    key: String,
+    display: String,
+}
+
+/// Checks that `formatted` contains the same directives as `original`.
+///
+/// Both texts are re-parsed and their top-level entries compared as a multiset, ignoring
+/// whitespace and the spelling of `txn` and dates. Returns an error describing the first directive that was lost, duplicated
+/// or altered.
+pub fn verify(original: &str, formatted: &str) -> Result<()> {
+    let mut before = directive_fingerprints(original)?;
+    let mut after = directive_fingerprints(formatted)?;
+    before.sort();
+    after.sort();
+
+    // Both lists are sorted, so the first difference tells which side has an extra entry
+    for i in 0..before.len().max(after.len()) {
+        match (before.get(i), after.get(i)) {
+            (Some(b), Some(a)) if b.key == a.key => continue,
+            (Some(b), Some(a)) if b.key > a.key => {
+                anyhow::bail!("directive added or altered: {}", a.display)
+            }
+            (Some(b), _) => anyhow::bail!("directive lost or altered: {}", b.display),
+            (None, Some(a)) => anyhow::bail!("directive added or altered: {}", a.display),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the kind and text of every top-level entry in `text`, with whitespace removed
+/// for comparison and collapsed for display.
+fn directive_fingerprints(text: &str) -> Result<Vec<Fingerprint>> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_beancount::language())?;
+    let tree = parser
+        .parse(text, None)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse document"))?;
+
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let fingerprints = root
+        .named_children(&mut cursor)
+        .map(|node| {
+            let mut words: Vec<&str> = text[node.byte_range()].split_whitespace().collect();
+            // `txn` and `*` are the same flag
+            if node.kind() == "transaction" && words.get(1) == Some(&"txn") {
+                words[1] = "*";
+            }
+            let text = words.concat();
+            let key = get_slash_date_regex().replace_all(&text, "$1-$2-$3");
+            Fingerprint {
+                key: format!("{}:{}", node.kind(), key),
+                display: words.join(" "),
+            }
+        })
+        .collect();
+
+    Ok(fingerprints)
+}
+
+/// Formats `text` and returns the formatted content.
+pub fn format_text(text: &str, config: &FormattingConfig) -> Result<String> {
+    let edits = format_text_edits(text, config)?;
+    Ok(apply_text_edits(text, &edits))
+}
+
+/// Like [`format_text`], but leaves documents with syntax errors alone.
+///
+/// [`format_text`] keeps the lines of a syntax error as written and formats the rest;
+/// this variant returns the errors instead, so callers can report them and keep the
+/// input unchanged.
+pub fn format_text_strict(
+    text: &str,
+    config: &FormattingConfig,
+) -> Result<std::result::Result<String, Vec<SyntaxError>>> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_beancount::language())?;
+    let tree = parser
+        .parse(text, None)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse document"))?;
+    let errors = syntax_error_lines(&tree);
+    if !errors.is_empty() {
+        return Ok(Err(errors));
+    }
+    format_text(text, config).map(Ok)
+}
+
+/// Formats `text` and returns the minimal set of range replacements instead of the
+/// rewritten content. Each edit touches a single line and only covers the characters
+/// that change.
+pub fn format_text_edits(
+    text: &str,
+    config: &FormattingConfig,
+) -> Result<Vec<lsp_types::TextEdit>> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_beancount::language())?;
+    let tree = parser
+        .parse(text, None)
+        .ok_or_else(|| anyhow::anyhow!("Failed to parse document"))?;
+    let doc = crate::document::Document {
+        content: ropey::Rope::from_str(text),
+        version: 0,
+    };
+
+    format_edits(&doc, &tree, config)
+}
+
+/// Applies formatting edits to `text`.
+///
+/// Edit positions are interpreted as (line, character) pairs, as produced by this module.
+pub fn apply_text_edits(text: &str, edits: &[lsp_types::TextEdit]) -> String {
+    let mut result = ropey::Rope::from_str(text);
+    let mut sorted_edits = edits.to_vec();
+
+    // Apply edits back to front so earlier positions stay valid
+    sorted_edits.sort_by(|a, b| {
+        (b.range.start.line, b.range.start.character)
+            .cmp(&(a.range.start.line, a.range.start.character))
+    });
+
+    for edit in sorted_edits {
+        let start = result.line_to_char(edit.range.start.line as usize)
+            + edit.range.start.character as usize;
+        let end =
+            result.line_to_char(edit.range.end.line as usize) + edit.range.end.character as usize;
+
+        if start < end {
+            result.remove(start..end);
+        }
+        if !edit.new_text.is_empty() {
+            result.insert(start, &edit.new_text);
+        }
+    }
+
+    result.to_string()
 }
 
 /// Extracts formateable lines from the document using tree-sitter
@@ -182,20 +523,14 @@ fn extract_formateable_lines(
     doc: &crate::document::Document,
     tree: &tree_sitter::Tree,
 ) -> Result<Vec<FormatableLine>> {
-    let query = match tree_sitter::Query::new(&tree.language(), QUERY_STR) {
-        Ok(query) => query,
-        Err(e) => {
-            debug!("Failed to create tree-sitter query: {}", e);
-            return Ok(vec![]);
-        }
-    };
+    let query = get_format_query();
 
     let mut query_cursor = tree_sitter::QueryCursor::new();
     let rope_slice = doc
         .content
         .get_slice(..)
         .ok_or_else(|| anyhow::anyhow!("Failed to get rope slice for document"))?;
-    let mut matches = query_cursor.matches(&query, tree.root_node(), RopeProvider(rope_slice));
+    let mut matches = query_cursor.matches(query, tree.root_node(), RopeProvider(rope_slice));
 
     let mut formateable_lines = Vec::new();
 
@@ -469,68 +804,634 @@ fn generate_template_edits(
             account_name.len() // fallback to actual account name length
         };
 
-        let formatted_line = format!(
-            "{}{:<width$}  {:>num_width$}{}",
-            indent_str,
-            account_name,
-            line.number,
-            formatted_rest,
-            width = adjusted_prefix_width,
-            num_width = config.final_num_width
-        );
+        let formatted_line = format!(
+            "{}{:<width$}  {:>num_width$}{}",
+            indent_str,
+            account_name,
+            line.number,
+            formatted_rest,
+            width = adjusted_prefix_width,
+            num_width = config.final_num_width
+        );
+
+        if let Some(edit) = create_line_replacement_edit(line.line_num, &formatted_line, doc) {
+            text_edits.push(edit);
+        }
+    }
+
+    text_edits
+}
+
+/// Creates a minimal text edit that turns a line into `new_content`
+fn create_line_replacement_edit(
+    line_num: usize,
+    new_content: &str,
+    doc: &crate::document::Document,
+) -> Option<lsp_types::TextEdit> {
+    // Calculate the range of the original line (without trailing newline)
+    let line_start_char = doc.content.line_to_char(line_num);
+    let line_end_char = if line_num + 1 < doc.content.len_lines() {
+        doc.content.line_to_char(line_num + 1)
+    } else {
+        doc.content.len_chars()
+    };
+
+    let original_line = doc
+        .content
+        .slice(line_start_char..line_end_char)
+        .to_string();
+
+    // Skip edit if the line content hasn't changed
+    // This optimization prevents unnecessary edits for already-formatted lines
+    if original_line.trim_end() == new_content.trim_end() {
+        return None;
+    }
+
+    // Only replace the part of the line that differs, so editors keep the cursor
+    // position and produce small undo steps. Work on chars for UTF-8 safety.
+    let original: Vec<char> = original_line.trim_end().chars().collect();
+    let target: Vec<char> = new_content.trim_end().chars().collect();
+
+    let prefix_len = original
+        .iter()
+        .zip(&target)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let max_suffix = original.len().min(target.len()) - prefix_len;
+    let suffix_len = original
+        .iter()
+        .rev()
+        .zip(target.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let start = lsp_types::Position {
+        line: line_num as u32,
+        character: prefix_len as u32,
+    };
+    let end = lsp_types::Position {
+        line: line_num as u32,
+        character: (original.len() - suffix_len) as u32,
+    };
+
+    Some(lsp_types::TextEdit {
+        range: lsp_types::Range { start, end },
+        new_text: target[prefix_len..target.len() - suffix_len]
+            .iter()
+            .collect(),
+    })
+}
+
+/// Returns the line break of a line, if it has one.
+fn line_break(line: ropey::RopeSlice) -> Option<&'static str> {
+    let len = line.len_chars();
+    if len >= 2 && line.char(len - 2) == '\r' && line.char(len - 1) == '\n' {
+        Some("\r\n")
+    } else if len >= 1 && line.char(len - 1) == '\n' {
+        Some("\n")
+    } else {
+        None
+    }
+}
+
+/// Resolves the configured line ending policy to the line break to enforce, if any.
+fn target_line_ending(doc: &crate::document::Document, policy: LineEnding) -> Option<&'static str> {
+    match policy {
+        LineEnding::Preserve => None,
+        LineEnding::Lf => Some("\n"),
+        LineEnding::Crlf => Some("\r\n"),
+        LineEnding::Auto => {
+            let (mut lf, mut crlf) = (0usize, 0usize);
+            for line in doc.content.lines() {
+                match line_break(line) {
+                    Some("\r\n") => crlf += 1,
+                    Some(_) => lf += 1,
+                    None => {}
+                }
+            }
+            if lf == 0 && crlf == 0 {
+                None
+            } else if crlf > lf {
+                Some("\r\n")
+            } else {
+                Some("\n")
+            }
+        }
+    }
+}
+
+/// Returns the line break used by the first line that has one, `\n` otherwise.
+fn dominant_line_break(doc: &crate::document::Document) -> &'static str {
+    doc.content.lines().find_map(line_break).unwrap_or("\n")
+}
+
+/// Directive kinds that carry a date, i.e. the entries a journal is ordered by.
+const DATED_DIRECTIVES: &[&str] = &[
+    "transaction",
+    "balance",
+    "open",
+    "close",
+    "pad",
+    "document",
+    "note",
+    "event",
+    "price",
+    "commodity",
+    "query",
+    "custom",
+];
+
+/// Adds edits enforcing the blank line options: exactly `between_directives` blank lines
+/// between consecutive dated directives, and at most `max_blank_lines` in a row anywhere.
+///
+/// The directive policy only touches gaps that contain nothing but blank lines; a comment
+/// or an undated directive between two entries leaves the spacing around it alone.
+fn apply_blank_line_policy(
+    doc: &crate::document::Document,
+    tree: &tree_sitter::Tree,
+    between_directives: BlankLines,
+    max_blank_lines: Option<usize>,
+    line_ending: &str,
+    mut existing_edits: Vec<lsp_types::TextEdit>,
+) -> Vec<lsp_types::TextEdit> {
+    let mut is_blank: Vec<bool> = doc
+        .content
+        .lines()
+        .map(|line| line.chars().all(char::is_whitespace))
+        .collect();
+    // The empty line after a final line break is not a line of its own
+    if doc.content.line(is_blank.len() - 1).len_chars() == 0 {
+        is_blank.pop();
+    }
+    let cap = |len: usize| max_blank_lines.map_or(len, |max| len.min(max));
+
+    // Blank line runs by first line, with their length and the length to enforce
+    let mut gaps: std::collections::BTreeMap<usize, (usize, usize)> =
+        std::collections::BTreeMap::new();
+    let mut line = 0;
+    while line < is_blank.len() {
+        if is_blank[line] {
+            let len = is_blank[line..].iter().take_while(|b| **b).count();
+            gaps.insert(line, (len, cap(len)));
+            line += len;
+        } else {
+            line += 1;
+        }
+    }
+
+    if let BlankLines::Exactly(count) = between_directives {
+        // Top-level nodes in document order, descending into org-mode sections
+        let mut nodes = Vec::new();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                if child.kind() == "section" {
+                    stack.push(child);
+                } else {
+                    nodes.push(child);
+                }
+            }
+        }
+        nodes.sort_by_key(|node| node.start_byte());
+
+        for pair in nodes.windows(2) {
+            let [prev, next] = pair else { continue };
+            if !DATED_DIRECTIVES.contains(&prev.kind()) || !DATED_DIRECTIVES.contains(&next.kind())
+            {
+                continue;
+            }
+
+            // Directive nodes may extend over trailing indented blank lines
+            let mut last_row = prev.end_position().row;
+            if prev.end_position().column == 0 {
+                last_row = last_row.saturating_sub(1);
+            }
+            while last_row > prev.start_position().row && is_blank[last_row] {
+                last_row -= 1;
+            }
+
+            let gap_start = last_row + 1;
+            let next_row = next.start_position().row;
+            if gap_start > next_row || !is_blank[gap_start..next_row].iter().all(|b| *b) {
+                continue;
+            }
+            gaps.insert(gap_start, (next_row - gap_start, cap(count)));
+        }
+    }
+
+    for (gap_start, (len, target)) in gaps {
+        let gap_end = gap_start + len;
+        if len > target {
+            existing_edits.push(lsp_types::TextEdit {
+                range: lsp_types::Range {
+                    start: lsp_types::Position::new((gap_start + target) as u32, 0),
+                    end: lsp_types::Position::new(gap_end as u32, 0),
+                },
+                new_text: String::new(),
+            });
+        } else if len < target {
+            let breaks = line_ending.repeat(target - len);
+            let position = lsp_types::Position::new(gap_end as u32, 0);
+
+            // Merge with an edit that starts at the same place to keep their order
+            match existing_edits
+                .iter_mut()
+                .find(|edit| edit.range.start == position)
+            {
+                Some(edit) => edit.new_text.insert_str(0, &breaks),
+                None => existing_edits.push(lsp_types::TextEdit {
+                    range: lsp_types::Range {
+                        start: position,
+                        end: position,
+                    },
+                    new_text: breaks,
+                }),
+            }
+        }
+    }
+
+    existing_edits
+}
+
+/// Adds edits replacing every line break that differs from `line_ending`.
+fn apply_line_ending_normalization(
+    doc: &crate::document::Document,
+    line_ending: &str,
+    mut existing_edits: Vec<lsp_types::TextEdit>,
+) -> Vec<lsp_types::TextEdit> {
+    // Lines removed by earlier edits have no line break left to normalize
+    let mut removed = vec![false; doc.content.len_lines()];
+    for edit in &existing_edits {
+        if edit.range.start.character == 0 {
+            for line in edit.range.start.line..edit.range.end.line {
+                removed[line as usize] = true;
+            }
+        }
+    }
+
+    for (line_num, line) in doc.content.lines().enumerate() {
+        if removed[line_num] {
+            continue;
+        }
+        let Some(current) = line_break(line) else {
+            continue;
+        };
+        if current == line_ending {
+            continue;
+        }
+
+        let content_len = line.len_chars() - current.chars().count();
+        existing_edits.push(lsp_types::TextEdit {
+            range: lsp_types::Range {
+                start: lsp_types::Position::new(line_num as u32, content_len as u32),
+                end: lsp_types::Position::new(line_num as u32 + 1, 0),
+            },
+            new_text: line_ending.to_string(),
+        });
+    }
+
+    existing_edits
+}
+
+/// Rewrites transaction header lines as `date flag strings tags`, separated by single
+/// spaces, with `txn` written as `*`. Whatever follows the last of these (usually a
+/// comment) keeps its original spacing.
+fn apply_header_normalization(
+    doc: &crate::document::Document,
+    tree: &tree_sitter::Tree,
+    mut existing_edits: Vec<lsp_types::TextEdit>,
+) -> Result<Vec<lsp_types::TextEdit>> {
+    let query = get_transaction_query();
+
+    let mut query_cursor = tree_sitter::QueryCursor::new();
+    let rope_slice = doc
+        .content
+        .get_slice(..)
+        .ok_or_else(|| anyhow::anyhow!("Failed to get rope slice for document"))?;
+    let mut matches = query_cursor.matches(query, tree.root_node(), RopeProvider(rope_slice));
+
+    while let Some(matched) = matches.next() {
+        for capture in matched.captures {
+            let transaction = capture.node;
+            let line_num = transaction.start_position().row;
+            let line = doc.content.line(line_num).to_string();
+            let line_start_byte = doc.content.line_to_byte(line_num);
+
+            let parts: Vec<tree_sitter::Node> = ["date", "txn", "payee", "narration", "tags_links"]
+                .iter()
+                .filter_map(|field| transaction.child_by_field_name(field))
+                .collect();
+            // Leave headers that span several lines alone
+            if parts.iter().any(|node| node.end_position().row != line_num) {
+                continue;
+            }
+            let Some(last) = parts.last() else {
+                continue;
+            };
+
+            let words: Vec<String> = parts
+                .iter()
+                .map(|node| {
+                    let text = &line
+                        [node.start_byte() - line_start_byte..node.end_byte() - line_start_byte];
+                    match node.kind() {
+                        "txn" if text == "txn" => "*".to_string(),
+                        "tags_links" => text.split_whitespace().collect::<Vec<_>>().join(" "),
+                        _ => text.to_string(),
+                    }
+                })
+                .collect();
+            let rest = &line[last.end_byte() - line_start_byte..];
+
+            let target_line = format!("{}{}", words.join(" "), rest.trim_end());
+            if let Some(edit) = create_line_replacement_edit(line_num, &target_line, doc) {
+                existing_edits.push(edit);
+            }
+        }
+    }
+
+    Ok(existing_edits)
+}
+
+/// Rewrites dates using `/` as separator into the canonical `YYYY-MM-DD` form.
+///
+/// The grammar only accepts `-` and `/` separators, so other spellings are parse errors
+/// and are not touched.
+fn apply_date_normalization(
+    doc: &crate::document::Document,
+    tree: &tree_sitter::Tree,
+    mut existing_edits: Vec<lsp_types::TextEdit>,
+) -> Result<Vec<lsp_types::TextEdit>> {
+    let query = get_date_query();
+
+    let mut query_cursor = tree_sitter::QueryCursor::new();
+    let rope_slice = doc
+        .content
+        .get_slice(..)
+        .ok_or_else(|| anyhow::anyhow!("Failed to get rope slice for document"))?;
+    let mut matches = query_cursor.matches(query, tree.root_node(), RopeProvider(rope_slice));
+
+    while let Some(matched) = matches.next() {
+        for capture in matched.captures {
+            let node = capture.node;
+            let date = doc
+                .content
+                .byte_slice(node.start_byte()..node.end_byte())
+                .to_string();
+            if !date.contains('/') {
+                continue;
+            }
+            let normalized = date.replace('/', "-");
+
+            let line_num = node.start_position().row;
+            let line_start_char = doc.content.line_to_char(line_num);
+            let start = (doc.content.byte_to_char(node.start_byte()) - line_start_char) as u32;
+            let end = start + date.chars().count() as u32;
+            let range = lsp_types::Range {
+                start: lsp_types::Position::new(line_num as u32, start),
+                end: lsp_types::Position::new(line_num as u32, end),
+            };
+
+            // An earlier edit may already rewrite the text around the date
+            if let Some(edit) = existing_edits
+                .iter_mut()
+                .find(|edit| edit.range.start < range.end && edit.range.end > range.start)
+            {
+                merge_date_rewrite(edit, range, &date, &normalized);
+                continue;
+            }
+
+            existing_edits.push(lsp_types::TextEdit {
+                range,
+                new_text: normalized,
+            });
+        }
+    }
+
+    Ok(existing_edits)
+}
+
+/// Folds the rewrite of the date at `range` into an `edit` overlapping it. The part
+/// of the date the edit replaces is rewritten in its new text, and the edit grows to
+/// cover the rest of the date. Dates are ASCII, so characters and bytes line up.
+fn merge_date_rewrite(
+    edit: &mut lsp_types::TextEdit,
+    range: lsp_types::Range,
+    date: &str,
+    normalized: &str,
+) {
+    let (start, end) = (range.start.character, range.end.character);
+    let covered_start = if edit.range.start.line == range.start.line {
+        edit.range.start.character.max(start)
+    } else {
+        start
+    };
+    let covered_end = if edit.range.end.line == range.end.line {
+        edit.range.end.character.min(end)
+    } else {
+        end
+    };
+    let covered = (covered_start - start) as usize..(covered_end - start) as usize;
+
+    let mut new_text =
+        edit.new_text
+            .replacen(&date[covered.clone()], &normalized[covered.clone()], 1);
+    if covered_start > start {
+        new_text.insert_str(0, &normalized[..covered.start]);
+        edit.range.start = range.start;
+    }
+    if covered_end < end {
+        new_text.push_str(&normalized[covered.end..]);
+        edit.range.end = range.end;
+    }
+    edit.new_text = new_text;
+}
+
+/// Aligns metadata values so that, within each directive or posting, they start at
+/// the same column. Lines are grouped by their parent node and indentation.
+fn apply_metadata_alignment(
+    doc: &crate::document::Document,
+    tree: &tree_sitter::Tree,
+    indent_width: Option<usize>,
+    mut existing_edits: Vec<lsp_types::TextEdit>,
+) -> Result<Vec<lsp_types::TextEdit>> {
+    let query = get_metadata_query();
+
+    let mut query_cursor = tree_sitter::QueryCursor::new();
+    let rope_slice = doc
+        .content
+        .get_slice(..)
+        .ok_or_else(|| anyhow::anyhow!("Failed to get rope slice for document"))?;
+    let mut matches = query_cursor.matches(query, tree.root_node(), RopeProvider(rope_slice));
+
+    // ((parent node, indent), line number, key, value) in document order
+    let mut entries: Vec<((usize, String), usize, String, String)> = Vec::new();
+    while let Some(matched) = matches.next() {
+        for capture in matched.captures {
+            let key_node = capture.node;
+            let group_id = key_node
+                .parent()
+                .and_then(|kv| kv.parent())
+                .map_or(0, |p| p.id());
+            let line_num = key_node.start_position().row;
+            let line = doc.content.line(line_num).to_string();
+
+            let indent: String = line
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect();
+            let content = line[indent.len()..].trim_end();
+            let Some((key, value)) = content.split_once(':') else {
+                continue;
+            };
+            let value = value.trim_start();
+            if value.is_empty() {
+                continue;
+            }
+
+            let indent = match indent_width {
+                Some(width) if !indent.is_empty() => " ".repeat(width),
+                _ => indent,
+            };
+            entries.push((
+                (group_id, indent),
+                line_num,
+                key.to_string(),
+                value.to_string(),
+            ));
+        }
+    }
+
+    let mut key_widths: HashMap<&(usize, String), usize> = HashMap::new();
+    for (group, _, key, _) in &entries {
+        let width = key_widths.entry(group).or_default();
+        *width = (*width).max(key.chars().count());
+    }
 
-        if let Some(edit) = create_line_replacement_edit(line.line_num, &formatted_line, doc) {
-            text_edits.push(edit);
+    for (group, line_num, key, value) in &entries {
+        let padding = key_widths[group] - key.chars().count() + 1;
+        let target_line = format!("{}{}:{}{}", group.1, key, " ".repeat(padding), value);
+        if let Some(edit) = create_line_replacement_edit(*line_num, &target_line, doc) {
+            existing_edits.push(edit);
         }
     }
 
-    text_edits
+    Ok(existing_edits)
 }
 
-/// Creates a text edit to replace an entire line with new content
-fn create_line_replacement_edit(
-    line_num: usize,
-    new_content: &str,
+/// Aligns trailing posting comments to a common column within each transaction.
+///
+/// The column is the larger of `comment_column` and two spaces past the longest
+/// posting in the transaction. Lines that already have an edit are aligned based on
+/// their formatted content.
+fn apply_comment_alignment(
     doc: &crate::document::Document,
-) -> Option<lsp_types::TextEdit> {
-    // Calculate the range of the original line (without trailing newline)
-    let line_start_char = doc.content.line_to_char(line_num);
-    let line_end_char = if line_num + 1 < doc.content.len_lines() {
-        doc.content.line_to_char(line_num + 1)
-    } else {
-        doc.content.len_chars()
-    };
+    tree: &tree_sitter::Tree,
+    comment_column: usize,
+    mut existing_edits: Vec<lsp_types::TextEdit>,
+) -> Result<Vec<lsp_types::TextEdit>> {
+    let query = get_comment_query();
 
-    let original_line = doc
+    let mut query_cursor = tree_sitter::QueryCursor::new();
+    let rope_slice = doc
         .content
-        .slice(line_start_char..line_end_char)
-        .to_string();
+        .get_slice(..)
+        .ok_or_else(|| anyhow::anyhow!("Failed to get rope slice for document"))?;
+    let mut matches = query_cursor.matches(query, tree.root_node(), RopeProvider(rope_slice));
+
+    // Index earlier edits by line so each comment line is looked up once
+    let mut edits_by_line: HashMap<usize, Vec<&lsp_types::TextEdit>> = HashMap::new();
+    for edit in &existing_edits {
+        if edit.range.start.line == edit.range.end.line {
+            edits_by_line
+                .entry(edit.range.start.line as usize)
+                .or_default()
+                .push(edit);
+        }
+    }
 
-    // Skip edit if the line content hasn't changed
-    // This optimization prevents unnecessary edits for already-formatted lines
-    if original_line.trim_end() == new_content.trim_end() {
-        return None;
+    // (transaction node, line number, content before the comment, comment)
+    let mut entries: Vec<(usize, usize, String, String)> = Vec::new();
+    while let Some(matched) = matches.next() {
+        for capture in matched.captures {
+            let comment_node = capture.node;
+            let transaction_id = comment_node
+                .parent()
+                .and_then(|posting| posting.parent())
+                .map_or(0, |transaction| transaction.id());
+            let line_num = comment_node.start_position().row;
+            let comment = doc
+                .content
+                .byte_slice(comment_node.start_byte()..comment_node.end_byte())
+                .to_string();
+
+            let line_edits = edits_by_line.get(&line_num).map_or(&[][..], Vec::as_slice);
+            let line = formatted_line(doc, line_num, line_edits);
+            let Some(comment_start) = line.rfind(&comment) else {
+                continue;
+            };
+            let content = line[..comment_start].trim_end().to_string();
+            if content.trim().is_empty() {
+                continue;
+            }
+            entries.push((
+                transaction_id,
+                line_num,
+                content,
+                comment.trim_end().to_string(),
+            ));
+        }
     }
 
-    // Calculate character length (not byte length) for UTF-8 safety
-    let original_line_char_len = original_line.trim_end().chars().count();
+    let mut columns: HashMap<usize, usize> = HashMap::new();
+    for (transaction_id, _, content, _) in &entries {
+        let column = columns.entry(*transaction_id).or_insert(comment_column);
+        *column = (*column).max(content.chars().count() + 2);
+    }
 
-    let line_start = lsp_types::Position {
-        line: line_num as u32,
-        character: 0,
-    };
-    let line_end = lsp_types::Position {
-        line: line_num as u32,
-        character: original_line_char_len as u32,
-    };
+    let mut comment_edits = Vec::with_capacity(entries.len());
+    for (transaction_id, line_num, content, comment) in &entries {
+        let padding = columns[transaction_id] - content.chars().count();
+        let target_line = format!("{}{}{}", content, " ".repeat(padding), comment);
+        comment_edits.extend(create_line_replacement_edit(*line_num, &target_line, doc));
+    }
 
-    Some(lsp_types::TextEdit {
-        range: lsp_types::Range {
-            start: line_start,
-            end: line_end,
-        },
-        new_text: new_content.trim_end().to_string(),
-    })
+    // Replace earlier edits on the realigned lines; the targets already include them
+    let mut realigned = vec![false; doc.content.len_lines()];
+    for (_, line_num, _, _) in &entries {
+        realigned[*line_num] = true;
+    }
+    existing_edits.retain(|edit| !realigned[edit.range.start.line as usize]);
+    existing_edits.extend(comment_edits);
+
+    Ok(existing_edits)
+}
+
+/// Returns the content of a line with its single-line `edits` applied, without the line break.
+fn formatted_line(
+    doc: &crate::document::Document,
+    line_num: usize,
+    edits: &[&lsp_types::TextEdit],
+) -> String {
+    let mut line: Vec<char> = doc
+        .content
+        .line(line_num)
+        .to_string()
+        .trim_end()
+        .chars()
+        .collect();
+    let mut line_edits = edits.to_vec();
+    line_edits.sort_by_key(|edit| std::cmp::Reverse(edit.range.start.character));
+
+    for edit in line_edits {
+        let start = (edit.range.start.character as usize).min(line.len());
+        let end = (edit.range.end.character as usize).min(line.len());
+        line.splice(start..end, edit.new_text.chars());
+    }
+
+    line.into_iter().collect()
 }
 
 /// Applies indent normalization to lines not already handled by main formatting
@@ -541,36 +1442,22 @@ fn apply_indent_normalization_to_remaining_lines(
     target_indent_width: usize,
     mut existing_edits: Vec<lsp_types::TextEdit>,
 ) -> Result<Vec<lsp_types::TextEdit>> {
-    use std::collections::HashSet;
-
     let target_indent = " ".repeat(target_indent_width);
 
-    // Collect line numbers that already have edits from main formatting
-    let edited_lines: HashSet<u32> = existing_edits
-        .iter()
-        .map(|edit| edit.range.start.line)
-        .collect();
+    // Mark lines that already have edits from main formatting
+    let mut edited_lines = vec![false; doc.content.len_lines()];
+    for edit in &existing_edits {
+        edited_lines[edit.range.start.line as usize] = true;
+    }
 
     // Process all lines in the document
-    for line_num in 0..doc.content.len_lines() {
-        let line_num_u32 = line_num as u32;
-
+    for (line_num, line) in doc.content.lines().enumerate() {
         // Skip lines that already have formatting edits
-        if edited_lines.contains(&line_num_u32) {
+        if edited_lines[line_num] {
             continue;
         }
 
-        let line_start_char = doc.content.line_to_char(line_num);
-        let line_end_char = if line_num + 1 < doc.content.len_lines() {
-            doc.content.line_to_char(line_num + 1)
-        } else {
-            doc.content.len_chars()
-        };
-
-        let current_line = doc
-            .content
-            .slice(line_start_char..line_end_char)
-            .to_string();
+        let current_line = line.to_string();
 
         // Only process lines that start with whitespace AND are likely to be postings/metadata
         // Don't normalize lines that contain top-level directive keywords at the start
@@ -610,14 +1497,10 @@ fn apply_indent_normalization_to_remaining_lines(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::beancount_data::BeancountData;
     use crate::config::Config;
-    use crate::document::Document;
     use crate::server::LspServerStateSnapshot;
-    use std::collections::HashMap;
+    use lsp_types::TextDocumentSaveReason;
     use std::str::FromStr;
-    use std::sync::Arc;
-    use tree_sitter_beancount::tree_sitter;
 
     struct TestState {
         snapshot: LspServerStateSnapshot,
@@ -625,43 +1508,7 @@ mod tests {
 
     impl TestState {
         fn new(content: &str) -> anyhow::Result<Self> {
-            // Use a consistent path that works across platforms
-            let path = std::env::current_dir()?.join("test.beancount");
-            let rope_content = ropey::Rope::from_str(content);
-
-            // Parse the content with tree-sitter
-            let mut parser = tree_sitter::Parser::new();
-            parser.set_language(&tree_sitter_beancount::language())?;
-            let tree = parser.parse(content, None).unwrap();
-
-            // Create the necessary data structures
-            let mut forest = HashMap::new();
-            forest.insert(path.clone(), Arc::new(tree.clone()));
-
-            let mut open_docs = HashMap::new();
-            open_docs.insert(
-                path.clone(),
-                Document {
-                    content: rope_content.clone(),
-                    version: 0,
-                },
-            );
-
-            let mut beancount_data = HashMap::new();
-            beancount_data.insert(
-                path.clone(),
-                Arc::new(BeancountData::new(&tree, &rope_content)),
-            );
-
-            let snapshot = LspServerStateSnapshot {
-                beancount_data,
-                config: Config::new(std::env::current_dir()?),
-                forest,
-                open_docs,
-                checker: None,
-            };
-
-            Ok(TestState { snapshot })
+            Self::new_with_config(content, Default::default())
         }
 
         fn new_with_config(
@@ -670,43 +1517,9 @@ mod tests {
         ) -> anyhow::Result<Self> {
             // Use a consistent path that works across platforms
             let path = std::env::current_dir()?.join("test.beancount");
-            let rope_content = ropey::Rope::from_str(content);
-
-            // Parse the content with tree-sitter
-            let mut parser = tree_sitter::Parser::new();
-            parser.set_language(&tree_sitter_beancount::language())?;
-            let tree = parser.parse(content, None).unwrap();
-
-            // Create the necessary data structures
-            let mut forest = HashMap::new();
-            forest.insert(path.clone(), Arc::new(tree.clone()));
-
-            let mut open_docs = HashMap::new();
-            open_docs.insert(
-                path.clone(),
-                Document {
-                    content: rope_content.clone(),
-                    version: 0,
-                },
-            );
-
-            let mut beancount_data = HashMap::new();
-            beancount_data.insert(
-                path.clone(),
-                Arc::new(BeancountData::new(&tree, &rope_content)),
-            );
-
             let mut config = Config::new(std::env::current_dir()?);
             config.formatting = format_config;
-
-            let snapshot = LspServerStateSnapshot {
-                beancount_data,
-                config,
-                forest,
-                open_docs,
-                checker: None,
-            };
-
+            let snapshot = LspServerStateSnapshot::for_test(config, &[(&path, content)]);
             Ok(TestState { snapshot })
         }
 
@@ -733,55 +1546,41 @@ mod tests {
                 },
             };
 
-            // Create a new snapshot for each test call
-            let snapshot = LspServerStateSnapshot {
-                beancount_data: self.snapshot.beancount_data.clone(),
-                config: self.snapshot.config.clone(),
-                forest: self.snapshot.forest.clone(),
-                open_docs: self.snapshot.open_docs.clone(),
-                checker: self.snapshot.checker.clone(),
-            };
-
-            formatting(snapshot, params)
+            formatting(self.snapshot.clone(), params)
         }
     }
 
     fn apply_edits(content: &str, edits: &[lsp_types::TextEdit]) -> String {
-        let rope = ropey::Rope::from_str(content);
-        let mut sorted_edits = edits.to_vec();
-
-        // Sort edits in reverse order by position to avoid invalidating positions
-        sorted_edits.sort_by(|a, b| {
-            let line_cmp = b.range.start.line.cmp(&a.range.start.line);
-            if line_cmp == std::cmp::Ordering::Equal {
-                b.range.start.character.cmp(&a.range.start.character)
-            } else {
-                line_cmp
-            }
-        });
-
-        let mut result = rope;
-        for edit in sorted_edits {
-            let start_line = edit.range.start.line as usize;
-            let start_char = edit.range.start.character as usize;
-            let end_line = edit.range.end.line as usize;
-            let end_char = edit.range.end.character as usize;
-
-            let start_char_idx = result.line_to_char(start_line) + start_char;
-            let end_char_idx = result.line_to_char(end_line) + end_char;
-
-            // Remove the old text
-            if start_char_idx < end_char_idx {
-                result.remove(start_char_idx..end_char_idx);
-            }
+        apply_text_edits(content, edits)
+    }
 
-            // Insert the new text
-            if !edit.new_text.is_empty() {
-                result.insert(start_char_idx, &edit.new_text);
-            }
-        }
+    #[test]
+    fn test_currency_column_from_fava_option() {
+        let content = "2023-01-01 custom \"fava-option\" \"currency-column\" \"40\"\n2023-01-01 * \"Test\"\n  Assets:Cash  100.00 USD\n  Expenses:Food\n";
+        let with_column = |currency_column| {
+            let config = crate::config::FormattingConfig {
+                currency_column,
+                ..Default::default()
+            };
+            format_text(content, &config).unwrap()
+        };
 
-        result.to_string()
+        let state = TestState::new(content).unwrap();
+        let formatted = apply_edits(content, &state.format().unwrap().unwrap());
+        assert_eq!(formatted, with_column(Some(40)));
+        assert_ne!(formatted, with_column(None));
+
+        // A configured column takes precedence
+        let state = TestState::new_with_config(
+            content,
+            crate::config::FormattingConfig {
+                currency_column: Some(50),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let formatted = apply_edits(content, &state.format().unwrap().unwrap());
+        assert_eq!(formatted, with_column(Some(50)));
     }
 
     #[test]
@@ -820,6 +1619,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_will_save_wait_until_formats_with_format_on_save() {
+        let content =
+            "2023-01-01 * \"Test\"\n  Assets:Cash     100.00 USD\n  Expenses:Food 50.0 USD\n";
+        let mut state = TestState::new(content).unwrap();
+        let path = std::env::current_dir().unwrap().join("test.beancount");
+        let uri = crate::utils::file_path_to_uri(&path).unwrap();
+        let will_save = |snapshot: &LspServerStateSnapshot, reason| {
+            will_save_wait_until(
+                snapshot.clone(),
+                lsp_types::WillSaveTextDocumentParams {
+                    text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+                    reason,
+                },
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            will_save(&state.snapshot, TextDocumentSaveReason::MANUAL),
+            None
+        );
+
+        state.snapshot.config.format_on_save = true;
+        let edits = will_save(&state.snapshot, TextDocumentSaveReason::MANUAL).unwrap();
+        assert_eq!(Some(edits), state.format().unwrap());
+        assert_eq!(
+            will_save(&state.snapshot, TextDocumentSaveReason::AFTER_DELAY),
+            None
+        );
+    }
+
     #[test]
     fn test_formatting_already_aligned() {
         let content = r#"2023-01-01 * "Test transaction"
@@ -1019,6 +1850,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1062,6 +1894,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1108,6 +1941,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1144,6 +1978,7 @@ mod tests {
             account_amount_spacing: 3,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1179,6 +2014,7 @@ mod tests {
             account_amount_spacing: 5,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1228,6 +2064,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1282,6 +2119,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 2,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1327,6 +2165,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 0,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1363,6 +2202,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1412,6 +2252,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1479,6 +2320,7 @@ mod tests {
             account_amount_spacing: 2, // Should have at least 2 spaces
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1686,6 +2528,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1736,6 +2579,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: Some(4),
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1780,6 +2624,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: Some(2),
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1820,6 +2665,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: Some(2),
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -1975,6 +2821,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -2020,6 +2867,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: None,
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -2290,6 +3138,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: Some(2),
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -2335,6 +3184,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: Some(2),
+            ..Default::default()
         };
 
         let state = TestState::new_with_config(content, format_config).unwrap();
@@ -2373,6 +3223,7 @@ mod tests {
             account_amount_spacing: 2,
             number_currency_spacing: 1,
             indent_width: Some(2),
+            ..Default::default()
         };
         let state2 = TestState::new_with_config(&formatted, format_config2).unwrap();
         let edits2 = state2.format().unwrap().unwrap();
@@ -2382,4 +3233,359 @@ mod tests {
             "Second format should generate no edits (idempotent after single pass)"
         );
     }
+
+    #[test]
+    fn test_edits_only_cover_changed_whitespace() {
+        let content =
+            "2024-01-01 * \"Café\"\n  Assets:Café  1.00 USD\n  Expenses:Food    -1.00 USD\n";
+        let edits =
+            format_text_edits(content, &crate::config::FormattingConfig::default()).unwrap();
+
+        assert!(!edits.is_empty());
+        for edit in &edits {
+            assert_eq!(edit.range.start.line, edit.range.end.line);
+            assert!(
+                edit.new_text.chars().all(|c| c == ' '),
+                "edit should only touch whitespace: {edit:?}"
+            );
+        }
+        // Spaces are inserted after the existing "  Assets:Café  " prefix
+        let cash = edits.iter().find(|e| e.range.start.line == 1).unwrap();
+        assert_eq!(cash.range.start.character, 15);
+
+        let formatted = apply_text_edits(content, &edits);
+        assert_eq!(
+            formatted,
+            format_text(content, &crate::config::FormattingConfig::default()).unwrap()
+        );
+        assert!(formatted.contains("  Assets:Café     1.00 USD"));
+    }
+
+    #[test]
+    fn test_crlf_line_endings_are_preserved() {
+        let content =
+            "2024-01-01 * \"Test\"\r\n  Assets:Cash  1.00 USD\r\n  Expenses:Food    -1.00 USD\r\n";
+        let formatted = format_text(content, &crate::config::FormattingConfig::default()).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 * \"Test\"\r\n  Assets:Cash     1.00 USD\r\n  Expenses:Food  -1.00 USD\r\n"
+        );
+    }
+
+    #[test]
+    fn test_line_ending_auto_uses_dominant_ending() {
+        let content = "2024-01-01 open Assets:Cash\r\n2024-01-01 open Assets:Bank\r\n2024-01-01 open Income:Job\n";
+        let config = crate::config::FormattingConfig {
+            line_ending: LineEnding::Auto,
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 open Assets:Cash\r\n2024-01-01 open Assets:Bank\r\n2024-01-01 open Income:Job\r\n"
+        );
+    }
+
+    #[test]
+    fn test_line_ending_forced_lf_with_alignment() {
+        let content =
+            "2024-01-01 * \"Test\"\r\n  Assets:Cash  1.00 USD\r\n  Expenses:Food    -1.00 USD";
+        let config = crate::config::FormattingConfig {
+            line_ending: LineEnding::Lf,
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 * \"Test\"\n  Assets:Cash     1.00 USD\n  Expenses:Food  -1.00 USD"
+        );
+    }
+
+    #[test]
+    fn test_metadata_alignment_disabled_by_default() {
+        let content = "2024-01-01 * \"Test\"\n  id: \"1\"\n  category: \"food\"\n";
+        let formatted = format_text(content, &crate::config::FormattingConfig::default()).unwrap();
+        assert_eq!(formatted, content);
+    }
+
+    #[test]
+    fn test_metadata_alignment() {
+        let content = "2024-01-01 * \"Test\"\n  id:    \"1\"\n  category: \"food\"\n  Assets:Cash  -1.00 USD\n    receipt: \"a.pdf\"\n    ref: 2\n  Expenses:Food  1.00 USD\n";
+        let config = crate::config::FormattingConfig {
+            align_metadata: true,
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 * \"Test\"\n  id:       \"1\"\n  category: \"food\"\n  Assets:Cash    -1.00 USD\n    receipt: \"a.pdf\"\n    ref:     2\n  Expenses:Food   1.00 USD\n"
+        );
+        assert_eq!(format_text(&formatted, &config).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_metadata_alignment_with_indent_width() {
+        let content = "2024-01-01 open Assets:Cash\n  institution: \"Bank\"\n  id: \"42\"\n";
+        let config = crate::config::FormattingConfig {
+            align_metadata: true,
+            indent_width: Some(4),
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 open Assets:Cash\n    institution: \"Bank\"\n    id:          \"42\"\n"
+        );
+    }
+
+    #[test]
+    fn test_comment_alignment() {
+        let content = "2024-01-01 * \"Test\"\n  Assets:Cash  -1.00 USD ; cash\n  Expenses:Food    1.00 USD    ; lunch\n  Expenses:Tip ; none\n\n2024-01-02 * \"Other\"\n  Assets:Cash  -2.00 USD ; far\n  Expenses:Food\n";
+        let config = crate::config::FormattingConfig {
+            comment_column: Some(0),
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 * \"Test\"\n  Assets:Cash    -1.00 USD  ; cash\n  Expenses:Food   1.00 USD  ; lunch\n  Expenses:Tip              ; none\n\n2024-01-02 * \"Other\"\n  Assets:Cash    -2.00 USD  ; far\n  Expenses:Food\n"
+        );
+        assert_eq!(format_text(&formatted, &config).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_comment_alignment_at_configured_column() {
+        let content = "2024-01-01 * \"Test\"\n   Assets:Cash ; cash\n  Expenses:Food ; food\n";
+        let config = crate::config::FormattingConfig {
+            comment_column: Some(30),
+            indent_width: Some(4),
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 * \"Test\"\n    Assets:Cash               ; cash\n    Expenses:Food             ; food\n"
+        );
+    }
+
+    #[test]
+    fn test_verify_accepts_whitespace_changes() {
+        let original =
+            "2024-01-01 * \"Test\"\n  Assets:Cash  -1.00 USD\n  Expenses:Food 1.00 USD\n";
+        let formatted = format_text(original, &crate::config::FormattingConfig::default()).unwrap();
+        assert!(verify(original, &formatted).is_ok());
+    }
+
+    #[test]
+    fn test_verify_rejects_lost_and_altered_directives() {
+        let original = "2024-01-01 open Assets:Cash\n2024-01-01 open Assets:Bank\n";
+        assert!(verify(original, "2024-01-01 open Assets:Cash\n").is_err());
+        assert!(
+            verify(
+                original,
+                "2024-01-01 open Assets:Cash\n2024-01-01 open Assets:Bonk\n"
+            )
+            .is_err()
+        );
+        assert!(
+            verify(
+                original,
+                "2024-01-01 open Assets:Cash\n2024-01-01 open Assets:Cash\n2024-01-01 open Assets:Bank\n"
+            )
+            .is_err()
+        );
+        assert!(
+            verify(
+                original,
+                "2024-01-01 open Assets:Bank\n2024-01-01 open Assets:Cash\n"
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_transaction_header_normalization() {
+        let content = "2024-01-01  txn   \"Shop\"  \"Food\"   #tag  ^link   ; note\n  Assets:Cash  -1.00 USD\n  Expenses:Food  1.00 USD\n2024-01-02 !\"Pending\"\n  Assets:Cash  -2.00 USD\n  Expenses:Food  2.00 USD\n";
+        let config = crate::config::FormattingConfig {
+            normalize_transaction_headers: true,
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 * \"Shop\" \"Food\" #tag ^link   ; note\n  Assets:Cash    -1.00 USD\n  Expenses:Food   1.00 USD\n2024-01-02 ! \"Pending\"\n  Assets:Cash    -2.00 USD\n  Expenses:Food   2.00 USD\n"
+        );
+        assert!(verify(content, &formatted).is_ok());
+    }
+
+    #[test]
+    fn test_transaction_header_normalization_disabled_by_default() {
+        let content = "2024-01-01  txn \"Shop\"\n";
+        let formatted = format_text(content, &crate::config::FormattingConfig::default()).unwrap();
+        assert_eq!(formatted, content);
+    }
+
+    #[test]
+    fn test_blank_lines_between_directives() {
+        let content = "2024-01-01 open Assets:Cash\n2024-01-01 open Expenses:Food\n\n\n\n2024-01-02 * \"Shop\"\n  Assets:Cash  -1.00 USD\n  Expenses:Food  1.00 USD\n  \n; comment\n\n\n2024-01-03 close Assets:Cash\n";
+        let config = crate::config::FormattingConfig {
+            blank_lines_between_directives: BlankLines::Exactly(1),
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 open Assets:Cash\n\n2024-01-01 open Expenses:Food\n\n2024-01-02 * \"Shop\"\n  Assets:Cash    -1.00 USD\n  Expenses:Food   1.00 USD\n  \n; comment\n\n\n2024-01-03 close Assets:Cash\n"
+        );
+        assert_eq!(format_text(&formatted, &config).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_no_blank_lines_between_directives_with_crlf() {
+        let content = "2024-01-01 open Assets:Cash\r\n\r\n\r\n2024-01-01 open Expenses:Food\r\n";
+        let config = crate::config::FormattingConfig {
+            blank_lines_between_directives: BlankLines::Exactly(0),
+            line_ending: LineEnding::Lf,
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 open Assets:Cash\n2024-01-01 open Expenses:Food\n"
+        );
+    }
+
+    #[test]
+    fn test_max_blank_lines() {
+        let content = "\n\n\n2024-01-01 open Assets:Cash\n\n\n\n\n; comment\n\n\n\n2024-01-02 * \"Shop\"\n  Assets:Cash  -1.00 USD\n  Expenses:Food  1.00 USD\n\n\n\n";
+        let config = crate::config::FormattingConfig {
+            max_blank_lines: Some(2),
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "\n\n2024-01-01 open Assets:Cash\n\n\n; comment\n\n\n2024-01-02 * \"Shop\"\n  Assets:Cash    -1.00 USD\n  Expenses:Food   1.00 USD\n\n\n"
+        );
+    }
+
+    #[test]
+    fn test_max_blank_lines_caps_directive_policy() {
+        let content = "2024-01-01 open Assets:Cash\n2024-01-01 open Expenses:Food\n";
+        let config = crate::config::FormattingConfig {
+            blank_lines_between_directives: BlankLines::Exactly(3),
+            max_blank_lines: Some(1),
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert_eq!(
+            formatted,
+            "2024-01-01 open Assets:Cash\n\n2024-01-01 open Expenses:Food\n"
+        );
+    }
+
+    #[test]
+    fn test_char_column_to_lsp_position() {
+        let content = ropey::Rope::from_str("2024-01-01 * \"𝄞\"  ; note\n");
+        // The comment starts at character 18, after a character outside the BMP
+        let position = char_column_to_lsp_position(&content, lsp_types::Position::new(0, 18));
+        assert_eq!(position, lsp_types::Position::new(0, 19));
+
+        // Positions past the line end or on missing lines are left alone
+        let end = lsp_types::Position::new(0, u32::MAX);
+        assert_eq!(char_column_to_lsp_position(&content, end), end);
+        let missing = lsp_types::Position::new(5, 3);
+        assert_eq!(char_column_to_lsp_position(&content, missing), missing);
+    }
+
+    #[test]
+    fn test_date_normalization() {
+        let content = "2024/03/05 open Assets:Cash\n2024/03/06 * \"Shop\"\n  due: 2024/04/01\n  Assets:Cash  -1.00 USD\n  Expenses:Food  1.00 USD\n2024/03/07 balance Assets:Cash  -1.00 USD\n";
+        let config = crate::config::FormattingConfig {
+            normalize_dates: true,
+            align_metadata: true,
+            ..Default::default()
+        };
+        let formatted = format_text(content, &config).unwrap();
+        assert!(!formatted.contains('/'), "{formatted}");
+        assert!(formatted.starts_with(
+            "2024-03-05 open Assets:Cash\n2024-03-06 * \"Shop\"\n  due: 2024-04-01\n"
+        ));
+        assert!(formatted.contains("2024-03-07 balance Assets:Cash"));
+        assert!(verify(content, &formatted).is_ok());
+        assert_eq!(format_text(&formatted, &config).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_date_normalization_merges_with_line_edits() {
+        let content = "2024/03/06 * \"Shop 2024/03/06\"\n  Assets:Cash  -1.00 USD\n  Expenses:Food    1.00 USD\n";
+        let config = crate::config::FormattingConfig {
+            normalize_dates: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_text(content, &config).unwrap(),
+            "2024-03-06 * \"Shop 2024/03/06\"\n  Assets:Cash    -1.00 USD\n  Expenses:Food   1.00 USD\n"
+        );
+
+        // Edits of other passes that rewrite the date keep their own changes
+        let doc = crate::document::Document {
+            content: ropey::Rope::from_str(content),
+            version: 0,
+        };
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let edit = |line, start, end, text: &str| lsp_types::TextEdit {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(line, start),
+                lsp_types::Position::new(line, end),
+            ),
+            new_text: text.to_string(),
+        };
+        for (existing, header) in [
+            (
+                edit(0, 0, 12, "2024/03/06 !"),
+                "2024-03-06 ! \"Shop 2024/03/06\"",
+            ),
+            (edit(0, 7, 12, "/06 !"), "2024-03-06 ! \"Shop 2024/03/06\""),
+            (
+                edit(0, 0, 7, "  2024/03"),
+                "  2024-03-06 * \"Shop 2024/03/06\"",
+            ),
+        ] {
+            let edits = apply_date_normalization(&doc, &tree, vec![existing]).unwrap();
+            assert_eq!(edits.len(), 1, "{edits:?}");
+            let formatted = apply_text_edits(content, &edits);
+            assert_eq!(formatted.lines().next(), Some(header));
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors_left_unformatted() {
+        let content = "2024-01-01 open   Assets:Cash\n\n2024-01-02 * \"Shop\"\n  Assets:Cash   -1.00 USD\n  Expenses:Food  1.00 USD  )(\n\n2024-01-03 open   Expenses:Food\n";
+        let config = crate::config::FormattingConfig::default();
+        let formatted = format_text(content, &config).unwrap();
+        let errors = format_text_strict(content, &config).unwrap().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "line 5: syntax error");
+        // Lines outside the error are still formatted
+        assert!(
+            formatted.contains("  Assets:Cash  -1.00 USD\n"),
+            "{formatted}"
+        );
+        // The broken transaction is kept verbatim
+        for line in errors[0].line..=errors[0].end_line {
+            let original = content.lines().nth(line).unwrap();
+            assert!(formatted.contains(original), "{formatted}");
+        }
+        assert_eq!(
+            format_text_strict(&formatted.replace(" )(", ""), &config)
+                .unwrap()
+                .map(|_| ()),
+            Ok(())
+        );
+    }
 }
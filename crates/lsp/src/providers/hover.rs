@@ -1,14 +1,21 @@
+use crate::forest;
+use crate::ledger::{self, Inventory};
+use crate::ledger_options::option_doc;
 use crate::providers::inlay_hints::transaction_inlay_hints;
+use crate::providers::status::count_directives;
 use crate::server::LspServerStateSnapshot;
 use crate::treesitter_utils::{
     lsp_position_to_tree_sitter_point_range, text_for_tree_sitter_node,
     tree_sitter_node_to_lsp_range,
 };
+use crate::utils::ToFilePath;
 use anyhow::Result;
+use chrono::Datelike;
 use lsp_types::{
     Hover, HoverContents, HoverParams, InlayHintLabel, MarkupContent, MarkupKind, Range,
 };
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
 use tree_sitter_beancount::{NodeKind, tree_sitter};
 
 /// Provider function for `textDocument/hover`.
@@ -38,6 +45,53 @@ pub(crate) fn hover(
         return Ok(None);
     };
 
+    match NodeKind::from(node.kind()) {
+        NodeKind::Currency => {
+            return Ok(uri
+                .to_file_path()
+                .ok()
+                .and_then(|file| commodity_hover(&snapshot, &file, &content, node)));
+        }
+        NodeKind::Tag | NodeKind::Link => {
+            return Ok(uri
+                .to_file_path()
+                .ok()
+                .and_then(|file| tag_hover(&snapshot, &file, &content, node)));
+        }
+        _ => {}
+    }
+
+    // Amounts in the operating currencies have nothing to convert and fall through
+    if let Some(hover) = find_amount_node(node).and_then(|amount_node| {
+        let file = uri.to_file_path().ok()?;
+        amount_hover(&snapshot, &file, &content, amount_node)
+    }) {
+        return Ok(Some(hover));
+    }
+
+    if let Some(include_node) = find_node_of_kind(node, NodeKind::Include) {
+        return Ok(uri
+            .to_file_path()
+            .ok()
+            .and_then(|file| include_hover(&snapshot, &file, &content, include_node)));
+    }
+
+    if let Some(option_node) = find_node_of_kind(node, NodeKind::Option) {
+        return Ok(option_hover(&content, option_node));
+    }
+
+    if let Some(balance_node) = find_node_of_kind(node, NodeKind::Balance) {
+        return Ok(uri
+            .to_file_path()
+            .ok()
+            .and_then(|file| balance_assertion_hover(&snapshot, &file, &content, balance_node)));
+    }
+
+    if NodeKind::from(node.kind()) == NodeKind::Date {
+        let today = chrono::Local::now().date_naive();
+        return Ok(date_hover(&content, node, today));
+    }
+
     let posting_hint = find_posting_inlay_hint(&content, node);
 
     let account_node = find_node_of_kind(node, NodeKind::Account);
@@ -47,8 +101,12 @@ pub(crate) fn hover(
 
     let account_name = text_for_tree_sitter_node(&content, &account_node);
     let notes = collect_account_notes(&snapshot.beancount_data, &account_name);
+    let balance = uri
+        .to_file_path()
+        .ok()
+        .and_then(|file| posting_balance(&snapshot, &file, &content, account_node));
 
-    if notes.is_empty() && posting_hint.is_none() {
+    if notes.is_empty() && posting_hint.is_none() && balance.is_none() {
         return Ok(None);
     }
 
@@ -62,6 +120,10 @@ pub(crate) fn hover(
         sections.push(format_posting_hover_text(&label));
     }
 
+    if let Some((date, inventory)) = balance {
+        sections.push(format_balance_hover_text(&date, &inventory));
+    }
+
     let hover_text = sections.join("\n\n");
     let range = tree_sitter_node_to_lsp_range(&content, &account_node);
 
@@ -77,6 +139,385 @@ pub(crate) fn hover(
     }))
 }
 
+/// The computed balance at a balance assertion and how far it is off, with the notes
+/// of the asserted account.
+fn balance_assertion_hover(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+    balance_node: tree_sitter::Node,
+) -> Option<Hover> {
+    let line = balance_node.start_position().row as u32;
+    let ledger = snapshot.ledger_for(file);
+    let check = ledger
+        .balance_checks()
+        .iter()
+        .find(|check| check.file == file && check.line == line)?;
+
+    let currency = &check.expected.currency;
+    let status = if check.passed() { "passes" } else { "fails" };
+    let mut summary = format!(
+        "**Balance assertion {status}**\n\n- Asserted: {} {currency}\n- Actual: {} {currency}\n",
+        check.expected.value, check.actual,
+    );
+    if let Some(difference) = check.actual.checked_sub(check.expected.value) {
+        summary.push_str(&format!("- Difference: {difference} {currency}\n"));
+    }
+    let mut sections = vec![summary];
+    let notes = collect_account_notes(&snapshot.beancount_data, &check.account);
+    if !notes.is_empty() {
+        sections.push(format_account_hover_text(&check.account, &notes));
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: sections.join("\n\n"),
+        }),
+        range: Some(tree_sitter_node_to_lsp_range(content, &balance_node)),
+    })
+}
+
+/// Where an include points to, and how many directives over which dates it adds.
+fn include_hover(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+    include_node: tree_sitter::Node,
+) -> Option<Hover> {
+    let mut cursor = include_node.walk();
+    let string_node = include_node
+        .named_children(&mut cursor)
+        .find(|child| child.kind() == "string")?;
+    let filename = text_for_tree_sitter_node(content, &string_node);
+    let pattern = forest::resolve_include_pattern(filename.trim_matches('"'), file);
+
+    let matches = include_matches(snapshot, &pattern);
+
+    let mut value = format!("**Include**\n\n`{}`\n\n", pattern.display());
+    match matches.len() {
+        0 => value.push_str("- File not found\n"),
+        1 if matches[0] == pattern => value.push_str("- File exists\n"),
+        n => value.push_str(&format!("- Matches {n} file{}\n", plural(n))),
+    }
+
+    let mut directives = 0;
+    let mut dates: Option<(String, String)> = None;
+    for path in &matches {
+        let Some((tree, text)) = snapshot.tree_and_text(path) else {
+            continue;
+        };
+        let mut counts = BTreeMap::new();
+        count_directives(tree.root_node(), &mut counts);
+        directives += counts.values().sum::<usize>();
+        collect_date_range(tree.root_node(), &text, &mut dates);
+    }
+    if !matches.is_empty() {
+        value.push_str(&format!("- {directives} directive{}", plural(directives)));
+        match dates {
+            Some((first, last)) if first == last => value.push_str(&format!(" on {first}")),
+            Some((first, last)) => value.push_str(&format!(" from {first} to {last}")),
+            None => {}
+        }
+        value.push('\n');
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(tree_sitter_node_to_lsp_range(content, &string_node)),
+    })
+}
+
+/// The files `pattern` includes. Indexed files are matched in the forest; the disk is
+/// only searched when none of them match, e.g. while indexing or for a new file.
+fn include_matches(snapshot: &LspServerStateSnapshot, pattern: &Path) -> Vec<PathBuf> {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    let glob_pattern = glob::Pattern::new(&pattern.to_string_lossy()).ok();
+    let mut indexed: Vec<PathBuf> = snapshot
+        .forest
+        .keys()
+        .filter(|path| {
+            *path == pattern
+                || glob_pattern
+                    .as_ref()
+                    .is_some_and(|glob_pattern| glob_pattern.matches_path_with(path, options))
+        })
+        .cloned()
+        .collect();
+    if !indexed.is_empty() {
+        indexed.sort();
+        return indexed;
+    }
+    glob::glob(&pattern.to_string_lossy())
+        .map(|paths| paths.filter_map(|path| path.ok()).collect())
+        .unwrap_or_default()
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+/// Widens `range` to the dates of the dated directives below `node`.
+fn collect_date_range(
+    node: tree_sitter::Node,
+    content: &ropey::Rope,
+    range: &mut Option<(String, String)>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "section" {
+            collect_date_range(child, content, range);
+            continue;
+        }
+        let Some(date) = child.child_by_field_name("date") else {
+            continue;
+        };
+        let date = text_for_tree_sitter_node(content, &date).replace('/', "-");
+        match range {
+            Some((first, last)) => {
+                if date < *first {
+                    *first = date.clone();
+                }
+                if date > *last {
+                    *last = date;
+                }
+            }
+            None => *range = Some((date.clone(), date)),
+        }
+    }
+}
+
+/// The built-in documentation of the option an `option` directive sets.
+fn option_hover(content: &ropey::Rope, option_node: tree_sitter::Node) -> Option<Hover> {
+    let key_node = option_node.child_by_field_name("key")?;
+    let key = text_for_tree_sitter_node(content, &key_node);
+    let doc = option_doc(key.trim_matches('"'))?;
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "**option \"{}\"**\n\n{}\n\n- Type: {}\n- Default: `{}`\n",
+                doc.name, doc.description, doc.value_type, doc.default
+            ),
+        }),
+        range: Some(tree_sitter_node_to_lsp_range(content, &key_node)),
+    })
+}
+
+/// The weekday, ISO week and distance from `today` of the hovered date.
+fn date_hover(
+    content: &ropey::Rope,
+    date_node: tree_sitter::Node,
+    today: chrono::NaiveDate,
+) -> Option<Hover> {
+    let text = text_for_tree_sitter_node(content, &date_node).replace('/', "-");
+    let date = chrono::NaiveDate::parse_from_str(&text, "%Y-%m-%d").ok()?;
+    let week = date.iso_week();
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "**{}**, week {} of {}\n\n{}",
+                date.format("%A"),
+                week.week(),
+                week.year(),
+                relative_date(date, today)
+            ),
+        }),
+        range: Some(tree_sitter_node_to_lsp_range(content, &date_node)),
+    })
+}
+
+/// Describes how far `date` is from `today`, e.g. "3 weeks ago" or "in 2 days".
+fn relative_date(date: chrono::NaiveDate, today: chrono::NaiveDate) -> String {
+    let days = (date - today).num_days();
+    let (count, unit) = match days.abs() {
+        0 => return "today".to_string(),
+        1 if days < 0 => return "yesterday".to_string(),
+        1 => return "tomorrow".to_string(),
+        n @ 2..14 => (n, "day"),
+        n @ 14..60 => (n / 7, "week"),
+        n @ 60..730 => (n * 12 / 365, "month"),
+        n => (n / 365, "year"),
+    };
+    let unit = format!("{count} {unit}{}", plural(count as usize));
+    if days < 0 {
+        format!("{unit} ago")
+    } else {
+        format!("in {unit}")
+    }
+}
+
+fn find_amount_node(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    [
+        NodeKind::Amount,
+        NodeKind::IncompleteAmount,
+        NodeKind::AmountTolerance,
+    ]
+    .into_iter()
+    .find_map(|kind| find_node_of_kind(node, kind))
+}
+
+/// The value of a foreign amount in the operating currencies, at the price closest to
+/// the date of its directive.
+fn amount_hover(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+    amount_node: tree_sitter::Node,
+) -> Option<Hover> {
+    let amount = ledger::extract_simple_amount(&amount_node, content)?;
+    let operating_currencies = snapshot.ledger_options_for(file).operating_currencies;
+    if operating_currencies.contains(&amount.currency) {
+        return None;
+    }
+    let mut directive = amount_node;
+    let date_node = loop {
+        if let Some(date) = directive.child_by_field_name("date") {
+            break date;
+        }
+        directive = directive.parent()?;
+    };
+    let date = text_for_tree_sitter_node(content, &date_node).replace('/', "-");
+
+    let ledger = snapshot.ledger_for(file);
+    let conversions: Vec<String> = operating_currencies
+        .iter()
+        .filter_map(|quote| {
+            let (rate, price_date) = ledger.nearest_price(&amount.currency, quote, &date)?;
+            let value = amount.value.checked_mul(rate)?;
+            Some(format!(
+                "**≈ {} {quote}**\n\nat {} {quote} per {} (price of {price_date})",
+                value.round_dp(2).normalize(),
+                rate.round_dp(6).normalize(),
+                amount.currency,
+            ))
+        })
+        .collect();
+    if conversions.is_empty() {
+        return None;
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: conversions.join("\n\n"),
+        }),
+        range: Some(tree_sitter_node_to_lsp_range(content, &amount_node)),
+    })
+}
+
+/// Number of prices listed in the history of a commodity hover.
+const PRICE_HISTORY_LEN: usize = 5;
+
+/// The latest price of the hovered commodity and its most recent price history.
+fn commodity_hover(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+    currency_node: tree_sitter::Node,
+) -> Option<Hover> {
+    let currency = text_for_tree_sitter_node(content, &currency_node);
+    let ledger = snapshot.ledger_for(file);
+    let prices = ledger.prices(&currency);
+    let latest = prices.last()?;
+
+    let mut text = format!(
+        "**{currency}**\n\nLatest price: {} {} ({})\n",
+        latest.amount.value, latest.amount.currency, latest.date
+    );
+    if prices.len() > 1 {
+        text.push_str("\nRecent prices:\n");
+        for price in prices.iter().rev().take(PRICE_HISTORY_LEN) {
+            text.push_str(&format!(
+                "- {}: {} {}\n",
+                price.date, price.amount.value, price.amount.currency
+            ));
+        }
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: text,
+        }),
+        range: Some(tree_sitter_node_to_lsp_range(content, &currency_node)),
+    })
+}
+
+/// How many transactions carry the hovered tag or link, when, and what they spent.
+fn tag_hover(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+    node: tree_sitter::Node,
+) -> Option<Hover> {
+    let text = text_for_tree_sitter_node(content, &node);
+    let ledger = snapshot.ledger_for(file);
+    let summary = match NodeKind::from(node.kind()) {
+        NodeKind::Tag => ledger.tag(&text[1..]),
+        _ => ledger.link(&text[1..]),
+    }?;
+
+    let mut value = format!(
+        "**{text}**\n\n{} transaction{} ",
+        summary.transactions,
+        plural(summary.transactions)
+    );
+    if summary.first_date == summary.last_date {
+        value.push_str(&format!("on {}\n", summary.first_date));
+    } else {
+        value.push_str(&format!(
+            "from {} to {}\n",
+            summary.first_date, summary.last_date
+        ));
+    }
+
+    let expenses_root = &snapshot.ledger_options_for(file).root_accounts[4];
+    let mut expenses = Inventory::new();
+    let mut overflowed = BTreeSet::new();
+    for (account, inventory) in &summary.accounts {
+        if account.split(':').next() == Some(expenses_root.as_str()) {
+            for (currency, amount) in inventory {
+                let total = expenses.entry(currency.clone()).or_default();
+                match total.checked_add(*amount) {
+                    Some(sum) => *total = sum,
+                    None => {
+                        overflowed.insert(currency.clone());
+                    }
+                }
+            }
+        }
+    }
+    expenses.retain(|currency, amount| !amount.is_zero() || overflowed.contains(currency));
+    if !expenses.is_empty() {
+        value.push_str(&format!("\n{expenses_root}:\n"));
+        for (currency, amount) in expenses {
+            if overflowed.contains(&currency) {
+                value.push_str(&format!("- total too large in {currency}\n"));
+            } else {
+                value.push_str(&format!("- {amount} {currency}\n"));
+            }
+        }
+    }
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(tree_sitter_node_to_lsp_range(content, &node)),
+    })
+}
+
 fn find_node_of_kind<'a>(
     mut node: tree_sitter::Node<'a>,
     kind: NodeKind,
@@ -132,6 +573,41 @@ fn format_posting_hover_text(label: &str) -> String {
     format!("**Posting hint**\n\n{}", label.trim_start())
 }
 
+/// The balance of the posting's account right after the posting, with the date of its
+/// transaction.
+fn posting_balance(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    content: &ropey::Rope,
+    account_node: tree_sitter::Node,
+) -> Option<(String, Inventory)> {
+    let posting_node = account_node
+        .parent()
+        .filter(|parent| NodeKind::from(parent.kind()) == NodeKind::Posting)?;
+    let transaction_node = find_node_of_kind(posting_node, NodeKind::Transaction)?;
+    let date = transaction_node.child_by_field_name("date")?;
+    let date = text_for_tree_sitter_node(content, &date).replace('/', "-");
+    let account = text_for_tree_sitter_node(content, &account_node);
+    let line = posting_node.start_position().row as u32;
+
+    let ledger = snapshot.ledger_for(file);
+    let inventory = ledger
+        .balance_after(&account, file, line)
+        .unwrap_or_else(|| ledger.balance_at(&account, &date));
+    Some((date, inventory))
+}
+
+fn format_balance_hover_text(date: &str, inventory: &Inventory) -> String {
+    let mut text = format!("**Balance on {date}**\n\n");
+    if inventory.is_empty() {
+        text.push_str("- 0\n");
+    }
+    for (currency, value) in inventory {
+        text.push_str(&format!("- {value} {currency}\n"));
+    }
+    text
+}
+
 fn find_posting_inlay_hint(content: &ropey::Rope, node: tree_sitter::Node) -> Option<String> {
     let posting_node = find_node_of_kind(node, NodeKind::Posting)?;
     let transaction_node = find_node_of_kind(posting_node, NodeKind::Transaction)?;
@@ -151,14 +627,10 @@ fn find_posting_inlay_hint(content: &ropey::Rope, node: tree_sitter::Node) -> Op
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::beancount_data::BeancountData;
     use crate::config::Config;
-    use crate::document::Document;
     use ropey::Rope;
-    use std::collections::HashMap;
     use std::path::PathBuf;
     use std::str::FromStr;
-    use std::sync::Arc;
     use tree_sitter_beancount::tree_sitter;
     use url::Url;
 
@@ -169,41 +641,15 @@ mod tests {
 
     impl TestState {
         fn new(content: &str) -> anyhow::Result<Self> {
-            let path = std::env::current_dir()?.join("test.beancount");
-            let rope_content = Rope::from_str(content);
-
-            let mut parser = tree_sitter::Parser::new();
-            parser.set_language(&tree_sitter_beancount::language())?;
-            let tree = parser.parse(content, None).unwrap();
-
-            let mut forest = HashMap::new();
-            forest.insert(path.clone(), Arc::new(tree.clone()));
-
-            let mut open_docs = HashMap::new();
-            open_docs.insert(
-                path.clone(),
-                Document {
-                    content: rope_content.clone(),
-                    version: 0,
-                },
-            );
-
-            let mut beancount_data = HashMap::new();
-            beancount_data.insert(
-                path.clone(),
-                Arc::new(BeancountData::new(&tree, &rope_content)),
-            );
+            Self::at(std::env::current_dir()?.join("test.beancount"), content)
+        }
 
-            let config = Config::new(path.clone());
+        fn at(path: PathBuf, content: &str) -> anyhow::Result<Self> {
+            let mut config = Config::new(path.clone());
+            config.journal_root = Some(path.clone());
 
             Ok(Self {
-                snapshot: LspServerStateSnapshot {
-                    forest,
-                    open_docs,
-                    beancount_data,
-                    config,
-                    checker: None,
-                },
+                snapshot: LspServerStateSnapshot::for_test(config, &[(&path, content)]),
                 path,
             })
         }
@@ -261,4 +707,386 @@ mod tests {
             _ => panic!("Expected markup hover content"),
         }
     }
+
+    #[test]
+    fn test_hover_shows_balance_after_posting() {
+        let content = "2024-01-01 * \"Salary\"\n  Assets:Cash  100 USD\n  Income:Salary\n\n2024-01-05 * \"Food\"\n  Expenses:Food  25.50 USD\n  Assets:Cash\n\n2024-01-06 * \"Later\"\n  Assets:Cash  1 USD\n  Income:Salary\n";
+        let state = TestState::new(content).unwrap();
+
+        let uri =
+            lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref()).unwrap();
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(6, 4),
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        let result = hover(state.snapshot, params).unwrap();
+        let hover = result.expect("Expected hover result");
+        match hover.contents {
+            HoverContents::Markup(markup) => {
+                assert!(markup.value.contains("**Balance on 2024-01-05**"));
+                assert!(markup.value.contains("- 74.50 USD"), "{}", markup.value);
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+    }
+
+    #[test]
+    fn test_hover_shows_commodity_prices() {
+        let mut content = String::from("2024-01-01 commodity ABC\n");
+        for day in 1..=7 {
+            content.push_str(&format!("2024-02-0{day} price ABC  {day}.50 USD\n"));
+        }
+        let state = TestState::new(&content).unwrap();
+
+        let uri =
+            lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref()).unwrap();
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(0, 22),
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        let result = hover(state.snapshot, params).unwrap();
+        let hover = result.expect("Expected hover result");
+        assert_eq!(hover.range.unwrap().start, lsp_types::Position::new(0, 21));
+        match hover.contents {
+            HoverContents::Markup(markup) => {
+                assert!(
+                    markup.value.contains("Latest price: 7.50 USD (2024-02-07)"),
+                    "{}",
+                    markup.value
+                );
+                assert!(markup.value.contains("- 2024-02-03: 3.50 USD"));
+                assert!(!markup.value.contains("2024-02-02"));
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+    }
+
+    #[test]
+    fn test_hover_summarizes_tag() {
+        let content = "2024-03-01 * \"Flight\" #trip\n  Expenses:Travel  300 EUR\n  Assets:Cash\n\n2024-03-02 * \"Hotel\" #trip\n  Expenses:Travel  200 EUR\n  Expenses:Food  20.50 EUR\n  Assets:Cash\n";
+        let state = TestState::new(content).unwrap();
+
+        let uri =
+            lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref()).unwrap();
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(0, 25),
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        let result = hover(state.snapshot, params).unwrap();
+        let hover = result.expect("Expected hover result");
+        match hover.contents {
+            HoverContents::Markup(markup) => {
+                assert_eq!(
+                    markup.value,
+                    "**#trip**\n\n2 transactions from 2024-03-01 to 2024-03-02\n\nExpenses:\n- 520.50 EUR\n"
+                );
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+    }
+
+    #[test]
+    fn test_hover_marks_overflowing_tag_total() {
+        let content = "2024-03-01 * \"Flight\" #trip\n  Expenses:Travel  50000000000000000000000000000 EUR\n  Assets:Cash\n\n2024-03-02 * \"Hotel\" #trip\n  Expenses:Lodging  50000000000000000000000000000 EUR\n  Expenses:Food  20.50 USD\n  Assets:Cash\n";
+        let state = TestState::new(content).unwrap();
+
+        let uri =
+            lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref()).unwrap();
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(0, 25),
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        match hover(state.snapshot, params).unwrap().unwrap().contents {
+            HoverContents::Markup(markup) => {
+                assert!(
+                    markup
+                        .value
+                        .ends_with("Expenses:\n- total too large in EUR\n- 20.50 USD\n"),
+                    "{}",
+                    markup.value
+                );
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+    }
+
+    #[test]
+    fn test_hover_shows_balance_assertion_delta() {
+        let content = "2024-01-01 * \"Deposit\"\n  Assets:Bank  100 USD\n  Income:Other\n\n2024-01-02 balance Assets:Bank  100 USD\n2024-01-03 balance Assets:Bank  90.5 USD\n";
+        let hover_text = |line| {
+            let state = TestState::new(content).unwrap();
+            let uri = lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref())
+                .unwrap();
+            let params = HoverParams {
+                text_document_position_params: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier { uri },
+                    position: lsp_types::Position::new(line, 2),
+                },
+                work_done_progress_params: Default::default(),
+            };
+            match hover(state.snapshot, params).unwrap().unwrap().contents {
+                HoverContents::Markup(markup) => markup.value,
+                _ => panic!("Expected markup hover content"),
+            }
+        };
+
+        let passing = hover_text(4);
+        assert!(passing.contains("**Balance assertion passes**"));
+        assert!(passing.contains("- Difference: 0 USD"));
+
+        let failing = hover_text(5);
+        assert!(failing.contains("**Balance assertion fails**"));
+        assert!(failing.contains("- Actual: 100 USD"));
+        assert!(failing.contains("- Difference: 9.5 USD"), "{failing}");
+    }
+
+    #[test]
+    fn test_hover_leaves_out_overflowing_balance_delta() {
+        let content = "2024-01-01 * \"Withdrawal\"\n  Assets:Bank  -50000000000000000000000000000 USD\n  Income:Other\n\n2024-01-02 balance Assets:Bank  50000000000000000000000000000 USD\n";
+        let state = TestState::new(content).unwrap();
+        let uri =
+            lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref()).unwrap();
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(4, 2),
+            },
+            work_done_progress_params: Default::default(),
+        };
+        match hover(state.snapshot, params).unwrap().unwrap().contents {
+            HoverContents::Markup(markup) => {
+                assert!(markup.value.contains("**Balance assertion fails**"));
+                assert!(!markup.value.contains("Difference"), "{}", markup.value);
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+    }
+
+    #[test]
+    fn test_hover_on_balance_amount_shows_assertion() {
+        let content = "2024-01-01 * \"Deposit\"\n  Assets:Bank  100 USD\n  Income:Other\n\n2024-01-02 balance Assets:Bank  90.5 USD\n";
+        let state = TestState::new(content).unwrap();
+        let uri =
+            lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref()).unwrap();
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(4, 33),
+            },
+            work_done_progress_params: Default::default(),
+        };
+        match hover(state.snapshot, params).unwrap().unwrap().contents {
+            HoverContents::Markup(markup) => {
+                assert!(markup.value.contains("**Balance assertion fails**"));
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+    }
+
+    #[test]
+    fn test_hover_summarizes_include_target() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("2024.beancount"),
+            "2024-01-01 open Assets:Cash\n2024-03-01 * \"Test\"\n  Assets:Cash  1 USD\n  Income:Other\noption \"title\" \"x\"\n",
+        )
+        .unwrap();
+        let content = "include \"2024.beancount\"\ninclude \"missing.beancount\"\n";
+        let hover_text = |line| {
+            let state = TestState::at(dir.path().join("main.beancount"), content).unwrap();
+            let uri = lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref())
+                .unwrap();
+            let params = HoverParams {
+                text_document_position_params: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier { uri },
+                    position: lsp_types::Position::new(line, 12),
+                },
+                work_done_progress_params: Default::default(),
+            };
+            match hover(state.snapshot, params).unwrap().unwrap().contents {
+                HoverContents::Markup(markup) => markup.value,
+                _ => panic!("Expected markup hover content"),
+            }
+        };
+
+        let existing = hover_text(0);
+        let expected_path = dir.path().join("2024.beancount");
+        assert!(existing.contains(&format!("`{}`", expected_path.display())));
+        assert!(existing.contains("- File exists\n"));
+        assert!(
+            existing.contains("- 3 directives from 2024-01-01 to 2024-03-01\n"),
+            "{existing}"
+        );
+
+        let missing = hover_text(1);
+        assert!(missing.contains("- File not found\n"));
+        assert!(!missing.contains("directive"));
+    }
+
+    #[test]
+    fn test_hover_on_include_uses_indexed_files() {
+        // None of these files exist on disk
+        let dir = PathBuf::from("/nonexistent/ledger");
+        let main = dir.join("main.beancount");
+        let content = "include \"txns/*.beancount\"\n";
+        let snapshot = LspServerStateSnapshot::for_test(
+            Config::new(dir.clone()),
+            &[
+                (&main, content),
+                (
+                    &dir.join("txns/a.beancount"),
+                    "2024-01-01 open Assets:Cash\n",
+                ),
+                (
+                    &dir.join("txns/b.beancount"),
+                    "2024-02-01 open Assets:Bank\n",
+                ),
+                (
+                    &dir.join("txns/old/c.beancount"),
+                    "2020-01-01 open Assets:Old\n",
+                ),
+            ],
+        );
+        let uri = lsp_types::Uri::from_str(Url::from_file_path(&main).unwrap().as_ref()).unwrap();
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(0, 12),
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        let HoverContents::Markup(markup) = hover(snapshot, params).unwrap().unwrap().contents
+        else {
+            panic!("Expected markup hover content");
+        };
+        assert!(
+            markup.value.contains("- Matches 2 files\n"),
+            "{}",
+            markup.value
+        );
+        assert!(
+            markup
+                .value
+                .contains("- 2 directives from 2024-01-01 to 2024-02-01\n"),
+            "{}",
+            markup.value
+        );
+    }
+
+    #[test]
+    fn test_hover_documents_option() {
+        let state = TestState::new("option \"booking_method\" \"FIFO\"\n").unwrap();
+        let uri =
+            lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref()).unwrap();
+        let params = HoverParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(0, 10),
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        let hover = hover(state.snapshot, params).unwrap().unwrap();
+        assert_eq!(
+            hover.range.unwrap(),
+            Range::new(
+                lsp_types::Position::new(0, 7),
+                lsp_types::Position::new(0, 23)
+            )
+        );
+        match hover.contents {
+            HoverContents::Markup(markup) => {
+                assert!(markup.value.starts_with("**option \"booking_method\"**"));
+                assert!(markup.value.contains("- Default: `STRICT`"));
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+    }
+
+    #[test]
+    fn test_date_hover_shows_weekday_and_week() {
+        let content = Rope::from_str("2024-12-30 open Assets:Cash\n");
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content.to_string(), None).unwrap();
+        let date_node = tree
+            .root_node()
+            .named_descendant_for_point_range(
+                tree_sitter::Point::new(0, 2),
+                tree_sitter::Point::new(0, 2),
+            )
+            .unwrap();
+        let today = chrono::NaiveDate::from_ymd_opt(2025, 1, 20).unwrap();
+
+        let hover = date_hover(&content, date_node, today).unwrap();
+        match hover.contents {
+            HoverContents::Markup(markup) => {
+                assert_eq!(markup.value, "**Monday**, week 1 of 2025\n\n3 weeks ago");
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+    }
+
+    #[test]
+    fn test_relative_date() {
+        let today = chrono::NaiveDate::from_ymd_opt(2024, 6, 15).unwrap();
+        let days = |n| today + chrono::Duration::days(n);
+        assert_eq!(relative_date(today, today), "today");
+        assert_eq!(relative_date(days(-1), today), "yesterday");
+        assert_eq!(relative_date(days(1), today), "tomorrow");
+        assert_eq!(relative_date(days(-5), today), "5 days ago");
+        assert_eq!(relative_date(days(21), today), "in 3 weeks");
+        assert_eq!(relative_date(days(-90), today), "2 months ago");
+        assert_eq!(relative_date(days(-800), today), "2 years ago");
+    }
+
+    #[test]
+    fn test_hover_converts_amount_to_operating_currency() {
+        let content = "option \"operating_currency\" \"USD\"\n2024-01-01 price CHF  1.10 USD\n2024-02-01 price CHF  1.20 USD\n2024-01-10 * \"Lunch\"\n  Expenses:Food  25.50 CHF\n  Assets:Cash  -28.05 USD\n";
+        let hover_at = |line, character| {
+            let state = TestState::new(content).unwrap();
+            let uri = lsp_types::Uri::from_str(Url::from_file_path(&state.path).unwrap().as_ref())
+                .unwrap();
+            let params = HoverParams {
+                text_document_position_params: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier { uri },
+                    position: lsp_types::Position::new(line, character),
+                },
+                work_done_progress_params: Default::default(),
+            };
+            hover(state.snapshot, params).unwrap()
+        };
+
+        let hover = hover_at(4, 18).expect("Expected hover result");
+        match hover.contents {
+            HoverContents::Markup(markup) => {
+                assert_eq!(
+                    markup.value,
+                    "**≈ 28.05 USD**\n\nat 1.1 USD per CHF (price of 2024-01-01)"
+                );
+            }
+            _ => panic!("Expected markup hover content"),
+        }
+        // Amounts in the operating currency need no conversion
+        assert!(hover_at(5, 18).is_none());
+    }
 }
@@ -0,0 +1,454 @@
+//! Places transactions from importer scripts into the journal
+//!
+//! Each transaction goes to the file that holds the latest transaction posting to its
+//! first known account, so that per-account or per-year files keep growing where they
+//! are, and to the journal file for accounts without any transactions yet. Within the
+//! file it is inserted after the last directive not dated later, i.e. in date order
+//! for files that are sorted.
+
+use crate::config::FormattingConfig;
+use crate::importer::ImportRules;
+use crate::ledger::EntryKind;
+use crate::lsp_ext::{ImportedTransaction, InsertTransactionResult};
+use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::utils::file_path_to_uri;
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tree_sitter_beancount::tree_sitter;
+
+/// Provider function for the `beancount.importCsv` command.
+pub(crate) fn import_csv(
+    snapshot: &LspServerStateSnapshot,
+    journal: Option<&Path>,
+    csv: &Path,
+    rules: &Path,
+) -> Result<lsp_types::WorkspaceEdit> {
+    let resolve = |path: &Path| snapshot.config.root_dir.join(path);
+    let rules = ImportRules::from_file(&resolve(rules))?;
+    let csv = resolve(csv);
+    let content = std::fs::read_to_string(&csv)
+        .with_context(|| format!("failed to read {}", csv.display()))?;
+    let transactions = rules
+        .transactions(&content)
+        .with_context(|| format!("failed to import {}", csv.display()))?;
+    import_transactions(snapshot, journal, transactions)
+}
+
+/// Provider function for the `beancount.importTransactions` command.
+#[allow(clippy::mutable_key_type)]
+pub(crate) fn import_transactions(
+    snapshot: &LspServerStateSnapshot,
+    journal: Option<&Path>,
+    mut transactions: Vec<ImportedTransaction>,
+) -> Result<lsp_types::WorkspaceEdit> {
+    // Stable, so transactions of a day keep the importer's order
+    transactions.sort_by(|a, b| a.date.cmp(&b.date));
+
+    let mut files: Vec<&PathBuf> = snapshot
+        .beancount_data
+        .keys()
+        .filter(|file| journal.is_none() || snapshot.journal_for(file).as_deref() == journal)
+        .collect();
+    files.sort();
+
+    let mut edits: HashMap<PathBuf, Vec<lsp_types::TextEdit>> = HashMap::new();
+    for transaction in &transactions {
+        let text = render(transaction, &snapshot.config.formatting)
+            .with_context(|| format!("invalid transaction dated {}", transaction.date))?;
+        let file = target_file(snapshot, &files, transaction)
+            .or(journal)
+            .context("no journal file to import into")?;
+        let (tree, content) = snapshot
+            .tree_and_text(file)
+            .with_context(|| format!("failed to read {}", file.display()))?;
+        edits.entry(file.to_path_buf()).or_default().push(insertion(
+            &tree,
+            &content,
+            &transaction.date,
+            text,
+            true,
+        ));
+    }
+
+    let mut changes = HashMap::new();
+    for (file, edits) in edits {
+        let uri = file_path_to_uri(&file)
+            .map_err(|()| anyhow!("failed to convert {} to a URI", file.display()))?;
+        changes.insert(uri, edits);
+    }
+    Ok(snapshot.workspace_edit(changes))
+}
+
+/// Provider function for the `beancount.insertTransaction` command: where `file` takes
+/// a transaction dated `date`, with a skeleton to fill in there.
+#[allow(clippy::mutable_key_type)]
+pub(crate) fn insert_transaction(
+    snapshot: &LspServerStateSnapshot,
+    file: &Path,
+    date: &str,
+) -> Result<InsertTransactionResult> {
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .with_context(|| format!("invalid date `{date}`"))?;
+    let (tree, content) = snapshot
+        .tree_and_text(file)
+        .with_context(|| format!("failed to read {}", file.display()))?;
+    let uri = file_path_to_uri(file)
+        .map_err(|()| anyhow!("failed to convert {} to a URI", file.display()))?;
+
+    let skeleton = format!("{date} * \"\"\n");
+    let edit = insertion(&tree, &content, date, skeleton, true);
+    let position = edit.range.start;
+    // Blank lines and a missing final newline come before the skeleton
+    let leading = edit.new_text.len() - edit.new_text.trim_start_matches('\n').len();
+    let line = position.line + leading as u32;
+    let cursor = lsp_types::Position::new(line, date.len() as u32 + 4);
+    Ok(InsertTransactionResult {
+        uri: uri.clone(),
+        position,
+        edit: snapshot.workspace_edit(HashMap::from([(uri, vec![edit])])),
+        cursor,
+    })
+}
+
+/// The file with the latest transaction, preferably not after `transaction`, posting
+/// to the first of its accounts that has any.
+fn target_file<'a>(
+    snapshot: &LspServerStateSnapshot,
+    files: &[&'a PathBuf],
+    transaction: &ImportedTransaction,
+) -> Option<&'a Path> {
+    transaction.postings.iter().find_map(|posting| {
+        files
+            .iter()
+            .flat_map(|file| {
+                snapshot.beancount_data[*file]
+                    .get_ledger_entries()
+                    .iter()
+                    .filter(|entry| match &entry.kind {
+                        EntryKind::Transaction { postings, .. } => postings
+                            .iter()
+                            .any(|existing| existing.account == posting.account),
+                        _ => false,
+                    })
+                    .map(|entry| (entry.date <= transaction.date, entry.date.clone(), *file))
+                    .collect::<Vec<_>>()
+            })
+            .max()
+            .map(|(_, _, file)| file.as_path())
+    })
+}
+
+/// An edit inserting `text` after the last dated directive before the first one dated
+/// after `date`, separated from its neighbours by a blank line if `blank_line`.
+pub(crate) fn insertion(
+    tree: &tree_sitter::Tree,
+    content: &ropey::Rope,
+    date: &str,
+    text: String,
+    blank_line: bool,
+) -> lsp_types::TextEdit {
+    let separator = if blank_line { "\n" } else { "" };
+    let mut directives = vec![];
+    collect_dated_directives(tree.root_node(), content, &mut directives);
+    let later = directives
+        .iter()
+        .position(|(directive_date, _)| directive_date.as_str() > date)
+        .unwrap_or(directives.len());
+
+    let (line, text) = match later.checked_sub(1).map(|i| directives[i].1) {
+        Some(node) => {
+            let end = node.end_position();
+            let line = if end.column == 0 {
+                end.row
+            } else {
+                end.row + 1
+            };
+            (line, format!("{separator}{text}"))
+        }
+        None if later < directives.len() => (
+            directives[later].1.start_position().row,
+            format!("{text}{separator}"),
+        ),
+        None if content.len_chars() == 0 => (0, text),
+        None => (content.len_lines(), format!("{separator}{text}")),
+    };
+    insert_at_line(content, line, text)
+}
+
+/// An edit inserting `text` at the start of `line`, or at the end of the file when
+/// `line` is past it.
+pub(crate) fn insert_at_line(
+    content: &ropey::Rope,
+    line: usize,
+    text: String,
+) -> lsp_types::TextEdit {
+    let last = content.len_lines() - 1;
+    let last_len = content.line(last).len_utf16_cu();
+    let (position, text) = if line <= last {
+        (lsp_types::Position::new(line as u32, 0), text)
+    } else if last_len == 0 {
+        (lsp_types::Position::new(last as u32, 0), text)
+    } else {
+        // The file lacks a final newline to insert after
+        (
+            lsp_types::Position::new(last as u32, last_len as u32),
+            format!("\n{text}"),
+        )
+    };
+    lsp_types::TextEdit::new(lsp_types::Range::new(position, position), text)
+}
+
+fn collect_dated_directives<'a>(
+    node: tree_sitter::Node<'a>,
+    content: &ropey::Rope,
+    out: &mut Vec<(String, tree_sitter::Node<'a>)>,
+) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "section" {
+            collect_dated_directives(child, content, out);
+        } else if let Some(date) = child.child_by_field_name("date") {
+            let date = text_for_tree_sitter_node(content, &date).replace('/', "-");
+            out.push((date, child));
+        }
+    }
+}
+
+/// Renders `transaction` as beancount text, ending with a newline.
+pub(crate) fn render(
+    transaction: &ImportedTransaction,
+    formatting: &FormattingConfig,
+) -> Result<String> {
+    chrono::NaiveDate::parse_from_str(&transaction.date, "%Y-%m-%d")
+        .with_context(|| format!("invalid date `{}`", transaction.date))?;
+    let indent = " ".repeat(formatting.indent_width.unwrap_or(2));
+
+    let mut text = format!(
+        "{} {}",
+        transaction.date,
+        transaction.flag.as_deref().unwrap_or("*")
+    );
+    if let Some(payee) = &transaction.payee {
+        text.push_str(&format!(" {}", quote(payee)));
+    }
+    text.push_str(&format!(" {}", quote(&transaction.narration)));
+    for tag in &transaction.tags {
+        text.push_str(&format!(" #{tag}"));
+    }
+    for link in &transaction.links {
+        text.push_str(&format!(" ^{link}"));
+    }
+    text.push('\n');
+
+    for (key, value) in &transaction.metadata {
+        text.push_str(&format!("{indent}{key}: {}\n", quote(value)));
+    }
+    let width = transaction
+        .postings
+        .iter()
+        .map(|posting| posting.account.chars().count())
+        .max()
+        .unwrap_or(0);
+    for posting in &transaction.postings {
+        if posting.account.is_empty() {
+            return Err(anyhow!("posting without an account"));
+        }
+        match &posting.amount {
+            Some(amount) => text.push_str(&format!(
+                "{indent}{:width$}{}{}\n",
+                posting.account,
+                " ".repeat(formatting.account_amount_spacing),
+                amount.trim()
+            )),
+            None => text.push_str(&format!("{indent}{}\n", posting.account)),
+        }
+    }
+    Ok(text)
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::lsp_ext::ImportedPosting;
+    use crate::utils::ToFilePath;
+
+    /// The first file is the journal.
+    fn snapshot(root: &Path, files: &[(&str, &str)]) -> LspServerStateSnapshot {
+        let paths: Vec<PathBuf> = files.iter().map(|(name, _)| root.join(name)).collect();
+        for (path, (_, text)) in paths.iter().zip(files) {
+            std::fs::write(path, text).unwrap();
+        }
+        let mut config = Config::new(root.to_path_buf());
+        config.journal_root = Some(paths[0].clone());
+        let files: Vec<(&Path, &str)> = paths
+            .iter()
+            .zip(files)
+            .map(|(path, (_, text))| (path.as_path(), *text))
+            .collect();
+        LspServerStateSnapshot::for_test(config, &files)
+    }
+
+    fn transaction(date: &str, account: &str) -> ImportedTransaction {
+        ImportedTransaction {
+            date: date.to_string(),
+            flag: None,
+            payee: Some("Grocer".to_string()),
+            narration: "Weekly shopping".to_string(),
+            tags: vec![],
+            links: vec![],
+            metadata: Default::default(),
+            postings: vec![
+                ImportedPosting {
+                    account: account.to_string(),
+                    amount: Some("-25.50 USD".to_string()),
+                },
+                ImportedPosting {
+                    account: "Expenses:Food".to_string(),
+                    amount: None,
+                },
+            ],
+        }
+    }
+
+    /// Applies the edits of each file, which only insert text.
+    #[allow(clippy::mutable_key_type)]
+    fn apply(root: &Path, edit: &lsp_types::WorkspaceEdit) -> HashMap<String, String> {
+        let mut result = HashMap::new();
+        for (uri, edits) in crate::utils::text_edits(edit) {
+            let path = uri.to_file_path().unwrap();
+            let mut content = ropey::Rope::from_str(&std::fs::read_to_string(&path).unwrap());
+            let mut offset = 0;
+            let mut last = None;
+            for edit in edits {
+                let start = edit.range.start;
+                let char_idx = content.line_to_char(start.line as usize) + start.character as usize;
+                // Edits at the same position apply in order
+                offset = if last == Some(start) { offset } else { 0 };
+                content.insert(char_idx + offset, &edit.new_text);
+                offset += edit.new_text.chars().count();
+                last = Some(start);
+            }
+            let name = path.strip_prefix(root).unwrap().display().to_string();
+            result.insert(name, content.to_string());
+        }
+        result
+    }
+
+    #[test]
+    fn test_inserts_by_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = snapshot(
+            dir.path(),
+            &[(
+                "main.beancount",
+                "2024-01-01 open Assets:Cash\n\n2024-03-01 * \"Rent\"\n  Assets:Cash  -500 USD\n  Expenses:Rent\n",
+            )],
+        );
+        let journal = dir.path().join("main.beancount");
+        let edit = import_transactions(
+            &snapshot,
+            Some(&journal),
+            vec![
+                transaction("2024-02-01", "Assets:Cash"),
+                transaction("2024-01-15", "Assets:Cash"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            apply(dir.path(), &edit)["main.beancount"],
+            "2024-01-01 open Assets:Cash\n\n\
+             2024-01-15 * \"Grocer\" \"Weekly shopping\"\n  Assets:Cash    -25.50 USD\n  Expenses:Food\n\n\
+             2024-02-01 * \"Grocer\" \"Weekly shopping\"\n  Assets:Cash    -25.50 USD\n  Expenses:Food\n\n\
+             2024-03-01 * \"Rent\"\n  Assets:Cash  -500 USD\n  Expenses:Rent\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = snapshot(
+            dir.path(),
+            &[(
+                "main.beancount",
+                "2024-01-01 open Assets:Cash\n\n2024-03-01 * \"Rent\"\n  Assets:Cash  -500 USD\n  Expenses:Rent",
+            )],
+        );
+        let journal = dir.path().join("main.beancount");
+
+        let result = insert_transaction(&snapshot, &journal, "2024-02-01").unwrap();
+        assert_eq!(result.position, lsp_types::Position::new(1, 0));
+        assert_eq!(result.cursor, lsp_types::Position::new(2, 14));
+        assert_eq!(
+            apply(dir.path(), &result.edit)["main.beancount"],
+            "2024-01-01 open Assets:Cash\n\n2024-02-01 * \"\"\n\n2024-03-01 * \"Rent\"\n  Assets:Cash  -500 USD\n  Expenses:Rent"
+        );
+
+        // After the last transaction of a file without a final newline
+        let result = insert_transaction(&snapshot, &journal, "2024-04-01").unwrap();
+        assert_eq!(result.position, lsp_types::Position::new(4, 15));
+        assert_eq!(result.cursor, lsp_types::Position::new(6, 14));
+
+        assert!(insert_transaction(&snapshot, &journal, "2024-02-30").is_err());
+    }
+
+    #[test]
+    fn test_picks_the_file_of_the_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = snapshot(
+            dir.path(),
+            &[
+                ("main.beancount", "include \"bank.beancount\"\n"),
+                (
+                    "bank.beancount",
+                    "2024-01-01 * \"Salary\"\n  Assets:Bank  1000 USD\n  Income:Salary",
+                ),
+            ],
+        );
+        let journal = dir.path().join("main.beancount");
+        let edit = import_transactions(
+            &snapshot,
+            Some(&journal),
+            vec![
+                transaction("2024-01-10", "Assets:Bank"),
+                transaction("2024-01-10", "Assets:Wallet"),
+            ],
+        )
+        .unwrap();
+        let files = apply(dir.path(), &edit);
+        assert_eq!(
+            files["bank.beancount"],
+            "2024-01-01 * \"Salary\"\n  Assets:Bank  1000 USD\n  Income:Salary\n\n\
+             2024-01-10 * \"Grocer\" \"Weekly shopping\"\n  Assets:Bank    -25.50 USD\n  Expenses:Food\n"
+        );
+        assert_eq!(
+            files["main.beancount"],
+            "include \"bank.beancount\"\n\n\
+             2024-01-10 * \"Grocer\" \"Weekly shopping\"\n  Assets:Wallet  -25.50 USD\n  Expenses:Food\n"
+        );
+    }
+
+    #[test]
+    fn test_render_escapes_and_metadata() {
+        let mut transaction = transaction("2024-01-10", "Assets:Cash");
+        transaction.flag = Some("!".to_string());
+        transaction.payee = None;
+        transaction.narration = "Say \"hi\"".to_string();
+        transaction.tags = vec!["trip".to_string()];
+        transaction.links = vec!["receipt-1".to_string()];
+        transaction.metadata = [("import-id".to_string(), "42".to_string())].into();
+        assert_eq!(
+            render(&transaction, &FormattingConfig::default()).unwrap(),
+            "2024-01-10 ! \"Say \\\"hi\\\"\" #trip ^receipt-1\n  import-id: \"42\"\n  Assets:Cash    -25.50 USD\n  Expenses:Food\n"
+        );
+
+        transaction.date = "2024-13-01".to_string();
+        assert!(render(&transaction, &FormattingConfig::default()).is_err());
+    }
+}
@@ -3,75 +3,20 @@
 /// Provides inline hints for:
 /// 1. Calculated balancing amounts - shows implicit amounts for postings without explicit amounts
 /// 2. Transaction totals - displays total when transaction doesn't balance
+/// 3. Conversions - the value of foreign amounts in the operating currency, if enabled
+/// 4. Running balances - the balance of a posting's account after it, if enabled
+use crate::config::InlayHintsConfig;
+use crate::ledger::{Ledger, PostingAmount, extract_amount};
 use crate::server::LspServerStateSnapshot;
-use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::{text_for_tree_sitter_node, tree_sitter_point_to_lsp_position};
+use crate::utils::ToFilePath;
 use anyhow::{Context, Result};
 use lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, InlayHintParams, Position};
 use std::collections::HashMap;
+use std::path::Path;
 use tree_sitter::StreamingIterator;
 use tree_sitter_beancount::tree_sitter;
 
-/// Transaction query to find all transactions and their postings
-const TRANSACTION_QUERY: &str = r#"
-(transaction) @transaction
-"#;
-
-#[derive(Debug, Clone)]
-struct Amount {
-    value: rust_decimal::Decimal,
-    currency: String,
-}
-
-#[derive(Debug, Clone)]
-struct Price {
-    amount: Amount,
-    is_total: bool, // true for @@, false for @
-}
-
-#[derive(Debug, Clone)]
-struct Cost {
-    amount: Amount,
-    is_total: bool, // true for {{total}}, false for {unit}
-}
-
-#[derive(Debug, Clone)]
-struct PostingAmount {
-    amount: Amount,
-    price: Option<Price>,
-    cost: Option<Cost>,
-}
-
-impl PostingAmount {
-    /// Convert this posting amount to a specific currency if price or cost is specified
-    fn convert_to_currency(&self) -> Option<(rust_decimal::Decimal, String)> {
-        // Cost basis takes precedence over price for balance calculations
-        if let Some(cost) = &self.cost {
-            // Cost is specified, convert to the cost currency
-            let converted_value = if cost.is_total {
-                // {{total}} means total cost, use the cost amount directly
-                cost.amount.value
-            } else {
-                // {unit} means unit cost, multiply by quantity
-                self.amount.value * cost.amount.value
-            };
-            Some((converted_value, cost.amount.currency.clone()))
-        } else if let Some(price) = &self.price {
-            // Price is specified, convert to the price currency
-            let converted_value = if price.is_total {
-                // @@ means total cost, use the price amount directly
-                price.amount.value
-            } else {
-                // @ means unit price, multiply by quantity
-                self.amount.value * price.amount.value
-            };
-            Some((converted_value, price.amount.currency.clone()))
-        } else {
-            // No price or cost, return original
-            None
-        }
-    }
-}
-
 #[derive(Debug)]
 struct Posting {
     node: tree_sitter::Node<'static>,
@@ -83,26 +28,57 @@ pub(crate) fn inlay_hints(
     snapshot: LspServerStateSnapshot,
     params: InlayHintParams,
 ) -> Result<Option<Vec<InlayHint>>> {
+    if !snapshot.config.features.inlay_hints {
+        return Ok(None);
+    }
     let uri = &params.text_document.uri;
 
     let (tree, doc) = snapshot
         .tree_and_document_for_uri(uri)
         .context("Failed to get tree/document for inlay hints")?;
     let content = &doc.content;
+    if !snapshot
+        .config
+        .large_file
+        .inlay_hints_enabled(content.len_lines())
+    {
+        tracing::debug!(
+            "Skipping inlay hints for large file: {} lines",
+            content.len_lines()
+        );
+        return Ok(None);
+    }
     let content_str = content.to_string();
     let content_bytes = content_str.as_bytes();
 
     let mut hints = Vec::new();
 
+    // Conversions and running balances need the whole journal, so the ledger is only
+    // built when one of them is enabled
+    let config = &snapshot.config.inlay_hints;
+    let path = uri.to_file_path().ok();
+    let operating = path
+        .as_ref()
+        .filter(|_| config.conversions)
+        .and_then(|path| {
+            snapshot
+                .ledger_options_for(path)
+                .operating_currencies
+                .into_iter()
+                .next()
+        });
+    let ledger = path
+        .as_ref()
+        .filter(|_| operating.is_some() || config.running_balances)
+        .map(|path| snapshot.ledger_for(path));
+
     // Query for all transactions
-    let transaction_query =
-        tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY)
-            .context("Failed to compile transaction query")?;
+    let transaction_query = crate::queries::get_transaction_query();
 
     let mut cursor = tree_sitter::QueryCursor::new();
     cursor.set_byte_range(0..content_bytes.len());
 
-    let mut matches = cursor.matches(&transaction_query, tree.root_node(), content_bytes);
+    let mut matches = cursor.matches(transaction_query, tree.root_node(), content_bytes);
 
     while let Some(qmatch) = matches.next() {
         for capture in qmatch.captures {
@@ -110,14 +86,8 @@ pub(crate) fn inlay_hints(
 
             // Check if this transaction is in the requested range
             let txn_range = txn_node.range();
-            let txn_start = Position::new(
-                txn_range.start_point.row as u32,
-                txn_range.start_point.column as u32,
-            );
-            let txn_end = Position::new(
-                txn_range.end_point.row as u32,
-                txn_range.end_point.column as u32,
-            );
+            let txn_start = tree_sitter_point_to_lsp_position(content, txn_range.start_point);
+            let txn_end = tree_sitter_point_to_lsp_position(content, txn_range.end_point);
 
             // Skip if transaction is outside the requested range
             if txn_end < params.range.start || txn_start > params.range.end {
@@ -125,9 +95,23 @@ pub(crate) fn inlay_hints(
             }
 
             // Process this transaction
-            if let Some(txn_hints) = process_transaction(&txn_node, content) {
+            if let Some(txn_hints) = process_transaction(&txn_node, content, config) {
                 hints.extend(txn_hints);
             }
+            if let (Some(ledger), Some(operating)) = (&ledger, &operating) {
+                hints.extend(conversion_hints(&txn_node, content, ledger, operating));
+            }
+            if let (Some(ledger), Some(path)) = (&ledger, &path)
+                && config.running_balances
+            {
+                hints.extend(running_balance_hints(
+                    &txn_node,
+                    content,
+                    ledger,
+                    path,
+                    &params.range,
+                ));
+            }
         }
     }
 
@@ -142,13 +126,14 @@ pub(crate) fn transaction_inlay_hints(
     txn_node: &tree_sitter::Node,
     content: &ropey::Rope,
 ) -> Option<Vec<InlayHint>> {
-    process_transaction(txn_node, content)
+    process_transaction(txn_node, content, &InlayHintsConfig::default())
 }
 
-/// Process a single transaction and return hints
+/// Process a single transaction and return the hints enabled in `config`
 fn process_transaction(
     txn_node: &tree_sitter::Node,
     content: &ropey::Rope,
+    config: &InlayHintsConfig,
 ) -> Option<Vec<InlayHint>> {
     let mut hints = Vec::new();
 
@@ -160,12 +145,14 @@ fn process_transaction(
 
     if has_missing_amount {
         // If there's a missing amount, show the balancing amount at the end of that posting line
-        if let Some(hint) = calculate_balancing_hint(&postings) {
+        if config.balancing_amounts
+            && let Some(hint) = calculate_balancing_hint(&postings, content)
+        {
             hints.push(hint);
         }
-    } else {
+    } else if config.unbalanced_totals {
         // If all postings have amounts, only show hint if transaction doesn't balance
-        let txn_line_end_pos = get_transaction_line_end_position(txn_node);
+        let txn_line_end_pos = get_transaction_line_end_position(txn_node, content);
         if let Some(hint) = calculate_total_hint(&postings, txn_line_end_pos) {
             hints.push(hint);
         }
@@ -174,8 +161,133 @@ fn process_transaction(
     Some(hints)
 }
 
+/// The value of each posting amount in a foreign commodity in the `operating`
+/// currency, at the price closest to the date of the transaction.
+fn conversion_hints(
+    txn_node: &tree_sitter::Node,
+    content: &ropey::Rope,
+    ledger: &Ledger,
+    operating: &str,
+) -> Vec<InlayHint> {
+    let Some(date) = txn_node
+        .child_by_field_name("date")
+        .map(|date| text_for_tree_sitter_node(content, &date).replace('/', "-"))
+    else {
+        return vec![];
+    };
+
+    let mut hints = Vec::new();
+    let mut cursor = txn_node.walk();
+    for posting in txn_node
+        .children(&mut cursor)
+        .filter(|child| child.kind() == "posting")
+    {
+        let Some(amount) = extract_amount(&posting, content).map(|amount| amount.amount) else {
+            continue;
+        };
+        if amount.currency == operating {
+            continue;
+        }
+        let mut posting_cursor = posting.walk();
+        let Some(amount_node) = posting
+            .children(&mut posting_cursor)
+            .find(|child| child.kind() == "incomplete_amount" || child.kind() == "amount")
+        else {
+            continue;
+        };
+        let Some((rate, price_date)) = ledger.nearest_price(&amount.currency, operating, &date)
+        else {
+            continue;
+        };
+        let Some(value) = amount.value.checked_mul(rate) else {
+            continue;
+        };
+
+        hints.push(InlayHint {
+            position: tree_sitter_point_to_lsp_position(content, amount_node.end_position()),
+            label: InlayHintLabel::String(format!(
+                "≈ {} {operating}",
+                value.round_dp(2).normalize()
+            )),
+            kind: Some(InlayHintKind::TYPE),
+            text_edits: None,
+            tooltip: Some(lsp_types::InlayHintTooltip::String(format!(
+                "at {} {operating} per {} (price of {price_date})",
+                rate.round_dp(6).normalize(),
+                amount.currency
+            ))),
+            padding_left: Some(true),
+            padding_right: None,
+            data: None,
+        });
+    }
+    hints
+}
+
+/// The balance of each posting's account right after it, at the end of the posting
+/// lines within `range`.
+fn running_balance_hints(
+    txn_node: &tree_sitter::Node,
+    content: &ropey::Rope,
+    ledger: &Ledger,
+    file: &Path,
+    range: &lsp_types::Range,
+) -> Vec<InlayHint> {
+    let mut hints = Vec::new();
+    let mut cursor = txn_node.walk();
+    for posting in txn_node
+        .children(&mut cursor)
+        .filter(|child| child.kind() == "posting")
+    {
+        let row = posting.start_position().row;
+        if (row as u32) < range.start.line || (row as u32) > range.end.line {
+            continue;
+        }
+        let Some(account) = posting
+            .child_by_field_name("account")
+            .map(|account| text_for_tree_sitter_node(content, &account))
+        else {
+            continue;
+        };
+        let Some(balance) = ledger.balance_after(&account, file, row as u32) else {
+            continue;
+        };
+
+        let label = if balance.is_empty() {
+            "= 0".to_string()
+        } else {
+            let amounts: Vec<String> = balance
+                .iter()
+                .map(|(currency, value)| format!("{value} {currency}"))
+                .collect();
+            format!("= {}", amounts.join(", "))
+        };
+        let line = content.line(row).to_string();
+        let line_end = line.trim_end().len();
+        hints.push(InlayHint {
+            position: tree_sitter_point_to_lsp_position(
+                content,
+                tree_sitter::Point::new(row, line_end),
+            ),
+            label: InlayHintLabel::String(label),
+            kind: Some(InlayHintKind::TYPE),
+            text_edits: None,
+            tooltip: Some(lsp_types::InlayHintTooltip::String(format!(
+                "Balance of {account} after this posting"
+            ))),
+            padding_left: Some(true),
+            padding_right: None,
+            data: None,
+        });
+    }
+    hints
+}
+
 /// Get the position at the end of the transaction's first line
-fn get_transaction_line_end_position(txn_node: &tree_sitter::Node) -> Position {
+fn get_transaction_line_end_position(
+    txn_node: &tree_sitter::Node,
+    content: &ropey::Rope,
+) -> Position {
     // Find the end of the first line of the transaction (after narration/payee)
     let mut cursor = txn_node.walk();
     let mut last_col = txn_node.start_position().column;
@@ -191,7 +303,7 @@ fn get_transaction_line_end_position(txn_node: &tree_sitter::Node) -> Position {
         }
     }
 
-    Position::new(txn_row as u32, last_col as u32)
+    tree_sitter_point_to_lsp_position(content, tree_sitter::Point::new(txn_row, last_col))
 }
 
 /// Extract all postings from a transaction
@@ -222,232 +334,8 @@ fn extract_postings(txn_node: &tree_sitter::Node, content: &ropey::Rope) -> Opti
     }
 }
 
-/// Extract amount and optional price/cost from a posting node
-fn extract_amount(
-    posting_node: &tree_sitter::Node,
-    content: &ropey::Rope,
-) -> Option<PostingAmount> {
-    let mut cursor = posting_node.walk();
-    let mut amount_opt: Option<Amount> = None;
-    let mut is_price_total = false;
-    let mut price_amount_opt: Option<Amount> = None;
-    let mut cost_opt: Option<Cost> = None;
-
-    for child in posting_node.children(&mut cursor) {
-        match child.kind() {
-            "incomplete_amount" | "amount" => {
-                if amount_opt.is_none() {
-                    // First amount is the posting amount - parse it from structure
-                    amount_opt = extract_amount_from_node(&child, content);
-                }
-            }
-            "at" => {
-                // @ means unit price
-                is_price_total = false;
-            }
-            "atat" => {
-                // @@ means total cost
-                is_price_total = true;
-            }
-            "price_annotation" => {
-                // Parse the price amount from price_annotation
-                price_amount_opt = extract_price_annotation(&child, content);
-            }
-            "cost_spec" => {
-                // Parse cost basis from cost_spec
-                cost_opt = extract_cost_spec(&child, content);
-            }
-            _ => {}
-        }
-    }
-
-    amount_opt.map(|amount| PostingAmount {
-        amount,
-        price: price_amount_opt.map(|price_amt| Price {
-            amount: price_amt,
-            is_total: is_price_total,
-        }),
-        cost: cost_opt,
-    })
-}
-
-/// Extract amount from an incomplete_amount or amount node by parsing its children
-fn extract_amount_from_node(
-    amount_node: &tree_sitter::Node,
-    content: &ropey::Rope,
-) -> Option<Amount> {
-    let mut cursor = amount_node.walk();
-    let mut number_str = String::new();
-    let mut currency_str = String::new();
-
-    for child in amount_node.children(&mut cursor) {
-        match child.kind() {
-            "number" | "unary_number_expr" | "binary_number_expr" => {
-                number_str = text_for_tree_sitter_node(content, &child);
-            }
-            "currency" => {
-                currency_str = text_for_tree_sitter_node(content, &child);
-            }
-            _ => {}
-        }
-    }
-
-    if !number_str.is_empty() && !currency_str.is_empty() {
-        // Try to evaluate the expression if it's a calculation
-        let value = if number_str.contains('*')
-            || number_str.contains('+')
-            || (number_str.contains('-') && number_str.matches('-').count() > 1)
-        {
-            // For binary expressions, try to evaluate them
-            evaluate_expression(&number_str)
-                .or_else(|| rust_decimal::Decimal::from_str_exact(&number_str).ok())?
-        } else {
-            rust_decimal::Decimal::from_str_exact(&number_str).ok()?
-        };
-        Some(Amount {
-            value,
-            currency: currency_str,
-        })
-    } else {
-        None
-    }
-}
-
-/// Extract price amount from a price_annotation node
-fn extract_price_annotation(
-    price_annotation_node: &tree_sitter::Node,
-    content: &ropey::Rope,
-) -> Option<Amount> {
-    let mut cursor = price_annotation_node.walk();
-
-    for child in price_annotation_node.children(&mut cursor) {
-        if child.kind() == "incomplete_amount" || child.kind() == "amount" {
-            return extract_amount_from_node(&child, content);
-        }
-    }
-
-    None
-}
-
-/// Extract cost basis from a cost_spec node
-fn extract_cost_spec(cost_spec_node: &tree_sitter::Node, content: &ropey::Rope) -> Option<Cost> {
-    let mut cursor = cost_spec_node.walk();
-    let mut is_total = false;
-    let mut cost_amount: Option<Amount> = None;
-
-    // Check for {{ or {
-    for child in cost_spec_node.children(&mut cursor) {
-        match child.kind() {
-            "{{" => is_total = true,
-            "{" => is_total = false,
-            "cost_comp" => {
-                // Extract the amount from cost_comp -> compound_amount
-                cost_amount = extract_cost_comp(&child, content);
-            }
-            _ => {}
-        }
-    }
-
-    cost_amount.map(|amount| Cost { amount, is_total })
-}
-
-/// Extract amount from a cost_comp node
-fn extract_cost_comp(cost_comp_node: &tree_sitter::Node, content: &ropey::Rope) -> Option<Amount> {
-    let mut cursor = cost_comp_node.walk();
-
-    for child in cost_comp_node.children(&mut cursor) {
-        if child.kind() == "compound_amount" {
-            return extract_compound_amount(&child, content);
-        }
-    }
-
-    None
-}
-
-/// Extract amount from a compound_amount node (has number and currency children)
-fn extract_compound_amount(
-    compound_amount_node: &tree_sitter::Node,
-    content: &ropey::Rope,
-) -> Option<Amount> {
-    let mut cursor = compound_amount_node.walk();
-    let mut number_str = String::new();
-    let mut currency_str = String::new();
-
-    for child in compound_amount_node.children(&mut cursor) {
-        match child.kind() {
-            "number" | "unary_number_expr" | "binary_number_expr" => {
-                number_str = text_for_tree_sitter_node(content, &child);
-            }
-            "currency" => {
-                currency_str = text_for_tree_sitter_node(content, &child);
-            }
-            _ => {}
-        }
-    }
-
-    if !number_str.is_empty() && !currency_str.is_empty() {
-        // Try to evaluate the expression if it's a calculation
-        let value = if number_str.contains('*')
-            || number_str.contains('+')
-            || number_str.contains('-') && number_str.matches('-').count() > 1
-        {
-            // For binary expressions, try to evaluate them
-            evaluate_expression(&number_str)
-                .or_else(|| rust_decimal::Decimal::from_str_exact(&number_str).ok())?
-        } else {
-            rust_decimal::Decimal::from_str_exact(&number_str).ok()?
-        };
-        Some(Amount {
-            value,
-            currency: currency_str,
-        })
-    } else {
-        None
-    }
-}
-
-/// Simple expression evaluator for basic arithmetic
-fn evaluate_expression(expr: &str) -> Option<rust_decimal::Decimal> {
-    use rust_decimal::Decimal;
-
-    let expr = expr.trim();
-
-    // Handle simple binary operations: a * b, a + b, a - b
-    if let Some(pos) = expr.rfind('*') {
-        let left = expr[..pos].trim();
-        let right = expr[pos + 1..].trim();
-        let left_val = Decimal::from_str_exact(left).ok()?;
-        let right_val = Decimal::from_str_exact(right).ok()?;
-        return Some(left_val * right_val);
-    }
-
-    if let Some(pos) = expr.rfind('+') {
-        let left = expr[..pos].trim();
-        let right = expr[pos + 1..].trim();
-        let left_val = Decimal::from_str_exact(left).ok()?;
-        let right_val = Decimal::from_str_exact(right).ok()?;
-        return Some(left_val + right_val);
-    }
-
-    // Handle subtraction (but not unary minus)
-    if let Some(pos) = expr.rfind('-')
-        && pos > 0
-    {
-        let left = expr[..pos].trim();
-        let right = expr[pos + 1..].trim();
-        if let (Ok(left_val), Ok(right_val)) = (
-            Decimal::from_str_exact(left),
-            Decimal::from_str_exact(right),
-        ) {
-            return Some(left_val - right_val);
-        }
-    }
-
-    None
-}
-
 /// Calculate hint for balancing amounts (postings without explicit amounts)
-fn calculate_balancing_hint(postings: &[Posting]) -> Option<InlayHint> {
+fn calculate_balancing_hint(postings: &[Posting], content: &ropey::Rope) -> Option<InlayHint> {
     // Find posting without amount
     let posting_without_amount = postings.iter().find(|p| p.amount.is_none())?;
 
@@ -457,20 +345,12 @@ fn calculate_balancing_hint(postings: &[Posting]) -> Option<InlayHint> {
 
     for posting in postings {
         if let Some(posting_amount) = &posting.amount {
-            // Check if this posting has a price annotation
-            if let Some((converted_value, converted_currency)) =
-                posting_amount.convert_to_currency()
-            {
-                // Use the converted amount and currency
-                *totals
-                    .entry(converted_currency)
-                    .or_insert(rust_decimal::Decimal::ZERO) += converted_value;
-            } else {
-                // Use the original amount and currency
-                *totals
-                    .entry(posting_amount.amount.currency.clone())
-                    .or_insert(rust_decimal::Decimal::ZERO) += posting_amount.amount.value;
-            }
+            // Postings with a price or cost count in the converted currency
+            let weight = posting_amount.weight()?;
+            let total = totals
+                .entry(weight.currency)
+                .or_insert(rust_decimal::Decimal::ZERO);
+            *total = total.checked_add(weight.value)?;
         }
     }
 
@@ -524,7 +404,10 @@ fn calculate_balancing_hint(postings: &[Posting]) -> Option<InlayHint> {
     // Position at the end of the account name
     let range = posting_without_amount.node.range();
     // Use start_point.row to ensure we're on the posting line itself
-    let position = Position::new(range.start_point.row as u32, account_end_column as u32);
+    let position = tree_sitter_point_to_lsp_position(
+        content,
+        tree_sitter::Point::new(range.start_point.row, account_end_column),
+    );
 
     Some(InlayHint {
         position,
@@ -579,20 +462,12 @@ fn calculate_total_hint(postings: &[Posting], position: Position) -> Option<Inla
 
     for posting in postings {
         if let Some(posting_amount) = &posting.amount {
-            // Check if this posting has a price annotation
-            if let Some((converted_value, converted_currency)) =
-                posting_amount.convert_to_currency()
-            {
-                // Use the converted amount and currency
-                *totals
-                    .entry(converted_currency)
-                    .or_insert(rust_decimal::Decimal::ZERO) += converted_value;
-            } else {
-                // Use the original amount and currency
-                *totals
-                    .entry(posting_amount.amount.currency.clone())
-                    .or_insert(rust_decimal::Decimal::ZERO) += posting_amount.amount.value;
-            }
+            // Postings with a price or cost count in the converted currency
+            let weight = posting_amount.weight()?;
+            let total = totals
+                .entry(weight.currency)
+                .or_insert(rust_decimal::Decimal::ZERO);
+            *total = total.checked_add(weight.value)?;
         }
     }
 
@@ -653,15 +528,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have at least the balancing hint
             assert!(!hints.is_empty());
@@ -706,6 +581,25 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_total_hint_position_after_non_ascii_payee() {
+        let content = "2024-01-15 * \"Café ☕\" \"Lunch\"\n  Expenses:Food    4.50 EUR\n  Assets:Cash    -4.00 EUR\n";
+        let rope_content = ropey::Rope::from_str(content);
+
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let txn_node = tree.root_node().child(0).unwrap();
+
+        let hints =
+            process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default()).unwrap();
+        assert_eq!(hints.len(), 1);
+        // The first line is 32 bytes but 29 UTF-16 code units long
+        assert_eq!(hints[0].position, Position::new(0, 29));
+    }
+
     #[test]
     fn test_unbalanced_transaction_hint() {
         // Test unbalanced transaction
@@ -721,15 +615,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have the unbalanced hint
             assert!(!hints.is_empty());
@@ -773,15 +667,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should not have a warning hint for balanced transaction
             let has_warning = hints.iter().any(|h| {
@@ -815,15 +709,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have balancing hint
             let balancing_hint = hints.iter().find(|h| {
@@ -893,15 +787,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Find the balancing hint (positive amount)
             let balancing_hint = hints.iter().find(|h| {
@@ -955,15 +849,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Find the balancing hint (negative amount)
             let balancing_hint = hints.iter().find(|h| {
@@ -1013,15 +907,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have balancing hint
             assert!(!hints.is_empty(), "Should have balancing hint");
@@ -1062,15 +956,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should not have warning hint - transaction should balance with conversion
             let has_warning = hints.iter().any(|h| {
@@ -1104,15 +998,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should not have warning hint - transaction should balance with conversion
             let has_warning = hints.iter().any(|h| {
@@ -1146,15 +1040,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have warning hint - different currencies without conversion
             let has_warning = hints.iter().any(|h| {
@@ -1188,15 +1082,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should not have warning hint - transaction should balance with cost basis
             let has_warning = hints.iter().any(|h| {
@@ -1230,15 +1124,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have balancing hint showing the converted cost amount
             let balancing_hint = hints.iter().find(|h| {
@@ -1273,15 +1167,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have warning hint - different commodities without conversion
             let has_warning = hints.iter().any(|h| {
@@ -1315,15 +1209,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have balancing hint showing the converted amount
             let balancing_hint = hints.iter().find(|h| {
@@ -1358,15 +1252,14 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content);
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default());
 
             if let Some(hints) = hints {
                 println!("Hints: {:?}", hints);
@@ -1406,15 +1299,15 @@ mod tests {
             .unwrap();
         let tree = parser.parse(content, None).unwrap();
 
-        let txn_query =
-            tree_sitter::Query::new(&tree_sitter_beancount::language(), TRANSACTION_QUERY).unwrap();
+        let txn_query = crate::queries::get_transaction_query();
         let mut cursor = tree_sitter::QueryCursor::new();
         let content_bytes = content.as_bytes();
-        let mut matches = cursor.matches(&txn_query, tree.root_node(), content_bytes);
+        let mut matches = cursor.matches(txn_query, tree.root_node(), content_bytes);
 
         if let Some(qmatch) = matches.next() {
             let txn_node = qmatch.captures[0].node;
-            let hints = process_transaction(&txn_node, &rope_content).unwrap();
+            let hints = process_transaction(&txn_node, &rope_content, &InlayHintsConfig::default())
+                .unwrap();
 
             // Should have balancing hint with calculated amount (100 + 50 = 150)
             let balancing_hint = hints.iter().find(|h| {
@@ -1432,4 +1325,118 @@ mod tests {
             panic!("No transaction found");
         }
     }
+
+    /// Inlay hints for the whole of `content`, with the config changed by `configure`.
+    fn hints_with(
+        content: &str,
+        configure: fn(&mut crate::config::Config),
+    ) -> Option<Vec<InlayHint>> {
+        let path = std::env::current_dir().unwrap().join("test.beancount");
+        let rope = ropey::Rope::from_str(content);
+        let mut config = crate::config::Config::new(path.clone());
+        config.journal_root = Some(path.clone());
+        configure(&mut config);
+        let snapshot = LspServerStateSnapshot::for_test(config, &[(&path, content)]);
+        let params = InlayHintParams {
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: crate::utils::file_path_to_uri(&path).unwrap(),
+            },
+            range: lsp_types::Range::new(
+                Position::new(0, 0),
+                Position::new(rope.len_lines() as u32, 0),
+            ),
+            work_done_progress_params: Default::default(),
+        };
+        inlay_hints(snapshot, params).unwrap()
+    }
+
+    #[test]
+    fn test_conversion_hints_in_operating_currency() {
+        let content = "option \"operating_currency\" \"USD\"\n2024-01-01 price CHF  1.10 USD\n2024-01-10 * \"Lunch\"\n  Expenses:Food  25.50 CHF @ 1.10 USD\n  Assets:Cash  -28.05 USD\n";
+
+        // Off by default
+        assert!(hints_with(content, |_| {}).is_none());
+
+        let hints = hints_with(content, |config| config.inlay_hints.conversions = true).unwrap();
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].position, Position::new(3, 26));
+        assert!(matches!(&hints[0].label, InlayHintLabel::String(label) if label == "≈ 28.05 USD"));
+        assert!(matches!(
+            &hints[0].tooltip,
+            Some(lsp_types::InlayHintTooltip::String(tooltip))
+                if tooltip == "at 1.1 USD per CHF (price of 2024-01-01)"
+        ));
+    }
+
+    #[test]
+    fn test_conversion_hints_skip_overflowing_values() {
+        let content = "option \"operating_currency\" \"USD\"\n2024-01-01 price CHF  1000 USD\n2024-01-10 * \"Lunch\"\n  Expenses:Food  79000000000000000000000000000 CHF\n  Assets:Cash  -79000000000000000000000000000 CHF\n";
+
+        assert!(hints_with(content, |config| config.inlay_hints.conversions = true).is_none());
+    }
+
+    #[test]
+    fn test_running_balance_hints() {
+        let content = r#"2024-01-01 open Assets:Cash
+2024-01-02 * "Lunch"
+  Expenses:Food   12.50 USD
+  Assets:Cash    -12.50 USD ; paid cash
+2024-01-03 * "Dinner"
+  Expenses:Food   20 USD
+  Assets:Cash
+"#;
+        let hints =
+            hints_with(content, |config| config.inlay_hints.running_balances = true).unwrap();
+        assert!(
+            hints
+                .iter()
+                .any(|hint| hint.kind == Some(InlayHintKind::PARAMETER))
+        );
+        let labels: Vec<(u32, u32, String)> = hints
+            .iter()
+            .filter_map(|hint| match &hint.label {
+                InlayHintLabel::String(label) if label.starts_with('=') => {
+                    Some((hint.position.line, hint.position.character, label.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                (2, 27, "= 12.50 USD".to_string()),
+                (3, 39, "= -12.50 USD".to_string()),
+                (5, 24, "= 32.50 USD".to_string()),
+                (6, 13, "= -32.50 USD".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disabled_hint_kinds_are_skipped() {
+        let content = r#"2024-01-02 * "Lunch"
+  Expenses:Food   12.50 USD
+  Assets:Cash
+2024-01-03 * "Dinner"
+  Expenses:Food   20 USD
+  Assets:Cash    -10 USD
+"#;
+        assert_eq!(
+            hints_with(content, |_| {}).map(|hints| hints.len()),
+            Some(2)
+        );
+        let hints = hints_with(content, |config| {
+            config.inlay_hints.balancing_amounts = false;
+        })
+        .unwrap();
+        assert_eq!(hints.len(), 1);
+        assert_eq!(hints[0].position.line, 3);
+        assert!(
+            hints_with(content, |config| {
+                config.inlay_hints.balancing_amounts = false;
+                config.inlay_hints.unbalanced_totals = false;
+            })
+            .is_none()
+        );
+    }
 }
@@ -0,0 +1,191 @@
+use crate::beancount_data::BeancountData;
+use crate::ledger::{EntryKind, book_postings};
+use crate::ledger_options::LedgerOptions;
+use crate::lsp_ext::{MonthlyTotalsResult, MonthlyTotalsSeries};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Provider function for the `beancount/monthlyTotals` request.
+///
+/// Sums the postings to the income and expense accounts of `files` per root account,
+/// currency and month.
+pub(crate) fn monthly_totals(
+    files: &HashMap<PathBuf, Arc<BeancountData>>,
+    options: &LedgerOptions,
+) -> MonthlyTotalsResult {
+    let [.., income, expenses] = &options.root_accounts;
+
+    let mut sums: BTreeMap<(&str, String), BTreeMap<String, Decimal>> = BTreeMap::new();
+    let mut first_month: Option<String> = None;
+    let mut last_month: Option<String> = None;
+    for data in files.values() {
+        for entry in data.get_ledger_entries().iter() {
+            let EntryKind::Transaction { postings, .. } = &entry.kind else {
+                continue;
+            };
+            let Some(month) = entry.date.get(..7) else {
+                continue;
+            };
+            for (account, _, amount) in book_postings(postings) {
+                let root = account.split(':').next().unwrap_or_default();
+                let root = if root == income {
+                    income.as_str()
+                } else if root == expenses {
+                    expenses.as_str()
+                } else {
+                    continue;
+                };
+                let total = sums
+                    .entry((root, amount.currency))
+                    .or_default()
+                    .entry(month.to_string())
+                    .or_default();
+                // A sum beyond what a decimal holds has no meaningful value to chart
+                let Some(sum) = total.checked_add(amount.value) else {
+                    continue;
+                };
+                *total = sum;
+                if first_month.as_deref().is_none_or(|first| month < first) {
+                    first_month = Some(month.to_string());
+                }
+                if last_month.as_deref().is_none_or(|last| month > last) {
+                    last_month = Some(month.to_string());
+                }
+            }
+        }
+    }
+
+    let months = match (first_month, last_month) {
+        (Some(first), Some(last)) => month_range(&first, &last),
+        _ => Vec::new(),
+    };
+    let series = sums
+        .into_iter()
+        .map(|((account, currency), by_month)| MonthlyTotalsSeries {
+            account: account.to_string(),
+            currency,
+            totals: months
+                .iter()
+                .map(|month| by_month.get(month).copied().unwrap_or_default().to_string())
+                .collect(),
+        })
+        .collect();
+    MonthlyTotalsResult { months, series }
+}
+
+/// Every `YYYY-MM` from `first` to `last`, both included.
+fn month_range(first: &str, last: &str) -> Vec<String> {
+    let parse = |month: &str| -> Option<(i32, u32)> {
+        let (year, month) = month.split_once('-')?;
+        Some((year.parse().ok()?, month.parse().ok()?))
+    };
+    let (Some((mut year, mut month)), Some(end)) = (parse(first), parse(last)) else {
+        return Vec::new();
+    };
+    let mut months = Vec::new();
+    while (year, month) <= end {
+        months.push(format!("{year:04}-{month:02}"));
+        if month == 12 {
+            year += 1;
+            month = 1;
+        } else {
+            month += 1;
+        }
+    }
+    months
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_sitter_beancount::tree_sitter;
+
+    fn files(content: &str) -> HashMap<PathBuf, Arc<BeancountData>> {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        let data = BeancountData::new(&tree, &ropey::Rope::from_str(content));
+        HashMap::from([(PathBuf::from("/main.beancount"), Arc::new(data))])
+    }
+
+    #[test]
+    fn test_monthly_totals() {
+        let files = files(
+            r#"2023-11-25 * "Employer" "Salary"
+  Assets:Cash  1000.00 USD
+  Income:Salary
+
+2023-11-28 * "Grocer"
+  Expenses:Food  25.00 USD
+  Assets:Cash
+
+2024-01-03 * "Grocer"
+  Expenses:Food  30.00 USD
+  Expenses:Household  5.00 USD
+  Assets:Cash
+
+2024-01-04 * "Bakery"
+  Expenses:Food  3.00 EUR
+  Assets:Cash
+"#,
+        );
+        let result = monthly_totals(&files, &LedgerOptions::default());
+
+        assert_eq!(result.months, vec!["2023-11", "2023-12", "2024-01"]);
+        let series: Vec<(&str, &str, Vec<&str>)> = result
+            .series
+            .iter()
+            .map(|series| {
+                (
+                    series.account.as_str(),
+                    series.currency.as_str(),
+                    series.totals.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            series,
+            vec![
+                ("Expenses", "EUR", vec!["0", "0", "3.00"]),
+                ("Expenses", "USD", vec!["25.00", "0", "35.00"]),
+                ("Income", "USD", vec!["-1000.00", "0", "0"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overflowing_totals_are_skipped() {
+        let files = files(
+            r#"2024-01-03 * "Grocer"
+  Expenses:Food  60000000000000000000000000000 USD
+  Assets:Cash
+
+2024-01-04 * "Grocer"
+  Expenses:Food  60000000000000000000000000000 USD
+  Assets:Cash
+
+2024-01-05 * "Bakery"
+  Expenses:Food  1 USD
+  Assets:Cash
+"#,
+        );
+        let result = monthly_totals(&files, &LedgerOptions::default());
+        assert_eq!(
+            result.series[0].totals,
+            vec!["60000000000000000000000000001"]
+        );
+    }
+
+    #[test]
+    fn test_month_range_crosses_years() {
+        assert_eq!(
+            month_range("2023-12", "2024-02"),
+            vec!["2023-12", "2024-01", "2024-02"]
+        );
+        assert!(month_range("2024-02", "2024-01").is_empty());
+    }
+}
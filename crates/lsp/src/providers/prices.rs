@@ -0,0 +1,188 @@
+//! Fetches prices with `bean-price` and adds them to the prices file
+//!
+//! `bean-price` reads the journal, looks up the commodities held on the date that
+//! declare a `price` source, and prints one `price` directive per commodity. Prices the
+//! file already has for that day are skipped; the others are inserted by date, without
+//! blank lines, as prices files usually are.
+
+use crate::checkers::resolve_bean_price_cmd;
+use crate::ledger::{EntryKind, LedgerEntry, extract_entries};
+use crate::providers::import::insertion;
+use crate::server::LspServerStateSnapshot;
+use crate::utils::file_path_to_uri;
+use anyhow::{Context, Result, anyhow, bail};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::process::Command;
+use tree_sitter_beancount::tree_sitter;
+
+/// Provider function for the `beancount.updatePrices` command.
+#[allow(clippy::mutable_key_type)]
+pub(crate) fn update_prices(
+    snapshot: &LspServerStateSnapshot,
+    journal: &Path,
+    date: Option<&str>,
+) -> Result<lsp_types::WorkspaceEdit> {
+    if let Some(date) = date {
+        chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| format!("invalid date `{date}`"))?;
+    }
+    let output = run_bean_price(snapshot, journal, date)?;
+
+    let prices_file = snapshot
+        .config
+        .resolved_prices_file()
+        .unwrap_or_else(|| journal.to_path_buf());
+    let (tree, content) = snapshot
+        .tree_and_text(&prices_file)
+        .with_context(|| format!("failed to read {}", prices_file.display()))?;
+    let known: HashSet<(String, String)> = extract_entries(&tree, &content)
+        .into_iter()
+        .filter_map(|entry| match entry.kind {
+            EntryKind::Price { currency, .. } => Some((entry.date, currency)),
+            _ => None,
+        })
+        .collect();
+
+    let mut by_date: BTreeMap<String, String> = BTreeMap::new();
+    for (entry, line) in parse_prices(&output)? {
+        let EntryKind::Price { currency, .. } = entry.kind else {
+            continue;
+        };
+        if !known.contains(&(entry.date.clone(), currency)) {
+            let text = by_date.entry(entry.date).or_default();
+            text.push_str(&line);
+            text.push('\n');
+        }
+    }
+
+    let edits: Vec<_> = by_date
+        .into_iter()
+        .map(|(date, text)| insertion(&tree, &content, &date, text, false))
+        .collect();
+    let mut changes = HashMap::new();
+    if !edits.is_empty() {
+        let uri = file_path_to_uri(&prices_file)
+            .map_err(|()| anyhow!("failed to convert {} to a URI", prices_file.display()))?;
+        changes.insert(uri, edits);
+    }
+    Ok(snapshot.workspace_edit(changes))
+}
+
+fn run_bean_price(
+    snapshot: &LspServerStateSnapshot,
+    journal: &Path,
+    date: Option<&str>,
+) -> Result<String> {
+    let cmd = resolve_bean_price_cmd(
+        snapshot.config.bean_price_cmd.as_ref(),
+        &snapshot.config.root_dir,
+    )
+    .context("bean-price not found; install beanprice or set bean_price_cmd")?;
+    tracing::debug!("Running {} on {}", cmd.display(), journal.display());
+
+    let mut command = Command::new(&cmd);
+    if let Some(date) = date {
+        command.arg("--date").arg(date);
+    }
+    let output = command
+        .arg(journal)
+        .output()
+        .with_context(|| format!("failed to run {}", cmd.display()))?;
+    if !output.status.success() {
+        bail!(
+            "bean-price failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The `price` directives in `output`, with their text.
+fn parse_prices(output: &str) -> Result<Vec<(LedgerEntry, String)>> {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(&tree_sitter_beancount::language())?;
+    let tree = parser
+        .parse(output, None)
+        .context("failed to parse the bean-price output")?;
+    let content = ropey::Rope::from_str(output);
+    Ok(extract_entries(&tree, &content)
+        .into_iter()
+        .filter(|entry| matches!(entry.kind, EntryKind::Price { .. }))
+        .map(|entry| {
+            let line = content.line(entry.line as usize).to_string();
+            (entry, line.trim_end().to_string())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_parse_prices() {
+        let prices = parse_prices(
+            "2024-02-01 price HOOL  123.45 USD\n\n2024-02-01 price EUR 1.08 USD\n;comment\n",
+        )
+        .unwrap();
+        let lines: Vec<_> = prices.iter().map(|(_, line)| line.as_str()).collect();
+        assert_eq!(
+            lines,
+            [
+                "2024-02-01 price HOOL  123.45 USD",
+                "2024-02-01 price EUR 1.08 USD"
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_update_prices_with_mock_bean_price() {
+        use crate::utils::ToFilePath;
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("bean-price");
+        // Echoes the requested date into the prices, and repeats one the file has
+        std::fs::write(
+            &script,
+            "#!/bin/sh\n[ \"$1\" = --date ] || exit 1\n\
+             printf '%s price HOOL 123.45 USD\\n%s price EUR 1.08 USD\\n' \"$2\" \"$2\"\n\
+             printf '2024-01-01 price HOOL 100 USD\\n'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let journal = dir.path().join("main.beancount");
+        std::fs::write(&journal, "include \"prices.beancount\"\n").unwrap();
+        let prices = dir.path().join("prices.beancount");
+        let text = "2024-01-01 price HOOL 100 USD\n2024-03-01 price HOOL 130 USD\n";
+        std::fs::write(&prices, text).unwrap();
+
+        let mut config = Config::new(dir.path().to_path_buf());
+        config.bean_price_cmd = Some(script);
+        config.prices_file = Some(PathBuf::from("prices.beancount"));
+        let snapshot = LspServerStateSnapshot::for_test(config, &[(&prices, text)]);
+
+        let edit = update_prices(&snapshot, &journal, Some("2024-02-01")).unwrap();
+        let changes = crate::utils::text_edits(&edit);
+        assert_eq!(changes.len(), 1);
+        let (uri, edits) = changes.into_iter().next().unwrap();
+        assert_eq!(uri.to_file_path().unwrap(), prices);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range.start, lsp_types::Position::new(1, 0));
+        assert_eq!(
+            edits[0].new_text,
+            "2024-02-01 price HOOL 123.45 USD\n2024-02-01 price EUR 1.08 USD\n"
+        );
+
+        assert!(update_prices(&snapshot, &journal, Some("yesterday")).is_err());
+        // Without --date the mock fails
+        let error = update_prices(&snapshot, &journal, None).unwrap_err();
+        assert!(error.to_string().starts_with("bean-price failed"));
+    }
+}
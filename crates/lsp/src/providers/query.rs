@@ -0,0 +1,676 @@
+//! A small subset of bean-query, evaluated against the native index
+//!
+//! Supported syntax (keywords are case-insensitive):
+//!
+//! ```text
+//! SELECT target [, target]* [WHERE condition [AND condition]*]
+//!     [GROUP BY column [, column]*] [ORDER BY column [ASC | DESC]] [LIMIT n]
+//! ```
+//!
+//! - columns: `date`, `account`, `payee`, `narration`, `position`, `number`, `currency`;
+//!   `*` selects `date, payee, narration, account, position`
+//! - targets: a column, or `sum(position)` / `sum(number)`
+//! - conditions: `column op value` with `=`, `!=`, `<`, `<=`, `>`, `>=`, or
+//!   `column ~ "regex"`; values are quoted strings, dates or numbers
+//!
+//! Each row is a posting, with elided amounts filled in like the ledger does. A query
+//! with `sum(...)` groups the postings by its other targets; `GROUP BY` may list them
+//! but grouping never depends on it. `ORDER BY` sorts the result by a selected column.
+
+use crate::ledger::{self, Amount, EntryKind, Inventory};
+use crate::lsp_ext::QueryResult;
+use crate::server::LspServerStateSnapshot;
+use anyhow::{Context, Result, anyhow, bail};
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Date,
+    Account,
+    Payee,
+    Narration,
+    Position,
+    Number,
+    Currency,
+}
+
+impl Column {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "date" => Column::Date,
+            "account" => Column::Account,
+            "payee" => Column::Payee,
+            "narration" => Column::Narration,
+            "position" => Column::Position,
+            "number" => Column::Number,
+            "currency" => Column::Currency,
+            _ => bail!("unknown column `{name}`"),
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Column::Date => "date",
+            Column::Account => "account",
+            Column::Payee => "payee",
+            Column::Narration => "narration",
+            Column::Position => "position",
+            Column::Number => "number",
+            Column::Currency => "currency",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Column(Column),
+    Sum(Column),
+}
+
+impl Target {
+    fn name(self) -> String {
+        match self {
+            Target::Column(column) => column.name().to_string(),
+            Target::Sum(column) => format!("sum({})", column.name()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+enum Condition {
+    Compare(Column, Op, String),
+    Match(Column, Regex),
+}
+
+#[derive(Debug)]
+struct Query {
+    targets: Vec<Target>,
+    conditions: Vec<Condition>,
+    order_by: Option<(Column, bool)>,
+    limit: Option<usize>,
+}
+
+/// Operators and punctuation, two-character ones first.
+const SYMBOLS: [&str; 11] = ["!=", "<=", ">=", "=", "<", ">", "~", ",", "(", ")", "*"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Symbol(&'static str),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, ch)) if ch == c => break,
+                    Some((_, ch)) => value.push(ch),
+                    None => bail!("unterminated string starting at {start}"),
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else if c.is_alphanumeric() || "_-.:".contains(c) {
+            let mut end = start;
+            while let Some(&(i, ch)) = chars.peek() {
+                if !(ch.is_alphanumeric() || "_-.:/".contains(ch)) {
+                    break;
+                }
+                end = i + ch.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Word(input[start..end].to_string()));
+        } else {
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|symbol| input[start..].starts_with(symbol))
+                .ok_or_else(|| anyhow!("unexpected character `{c}`"))?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            bail!("expected `{keyword}`")
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn word(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            other => bail!("expected a name, found {other:?}"),
+        }
+    }
+
+    fn column(&mut self) -> Result<Column> {
+        Column::parse(&self.word()?)
+    }
+
+    fn target(&mut self) -> Result<Target> {
+        let name = self.word()?;
+        if !self.symbol("(") {
+            return Ok(Target::Column(Column::parse(&name)?));
+        }
+        if !name.eq_ignore_ascii_case("sum") {
+            bail!("unknown function `{name}`");
+        }
+        let column = self.column()?;
+        if !self.symbol(")") {
+            bail!("expected `)`");
+        }
+        match column {
+            Column::Position | Column::Number => Ok(Target::Sum(column)),
+            _ => bail!("cannot sum `{}`", column.name()),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition> {
+        let column = self.column()?;
+        if column == Column::Position {
+            bail!("cannot compare `position`");
+        }
+        let op = match self.next() {
+            Some(Token::Symbol("~")) => {
+                let pattern = self.value()?;
+                let regex = Regex::new(&pattern)
+                    .with_context(|| format!("invalid regular expression `{pattern}`"))?;
+                return Ok(Condition::Match(column, regex));
+            }
+            Some(Token::Symbol("=")) => Op::Eq,
+            Some(Token::Symbol("!=")) => Op::Ne,
+            Some(Token::Symbol("<")) => Op::Lt,
+            Some(Token::Symbol("<=")) => Op::Le,
+            Some(Token::Symbol(">")) => Op::Gt,
+            Some(Token::Symbol(">=")) => Op::Ge,
+            other => bail!("expected a comparison, found {other:?}"),
+        };
+        let mut value = self.value()?;
+        match column {
+            Column::Number => {
+                Decimal::from_str(&value).with_context(|| format!("invalid number `{value}`"))?;
+            }
+            // Ledger dates are normalized to `YYYY-MM-DD`
+            Column::Date => value = value.replace('/', "-"),
+            _ => {}
+        }
+        Ok(Condition::Compare(column, op, value))
+    }
+
+    fn value(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Str(value) | Token::Word(value)) => Ok(value),
+            other => bail!("expected a value, found {other:?}"),
+        }
+    }
+}
+
+fn parse(input: &str) -> Result<Query> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+
+    parser.expect_keyword("select")?;
+    let mut targets = vec![];
+    if parser.symbol("*") {
+        targets.extend(
+            [
+                Column::Date,
+                Column::Payee,
+                Column::Narration,
+                Column::Account,
+                Column::Position,
+            ]
+            .map(Target::Column),
+        );
+    } else {
+        loop {
+            targets.push(parser.target()?);
+            if !parser.symbol(",") {
+                break;
+            }
+        }
+    }
+
+    let mut conditions = vec![];
+    if parser.keyword("where") {
+        loop {
+            conditions.push(parser.condition()?);
+            if !parser.keyword("and") {
+                break;
+            }
+        }
+    }
+
+    if parser.keyword("group") {
+        parser.expect_keyword("by")?;
+        loop {
+            let column = parser.column()?;
+            if !targets.contains(&Target::Column(column)) {
+                bail!("cannot group by `{}` without selecting it", column.name());
+            }
+            if !parser.symbol(",") {
+                break;
+            }
+        }
+    }
+
+    let mut order_by = None;
+    if parser.keyword("order") {
+        parser.expect_keyword("by")?;
+        let column = parser.column()?;
+        if !targets.contains(&Target::Column(column)) {
+            bail!("cannot order by `{}` without selecting it", column.name());
+        }
+        let descending = parser.keyword("desc");
+        if !descending {
+            parser.keyword("asc");
+        }
+        order_by = Some((column, descending));
+    }
+
+    let mut limit = None;
+    if parser.keyword("limit") {
+        let value = parser.word()?;
+        limit = Some(
+            value
+                .parse()
+                .with_context(|| format!("invalid limit `{value}`"))?,
+        );
+    }
+
+    if let Some(token) = parser.peek() {
+        bail!("unexpected {token:?}");
+    }
+
+    Ok(Query {
+        targets,
+        conditions,
+        order_by,
+        limit,
+    })
+}
+
+/// A posting with the transaction it belongs to.
+struct Row<'a> {
+    date: &'a str,
+    payee: Option<&'a str>,
+    narration: Option<&'a str>,
+    account: String,
+    amount: Amount,
+}
+
+impl Row<'_> {
+    fn text(&self, column: Column) -> String {
+        match column {
+            Column::Date => self.date.to_string(),
+            Column::Account => self.account.clone(),
+            Column::Payee => self.payee.unwrap_or_default().to_string(),
+            Column::Narration => self.narration.unwrap_or_default().to_string(),
+            Column::Position => format!("{} {}", self.amount.value, self.amount.currency),
+            Column::Number => self.amount.value.to_string(),
+            Column::Currency => self.amount.currency.clone(),
+        }
+    }
+
+    fn matches(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Match(column, regex) => regex.is_match(&self.text(*column)),
+            Condition::Compare(column, op, value) => {
+                let ordering = match column {
+                    Column::Number => Decimal::from_str(value)
+                        .map(|value| self.amount.value.cmp(&value))
+                        .unwrap_or(Ordering::Less),
+                    _ => self.text(*column).as_str().cmp(value.as_str()),
+                };
+                match op {
+                    Op::Eq => ordering == Ordering::Equal,
+                    Op::Ne => ordering != Ordering::Equal,
+                    Op::Lt => ordering == Ordering::Less,
+                    Op::Le => ordering != Ordering::Greater,
+                    Op::Gt => ordering == Ordering::Greater,
+                    Op::Ge => ordering != Ordering::Less,
+                }
+            }
+        }
+    }
+}
+
+/// A result cell, before it is turned into JSON.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Value {
+    Text(String),
+    Number(Decimal),
+    Inventory(Inventory),
+}
+
+impl Value {
+    fn of(row: &Row, column: Column) -> Self {
+        match column {
+            Column::Number => Value::Number(row.amount.value),
+            _ => Value::Text(row.text(column)),
+        }
+    }
+
+    fn into_json(self) -> serde_json::Value {
+        match self {
+            Value::Text(text) => text.into(),
+            // Strings keep the precision of the amounts
+            Value::Number(number) => number.to_string().into(),
+            Value::Inventory(inventory) => inventory
+                .into_iter()
+                .filter(|(_, value)| !value.is_zero())
+                .map(|(currency, value)| format!("{value} {currency}"))
+                .collect::<Vec<_>>()
+                .into(),
+        }
+    }
+}
+
+/// Provider function for the `beancount.query` command.
+pub(crate) fn query(snapshot: &LspServerStateSnapshot, input: &str) -> Result<QueryResult> {
+    let query = parse(input).with_context(|| format!("invalid query `{input}`"))?;
+
+    let mut entries: Vec<_> = snapshot
+        .beancount_data
+        .iter()
+        .map(|(file, data)| (file, data.get_ledger_entries()))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let mut transactions: Vec<_> = entries
+        .iter()
+        .flat_map(|(_, entries)| entries.iter())
+        .filter_map(|entry| match &entry.kind {
+            EntryKind::Transaction {
+                payee,
+                narration,
+                postings,
+                ..
+            } => Some((entry.date.as_str(), payee, narration, postings)),
+            _ => None,
+        })
+        .collect();
+    // Stable, so transactions of a day stay in file order
+    transactions.sort_by_key(|(date, ..)| *date);
+
+    let rows = transactions
+        .into_iter()
+        .flat_map(|(date, payee, narration, postings)| {
+            ledger::book_postings(postings)
+                .into_iter()
+                .map(move |(account, _, amount)| Row {
+                    date,
+                    payee: payee.as_deref(),
+                    narration: narration.as_deref(),
+                    account,
+                    amount,
+                })
+        })
+        .filter(|row| query.conditions.iter().all(|c| row.matches(c)));
+
+    let mut result_rows: Vec<Vec<Value>> =
+        if query.targets.iter().any(|t| matches!(t, Target::Sum(_))) {
+            let mut groups: BTreeMap<Vec<Value>, Vec<Value>> = BTreeMap::new();
+            for row in rows {
+                let key = query
+                    .targets
+                    .iter()
+                    .filter_map(|target| match target {
+                        Target::Column(column) => Some(Value::of(&row, *column)),
+                        Target::Sum(_) => None,
+                    })
+                    .collect();
+                let sums = groups.entry(key).or_insert_with(|| {
+                    query
+                        .targets
+                        .iter()
+                        .filter_map(|target| match target {
+                            Target::Sum(Column::Number) => Some(Value::Number(Decimal::ZERO)),
+                            Target::Sum(_) => Some(Value::Inventory(Inventory::new())),
+                            Target::Column(_) => None,
+                        })
+                        .collect()
+                });
+                for sum in sums {
+                    let total = match sum {
+                        Value::Number(total) => total,
+                        Value::Inventory(inventory) => {
+                            inventory.entry(row.amount.currency.clone()).or_default()
+                        }
+                        Value::Text(_) => continue,
+                    };
+                    *total = total
+                        .checked_add(row.amount.value)
+                        .ok_or_else(|| anyhow!("sum overflowed"))?;
+                }
+            }
+            groups
+                .into_iter()
+                .map(|(key, sums)| {
+                    let (mut key, mut sums) = (key.into_iter(), sums.into_iter());
+                    query
+                        .targets
+                        .iter()
+                        .filter_map(|target| match target {
+                            Target::Column(_) => key.next(),
+                            Target::Sum(_) => sums.next(),
+                        })
+                        .collect()
+                })
+                .collect()
+        } else {
+            rows.map(|row| {
+                query
+                    .targets
+                    .iter()
+                    .filter_map(|target| match target {
+                        Target::Column(column) => Some(Value::of(&row, *column)),
+                        Target::Sum(_) => None,
+                    })
+                    .collect()
+            })
+            .collect()
+        };
+
+    if let Some((column, descending)) = query.order_by {
+        let index = query
+            .targets
+            .iter()
+            .position(|target| *target == Target::Column(column))
+            .context("order column is selected")?;
+        result_rows.sort_by(|a, b| {
+            let ordering = a[index].cmp(&b[index]);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+    if let Some(limit) = query.limit {
+        result_rows.truncate(limit);
+    }
+
+    Ok(QueryResult {
+        columns: query.targets.iter().map(|target| target.name()).collect(),
+        rows: result_rows
+            .into_iter()
+            .map(|row| row.into_iter().map(Value::into_json).collect())
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn snapshot(text: &str) -> LspServerStateSnapshot {
+        LspServerStateSnapshot::for_test(
+            Config::new(PathBuf::from("/")),
+            &[(std::path::Path::new("/main.beancount"), text)],
+        )
+    }
+
+    const JOURNAL: &str = r#"2024-01-01 * "Employer" "Salary"
+  Assets:Cash  1000 USD
+  Income:Salary
+
+2024-01-05 * "Grocer" "Weekly shopping"
+  Expenses:Food  25.50 USD
+  Assets:Cash
+
+2024-02-01 * "Grocer" "Weekly shopping"
+  Expenses:Food  30 USD
+  Assets:Cash
+"#;
+
+    #[test]
+    fn test_select_with_filters() {
+        let result = query(
+            &snapshot(JOURNAL),
+            r#"SELECT date, payee, position WHERE account ~ "^Expenses" AND date >= 2024-01-10"#,
+        )
+        .unwrap();
+        assert_eq!(result.columns, ["date", "payee", "position"]);
+        assert_eq!(
+            result.rows,
+            [[json!("2024-02-01"), json!("Grocer"), json!("30 USD")]]
+        );
+    }
+
+    #[test]
+    fn test_sum_groups_by_other_targets() {
+        let result = query(
+            &snapshot(JOURNAL),
+            "select account, sum(position) group by account order by account desc",
+        )
+        .unwrap();
+        assert_eq!(result.columns, ["account", "sum(position)"]);
+        assert_eq!(
+            result.rows,
+            [
+                [json!("Income:Salary"), json!(["-1000 USD"])],
+                [json!("Expenses:Food"), json!(["55.50 USD"])],
+                [json!("Assets:Cash"), json!(["944.50 USD"])],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overflowing_sum_is_an_error() {
+        let journal = r#"2024-01-01 * "Big"
+  Assets:Cash  79000000000000000000000000000 USD
+  Income:Salary
+
+2024-01-02 * "Bigger"
+  Assets:Cash  79000000000000000000000000000 USD
+  Income:Salary
+"#;
+        for target in ["sum(number)", "sum(position)"] {
+            let error = query(
+                &snapshot(journal),
+                &format!(r#"SELECT {target} WHERE account = "Assets:Cash""#),
+            )
+            .unwrap_err();
+            assert_eq!(error.to_string(), "sum overflowed");
+        }
+    }
+
+    #[test]
+    fn test_elided_amounts_and_limit() {
+        let result = query(
+            &snapshot(JOURNAL),
+            r#"SELECT narration, number WHERE account = "Assets:Cash" LIMIT 2"#,
+        )
+        .unwrap();
+        assert_eq!(
+            result.rows,
+            [
+                [json!("Salary"), json!("1000")],
+                [json!("Weekly shopping"), json!("-25.50")],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_queries() {
+        let snapshot = snapshot(JOURNAL);
+        for input in [
+            "SELECT",
+            "SELECT balance",
+            "SELECT sum(account)",
+            "SELECT date WHERE payee ~ \"(\"",
+            "SELECT date ORDER BY account",
+            "SELECT date LIMIT ten",
+            "SELECT date FROM postings",
+        ] {
+            assert!(query(&snapshot, input).is_err(), "{input}");
+        }
+    }
+}
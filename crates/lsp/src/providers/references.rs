@@ -1,9 +1,6 @@
-use crate::document::Document;
+use crate::beancount_data::BeancountData;
 use crate::server::LspServerStateSnapshot;
-use crate::treesitter_utils::{
-    lsp_position_to_tree_sitter_point_range, text_for_tree_sitter_node,
-    tree_sitter_node_to_lsp_range,
-};
+use crate::treesitter_utils::{lsp_position_to_tree_sitter_point_range, text_for_tree_sitter_node};
 use crate::utils::file_path_to_uri;
 use anyhow::{Context, Result};
 use lsp_types::Location;
@@ -13,7 +10,6 @@ use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use tracing::debug;
-use tree_sitter::StreamingIterator;
 use tree_sitter_beancount::tree_sitter;
 
 fn node_text_at_position(
@@ -59,7 +55,11 @@ pub(crate) fn references(
         return Ok(None);
     };
 
-    let locs = find_references(&snapshot.forest, &snapshot.open_docs, &node_text);
+    let locs = find_references(
+        &snapshot.beancount_data,
+        &node_text,
+        params.context.include_declaration,
+    );
     Ok(Some(locs))
 }
 
@@ -90,17 +90,25 @@ pub(crate) fn rename(
         return Ok(None);
     };
 
-    let locs = find_references(&snapshot.forest, &snapshot.open_docs, &node_text);
+    // With `rename_subaccounts`, `Assets:Bank:Checking:Sub` follows `Assets:Bank:Checking`
+    let subaccount_prefix = format!("{node_text}:");
+    let subaccounts = snapshot.config.rename_subaccounts;
+    let occurrences = find_accounts(
+        &snapshot.beancount_data,
+        &|account| account == node_text || (subaccounts && account.starts_with(&subaccount_prefix)),
+        true,
+    );
     let new_name = params.new_name;
 
     // Group locations by URI string to avoid mutable key type warning
-    let mut grouped_locs: std::collections::HashMap<String, Vec<lsp_types::Location>> =
+    let mut grouped_locs: std::collections::HashMap<String, Vec<(lsp_types::Location, String)>> =
         std::collections::HashMap::new();
-    for loc in locs {
+    for (loc, account) in occurrences {
+        let new_text = format!("{new_name}{}", &account[node_text.len()..]);
         grouped_locs
             .entry(loc.uri.to_string())
             .or_default()
-            .push(loc);
+            .push((loc, new_text));
     }
 
     let mut changes: std::collections::HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>> =
@@ -115,81 +123,71 @@ pub(crate) fn rename(
         };
         let mut edits: Vec<_> = locations
             .into_iter()
-            .map(|l| lsp_types::TextEdit::new(l.range, new_name.clone()))
+            .map(|(l, new_text)| lsp_types::TextEdit::new(l.range, new_text))
             .collect();
         // Send edits ordered from the back so we do not invalidate following positions.
         edits.sort_by_key(|edit| edit.range.start);
         edits.reverse();
         changes.insert(uri, edits);
     }
-    Ok(Some(lsp_types::WorkspaceEdit::new(changes)))
+    Ok(Some(snapshot.workspace_edit(changes)))
 }
 
-/// Find all references to a given text in the project using tree-sitter queries.
+/// Find all references to a given account in the project.
+///
+/// Every directive naming the account counts: postings, `open`, `close`, `balance`,
+/// `pad` (either account), `note` and `document`. The `open` directives are the
+/// declaration and are left out unless `include_declaration` is set.
 fn find_references(
-    forest: &HashMap<PathBuf, Arc<tree_sitter::Tree>>,
-    open_docs: &HashMap<PathBuf, Document>,
+    beancount_data: &HashMap<PathBuf, Arc<BeancountData>>,
     node_text: &str,
+    include_declaration: bool,
 ) -> Vec<lsp_types::Location> {
-    forest
+    find_accounts(
+        beancount_data,
+        &|account| account == node_text,
+        include_declaration,
+    )
+    .into_iter()
+    .map(|(location, _)| location)
+    .collect()
+}
+
+/// The accounts in the project for which `matches` holds, with their names.
+///
+/// Works on the accounts recorded while indexing, so files that are not open in the
+/// editor are not read again for every request.
+fn find_accounts(
+    beancount_data: &HashMap<PathBuf, Arc<BeancountData>>,
+    matches: &dyn Fn(&str) -> bool,
+    include_declaration: bool,
+) -> Vec<(lsp_types::Location, String)> {
+    beancount_data
         .iter()
-        .flat_map(|(url, tree)| {
-            let query = match tree_sitter::Query::new(
-                &tree_sitter_beancount::language(),
-                "(account)@account",
-            ) {
-                Ok(q) => q,
-                Err(_e) => return vec![],
+        .flat_map(|(path, data)| {
+            let Ok(uri) = file_path_to_uri(path) else {
+                debug!("Failed to convert path to uri: {:?}", path);
+                return vec![];
             };
-            let capture_account = query
-                .capture_index_for_name("account")
-                .expect("account should be captured");
-
-            let (rope, text) = if let Some(doc) = open_docs.get(url) {
-                let rope = doc.content.clone();
-                let text = rope.to_string();
-                (rope, text)
-            } else {
-                match std::fs::read_to_string(url) {
-                    Ok(content) => {
-                        let rope = Rope::from_str(&content);
-                        (rope, content)
-                    }
-                    Err(_) => {
-                        debug!("Failed to read file: {:?}", url);
-                        return vec![];
-                    }
-                }
-            };
-
-            let source = text.as_bytes();
-
-            let mut query_cursor = tree_sitter::QueryCursor::new();
-            let mut matches = query_cursor.matches(&query, tree.root_node(), source);
-            let mut results = Vec::new();
-            while let Some(m) = matches.next() {
-                if let Some(node) = m.nodes_for_capture_index(capture_account).next() {
-                    let m_text = node.utf8_text(source).expect("");
-                    if m_text == node_text {
-                        results.push((url.clone(), rope.clone(), node));
-                    }
-                }
-            }
-
-            results
+            data.get_account_references()
+                .iter()
+                .filter(|reference| {
+                    matches(&reference.account) && (include_declaration || !reference.declaration)
+                })
+                .map(|reference| {
+                    (
+                        Location::new(uri.clone(), reference.range),
+                        reference.account.clone(),
+                    )
+                })
+                .collect()
         })
-        .filter_map(|(url, rope, node): (PathBuf, Rope, tree_sitter::Node)| {
-            let uri = file_path_to_uri(&url).ok()?;
-            let range = tree_sitter_node_to_lsp_range(&rope, &node);
-            Some(Location::new(uri, range))
-        })
-        .collect::<Vec<_>>()
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::beancount_data::BeancountData;
     use crate::config::Config;
     use std::collections::HashMap;
 
@@ -201,40 +199,10 @@ mod tests {
     impl TestState {
         fn new(content: &str) -> anyhow::Result<Self> {
             let path = std::env::current_dir()?.join("test.beancount");
-            let rope_content = ropey::Rope::from_str(content);
-
-            let mut parser = tree_sitter::Parser::new();
-            parser.set_language(&tree_sitter_beancount::language())?;
-            let tree = parser.parse(content, None).unwrap();
-
-            let mut forest = HashMap::new();
-            forest.insert(path.clone(), Arc::new(tree.clone()));
-
-            let mut open_docs = HashMap::new();
-            open_docs.insert(
-                path.clone(),
-                Document {
-                    content: rope_content.clone(),
-                    version: 0,
-                },
-            );
-
-            let mut beancount_data = HashMap::new();
-            beancount_data.insert(
-                path.clone(),
-                Arc::new(BeancountData::new(&tree, &rope_content)),
-            );
-
             let config = Config::new(path.clone());
 
             Ok(Self {
-                snapshot: LspServerStateSnapshot {
-                    forest,
-                    open_docs,
-                    beancount_data,
-                    config,
-                    checker: None,
-                },
+                snapshot: LspServerStateSnapshot::for_test(config, &[(&path, content)]),
                 path,
             })
         }
@@ -249,11 +217,7 @@ mod tests {
   Expenses:Food   -100.00 USD
 "#;
         let state = TestState::new(content).unwrap();
-        let locs = find_references(
-            &state.snapshot.forest,
-            &state.snapshot.open_docs,
-            "Assets:Checking",
-        );
+        let locs = find_references(&state.snapshot.beancount_data, "Assets:Checking", true);
 
         assert_eq!(locs.len(), 2); // open + posting
         assert!(locs[0].range.start.line == 1 || locs[1].range.start.line == 1);
@@ -268,11 +232,7 @@ mod tests {
   Assets:Checking  100.00 USD
 "#;
         let state = TestState::new(content).unwrap();
-        let locs = find_references(
-            &state.snapshot.forest,
-            &state.snapshot.open_docs,
-            "Assets:Nonexistent",
-        );
+        let locs = find_references(&state.snapshot.beancount_data, "Assets:Nonexistent", true);
 
         assert_eq!(locs.len(), 0);
     }
@@ -299,27 +259,18 @@ mod tests {
         let tree1 = parser.parse(content1, None).unwrap();
         let tree2 = parser.parse(content2, None).unwrap();
 
-        let mut forest = HashMap::new();
-        forest.insert(path1.clone(), Arc::new(tree1));
-        forest.insert(path2.clone(), Arc::new(tree2));
-
-        let mut open_docs = HashMap::new();
-        open_docs.insert(
+        // Neither file is open in the editor nor exists on disk; the index is enough
+        let mut beancount_data = HashMap::new();
+        beancount_data.insert(
             path1,
-            Document {
-                content: ropey::Rope::from_str(content1),
-                version: 0,
-            },
+            Arc::new(BeancountData::new(&tree1, &ropey::Rope::from_str(content1))),
         );
-        open_docs.insert(
+        beancount_data.insert(
             path2,
-            Document {
-                content: ropey::Rope::from_str(content2),
-                version: 0,
-            },
+            Arc::new(BeancountData::new(&tree2, &ropey::Rope::from_str(content2))),
         );
 
-        let locs = find_references(&forest, &open_docs, "Assets:Bank");
+        let locs = find_references(&beancount_data, "Assets:Bank", true);
 
         assert_eq!(locs.len(), 3); // open in file1 + posting in file1 + posting in file2
     }
@@ -383,8 +334,7 @@ mod tests {
         let result = rename(state.snapshot, params).unwrap();
         assert!(result.is_some());
         let edit = result.unwrap();
-        assert!(edit.changes.is_some());
-        let changes = edit.changes.unwrap();
+        let changes = crate::utils::text_edits(&edit);
         assert_eq!(changes.len(), 1);
         let edits = changes.get(&uri).unwrap();
         assert_eq!(edits.len(), 2); // Rename in both locations
@@ -435,18 +385,109 @@ mod tests {
 "#;
         let state = TestState::new(content).unwrap();
 
-        let locs_food = find_references(
-            &state.snapshot.forest,
-            &state.snapshot.open_docs,
-            "Expenses:Food",
-        );
+        let locs_food = find_references(&state.snapshot.beancount_data, "Expenses:Food", true);
         assert_eq!(locs_food.len(), 2); // open + posting
 
-        let locs_cash = find_references(
-            &state.snapshot.forest,
-            &state.snapshot.open_docs,
-            "Assets:Cash",
-        );
+        let locs_cash = find_references(&state.snapshot.beancount_data, "Assets:Cash", true);
         assert_eq!(locs_cash.len(), 2); // open + posting
     }
+
+    #[test]
+    fn test_references_in_all_directives() {
+        let content = r#"
+2024-01-01 open Assets:Checking
+2024-01-01 open Equity:Opening
+2024-01-01 pad Assets:Checking Equity:Opening
+2024-01-02 balance Assets:Checking  100.00 USD
+2024-01-03 note Assets:Checking "Called the bank"
+2024-01-04 document Assets:Checking "statement.pdf"
+2024-01-05 * "Test"
+  Assets:Checking  -10.00 USD
+  Expenses:Food
+2024-12-31 close Assets:Checking
+"#;
+        let state = TestState::new(content).unwrap();
+        let lines = |include_declaration| {
+            let mut lines: Vec<u32> = find_references(
+                &state.snapshot.beancount_data,
+                "Assets:Checking",
+                include_declaration,
+            )
+            .iter()
+            .map(|loc| loc.range.start.line)
+            .collect();
+            lines.sort();
+            lines
+        };
+        assert_eq!(lines(true), [1, 3, 4, 5, 6, 8, 10]);
+        assert_eq!(lines(false), [3, 4, 5, 6, 8, 10]);
+
+        let uri = file_path_to_uri(&state.path).unwrap();
+        let params = lsp_types::ReferenceParams {
+            text_document_position: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position: lsp_types::Position::new(3, 34),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: lsp_types::ReferenceContext {
+                include_declaration: false,
+            },
+        };
+        let locs = references(state.snapshot, params).unwrap().unwrap();
+        assert_eq!(locs.len(), 1); // the pad's source account
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_rename_subaccounts() {
+        let content = r#"
+2024-01-01 open Assets:Bank:Checking
+2024-01-01 open Assets:Bank:Checking:Savings
+2024-01-01 open Assets:Bank:CheckingOld
+2024-01-02 * "Test"
+  Assets:Bank:Checking:Savings  100.00 USD
+  Assets:Bank:Checking
+"#;
+        let state = TestState::new(content).unwrap();
+        let uri = file_path_to_uri(&state.path).unwrap();
+        let params = || lsp_types::RenameParams {
+            text_document_position: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+                position: lsp_types::Position::new(1, 20),
+            },
+            new_name: "Assets:N26:Checking".to_string(),
+            work_done_progress_params: Default::default(),
+        };
+        let new_texts = |edit: lsp_types::WorkspaceEdit| {
+            let mut edits: Vec<_> = crate::utils::text_edits(&edit).remove(&uri).unwrap();
+            edits.sort_by_key(|edit| edit.range.start);
+            edits
+                .into_iter()
+                .map(|edit| (edit.range.start.line, edit.new_text))
+                .collect::<Vec<_>>()
+        };
+
+        let edit = rename(state.snapshot, params()).unwrap().unwrap();
+        assert_eq!(
+            new_texts(edit),
+            [
+                (1, "Assets:N26:Checking".to_string()),
+                (6, "Assets:N26:Checking".to_string()),
+            ]
+        );
+
+        let mut state = TestState::new(content).unwrap();
+        state.snapshot.config.rename_subaccounts = true;
+        let edit = rename(state.snapshot, params()).unwrap().unwrap();
+        assert_eq!(
+            new_texts(edit),
+            [
+                (1, "Assets:N26:Checking".to_string()),
+                (2, "Assets:N26:Checking:Savings".to_string()),
+                (5, "Assets:N26:Checking:Savings".to_string()),
+                (6, "Assets:N26:Checking".to_string()),
+            ]
+        );
+    }
 }
@@ -1,4 +1,9 @@
+use crate::beancount_data::BeancountData;
 use crate::server::LspServerStateSnapshot;
+use crate::treesitter_utils::{
+    position_encoding, text_for_tree_sitter_node, tree_sitter_point_to_lsp_position,
+};
+use crate::utils::ToFilePath;
 use anyhow::Result;
 use lsp_types::{
     SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokens, SemanticTokensLegend,
@@ -6,7 +11,9 @@ use lsp_types::{
 };
 use ropey::Rope;
 use std::cmp::Ordering;
-use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
 use strum::IntoEnumIterator;
 use tree_sitter_beancount::NodeKind;
 use tree_sitter_beancount::tree_sitter::Node;
@@ -28,6 +35,10 @@ enum TokenKind {
     Property,
     Class,
     Function,
+    Decorator,
+    Modifier,
+    EnumMember,
+    Namespace,
 }
 
 fn token_types() -> Vec<SemanticTokenType> {
@@ -51,11 +62,49 @@ fn token_type(kind: TokenKind) -> SemanticTokenType {
         TokenKind::Property => SemanticTokenType::PROPERTY,
         TokenKind::Class => SemanticTokenType::CLASS,
         TokenKind::Function => SemanticTokenType::FUNCTION,
+        TokenKind::Decorator => SemanticTokenType::DECORATOR,
+        TokenKind::Modifier => SemanticTokenType::MODIFIER,
+        TokenKind::EnumMember => SemanticTokenType::ENUM_MEMBER,
+        TokenKind::Namespace => SemanticTokenType::NAMESPACE,
     }
 }
 
-// We currently do not expose token modifiers; keep list empty for a minimal implementation.
-const TOKEN_MODIFIERS: &[SemanticTokenModifier] = &[];
+/// Beancount-specific modifiers, so themes can tell apart tokens sharing a type, such as
+/// payees and narrations.
+#[derive(strum_macros::EnumIter, Copy, Clone, Debug, PartialEq, Eq)]
+enum TokenModifier {
+    Payee,
+    Narration,
+    Tag,
+    Link,
+    Flag,
+    Commodity,
+    /// An account used after its `close` date.
+    Deprecated,
+    /// An account without an `open` directive.
+    Undeclared,
+}
+
+fn token_modifiers() -> Vec<SemanticTokenModifier> {
+    TokenModifier::iter().map(token_modifier).collect()
+}
+
+fn token_modifier(modifier: TokenModifier) -> SemanticTokenModifier {
+    match modifier {
+        TokenModifier::Payee => SemanticTokenModifier::new("payee"),
+        TokenModifier::Narration => SemanticTokenModifier::new("narration"),
+        TokenModifier::Tag => SemanticTokenModifier::new("tag"),
+        TokenModifier::Link => SemanticTokenModifier::new("link"),
+        TokenModifier::Flag => SemanticTokenModifier::new("flag"),
+        TokenModifier::Commodity => SemanticTokenModifier::new("commodity"),
+        TokenModifier::Deprecated => SemanticTokenModifier::DEPRECATED,
+        TokenModifier::Undeclared => SemanticTokenModifier::new("undeclared"),
+    }
+}
+
+fn modifier_bit(modifier: TokenModifier) -> u32 {
+    1 << modifier as u32
+}
 
 #[derive(Debug)]
 struct RawToken {
@@ -66,11 +115,43 @@ struct RawToken {
     modifiers_bitset: u32,
 }
 
+/// The opened and closed accounts of a journal.
+#[derive(Debug, Default)]
+struct AccountStates {
+    opened: HashSet<String>,
+    closed: HashMap<String, String>,
+}
+
+impl AccountStates {
+    fn new(files: &HashMap<PathBuf, Arc<BeancountData>>) -> Self {
+        let mut states = Self::default();
+        for data in files.values() {
+            for (account, _) in data.get_account_definitions().iter() {
+                states.opened.insert(account.clone());
+            }
+            for (account, date) in data.get_account_closes().iter() {
+                states.closed.insert(account.clone(), date.clone());
+            }
+        }
+        states
+    }
+
+    /// The modifier for `account` named in a directive dated `date`, if it is a problem.
+    fn modifier(&self, account: &str, date: Option<&str>) -> Option<TokenModifier> {
+        if !self.opened.contains(account) {
+            return Some(TokenModifier::Undeclared);
+        }
+        let closed = self.closed.get(account)?;
+        date.is_some_and(|date| date > closed.as_str())
+            .then_some(TokenModifier::Deprecated)
+    }
+}
+
 /// Public legend shared between capability advertisement and token responses.
 pub(crate) fn legend() -> SemanticTokensLegend {
     SemanticTokensLegend {
         token_types: token_types(),
-        token_modifiers: TOKEN_MODIFIERS.to_vec(),
+        token_modifiers: token_modifiers(),
     }
 }
 
@@ -79,14 +160,41 @@ pub(crate) fn semantic_tokens_full(
     snapshot: LspServerStateSnapshot,
     params: SemanticTokensParams,
 ) -> Result<Option<SemanticTokensResult>> {
+    if !snapshot.config.features.semantic_tokens {
+        return Ok(None);
+    }
     let (tree, doc) = match snapshot.tree_and_document_for_uri(&params.text_document.uri) {
         Ok(v) => v,
         Err(_) => return Ok(None),
     };
     let content: Rope = doc.content.clone();
 
+    if !snapshot
+        .config
+        .large_file
+        .semantic_tokens_enabled(content.len_lines())
+    {
+        tracing::debug!(
+            "Skipping semantic tokens for large file: {} lines",
+            content.len_lines()
+        );
+        return Ok(None);
+    }
+
+    // Without indexed files every account would look undeclared
+    let files = match params.text_document.uri.to_file_path() {
+        Ok(file) => snapshot.journal_files(snapshot.journal_for(&file).as_deref()),
+        Err(_) => HashMap::new(),
+    };
+    let accounts = (!files.is_empty()).then(|| AccountStates::new(&files));
+
     let mut raw_tokens = Vec::new();
-    collect_tokens(&tree.root_node(), &content, &mut raw_tokens);
+    collect_tokens(
+        &tree.root_node(),
+        &content,
+        accounts.as_ref(),
+        &mut raw_tokens,
+    );
 
     if raw_tokens.is_empty() {
         return Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
@@ -130,7 +238,12 @@ pub(crate) fn semantic_tokens_full(
     })))
 }
 
-fn collect_tokens(node: &Node, content: &Rope, out: &mut Vec<RawToken>) {
+fn collect_tokens(
+    node: &Node,
+    content: &Rope,
+    accounts: Option<&AccountStates>,
+    out: &mut Vec<RawToken>,
+) {
     let child = match NodeKind::from(node.kind()) {
         NodeKind::Include
         | NodeKind::Pushtag
@@ -150,22 +263,48 @@ fn collect_tokens(node: &Node, content: &Rope, out: &mut Vec<RawToken>) {
         _ => None,
     };
 
+    // A transaction's flag gets its own token below
     if let Some((index, kind)) = child
         && let Some(child) = node.child(index)
+        && child
+            .named_child(0)
+            .is_none_or(|flag| flag.kind() != "flag")
         && let Some(token) = to_semantic_token(&child, content, kind)
     {
         out.push(token);
     }
 
-    if let Some(kind) = classify_node(node.kind().into())
-        && let Some(tok) = to_semantic_token(node, content, kind)
+    let node_kind = NodeKind::from(node.kind());
+    let kind = if is_option_bool(node, content) {
+        Some(TokenKind::EnumMember)
+    } else {
+        classify_node(node_kind)
+    };
+    if let Some(kind) = kind
+        && let Some(mut tok) = to_semantic_token(node, content, kind)
+    {
+        if let Some(modifier) = classify_modifier(node_kind) {
+            tok.modifiers_bitset = modifier_bit(modifier);
+        }
+        out.push(tok);
+    }
+
+    // Accounts are left to the client's grammar unless something is wrong with them
+    if node_kind == NodeKind::Account
+        && let Some(accounts) = accounts
+        && let Some(modifier) = accounts.modifier(
+            &text_for_tree_sitter_node(content, node),
+            directive_date(node, content).as_deref(),
+        )
+        && let Some(mut tok) = to_semantic_token(node, content, TokenKind::Namespace)
     {
+        tok.modifiers_bitset = modifier_bit(modifier);
         out.push(tok);
     }
 
     let mut cursor = node.walk();
     for child in node.children(&mut cursor) {
-        collect_tokens(&child, content, out);
+        collect_tokens(&child, content, accounts, out);
     }
 }
 
@@ -179,8 +318,8 @@ fn classify_node(kind: NodeKind) -> Option<TokenKind> {
         NodeKind::Plus => Option::Some(TokenKind::Operator),
         NodeKind::Minus => Option::Some(TokenKind::Operator),
         NodeKind::Slash => Option::Some(TokenKind::Operator),
-        NodeKind::Flag => Option::Some(TokenKind::Keyword),
-        NodeKind::Bool => Option::Some(TokenKind::Keyword),
+        NodeKind::Flag => Option::Some(TokenKind::Modifier),
+        NodeKind::Bool => Option::Some(TokenKind::EnumMember),
 
         NodeKind::Comment => Option::Some(TokenKind::Comment),
 
@@ -189,10 +328,10 @@ fn classify_node(kind: NodeKind) -> Option<TokenKind> {
         NodeKind::Number => Option::Some(TokenKind::Number),
 
         NodeKind::Item => Option::Some(TokenKind::Keyword),
-        NodeKind::Key => Option::Some(TokenKind::Keyword),
+        NodeKind::Key => Option::Some(TokenKind::Property),
 
-        NodeKind::Link => Option::Some(TokenKind::Parameter),
-        NodeKind::Tag => Option::Some(TokenKind::Parameter),
+        NodeKind::Link => Option::Some(TokenKind::Decorator),
+        NodeKind::Tag => Option::Some(TokenKind::Decorator),
 
         NodeKind::Narration => Option::Some(TokenKind::String),
         NodeKind::Payee => Option::Some(TokenKind::String),
@@ -205,30 +344,60 @@ fn classify_node(kind: NodeKind) -> Option<TokenKind> {
     }
 }
 
-fn to_semantic_token(node: &Node, content: &Rope, kind: TokenKind) -> Option<RawToken> {
-    let start_byte = node.start_byte();
-    let end_byte = node.end_byte();
+fn classify_modifier(kind: NodeKind) -> Option<TokenModifier> {
+    match kind {
+        NodeKind::Payee => Some(TokenModifier::Payee),
+        NodeKind::Narration => Some(TokenModifier::Narration),
+        NodeKind::Tag => Some(TokenModifier::Tag),
+        NodeKind::Link => Some(TokenModifier::Link),
+        NodeKind::Flag => Some(TokenModifier::Flag),
+        NodeKind::Currency => Some(TokenModifier::Commodity),
+        _ => None,
+    }
+}
 
-    let line = u32::try_from(content.byte_to_line(start_byte)).ok()?;
-    let start_char_idx = content.byte_to_char(start_byte);
-    let end_char_idx = content.byte_to_char(end_byte);
+/// The date of the directive containing `node`.
+fn directive_date(node: &Node, content: &Rope) -> Option<String> {
+    let mut current = node.parent();
+    while let Some(parent) = current {
+        if let Some(date) = parent.child_by_field_name("date") {
+            return Some(text_for_tree_sitter_node(content, &date));
+        }
+        current = parent.parent();
+    }
+    None
+}
 
-    let line_start_char_idx = content.line_to_char(line as usize);
-    let line_start_utf16 = content.char_to_utf16_cu(line_start_char_idx);
+/// Whether `node` is an `option` value spelling a boolean, like `"TRUE"`.
+fn is_option_bool(node: &Node, content: &Rope) -> bool {
+    if node.kind() != "string" {
+        return false;
+    }
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+    if parent.kind() != "option" || parent.child_by_field_name("value") != Some(*node) {
+        return false;
+    }
+    let text = text_for_tree_sitter_node(content, node);
+    let value = text.trim_matches('"');
+    value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false")
+}
 
-    let start_utf16 = content.char_to_utf16_cu(start_char_idx);
-    let end_utf16 = content.char_to_utf16_cu(end_char_idx);
+fn to_semantic_token(node: &Node, content: &Rope, kind: TokenKind) -> Option<RawToken> {
+    let start = tree_sitter_point_to_lsp_position(content, node.start_position());
 
-    let column_utf16 = start_utf16.checked_sub(line_start_utf16)?;
-    let length_utf16 = end_utf16.checked_sub(start_utf16)?;
-    if length_utf16 == 0 {
+    // Token lengths are measured in the same encoding as positions
+    let text = text_for_tree_sitter_node(content, node);
+    let length = position_encoding().character(&text, text.len());
+    if length == 0 {
         return None;
     }
 
     Some(RawToken {
-        line,
-        start: u32::try_from(column_utf16).ok()?,
-        length: u32::try_from(length_utf16).ok()?,
+        line: start.line,
+        start: start.character,
+        length,
         token_type: token_index(kind),
         modifiers_bitset: 0,
     })
@@ -292,11 +461,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_semantic_tokens_skipped_for_large_files() {
+        use crate::server::LspServerStateSnapshot;
+        use std::path::PathBuf;
+        use std::str::FromStr;
+
+        let text = "2024-01-01 open Assets:Cash\n2024-01-02 open Assets:Bank\n";
+        let path = PathBuf::from("/test/main.beancount");
+        let mut config = crate::config::Config::new(PathBuf::from("/test"));
+        config.large_file.semantic_tokens_max_lines = Some(2);
+
+        let snapshot = LspServerStateSnapshot::for_test(config, &[(&path, text)]);
+        let params = SemanticTokensParams {
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            text_document: lsp_types::TextDocumentIdentifier {
+                uri: lsp_types::Uri::from_str("file:///test/main.beancount").unwrap(),
+            },
+        };
+
+        // Three lines, counting the empty one after the final newline
+        assert!(semantic_tokens_full(snapshot, params).unwrap().is_none());
+    }
+
     #[test]
     fn test_legend() {
         let legend = legend();
         assert_eq!(legend.token_types.len(), TokenKind::iter().count());
-        assert_eq!(legend.token_modifiers.len(), 0);
+        assert_eq!(legend.token_modifiers.len(), TokenModifier::iter().count());
+        for (idx, modifier) in TokenModifier::iter().enumerate() {
+            assert_eq!(modifier_bit(modifier), 1 << idx);
+            assert_eq!(legend.token_modifiers[idx], token_modifier(modifier));
+        }
     }
 
     #[test]
@@ -311,10 +508,10 @@ mod tests {
 
     #[test]
     fn test_classify_node_keywords() {
-        assert_eq!(classify_node(NodeKind::Flag), Some(TokenKind::Keyword));
-        assert_eq!(classify_node(NodeKind::Bool), Some(TokenKind::Keyword));
+        assert_eq!(classify_node(NodeKind::Flag), Some(TokenKind::Modifier));
+        assert_eq!(classify_node(NodeKind::Bool), Some(TokenKind::EnumMember));
         assert_eq!(classify_node(NodeKind::Item), Some(TokenKind::Keyword));
-        assert_eq!(classify_node(NodeKind::Key), Some(TokenKind::Keyword));
+        assert_eq!(classify_node(NodeKind::Key), Some(TokenKind::Property));
     }
 
     #[test]
@@ -334,8 +531,8 @@ mod tests {
     fn test_classify_node_other_types() {
         assert_eq!(classify_node(NodeKind::Comment), Some(TokenKind::Comment));
         assert_eq!(classify_node(NodeKind::Currency), Some(TokenKind::Class));
-        assert_eq!(classify_node(NodeKind::Link), Some(TokenKind::Parameter));
-        assert_eq!(classify_node(NodeKind::Tag), Some(TokenKind::Parameter));
+        assert_eq!(classify_node(NodeKind::Link), Some(TokenKind::Decorator));
+        assert_eq!(classify_node(NodeKind::Tag), Some(TokenKind::Decorator));
     }
 
     #[test]
@@ -408,8 +605,9 @@ mod tests {
         let token = to_semantic_token(&narration_node, &content, TokenKind::String).unwrap();
 
         assert_eq!(token.line, 0);
-        // UTF-16 length should account for multi-byte characters
-        assert!(token.length > 0);
+        // UTF-16 length of "\"Café ☕\"" counts each character as one unit
+        assert_eq!(token.start, 13);
+        assert_eq!(token.length, 8);
         assert_eq!(token.token_type, token_index(TokenKind::String));
     }
 
@@ -426,7 +624,7 @@ mod tests {
             .unwrap();
 
         let mut tokens = Vec::new();
-        collect_tokens(&tree.root_node(), &content, &mut tokens);
+        collect_tokens(&tree.root_node(), &content, None, &mut tokens);
 
         // Should collect at least the date token
         assert!(!tokens.is_empty());
@@ -452,7 +650,7 @@ mod tests {
             .unwrap();
 
         let mut tokens = Vec::new();
-        collect_tokens(&tree.root_node(), &content, &mut tokens);
+        collect_tokens(&tree.root_node(), &content, None, &mut tokens);
 
         // Should collect multiple tokens: date, payee, narration, numbers, currency
         assert!(tokens.len() >= 4, "Should collect at least 4 tokens");
@@ -492,7 +690,7 @@ mod tests {
             .unwrap();
 
         let mut tokens = Vec::new();
-        collect_tokens(&tree.root_node(), &content, &mut tokens);
+        collect_tokens(&tree.root_node(), &content, None, &mut tokens);
 
         // Should have both comment and date tokens
         let has_comment = tokens
@@ -505,4 +703,98 @@ mod tests {
         assert!(has_comment, "Should have comment token");
         assert!(has_date, "Should have date token");
     }
+
+    #[test]
+    fn test_collect_tokens_distinguishes_kinds() {
+        let text = r#"option "infer_tolerance_from_cost" "TRUE"
+option "title" "True Ledger"
+2024-01-01 ! "Grocer" "Weekly shopping" #food ^receipt-1
+  receipt: "scan.pdf"
+  Expenses:Food  10.00 EUR
+"#;
+        let content = ropey::Rope::from_str(text);
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(text, None).unwrap();
+
+        let mut tokens = Vec::new();
+        collect_tokens(&tree.root_node(), &content, None, &mut tokens);
+        let token_at = |line: u32, start: u32| {
+            tokens
+                .iter()
+                .find(|t| t.line == line && t.start == start)
+                .map(|t| (t.token_type, t.modifiers_bitset))
+        };
+        let token = |kind, modifier: Option<TokenModifier>| {
+            Some((token_index(kind), modifier.map_or(0, modifier_bit)))
+        };
+
+        assert_eq!(token_at(0, 35), token(TokenKind::EnumMember, None));
+        assert_eq!(token_at(1, 15), token(TokenKind::String, None));
+        assert_eq!(
+            token_at(2, 11),
+            token(TokenKind::Modifier, Some(TokenModifier::Flag))
+        );
+        assert_eq!(
+            token_at(2, 13),
+            token(TokenKind::String, Some(TokenModifier::Payee))
+        );
+        assert_eq!(
+            token_at(2, 22),
+            token(TokenKind::String, Some(TokenModifier::Narration))
+        );
+        assert_eq!(
+            token_at(2, 40),
+            token(TokenKind::Decorator, Some(TokenModifier::Tag))
+        );
+        assert_eq!(
+            token_at(2, 46),
+            token(TokenKind::Decorator, Some(TokenModifier::Link))
+        );
+        assert_eq!(token_at(3, 2), token(TokenKind::Property, None));
+        assert_eq!(
+            token_at(4, 23),
+            token(TokenKind::Class, Some(TokenModifier::Commodity))
+        );
+    }
+
+    #[test]
+    fn test_account_state_modifiers() {
+        let text = r#"2024-01-01 open Assets:Cash
+2024-03-01 close Assets:Cash
+2024-02-01 * "Before"
+  Assets:Cash  -5.00 EUR
+  Expenses:Food
+2024-04-01 * "After"
+  Assets:Cash  -5.00 EUR
+"#;
+        let content = ropey::Rope::from_str(text);
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(text, None).unwrap();
+        let files = HashMap::from([(
+            PathBuf::from("/test/main.beancount"),
+            Arc::new(BeancountData::new(&tree, &content)),
+        )]);
+        let accounts = AccountStates::new(&files);
+
+        let mut tokens = Vec::new();
+        collect_tokens(&tree.root_node(), &content, Some(&accounts), &mut tokens);
+        let accounts: Vec<_> = tokens
+            .iter()
+            .filter(|t| t.token_type == token_index(TokenKind::Namespace))
+            .map(|t| (t.line, t.modifiers_bitset))
+            .collect();
+        assert_eq!(
+            accounts,
+            [
+                (4, modifier_bit(TokenModifier::Undeclared)),
+                (6, modifier_bit(TokenModifier::Deprecated)),
+            ]
+        );
+    }
 }
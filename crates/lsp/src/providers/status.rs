@@ -0,0 +1,128 @@
+use crate::lsp_ext::{ConfigStatus, StatusResult};
+use crate::server::LspServerState;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use tree_sitter_beancount::tree_sitter;
+
+/// Node kinds of dated entries and undated directives.
+const DIRECTIVE_KINDS: &[&str] = &[
+    "balance",
+    "close",
+    "commodity",
+    "custom",
+    "document",
+    "event",
+    "include",
+    "note",
+    "open",
+    "option",
+    "pad",
+    "plugin",
+    "popmeta",
+    "poptag",
+    "price",
+    "pushmeta",
+    "pushtag",
+    "query",
+    "transaction",
+];
+
+/// Provider function for the `beancount/status` request.
+pub(crate) fn status(state: &LspServerState) -> Result<StatusResult> {
+    let mut directives = BTreeMap::new();
+    for tree in state.forest.values() {
+        count_directives(tree.root_node(), &mut directives);
+    }
+
+    let config = &state.config;
+    Ok(StatusResult {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        indexed_files: state.forest.len(),
+        open_documents: state.open_docs.len(),
+        directives,
+        index: state.index_status.clone(),
+        bean_check: state.last_check.clone(),
+        config: ConfigStatus {
+            root_dir: config.root_dir.clone(),
+            journal_file: config.resolved_journal_root(),
+            bean_check_method: config
+                .bean_check
+                .method
+                .map(|method| method.as_str().to_string()),
+            diagnostic_flags: config.diagnostic_flags.clone(),
+            formatting: config.formatting.clone(),
+            log_level: config.log_level.map(|level| level.to_string()),
+            log_file: config.log_file.clone(),
+        },
+    })
+}
+
+/// Counts directives below `node`, descending into org-mode sections.
+pub(crate) fn count_directives(node: tree_sitter::Node, counts: &mut BTreeMap<String, usize>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == "section" {
+            count_directives(child, counts);
+        } else if DIRECTIVE_KINDS.contains(&child.kind()) {
+            *counts.entry(child.kind().to_string()).or_insert(0) += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn parse(text: &str) -> tree_sitter::Tree {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        parser.parse(text, None).unwrap()
+    }
+
+    #[test]
+    fn test_status_counts_directives_across_forest() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut config = Config::new(PathBuf::from("/ledger"));
+        config.journal_root = Some(PathBuf::from("main.beancount"));
+        let mut state = LspServerState::new(sender, config);
+        state.forest.insert(
+            PathBuf::from("/ledger/main.beancount"),
+            Arc::new(parse(
+                "option \"title\" \"Test\"\ninclude \"2024.beancount\"\n* Accounts\n2024-01-01 open Assets:Cash\n",
+            )),
+        );
+        state.forest.insert(
+            PathBuf::from("/ledger/2024.beancount"),
+            Arc::new(parse(
+                "; comment\n2024-01-02 * \"Shop\"\n  Assets:Cash  -1 EUR\n  Expenses:Food\n2024-01-03 * \"Shop\"\n",
+            )),
+        );
+
+        let status = status(&state).unwrap();
+        assert_eq!(status.indexed_files, 2);
+        assert_eq!(status.open_documents, 0);
+        assert_eq!(
+            status.directives,
+            BTreeMap::from([
+                ("include".to_string(), 1),
+                ("open".to_string(), 1),
+                ("option".to_string(), 1),
+                ("transaction".to_string(), 2),
+            ])
+        );
+        assert_eq!(
+            status.config.journal_file,
+            Some(PathBuf::from("/ledger/main.beancount"))
+        );
+        assert!(status.bean_check.is_none());
+
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["indexedFiles"], 2);
+        assert_eq!(json["config"]["formatting"]["account_amount_spacing"], 2);
+    }
+}
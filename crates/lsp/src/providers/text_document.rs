@@ -1,137 +1,27 @@
 use crate::beancount_data::BeancountData;
+use crate::checkers::{BeancountChecker, CheckRun, InterruptibleChecker, Interruption};
 use crate::document::Document;
+use crate::include_graph;
+use crate::lsp_ext::BeanCheckStatus;
 use crate::providers::diagnostics;
+use crate::scheduler::Lane;
 use crate::server::LspServerState;
 use crate::server::LspServerStateSnapshot;
 use crate::server::ProgressMsg;
 use crate::server::Task;
 use crate::to_json;
-use crate::treesitter_utils::lsp_textdocchange_to_ts_inputedit;
-use crate::utils::ToFilePath;
-use anyhow::{Context, Result, anyhow};
+use crate::treesitter_utils::{lsp_position_to_char_idx, lsp_textdocchange_to_ts_inputedit};
+use crate::utils::{ToFilePath, file_path_to_uri, is_virtual_document};
+use anyhow::{Result, anyhow};
 use crossbeam_channel::Sender;
-use glob::glob;
 use lsp_types::notification::Notification;
 use std::collections::{HashMap, HashSet};
-use std::fs;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
 use tracing::{debug, warn};
 use tree_sitter_beancount::tree_sitter;
 
-/// Process included files recursively from a given beancount file
-fn process_includes(
-    state: &mut LspServerState,
-    file_path: &PathBuf,
-    processed: &mut HashSet<PathBuf>,
-) -> Result<()> {
-    // Avoid infinite loops in case of circular includes
-    if processed.contains(file_path) {
-        return Ok(());
-    }
-    processed.insert(file_path.clone());
-
-    // Get the tree for this file (should already be parsed)
-    let tree = match state.forest.get(file_path) {
-        Some(tree) => tree.clone(),
-        None => return Ok(()), // File not parsed yet, skip
-    };
-
-    // Find all include directives in this file using tree-sitter query
-    let text = fs::read_to_string(file_path)?;
-    let bytes = text.as_bytes();
-
-    let include_query_string = r#"
-    (include (string) @string)
-    "#;
-    let include_query =
-        tree_sitter::Query::new(&tree_sitter_beancount::language(), include_query_string)
-            .unwrap_or_else(|_| panic!("Invalid query for includes: {include_query_string}"));
-    let mut cursor_qry = tree_sitter::QueryCursor::new();
-    let mut include_matches = cursor_qry.matches(&include_query, tree.root_node(), bytes);
-
-    let include_paths: Vec<PathBuf> = {
-        use tree_sitter::StreamingIterator;
-        let mut paths = Vec::new();
-
-        while let Some(qmatch) = include_matches.next() {
-            for capture in qmatch.captures {
-                let filename = capture
-                    .node
-                    .utf8_text(bytes)
-                    .ok()
-                    .map(|s| s.trim_start_matches('"').trim_end_matches('"'));
-
-                if let Some(filename) = filename {
-                    let path = std::path::Path::new(filename);
-                    let resolved_path = if path.is_absolute() {
-                        path.to_path_buf()
-                    } else if let Some(parent) = file_path.parent() {
-                        parent.join(path)
-                    } else {
-                        continue;
-                    };
-
-                    paths.push(resolved_path);
-                }
-            }
-        }
-
-        paths
-    };
-
-    // Process each included file
-    for include_path in include_paths {
-        // Handle glob patterns
-        for path in glob(include_path.to_str().unwrap_or(""))
-            .unwrap_or_else(|_| {
-                // If glob fails, try the path as-is
-                glob::glob_with(
-                    &include_path.to_string_lossy(),
-                    glob::MatchOptions::default(),
-                )
-                .unwrap_or_else(|e| {
-                    tracing::error!("Failed to create glob pattern: {}", e);
-                    glob("").expect("empty glob pattern should always work")
-                })
-            })
-            .flatten()
-        {
-            // Skip if already processed
-            if state.forest.contains_key(&path) {
-                continue;
-            }
-
-            // Parse the included file
-            if let Ok(text) = fs::read_to_string(&path) {
-                let mut parser = tree_sitter::Parser::new();
-                if parser
-                    .set_language(&tree_sitter_beancount::language())
-                    .is_ok()
-                    && let Some(tree) = parser.parse(&text, None)
-                {
-                    let content = ropey::Rope::from_str(&text);
-                    let beancount_data = BeancountData::new(&tree, &content);
-
-                    // Add to state
-                    state.forest.insert(path.clone(), Arc::new(tree));
-                    state
-                        .beancount_data
-                        .insert(path.clone(), Arc::new(beancount_data));
-
-                    debug!("Processed included file: {:?}", path);
-
-                    // Recursively process includes in this file
-                    process_includes(state, &path, processed)?;
-                }
-            }
-        }
-    }
-
-    Ok(())
-}
-
 /// Provider function for `textDocument/didOpen`.
 pub(crate) fn did_open(
     state: &mut LspServerState,
@@ -151,7 +41,9 @@ pub(crate) fn did_open(
 
     let document = Document::open(params.clone());
     tracing::debug!("text_document::did_open - adding {:#?}", &uri);
+    state.doc_versions.set(uri.clone(), document.version);
     state.open_docs.insert(uri.clone(), document);
+    state.pending_changes.remove(&uri);
 
     state.parsers.entry(uri.clone()).or_insert_with(|| {
         let mut parser = tree_sitter::Parser::new();
@@ -172,29 +64,19 @@ pub(crate) fn did_open(
             .parse(&params.text_document.text, None)
             .expect("Failed to parse document"),
     );
-    state.forest.insert(uri.clone(), tree);
-
     // Always extract fresh beancount data from the newly parsed tree
     let content = ropey::Rope::from_str(&params.text_document.text);
-    state.beancount_data.insert(
-        uri.clone(),
-        Arc::new(BeancountData::new(
-            state.forest.get(&uri).unwrap(),
-            &content,
-        )),
-    );
-
-    // Process any included files from this document
-    let mut processed = HashSet::new();
-    if let Err(e) = process_includes(state, &uri, &mut processed) {
-        debug!("Error processing includes for {:?}: {}", uri, e);
+    let beancount_data = BeancountData::new(&tree, &content);
+    state.insert_tree(uri.clone(), tree, content);
+    state.insert_beancount_data(uri.clone(), Arc::new(beancount_data));
+
+    // Process any included files from this document. Scratch buffers have no
+    // directory to resolve includes against.
+    if !is_virtual_document(&uri) {
+        include_graph::update_includes(state, &uri);
     }
 
-    let snapshot = state.snapshot();
-    let task_sender = state.task_sender.clone();
-    state.thread_pool.execute(move || {
-        let _result = handle_diagnostics(snapshot, task_sender, params.text_document.uri);
-    });
+    spawn_diagnostics(state, params.text_document.uri);
 
     Ok(())
 }
@@ -208,14 +90,14 @@ pub(crate) fn did_save(
 
     // Lazy extraction: Ensure BeancountData is extracted before diagnostics
     if let Ok(uri) = params.text_document.uri.to_file_path() {
+        // Saving runs the diagnostics a change would have been waiting for
+        state.pending_changes.remove(&uri);
         state.ensure_beancount_data(&uri);
+        // Includes may have been added or removed since the last save
+        include_graph::update_includes(state, &uri);
     }
 
-    let snapshot = state.snapshot();
-    let task_sender = state.task_sender.clone();
-    state.thread_pool.execute(move || {
-        let _result = handle_diagnostics(snapshot, task_sender, params.text_document.uri);
-    });
+    spawn_diagnostics(state, params.text_document.uri);
 
     Ok(())
 }
@@ -237,11 +119,28 @@ pub(crate) fn did_close(
         }
     };
     state.open_docs.remove(&uri);
+    state.doc_versions.remove(&uri);
+    state.pending_changes.remove(&uri);
     // Clear cached parse tree and beancount data to ensure fresh parsing on reopen.
     // This handles external modifications made while the file was closed.
     // Note: We keep parsers for reuse as they are stateless.
-    state.forest.remove(&uri);
-    state.beancount_data.remove(&uri);
+    state.remove_tree(&uri);
+    state.remove_beancount_data(&uri);
+    state.include_graph.remove(&uri);
+
+    // A file that is still part of the journal is reloaded from disk, since the
+    // editor may have discarded unsaved changes.
+    let journal_root = state.config.resolved_journal_root();
+    let roots = state
+        .open_docs
+        .keys()
+        .map(PathBuf::as_path)
+        .chain(journal_root.as_deref());
+    if state.include_graph.reachable(roots).contains(&uri) {
+        include_graph::update_includes(state, &uri);
+    } else {
+        include_graph::prune_unreachable(state);
+    }
     Ok(())
 }
 
@@ -279,41 +178,53 @@ pub(crate) fn did_change_watched_files(
                     continue;
                 }
 
-                // Clear stale cache so next access will re-parse
-                if state.forest.remove(&uri).is_some() {
+                // Only files that belong to the journal are tracked. A new file
+                // may belong to it by matching an include glob.
+                let includers = state.include_graph.includers_matching(&uri);
+                if !state.forest.contains_key(&uri) && includers.is_empty() {
+                    tracing::debug!("Skipping {:?} - not included by the journal", uri);
+                    continue;
+                }
+
+                // Clear stale cache so the file is re-parsed from disk
+                if state.remove_tree(&uri) {
                     tracing::debug!("Cleared stale tree for {:?}", uri);
                 }
-                if state.beancount_data.remove(&uri).is_some() {
+                if state.beancount_data.contains_key(&uri) {
+                    state.remove_beancount_data(&uri);
                     tracing::debug!("Cleared stale beancount_data for {:?}", uri);
                 }
 
-                // If this file is part of our forest (included files), re-parse it
-                if let Ok(content) = fs::read_to_string(&uri) {
-                    let mut parser = tree_sitter::Parser::new();
-                    if parser
-                        .set_language(&tree_sitter_beancount::language())
-                        .is_ok()
-                        && let Some(tree) = parser.parse(&content, None)
-                    {
-                        let rope_content = ropey::Rope::from_str(&content);
-                        let beancount_data = BeancountData::new(&tree, &rope_content);
-
-                        state.forest.insert(uri.clone(), Arc::new(tree));
-                        state
-                            .beancount_data
-                            .insert(uri.clone(), Arc::new(beancount_data));
-
-                        tracing::debug!("Re-parsed external file: {:?}", uri);
+                if includers.is_empty() || state.include_graph.contains(&uri) {
+                    include_graph::update_includes(state, &uri);
+                } else {
+                    for includer in includers {
+                        include_graph::update_includes(state, &includer);
                     }
                 }
+                tracing::debug!("Re-parsed external file: {:?}", uri);
             }
             lsp_types::FileChangeType::DELETED => {
                 tracing::debug!("External file deleted: {:?}", uri);
 
                 // Remove from all caches
-                state.forest.remove(&uri);
-                state.beancount_data.remove(&uri);
+                state.remove_tree(&uri);
+                state.remove_beancount_data(&uri);
                 state.parsers.remove(&uri);
+                // Re-expand the includes that referred to the file, which also
+                // drops anything only reachable through it
+                let includers: Vec<PathBuf> = state
+                    .include_graph
+                    .included_by(&uri)
+                    .into_iter()
+                    .map(Path::to_path_buf)
+                    .collect();
+                if state.include_graph.remove(&uri).is_some() {
+                    for includer in includers {
+                        include_graph::update_includes(state, &includer);
+                    }
+                    include_graph::prune_unreachable(state);
+                }
             }
             _ => {
                 tracing::debug!("Unknown file change type: {:?}", change.typ);
@@ -323,29 +234,17 @@ pub(crate) fn did_change_watched_files(
 
     // Trigger diagnostics refresh for open documents
     if state.config.journal_root.is_some() {
-        let snapshot = state.snapshot();
-        let task_sender = state.task_sender.clone();
-
         // Find an open document to use for diagnostics URI
         if let Some(open_uri) = state.open_docs.keys().next().cloned() {
-            let url = match url::Url::from_file_path(&open_uri) {
-                Ok(url) => url,
-                Err(_) => {
-                    tracing::warn!("Failed to convert path to URL: {:?}", open_uri);
-                    return Ok(());
-                }
-            };
-            let lsp_uri = match lsp_types::Uri::from_str(url.as_str()) {
+            let lsp_uri = match file_path_to_uri(&open_uri) {
                 Ok(uri) => uri,
-                Err(e) => {
-                    tracing::warn!("Failed to create LSP URI: {}", e);
+                Err(()) => {
+                    tracing::warn!("Failed to convert path to URI: {:?}", open_uri);
                     return Ok(());
                 }
             };
 
-            state.thread_pool.execute(move || {
-                let _result = handle_diagnostics(snapshot, task_sender, lsp_uri);
-            });
+            spawn_diagnostics(state, lsp_uri);
         } else {
             tracing::debug!(
                 "No open documents, skipping diagnostics refresh after external change"
@@ -394,65 +293,31 @@ pub(crate) fn did_change(
 
     tracing::debug!("text_document::did_change - convert edits and apply changes");
 
-    // Calculate tree-sitter edits before modifying the document
-    // This must be done first since edits are based on the old content
-    let ts_edits = params
-        .content_changes
-        .iter()
-        .map(|change| lsp_textdocchange_to_ts_inputedit(&doc.content, change))
-        .collect::<Result<Vec<_>, _>>()?;
+    // Content changes are applied in order and each range refers to the document
+    // as left by the previous change, so every tree-sitter edit has to be computed
+    // against the current content right before its change is applied.
+    let mut ts_edits = Vec::with_capacity(params.content_changes.len());
 
     // Apply changes to document content
-    // We reuse position calculations to avoid redundant UTF-16 conversions
     for change in &params.content_changes {
+        ts_edits.push(lsp_textdocchange_to_ts_inputedit(&doc.content, change)?);
+
         let text = change.text.as_str();
 
-        let range = if let Some(range) = change.range {
-            range
+        // Convert LSP positions (line, column in the negotiated encoding) to rope character indices
+        let (start_char_idx, end_char_idx) = if let Some(range) = change.range {
+            (
+                lsp_position_to_char_idx(&doc.content, range.start)?,
+                lsp_position_to_char_idx(&doc.content, range.end)?,
+            )
         } else {
-            // Full document replacement: range should cover entire current document
-            let end_line = (doc.content.len_lines().saturating_sub(1)) as u32;
-            let end_line_len = if doc.content.len_lines() > 0 {
-                // Get the character length of the last line (excluding newline)
-                let last_line = doc.content.line(end_line as usize);
-                last_line.len_chars().saturating_sub(1).max(0) as u32
-            } else {
-                0
-            };
-            lsp_types::Range {
-                start: lsp_types::Position::new(0, 0),
-                end: lsp_types::Position::new(end_line, end_line_len),
-            }
+            // Full document replacement: range covers the entire current document
+            (0, doc.content.len_chars())
         };
 
-        // Convert LSP positions (line, UTF-16 column) to rope character indices
-        // LSP positions use UTF-16 code units for columns, rope uses UTF-8 characters
-        let start_row_char_idx = doc.content.line_to_char(range.start.line as usize);
-        let end_row_char_idx = doc.content.line_to_char(range.end.line as usize);
-
-        // Convert UTF-16 column offsets to character offsets within the line
-        // CRITICAL: range.start.character is UTF-16 offset *within the line*, not document-wide
-        let start_line_utf16_cu = doc.content.char_to_utf16_cu(start_row_char_idx);
-        let start_utf16_idx = start_line_utf16_cu + range.start.character as usize;
-        // Clamp to document bounds to prevent panic if client sends invalid positions
-        let start_utf16_idx = start_utf16_idx.min(doc.content.len_utf16_cu());
-        let start_col_char_idx = doc.content.utf16_cu_to_char(start_utf16_idx) - start_row_char_idx;
-
-        let end_line_utf16_cu = doc.content.char_to_utf16_cu(end_row_char_idx);
-        let end_utf16_idx = end_line_utf16_cu + range.end.character as usize;
-        // Clamp to document bounds to prevent panic if client sends invalid positions
-        let end_utf16_idx = end_utf16_idx.min(doc.content.len_utf16_cu());
-        let end_col_char_idx = doc.content.utf16_cu_to_char(end_utf16_idx) - end_row_char_idx;
-
-        let start_char_idx = start_row_char_idx + start_col_char_idx;
-        let end_char_idx = end_row_char_idx + end_col_char_idx;
-
         tracing::trace!(
-            "Applying change: range={}:{}-{}:{}, char_idx={}-{}, text_len={}",
-            range.start.line,
-            range.start.character,
-            range.end.line,
-            range.end.character,
+            "Applying change: range={:?}, char_idx={}-{}, text_len={}",
+            change.range,
             start_char_idx,
             end_char_idx,
             text.len()
@@ -465,62 +330,222 @@ pub(crate) fn did_change(
         }
     }
 
-    debug!("text_document::did_change - incremental tree parse");
-    let result = {
-        let parser = match state.parsers.get_mut(&uri) {
-            Some(p) => p,
-            None => {
-                tracing::warn!("Parser not found for document: {:?}", uri);
-                return Ok(());
-            }
-        };
-        let old_tree_arc = match state.forest.get(&uri) {
-            Some(t) => t,
-            None => {
-                tracing::warn!("Tree not found in forest: {:?}", uri);
-                return Ok(());
+    // Edit the tree so that it keeps matching the text until it is reparsed
+    match state.forest.get_mut(&uri) {
+        Some(tree) => {
+            let mut edited = (**tree).clone();
+            for edit in &ts_edits {
+                edited.edit(edit);
             }
-        };
+            *tree = Arc::new(edited);
+        }
+        None => {
+            tracing::warn!("Tree not found in forest: {:?}", uri);
+            return Ok(());
+        }
+    }
 
-        // Avoid cloning the tree when possible - tree-sitter's edit() takes &mut
-        // We clone here because we need to preserve the old tree in the Arc
-        // until we've successfully parsed the new tree
-        let mut old_tree = (**old_tree_arc).clone();
+    // Update document version after successfully applying changes
+    doc.version = new_version;
+    state.doc_versions.set(uri.clone(), new_version);
+
+    let delay = state.config.change_debounce;
+    state
+        .pending_changes
+        .record(uri, params.text_document.uri, Instant::now() + delay);
+    if delay.is_zero() {
+        settle_changes(state, Instant::now());
+    }
 
-        // Apply all edits to the tree to prepare for incremental parsing
-        for edit in &ts_edits {
-            old_tree.edit(edit);
-        }
+    debug!("text_document::did_change - done");
+    Ok(())
+}
+
+/// Reparses the documents changed since they were last parsed. Runs before any
+/// message other than a change is handled, so that providers see current trees.
+pub(crate) fn reparse_changed(state: &mut LspServerState) {
+    for path in state.pending_changes.take_unparsed() {
+        debug!(
+            "text_document::reparse_changed - incremental tree parse of {:?}",
+            path
+        );
+        let (Some(parser), Some(tree), Some(doc)) = (
+            state.parsers.get_mut(&path),
+            state.forest.get(&path),
+            state.open_docs.get(&path),
+        ) else {
+            tracing::warn!("Parser, tree or document not found for {:?}", path);
+            continue;
+        };
 
-        // Parse with incremental tree
         // Note: We could avoid the string allocation by implementing a custom TextProvider
         // that yields rope chunks, but the current tree-sitter bindings make this complex
-        parser.parse(doc.text_string(), Some(&old_tree))
+        if let Some(tree) = parser.parse(doc.text_string(), Some(tree)) {
+            let content = doc.content.clone();
+            state.insert_tree(path.clone(), Arc::new(tree), content);
+            // Lazy extraction: Don't extract BeancountData on every reparse
+            // It will be extracted on-demand when needed (e.g., for completion),
+            // or once the document is idle. Until then the index keeps the
+            // symbols of the previous extraction.
+            state.beancount_data.remove(&path);
+        }
+    }
+}
+
+/// Re-indexes the documents that were idle since their last change and lints them.
+///
+/// bean-check and the external checkers read the files on disk, which lag behind the
+/// unsaved buffer, so they only run when a document is opened or saved.
+pub(crate) fn settle_changes(state: &mut LspServerState, now: Instant) {
+    reparse_changed(state);
+    for (path, uri) in state.pending_changes.take_due(now) {
+        debug!("text_document::settle_changes - {:?}", path);
+        state.ensure_beancount_data(&path);
+        spawn_check(state, uri, CheckScope::Lints);
+    }
+}
+
+/// What a diagnostics run in the diagnostics lane looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CheckScope {
+    /// The checkers on the files as saved, together with the server's own lints
+    Full,
+    /// Only the server's own lints, which see unsaved changes
+    Lints,
+}
+
+/// Checks the journal of `uri` in the diagnostics lane. A check started later for the
+/// same journal supersedes this one, even while it is still queued; lints runs don't.
+fn spawn_diagnostics(state: &mut LspServerState, uri: lsp_types::Uri) {
+    spawn_check(state, uri, CheckScope::Full);
+}
+
+fn spawn_check(state: &mut LspServerState, uri: lsp_types::Uri, scope: CheckScope) {
+    let task_sender = state.task_sender.clone();
+    let journal = uri
+        .to_file_path()
+        .map(|file| state.journal_for(&file).unwrap_or(file))
+        .unwrap_or_else(|_| PathBuf::from(uri.as_str()));
+    let run = match scope {
+        CheckScope::Full => state.check_runs.begin(journal),
+        CheckScope::Lints => state.check_runs.begin_lints(journal),
     };
+    state.spawn(
+        Lane::Diagnostics,
+        Box::new(move |snapshot| {
+            let _result = match scope {
+                CheckScope::Full => handle_diagnostics(snapshot, task_sender, uri, run),
+                CheckScope::Lints => handle_lints(snapshot, task_sender, uri, run),
+            };
+        }),
+    );
+}
+
+/// The server's own diagnostics for the files of `journal`, computed from the trees and
+/// the indexed data, so they reflect unsaved changes.
+struct OwnDiagnostics {
+    /// The files of the journal, which get their diagnostics replaced
+    checked_files: Vec<PathBuf>,
+    /// Lints based on the trees and on posting amounts
+    diagnostics: HashMap<PathBuf, Vec<lsp_types::Diagnostic>>,
+    /// The indexed data of the files small enough to lint
+    lint_data: HashMap<PathBuf, Arc<BeancountData>>,
+}
 
-    debug!("text_document::did_change - save tree");
-    if let Some(tree) = result {
-        let tree_arc = Arc::new(tree);
-        *state
+fn own_diagnostics(
+    snapshot: &LspServerStateSnapshot,
+    journal: &Option<PathBuf>,
+    options: &crate::ledger_options::LedgerOptions,
+) -> OwnDiagnostics {
+    // Files of other workspace folders keep the diagnostics of their own journal
+    let checked_files: Vec<PathBuf> = snapshot
+        .forest
+        .keys()
+        .filter(|file| snapshot.journal_for(file) == *journal)
+        .cloned()
+        .collect();
+    let mut file_diagnostics = HashMap::new();
+    for file in &checked_files {
+        if let Some((tree, text)) = snapshot.tree_and_text(file) {
+            let mut found = diagnostics::missing_includes(&tree, &text, file);
+            found.extend(diagnostics::unbalanced_tags(&tree, &text));
+            found.extend(diagnostics::invalid_dates(&tree, &text));
+            if let Some(horizon_days) = snapshot.config.future_date_days {
+                let today = chrono::Local::now().date_naive();
+                found.extend(diagnostics::future_dates(&tree, &text, today, horizon_days));
+            }
+            file_diagnostics.insert(file.clone(), found);
+        }
+    }
+    // The server's own lints walk every directive; skip them for huge files
+    let mut lint_data = snapshot.beancount_data.clone();
+    lint_data.retain(|file, _| {
+        let lines = snapshot
             .forest
-            .get_mut(&uri)
-            .expect("tree should exist in forest") = tree_arc.clone();
-        // Lazy extraction: Don't extract BeancountData on every keystroke
-        // It will be extracted on-demand when needed (e.g., for completion)
-        state.beancount_data.remove(&uri);
+            .get(file)
+            .map_or(0, |tree| tree.root_node().end_position().row + 1);
+        let enabled = snapshot.config.large_file.lints_enabled(lines);
+        if !enabled {
+            tracing::debug!(
+                "Skipping lints for large file {}: {} lines",
+                file.display(),
+                lines
+            );
+        }
+        enabled
+    });
+    let posting_lints =
+        diagnostics::posting_lints(&lint_data, options, &snapshot.config.posting_lints);
+    for (file, diagnostics) in posting_lints {
+        file_diagnostics
+            .entry(file)
+            .or_default()
+            .extend(diagnostics);
     }
+    OwnDiagnostics {
+        checked_files,
+        diagnostics: file_diagnostics,
+        lint_data,
+    }
+}
 
-    // Update document version after successfully applying changes
-    doc.version = new_version;
-
-    debug!("text_document::did_change - done");
-    Ok(())
+/// Publishes the server's own diagnostics for the journal of `uri`, along with what the
+/// checkers reported when they last ran.
+fn handle_lints(
+    snapshot: LspServerStateSnapshot,
+    sender: Sender<Task>,
+    uri: lsp_types::Uri,
+    run: CheckRun,
+) -> Result<()> {
+    tracing::debug!("text_document::handle_lints");
+    if run.is_superseded() {
+        return Ok(());
+    }
+    let file_path = uri
+        .to_file_path()
+        .map_err(|_| anyhow!("Failed to convert URI to file path: {}", uri.as_str()))?;
+    let journal = snapshot.journal_for(&file_path);
+    let root_journal_path = journal.clone().unwrap_or(file_path);
+
+    let options = snapshot.ledger_options_for(&root_journal_path);
+    let own = own_diagnostics(&snapshot, &journal, &options);
+    let mut diags = run.checker_diagnostics();
+    merge_diagnostics(
+        &mut diags,
+        diagnostics::parsed_data_lints(&own.lint_data, &snapshot.config.diagnostic_flags, &options),
+    );
+    merge_diagnostics(&mut diags, own.diagnostics);
+    if run.is_superseded() {
+        return Ok(());
+    }
+    publish_diagnostics(&sender, &run, &root_journal_path, &own.checked_files, diags)
 }
 
 fn handle_diagnostics(
     snapshot: LspServerStateSnapshot,
     sender: Sender<Task>,
     uri: lsp_types::Uri,
+    run: CheckRun,
 ) -> Result<()> {
     tracing::debug!("text_document::handle_diagnostics");
 
@@ -531,6 +556,12 @@ fn handle_diagnostics(
             return Ok(());
         }
     };
+    if run.is_superseded() {
+        tracing::debug!("Skipping diagnostics superseded by a newer check");
+        return Ok(());
+    }
+    let checker =
+        InterruptibleChecker::new(checker, snapshot.config.bean_check.timeout, run.clone());
 
     tracing::debug!(
         "Using checker: {}, available: {}",
@@ -538,16 +569,22 @@ fn handle_diagnostics(
         checker.is_available()
     );
 
-    let root_journal_path = match snapshot.config.journal_root.clone() {
+    let file_path = uri
+        .to_file_path()
+        .map_err(|_| anyhow!("Failed to convert URI to file path: {}", uri.as_str()))?;
+    let journal = snapshot.journal_for(&file_path);
+    let root_journal_path = match journal.clone() {
         Some(path) => {
-            tracing::debug!("Using configured journal_root: {}", path.display());
+            tracing::debug!("Using journal_root: {}", path.display());
             path
         }
         None => {
             // Fallback to using the current file as the root journal
-            let path = uri
-                .to_file_path()
-                .map_err(|_| anyhow!("Failed to convert URI to file path: {}", uri.as_str()))?;
+            let path = file_path;
+            if is_virtual_document(&path) {
+                tracing::debug!("Skipping bean-check for {}: not a file", uri.as_str());
+                return Ok(());
+            }
             tracing::debug!(
                 "No journal_root configured; using current file as root: {}",
                 path.display()
@@ -573,12 +610,66 @@ fn handle_diagnostics(
         run_id,
     }))?;
 
-    let diags = diagnostics::diagnostics(
-        snapshot.beancount_data,
-        checker.as_ref(),
-        &root_journal_path,
-        &snapshot.config.diagnostic_flags,
+    let options = snapshot.ledger_options_for(&root_journal_path);
+    let own = own_diagnostics(&snapshot, &journal, &options);
+    let started = std::time::Instant::now();
+    let mut checker_diags = diagnostics::checker_diagnostics(&checker, &root_journal_path);
+
+    for external in &snapshot.config.external_checkers {
+        match external.check(&root_journal_path, snapshot.config.bean_check.timeout, &run) {
+            Ok(external_diags) => merge_diagnostics(&mut checker_diags, external_diags),
+            Err(e) => warn!("External checker {} failed: {}", external.name, e),
+        }
+    }
+
+    let interruption = checker.interruption();
+    if interruption == Some(Interruption::Superseded) || run.is_superseded() {
+        tracing::debug!("Dropping diagnostics superseded by a newer check");
+        sender.send(Task::Progress(ProgressMsg::BeanCheck {
+            done: 1,
+            total: 1,
+            checker_name: checker.name().to_string(),
+            run_id,
+        }))?;
+        return Ok(());
+    }
+    // The lints runs until the next check publish these with their fresh lints
+    run.replace_checker_diagnostics(checker_diags.clone());
+    let mut diags = checker_diags;
+    merge_diagnostics(
+        &mut diags,
+        diagnostics::parsed_data_lints(&own.lint_data, &snapshot.config.diagnostic_flags, &options),
     );
+    merge_diagnostics(&mut diags, own.diagnostics);
+
+    if let Some(Interruption::TimedOut(timeout)) = interruption {
+        sender.send(Task::Notify(lsp_server::Notification {
+            method: lsp_types::notification::ShowMessage::METHOD.to_owned(),
+            params: to_json(lsp_types::ShowMessageParams {
+                typ: lsp_types::MessageType::WARNING,
+                message: format!(
+                    "bean-check ({}) did not finish within {}s; showing the language server's own diagnostics only",
+                    checker.name(),
+                    timeout.as_secs()
+                ),
+            })?,
+        }))?;
+    }
+
+    let count = |severity| {
+        diags
+            .values()
+            .flatten()
+            .filter(|d| d.severity == Some(severity))
+            .count()
+    };
+    sender.send(Task::CheckFinished(BeanCheckStatus {
+        checker: checker.name().to_string(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        errors: count(lsp_types::DiagnosticSeverity::ERROR),
+        warnings: count(lsp_types::DiagnosticSeverity::WARNING),
+        finished_at: crate::lsp_ext::now_millis(),
+    }))?;
 
     sender.send(Task::Progress(ProgressMsg::BeanCheck {
         done: 1,
@@ -587,26 +678,51 @@ fn handle_diagnostics(
         run_id,
     }))?;
 
+    publish_diagnostics(&sender, &run, &root_journal_path, &own.checked_files, diags)
+}
+
+fn merge_diagnostics(
+    into: &mut HashMap<PathBuf, Vec<lsp_types::Diagnostic>>,
+    diags: HashMap<PathBuf, Vec<lsp_types::Diagnostic>>,
+) {
+    for (file, diagnostics) in diags {
+        into.entry(file).or_default().extend(diagnostics);
+    }
+}
+
+/// Publishes `diags` for the files of a journal, clearing the files the previous check
+/// of the journal reported on but this one did not.
+fn publish_diagnostics(
+    sender: &Sender<Task>,
+    run: &CheckRun,
+    root_journal_path: &Path,
+    checked_files: &[PathBuf],
+    diags: HashMap<PathBuf, Vec<lsp_types::Diagnostic>>,
+) -> Result<()> {
+    // Relative names in checker output are relative to the journal, not to the server
+    let journal_dir = root_journal_path.parent().unwrap_or(Path::new(""));
     let mut normalized_diags: HashMap<PathBuf, Vec<lsp_types::Diagnostic>> = HashMap::new();
     for (path, diagnostics) in diags {
+        let path = match journal_dir.join(&path) {
+            joined if path.is_relative() && joined.exists() => joined,
+            _ => path,
+        };
         let key = normalize_path_for_diagnostics(&path);
         normalized_diags.entry(key).or_default().extend(diagnostics);
     }
 
-    for file in snapshot.forest.keys() {
+    let mut published = HashSet::new();
+    for file in checked_files {
         let lookup = normalize_path_for_diagnostics(file);
         let diagnostics = normalized_diags.remove(&lookup).unwrap_or_default();
+        published.insert(lookup);
         sender
             .send(Task::Notify(lsp_server::Notification {
                 method: lsp_types::notification::PublishDiagnostics::METHOD.to_owned(),
                 params: to_json(lsp_types::PublishDiagnosticsParams {
-                    uri: {
-                        let url = url::Url::from_file_path(file).map_err(|()| {
-                            anyhow!("Failed to convert file path to URI: {}", file.display())
-                        })?;
-                        lsp_types::Uri::from_str(url.as_str())
-                            .with_context(|| format!("Failed to parse URL as LSP URI: {}", url))?
-                    },
+                    uri: file_path_to_uri(file).map_err(|()| {
+                        anyhow!("Failed to convert file path to URI: {}", file.display())
+                    })?,
                     diagnostics,
                     version: None,
                 })
@@ -617,18 +733,11 @@ fn handle_diagnostics(
 
     // ignore the broken file paths
     for (file, diagnostics) in normalized_diags {
-        let url = match url::Url::from_file_path(&file) {
-            Ok(url) => url,
-            Err(_) => {
-                warn!("Failed to convert file path to URI: {}", file.display());
-                continue;
-            }
-        };
-
-        let uri = match lsp_types::Uri::from_str(url.as_str()) {
+        published.insert(file.clone());
+        let uri = match file_path_to_uri(&file) {
             Ok(uri) => uri,
-            Err(e) => {
-                warn!("Failed to parse URL as LSP URI ({}): {}", url, e);
+            Err(()) => {
+                warn!("Failed to convert file path to URI: {}", file.display());
                 continue;
             }
         };
@@ -657,6 +766,21 @@ fn handle_diagnostics(
             return Err(e.into());
         }
     }
+
+    // Clear files the previous check of this journal reported on but this one did not
+    for file in run.replace_published(published) {
+        let Ok(uri) = file_path_to_uri(&file) else {
+            continue;
+        };
+        sender.send(Task::Notify(lsp_server::Notification {
+            method: lsp_types::notification::PublishDiagnostics::METHOD.to_owned(),
+            params: to_json(lsp_types::PublishDiagnosticsParams {
+                uri,
+                diagnostics: vec![],
+                version: None,
+            })?,
+        }))?;
+    }
     Ok(())
 }
 
@@ -942,6 +1066,176 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_untitled_document_is_kept_in_open_docs() {
+        use super::{did_close, did_open};
+        use crate::config::Config;
+        use crate::server::LspServerState;
+        use std::path::PathBuf;
+        use std::str::FromStr;
+
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(PathBuf::from("/test")));
+        let uri = lsp_types::Uri::from_str("untitled:Untitled-1").unwrap();
+
+        did_open(
+            &mut state,
+            lsp_types::DidOpenTextDocumentParams {
+                text_document: lsp_types::TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "beancount".to_string(),
+                    version: 1,
+                    text: "2024-01-01 open Assets:Scratch\n".to_string(),
+                },
+            },
+        )
+        .unwrap();
+
+        let snapshot = state.snapshot();
+        let (_, doc) = snapshot.tree_and_document_for_uri(&uri).unwrap();
+        assert_eq!(doc.version, 1);
+        assert_eq!(
+            state.index.account_definitions("Assets:Scratch")[0].0,
+            PathBuf::from("untitled:Untitled-1")
+        );
+
+        did_close(
+            &mut state,
+            lsp_types::DidCloseTextDocumentParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+            },
+        )
+        .unwrap();
+        assert!(state.open_docs.is_empty());
+        assert!(state.forest.is_empty());
+        assert!(state.sources.is_empty());
+    }
+
+    #[test]
+    fn test_did_change_applies_changes_sequentially() {
+        use super::did_change;
+        use crate::config::Config;
+        use crate::server::LspServerState;
+        use std::path::PathBuf;
+        use std::str::FromStr;
+        use std::sync::Arc;
+        use tree_sitter_beancount::tree_sitter;
+
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(PathBuf::from("/test")));
+        let path = PathBuf::from("/test/main.beancount");
+        let content = "2024-01-01 open Assets:Cash\n2024-01-02 open Expenses:Food\n";
+
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let tree = parser.parse(content, None).unwrap();
+        state.forest.insert(path.clone(), Arc::new(tree));
+        state.parsers.insert(path.clone(), parser);
+        state
+            .open_docs
+            .insert(path.clone(), create_test_document(content));
+
+        let change = |line, start, end, text: &str| TextDocumentContentChangeEvent {
+            range: Some(Range {
+                start: Position::new(line, start),
+                end: Position::new(line, end),
+            }),
+            range_length: None,
+            text: text.to_string(),
+        };
+        // The second range is only valid after the first change inserted a line
+        let params = lsp_types::DidChangeTextDocumentParams {
+            text_document: lsp_types::VersionedTextDocumentIdentifier {
+                uri: lsp_types::Uri::from_str("file:///test/main.beancount").unwrap(),
+                version: 1,
+            },
+            content_changes: vec![
+                change(1, 0, 0, "2024-01-01 open Assets:Bank\n"),
+                change(2, 29, 29, " USD"),
+            ],
+        };
+        did_change(&mut state, params).unwrap();
+
+        let expected = "2024-01-01 open Assets:Cash\n2024-01-01 open Assets:Bank\n2024-01-02 open Expenses:Food USD\n";
+        assert_eq!(state.open_docs[&path].text_string(), expected);
+        super::reparse_changed(&mut state);
+
+        let mut fresh_parser = tree_sitter::Parser::new();
+        fresh_parser
+            .set_language(&tree_sitter_beancount::language())
+            .unwrap();
+        let fresh = fresh_parser.parse(expected, None).unwrap();
+        assert_eq!(
+            state.forest[&path].root_node().to_sexp(),
+            fresh.root_node().to_sexp()
+        );
+        assert!(!state.forest[&path].root_node().has_error());
+        assert_eq!(state.sources[&path].to_string(), expected);
+    }
+
+    #[test]
+    fn test_did_change_waits_until_idle_to_index() {
+        use super::{did_change, did_open, settle_changes};
+        use crate::config::Config;
+        use crate::server::LspServerState;
+        use std::path::PathBuf;
+        use std::str::FromStr;
+        use std::time::{Duration, Instant};
+
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(PathBuf::from("/test")));
+        let uri = lsp_types::Uri::from_str("untitled:Untitled-1").unwrap();
+        let path = PathBuf::from("untitled:Untitled-1");
+        did_open(
+            &mut state,
+            lsp_types::DidOpenTextDocumentParams {
+                text_document: lsp_types::TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "beancount".to_string(),
+                    version: 1,
+                    text: "2024-01-01 open Assets:Cash\n".to_string(),
+                },
+            },
+        )
+        .unwrap();
+
+        for (version, account) in [(2, "Assets:Ba"), (3, "Assets:Bank")] {
+            did_change(
+                &mut state,
+                lsp_types::DidChangeTextDocumentParams {
+                    text_document: lsp_types::VersionedTextDocumentIdentifier {
+                        uri: uri.clone(),
+                        version,
+                    },
+                    content_changes: vec![TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: format!("2024-01-01 open {account}\n"),
+                    }],
+                },
+            )
+            .unwrap();
+        }
+        let deadline = state.pending_changes.next_deadline().unwrap();
+        assert!(deadline > Instant::now());
+
+        settle_changes(&mut state, deadline - Duration::from_millis(1));
+        assert!(!state.beancount_data.contains_key(&path));
+        assert_eq!(
+            state.sources[&path].to_string(),
+            "2024-01-01 open Assets:Bank\n"
+        );
+
+        settle_changes(&mut state, deadline);
+        assert_eq!(
+            *state.beancount_data[&path].get_accounts(),
+            vec!["Assets:Bank".to_string()]
+        );
+        assert_eq!(state.pending_changes.next_deadline(), None);
+    }
+
     #[test]
     fn test_handle_diagnostics_without_journal_root() {
         // Regression test for issue #822
@@ -951,7 +1245,6 @@ mod tests {
         use crate::config::Config;
         use crate::server::LspServerStateSnapshot;
         use crossbeam_channel;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::str::FromStr;
         use std::sync::Arc;
@@ -973,19 +1266,19 @@ mod tests {
         let checker = SystemCallChecker::new(PathBuf::from("/bin/true"));
 
         // Create snapshot
-        let snapshot = LspServerStateSnapshot {
-            beancount_data: HashMap::new(),
-            config,
-            forest: HashMap::new(),
-            open_docs: HashMap::new(),
-            checker: Some(Arc::new(checker)),
-        };
+        let mut snapshot = LspServerStateSnapshot::for_test(config, &[]);
+        snapshot.checker = Some(Arc::new(checker));
 
         // Create channel for task communication using crossbeam_channel
         let (sender, receiver) = crossbeam_channel::unbounded();
 
         // Call handle_diagnostics - this should NOT skip diagnostics
-        let result = handle_diagnostics(snapshot, sender, uri.clone());
+        let result = handle_diagnostics(
+            snapshot,
+            sender,
+            uri.clone(),
+            Arc::new(crate::checkers::CheckRuns::default()).begin(PathBuf::new()),
+        );
 
         // The function should succeed (not return error about missing journal_root)
         assert!(
@@ -1015,7 +1308,6 @@ mod tests {
         use crate::config::Config;
         use crate::server::LspServerStateSnapshot;
         use crossbeam_channel;
-        use std::collections::HashMap;
         use std::path::PathBuf;
         use std::str::FromStr;
         use std::sync::Arc;
@@ -1039,19 +1331,19 @@ mod tests {
         let checker = SystemCallChecker::new(PathBuf::from("/bin/true"));
 
         // Create snapshot
-        let snapshot = LspServerStateSnapshot {
-            beancount_data: HashMap::new(),
-            config,
-            forest: HashMap::new(),
-            open_docs: HashMap::new(),
-            checker: Some(Arc::new(checker)),
-        };
+        let mut snapshot = LspServerStateSnapshot::for_test(config, &[]);
+        snapshot.checker = Some(Arc::new(checker));
 
         // Create channel for task communication using crossbeam_channel
         let (sender, receiver) = crossbeam_channel::unbounded();
 
         // Call handle_diagnostics with a different file than journal_root
-        let result = handle_diagnostics(snapshot, sender, uri.clone());
+        let result = handle_diagnostics(
+            snapshot,
+            sender,
+            uri.clone(),
+            Arc::new(crate::checkers::CheckRuns::default()).begin(PathBuf::new()),
+        );
 
         // Should succeed
         assert!(
@@ -1071,6 +1363,134 @@ mod tests {
         );
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_handle_diagnostics_publishes_to_included_files_and_clears_them() {
+        use super::handle_diagnostics;
+        use crate::checkers::{CheckRuns, SystemCallChecker};
+        use crate::config::Config;
+        use crate::server::{LspServerStateSnapshot, Task};
+        use crate::utils::{ToFilePath, file_path_to_uri};
+        use std::path::PathBuf;
+        use std::sync::Arc;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let journal = root.join("main.beancount");
+        let included = root.join("sub").join("2024.beancount");
+        std::fs::create_dir(root.join("sub")).unwrap();
+        std::fs::write(&journal, "include \"sub/2024.beancount\"\n").unwrap();
+        std::fs::write(&included, "2024-01-01 open Assets:Cash\n").unwrap();
+
+        let runs = Arc::new(CheckRuns::default());
+        // Reports an error in the included file by its name relative to the journal
+        let check = |script: &str| {
+            let mut config = Config::new(root.clone());
+            config.journal_root = Some(journal.clone());
+            let checker = SystemCallChecker::new(PathBuf::from("sh"))
+                .with_args(vec!["-c".to_string(), script.to_string()]);
+            let mut snapshot = LspServerStateSnapshot::for_test(config, &[]);
+            snapshot.checker = Some(Arc::new(checker));
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let uri = file_path_to_uri(&journal).unwrap();
+            handle_diagnostics(snapshot, sender, uri, runs.begin(journal.clone())).unwrap();
+            receiver
+                .try_iter()
+                .filter_map(|task| match task {
+                    Task::Notify(n) if n.method == "textDocument/publishDiagnostics" => {
+                        let params: lsp_types::PublishDiagnosticsParams =
+                            serde_json::from_value(n.params).unwrap();
+                        Some((params.uri.to_file_path().unwrap(), params.diagnostics.len()))
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let published = check(r#"echo "sub/2024.beancount:1: Invalid" >&2; exit 1"#);
+        assert_eq!(published, [(included.clone(), 1)]);
+
+        let published = check("exit 0");
+        assert_eq!(published, [(included, 0)]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_handle_lints_skips_the_checker_and_keeps_its_diagnostics() {
+        use super::{handle_diagnostics, handle_lints};
+        use crate::checkers::{CheckRuns, SystemCallChecker};
+        use crate::config::Config;
+        use crate::server::{LspServerStateSnapshot, Task};
+        use crate::utils::file_path_to_uri;
+        use std::path::PathBuf;
+        use std::sync::Arc;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let journal = root.join("main.beancount");
+        std::fs::write(&journal, "2024-02-01 open Assets:Cash\n").unwrap();
+
+        let runs = Arc::new(CheckRuns::default());
+        let uri = file_path_to_uri(&journal).unwrap();
+        let snapshot = |text: &str, message: &str| {
+            let mut config = Config::new(root.clone());
+            config.journal_root = Some(journal.clone());
+            let checker = SystemCallChecker::new(PathBuf::from("sh")).with_args(vec![
+                "-c".to_string(),
+                format!("echo 'main.beancount:1: {message}' >&2; exit 1"),
+            ]);
+            let mut snapshot = LspServerStateSnapshot::for_test(config, &[(&journal, text)]);
+            snapshot.checker = Some(Arc::new(checker));
+            snapshot
+        };
+        let messages = |receiver: crossbeam_channel::Receiver<Task>| {
+            receiver
+                .try_iter()
+                .filter_map(|task| match task {
+                    Task::Notify(n) if n.method == "textDocument/publishDiagnostics" => {
+                        let params: lsp_types::PublishDiagnosticsParams =
+                            serde_json::from_value(n.params).unwrap();
+                        Some(params.diagnostics)
+                    }
+                    _ => None,
+                })
+                .flatten()
+                .map(|diagnostic| diagnostic.message)
+                .collect::<Vec<String>>()
+        };
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let check = runs.begin(journal.clone());
+        handle_diagnostics(
+            snapshot("2024-02-01 open Assets:Cash\n", "From disk"),
+            sender,
+            uri.clone(),
+            check,
+        )
+        .unwrap();
+        assert_eq!(messages(receiver), ["From disk"]);
+
+        // The unsaved buffer has an invalid date the saved file does not
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let lints = runs.begin_lints(journal.clone());
+        handle_lints(
+            snapshot("2024-02-30 open Assets:Cash\n", "Checker ran again"),
+            sender,
+            uri,
+            lints,
+        )
+        .unwrap();
+        let mut messages = messages(receiver);
+        messages.sort();
+        assert_eq!(
+            messages,
+            [
+                "From disk",
+                "Invalid date 2024-02-30: February 2024 has 29 days"
+            ]
+        );
+    }
+
     #[test]
     fn test_handle_diagnostics_without_checker() {
         // Verify that diagnostics gracefully handle missing checker
@@ -1078,7 +1498,6 @@ mod tests {
         use crate::config::Config;
         use crate::server::LspServerStateSnapshot;
         use crossbeam_channel;
-        use std::collections::HashMap;
         use std::str::FromStr;
 
         let temp_dir = tempfile::tempdir().unwrap();
@@ -1091,18 +1510,18 @@ mod tests {
         let config = Config::new(temp_dir.path().to_path_buf());
 
         // Create snapshot WITHOUT checker
-        let snapshot = LspServerStateSnapshot {
-            beancount_data: HashMap::new(),
-            config,
-            forest: HashMap::new(),
-            open_docs: HashMap::new(),
-            checker: None, // No checker available
-        };
+        let snapshot = LspServerStateSnapshot::for_test(config, &[]);
 
         let (sender, _receiver) = crossbeam_channel::unbounded();
 
         // Should succeed but skip diagnostics
-        let result = handle_diagnostics(snapshot, sender, uri);
+        let result = handle_diagnostics(
+            snapshot,
+            sender,
+            uri,
+            std::sync::Arc::new(crate::checkers::CheckRuns::default())
+                .begin(std::path::PathBuf::new()),
+        );
 
         assert!(result.is_ok(), "Should handle missing checker gracefully");
     }
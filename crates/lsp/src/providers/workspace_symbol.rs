@@ -1,12 +1,11 @@
 use crate::server::LspServerStateSnapshot;
-use crate::treesitter_utils::text_for_tree_sitter_node;
+use crate::treesitter_utils::{text_for_tree_sitter_node, tree_sitter_node_to_lsp_range};
+use crate::utils::file_path_to_uri;
 use anyhow::Result;
 use lsp_types::{Location, SymbolInformation, SymbolKind, WorkspaceSymbolParams};
 use ropey::Rope;
-use std::str::FromStr;
 use tree_sitter_beancount::tree_sitter::Node;
 use tree_sitter_beancount::tree_sitter::StreamingIterator;
-use url::Url;
 
 /// Provider function for `workspace/symbol`.
 pub(crate) fn workspace_symbols(
@@ -26,18 +25,10 @@ pub(crate) fn workspace_symbols(
             }
         };
 
-        let url = match Url::from_file_path(path) {
-            Ok(url) => url,
-            Err(_) => {
-                tracing::warn!("Failed to convert path to URL: {:?}", path);
-                continue;
-            }
-        };
-
-        let uri = match lsp_types::Uri::from_str(url.as_ref()) {
+        let uri = match file_path_to_uri(path) {
             Ok(uri) => uri,
-            Err(_) => {
-                tracing::warn!("Failed to convert URL to URI: {:?}", url);
+            Err(()) => {
+                tracing::warn!("Failed to convert path to URI: {:?}", path);
                 continue;
             }
         };
@@ -132,7 +123,7 @@ fn extract_account_symbol(
             kind: SymbolKind::NAMESPACE,
             location: Location {
                 uri: uri.clone(),
-                range: node_to_range(node),
+                range: tree_sitter_node_to_lsp_range(content, node),
             },
             container_name: Some(uri.path().to_string()),
             deprecated: None,
@@ -194,7 +185,7 @@ fn extract_transaction_symbol(
             kind: SymbolKind::EVENT,
             location: Location {
                 uri: uri.clone(),
-                range: node_to_range(node),
+                range: tree_sitter_node_to_lsp_range(content, node),
             },
             container_name: Some(uri.path().to_string()),
             deprecated: None,
@@ -215,19 +206,11 @@ fn extract_tags_and_links_query(
 ) {
     use tree_sitter_beancount::tree_sitter;
 
-    // Create a simple query to find all tags and links
-    let query = tree_sitter::Query::new(
-        &tree_sitter_beancount::language(),
-        r#"
-            (tag) @tag
-            (link) @link
-        "#,
-    )
-    .expect("Failed to compile tag/link query");
+    let query = crate::queries::get_tag_link_query();
 
     let content_bytes = content.to_string().into_bytes();
     let mut cursor_qry = tree_sitter::QueryCursor::new();
-    let mut matches = cursor_qry.matches(&query, tree.root_node(), content_bytes.as_slice());
+    let mut matches = cursor_qry.matches(query, tree.root_node(), content_bytes.as_slice());
 
     let tag_idx = query
         .capture_index_for_name("tag")
@@ -249,7 +232,7 @@ fn extract_tags_and_links_query(
                         kind: SymbolKind::STRING,
                         location: Location {
                             uri: uri.clone(),
-                            range: node_to_range(&capture.node),
+                            range: tree_sitter_node_to_lsp_range(content, &capture.node),
                         },
                         container_name: Some(uri.path().to_string()),
                         deprecated: None,
@@ -265,7 +248,7 @@ fn extract_tags_and_links_query(
                         kind: SymbolKind::KEY,
                         location: Location {
                             uri: uri.clone(),
-                            range: node_to_range(&capture.node),
+                            range: tree_sitter_node_to_lsp_range(content, &capture.node),
                         },
                         container_name: Some(uri.path().to_string()),
                         deprecated: None,
@@ -301,7 +284,7 @@ fn extract_commodity_symbol(
             kind: SymbolKind::CLASS,
             location: Location {
                 uri: uri.clone(),
-                range: node_to_range(node),
+                range: tree_sitter_node_to_lsp_range(content, node),
             },
             container_name: Some(uri.path().to_string()),
             deprecated: None,
@@ -350,7 +333,7 @@ fn extract_price_symbol(
             kind: SymbolKind::NUMBER,
             location: Location {
                 uri: uri.clone(),
-                range: node_to_range(node),
+                range: tree_sitter_node_to_lsp_range(content, node),
             },
             container_name: Some(uri.path().to_string()),
             deprecated: None,
@@ -361,29 +344,10 @@ fn extract_price_symbol(
     }
 }
 
-/// Convert a tree-sitter node to an LSP Range.
-fn node_to_range(node: &Node) -> lsp_types::Range {
-    lsp_types::Range {
-        start: lsp_types::Position {
-            line: node.start_position().row as u32,
-            character: node.start_position().column as u32,
-        },
-        end: lsp_types::Position {
-            line: node.end_position().row as u32,
-            character: node.end_position().column as u32,
-        },
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::beancount_data::BeancountData;
     use crate::config::Config;
-    use crate::document::Document;
-    use std::collections::HashMap;
-    use std::sync::Arc;
-    use tree_sitter_beancount::tree_sitter;
 
     struct TestState {
         snapshot: LspServerStateSnapshot,
@@ -392,40 +356,10 @@ mod tests {
     impl TestState {
         fn new(content: &str) -> anyhow::Result<Self> {
             let path = std::env::current_dir()?.join("test.beancount");
-            let rope_content = ropey::Rope::from_str(content);
-
-            let mut parser = tree_sitter::Parser::new();
-            parser.set_language(&tree_sitter_beancount::language())?;
-            let tree = parser.parse(content, None).unwrap();
-
-            let mut forest = HashMap::new();
-            forest.insert(path.clone(), Arc::new(tree.clone()));
-
-            let mut open_docs = HashMap::new();
-            open_docs.insert(
-                path.clone(),
-                Document {
-                    content: rope_content.clone(),
-                    version: 0,
-                },
-            );
-
-            let mut beancount_data = HashMap::new();
-            beancount_data.insert(
-                path.clone(),
-                Arc::new(BeancountData::new(&tree, &rope_content)),
-            );
-
             let config = Config::new(path.clone());
 
             Ok(Self {
-                snapshot: LspServerStateSnapshot {
-                    forest,
-                    open_docs,
-                    beancount_data,
-                    config,
-                    checker: None,
-                },
+                snapshot: LspServerStateSnapshot::for_test(config, &[(&path, content)]),
             })
         }
     }
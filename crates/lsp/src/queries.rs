@@ -0,0 +1,192 @@
+//! Tree-sitter queries shared by the providers.
+//!
+//! Compiling a query walks the whole grammar, so every query is compiled once on
+//! first use and reused by all requests and threads.
+
+use std::sync::OnceLock;
+use tree_sitter_beancount::tree_sitter;
+
+static UNIFIED_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static CURRENCY_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static NOTE_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static OPTION_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static INCLUDE_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static TAG_LINK_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static TRANSACTION_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static DATE_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static METADATA_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static COMMENT_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+static FORMAT_QUERY: OnceLock<tree_sitter::Query> = OnceLock::new();
+
+fn compile(query_string: &str, name: &str) -> tree_sitter::Query {
+    tree_sitter::Query::new(&tree_sitter_beancount::language(), query_string)
+        .unwrap_or_else(|e| panic!("Failed to compile {name} query: {e}"))
+}
+
+/// Get or compile the unified query (tags, links, flags, accounts, transactions)
+pub(crate) fn get_unified_query() -> &'static tree_sitter::Query {
+    UNIFIED_QUERY.get_or_init(|| {
+        compile(
+            r#"
+            (tag) @tag
+            (link) @link
+            (flag) @flag
+            (open account: (account) @account)
+            (account) @reference
+            (transaction) @transaction
+            "#,
+            "unified",
+        )
+    })
+}
+
+/// Get or compile the currency query (open, commodity, all currencies)
+pub(crate) fn get_currency_query() -> &'static tree_sitter::Query {
+    CURRENCY_QUERY.get_or_init(|| {
+        compile(
+            r#"
+            (open (currency) @currency)
+            (commodity (currency) @currency)
+            (currency) @currency
+            "#,
+            "currency",
+        )
+    })
+}
+
+/// Get or compile the note query (note directives with account and string)
+pub(crate) fn get_note_query() -> &'static tree_sitter::Query {
+    NOTE_QUERY.get_or_init(|| {
+        compile(
+            r#"
+            (note account: (account) @account (string) @note)
+            (note (account) @account (string) @note)
+            "#,
+            "note",
+        )
+    })
+}
+
+/// Get or compile the option query (option directives with key and value, and
+/// custom directives that may be Fava's `"fava-option"`)
+pub(crate) fn get_option_query() -> &'static tree_sitter::Query {
+    OPTION_QUERY.get_or_init(|| {
+        compile(
+            r#"
+            (option key: (string) @key value: (string) @value)
+            (custom
+              name: (string) @custom
+              .
+              (custom_value (string) @key)
+              .
+              (custom_value (string) @value))
+            "#,
+            "option",
+        )
+    })
+}
+
+/// Get or compile the include query (the file name string of include directives)
+pub(crate) fn get_include_query() -> &'static tree_sitter::Query {
+    INCLUDE_QUERY.get_or_init(|| compile("(include (string) @string)", "include"))
+}
+
+/// Get or compile the tag and link query
+pub(crate) fn get_tag_link_query() -> &'static tree_sitter::Query {
+    TAG_LINK_QUERY.get_or_init(|| compile("(tag) @tag\n(link) @link", "tag/link"))
+}
+
+/// Get or compile the transaction query
+pub(crate) fn get_transaction_query() -> &'static tree_sitter::Query {
+    TRANSACTION_QUERY.get_or_init(|| compile("(transaction) @transaction", "transaction"))
+}
+
+/// Get or compile the date query
+pub(crate) fn get_date_query() -> &'static tree_sitter::Query {
+    DATE_QUERY.get_or_init(|| compile("(date) @date", "date"))
+}
+
+/// Get or compile the metadata key query
+pub(crate) fn get_metadata_query() -> &'static tree_sitter::Query {
+    METADATA_QUERY.get_or_init(|| compile("(key_value (key) @key)", "metadata"))
+}
+
+/// Get or compile the posting comment query
+pub(crate) fn get_comment_query() -> &'static tree_sitter::Query {
+    COMMENT_QUERY.get_or_init(|| compile("(posting comment: (comment) @comment)", "comment"))
+}
+
+/// Get or compile the query for lines aligned by bean-format
+pub(crate) fn get_format_query() -> &'static tree_sitter::Query {
+    FORMAT_QUERY.get_or_init(|| {
+        compile(
+            r#"
+            ( posting
+                (account) @prefix
+                amount: (incomplete_amount
+                    [
+                        (number)
+                        (unary_number_expr)
+                        (binary_number_expr)
+                    ] @number
+                )?
+            )
+            ( balance
+                (account) @prefix
+                (amount_tolerance
+                    ([
+                        (number)
+                        (unary_number_expr)
+                        (binary_number_expr)
+                    ] @number)
+                )
+            )
+            ( price
+                currency: (_) @prefix
+                amount: (amount
+                    ([
+                        (number)
+                        (unary_number_expr)
+                        (binary_number_expr)
+                    ] @number)
+                )
+            )
+            ( open
+                (account) @prefix
+                (currency) @number
+            )
+            "#,
+            "formatting",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_compiled_once() {
+        assert!(std::ptr::eq(get_unified_query(), get_unified_query()));
+        assert!(std::ptr::eq(get_currency_query(), get_currency_query()));
+        assert!(std::ptr::eq(
+            get_transaction_query(),
+            get_transaction_query()
+        ));
+    }
+
+    #[test]
+    fn test_all_queries_compile() {
+        get_unified_query();
+        get_currency_query();
+        get_note_query();
+        get_option_query();
+        get_include_query();
+        get_tag_link_query();
+        get_transaction_query();
+        get_date_query();
+        get_metadata_query();
+        get_comment_query();
+        get_format_query();
+    }
+}
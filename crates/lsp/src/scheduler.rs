@@ -0,0 +1,163 @@
+//! Scheduling of the work done off the main loop.
+//!
+//! Notifications are handled on the main loop as they arrive, ahead of everything
+//! else. All other work is queued in a lane and started once a worker is free, the
+//! most important lane first. Each job gets a snapshot of the state taken right
+//! before it starts, so work that waited in a queue sees the changes made meanwhile.
+//! Indexing and diagnostics never occupy every worker, keeping one free for requests.
+
+use crate::server::LspServerStateSnapshot;
+use crossbeam_channel::{Receiver, Sender};
+use std::collections::VecDeque;
+
+/// The queues of the scheduler, from the most to the least important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Lane {
+    /// Requests the user is waiting on
+    Interactive,
+    /// Parsing the journal and its includes
+    Indexing,
+    /// bean-check and external checkers
+    Diagnostics,
+}
+
+impl Lane {
+    const ALL: [Lane; 3] = [Lane::Interactive, Lane::Indexing, Lane::Diagnostics];
+
+    fn is_background(self) -> bool {
+        self != Lane::Interactive
+    }
+}
+
+pub(crate) type Job = Box<dyn FnOnce(LspServerStateSnapshot) + Send + 'static>;
+
+pub(crate) struct Scheduler {
+    queues: [VecDeque<Job>; 3],
+    running: [usize; 3],
+    workers: usize,
+    pool: threadpool::ThreadPool,
+    finished_sender: Sender<Lane>,
+    // Receives the lane of every job that finished, to start the next one
+    pub finished_receiver: Receiver<Lane>,
+}
+
+/// Reports the job as finished when dropped, even if it panicked.
+struct Finished(Sender<Lane>, Lane);
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        let _ = self.0.send(self.1);
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        let workers = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .max(2);
+        Self::new(workers)
+    }
+}
+
+impl Scheduler {
+    pub fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        let (finished_sender, finished_receiver) = crossbeam_channel::unbounded();
+        Self {
+            queues: Default::default(),
+            running: [0; 3],
+            workers,
+            pool: threadpool::ThreadPool::new(workers),
+            finished_sender,
+            finished_receiver,
+        }
+    }
+
+    /// Queues `job` behind the other jobs of its lane.
+    pub fn push(&mut self, lane: Lane, job: Job) {
+        self.queues[lane as usize].push_back(job);
+    }
+
+    /// Takes the next job a free worker may run, if any.
+    pub fn next_job(&mut self) -> Option<(Lane, Job)> {
+        let running: usize = self.running.iter().sum();
+        if running >= self.workers {
+            return None;
+        }
+        let background: usize = Lane::ALL
+            .iter()
+            .filter(|lane| lane.is_background())
+            .map(|lane| self.running[*lane as usize])
+            .sum();
+        let background_workers = (self.workers - 1).max(1);
+
+        Lane::ALL
+            .into_iter()
+            .filter(|lane| !lane.is_background() || background < background_workers)
+            .find_map(|lane| Some((lane, self.queues[lane as usize].pop_front()?)))
+    }
+
+    /// Runs `job` of `lane` on a worker.
+    pub fn start(&mut self, lane: Lane, job: Job, snapshot: LspServerStateSnapshot) {
+        self.running[lane as usize] += 1;
+        let finished = Finished(self.finished_sender.clone(), lane);
+        self.pool.execute(move || {
+            let _finished = finished;
+            job(snapshot);
+        });
+    }
+
+    /// Frees the worker of a job of `lane` that finished.
+    pub fn finished(&mut self, lane: Lane) {
+        let running = &mut self.running[lane as usize];
+        *running = running.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job() -> Job {
+        Box::new(|_| {})
+    }
+
+    #[test]
+    fn test_next_job_prefers_interactive_lane() {
+        let mut scheduler = Scheduler::new(4);
+        scheduler.push(Lane::Diagnostics, job());
+        scheduler.push(Lane::Indexing, job());
+        scheduler.push(Lane::Interactive, job());
+
+        let lanes: Vec<Lane> = std::iter::from_fn(|| scheduler.next_job())
+            .map(|(lane, _)| lane)
+            .collect();
+        assert_eq!(
+            lanes,
+            vec![Lane::Interactive, Lane::Indexing, Lane::Diagnostics]
+        );
+    }
+
+    #[test]
+    fn test_background_work_leaves_a_worker_for_requests() {
+        let mut scheduler = Scheduler::new(2);
+        scheduler.running[Lane::Indexing as usize] = 1;
+        scheduler.push(Lane::Diagnostics, job());
+        assert!(scheduler.next_job().is_none());
+
+        scheduler.push(Lane::Interactive, job());
+        assert_eq!(
+            scheduler.next_job().map(|(lane, _)| lane),
+            Some(Lane::Interactive)
+        );
+        scheduler.running[Lane::Interactive as usize] = 1;
+
+        scheduler.finished(Lane::Indexing);
+        assert_eq!(
+            scheduler.next_job().map(|(lane, _)| lane),
+            Some(Lane::Diagnostics)
+        );
+        assert!(scheduler.next_job().is_none());
+    }
+}
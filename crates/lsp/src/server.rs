@@ -1,25 +1,42 @@
 use crate::beancount_data::BeancountData;
 use crate::checkers::BeancountChecker;
+use crate::checkers::CheckRuns;
 use crate::checkers::create_checker;
 use crate::config::Config;
 use crate::dispatcher::NotificationDispatcher;
 use crate::dispatcher::RequestRouter;
-use crate::document::Document;
+use crate::document::{Document, DocumentVersions};
 use crate::forest;
 use crate::handlers;
+use crate::include_graph::{self, FileIncludes, IncludeGraph};
+use crate::index::WorkspaceIndex;
+use crate::ledger::{Ledger, LedgerCache};
+use crate::ledger_options::LedgerOptions;
+use crate::logging;
+use crate::lsp_ext::{self, BeanCheckStatus, IndexStatus};
+use crate::pending_changes::PendingChanges;
 use crate::progress::Progress;
+use crate::scheduler::{Job, Lane, Scheduler};
 use crate::utils::ToFilePath;
 use anyhow::{Context, Result};
 use crossbeam_channel::{Receiver, Sender};
 use lsp_types::notification::Notification;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 use tree_sitter_beancount::tree_sitter;
 
 pub(crate) type RequestHandler = fn(&mut LspServerState, lsp_server::Response);
-pub(crate) type ForestData = Box<Option<(PathBuf, Arc<tree_sitter::Tree>, Arc<BeancountData>)>>;
+pub(crate) type ForestData = Box<
+    Option<(
+        PathBuf,
+        Arc<tree_sitter::Tree>,
+        ropey::Rope,
+        Arc<BeancountData>,
+        FileIncludes,
+    )>,
+>;
 
 #[derive(Debug)]
 pub(crate) enum ProgressMsg {
@@ -42,12 +59,22 @@ pub(crate) enum Task {
     Response(lsp_server::Response),
     Notify(lsp_server::Notification),
     Progress(ProgressMsg),
+    CheckFinished(BeanCheckStatus),
+    /// The journal file detected in `folder`, if any
+    JournalDetected {
+        folder: PathBuf,
+        journal: Option<PathBuf>,
+    },
 }
 
 #[derive(Debug)]
 pub(crate) enum Event {
     Lsp(lsp_server::Message),
     Task(Task),
+    /// A changed document was idle long enough to be re-indexed and checked
+    ChangesSettled,
+    /// A job of the scheduler finished, freeing its worker
+    JobFinished(Lane),
 }
 
 /*
@@ -60,14 +87,29 @@ struct LspServer {
 pub(crate) struct LspServerState {
     pub beancount_data: HashMap<PathBuf, Arc<BeancountData>>,
 
+    // Symbols of all files in the forest, updated per file
+    pub index: Arc<WorkspaceIndex>,
+
     // the lsp server config options
     pub config: Config,
 
     pub forest: HashMap<PathBuf, Arc<tree_sitter::Tree>>,
 
+    // The text each tree of the forest was parsed from, for open and closed files
+    pub sources: HashMap<PathBuf, ropey::Rope>,
+
+    // Documents changed since they were last parsed, indexed and checked
+    pub pending_changes: PendingChanges,
+
+    // Which file of the forest included which
+    pub include_graph: IncludeGraph,
+
     // Documents that are currently kept in memory from the client
     pub open_docs: HashMap<PathBuf, Document>,
 
+    // Latest versions of the open documents, readable from request workers
+    pub doc_versions: Arc<DocumentVersions>,
+
     pub parsers: HashMap<PathBuf, tree_sitter::Parser>,
 
     // The request queue keeps track of all incoming and outgoing requests.
@@ -85,23 +127,82 @@ pub(crate) struct LspServerState {
     // Channel to receive tasks on from background operations
     pub task_receiver: Receiver<Task>,
 
-    // Thread pool for async execution
-    pub thread_pool: threadpool::ThreadPool,
+    // Channel the background indexer reports parsed files on. It is drained
+    // with the lowest priority so interactive requests are never queued
+    // behind bulk indexing.
+    pub index_sender: Sender<Task>,
+
+    pub index_receiver: Receiver<Task>,
+
+    // Runs requests, indexing and diagnostics on worker threads by priority
+    pub scheduler: Scheduler,
 
     // Cached checker instance (created once and reused)
     pub checker: Option<Arc<dyn BeancountChecker>>,
 
+    // The latest bean-check run per journal, so superseded runs stop waiting
+    pub check_runs: Arc<CheckRuns>,
+
     // Request router with registered handlers
     pub request_router: Arc<RequestRouter>,
+
+    // Progress of indexing and when the index last changed, for `beancount/status`
+    pub index_status: IndexStatus,
+
+    // The last finished bean-check run, for `beancount/status`
+    pub last_check: Option<BeanCheckStatus>,
+
+    // Workspace folders besides `config.root_dir`, with the journal file detected in each
+    pub workspace_folders: BTreeMap<PathBuf, Option<PathBuf>>,
+
+    // Ledgers evaluated from the current index, shared with the snapshots taken from it
+    pub ledgers: Arc<LedgerCache>,
+
+    // Whether the client accepts `workspace/inlayHint/refresh`
+    pub inlay_hint_refresh_support: bool,
+
+    // Whether the client accepts versioned `documentChanges` in workspace edits
+    pub document_changes_support: bool,
+
+    // Whether the client accepts `window/showDocument`
+    pub show_document_support: bool,
+
+    // Inlay hints shown by the client may be outdated by a change of the index or config
+    pub inlay_hints_outdated: bool,
+
+    // A `workspace/inlayHint/refresh` request is waiting for its response
+    pub inlay_hint_refresh_pending: bool,
+
+    // Includes of new files waiting for the user to accept them, by prompt request
+    pub pending_includes: HashMap<lsp_server::RequestId, lsp_types::WorkspaceEdit>,
+}
+
+fn journal_for(
+    workspace_folders: &BTreeMap<PathBuf, Option<PathBuf>>,
+    config: &Config,
+    file: &Path,
+) -> Option<PathBuf> {
+    workspace_folders
+        .iter()
+        .filter(|(folder, _)| file.starts_with(folder))
+        .max_by_key(|(folder, _)| folder.components().count())
+        .and_then(|(_, journal)| journal.clone())
+        .or_else(|| config.resolved_journal_root())
 }
 
 /// A snapshot of the state of the language server
+#[derive(Clone)]
 pub(crate) struct LspServerStateSnapshot {
     pub beancount_data: HashMap<PathBuf, Arc<BeancountData>>,
+    pub index: Arc<WorkspaceIndex>,
     pub config: Config,
     pub forest: HashMap<PathBuf, Arc<tree_sitter::Tree>>,
+    pub sources: HashMap<PathBuf, ropey::Rope>,
     pub open_docs: HashMap<PathBuf, Document>,
     pub checker: Option<Arc<dyn BeancountChecker>>,
+    pub workspace_folders: BTreeMap<PathBuf, Option<PathBuf>>,
+    pub ledgers: Arc<LedgerCache>,
+    pub document_changes_support: bool,
 }
 
 impl LspServerStateSnapshot {
@@ -123,6 +224,117 @@ impl LspServerStateSnapshot {
             .with_context(|| format!("Document not found for file: {}", path.display()))?;
         Ok((tree, doc))
     }
+
+    /// A workspace edit making `changes`, versioned against the open documents.
+    #[allow(clippy::mutable_key_type)]
+    pub fn workspace_edit(
+        &self,
+        changes: HashMap<lsp_types::Uri, Vec<lsp_types::TextEdit>>,
+    ) -> lsp_types::WorkspaceEdit {
+        crate::utils::versioned_workspace_edit(
+            &self.open_docs,
+            changes,
+            self.document_changes_support,
+        )
+    }
+
+    /// The syntax tree of `path` and the text it was parsed from. Files outside the
+    /// forest, like a prices file that is not included yet, are read from disk.
+    pub fn tree_and_text(&self, path: &Path) -> Option<(Arc<tree_sitter::Tree>, ropey::Rope)> {
+        if let (Some(tree), Some(text)) = (self.forest.get(path), self.sources.get(path)) {
+            return Some((Arc::clone(tree), text.clone()));
+        }
+        let text = match self.open_docs.get(path) {
+            Some(doc) => doc.content.clone(),
+            None => ropey::Rope::from_str(&std::fs::read_to_string(path).ok()?),
+        };
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .ok()?;
+        let tree = Arc::new(parser.parse(text.to_string(), None)?);
+        Some((tree, text))
+    }
+
+    /// The journal file `file` belongs to: the one detected in the workspace folder
+    /// containing it, or the configured journal file.
+    pub fn journal_for(&self, file: &Path) -> Option<PathBuf> {
+        journal_for(&self.workspace_folders, &self.config, file)
+    }
+
+    /// The ledger of the journal `file` belongs to, evaluated once per index update.
+    pub fn ledger_for(&self, file: &Path) -> Arc<Ledger> {
+        let journal = self.journal_for(file);
+        self.ledgers.get_or_evaluate(journal.clone(), || {
+            Ledger::new(&self.journal_files(journal.as_deref()))
+        })
+    }
+
+    /// The indexed files belonging to `journal`, as decided by [`Self::journal_for`].
+    pub fn journal_files(&self, journal: Option<&Path>) -> HashMap<PathBuf, Arc<BeancountData>> {
+        self.beancount_data
+            .iter()
+            .filter(|(path, _)| self.journal_for(path).as_deref() == journal)
+            .map(|(path, data)| (path.clone(), Arc::clone(data)))
+            .collect()
+    }
+
+    /// Options declared by the journal file, or the defaults when it is unknown.
+    pub fn ledger_options(&self) -> LedgerOptions {
+        self.config
+            .resolved_journal_root()
+            .and_then(|journal| self.beancount_data.get(&journal))
+            .map(|data| LedgerOptions::from_data(data))
+            .unwrap_or_default()
+    }
+
+    /// Options declared by the journal `file` belongs to, or by `file` itself when it
+    /// belongs to none.
+    pub fn ledger_options_for(&self, file: &Path) -> LedgerOptions {
+        let journal = self.journal_for(file).unwrap_or_else(|| file.to_path_buf());
+        self.beancount_data
+            .get(&journal)
+            .map(|data| LedgerOptions::from_data(data))
+            .unwrap_or_default()
+    }
+
+    /// A snapshot with each of `files` parsed, indexed and open at version 0.
+    #[cfg(test)]
+    pub(crate) fn for_test(config: Config, files: &[(&Path, &str)]) -> Self {
+        let mut parser = tree_sitter::Parser::new();
+        parser
+            .set_language(&tree_sitter_beancount::language())
+            .expect("beancount grammar");
+        let mut snapshot = Self {
+            beancount_data: HashMap::new(),
+            index: Default::default(),
+            config,
+            forest: HashMap::new(),
+            sources: HashMap::new(),
+            open_docs: HashMap::new(),
+            checker: None,
+            workspace_folders: Default::default(),
+            ledgers: Default::default(),
+            document_changes_support: true,
+        };
+        for (path, text) in files {
+            let path = path.to_path_buf();
+            let tree = parser.parse(text, None).expect("parsed file");
+            let content = ropey::Rope::from_str(text);
+            let data = BeancountData::new(&tree, &content);
+            snapshot.beancount_data.insert(path.clone(), Arc::new(data));
+            snapshot.forest.insert(path.clone(), Arc::new(tree));
+            snapshot.sources.insert(path.clone(), content.clone());
+            snapshot.open_docs.insert(
+                path,
+                Document {
+                    content,
+                    version: 0,
+                },
+            );
+        }
+        snapshot
+    }
 }
 
 /*
@@ -137,22 +349,41 @@ impl LspServer {
 impl LspServerState {
     pub fn new(sender: Sender<lsp_server::Message>, config: Config) -> Self {
         let (task_sender, task_receiver) = crossbeam_channel::unbounded();
+        let (index_sender, index_receiver) = crossbeam_channel::unbounded();
         //let (event_tx, event_rx) = crossbeam_channel::unbounded();
         let request_router = Arc::new(Self::build_request_router());
         Self {
             beancount_data: HashMap::new(),
+            index: Arc::default(),
             config,
             forest: HashMap::new(),
+            sources: HashMap::new(),
+            pending_changes: PendingChanges::default(),
+            include_graph: IncludeGraph::default(),
             open_docs: HashMap::new(),
+            doc_versions: Arc::default(),
             parsers: HashMap::new(),
             req_queue: lsp_server::ReqQueue::default(),
             sender,
             shutdown_requested: false,
             task_sender,
             task_receiver,
-            thread_pool: threadpool::ThreadPool::default(),
+            index_sender,
+            index_receiver,
+            scheduler: Scheduler::default(),
             checker: None,
+            check_runs: Arc::default(),
             request_router,
+            index_status: IndexStatus::default(),
+            last_check: None,
+            workspace_folders: BTreeMap::new(),
+            ledgers: Arc::default(),
+            inlay_hint_refresh_support: false,
+            document_changes_support: false,
+            show_document_support: false,
+            inlay_hints_outdated: false,
+            inlay_hint_refresh_pending: false,
+            pending_includes: HashMap::new(),
         }
     }
 
@@ -162,52 +393,17 @@ impl LspServerState {
         // Register file watchers for .beancount files
         self.register_file_watchers();
 
+        self.apply_logging_config();
+
         // Initialize checker once (can be slow); report progress to users.
         self.ensure_checker();
 
-        // init forest
-        if let Some(file) = self.config.journal_root.as_ref() {
-            let journal_root = if file.is_relative() {
-                self.config.root_dir.join(file)
-            } else {
-                file.clone()
-            };
-
-            // Check if exists
-            if !journal_root.exists() {
-                let error_msg = format!("Journal root does not exist: {}", journal_root.display());
-                tracing::error!("{}", error_msg);
-
-                // Send error message to client
-                self.send_notification::<lsp_types::notification::ShowMessage>(
-                    lsp_types::ShowMessageParams {
-                        typ: lsp_types::MessageType::ERROR,
-                        message: error_msg.clone(),
-                    },
-                );
-
-                // Log warning and continue without forest initialization instead of returning error
-                // This allows the language server to continue functioning for open documents
-                tracing::warn!(
-                    "Continuing without forest initialization due to invalid journal root"
-                );
-            } else {
-                tracing::info!(
-                    "Initializing forest for journal root: {}",
-                    journal_root.display()
-                );
-                let snapshot = self.snapshot();
-                let sender = self.task_sender.clone();
-                self.thread_pool.execute(move || {
-                    match forest::parse_initial_forest(snapshot, journal_root, sender) {
-                        Ok(_) => tracing::info!("Forest initialization completed successfully"),
-                        Err(e) => tracing::error!("Forest initialization failed: {}", e),
-                    }
-                });
-            }
+        if self.config.journal_root.is_none() {
+            self.spawn_journal_detection(self.config.root_dir.clone());
         } else {
-            tracing::warn!("No journal_root configured, skipping forest initialization");
+            self.index_journal();
         }
+        self.index_workspace_folders();
 
         tracing::debug!("Entering main event loop");
         while let Some(event) = self.next_event(&receiver) {
@@ -223,11 +419,20 @@ impl LspServerState {
         Ok(())
     }
 
-    // Blocks until new event is received
+    // Blocks until new event is received. Client messages take precedence over
+    // background tasks, and indexing results are only handled when nothing else
+    // is pending. Work on the workers is prioritized by the scheduler.
     pub fn next_event(&self, receiver: &Receiver<lsp_server::Message>) -> Option<Event> {
-        crossbeam_channel::select! {
+        let settled = match self.pending_changes.next_deadline() {
+            Some(deadline) => crossbeam_channel::at(deadline),
+            None => crossbeam_channel::never(),
+        };
+        crossbeam_channel::select_biased! {
             recv(receiver) -> msg => msg.ok().map(Event::Lsp),
             recv(self.task_receiver) -> task => task.ok().map(Event::Task),
+            recv(self.scheduler.finished_receiver) -> lane => lane.ok().map(Event::JobFinished),
+            recv(settled) -> _ => Some(Event::ChangesSettled),
+            recv(self.index_receiver) -> task => task.ok().map(Event::Task),
         }
     }
 
@@ -235,11 +440,27 @@ impl LspServerState {
     fn handle_event(&mut self, event: Event) -> Result<()> {
         let start_time = Instant::now();
 
+        // Changes typed in a row share one reparse, done once something else needs the tree
+        let is_change = matches!(
+            &event,
+            Event::Lsp(lsp_server::Message::Notification(notification))
+                if notification.method == lsp_types::notification::DidChangeTextDocument::METHOD
+        );
+        if !is_change {
+            crate::providers::text_document::reparse_changed(self);
+        }
+
         match event {
             Event::Task(task) => {
                 tracing::debug!("Handling task: {:?}", task);
                 self.handle_task(task)?;
             }
+            Event::ChangesSettled => {
+                crate::providers::text_document::settle_changes(self, start_time);
+            }
+            Event::JobFinished(lane) => {
+                self.job_finished(lane);
+            }
             Event::Lsp(msg) => match msg {
                 lsp_server::Message::Request(req) => {
                     tracing::debug!("Handling LSP request: method={}, id={}", req.method, req.id);
@@ -256,6 +477,8 @@ impl LspServerState {
             },
         };
 
+        self.refresh_inlay_hints();
+
         let duration = start_time.elapsed();
         if duration.as_millis() > 100 {
             tracing::warn!("Event handling took longer than expected: {:?}", duration);
@@ -279,6 +502,13 @@ impl LspServerState {
                 tracing::debug!("Handling progress task: {:?}", progress_task);
                 self.handle_progress_task(progress_task)?;
             }
+            Task::CheckFinished(check) => {
+                tracing::debug!("Bean-check finished: {:?}", check);
+                self.last_check = Some(check);
+            }
+            Task::JournalDetected { folder, journal } => {
+                self.journal_detected(folder, journal);
+            }
         }
         Ok(())
     }
@@ -309,10 +539,18 @@ impl LspServerState {
                 )
             }
             ProgressMsg::ForestInit { total, done, data } => {
-                if let Some(data) = *data {
-                    self.forest.insert(data.0.clone(), data.1);
-                    self.beancount_data.insert(data.0, data.2);
+                // Files opened while indexing was running already have a tree for
+                // the editor's content, which is newer than what is on disk.
+                if let Some((path, tree, text, beancount_data, includes)) = *data
+                    && !self.open_docs.contains_key(&path)
+                {
+                    self.insert_tree(path.clone(), tree, text);
+                    self.insert_beancount_data(path.clone(), beancount_data);
+                    self.include_graph.insert(path, includes);
                 }
+                self.index_status.in_progress = done < total;
+                self.index_status.done = done;
+                self.index_status.total = total;
                 let progress_state = if done == 0 {
                     Progress::Begin
                 } else if done < total {
@@ -385,6 +623,14 @@ impl LspServerState {
             .on::<lsp_types::notification::DidChangeWatchedFiles>(
                 handlers::workspace::did_change_watched_files,
             )?
+            .on::<lsp_types::notification::DidCreateFiles>(handlers::workspace::did_create_files)?
+            .on::<lsp_types::notification::DidChangeConfiguration>(
+                handlers::workspace::did_change_configuration,
+            )?
+            .on::<lsp_types::notification::DidChangeWorkspaceFolders>(
+                handlers::workspace::did_change_workspace_folders,
+            )?
+            .on::<lsp_types::notification::SetTrace>(handlers::workspace::set_trace)?
             .finish();
         Ok(())
     }
@@ -454,12 +700,14 @@ impl LspServerState {
         &mut self,
         params: R::Params,
         handler: RequestHandler,
-    ) {
+    ) -> lsp_server::RequestId {
         let request = self
             .req_queue
             .outgoing
             .register(R::METHOD.to_string(), params, handler);
+        let id = request.id.clone();
         self.send(request.into());
+        id
     }
 
     // Sends a notification to the client
@@ -471,13 +719,42 @@ impl LspServerState {
         self.send(not.into());
     }
 
+    /// Queues `job` in `lane`, starting it right away if a worker is free.
+    pub(crate) fn spawn(&mut self, lane: Lane, job: Job) {
+        self.scheduler.push(lane, job);
+        self.schedule();
+    }
+
+    /// Starts queued jobs on the free workers, each with a fresh snapshot.
+    pub(crate) fn schedule(&mut self) {
+        while let Some((lane, job)) = self.scheduler.next_job() {
+            let snapshot = self.snapshot();
+            self.scheduler.start(lane, job, snapshot);
+        }
+    }
+
+    /// The journal file `file` belongs to, see [`LspServerStateSnapshot::journal_for`].
+    pub(crate) fn journal_for(&self, file: &Path) -> Option<PathBuf> {
+        journal_for(&self.workspace_folders, &self.config, file)
+    }
+
+    pub(crate) fn job_finished(&mut self, lane: Lane) {
+        self.scheduler.finished(lane);
+        self.schedule();
+    }
+
     pub(crate) fn snapshot(&self) -> LspServerStateSnapshot {
         LspServerStateSnapshot {
             beancount_data: self.beancount_data.clone(),
+            index: self.index.clone(),
             config: self.config.clone(),
             forest: self.forest.clone(),
+            sources: self.sources.clone(),
             open_docs: self.open_docs.clone(),
             checker: self.checker.clone(),
+            workspace_folders: self.workspace_folders.clone(),
+            ledgers: self.ledgers.clone(),
+            document_changes_support: self.document_changes_support,
         }
     }
 
@@ -497,15 +774,24 @@ impl LspServerState {
                 handlers::text_document::hover,
             )
             .expect("Failed to register Hover handler")
-            .on_with::<lsp_types::request::Completion>(
+            .on_latest::<lsp_types::request::Completion>(
                 |r, params| {
                     r.ensure_beancount_data_for_position(&params.text_document_position);
                 },
                 handlers::text_document::completion,
+                |params| &params.text_document_position.text_document.uri,
             )
             .expect("Failed to register Completion handler")
+            .on::<lsp_types::request::ResolveCompletionItem>(
+                handlers::text_document::completion_resolve,
+            )
+            .expect("Failed to register ResolveCompletionItem handler")
             .on::<lsp_types::request::Formatting>(handlers::text_document::formatting)
             .expect("Failed to register Formatting handler")
+            .on::<lsp_types::request::WillSaveWaitUntil>(
+                handlers::text_document::will_save_wait_until,
+            )
+            .expect("Failed to register WillSaveWaitUntil handler")
             .on_with::<lsp_types::request::Rename>(
                 |r, params| {
                     r.ensure_beancount_data_for_position(&params.text_document_position);
@@ -520,6 +806,19 @@ impl LspServerState {
                 handlers::text_document::handle_references,
             )
             .expect("Failed to register References handler")
+            .on_with::<lsp_types::request::CodeLensRequest>(
+                |r, params| {
+                    r.ensure_beancount_data_for_text_document(&params.text_document);
+                },
+                handlers::text_document::code_lens,
+            )
+            .expect("Failed to register CodeLens handler")
+            .on::<lsp_types::request::CodeActionRequest>(handlers::text_document::code_action)
+            .expect("Failed to register CodeAction handler")
+            .on::<lsp_types::request::DocumentHighlightRequest>(
+                handlers::text_document::document_highlight,
+            )
+            .expect("Failed to register DocumentHighlight handler")
             .on_with::<lsp_types::request::GotoDefinition>(
                 |r, params| {
                     r.ensure_beancount_data_for_position(&params.text_document_position_params);
@@ -527,11 +826,19 @@ impl LspServerState {
                 handlers::text_document::handle_definition,
             )
             .expect("Failed to register GotoDefinition handler")
-            .on_with::<lsp_types::request::SemanticTokensFullRequest>(
+            .on_with::<lsp_types::request::GotoImplementation>(
+                |r, params| {
+                    r.ensure_beancount_data_for_position(&params.text_document_position_params);
+                },
+                handlers::text_document::handle_implementation,
+            )
+            .expect("Failed to register GotoImplementation handler")
+            .on_latest::<lsp_types::request::SemanticTokensFullRequest>(
                 |r, params| {
                     r.ensure_beancount_data_for_text_document(&params.text_document);
                 },
                 handlers::text_document::semantic_tokens_full,
+                |params| &params.text_document.uri,
             )
             .expect("Failed to register SemanticTokens handler")
             .on::<lsp_types::request::InlayHintRequest>(handlers::text_document::inlay_hint)
@@ -545,7 +852,16 @@ impl LspServerState {
             .on::<lsp_types::request::WorkspaceSymbolRequest>(
                 handlers::text_document::workspace_symbol,
             )
-            .expect("Failed to register WorkspaceSymbol handler");
+            .expect("Failed to register WorkspaceSymbol handler")
+            .on_sync::<lsp_ext::Status>(handlers::workspace::status)
+            .expect("Failed to register Status handler")
+            .on::<lsp_ext::MonthlyTotals>(handlers::workspace::monthly_totals)
+            .expect("Failed to register MonthlyTotals handler")
+            .on_with::<lsp_types::request::ExecuteCommand>(
+                handlers::workspace::prepare_command,
+                handlers::workspace::execute_command,
+            )
+            .expect("Failed to register ExecuteCommand handler");
 
         router
     }
@@ -594,6 +910,250 @@ impl LspServerState {
         });
     }
 
+    /// Parses the journal and everything it includes into the forest.
+    fn index_journal(&mut self) {
+        if let Some(journal_root) = self.config.resolved_journal_root() {
+            // Check if exists
+            if !journal_root.exists() {
+                let error_msg = format!("Journal root does not exist: {}", journal_root.display());
+                tracing::error!("{}", error_msg);
+
+                // Send error message to client
+                self.send_notification::<lsp_types::notification::ShowMessage>(
+                    lsp_types::ShowMessageParams {
+                        typ: lsp_types::MessageType::ERROR,
+                        message: error_msg.clone(),
+                    },
+                );
+
+                // Log warning and continue without forest initialization instead of returning error
+                // This allows the language server to continue functioning for open documents
+                tracing::warn!(
+                    "Continuing without forest initialization due to invalid journal root"
+                );
+            } else {
+                self.spawn_indexer(journal_root);
+            }
+        } else {
+            tracing::warn!("No journal_root configured, skipping forest initialization");
+        }
+    }
+
+    /// Parses `journal_root` and everything it includes in the indexing lane, which
+    /// never takes the worker kept free for requests.
+    fn spawn_indexer(&mut self, journal_root: PathBuf) {
+        tracing::info!(
+            "Initializing forest for journal root: {}",
+            journal_root.display()
+        );
+        let sender = self.index_sender.clone();
+        self.spawn(
+            Lane::Indexing,
+            Box::new(move |snapshot| {
+                match forest::parse_initial_forest(snapshot, journal_root, sender) {
+                    Ok(_) => tracing::info!("Forest initialization completed successfully"),
+                    Err(e) => tracing::error!("Forest initialization failed: {}", e),
+                }
+            }),
+        );
+    }
+
+    /// Detects the journal of every workspace folder that has none yet, see
+    /// [`Self::workspace_folder_journal_detected`].
+    fn index_workspace_folders(&mut self) {
+        let folders: Vec<PathBuf> = self
+            .workspace_folders
+            .iter()
+            .filter(|(_, journal)| journal.is_none())
+            .map(|(folder, _)| folder.clone())
+            .collect();
+        for folder in folders {
+            self.spawn_journal_detection(folder);
+        }
+    }
+
+    /// Indexes the journal detected in a workspace folder.
+    fn workspace_folder_journal_detected(&mut self, folder: PathBuf, journal: Option<PathBuf>) {
+        if self.workspace_folders.get(&folder) != Some(&None) {
+            // Removed or detected by an earlier job while detecting
+            return;
+        }
+        let Some(journal) = journal else {
+            tracing::info!("No journal file detected in {}", folder.display());
+            return;
+        };
+        tracing::info!(
+            "Detected journal file {} in workspace folder {}",
+            journal.display(),
+            folder.display()
+        );
+        self.workspace_folders.insert(folder, Some(journal.clone()));
+        self.ledger_changed();
+        if Some(&journal) != self.config.resolved_journal_root().as_ref() {
+            self.spawn_indexer(journal);
+        }
+    }
+
+    /// Journal files the forest is built from: the configured one and those of
+    /// the other workspace folders.
+    pub(crate) fn journal_roots(&self) -> Vec<PathBuf> {
+        self.config
+            .resolved_journal_root()
+            .into_iter()
+            .chain(self.workspace_folders.values().flatten().cloned())
+            .collect()
+    }
+
+    /// Handles `workspace/didChangeWorkspaceFolders`: indexes the journals of added
+    /// folders and drops the files and diagnostics that only removed folders needed.
+    pub(crate) fn change_workspace_folders(&mut self, added: Vec<PathBuf>, removed: Vec<PathBuf>) {
+        for folder in removed {
+            tracing::info!("Workspace folder removed: {}", folder.display());
+            if folder == self.config.root_dir {
+                // The configured journal goes away with its folder
+                if self
+                    .config
+                    .resolved_journal_root()
+                    .is_some_and(|journal| journal.starts_with(&folder))
+                {
+                    self.config.journal_root = None;
+                }
+            } else {
+                self.workspace_folders.remove(&folder);
+            }
+        }
+
+        for folder in added {
+            if folder == self.config.root_dir || self.workspace_folders.contains_key(&folder) {
+                continue;
+            }
+            tracing::info!("Workspace folder added: {}", folder.display());
+            self.workspace_folders.insert(folder, None);
+        }
+
+        for file in include_graph::prune_unreachable(self) {
+            self.clear_diagnostics(&file);
+        }
+        self.ledger_changed();
+        self.index_workspace_folders();
+    }
+
+    /// Publishes an empty diagnostics list for a file the server stopped tracking.
+    fn clear_diagnostics(&mut self, file: &Path) {
+        let Ok(uri) = crate::utils::file_path_to_uri(file) else {
+            return;
+        };
+        self.send_notification::<lsp_types::notification::PublishDiagnostics>(
+            lsp_types::PublishDiagnosticsParams {
+                uri,
+                diagnostics: vec![],
+                version: None,
+            },
+        );
+    }
+
+    /// Rebuilds the configuration after the client settings changed, restarting the
+    /// checker and re-indexing when the settings they depend on changed.
+    pub(crate) fn reload_config(&mut self, settings: serde_json::Value) {
+        if settings.is_null() {
+            tracing::debug!("Configuration change without settings, keeping current config");
+            return;
+        }
+        let mut config = Config::load(self.config.root_dir.clone(), Some(settings));
+        if config.journal_root.is_none() {
+            // Keep a detected journal file
+            config.journal_root = self.config.journal_root.clone();
+        }
+
+        let journal_changed = config.resolved_journal_root() != self.config.resolved_journal_root();
+        let checker_changed = config.bean_check != self.config.bean_check;
+        if config.inlay_hints != self.config.inlay_hints {
+            self.inlay_hints_outdated = true;
+        }
+        self.config = config;
+        self.apply_logging_config();
+
+        if checker_changed {
+            self.checker = None;
+            self.ensure_checker();
+        }
+        if journal_changed {
+            self.ledger_changed();
+            include_graph::prune_unreachable(self);
+            self.index_journal();
+        }
+    }
+
+    /// Applies the configured log level and file on top of the command line flags.
+    fn apply_logging_config(&self) {
+        if let Some(level) = self.config.log_level {
+            logging::set_level(level);
+        }
+        if let Some(log_file) = &self.config.log_file
+            && logging::log_file().as_ref() != Some(log_file)
+        {
+            match logging::set_log_file(Some(log_file)) {
+                Ok(()) => tracing::info!("Logging to file: {}", log_file.display()),
+                Err(e) => tracing::warn!("Failed to open log file {}: {}", log_file.display(), e),
+            }
+        }
+    }
+
+    /// Guesses the journal file of `folder` on the indexing lane, which can take a
+    /// while in large workspaces. The result comes back as [`Task::JournalDetected`].
+    fn spawn_journal_detection(&mut self, folder: PathBuf) {
+        let sender = self.index_sender.clone();
+        self.spawn(
+            Lane::Indexing,
+            Box::new(move |_| {
+                let journal = forest::detect_journal_file(&folder);
+                if let Err(e) = sender.send(Task::JournalDetected { folder, journal }) {
+                    tracing::error!("Failed to send detected journal file: {}", e);
+                }
+            }),
+        );
+    }
+
+    /// Picks the journal file detected in the workspace root when none is configured,
+    /// so that zero-config workspaces still get completions and diagnostics across
+    /// included files. Other workspace folders index the journal detected in them.
+    fn journal_detected(&mut self, folder: PathBuf, journal: Option<PathBuf>) {
+        if folder != self.config.root_dir {
+            self.workspace_folder_journal_detected(folder, journal);
+            return;
+        }
+        if self.config.journal_root.is_some() {
+            // The workspace or its configuration changed while detecting
+            return;
+        }
+        let Some(journal_file) = journal else {
+            tracing::info!(
+                "No journal_file configured and none detected in {}",
+                self.config.root_dir.display()
+            );
+            return;
+        };
+
+        let message = format!(
+            "Detected journal file {}; set journal_file to override",
+            journal_file.display()
+        );
+        tracing::info!("{}", message);
+        self.send_notification::<lsp_types::notification::LogMessage>(
+            lsp_types::LogMessageParams {
+                typ: lsp_types::MessageType::INFO,
+                message,
+            },
+        );
+        // A workspace folder may have found the same journal first
+        let indexed = self.journal_roots().contains(&journal_file);
+        self.config.journal_root = Some(journal_file);
+        self.ledger_changed();
+        if !indexed {
+            self.index_journal();
+        }
+    }
+
     fn ensure_checker(&mut self) -> Option<Arc<dyn BeancountChecker>> {
         if let Some(checker) = &self.checker {
             return Some(checker.clone());
@@ -637,6 +1197,70 @@ impl LspServerState {
         checker
     }
 
+    /// Stores the extracted data of a file and updates the workspace index with it.
+    pub(crate) fn insert_beancount_data(&mut self, path: PathBuf, data: Arc<BeancountData>) {
+        Arc::make_mut(&mut self.index).update_file(path.clone(), data.clone());
+        self.beancount_data.insert(path, data);
+        self.index_status.updated_at = Some(lsp_ext::now_millis());
+        self.ledger_changed();
+    }
+
+    /// Notes that prices or balances may have changed, which outdates the cached
+    /// ledgers and the inlay hints computed from them.
+    fn ledger_changed(&mut self) {
+        // Snapshots already taken keep the cache matching their data
+        self.ledgers = Arc::default();
+        if self.config.inlay_hints.use_ledger() {
+            self.inlay_hints_outdated = true;
+        }
+    }
+
+    /// Asks the client to request inlay hints again when they are outdated. Waits for
+    /// the index to be built and for an earlier refresh to be answered, so a burst of
+    /// changes causes a single refresh.
+    fn refresh_inlay_hints(&mut self) {
+        if !self.inlay_hints_outdated
+            || self.index_status.in_progress
+            || self.inlay_hint_refresh_pending
+        {
+            return;
+        }
+        self.inlay_hints_outdated = false;
+        if !self.inlay_hint_refresh_support || !self.config.features.inlay_hints {
+            return;
+        }
+        tracing::debug!("Refreshing inlay hints");
+        self.inlay_hint_refresh_pending = true;
+        self.send_request::<lsp_types::request::InlayHintRefreshRequest>((), |state, _| {
+            state.inlay_hint_refresh_pending = false;
+        });
+    }
+
+    /// Puts the tree of `path` into the forest along with the text it was parsed from.
+    pub(crate) fn insert_tree(
+        &mut self,
+        path: PathBuf,
+        tree: Arc<tree_sitter::Tree>,
+        text: ropey::Rope,
+    ) {
+        self.sources.insert(path.clone(), text);
+        self.forest.insert(path, tree);
+    }
+
+    /// Removes `path` from the forest, returning whether it was in it.
+    pub(crate) fn remove_tree(&mut self, path: &Path) -> bool {
+        self.sources.remove(path);
+        self.forest.remove(path).is_some()
+    }
+
+    /// Forgets the extracted data of a file that left the forest.
+    pub(crate) fn remove_beancount_data(&mut self, path: &Path) {
+        Arc::make_mut(&mut self.index).remove_file(path);
+        self.beancount_data.remove(path);
+        self.index_status.updated_at = Some(lsp_ext::now_millis());
+        self.ledger_changed();
+    }
+
     /// Ensure BeancountData is extracted for the given URI.
     /// Lazily extracts on first access after tree changes (lazy extraction for #757).
     pub(crate) fn ensure_beancount_data(&mut self, uri: &PathBuf) {
@@ -648,8 +1272,7 @@ impl LspServerState {
         // Extract on-demand
         if let (Some(tree), Some(doc)) = (self.forest.get(uri), self.open_docs.get(uri)) {
             let beancount_data = BeancountData::new(tree, &doc.content);
-            self.beancount_data
-                .insert(uri.clone(), Arc::new(beancount_data));
+            self.insert_beancount_data(uri.clone(), Arc::new(beancount_data));
             tracing::debug!("Lazy extraction: BeancountData extracted for {:?}", uri);
         }
     }
@@ -663,7 +1286,7 @@ impl LspServerState {
         }
     }
 
-    fn ensure_beancount_data_for_position(
+    pub(crate) fn ensure_beancount_data_for_position(
         &mut self,
         params: &lsp_types::TextDocumentPositionParams,
     ) {
@@ -694,6 +1317,274 @@ mod tests {
         parser.parse(content, None).expect("Failed to parse")
     }
 
+    #[test]
+    fn test_reload_config_applies_settings_and_keeps_journal() {
+        let mut state = create_test_state();
+        state.config.journal_root = Some(PathBuf::from("/test/detected.beancount"));
+
+        state.reload_config(serde_json::json!({ "diagnostic_flags": ["P"] }));
+        assert_eq!(state.config.diagnostic_flags, vec!["P"]);
+        assert_eq!(
+            state.config.journal_root,
+            Some(PathBuf::from("/test/detected.beancount"))
+        );
+
+        // Clients that only signal a change without settings keep the current config
+        state.reload_config(serde_json::Value::Null);
+        assert_eq!(state.config.diagnostic_flags, vec!["P"]);
+    }
+
+    #[test]
+    fn test_inlay_hints_refreshed_once_when_ledger_changes() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(PathBuf::from("/test")));
+        state.inlay_hint_refresh_support = true;
+        let path = PathBuf::from("/test/main.beancount");
+        let tree = create_test_tree("2024-01-01 price CHF 1.10 USD\n");
+        let data = Arc::new(BeancountData::new(
+            &tree,
+            &ropey::Rope::from_str("2024-01-01 price CHF 1.10 USD\n"),
+        ));
+        let refreshes = |receiver: &Receiver<lsp_server::Message>| {
+            receiver
+                .try_iter()
+                .filter(|message| {
+                    matches!(message, lsp_server::Message::Request(request)
+                        if request.method == "workspace/inlayHint/refresh")
+                })
+                .count()
+        };
+
+        // The default hints do not depend on other files
+        state.insert_beancount_data(path.clone(), data.clone());
+        state.refresh_inlay_hints();
+        assert_eq!(refreshes(&receiver), 0);
+
+        state.reload_config(serde_json::json!({ "inlay_hints": { "conversions": true } }));
+        state.refresh_inlay_hints();
+        assert_eq!(refreshes(&receiver), 1);
+
+        // Changes while the client has not answered share the next refresh
+        state.insert_beancount_data(path.clone(), data.clone());
+        state.refresh_inlay_hints();
+        state.remove_beancount_data(&path);
+        state.refresh_inlay_hints();
+        assert_eq!(refreshes(&receiver), 0);
+
+        state.inlay_hint_refresh_pending = false;
+        state.refresh_inlay_hints();
+        assert_eq!(refreshes(&receiver), 1);
+    }
+
+    #[test]
+    fn test_ledger_cached_until_index_changes() {
+        let mut state = create_test_state();
+        let path = PathBuf::from("/test/main.beancount");
+        let content = "2024-01-01 price CHF 1.10 USD\n";
+        let data = Arc::new(BeancountData::new(
+            &create_test_tree(content),
+            &Rope::from_str(content),
+        ));
+        state.insert_beancount_data(path.clone(), data.clone());
+
+        let snapshot = state.snapshot();
+        let ledger = snapshot.ledger_for(&path);
+        assert!(Arc::ptr_eq(&ledger, &snapshot.ledger_for(&path)));
+        assert!(Arc::ptr_eq(&ledger, &state.snapshot().ledger_for(&path)));
+
+        state.insert_beancount_data(path.clone(), data);
+        assert!(!Arc::ptr_eq(&ledger, &state.snapshot().ledger_for(&path)));
+        // Older snapshots do not fill the cache of the current index
+        let stale = snapshot.ledger_for(&path);
+        assert!(Arc::ptr_eq(&ledger, &stale));
+        assert!(!Arc::ptr_eq(&stale, &state.snapshot().ledger_for(&path)));
+    }
+
+    #[test]
+    fn test_change_workspace_folders() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let other = dir.path().join("other");
+        std::fs::create_dir(&other).unwrap();
+        let journal = other.join("main.beancount");
+        std::fs::write(&journal, "2024-01-01 open Assets:Cash\n").unwrap();
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut state = LspServerState::new(sender, Config::new(dir.path().join("main")));
+
+        state.change_workspace_folders(vec![other.clone()], vec![]);
+        assert_eq!(state.workspace_folders.get(&other), Some(&None));
+        let detected = state
+            .index_receiver
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap();
+        state.handle_task(detected).unwrap();
+        assert_eq!(
+            state.workspace_folders.get(&other),
+            Some(&Some(journal.clone()))
+        );
+        assert_eq!(state.journal_roots(), vec![journal.clone()]);
+        assert_eq!(
+            state.snapshot().journal_for(&other.join("2024.beancount")),
+            Some(journal.clone())
+        );
+
+        // Pretend the indexer already parsed the journal
+        let tree = Arc::new(create_test_tree("2024-01-01 open Assets:Cash\n"));
+        state.forest.insert(journal.clone(), tree);
+        state
+            .include_graph
+            .insert(journal.clone(), FileIncludes::default());
+        while receiver.try_recv().is_ok() {}
+
+        state.change_workspace_folders(vec![], vec![other.clone()]);
+        assert!(state.workspace_folders.is_empty());
+        assert!(!state.forest.contains_key(&journal));
+        assert!(!state.include_graph.contains(&journal));
+
+        let cleared = receiver
+            .try_iter()
+            .filter_map(|message| match message {
+                lsp_server::Message::Notification(n)
+                    if n.method == lsp_types::notification::PublishDiagnostics::METHOD =>
+                {
+                    Some(n)
+                }
+                _ => None,
+            })
+            .count();
+        assert_eq!(cleared, 1);
+    }
+
+    #[test]
+    fn test_ledger_options_of_the_file_journal() {
+        let main = PathBuf::from("/test/main.beancount");
+        let other = PathBuf::from("/other/main.beancount");
+        let mut config = Config::new(PathBuf::from("/test"));
+        config.journal_root = Some(main.clone());
+        let mut snapshot = LspServerStateSnapshot::for_test(
+            config,
+            &[
+                (&main, "option \"name_assets\" \"Aktiva\"\n"),
+                (&other, "option \"name_assets\" \"Vermoegen\"\n"),
+            ],
+        );
+        snapshot
+            .workspace_folders
+            .insert(PathBuf::from("/other"), Some(other.clone()));
+
+        let root =
+            |file: &str| snapshot.ledger_options_for(Path::new(file)).root_accounts[0].clone();
+        assert_eq!(root("/test/2024.beancount"), "Aktiva");
+        assert_eq!(root("/other/2024.beancount"), "Vermoegen");
+
+        // Without a journal, a file is its own
+        let loose = Path::new("/loose.beancount");
+        let snapshot = LspServerStateSnapshot::for_test(
+            Config::new(PathBuf::from("/test")),
+            &[(loose, "option \"name_assets\" \"Actifs\"\n")],
+        );
+        assert_eq!(
+            snapshot.ledger_options_for(loose).root_accounts[0],
+            "Actifs"
+        );
+    }
+
+    #[test]
+    fn test_journal_detected_on_indexing_lane() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let journal = dir.path().join("main.beancount");
+        std::fs::write(&journal, "2024-01-01 open Assets:Cash\n").unwrap();
+        let mut state = create_test_state();
+        state.config.root_dir = dir.path().to_path_buf();
+
+        state.spawn_journal_detection(dir.path().to_path_buf());
+        assert_eq!(state.config.journal_root, None);
+        let task = state
+            .index_receiver
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .unwrap();
+        assert!(matches!(&task, Task::JournalDetected { journal: Some(j), .. } if *j == journal));
+        state.handle_task(task).unwrap();
+        assert_eq!(state.config.journal_root, Some(journal.clone()));
+
+        // A journal configured meanwhile is kept
+        state.config.journal_root = Some(dir.path().join("other.beancount"));
+        state.journal_detected(dir.path().to_path_buf(), Some(journal));
+        assert_eq!(
+            state.config.journal_root,
+            Some(dir.path().join("other.beancount"))
+        );
+    }
+
+    #[test]
+    fn test_next_event_prefers_client_messages_over_indexing() {
+        let state = create_test_state();
+        let (client_sender, client_receiver) = crossbeam_channel::unbounded();
+
+        state
+            .index_sender
+            .send(Task::Progress(ProgressMsg::ForestInit {
+                total: 1,
+                done: 1,
+                data: Box::new(None),
+            }))
+            .unwrap();
+        client_sender
+            .send(lsp_server::Message::Notification(
+                lsp_server::Notification::new("test".to_string(), ()),
+            ))
+            .unwrap();
+
+        assert!(matches!(
+            state.next_event(&client_receiver),
+            Some(Event::Lsp(_))
+        ));
+        assert!(matches!(
+            state.next_event(&client_receiver),
+            Some(Event::Task(Task::Progress(ProgressMsg::ForestInit { .. })))
+        ));
+    }
+
+    #[test]
+    fn test_indexing_does_not_replace_open_document_tree() {
+        let mut state = create_test_state();
+        let uri = PathBuf::from("/test/file.beancount");
+
+        let open_content = "2024-01-01 open Assets:Checking USD\n";
+        let open_tree = Arc::new(create_test_tree(open_content));
+        state.forest.insert(uri.clone(), open_tree.clone());
+        state.open_docs.insert(
+            uri.clone(),
+            Document {
+                content: Rope::from_str(open_content),
+                version: 1,
+            },
+        );
+
+        let disk_content = "2024-01-01 open Assets:Savings USD\n";
+        let disk_tree = Arc::new(create_test_tree(disk_content));
+        let disk_data = Arc::new(BeancountData::new(
+            &disk_tree,
+            &Rope::from_str(disk_content),
+        ));
+        state
+            .handle_progress_task(ProgressMsg::ForestInit {
+                total: 1,
+                done: 1,
+                data: Box::new(Some((
+                    uri.clone(),
+                    disk_tree,
+                    Rope::from_str(disk_content),
+                    disk_data,
+                    FileIncludes::default(),
+                ))),
+            })
+            .unwrap();
+
+        assert!(Arc::ptr_eq(&state.forest[&uri], &open_tree));
+        assert!(!state.beancount_data.contains_key(&uri));
+    }
+
     #[test]
     fn test_lazy_extraction_skips_if_data_exists() {
         let mut state = create_test_state();
@@ -0,0 +1,169 @@
+//! The channels the client and the server talk over.
+
+use anyhow::{Context, Result};
+use lsp_server::{Connection, Message};
+use std::io::{self, BufReader, Read, Write};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// How the server is reached by the client.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Transport {
+    /// Standard input and output of the process
+    #[default]
+    Stdio,
+    /// Wait for a client on a TCP address, like `127.0.0.1:9257`
+    Listen(String),
+    /// Connect to a pipe the client created: a Unix domain socket, or a named pipe
+    /// like `\\.\pipe\beancount` on Windows
+    Pipe(PathBuf),
+}
+
+/// The threads moving messages between a connection and its transport.
+pub enum IoThreads {
+    Lsp(lsp_server::IoThreads),
+    Stream {
+        reader: thread::JoinHandle<io::Result<()>>,
+        writer: thread::JoinHandle<io::Result<()>>,
+    },
+}
+
+impl IoThreads {
+    pub fn join(self) -> io::Result<()> {
+        match self {
+            IoThreads::Lsp(threads) => threads.join(),
+            IoThreads::Stream { reader, writer } => {
+                for thread in [reader, writer] {
+                    match thread.join() {
+                        Ok(result) => result?,
+                        Err(err) => std::panic::resume_unwind(err),
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Transport {
+    /// Opens the connection, blocking until a client is connected.
+    pub fn connect(&self) -> Result<(Connection, IoThreads)> {
+        match self {
+            Transport::Stdio => {
+                let (connection, io_threads) = Connection::stdio();
+                Ok((connection, IoThreads::Lsp(io_threads)))
+            }
+            Transport::Listen(addr) => {
+                tracing::info!("Waiting for a client on {}", addr);
+                let (connection, io_threads) = Connection::listen(addr.as_str())
+                    .with_context(|| format!("failed to listen on {addr}"))?;
+                Ok((connection, IoThreads::Lsp(io_threads)))
+            }
+            Transport::Pipe(name) => {
+                tracing::info!("Connecting to pipe {}", name.display());
+                let (reader, writer) = open_pipe(name)
+                    .with_context(|| format!("failed to connect to pipe {}", name.display()))?;
+                Ok(stream_transport(reader, writer))
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn open_pipe(name: &Path) -> io::Result<(UnixStream, UnixStream)> {
+    let stream = UnixStream::connect(name)?;
+    Ok((stream.try_clone()?, stream))
+}
+
+#[cfg(not(unix))]
+fn open_pipe(name: &Path) -> io::Result<(std::fs::File, std::fs::File)> {
+    let pipe = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(name)?;
+    Ok((pipe.try_clone()?, pipe))
+}
+
+/// Reads messages from `reader` and writes them to `writer` on their own threads.
+fn stream_transport(
+    reader: impl Read + Send + 'static,
+    mut writer: impl Write + Send + 'static,
+) -> (Connection, IoThreads) {
+    let (reader_sender, receiver) = crossbeam_channel::bounded::<Message>(0);
+    let reader = thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        while let Some(message) = Message::read(&mut reader)? {
+            let is_exit = matches!(&message, Message::Notification(n) if n.method == "exit");
+            if reader_sender.send(message).is_err() || is_exit {
+                break;
+            }
+        }
+        Ok(())
+    });
+
+    let (sender, writer_receiver) = crossbeam_channel::bounded::<Message>(0);
+    let writer = thread::spawn(move || {
+        for message in writer_receiver {
+            message.write(&mut writer)?;
+        }
+        Ok(())
+    });
+
+    (
+        Connection { sender, receiver },
+        IoThreads::Stream { reader, writer },
+    )
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn test_pipe_transport_exchanges_messages() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let name = dir.path().join("lsp.sock");
+        let listener = UnixListener::bind(&name).unwrap();
+
+        let (connection, io_threads) = Transport::Pipe(name).connect().unwrap();
+        let (client, _) = listener.accept().unwrap();
+        let mut client_reader = BufReader::new(client.try_clone().unwrap());
+        let mut client_writer = client;
+
+        Message::Notification(lsp_server::Notification::new(
+            "initialized".to_string(),
+            serde_json::json!({}),
+        ))
+        .write(&mut client_writer)
+        .unwrap();
+        let Message::Notification(received) = connection.receiver.recv().unwrap() else {
+            panic!("expected a notification");
+        };
+        assert_eq!(received.method, "initialized");
+
+        connection
+            .sender
+            .send(Message::Notification(lsp_server::Notification::new(
+                "window/logMessage".to_string(),
+                serde_json::json!({}),
+            )))
+            .unwrap();
+        let Some(Message::Notification(sent)) = Message::read(&mut client_reader).unwrap() else {
+            panic!("expected a notification");
+        };
+        assert_eq!(sent.method, "window/logMessage");
+
+        Message::Notification(lsp_server::Notification::new(
+            "exit".to_string(),
+            serde_json::json!(null),
+        ))
+        .write(&mut client_writer)
+        .unwrap();
+        connection.receiver.recv().unwrap();
+        drop(connection);
+        io_threads.join().unwrap();
+    }
+}
@@ -1,6 +1,97 @@
+use lsp_types::PositionEncodingKind;
+use std::sync::OnceLock;
 use tree_sitter_beancount::tree_sitter;
 
-/// Convert an LSP UTF-16 position into a tree-sitter `Point` (byte-based column).
+static POSITION_ENCODING: OnceLock<PositionEncoding> = OnceLock::new();
+
+/// How the `character` of an LSP position counts columns.
+///
+/// tree-sitter and the rope address text by byte, while LSP positions count
+/// UTF-16 code units unless another encoding was negotiated at initialize.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// Bytes of the UTF-8 encoded line.
+    Utf8,
+    /// UTF-16 code units, the LSP default.
+    #[default]
+    Utf16,
+}
+
+impl PositionEncoding {
+    /// Picks the encoding to use from the client's `general.positionEncodings`.
+    ///
+    /// UTF-8 matches tree-sitter's byte columns, so it is preferred when offered.
+    /// Otherwise the server stays on UTF-16, which every client must support.
+    pub fn negotiate(client_encodings: Option<&[PositionEncodingKind]>) -> Self {
+        let offered = client_encodings.unwrap_or_default();
+        if offered.contains(&PositionEncodingKind::UTF8) {
+            PositionEncoding::Utf8
+        } else {
+            PositionEncoding::Utf16
+        }
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+
+    /// Converts a byte column within `line` into an LSP `character`.
+    pub fn character(self, line: &str, byte_column: usize) -> u32 {
+        let prefix = &line[..floor_char_boundary(line, byte_column)];
+        let character = match self {
+            PositionEncoding::Utf8 => prefix.len(),
+            PositionEncoding::Utf16 => prefix.encode_utf16().count(),
+        };
+        character as u32
+    }
+
+    /// Converts an LSP `character` within `line` into a byte column.
+    ///
+    /// Columns past the end of the line are clamped to its length, and columns
+    /// inside a multi-byte character round down to the character's start.
+    pub fn byte_column(self, line: &str, character: u32) -> usize {
+        let character = character as usize;
+        match self {
+            PositionEncoding::Utf8 => floor_char_boundary(line, character),
+            PositionEncoding::Utf16 => {
+                let mut units = 0;
+                for (idx, c) in line.char_indices() {
+                    units += c.len_utf16();
+                    if units > character {
+                        return idx;
+                    }
+                }
+                line.len()
+            }
+        }
+    }
+}
+
+/// Sets the encoding negotiated with the client. Only the first call has an effect.
+pub fn set_position_encoding(encoding: PositionEncoding) {
+    let _ = POSITION_ENCODING.set(encoding);
+}
+
+/// The encoding negotiated with the client, UTF-16 until one was negotiated.
+pub fn position_encoding() -> PositionEncoding {
+    POSITION_ENCODING.get().copied().unwrap_or_default()
+}
+
+fn floor_char_boundary(s: &str, byte_idx: usize) -> usize {
+    if byte_idx >= s.len() {
+        return s.len();
+    }
+    let mut idx = byte_idx;
+    while !s.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+/// Convert an LSP position into a tree-sitter `Point` (byte-based column).
 pub fn lsp_position_to_tree_sitter_point(
     source: &ropey::Rope,
     position: lsp_types::Position,
@@ -33,6 +124,24 @@ pub fn tree_sitter_node_to_lsp_range(
     lsp_types::Range { start, end }
 }
 
+/// Convert a tree-sitter `Point` into an LSP position in the negotiated encoding.
+pub fn tree_sitter_point_to_lsp_position(
+    source: &ropey::Rope,
+    point: tree_sitter::Point,
+) -> lsp_types::Position {
+    let row = point.row.min(source.len_lines().saturating_sub(1));
+    let byte_idx = (source.line_to_byte(row) + point.column).min(source.len_bytes());
+    byte_to_lsp_position(source, byte_idx)
+}
+
+/// Convert an LSP position into a char index into `source`, for editing the rope.
+pub fn lsp_position_to_char_idx(
+    source: &ropey::Rope,
+    position: lsp_types::Position,
+) -> anyhow::Result<usize> {
+    Ok(lsp_position_to_core(source, position)?.char as usize)
+}
+
 pub fn lsp_textdocchange_to_ts_inputedit(
     source: &ropey::Rope,
     change: &lsp_types::TextDocumentContentChangeEvent,
@@ -55,17 +164,15 @@ pub fn lsp_textdocchange_to_ts_inputedit(
 
     let new_end_byte = start.byte as usize + text_end_byte_idx;
 
-    let new_end_position = {
-        if new_end_byte >= source.len_bytes() {
-            let line_idx = text.lines().count();
-            let line_byte_idx = ropey::str_utils::line_to_byte_idx(text, line_idx);
-            let row = u32::try_from(source.len_lines() + line_idx)? as usize;
-            let column = u32::try_from(text_end_byte_idx - line_byte_idx)? as usize;
-            Ok(tree_sitter::Point::new(row, column))
-        } else {
-            byte_to_tree_sitter_point(source, new_end_byte)
-        }
-    }?;
+    // The new end point only depends on where the edit starts and the inserted text,
+    // so it stays correct even though `source` still holds the pre-edit content.
+    let new_end_position = match text.rfind('\n') {
+        Some(last_newline) => tree_sitter::Point::new(
+            start.point.row + text.matches('\n').count(),
+            text_end_byte_idx - last_newline - 1,
+        ),
+        None => tree_sitter::Point::new(start.point.row, start.point.column + text_end_byte_idx),
+    };
 
     Ok(tree_sitter::InputEdit {
         start_byte: start.byte as usize,
@@ -78,29 +185,32 @@ pub fn lsp_textdocchange_to_ts_inputedit(
 }
 
 fn byte_to_lsp_position(text: &ropey::Rope, byte_idx: usize) -> lsp_types::Position {
-    let line_idx = text.byte_to_line(byte_idx);
+    byte_to_lsp_position_with(text, byte_idx, position_encoding())
+}
 
-    let line_utf16_cu_idx = {
-        let char_idx = text.line_to_char(line_idx);
-        text.char_to_utf16_cu(char_idx)
-    };
+fn byte_to_lsp_position_with(
+    text: &ropey::Rope,
+    byte_idx: usize,
+    encoding: PositionEncoding,
+) -> lsp_types::Position {
+    let line_idx = text.byte_to_line(byte_idx);
+    let line_char_idx = text.line_to_char(line_idx);
+    let char_idx = text.byte_to_char(byte_idx);
 
-    let character_utf16_cu_idx = {
-        let char_idx = text.byte_to_char(byte_idx);
-        text.char_to_utf16_cu(char_idx)
+    let character = match encoding {
+        PositionEncoding::Utf8 => text.char_to_byte(char_idx) - text.line_to_byte(line_idx),
+        PositionEncoding::Utf16 => {
+            text.char_to_utf16_cu(char_idx) - text.char_to_utf16_cu(line_char_idx)
+        }
     };
 
-    let line = line_idx;
-    let character = character_utf16_cu_idx - line_utf16_cu_idx;
-
-    lsp_types::Position::new(line as u32, character as u32)
+    lsp_types::Position::new(line_idx as u32, character as u32)
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct TextPosition {
     pub char: u32,
     pub byte: u32,
-    pub code: u32,
     pub point: tree_sitter::Point,
 }
 
@@ -108,20 +218,31 @@ fn lsp_position_to_core(
     source: &ropey::Rope,
     position: lsp_types::Position,
 ) -> anyhow::Result<TextPosition> {
-    let row_idx = position.line as usize;
+    lsp_position_to_core_with(source, position, position_encoding())
+}
 
-    // LSP `character` is a *line-relative* UTF-16 code-unit offset.
-    let col_utf16_cu_idx = position.character as usize;
+fn lsp_position_to_core_with(
+    source: &ropey::Rope,
+    position: lsp_types::Position,
+    encoding: PositionEncoding,
+) -> anyhow::Result<TextPosition> {
+    let row_idx = (position.line as usize).min(source.len_lines().saturating_sub(1));
 
-    // Convert the *line-relative* UTF-16 column into an absolute UTF-16 index.
+    // LSP `character` is a *line-relative* offset in the negotiated encoding.
+    // Clamp to document bounds to prevent panic if client sends invalid positions.
     let row_char_idx = source.line_to_char(row_idx);
-    let row_utf16_cu_idx = source.char_to_utf16_cu(row_char_idx);
-    let abs_utf16_cu_idx = row_utf16_cu_idx + col_utf16_cu_idx;
-    // Clamp to document bounds to prevent panic if client sends invalid positions
-    let abs_utf16_cu_idx = abs_utf16_cu_idx.min(source.len_utf16_cu());
-
-    // Convert absolute UTF-16 index -> absolute char index -> absolute byte index.
-    let abs_char_idx = source.utf16_cu_to_char(abs_utf16_cu_idx);
+    let abs_char_idx = match encoding {
+        PositionEncoding::Utf8 => {
+            let abs_byte_idx = source.line_to_byte(row_idx) + position.character as usize;
+            // Positions inside a multi-byte character round down to its start.
+            source.byte_to_char(abs_byte_idx.min(source.len_bytes()))
+        }
+        PositionEncoding::Utf16 => {
+            let row_utf16_cu_idx = source.char_to_utf16_cu(row_char_idx);
+            let abs_utf16_cu_idx = row_utf16_cu_idx + position.character as usize;
+            source.utf16_cu_to_char(abs_utf16_cu_idx.min(source.len_utf16_cu()))
+        }
+    };
     let abs_byte_idx = source.char_to_byte(abs_char_idx);
 
     // tree-sitter Point columns are byte offsets from the *start of the row*.
@@ -132,11 +253,11 @@ fn lsp_position_to_core(
     Ok(TextPosition {
         char: u32::try_from(abs_char_idx)?,
         byte: u32::try_from(abs_byte_idx)?,
-        code: u32::try_from(abs_utf16_cu_idx)?,
         point,
     })
 }
 
+#[cfg(test)]
 fn byte_to_tree_sitter_point(
     source: &ropey::Rope,
     byte_idx: usize,
@@ -259,6 +380,23 @@ mod tests {
         assert_eq!(edit.new_end_byte, 16); // Added "New line\n" (9 bytes)
     }
 
+    #[test]
+    fn test_lsp_textdocchange_new_end_position() {
+        let source = Rope::from("Line 1\nLine 2");
+        let change = TextDocumentContentChangeEvent {
+            range: Some(Range {
+                start: Position::new(1, 4),
+                end: Position::new(1, 6),
+            }),
+            range_length: None,
+            text: " 2a\nLine 2b".to_string(),
+        };
+
+        let edit = lsp_textdocchange_to_ts_inputedit(&source, &change).unwrap();
+        assert_eq!(edit.new_end_byte, 22);
+        assert_eq!(edit.new_end_position, Point::new(2, 7));
+    }
+
     #[test]
     fn test_lsp_textdocchange_with_multibyte_utf8() {
         let source = Rope::from("Hello 世界");
@@ -446,4 +584,59 @@ mod tests {
             "Should handle out-of-bounds range gracefully"
         );
     }
+
+    #[test]
+    fn test_negotiate_position_encoding() {
+        assert_eq!(PositionEncoding::negotiate(None), PositionEncoding::Utf16);
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&[PositionEncodingKind::UTF16])),
+            PositionEncoding::Utf16
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&[
+                PositionEncodingKind::UTF16,
+                PositionEncodingKind::UTF8
+            ])),
+            PositionEncoding::Utf8
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(Some(&[PositionEncodingKind::UTF32])),
+            PositionEncoding::Utf16
+        );
+    }
+
+    #[test]
+    fn test_line_column_conversions() {
+        // "é" is 2 bytes/1 UTF-16 unit, "𝄞" is 4 bytes/2 UTF-16 units
+        let line = "\"Café 𝄞\" x";
+        let x = line.find('x').unwrap();
+        assert_eq!(x, 13);
+
+        assert_eq!(PositionEncoding::Utf8.character(line, x), 13);
+        assert_eq!(PositionEncoding::Utf16.character(line, x), 10);
+
+        assert_eq!(PositionEncoding::Utf8.byte_column(line, 13), x);
+        assert_eq!(PositionEncoding::Utf16.byte_column(line, 10), x);
+
+        // Inside a multi-byte character rounds down, past the end clamps
+        assert_eq!(PositionEncoding::Utf8.byte_column(line, 5), 4);
+        assert_eq!(PositionEncoding::Utf16.byte_column(line, 7), 7);
+        assert_eq!(PositionEncoding::Utf16.byte_column(line, 100), line.len());
+    }
+
+    #[test]
+    fn test_positions_after_non_ascii_payee() {
+        let source = Rope::from("2024-01-01 * \"Café ☕\" \"Lunch\"\n  Assets:Cash\n");
+        let lunch = source.to_string().find("Lunch").unwrap();
+        let point = tree_sitter::Point::new(0, lunch);
+
+        for (encoding, character) in [(PositionEncoding::Utf8, 26), (PositionEncoding::Utf16, 23)] {
+            let position = byte_to_lsp_position_with(&source, lunch, encoding);
+            assert_eq!(position, Position::new(0, character), "{encoding:?}");
+
+            let core = lsp_position_to_core_with(&source, position, encoding).unwrap();
+            assert_eq!(core.byte as usize, lunch, "{encoding:?}");
+            assert_eq!(core.point, point, "{encoding:?}");
+        }
+    }
 }
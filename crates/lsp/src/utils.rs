@@ -1,18 +1,247 @@
-use std::path::PathBuf;
+use crate::document::Document;
+use lsp_types::{
+    DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, TextDocumentEdit, TextEdit,
+    WorkspaceEdit,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-pub fn file_path_to_uri(path: &std::path::Path) -> Result<lsp_types::Uri, ()> {
+/// Converts a path to a `file:` URI. Virtual paths of documents without a file on
+/// disk (see [`ToFilePath`]) turn back into the URI they were made from.
+pub fn file_path_to_uri(path: &Path) -> Result<lsp_types::Uri, ()> {
+    if let Some(uri) = virtual_document_uri(path) {
+        return Ok(uri);
+    }
     let url = url::Url::from_file_path(path).map_err(|_| ())?;
     lsp_types::Uri::from_str(url.as_str()).map_err(|_| ())
 }
 
+/// The URI of a document without a file on disk, such as an `untitled:` buffer,
+/// if `path` is the virtual path of one.
+pub fn virtual_document_uri(path: &Path) -> Option<lsp_types::Uri> {
+    if path.is_absolute() {
+        return None;
+    }
+    let path = path.to_str()?;
+    let (scheme, _) = path.split_once(':')?;
+    // A single letter would be a Windows drive
+    let is_scheme = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !is_scheme {
+        return None;
+    }
+    lsp_types::Uri::from_str(path).ok()
+}
+
+pub fn is_virtual_document(path: &Path) -> bool {
+    virtual_document_uri(path).is_some()
+}
+
 pub trait ToFilePath {
+    /// The path a document is keyed by.
+    ///
+    /// Documents without a file on disk, such as `untitled:` buffers, get a virtual
+    /// relative path made of the URI itself, so they can be kept and queried like
+    /// any other open document.
     fn to_file_path(&self) -> Result<PathBuf, ()>;
 }
 
 impl ToFilePath for lsp_types::Uri {
     fn to_file_path(&self) -> Result<PathBuf, ()> {
         let url = url::Url::from_str(self.as_str()).map_err(|_| ())?;
-        url.to_file_path()
+        if url.scheme() == "file" {
+            url.to_file_path()
+        } else {
+            Ok(PathBuf::from(self.as_str()))
+        }
+    }
+}
+
+/// A workspace edit making `changes` as `documentChanges`, each open document's edits
+/// tagged with the version they were computed against, so the client can reject them
+/// once the document has changed since. Files that are not open get no version.
+///
+/// Clients without `workspace.workspaceEdit.documentChanges` support get the plain
+/// `changes` map instead.
+#[allow(clippy::mutable_key_type)]
+pub(crate) fn versioned_workspace_edit(
+    open_docs: &HashMap<PathBuf, Document>,
+    changes: HashMap<lsp_types::Uri, Vec<TextEdit>>,
+    document_changes_support: bool,
+) -> WorkspaceEdit {
+    if !document_changes_support {
+        return WorkspaceEdit::new(changes);
+    }
+    let mut changes: Vec<_> = changes.into_iter().collect();
+    changes.sort_by(|(a, _), (b, _)| a.as_str().cmp(b.as_str()));
+    let edits = changes
+        .into_iter()
+        .map(|(uri, edits)| {
+            let version = uri
+                .to_file_path()
+                .ok()
+                .and_then(|path| open_docs.get(&path))
+                .map(|doc| doc.version);
+            TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            }
+        })
+        .collect();
+    WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(edits)),
+        ..Default::default()
+    }
+}
+
+/// The text edits of `edit` by document, from either `changes` or `documentChanges`.
+#[allow(clippy::mutable_key_type)]
+pub(crate) fn text_edits(edit: &WorkspaceEdit) -> HashMap<lsp_types::Uri, Vec<TextEdit>> {
+    let mut result = edit.changes.clone().unwrap_or_default();
+    let document_edits: Vec<&TextDocumentEdit> = match &edit.document_changes {
+        Some(DocumentChanges::Edits(edits)) => edits.iter().collect(),
+        Some(DocumentChanges::Operations(operations)) => operations
+            .iter()
+            .filter_map(|operation| match operation {
+                lsp_types::DocumentChangeOperation::Edit(edit) => Some(edit),
+                lsp_types::DocumentChangeOperation::Op(_) => None,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    for document_edit in document_edits {
+        result
+            .entry(document_edit.text_document.uri.clone())
+            .or_default()
+            .extend(document_edit.edits.iter().map(|edit| match edit {
+                OneOf::Left(edit) => edit.clone(),
+                OneOf::Right(annotated) => annotated.text_edit.clone(),
+            }));
+    }
+    result
+}
+
+/// Splits CSV into records, honoring quoted fields with `""` escapes and newlines.
+pub(crate) fn parse_csv(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == delimiter && !in_quotes => record.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let records = parse_csv(
+            "account,sum_position\r\nAssets:Cash,\"1,000.00 USD\"\n\"say \"\"hi\"\"\",\n",
+            ',',
+        );
+        assert_eq!(
+            records,
+            vec![
+                vec!["account", "sum_position"],
+                vec!["Assets:Cash", "1,000.00 USD"],
+                vec!["say \"hi\"", ""],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_csv_with_semicolons() {
+        assert_eq!(
+            parse_csv("Datum;Betrag\n01.02.2024;\"-1,50\"", ';'),
+            vec![vec!["Datum", "Betrag"], vec!["01.02.2024", "-1,50"]]
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_versioned_workspace_edit_tags_open_documents() {
+        let open = std::env::temp_dir().join("versioned_open.beancount");
+        let closed = std::env::temp_dir().join("versioned_closed.beancount");
+        let open_docs = HashMap::from([(
+            open.clone(),
+            Document {
+                content: ropey::Rope::from_str(""),
+                version: 7,
+            },
+        )]);
+        let edit = |text: &str| vec![TextEdit::new(lsp_types::Range::default(), text.to_string())];
+        let changes = HashMap::from([
+            (file_path_to_uri(&open).unwrap(), edit("a")),
+            (file_path_to_uri(&closed).unwrap(), edit("b")),
+        ]);
+
+        let workspace_edit = versioned_workspace_edit(&open_docs, changes.clone(), true);
+        assert_eq!(workspace_edit.changes, None);
+        let Some(DocumentChanges::Edits(edits)) = &workspace_edit.document_changes else {
+            panic!("expected text document edits");
+        };
+        let versions: HashMap<_, _> = edits
+            .iter()
+            .map(|edit| {
+                (
+                    edit.text_document.uri.to_file_path().unwrap(),
+                    edit.text_document.version,
+                )
+            })
+            .collect();
+        assert_eq!(versions, HashMap::from([(open, Some(7)), (closed, None)]));
+        assert_eq!(text_edits(&workspace_edit), changes);
+
+        // Clients that only understand `changes` get the unversioned edits
+        let workspace_edit = versioned_workspace_edit(&open_docs, changes.clone(), false);
+        assert_eq!(workspace_edit.document_changes, None);
+        assert_eq!(workspace_edit.changes, Some(changes));
+    }
+
+    #[test]
+    fn test_untitled_uri_round_trips_through_virtual_path() {
+        let uri = lsp_types::Uri::from_str("untitled:Untitled-1").unwrap();
+        let path = uri.to_file_path().unwrap();
+        assert!(is_virtual_document(&path));
+        assert_eq!(file_path_to_uri(&path).unwrap(), uri);
+    }
+
+    #[test]
+    fn test_file_uri_is_not_virtual() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("main.beancount");
+        let uri = file_path_to_uri(&file).unwrap();
+        let path = uri.to_file_path().unwrap();
+        assert_eq!(path, file);
+        assert!(!is_virtual_document(&path));
+        assert!(!is_virtual_document(Path::new("C:main.beancount")));
+        assert!(!is_virtual_document(Path::new("main.beancount")));
     }
 }